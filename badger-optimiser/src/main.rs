@@ -4,41 +4,60 @@ use crate::tracing::Tracer;
 
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{self, BufWriter};
+use std::net::TcpListener;
 use std::num::NonZeroUsize;
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::exit;
+use std::process::{exit, Command};
+use std::sync::Arc;
 
 use clap::Parser;
+use itertools::Itertools;
+use tket2::optimiser::badger::distributed::{self, WorkerConnection};
 use tket2::optimiser::badger::log::BadgerLogger;
-use tket2::optimiser::badger::BadgerOptions;
+use tket2::optimiser::badger::{BadgerOptions, FrontierRequest};
 use tket2::optimiser::{BadgerOptimiser, DefaultBadgerOptimiser};
-use tket2::serialize::{load_tk1_json_file, save_tk1_json_file};
-
-#[cfg(feature = "peak_alloc")]
-#[global_allocator]
-static PEAK_ALLOC: peak_alloc::PeakAlloc = peak_alloc::PeakAlloc;
+use tket2::passes::RebaseRegistry;
+use tket2::rewrite::profile::RewriteProfile;
+use tket2::rewrite::ECCPruneOptions;
+use tket2::serialize::{load_tk1_json_file, save_tk1_json_file, DecodeOptions};
+use tket2::{Circuit, Tk2Op, Tket2Error};
+use tket2_bench::pipeline::{parse_pipeline, PassContext, Pipeline, PASS_NAMES};
 
 #[cfg(all(not(target_env = "msvc"), not(feature = "peak_alloc")))]
 #[global_allocator]
 static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
+#[cfg(all(not(target_env = "msvc"), feature = "peak_alloc"))]
+#[global_allocator]
+static GLOBAL: tket2::memory::TrackingAllocator<tikv_jemallocator::Jemalloc> =
+    tket2::memory::TrackingAllocator::new(tikv_jemallocator::Jemalloc);
+
+#[cfg(all(target_env = "msvc", feature = "peak_alloc"))]
+#[global_allocator]
+static GLOBAL: tket2::memory::TrackingAllocator = tket2::memory::TrackingAllocator::system();
+
 /// Optimise circuits using Quartz-generated ECCs.
 ///
 /// Quartz: <https://github.com/quantum-compiler/quartz>
 #[derive(Parser, Debug)]
 #[clap(version = "1.0", long_about = None)]
 #[clap(about = "Optimise circuits using Quartz-generated ECCs.")]
+#[command(subcommand_negates_reqs = true)]
 struct CmdLineArgs {
+    /// Run as a worker for distributed optimisation.
+    #[command(subcommand)]
+    command: Option<Subcommand>,
     /// Input circuit file as TK1 JSON.
     #[arg(
         short,
         long,
+        required = true,
         value_name = "FILE",
         help = "Input. A quantum circuit in TK1 JSON format."
     )]
-    input: PathBuf,
+    input: Option<PathBuf>,
     /// Output circuit file
     #[arg(
         short,
@@ -52,10 +71,26 @@ struct CmdLineArgs {
     #[arg(
         short,
         long,
+        required = true,
         value_name = "ECC_FILE",
-        help = "Sets the ECC file to use. It is a JSON file of Quartz-generated ECCs."
+        help = "Sets the ECC file to use. It is a JSON file of Quartz-generated ECCs. Repeat the option to merge the ECC sets of several JSON files."
     )]
-    eccs: PathBuf,
+    eccs: Vec<PathBuf>,
+    /// Compiled ECC cache file
+    #[arg(
+        long = "compiled-eccs",
+        value_name = "CACHE_FILE",
+        help = "Cache the rewriter compiled from a JSON ECC file at CACHE_FILE. The cache is reused by later runs, and recompiled whenever the ECC file changes."
+    )]
+    compiled_eccs: Option<PathBuf>,
+    /// Gate set of the ECC rules
+    #[arg(
+        long = "gate-set",
+        value_delimiter = ',',
+        value_name = "GATES",
+        help = "Discard the ECC rules using quantum gates other than GATES, a comma-separated list such as `H,CX,RzF64`. Requires JSON ECC files."
+    )]
+    gate_set: Vec<Tk2Op>,
     /// Log output file
     #[arg(
         short,
@@ -65,6 +100,15 @@ struct CmdLineArgs {
         help = "Logfile to to output the progress of the optimisation."
     )]
     logfile: Option<PathBuf>,
+    /// Format of the optimisation progress log.
+    #[arg(
+        long = "log-format",
+        value_enum,
+        default_value_t = LogFormat::Csv,
+        value_name = "FORMAT",
+        help = "Format of the log of the optimisation progress. `csv` writes the successive best costs to best_circs.csv, `json` writes structured events to badger-events.jsonl."
+    )]
+    log_format: LogFormat,
     /// Timeout in seconds (default=no timeout)
     #[arg(
         short,
@@ -103,29 +147,230 @@ struct CmdLineArgs {
         help = "Split the circuit into chunks and optimize each one in a separate thread. Use `-j` to specify the number of threads to use."
     )]
     split_circ: bool,
+    /// Overlap between chunks.
+    #[arg(
+        long = "split-overlap",
+        value_name = "N_OPS",
+        help = "When using `--split-circ`, re-optimise up to N_OPS operations on each side of every chunk boundary after reassembling the circuit."
+    )]
+    split_overlap: Option<usize>,
+    /// Rounds of chunked optimisation.
+    #[arg(
+        long = "split-rounds",
+        default_value = "1",
+        value_name = "N_ROUNDS",
+        help = "When using `--split-circ`, re-split and optimise the circuit for up to N_ROUNDS rounds, shifting the chunk boundaries at each round. Stops early once the circuit no longer improves. Defaults to 1."
+    )]
+    split_rounds: usize,
+    /// Number of chunk offsets.
+    #[arg(
+        long = "split-offsets",
+        default_value = "2",
+        value_name = "N_OFFSETS",
+        help = "The number of distinct chunk boundary offsets to cycle through with `--split-rounds`. Defaults to 2."
+    )]
+    split_offsets: usize,
+    /// Local worker processes.
+    #[arg(
+        long = "workers",
+        value_name = "N_WORKERS",
+        conflicts_with_all = ["log_format", "frontier_log", "save_profile"],
+        help = "Split the circuit into chunks and optimise each one in a separate worker process, spawned with the `worker` subcommand. The optimisation logs are not available with workers."
+    )]
+    workers: Option<NonZeroUsize>,
+    /// Remote workers.
+    #[arg(
+        long = "connect",
+        value_name = "ADDR",
+        conflicts_with_all = ["log_format", "frontier_log", "save_profile"],
+        help = "Split the circuit into chunks and optimise them on the worker listening at ADDR, started with `worker --listen`. Can be repeated to use multiple workers, and combined with `--workers`. The optimisation logs are not available with workers."
+    )]
+    connect: Vec<String>,
     /// Max queue size.
     #[arg(
         short = 'q',
         long = "queue-size",
+        visible_alias = "max-queue",
         default_value = "100",
         value_name = "QUEUE_SIZE",
-        help = "The priority queue size. Defaults to 100."
+        help = "The priority queue size. The worst candidates are evicted when it is full. Defaults to 100."
     )]
     queue_size: usize,
+    /// Memory budget for the seen circuits.
+    #[arg(
+        long = "max-seen-memory",
+        value_name = "MIB",
+        help = "Maximum memory in MiB used to record the circuits seen so far. Once reached, seen circuits are tracked with a Bloom filter that may skip some new circuits. Defaults to no limit."
+    )]
+    max_seen_memory: Option<usize>,
     /// Trace each rewrite applied to the circuit.
     #[arg(
         long = "rewrite-tracing",
         help = "Trace each rewrite applied to the circuit. Prints statistics for the best circuit at the end of the optimisation."
     )]
     rewrite_tracing: bool,
+    /// Incremental matching radius.
+    #[arg(
+        long = "match-radius",
+        value_name = "RADIUS",
+        help = "Only re-match rewrites within RADIUS edges of the nodes modified by a rewrite, instead of rescanning every circuit. Should be at least the size of the largest ECC circuit. Only used when running on a single thread."
+    )]
+    match_radius: Option<usize>,
+    /// Compact queue storage.
+    #[arg(
+        long = "compact-queue",
+        help = "Store the pure quantum circuits of the priority queue in a compact form, using less memory. The angle computations of the queued circuits are replaced by constants, which may change the result. Ignored with `--match-radius`."
+    )]
+    compact_queue: bool,
+    /// Canonical hashing.
+    #[arg(
+        long = "canonical-hash",
+        help = "Identify circuits that only differ by the order of commuting gates, at the cost of slower hashing."
+    )]
+    canonical_hash: bool,
+    /// Random seed.
+    #[arg(
+        long,
+        value_name = "SEED",
+        help = "Explore the rewrites of each circuit in a random order drawn from SEED. Runs on a single thread are reproducible for a given seed. Defaults to the order of the rewriter."
+    )]
+    seed: Option<u64>,
+    /// Queue snapshot output file.
+    #[arg(
+        long = "frontier-log",
+        value_name = "FILE",
+        help = "Write a JSON line with the hashes and costs of the best queued circuits to FILE each time the process receives SIGUSR1."
+    )]
+    frontier_log: Option<PathBuf>,
+    /// Number of circuits in each queue snapshot.
+    #[arg(
+        long = "frontier-size",
+        default_value = "100",
+        value_name = "N",
+        help = "The number of circuits to include in each queue snapshot. Defaults to 100."
+    )]
+    frontier_size: usize,
+    /// Compilation pipeline.
+    #[arg(
+        long = "pipeline",
+        value_name = "PIPELINE",
+        value_parser = parse_pipeline,
+        conflicts_with_all = ["workers", "connect", "log_format", "frontier_log", "save_profile"],
+        help = format!("Compile the circuit through PIPELINE instead of only running the optimiser, and write the result to the output file. PIPELINE is a comma-separated list of passes, or a YAML file (`.yaml` or `.yml`) listing the passes and their options. The `badger` passes use the ECC files and the optimiser options. The passes are: {PASS_NAMES}.")
+    )]
+    pipeline: Option<Pipeline>,
+    /// Rewrite profile input file.
+    #[arg(
+        long = "profile",
+        value_name = "FILE",
+        help = "Try the rewrite classes and regions that paid off in the rewrite profile FILE first."
+    )]
+    profile: Option<PathBuf>,
+    /// Rewrite profile output file.
+    #[arg(
+        long = "save-profile",
+        value_name = "FILE",
+        help = "Write a profile of the rewrites leading to the best circuit to FILE. Only recorded by single-threaded runs."
+    )]
+    save_profile: Option<PathBuf>,
+}
+
+/// The subcommands of the optimiser.
+#[derive(clap::Subcommand, Debug)]
+enum Subcommand {
+    /// Optimise the circuit chunks sent by another optimiser process.
+    ///
+    /// Reads requests from the standard input and writes the results to the
+    /// standard output, unless `--listen` is given.
+    Worker {
+        /// ECC file
+        #[arg(
+            short,
+            long,
+            required = true,
+            value_name = "ECC_FILE",
+            help = "Sets the ECC files to use. Must be the same as the ones used by the dispatching process."
+        )]
+        eccs: Vec<PathBuf>,
+        /// Compiled ECC cache file
+        #[arg(
+            long = "compiled-eccs",
+            value_name = "CACHE_FILE",
+            help = "Cache the rewriter compiled from a JSON ECC file at CACHE_FILE."
+        )]
+        compiled_eccs: Option<PathBuf>,
+        /// Gate set of the ECC rules
+        #[arg(
+            long = "gate-set",
+            value_delimiter = ',',
+            value_name = "GATES",
+            help = "Discard the ECC rules using quantum gates other than GATES. Must be the same as the one used by the dispatching process."
+        )]
+        gate_set: Vec<Tk2Op>,
+        /// Socket address to listen on.
+        #[arg(
+            long,
+            value_name = "ADDR",
+            help = "Listen for connections on the socket address ADDR, such as `0.0.0.0:7878`, instead of using the standard streams."
+        )]
+        listen: Option<String>,
+    },
+}
+
+/// The format of the optimisation progress log.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum LogFormat {
+    /// A CSV file of the successive best costs.
+    Csv,
+    /// A JSON line per optimisation event.
+    Json,
+}
+
+/// Request a snapshot of the optimiser's queue whenever SIGUSR1 is received.
+#[cfg(unix)]
+fn request_frontier_on_signal(request: FrontierRequest) {
+    /// The handle used to request queue snapshots from the signal handler.
+    static FRONTIER_REQUEST: std::sync::OnceLock<FrontierRequest> = std::sync::OnceLock::new();
+
+    extern "C" fn handler(_: libc::c_int) {
+        if let Some(request) = FRONTIER_REQUEST.get() {
+            request.request();
+        }
+    }
+    FRONTIER_REQUEST.set(request).unwrap();
+    let handler: extern "C" fn(libc::c_int) = handler;
+    // SAFETY: The handler only performs an atomic store.
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler as libc::sighandler_t);
+    }
+    println!(
+        "Send SIGUSR1 to process {} to save a snapshot of the queue.",
+        std::process::id()
+    );
+}
+
+#[cfg(not(unix))]
+fn request_frontier_on_signal(_request: FrontierRequest) {
+    eprintln!("Queue snapshots on signals are only supported on unix platforms.");
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let opts = CmdLineArgs::parse();
 
-    let input_path = Path::new(&opts.input);
+    if let Some(Subcommand::Worker {
+        eccs,
+        compiled_eccs,
+        gate_set,
+        listen,
+    }) = &opts.command
+    {
+        return run_worker(eccs, compiled_eccs.as_deref(), gate_set, listen.as_deref());
+    }
+    let input_path = opts.input.as_deref().unwrap();
+    let ecc_paths = opts.eccs.as_slice();
+    let compiled_path = opts.compiled_eccs.as_deref();
+    let gate_set = opts.gate_set.as_slice();
     let output_path = Path::new(&opts.output);
-    let ecc_path = Path::new(&opts.eccs);
 
     let n_threads = opts
         .n_threads
@@ -138,22 +383,50 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // We need to keep the object around to keep the logging active.
     let _tracer = Tracer::setup_tracing(opts.logfile, n_threads.get() > 1);
 
-    // TODO: Remove this from the Logger, and use tracing events instead.
-    let circ_candidates_csv = BufWriter::new(File::create("best_circs.csv")?);
-
-    let badger_logger = BadgerLogger::new(circ_candidates_csv);
+    let mut badger_logger = match opts.log_format {
+        LogFormat::Csv => {
+            // TODO: Remove this from the Logger, and use tracing events instead.
+            let circ_candidates_csv = BufWriter::new(File::create("best_circs.csv")?);
+            BadgerLogger::new(circ_candidates_csv)
+        }
+        LogFormat::Json => {
+            let event_log = BufWriter::new(File::create("badger-events.jsonl")?);
+            BadgerLogger::default().with_event_log(event_log)
+        }
+    };
+    if let Some(frontier_log) = &opts.frontier_log {
+        let request = FrontierRequest::new();
+        let writer = BufWriter::new(File::create(frontier_log)?);
+        badger_logger =
+            badger_logger.with_frontier_log(request.clone(), opts.frontier_size, writer);
+        request_frontier_on_signal(request);
+    }
+    if let Some(save_profile) = &opts.save_profile {
+        let writer = BufWriter::new(File::create(save_profile)?);
+        badger_logger = badger_logger.with_profile_log(writer);
+    }
+    let profile = opts
+        .profile
+        .as_deref()
+        .map(RewriteProfile::load_json)
+        .transpose()?
+        .map(Arc::new);
 
-    let mut circ = load_tk1_json_file(input_path)?;
+    let mut circ = load_tk1_json_file(input_path, DecodeOptions::default())
+        .unwrap_or_else(|e| exit_with_diagnostic(e));
     if opts.rewrite_tracing {
         circ.enable_rewrite_tracing();
     }
 
     print!("Loading optimiser...");
     let load_ecc_start = std::time::Instant::now();
-    let Ok(optimiser) = load_optimiser(ecc_path) else {
-        println!();
-        eprintln!("Unable to load ECC file {ecc_path:?}. Is it a JSON file of Quartz-generated ECCs? Or a pre-compiled `.rwr` ECC set?");
-        exit(1);
+    let optimiser = match load_optimiser(ecc_paths, compiled_path, gate_set) {
+        Ok(optimiser) => optimiser,
+        Err(e) => {
+            println!();
+            eprintln!("Unable to load ECC files {ecc_paths:?}: {e}");
+            exit(1);
+        }
     };
     println!(" done in {:?}", load_ecc_start.elapsed());
 
@@ -161,39 +434,162 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         "Using {n_threads} threads. Queue size is {}.",
         opts.queue_size
     );
+    if let Some(mib) = opts.max_seen_memory {
+        println!("Seen circuits are limited to {mib} MiB.");
+    }
 
     if opts.split_circ && n_threads.get() > 1 {
         println!("Splitting circuit into {n_threads} chunks.");
+        if opts.split_rounds > 1 {
+            println!(
+                "Running up to {} rounds, cycling through {} chunk offsets.",
+                opts.split_rounds, opts.split_offsets
+            );
+        }
+        if let Some(overlap) = opts.split_overlap {
+            println!("Re-optimising {overlap} operations around each chunk boundary.");
+        }
     }
 
-    println!("Optimising...");
-    let opt_circ = optimiser.optimise_with_log(
-        &circ,
-        badger_logger,
-        BadgerOptions {
-            timeout: opts.timeout,
-            progress_timeout: opts.progress_timeout,
-            n_threads,
-            split_circuit: opts.split_circ,
-            queue_size: opts.queue_size,
-            max_circuit_count: opts.max_circuit_count,
-        },
-    );
+    let options = BadgerOptions {
+        timeout: opts.timeout,
+        progress_timeout: opts.progress_timeout,
+        n_threads,
+        split_circuit: opts.split_circ,
+        split_overlap: opts.split_overlap,
+        split_rounds: opts.split_rounds,
+        split_offsets: opts.split_offsets,
+        queue_size: opts.queue_size,
+        max_seen_memory: opts.max_seen_memory.map(|mib| mib << 20),
+        max_circuit_count: opts.max_circuit_count,
+        match_radius: opts.match_radius,
+        compact_queue: opts.compact_queue,
+        canonical_hashing: opts.canonical_hash,
+        seed: opts.seed,
+        profile,
+    };
+
+    let mut workers = Vec::new();
+    for _ in 0..opts.workers.map_or(0, NonZeroUsize::get) {
+        let mut worker = Command::new(std::env::current_exe()?);
+        worker.arg("worker");
+        for ecc_path in ecc_paths {
+            worker.arg("--eccs").arg(ecc_path);
+        }
+        if let Some(compiled_path) = compiled_path {
+            worker.arg("--compiled-eccs").arg(compiled_path);
+        }
+        if !gate_set.is_empty() {
+            worker
+                .arg("--gate-set")
+                .arg(gate_set.iter().map(|&op| <&str>::from(op)).join(","));
+        }
+        workers.push(WorkerConnection::spawn(&mut worker)?);
+    }
+    for addr in &opts.connect {
+        workers.push(WorkerConnection::connect(addr)?);
+    }
+
+    let opt_circ = if let Some(pipeline) = &opts.pipeline {
+        run_pipeline(circ, pipeline, optimiser, options)?
+    } else if workers.is_empty() {
+        println!("Optimising...");
+        let optimiser = optimiser.specialise_to_circuit(&circ);
+        optimiser.optimise_with_log(&circ, badger_logger, (), options)
+    } else {
+        let optimiser = optimiser.specialise_to_circuit(&circ);
+        println!("Optimising with {} workers...", workers.len());
+        optimiser.optimise_distributed(&circ, &mut workers, options)?
+    };
 
     println!("Saving result");
-    save_tk1_json_file(&opt_circ, output_path)?;
+    save_tk1_json_file(&opt_circ, output_path).unwrap_or_else(|e| exit_with_diagnostic(e));
 
     #[cfg(feature = "peak_alloc")]
-    println!("Peak memory usage: {} GB", PEAK_ALLOC.peak_usage_as_gb());
+    print!("{}", tket2::memory::memory_report());
 
     println!("Done.");
     Ok(())
 }
 
-fn load_optimiser(ecc_path: &Path) -> Result<DefaultBadgerOptimiser, Box<dyn std::error::Error>> {
-    Ok(match ecc_path.extension().and_then(OsStr::to_str) {
-        Some("json") => BadgerOptimiser::default_with_eccs_json_file(ecc_path)?,
-        Some("rwr") => BadgerOptimiser::default_with_rewriter_binary(ecc_path)?,
-        _ => Err("ECC file must be a `.json` file or a pre-compiled `.rwr` ECC set.".to_string())?,
+/// Compile a circuit through a pipeline of passes, running the `badger`
+/// passes with `optimiser`.
+fn run_pipeline(
+    mut circ: Circuit,
+    pipeline: &Pipeline,
+    optimiser: DefaultBadgerOptimiser,
+    options: BadgerOptions,
+) -> Result<Circuit, Box<dyn std::error::Error>> {
+    let ctx = PassContext {
+        rebase: RebaseRegistry::new(),
+        badger: Some(optimiser),
+        badger_options: options,
+    };
+    for pass in &pipeline.passes {
+        println!("Running {pass}...");
+        pass.run(&mut circ, &ctx)?;
+    }
+    Ok(circ)
+}
+
+/// Report a tket2 error to the user and exit.
+fn exit_with_diagnostic(err: impl Into<Tket2Error>) -> ! {
+    eprintln!("{}", err.into().diagnostic());
+    exit(1)
+}
+
+/// Optimise the circuit chunks requested by another optimiser process.
+fn run_worker(
+    ecc_paths: &[PathBuf],
+    compiled_path: Option<&Path>,
+    gate_set: &[Tk2Op],
+    listen: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let optimiser = load_optimiser(ecc_paths, compiled_path, gate_set)?;
+    match listen {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr)?;
+            eprintln!("Listening on {}.", listener.local_addr()?);
+            distributed::serve_tcp(&optimiser, listener)?;
+        }
+        None => distributed::run_worker(&optimiser, io::stdin().lock(), io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+fn load_optimiser(
+    ecc_paths: &[PathBuf],
+    compiled_path: Option<&Path>,
+    gate_set: &[Tk2Op],
+) -> Result<DefaultBadgerOptimiser, Box<dyn std::error::Error>> {
+    let extension = |path: &Path| path.extension().and_then(OsStr::to_str).map(str::to_owned);
+    let all_json = ecc_paths
+        .iter()
+        .all(|path| extension(path).as_deref() == Some("json"));
+    if !gate_set.is_empty() {
+        if !all_json || compiled_path.is_some() {
+            Err("`--gate-set` requires `.json` ECC files, and cannot be used with `--compiled-eccs`.".to_string())?;
+        }
+        let options = ECCPruneOptions {
+            gate_set: Some(gate_set.iter().copied().collect()),
+            ..Default::default()
+        };
+        return Ok(BadgerOptimiser::default_with_eccs_json_files_pruned(
+            ecc_paths, &options,
+        )?);
+    }
+    Ok(match (ecc_paths, compiled_path) {
+        (_, Some(compiled_path)) if all_json => {
+            BadgerOptimiser::default_with_compiled_rewriter(ecc_paths, compiled_path)?
+        }
+        ([ecc_path], None) if all_json => BadgerOptimiser::default_with_eccs_json_file(ecc_path)?,
+        (_, None) if all_json => BadgerOptimiser::default_with_eccs_json_files(ecc_paths)?,
+        ([ecc_path], None) if extension(ecc_path).as_deref() == Some("rwr") => {
+            BadgerOptimiser::default_with_rewriter_binary(ecc_path)?
+        }
+        (_, Some(_)) => Err("`--compiled-eccs` requires `.json` ECC files.".to_string())?,
+        _ => Err(
+            "ECC files must be `.json` files, or a single pre-compiled `.rwr` ECC set.".to_string(),
+        )?,
     })
 }
@@ -1,11 +1,13 @@
 use std::fs;
 use std::path::Path;
 use std::process::exit;
+use std::str::FromStr;
 use std::time::Instant;
 
 use clap::Parser;
 
-use tket2::rewrite::ECCRewriter;
+use tket2::rewrite::{ECCPruneOptions, ECCRewriter};
+use tket2::Tk2Op;
 
 /// Program to precompile patterns from files into a PatternMatcher stored as binary file.
 #[derive(Parser, Debug)]
@@ -32,6 +34,29 @@ struct CmdLineArgs {
         help = "Sets the output file or folder. Defaults to \"matcher.rwr\" if no file name is provided. The extension of the file name will always be set or amended to be `.rwr`."
     )]
     output: String,
+    /// Target gate set
+    #[arg(
+        long,
+        value_name = "GATES",
+        value_delimiter = ',',
+        value_parser = Tk2Op::from_str,
+        help = "Comma-separated list of gates to keep, e.g. \"H,CX,T,Tdg\". Circuits using other gates are pruned from the ECC set. Keeps all gates if not provided."
+    )]
+    gate_set: Option<Vec<Tk2Op>>,
+    /// Maximum pattern size
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Prunes circuits with more than N operations from the ECC set."
+    )]
+    max_pattern_size: Option<usize>,
+    /// Maximum number of qubits
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Prunes circuits acting on more than N qubits from the ECC set. Use this to specialise the rewriter to circuits with at most N qubits."
+    )]
+    max_qubits: Option<usize>,
 }
 
 fn main() {
@@ -45,13 +70,20 @@ fn main() {
     };
     let start_time = Instant::now();
     println!("Compiling rewriter...");
-    let Ok(rewriter) = ECCRewriter::try_from_eccs_json_file(input_path) else {
+    let prune_options = ECCPruneOptions {
+        gate_set: opts.gate_set.map(|gates| gates.into_iter().collect()),
+        max_pattern_size: opts.max_pattern_size,
+        max_qubits: opts.max_qubits,
+    };
+    let Ok(rewriter) = ECCRewriter::try_from_eccs_json_file_pruned(input_path, &prune_options)
+    else {
         eprintln!(
             "Unable to load ECC file {:?}. Is it a JSON file of Quartz-generated ECCs?",
             input_path
         );
         exit(1);
     };
+    println!("Compiled {} patterns", rewriter.n_patterns());
     print!("Saving to file...");
     let output_file = if output_path.is_dir() {
         output_path.join("matcher.rwr")
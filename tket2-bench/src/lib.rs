@@ -0,0 +1,6 @@
+//! Compilation pipelines of TKET2 passes.
+//!
+//! The pipelines are run by the `tket2-bench` benchmark harness, and by the
+//! `badger-optimiser` command line tool to compile a circuit.
+
+pub mod pipeline;
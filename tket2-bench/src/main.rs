@@ -0,0 +1,191 @@
+//! Benchmark harness for TKET2 compilation pipelines.
+//!
+//! Runs a suite of circuits through a set of pipelines of passes, and reports
+//! the wall time of each pass and the cost metrics of the circuit after it.
+//! The reports are labelled with the version of `tket2`, so that runs of
+//! different versions can be compared.
+
+mod report;
+mod suite;
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use clap::Parser;
+use tket2::optimiser::badger::BadgerOptions;
+use tket2::optimiser::{BadgerOptimiser, DefaultBadgerOptimiser};
+use tket2::passes::RebaseRegistry;
+use tket2_bench::pipeline::{parse_pipeline, Pass, PassContext, Pipeline, PASS_NAMES};
+
+use crate::report::{Metrics, Report, Run};
+use crate::suite::Benchmark;
+
+/// Benchmark TKET2 compilation pipelines.
+#[derive(Parser, Debug)]
+#[clap(version = "1.0", long_about = None)]
+#[clap(
+    about = "Runs a suite of circuits through compilation pipelines, and reports the wall time and the circuit cost metrics after each pass."
+)]
+struct CmdLineArgs {
+    /// Directory of input circuits.
+    #[arg(
+        short,
+        long,
+        value_name = "DIR",
+        help = "Benchmark the circuits in DIR, in TK1 JSON (`.json`) or HUGR (`.hugr`) format. Defaults to the built-in suite of generated circuits."
+    )]
+    input: Option<PathBuf>,
+    /// Sizes of the generated circuits.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "4,8,16",
+        value_name = "N_QUBITS",
+        help = "The numbers of qubits of the circuits of the built-in suite, as a comma-separated list. Defaults to 4,8,16."
+    )]
+    sizes: Vec<usize>,
+    /// Seed of the generated circuits.
+    #[arg(
+        long,
+        default_value = "0",
+        value_name = "SEED",
+        help = "The seed of the randomised circuits of the built-in suite. Defaults to 0."
+    )]
+    seed: u64,
+    /// Pipelines to run.
+    #[arg(
+        short,
+        long = "pipeline",
+        value_name = "PIPELINE",
+        value_parser = parse_pipeline,
+        help = format!("A pipeline to run on each circuit, as a comma-separated list of passes optionally prefixed with a name, as in `NAME=PASS,PASS`, or as a YAML file (`.yaml` or `.yml`) listing the passes and their options. Repeat the option to run several pipelines. The passes are: {PASS_NAMES}. Defaults to `{}`.", Pipeline::default_pipeline())
+    )]
+    pipelines: Vec<Pipeline>,
+    /// ECC file for the badger passes.
+    #[arg(
+        short,
+        long,
+        value_name = "ECC_FILE",
+        help = "The ECC file used by the `badger` passes, either a JSON file of Quartz-generated ECCs or a pre-compiled `.rwr` file."
+    )]
+    eccs: Option<PathBuf>,
+    /// Timeout of the badger passes.
+    #[arg(
+        long = "badger-timeout",
+        default_value = "10",
+        value_name = "TIMEOUT",
+        help = "Timeout in seconds of each `badger` pass without its own timeout. Defaults to 10."
+    )]
+    badger_timeout: u64,
+    /// Label of the benchmarked build.
+    #[arg(
+        long,
+        value_name = "LABEL",
+        help = "A label identifying the benchmarked build in the reports, such as a commit hash. Defaults to the version of tket2."
+    )]
+    label: Option<String>,
+    /// CSV report file.
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write a CSV report to FILE, with a row for each pass. If neither `--csv` nor `--json` is given, the CSV report is written to the standard output."
+    )]
+    csv: Option<PathBuf>,
+    /// JSON report file.
+    #[arg(long, value_name = "FILE", help = "Write a JSON report to FILE.")]
+    json: Option<PathBuf>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opts = CmdLineArgs::parse();
+
+    let suite = match &opts.input {
+        Some(dir) => suite::load_dir(dir)?,
+        None => suite::generate(&opts.sizes, opts.seed),
+    };
+    let pipelines = match opts.pipelines.is_empty() {
+        true => vec![Pipeline::default_pipeline()],
+        false => opts.pipelines,
+    };
+    let uses_badger = pipelines
+        .iter()
+        .flat_map(|pipeline| &pipeline.passes)
+        .any(|pass| matches!(pass, Pass::Badger { .. }));
+    if uses_badger && opts.eccs.is_none() {
+        Err("The `badger` pass requires an ECC file, given with `--eccs`.")?;
+    }
+    let ctx = PassContext {
+        rebase: RebaseRegistry::new(),
+        badger: opts.eccs.as_deref().map(load_optimiser).transpose()?,
+        badger_options: BadgerOptions {
+            timeout: Some(opts.badger_timeout),
+            ..Default::default()
+        },
+    };
+
+    let mut report = Report::new(opts.label.as_deref().unwrap_or(tket2::VERSION));
+    for benchmark in &suite {
+        for pipeline in &pipelines {
+            let run = run_pipeline(benchmark, pipeline, &ctx);
+            let (input, output) = (run.input, run.output());
+            eprintln!(
+                "{} / {}: {} -> {} operations, {} -> {} CX, in {:.3}s{}",
+                run.circuit,
+                run.pipeline,
+                input.operations,
+                output.operations,
+                input.cx_count,
+                output.cx_count,
+                run.seconds(),
+                run.error()
+                    .map_or(String::new(), |e| format!(" (failed: {e})")),
+            );
+            report.runs.push(run);
+        }
+    }
+
+    if let Some(path) = &opts.csv {
+        report.write_csv(BufWriter::new(File::create(path)?))?;
+    }
+    if let Some(path) = &opts.json {
+        report.write_json(BufWriter::new(File::create(path)?))?;
+    }
+    if opts.csv.is_none() && opts.json.is_none() {
+        report.write_csv(io::stdout().lock())?;
+    }
+    Ok(())
+}
+
+/// Run a pipeline on a copy of a circuit, timing each pass.
+///
+/// The pipeline stops at the first pass that fails.
+fn run_pipeline(benchmark: &Benchmark, pipeline: &Pipeline, ctx: &PassContext) -> Run {
+    let mut circ = benchmark.circuit.clone();
+    let mut run = Run {
+        circuit: benchmark.name.clone(),
+        pipeline: pipeline.name.clone(),
+        input: Metrics::new(&circ),
+        passes: Vec::new(),
+    };
+    for pass in &pipeline.passes {
+        let start = Instant::now();
+        let result = pass.run(&mut circ, ctx);
+        let error = result.err().map(|e| e.to_string());
+        let failed = error.is_some();
+        run.record(pass.to_string(), start.elapsed(), &circ, error);
+        if failed {
+            break;
+        }
+    }
+    run
+}
+
+/// Load the optimiser of the badger passes.
+fn load_optimiser(ecc_path: &Path) -> Result<DefaultBadgerOptimiser, Box<dyn std::error::Error>> {
+    Ok(match ecc_path.extension().and_then(|ext| ext.to_str()) {
+        Some("rwr") => BadgerOptimiser::default_with_rewriter_binary(ecc_path)?,
+        _ => BadgerOptimiser::default_with_eccs_json_file(ecc_path)?,
+    })
+}
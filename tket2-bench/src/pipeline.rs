@@ -0,0 +1,285 @@
+//! Compilation pipelines, given on the command line or in YAML files.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+
+use itertools::Itertools;
+use serde::Deserialize;
+use tket2::circuit::cost::{is_cx, is_quantum, LexicographicCost};
+use tket2::optimiser::badger::BadgerOptions;
+use tket2::optimiser::DefaultBadgerOptimiser;
+use tket2::passes::{
+    apply_greedy_commutation, cx_cancellation, decompose_controlled_gates, normalise_angles,
+    peephole, squash_single_qubit_gates, RebaseRegistry,
+};
+use tket2::Circuit;
+
+/// The passes that can be part of a pipeline, as listed in the help message.
+pub const PASS_NAMES: &str = "rebase:<GATE_SET>, squash, cx-cancellation, normalise-angles, commute, peephole, decompose-controlled, badger[:<TIMEOUT>]";
+
+/// A compilation pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pass {
+    /// Rebase to a gate set registered in the [`RebaseRegistry`].
+    Rebase(String),
+    /// Squash the runs of single-qubit gates.
+    Squash,
+    /// Cancel pairs of CX gates.
+    CxCancellation,
+    /// Normalise the rotation angles, and replace special angles with named
+    /// gates.
+    NormaliseAngles,
+    /// Greedily commute gates towards the start of the circuit.
+    Commute,
+    /// Resynthesise two-qubit blocks of gates, minimising the CX count.
+    Peephole,
+    /// Decompose the multi-controlled gates.
+    DecomposeControlled,
+    /// Optimise the circuit with the Badger optimiser, with an optional
+    /// timeout in seconds overriding the one of the [`PassContext`].
+    Badger {
+        /// The timeout of the pass, in seconds.
+        timeout: Option<u64>,
+    },
+}
+
+impl Pass {
+    /// Run the pass on a circuit.
+    pub fn run(&self, circ: &mut Circuit, ctx: &PassContext) -> Result<(), Box<dyn Error>> {
+        match self {
+            Pass::Rebase(gate_set) => {
+                ctx.rebase.rebase(circ, gate_set)?;
+            }
+            Pass::Squash => {
+                squash_single_qubit_gates(circ, Default::default());
+            }
+            Pass::CxCancellation => {
+                cx_cancellation(circ, Default::default());
+            }
+            Pass::NormaliseAngles => {
+                normalise_angles(circ, Default::default());
+            }
+            Pass::Commute => {
+                apply_greedy_commutation(circ)?;
+            }
+            Pass::Peephole => {
+                peephole(circ, 2, |op| {
+                    LexicographicCost::from([is_cx(op) as usize, is_quantum(op) as usize])
+                })?;
+            }
+            Pass::DecomposeControlled => {
+                decompose_controlled_gates(circ);
+            }
+            Pass::Badger { timeout } => {
+                let optimiser = ctx
+                    .badger
+                    .as_ref()
+                    .ok_or("the `badger` pass requires an ECC file, given with `--eccs`")?;
+                let options = BadgerOptions {
+                    timeout: timeout.or(ctx.badger_options.timeout),
+                    ..ctx.badger_options.clone()
+                };
+                *circ = optimiser.optimise(circ, options);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pass::Rebase(gate_set) => write!(f, "rebase:{gate_set}"),
+            Pass::Squash => f.write_str("squash"),
+            Pass::CxCancellation => f.write_str("cx-cancellation"),
+            Pass::NormaliseAngles => f.write_str("normalise-angles"),
+            Pass::Commute => f.write_str("commute"),
+            Pass::Peephole => f.write_str("peephole"),
+            Pass::DecomposeControlled => f.write_str("decompose-controlled"),
+            Pass::Badger { timeout: None } => f.write_str("badger"),
+            Pass::Badger {
+                timeout: Some(timeout),
+            } => write!(f, "badger:{timeout}"),
+        }
+    }
+}
+
+impl FromStr for Pass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(gate_set) = s.strip_prefix("rebase:") {
+            return Ok(Pass::Rebase(gate_set.to_string()));
+        }
+        if let Some(timeout) = s.strip_prefix("badger:") {
+            let timeout = timeout
+                .parse()
+                .map_err(|_| format!("invalid timeout `{timeout}` of the `badger` pass"))?;
+            return Ok(Pass::Badger {
+                timeout: Some(timeout),
+            });
+        }
+        Ok(match s {
+            "squash" => Pass::Squash,
+            "cx-cancellation" => Pass::CxCancellation,
+            "normalise-angles" => Pass::NormaliseAngles,
+            "commute" => Pass::Commute,
+            "peephole" => Pass::Peephole,
+            "decompose-controlled" => Pass::DecomposeControlled,
+            "badger" => Pass::Badger { timeout: None },
+            _ => return Err(format!("unknown pass `{s}`, expected one of {PASS_NAMES}")),
+        })
+    }
+}
+
+/// The shared state of the passes.
+pub struct PassContext {
+    /// The gate sets available to the `rebase` passes.
+    pub rebase: RebaseRegistry,
+    /// The optimiser of the `badger` passes, if an ECC file was given.
+    pub badger: Option<DefaultBadgerOptimiser>,
+    /// The options of the `badger` passes.
+    pub badger_options: BadgerOptions,
+}
+
+/// A named sequence of passes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline {
+    /// The name of the pipeline in the reports.
+    pub name: String,
+    /// The passes, in the order they are run.
+    pub passes: Vec<Pass>,
+}
+
+impl Pipeline {
+    /// The pipeline run when none is given on the command line.
+    pub fn default_pipeline() -> Self {
+        "default=cx-cancellation,squash,normalise-angles"
+            .parse()
+            .unwrap()
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.passes.iter().join(","))
+    }
+}
+
+/// Parse a pipeline from a comma-separated list of passes, optionally
+/// prefixed with its name, as in `NAME=PASS,PASS`. Unnamed pipelines are named
+/// after their list of passes.
+impl FromStr for Pipeline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, passes) = s.split_once('=').unwrap_or((s, s));
+        let passes: Vec<Pass> = passes.split(',').map(str::parse).try_collect()?;
+        if name.is_empty() {
+            return Err("the pipeline name cannot be empty".to_string());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            passes,
+        })
+    }
+}
+
+/// Parse a pipeline given on the command line, loading it from a YAML file if
+/// the argument has a `.yaml` or `.yml` extension.
+pub fn parse_pipeline(arg: &str) -> Result<Pipeline, String> {
+    let path = Path::new(arg);
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml" | "yml") => {
+            Pipeline::load(path).map_err(|e| format!("could not load `{arg}`: {e}"))
+        }
+        _ => arg.parse(),
+    }
+}
+
+impl Pipeline {
+    /// Load a pipeline from a YAML file.
+    ///
+    /// The file lists the passes of the pipeline, either in their
+    /// command-line syntax or as a map of the pass name and its options, and
+    /// optionally the name of the pipeline. Unnamed pipelines are named after
+    /// the file.
+    ///
+    /// ```yaml
+    /// name: reproducible
+    /// passes:
+    ///   - commute
+    ///   - pass: rebase
+    ///     gate-set: ibm
+    ///   - pass: badger
+    ///     timeout: 30
+    /// ```
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let config: PipelineConfig = serde_yaml::from_reader(File::open(path)?)?;
+        let name = match config.name {
+            Some(name) => name,
+            None => path
+                .file_stem()
+                .map_or(String::new(), |stem| stem.to_string_lossy().into_owned()),
+        };
+        if name.is_empty() {
+            Err("the pipeline name cannot be empty")?;
+        }
+        let passes = config
+            .passes
+            .into_iter()
+            .map(PassConfig::into_pass)
+            .try_collect()?;
+        Ok(Self { name, passes })
+    }
+}
+
+/// A pipeline, as described in a YAML file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PipelineConfig {
+    name: Option<String>,
+    passes: Vec<PassConfig>,
+}
+
+/// A pass of a [`PipelineConfig`].
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PassConfig {
+    /// A pass in its command-line syntax.
+    Short(String),
+    /// A pass with its options.
+    WithOptions(PassOptions),
+}
+
+/// The options of a pass in a [`PipelineConfig`].
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct PassOptions {
+    pass: String,
+    gate_set: Option<String>,
+    timeout: Option<u64>,
+}
+
+impl PassConfig {
+    fn into_pass(self) -> Result<Pass, String> {
+        let PassOptions {
+            pass,
+            gate_set,
+            timeout,
+        } = match self {
+            PassConfig::Short(pass) => return pass.parse(),
+            PassConfig::WithOptions(options) => options,
+        };
+        match (pass.as_str(), gate_set, timeout) {
+            ("rebase", Some(gate_set), None) => Ok(Pass::Rebase(gate_set)),
+            ("rebase", None, _) => Err("the `rebase` pass requires a `gate-set`".to_string()),
+            ("badger", None, timeout) => Ok(Pass::Badger { timeout }),
+            (_, None, None) => pass.parse(),
+            _ => Err(format!("invalid options for the `{pass}` pass")),
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Benchmark results and their CSV and JSON reports.
+
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+
+use serde::Serialize;
+use tket2::resource::estimate_resources;
+use tket2::Circuit;
+
+/// The cost metrics of a circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Metrics {
+    /// The number of operations.
+    pub operations: usize,
+    /// The peak number of live qubits.
+    pub qubits: usize,
+    /// The depth of the circuit.
+    pub depth: usize,
+    /// The number of CX gates.
+    pub cx_count: usize,
+    /// The number of T and Tdg gates.
+    pub t_count: usize,
+}
+
+impl Metrics {
+    /// Measure the metrics of a circuit.
+    pub fn new(circ: &Circuit) -> Self {
+        let estimate = estimate_resources(circ);
+        Self {
+            operations: circ.num_operations(),
+            qubits: estimate.qubits,
+            depth: estimate.depth,
+            cx_count: estimate.cx_count,
+            t_count: estimate.t_count,
+        }
+    }
+}
+
+/// The result of a single pass.
+#[derive(Debug, Clone, Serialize)]
+pub struct PassRecord {
+    /// The name of the pass.
+    pub pass: String,
+    /// The wall time of the pass, in seconds.
+    pub seconds: f64,
+    /// The metrics of the circuit after the pass.
+    pub metrics: Metrics,
+    /// The error returned by the pass, if any. No pass runs after a failed
+    /// one.
+    pub error: Option<String>,
+}
+
+/// The result of running a pipeline on a circuit.
+#[derive(Debug, Clone, Serialize)]
+pub struct Run {
+    /// The name of the circuit.
+    pub circuit: String,
+    /// The name of the pipeline.
+    pub pipeline: String,
+    /// The metrics of the input circuit.
+    pub input: Metrics,
+    /// The passes that ran, in order.
+    pub passes: Vec<PassRecord>,
+}
+
+impl Run {
+    /// The total wall time of the passes, in seconds.
+    pub fn seconds(&self) -> f64 {
+        self.passes.iter().map(|p| p.seconds).sum()
+    }
+
+    /// The metrics of the output circuit.
+    pub fn output(&self) -> Metrics {
+        self.passes.last().map_or(self.input, |p| p.metrics)
+    }
+
+    /// The error that stopped the pipeline, if any.
+    pub fn error(&self) -> Option<&str> {
+        self.passes.iter().find_map(|p| p.error.as_deref())
+    }
+
+    /// Record the wall time, the resulting metrics and the error of a pass.
+    pub fn record(&mut self, pass: String, time: Duration, circ: &Circuit, error: Option<String>) {
+        self.passes.push(PassRecord {
+            pass,
+            seconds: time.as_secs_f64(),
+            metrics: Metrics::new(circ),
+            error,
+        });
+    }
+}
+
+/// A benchmark report.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// A label identifying the benchmarked build, such as a commit hash.
+    pub label: String,
+    /// The version of `tket2`.
+    pub tket2_version: &'static str,
+    /// The results of each pipeline on each circuit.
+    pub runs: Vec<Run>,
+}
+
+/// A row of the CSV report.
+///
+/// Each run has a row for its input, with a `pass` of `input`, followed by
+/// a row for each pass.
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    label: &'a str,
+    tket2_version: &'a str,
+    circuit: &'a str,
+    pipeline: &'a str,
+    step: usize,
+    pass: &'a str,
+    seconds: f64,
+    operations: usize,
+    qubits: usize,
+    depth: usize,
+    cx_count: usize,
+    t_count: usize,
+    error: Option<&'a str>,
+}
+
+impl Report {
+    /// Create an empty report.
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            tket2_version: tket2::VERSION,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Write the report as CSV, with a row per pass.
+    pub fn write_csv(&self, writer: impl io::Write) -> Result<(), Box<dyn Error>> {
+        let mut csv = csv::Writer::from_writer(writer);
+        for run in &self.runs {
+            let row = |step, pass, seconds, metrics: Metrics, error| CsvRow {
+                label: &self.label,
+                tket2_version: self.tket2_version,
+                circuit: &run.circuit,
+                pipeline: &run.pipeline,
+                step,
+                pass,
+                seconds,
+                operations: metrics.operations,
+                qubits: metrics.qubits,
+                depth: metrics.depth,
+                cx_count: metrics.cx_count,
+                t_count: metrics.t_count,
+                error,
+            };
+            csv.serialize(row(0, "input", 0., run.input, None))?;
+            for (i, pass) in run.passes.iter().enumerate() {
+                let error = pass.error.as_deref();
+                csv.serialize(row(i + 1, &pass.pass, pass.seconds, pass.metrics, error))?;
+            }
+        }
+        csv.flush()?;
+        Ok(())
+    }
+
+    /// Write the report as JSON.
+    pub fn write_json(&self, writer: impl io::Write) -> Result<(), Box<dyn Error>> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+}
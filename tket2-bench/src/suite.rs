@@ -0,0 +1,71 @@
+//! The circuits to benchmark.
+
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+
+use itertools::Itertools;
+use tket2::circuit::generators::{ghz, qft, quantum_volume, random_clifford_t};
+use tket2::seed::Seed;
+use tket2::serialize::{load_tk1_json_file, DecodeOptions};
+use tket2::Circuit;
+
+/// A circuit to benchmark.
+pub struct Benchmark {
+    /// The name of the circuit in the reports.
+    pub name: String,
+    /// The circuit.
+    pub circuit: Circuit,
+}
+
+/// Load the circuits in a directory, sorted by file name.
+///
+/// Files with a `.json` extension are loaded as TK1 JSON circuits, and files
+/// with a `.hugr` extension as HUGR circuits. Other files are ignored.
+pub fn load_dir(dir: &Path) -> Result<Vec<Benchmark>, Box<dyn Error>> {
+    let paths = fs::read_dir(dir)?
+        .map_ok(|entry| entry.path())
+        .filter_ok(|path| path.is_file())
+        .try_collect::<_, Vec<_>, _>()?;
+    paths
+        .into_iter()
+        .sorted()
+        .filter_map(|path| {
+            let circuit = match path.extension().and_then(OsStr::to_str)? {
+                "json" => {
+                    load_tk1_json_file(&path, DecodeOptions::default()).map_err(|e| e.to_string())
+                }
+                "hugr" => Circuit::load_hugr_file(&path).map_err(|e| e.to_string()),
+                _ => return None,
+            };
+            let name = path.file_stem()?.to_string_lossy().into_owned();
+            Some(
+                circuit
+                    .map(|circuit| Benchmark { name, circuit })
+                    .map_err(|e| format!("Unable to load {}: {e}", path.display()).into()),
+            )
+        })
+        .collect()
+}
+
+/// Generate the built-in suite of circuits, with each family of circuits on
+/// each number of qubits.
+///
+/// The randomised circuits are deterministic for a given seed.
+pub fn generate(sizes: &[usize], seed: Seed) -> Vec<Benchmark> {
+    let mut suite = Vec::new();
+    for &n in sizes {
+        let mut add = |family: &str, circuit| {
+            suite.push(Benchmark {
+                name: format!("{family}_{n}"),
+                circuit,
+            })
+        };
+        add("qft", qft(n));
+        add("ghz", ghz(n));
+        add("quantum_volume", quantum_volume(n, n, seed));
+        add("clifford_t", random_clifford_t(n, 20 * n, seed));
+    }
+    suite
+}
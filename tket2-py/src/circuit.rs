@@ -15,6 +15,7 @@ use pyo3::prelude::*;
 use std::fmt;
 
 use hugr::{type_row, Hugr, HugrView, PortIndex};
+use tket2::circuit::units::Qubit;
 use tket2::extension::REGISTRY;
 use tket2::rewrite::CircuitRewrite;
 use tket2::serialize::TKETDecode;
@@ -34,6 +35,7 @@ pub fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
     m.add_class::<Tk2Circuit>()?;
     m.add_class::<PyNode>()?;
     m.add_class::<PyWire>()?;
+    m.add_class::<PyQubit>()?;
     m.add_class::<PyCircuitCost>()?;
 
     m.add_function(wrap_pyfunction!(validate_circuit, &m)?)?;
@@ -77,7 +79,7 @@ create_py_exception!(
 );
 
 create_py_exception!(
-    tket2::serialize::pytket::TK1ConvertError,
+    diagnostic tket2::serialize::pytket::TK1ConvertError,
     PyTK1ConvertError,
     "Error type for the conversion between tket2 and tket1 operations."
 );
@@ -173,3 +175,46 @@ impl PyWire {
         self.wire.source().index()
     }
 }
+
+/// A [`tket2::circuit::units::Qubit`] wrapper for Python.
+#[pyclass]
+#[pyo3(name = "Qubit")]
+#[repr(transparent)]
+#[derive(From, Into, PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct PyQubit {
+    /// Rust representation of the qubit
+    pub qubit: Qubit,
+}
+
+impl fmt::Display for PyQubit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.qubit.fmt(f)
+    }
+}
+
+#[pymethods]
+impl PyQubit {
+    #[new]
+    fn new(index: usize) -> Self {
+        Qubit::new(index).into()
+    }
+
+    /// A string representation of the qubit.
+    pub fn __repr__(&self) -> String {
+        format!("Qubit({})", self.qubit.index())
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __hash__(&self) -> usize {
+        self.qubit.index()
+    }
+
+    /// The index of the qubit in the circuit.
+    #[getter]
+    fn index(&self) -> usize {
+        self.qubit.index()
+    }
+}
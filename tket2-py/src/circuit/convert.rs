@@ -18,9 +18,9 @@ use pyo3::{
 use derive_more::From;
 use hugr::{Hugr, HugrView, Wire};
 use serde::Serialize;
+use tket2::circuit::CircuitChunks;
 use tket2::circuit::CircuitHash;
 use tket2::extension::REGISTRY;
-use tket2::passes::CircuitChunks;
 use tket2::serialize::TKETDecode;
 use tket2::{Circuit, Tk2Op};
 use tket_json_rs::circuit_json::SerialCircuit;
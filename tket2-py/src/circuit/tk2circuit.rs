@@ -18,11 +18,12 @@ use pyo3::{
 use derive_more::From;
 use hugr::{Hugr, HugrView, Wire};
 use serde::Serialize;
+use tket2::circuit::units::Qubit;
+use tket2::circuit::CircuitChunks;
 use tket2::circuit::CircuitHash;
 use tket2::extension::REGISTRY;
 use tket2::passes::pytket::lower_to_pytket;
-use tket2::passes::CircuitChunks;
-use tket2::serialize::TKETDecode;
+use tket2::serialize::{DecodeOptions, TKETDecode};
 use tket2::{Circuit, Tk2Op};
 use tket_json_rs::circuit_json::SerialCircuit;
 
@@ -31,7 +32,7 @@ use crate::rewrite::PyCircuitRewrite;
 use crate::types::PyHugrType;
 use crate::utils::{into_vec, ConvertPyErr};
 
-use super::{cost, with_circ, PyCircuitCost, PyNode, PyWire};
+use super::{cost, with_circ, PyCircuitCost, PyNode, PyQubit, PyWire};
 
 /// A circuit in tket2 format.
 ///
@@ -80,8 +81,10 @@ impl Tk2Circuit {
     }
 
     /// Apply a rewrite on the circuit.
-    pub fn apply_rewrite(&mut self, rw: PyCircuitRewrite) {
-        rw.rewrite.apply(&mut self.circ).expect("Apply error.");
+    pub fn apply_rewrite(&mut self, rw: PyCircuitRewrite) -> PyResult<()> {
+        rw.rewrite
+            .apply(&mut self.circ)
+            .map_err(|e| e.convert_pyerrs())
     }
 
     /// Encode the circuit as a HUGR json string.
@@ -118,9 +121,10 @@ impl Tk2Circuit {
     /// Decode a tket1 json string to a circuit.
     #[staticmethod]
     pub fn from_tket1_json(json: &str) -> PyResult<Self> {
-        let circ = tket2::serialize::load_tk1_json_str(json).map_err(|e| {
-            PyErr::new::<PyAttributeError, _>(format!("Could not load pytket circuit: {e}"))
-        })?;
+        let circ =
+            tket2::serialize::load_tk1_json_str(json, DecodeOptions::default()).map_err(|e| {
+                PyErr::new::<PyAttributeError, _>(format!("Could not load pytket circuit: {e}"))
+            })?;
         Ok(Tk2Circuit { circ })
     }
 
@@ -158,6 +162,14 @@ impl Tk2Circuit {
         self.circ.num_operations()
     }
 
+    /// Returns the qubits of the circuit, in input order.
+    pub fn qubits(&self) -> Vec<PyQubit> {
+        self.circ
+            .qubits()
+            .map(|(unit, _, _)| Qubit::from(unit).into())
+            .collect()
+    }
+
     /// Returns a hash of the circuit.
     pub fn hash(&self) -> u64 {
         self.circ.circuit_hash().unwrap()
@@ -5,6 +5,7 @@ pub mod optimiser;
 pub mod passes;
 pub mod pattern;
 pub mod rewrite;
+pub mod sim;
 pub mod types;
 pub mod utils;
 
@@ -19,7 +20,9 @@ fn _tket2(py: Python, m: &Bound<PyModule>) -> PyResult<()> {
     add_submodule(py, m, passes::module(py)?)?;
     add_submodule(py, m, pattern::module(py)?)?;
     add_submodule(py, m, rewrite::module(py)?)?;
+    add_submodule(py, m, sim::module(py)?)?;
     add_submodule(py, m, types::module(py)?)?;
+    m.add("Tket2Error", py.get_type_bound::<utils::PyTket2Error>())?;
     Ok(())
 }
 
@@ -12,8 +12,9 @@ use strum::IntoEnumIterator;
 use hugr::ops::{CustomOp, NamedOp, OpType};
 use tket2::{Pauli, Tk2Op};
 
+use crate::circuit::Tk2Circuit;
 use crate::types::PyHugrType;
-use crate::utils::into_vec;
+use crate::utils::{create_py_exception, into_vec, ConvertPyErr};
 
 /// The module definition
 pub fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
@@ -21,9 +22,28 @@ pub fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
     m.add_class::<PyTk2Op>()?;
     m.add_class::<PyPauli>()?;
     m.add_class::<PyCustomOp>()?;
+    m.add_function(wrap_pyfunction!(controlled, &m)?)?;
+    m.add("ControlError", py.get_type_bound::<PyControlError>())?;
     Ok(m)
 }
 
+create_py_exception!(
+    diagnostic tket2::ControlError,
+    PyControlError,
+    "Error while building a controlled operation."
+);
+
+/// Build a circuit applying an operation controlled by `n_controls` qubits.
+///
+/// The circuit acts on the control qubits followed by the qubits of the
+/// operation. Angle parameters are given in radians.
+#[pyfunction]
+#[pyo3(signature = (op, n_controls, params = Vec::new()))]
+pub fn controlled(op: PyTk2Op, n_controls: usize, params: Vec<f64>) -> PyResult<Tk2Circuit> {
+    let circ = tket2::controlled(op.op, &params, n_controls).convert_pyerrs()?;
+    Ok(Tk2Circuit { circ })
+}
+
 /// Enum of Tket2 operations in hugr.
 ///
 /// Python equivalent of [`Tk2Op`].
@@ -1,14 +1,24 @@
 //! PyO3 wrapper for the Badger circuit optimiser.
 
+pub mod cost;
+
 use std::io::BufWriter;
+use std::sync::Arc;
 use std::{fs, num::NonZeroUsize, path::PathBuf};
 
 use pyo3::prelude::*;
-use tket2::optimiser::badger::BadgerOptions;
-use tket2::optimiser::{BadgerLogger, DefaultBadgerOptimiser};
+use pyo3::types::PyTuple;
+use tket2::circuit::cost::CircuitCost;
+use tket2::optimiser::badger::{BadgerOptions, BadgerProgress};
+use tket2::optimiser::{BadgerLogger, BadgerOptimiser, DefaultBadgerOptimiser, OptimiserCallback};
+use tket2::rewrite::profile::RewriteProfile;
+use tket2::rewrite::strategy::ExhaustiveGreedyStrategy;
+use tket2::rewrite::ECCRewriter;
 use tket2::Circuit;
 
-use crate::circuit::update_circ;
+use crate::circuit::{try_update_circ, CircuitType};
+
+use self::cost::PyStrategyCost;
 
 /// The module definition
 pub fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
@@ -17,27 +27,55 @@ pub fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
     Ok(m)
 }
 
+/// A Badger optimiser whose cost function is defined in Python.
+type PyCostBadgerOptimiser = BadgerOptimiser<ECCRewriter, ExhaustiveGreedyStrategy<PyStrategyCost>>;
+
 /// Wrapped [`DefaultBadgerOptimiser`].
 ///
 /// Currently only exposes loading from an ECC file using the constructor
-/// and optimising using default logging settings.
+/// and optimising using default logging settings. The default CX count cost
+/// can be replaced by a Python cost function, see [`PyStrategyCost`].
 #[pyclass(name = "BadgerOptimiser")]
-pub struct PyBadgerOptimiser(DefaultBadgerOptimiser);
+pub struct PyBadgerOptimiser(Optimiser);
+
+/// The optimisers wrapped by [`PyBadgerOptimiser`].
+enum Optimiser {
+    /// The default optimiser, minimising the CX count.
+    Default(DefaultBadgerOptimiser),
+    /// An optimiser minimising a Python cost function.
+    PyCost(PyCostBadgerOptimiser),
+}
 
 #[pymethods]
 impl PyBadgerOptimiser {
     /// Create a new [`PyDefaultBadgerOptimiser`] from a precompiled rewriter.
+    ///
+    /// If `cost_fn` is given, it is used instead of the CX count as the cost
+    /// to minimise. See [`PyStrategyCost`].
     #[staticmethod]
-    pub fn load_precompiled(path: PathBuf) -> Self {
-        Self(DefaultBadgerOptimiser::default_with_rewriter_binary(path).unwrap())
+    pub fn load_precompiled(path: PathBuf, cost_fn: Option<&Bound<PyAny>>) -> PyResult<Self> {
+        let Some(cost_fn) = cost_fn else {
+            let opt = DefaultBadgerOptimiser::default_with_rewriter_binary(path).unwrap();
+            return Ok(Self(Optimiser::Default(opt)));
+        };
+        let rewriter = ECCRewriter::load_binary(path).unwrap();
+        Self::with_cost_fn(rewriter, cost_fn)
     }
 
     /// Create a new [`PyDefaultBadgerOptimiser`] from ECC sets.
     ///
     /// This will compile the rewriter from the provided ECC JSON file.
+    ///
+    /// If `cost_fn` is given, it is used instead of the CX count as the cost
+    /// to minimise. See [`PyStrategyCost`].
     #[staticmethod]
-    pub fn compile_eccs(path: &str) -> Self {
-        Self(DefaultBadgerOptimiser::default_with_eccs_json_file(path).unwrap())
+    pub fn compile_eccs(path: &str, cost_fn: Option<&Bound<PyAny>>) -> PyResult<Self> {
+        let Some(cost_fn) = cost_fn else {
+            let opt = DefaultBadgerOptimiser::default_with_eccs_json_file(path).unwrap();
+            return Ok(Self(Optimiser::Default(opt)));
+        };
+        let rewriter = ECCRewriter::try_from_eccs_json_file(path).unwrap();
+        Self::with_cost_fn(rewriter, cost_fn)
     }
 
     /// Run the optimiser on a circuit.
@@ -82,11 +120,51 @@ impl PyBadgerOptimiser {
     ///     If this option is set to `false`, the optimiser will run `n_threads`
     ///     parallel searches on the whole circuit (default).
     ///
+    /// * `split_overlap`: When splitting the circuit, the number of operations
+    ///     on each side of a chunk boundary to re-optimise after reassembling
+    ///     the circuit. Defaults to `None`, which means no re-optimisation.
+    ///
+    /// * `split_rounds`: When splitting the circuit, the maximum number of
+    ///     rounds of chunked optimisation, each with shifted chunk boundaries.
+    ///     Stops early once the circuit no longer improves. Defaults to `1`.
+    ///
+    /// * `split_offsets`: The number of distinct chunk offsets to cycle
+    ///     through over the rounds. Defaults to `2`.
+    ///
     /// * `queue_size`: The maximum size of the circuit candidates priority
     ///     queue. Defaults to `20`.
     ///
     /// * `log_progress`: The path to a CSV file to log progress to.
     ///
+    /// * `match_radius`: Update the rewrites of each circuit incrementally,
+    ///     only re-matching within this radius of the modified nodes. Only
+    ///     used when running on a single thread.
+    ///
+    /// * `compact_queue`: Store the pure quantum circuits of the queue in a
+    ///     compact form, using less memory. The angle computations of the
+    ///     queued circuits are replaced by constants. Defaults to `False`.
+    ///
+    /// * `max_seen_memory`: The maximum memory (in bytes) used to record the
+    ///     circuits seen so far. Once reached, seen circuits are tracked with a
+    ///     Bloom filter, which may skip some unseen circuits.
+    ///
+    /// * `canonical_hashing`: Identify circuits that only differ by the order
+    ///     of commuting gates. Slower to compute. Defaults to `False`.
+    ///
+    /// * `seed`: Explore the rewrites of each circuit in a random order drawn
+    ///     from this seed. Runs on a single thread are reproducible for a given
+    ///     seed. Defaults to `None`, which keeps the order of the rewriter.
+    ///
+    /// * `profile`: The path to a JSON rewrite profile of previous runs. The
+    ///     rewrites of the rule classes and regions that led to their best
+    ///     circuits are explored first. Defaults to `None`.
+    ///
+    /// * `callback`: An object notified of the progress of the optimisation.
+    ///     It may define any of the methods `on_new_best(circ, cost)`,
+    ///     `on_progress(circuits_processed, circuits_seen, queue_length,
+    ///     elapsed)` and `on_timeout(circ, cost)`. Exceptions raised by the
+    ///     callbacks are re-raised once the optimisation finishes.
+    ///
     #[pyo3(name = "optimise")]
     #[allow(clippy::too_many_arguments)]
     pub fn py_optimise<'py>(
@@ -97,27 +175,65 @@ impl PyBadgerOptimiser {
         max_circuit_count: Option<usize>,
         n_threads: Option<NonZeroUsize>,
         split_circ: Option<bool>,
+        split_overlap: Option<usize>,
+        split_rounds: Option<usize>,
+        split_offsets: Option<usize>,
         queue_size: Option<usize>,
         log_progress: Option<PathBuf>,
+        match_radius: Option<usize>,
+        compact_queue: Option<bool>,
+        max_seen_memory: Option<usize>,
+        canonical_hashing: Option<bool>,
+        seed: Option<u64>,
+        profile: Option<PathBuf>,
+        callback: Option<Bound<'py, PyAny>>,
     ) -> PyResult<Bound<'py, PyAny>> {
+        let profile = profile
+            .map(|path| RewriteProfile::load_json(path).map(Arc::new))
+            .transpose()?;
         let options = BadgerOptions {
             timeout,
             progress_timeout,
             max_circuit_count,
             n_threads: n_threads.unwrap_or(NonZeroUsize::new(1).unwrap()),
             split_circuit: split_circ.unwrap_or(false),
+            split_overlap,
+            split_rounds: split_rounds.unwrap_or(1),
+            split_offsets: split_offsets.unwrap_or(2),
             queue_size: queue_size.unwrap_or(100),
+            max_seen_memory,
+            match_radius,
+            compact_queue: compact_queue.unwrap_or_default(),
+            canonical_hashing: canonical_hashing.unwrap_or_default(),
+            seed,
+            profile,
         };
-        update_circ(circ, |circ, _| self.optimise(circ, log_progress, options))
+        try_update_circ(circ, |circ, typ| {
+            let mut callback = callback.map(|cb| PyOptimiserCallback::new(cb, typ));
+            let circ = self.optimise(circ, log_progress, callback.as_mut(), options);
+            match callback.and_then(|cb| cb.error) {
+                Some(err) => Err(err),
+                None => Ok(circ),
+            }
+        })
     }
 }
 
 impl PyBadgerOptimiser {
+    /// Create an optimiser minimising a Python cost function.
+    fn with_cost_fn(rewriter: ECCRewriter, cost_fn: &Bound<PyAny>) -> PyResult<Self> {
+        let strategy = ExhaustiveGreedyStrategy::from(PyStrategyCost::new(cost_fn)?);
+        Ok(Self(Optimiser::PyCost(BadgerOptimiser::new(
+            rewriter, strategy,
+        ))))
+    }
+
     /// The Python optimise method, but on Hugrs.
     pub(super) fn optimise(
         &self,
         circ: Circuit,
         log_progress: Option<PathBuf>,
+        callback: Option<&mut PyOptimiserCallback<'_>>,
         options: BadgerOptions,
     ) -> Circuit {
         let badger_logger = log_progress
@@ -127,6 +243,89 @@ impl PyBadgerOptimiser {
                 BadgerLogger::new(log_file)
             })
             .unwrap_or_default();
-        self.0.optimise_with_log(&circ, badger_logger, options)
+        match &self.0 {
+            Optimiser::Default(opt) => {
+                opt.optimise_with_log(&circ, badger_logger, callback, options)
+            }
+            Optimiser::PyCost(opt) => {
+                opt.optimise_with_log(&circ, badger_logger, callback, options)
+            }
+        }
+    }
+}
+
+/// Forwards the optimiser callbacks to a Python object.
+///
+/// Methods not defined by the object are skipped. The first exception raised
+/// by a callback is stored, and no further callbacks are made.
+pub(super) struct PyOptimiserCallback<'py> {
+    callback: Bound<'py, PyAny>,
+    /// The type of circuit passed to the callbacks.
+    typ: CircuitType,
+    /// The first exception raised by a callback.
+    error: Option<PyErr>,
+}
+
+impl<'py> PyOptimiserCallback<'py> {
+    fn new(callback: Bound<'py, PyAny>, typ: CircuitType) -> Self {
+        Self {
+            callback,
+            typ,
+            error: None,
+        }
+    }
+
+    /// Call a method of the callback object, if it is defined.
+    fn call(
+        &mut self,
+        method: &str,
+        args: impl FnOnce(Python<'py>) -> PyResult<Bound<'py, PyTuple>>,
+    ) {
+        if self.error.is_some() {
+            return;
+        }
+        let py = self.callback.py();
+        let res = self.callback.hasattr(method).and_then(|defined| {
+            if defined {
+                self.callback.call_method1(method, args(py)?)?;
+            }
+            Ok(())
+        });
+        self.error = res.err();
+    }
+
+    /// Call a method of the callback object with a circuit and its cost.
+    fn call_with_circ(&mut self, method: &str, circ: &Circuit, cost: usize) {
+        let typ = self.typ;
+        self.call(method, |py| {
+            let circ = typ.convert(py, circ.clone())?;
+            Ok(PyTuple::new_bound(
+                py,
+                [circ, cost.into_py(py).into_bound(py)],
+            ))
+        });
+    }
+}
+
+impl<'py, C: CircuitCost> OptimiserCallback<C> for PyOptimiserCallback<'py> {
+    fn on_new_best(&mut self, circ: &Circuit, cost: &C) {
+        self.call_with_circ("on_new_best", circ, cost.as_usize());
+    }
+
+    fn on_progress(&mut self, progress: &BadgerProgress) {
+        self.call("on_progress", |py| {
+            let args: Py<PyTuple> = (
+                progress.circuits_processed,
+                progress.circuits_seen,
+                progress.queue_length,
+                progress.elapsed.as_secs_f64(),
+            )
+                .into_py(py);
+            Ok(args.into_bound(py))
+        });
+    }
+
+    fn on_timeout(&mut self, best_circ: &Circuit, best_cost: &C) {
+        self.call_with_circ("on_timeout", best_circ, best_cost.as_usize());
     }
 }
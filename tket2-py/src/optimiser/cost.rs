@@ -0,0 +1,56 @@
+//! Rewrite strategy costs defined by python callables.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use hugr::ops::OpType;
+use pyo3::prelude::*;
+use strum::IntoEnumIterator;
+use tket2::circuit::cost::{is_quantum, LexicographicCost};
+use tket2::rewrite::strategy::StrategyCost;
+use tket2::Tk2Op;
+
+use crate::ops::PyTk2Op;
+
+/// A strategy cost computed from a python callable.
+///
+/// The callable takes a `Tk2Op` and returns a non-negative integer cost. It is
+/// called once for each operation type when the cost is created, so that the
+/// optimiser threads never need to acquire the GIL. Operations that are not
+/// [`Tk2Op`]s have zero cost.
+///
+/// The total number of quantum operations is used to rank circuits with equal
+/// cost.
+#[derive(Debug, Clone)]
+pub struct PyStrategyCost {
+    /// The cost of each operation, as returned by the callable.
+    costs: Arc<HashMap<Tk2Op, usize>>,
+}
+
+impl PyStrategyCost {
+    /// Evaluate a python cost function on every [`Tk2Op`].
+    pub fn new(cost_fn: &Bound<PyAny>) -> PyResult<Self> {
+        let costs = Tk2Op::iter()
+            .map(|op| {
+                let cost = cost_fn.call1((PyTk2Op::from(op),))?.extract::<usize>()?;
+                Ok((op, cost))
+            })
+            .collect::<PyResult<_>>()?;
+        Ok(Self {
+            costs: Arc::new(costs),
+        })
+    }
+}
+
+impl StrategyCost for PyStrategyCost {
+    type OpCost = LexicographicCost<usize, 2>;
+
+    #[inline]
+    fn op_cost(&self, op: &OpType) -> Self::OpCost {
+        let cost = Tk2Op::try_from(op)
+            .ok()
+            .and_then(|op| self.costs.get(&op).copied())
+            .unwrap_or_default();
+        [cost, is_quantum(op) as usize].into()
+    }
+}
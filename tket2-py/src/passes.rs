@@ -34,13 +34,13 @@ pub fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
 }
 
 create_py_exception!(
-    tket2::passes::PullForwardError,
+    diagnostic tket2::passes::PullForwardError,
     PyPullForwardError,
     "Error from a `PullForward` operation"
 );
 
 create_py_exception!(
-    tket2::passes::pytket::PytketLoweringError,
+    diagnostic tket2::passes::pytket::PytketLoweringError,
     PyPytketLoweringError,
     "Errors that can occur while removing high-level operations from HUGR intended to be encoded as a pytket circuit."
 );
@@ -49,7 +49,9 @@ create_py_exception!(
 fn greedy_depth_reduce<'py>(circ: &Bound<'py, PyAny>) -> PyResult<(Bound<'py, PyAny>, u32)> {
     let py = circ.py();
     try_with_circ(circ, |mut circ, typ| {
-        let n_moves = passes::apply_greedy_commutation(&mut circ).convert_pyerrs()?;
+        let n_moves = passes::apply_greedy_commutation(&mut circ)
+            .convert_pyerrs()?
+            .moves;
         let circ = typ.convert(py, circ)?;
         PyResult::Ok((circ, n_moves))
     })
@@ -177,7 +179,7 @@ fn badger_optimise<'py>(
                 max_circuit_count,
                 ..Default::default()
             };
-            circ = optimiser.optimise(circ, log_file, options);
+            circ = optimiser.optimise(circ, log_file, None, options);
         }
         PyResult::Ok(circ)
     })
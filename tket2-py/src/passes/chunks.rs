@@ -3,7 +3,7 @@
 use derive_more::From;
 use pyo3::exceptions::PyAttributeError;
 use pyo3::prelude::*;
-use tket2::passes::CircuitChunks;
+use tket2::circuit::CircuitChunks;
 
 use crate::circuit::CircuitType;
 use crate::circuit::{try_with_circ, with_circ};
@@ -23,7 +23,7 @@ pub fn chunks(c: &Bound<PyAny>, max_chunk_size: usize) -> PyResult<PyCircuitChun
 ///
 /// Python equivalent of [`CircuitChunks`].
 ///
-/// [`CircuitChunks`]: tket2::passes::chunks::CircuitChunks
+/// [`CircuitChunks`]: tket2::circuit::chunks::CircuitChunks
 #[pyclass]
 #[pyo3(name = "CircuitChunks")]
 #[derive(Debug, Clone, From)]
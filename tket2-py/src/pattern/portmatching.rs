@@ -1,15 +1,19 @@
 //! Python bindings for portmatching features
 
 use std::fmt;
+use std::path::PathBuf;
 
 use derive_more::{From, Into};
 use itertools::Itertools;
 use portmatching::PatternID;
+use pyo3::exceptions::PyIOError;
 use pyo3::{prelude::*, types::PyIterator};
 
 use tket2::portmatching::{CircuitPattern, PatternMatch, PatternMatcher};
 
-use crate::circuit::{try_with_circ, with_circ, PyNode};
+use crate::circuit::{try_with_circ, with_circ, PyNode, Tk2Circuit};
+use crate::rewrite::{PyCircuitRewrite, PySubcircuit};
+use crate::utils::ConvertPyErr;
 
 /// A pattern that match a circuit exactly
 ///
@@ -95,6 +99,49 @@ impl PyPatternMatcher {
                 .collect()
         })
     }
+
+    /// Find all convex matches in a circuit rooted at one of the given nodes.
+    pub fn find_matches_at(&self, circ: &Tk2Circuit, roots: Vec<PyNode>) -> Vec<PyPatternMatch> {
+        self.matcher
+            .find_matches_at(&circ.circ, roots.into_iter().map_into())
+            .into_iter()
+            .map_into()
+            .collect()
+    }
+
+    /// The number of patterns in the matcher.
+    pub fn n_patterns(&self) -> usize {
+        self.matcher.n_patterns()
+    }
+
+    /// Get a pattern by its ID.
+    pub fn get_pattern(&self, id: PyPatternID) -> Option<PyCircuitPattern> {
+        self.matcher.get_pattern(id.id).cloned().map(Into::into)
+    }
+
+    /// Save the compiled matcher as a binary file.
+    ///
+    /// The file extension is set to `.bin`. Returns the path of the new file.
+    pub fn save_binary(&self, path: PathBuf) -> PyResult<PathBuf> {
+        self.matcher
+            .save_binary(path)
+            .map_err(|e| PyErr::new::<PyIOError, _>(e.to_string()))
+    }
+
+    /// Load a matcher saved with `save_binary`.
+    #[staticmethod]
+    pub fn load_binary(path: PathBuf) -> PyResult<Self> {
+        let matcher = PatternMatcher::load_binary(path)
+            .map_err(|e| PyErr::new::<PyIOError, _>(e.to_string()))?;
+        Ok(matcher.into())
+    }
+
+    /// Rewrite a matcher file saved by an earlier release in the current
+    /// format.
+    #[staticmethod]
+    pub fn migrate_binary(path: PathBuf) -> PyResult<()> {
+        PatternMatcher::migrate_binary(path).map_err(|e| PyErr::new::<PyIOError, _>(e.to_string()))
+    }
 }
 
 /// A convex pattern match in a circuit, available from Python.
@@ -121,6 +168,32 @@ impl PyPatternMatch {
         self.pmatch.root().into()
     }
 
+    /// The nodes of the circuit matched by the pattern.
+    pub fn nodes(&self) -> Vec<PyNode> {
+        self.pmatch.nodes().iter().copied().map_into().collect()
+    }
+
+    /// The subcircuit matched by the pattern.
+    pub fn subcircuit(&self) -> PySubcircuit {
+        self.pmatch.subcircuit().clone().into()
+    }
+
+    /// Construct a rewrite replacing the match in `circ` with `replacement`.
+    ///
+    /// `circ` must be the circuit the match was found in.
+    pub fn to_rewrite(
+        &self,
+        circ: &Tk2Circuit,
+        replacement: &Bound<PyAny>,
+    ) -> PyResult<PyCircuitRewrite> {
+        let replacement = Tk2Circuit::new(replacement)?;
+        let rewrite = self
+            .pmatch
+            .to_rewrite(&circ.circ, replacement.circ)
+            .convert_pyerrs()?;
+        Ok(rewrite.into())
+    }
+
     /// A string representation of the pattern.
     pub fn __repr__(&self) -> String {
         format!("{:?}", self.pmatch)
@@ -4,19 +4,43 @@ use derive_more::From;
 use itertools::Itertools;
 use pyo3::prelude::*;
 use std::path::PathBuf;
-use tket2::rewrite::{CircuitRewrite, ECCRewriter, Rewriter, Subcircuit};
+use tket2::rewrite::rules::RewriteRule;
+use tket2::rewrite::{CircuitRewrite, ECCRewriter, Rewriter, RuleRewriter, Subcircuit};
 
 use crate::circuit::{PyNode, Tk2Circuit};
+use crate::pattern::portmatching::PyPatternMatcher;
+use crate::pattern::Rule;
+use crate::utils::{create_py_exception, ConvertPyErr};
 
 /// The module definition
 pub fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
     let m = PyModule::new_bound(py, "rewrite")?;
     m.add_class::<PyECCRewriter>()?;
+    m.add_class::<PyRuleRewriter>()?;
     m.add_class::<PyCircuitRewrite>()?;
     m.add_class::<PySubcircuit>()?;
+
+    m.add(
+        "SimpleReplacementError",
+        py.get_type_bound::<PySimpleReplacementError>(),
+    )?;
+    m.add("RuleError", py.get_type_bound::<PyRuleError>())?;
+
     Ok(m)
 }
 
+create_py_exception!(
+    diagnostic tket2::rewrite::rules::RuleError,
+    PyRuleError,
+    "Errors that can occur while loading rewrite rules."
+);
+
+create_py_exception!(
+    hugr::hugr::SimpleReplacementError,
+    PySimpleReplacementError,
+    "Errors that can occur while applying a rewrite to a circuit."
+);
+
 /// A rewrite rule for circuits.
 ///
 /// Python equivalent of [`CircuitRewrite`].
@@ -46,6 +70,38 @@ impl PyCircuitRewrite {
         self.rewrite.replacement().to_owned().into()
     }
 
+    /// The subcircuit that is replaced.
+    pub fn subcircuit(&self) -> PySubcircuit {
+        self.rewrite.subcircuit().clone().into()
+    }
+
+    /// The nodes referenced by the rewrite.
+    ///
+    /// Two rewrites can be applied one after the other if their invalidation
+    /// sets are disjoint.
+    pub fn invalidation_set(&self) -> Vec<PyNode> {
+        self.rewrite.invalidation_set().map_into().collect()
+    }
+
+    /// Apply the rewrite to a circuit, in place.
+    ///
+    /// The circuit must be the one the rewrite was created for.
+    pub fn apply(&self, mut circ: PyRefMut<Tk2Circuit>) -> PyResult<()> {
+        self.rewrite
+            .clone()
+            .apply(&mut circ.circ)
+            .map_err(|e| e.convert_pyerrs())
+    }
+
+    /// A string representation of the rewrite.
+    pub fn __repr__(&self) -> String {
+        format!(
+            "CircuitRewrite(nodes={:?}, node_count_delta={})",
+            self.rewrite.subcircuit().nodes(),
+            self.rewrite.node_count_delta()
+        )
+    }
+
     #[new]
     fn try_new(
         source_position: PySubcircuit,
@@ -84,6 +140,21 @@ impl PySubcircuit {
                 .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?,
         ))
     }
+
+    /// The nodes of the subcircuit.
+    pub fn nodes(&self) -> Vec<PyNode> {
+        self.0.nodes().iter().copied().map_into().collect()
+    }
+
+    /// Number of nodes in the subcircuit.
+    pub fn node_count(&self) -> usize {
+        self.0.node_count()
+    }
+
+    /// A string representation of the subcircuit.
+    pub fn __repr__(&self) -> String {
+        format!("Subcircuit(nodes={:?})", self.0.nodes())
+    }
 }
 
 /// A rewriter based on circuit equivalence classes.
@@ -113,3 +184,110 @@ impl PyECCRewriter {
             .collect()
     }
 }
+
+/// A rewriter applying a set of user-defined rewrite rules.
+///
+/// Python equivalent of [`RuleRewriter`].
+///
+/// [`RuleRewriter`]: tket2::rewrite::RuleRewriter
+#[pyclass(name = "RuleRewriter")]
+pub struct PyRuleRewriter(RuleRewriter);
+
+#[pymethods]
+impl PyRuleRewriter {
+    /// Compile a list of rules into a rewriter.
+    ///
+    /// Rules are named `rule_{i}` unless `names` are given.
+    #[new]
+    pub fn new(rules: Vec<Rule>, names: Option<Vec<String>>) -> PyResult<Self> {
+        if names
+            .as_ref()
+            .is_some_and(|names| names.len() != rules.len())
+        {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+                "Expected one name per rule.",
+            ));
+        }
+        let names =
+            names.unwrap_or_else(|| (0..rules.len()).map(|i| format!("rule_{i}")).collect());
+        let rules = rules
+            .into_iter()
+            .zip(names)
+            .map(|(Rule([lhs, rhs]), name)| RewriteRule {
+                name,
+                lhs,
+                rhs,
+                conditions: Vec::new(),
+            })
+            .collect_vec();
+        Ok(Self(RuleRewriter::try_from_rules(rules).convert_pyerrs()?))
+    }
+
+    /// Parse rules in the text format.
+    #[staticmethod]
+    pub fn from_text(text: &str) -> PyResult<Self> {
+        Ok(Self(RuleRewriter::from_text(text).convert_pyerrs()?))
+    }
+
+    /// Load rules from a text or JSON file.
+    #[staticmethod]
+    pub fn load(path: PathBuf) -> PyResult<Self> {
+        Ok(Self(RuleRewriter::load(path).convert_pyerrs()?))
+    }
+
+    /// Load a binary rule pack saved with `save_binary`.
+    #[staticmethod]
+    pub fn load_binary(path: PathBuf) -> PyResult<Self> {
+        Ok(Self(RuleRewriter::load_binary(path).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string())
+        })?))
+    }
+
+    /// Save the rules and their compiled matcher as a binary rule pack.
+    ///
+    /// The file extension is set to `.rwp`. Returns the path of the new file.
+    pub fn save_binary(&self, path: PathBuf) -> PyResult<PathBuf> {
+        self.0
+            .save_binary(path)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
+    }
+
+    /// The rules of the rewriter.
+    pub fn rules(&self) -> Vec<Rule> {
+        self.0
+            .rules()
+            .iter()
+            .map(|rule| Rule([rule.lhs.clone(), rule.rhs.clone()]))
+            .collect()
+    }
+
+    /// The names of the rules of the rewriter.
+    pub fn rule_names(&self) -> Vec<String> {
+        self.0
+            .rules()
+            .iter()
+            .map(|rule| rule.name.clone())
+            .collect()
+    }
+
+    /// The compiled matcher for the left-hand sides of the rules.
+    ///
+    /// The ID of each pattern is the index of its rule.
+    pub fn matcher(&self) -> PyPatternMatcher {
+        self.0.matcher().clone().into()
+    }
+
+    /// Returns a list of circuit rewrites that can be applied to the given Tk2Circuit.
+    pub fn get_rewrites(&self, circ: &Tk2Circuit) -> Vec<PyCircuitRewrite> {
+        self.0
+            .get_rewrites(&circ.circ)
+            .into_iter()
+            .map_into()
+            .collect()
+    }
+
+    /// The number of rules.
+    pub fn __len__(&self) -> usize {
+        self.0.rules().len()
+    }
+}
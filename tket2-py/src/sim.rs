@@ -0,0 +1,133 @@
+//! Circuit simulation.
+
+use itertools::Itertools;
+use pyo3::prelude::*;
+use pyo3::types::{PyComplex, PyDict, PyList, PyTuple};
+use tket2::sim::unitary::equal_up_to_phase;
+
+use crate::circuit::try_with_circ;
+use crate::utils::{create_py_exception, ConvertPyErr};
+
+/// The module definition
+///
+/// This module is re-exported from the python module with the same name.
+pub fn module(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
+    let m = PyModule::new_bound(py, "sim")?;
+    m.add_function(wrap_pyfunction!(sample, &m)?)?;
+    m.add_function(wrap_pyfunction!(statevector, &m)?)?;
+    m.add_function(wrap_pyfunction!(probabilities, &m)?)?;
+    m.add_function(wrap_pyfunction!(unitary, &m)?)?;
+    m.add_function(wrap_pyfunction!(equivalent, &m)?)?;
+    m.add("SimulationError", py.get_type_bound::<PySimulationError>())?;
+    Ok(m)
+}
+
+create_py_exception!(
+    diagnostic tket2::sim::SimulationError,
+    PySimulationError,
+    "Errors that can occur while simulating a circuit."
+);
+
+/// Run a circuit `shots` times and count the observed classical outputs.
+///
+/// Returns a dictionary from tuples of bit values to the number of times they
+/// were observed, in the same format as pytket's `BackendResult.get_counts`.
+#[pyfunction]
+#[pyo3(signature = (circ, shots, seed = None))]
+fn sample<'py>(
+    circ: &Bound<'py, PyAny>,
+    shots: usize,
+    seed: Option<u64>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let py = circ.py();
+    let seed = seed.unwrap_or_else(tket2::seed::from_entropy);
+    try_with_circ(circ, |circ, _| {
+        let counts = tket2::sim::sample(&circ, shots, seed).convert_pyerrs()?;
+        let dict = PyDict::new_bound(py);
+        for (outcome, count) in counts {
+            let key = PyTuple::new_bound(py, outcome.into_iter().map(u8::from));
+            dict.set_item(key, count)?;
+        }
+        PyResult::Ok(dict)
+    })
+}
+
+/// Compute the final statevector of a pure circuit.
+///
+/// Returns a one-dimensional numpy array of `2^n` complex amplitudes. Basis
+/// states are indexed in little-endian order: qubit `i` corresponds to the
+/// `i`-th least significant bit of the index.
+#[pyfunction]
+fn statevector<'py>(circ: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+    let py = circ.py();
+    try_with_circ(circ, |circ, _| {
+        let state = tket2::sim::simulate_statevector(&circ).convert_pyerrs()?;
+        let amplitudes = state.amplitudes().iter().map(|a| (a.re, a.im));
+        complex_array(py, amplitudes, &[state.amplitudes().len()])
+    })
+}
+
+/// Compute the probabilities of each computational basis state at the end of
+/// a pure circuit.
+///
+/// Returns a one-dimensional numpy array of `2^n` floats, indexed as in
+/// [`statevector`].
+#[pyfunction]
+fn probabilities<'py>(circ: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+    let py = circ.py();
+    try_with_circ(circ, |circ, _| {
+        let state = tket2::sim::simulate_statevector(&circ).convert_pyerrs()?;
+        let list = PyList::new_bound(py, state.probabilities());
+        numpy(py)?.call_method1("array", (list,))
+    })
+}
+
+/// Compute the unitary matrix implemented by a pure circuit.
+///
+/// Returns a `2^n x 2^n` complex numpy array. Entry `(i, j)` is the amplitude
+/// of basis state `i` after applying the circuit to basis state `j`, with
+/// basis states indexed as in [`statevector`].
+#[pyfunction]
+fn unitary<'py>(circ: &Bound<'py, PyAny>) -> PyResult<Bound<'py, PyAny>> {
+    let py = circ.py();
+    try_with_circ(circ, |circ, _| {
+        let matrix = tket2::sim::unitary::unitary(&circ).convert_pyerrs()?;
+        let (rows, cols) = matrix.dim();
+        let entries = matrix.iter().map(|a| (a.re, a.im));
+        complex_array(py, entries, &[rows, cols])
+    })
+}
+
+/// Check whether two pure circuits implement the same unitary, up to a global
+/// phase.
+///
+/// Matrix entries are compared with an absolute tolerance of `tol`.
+#[pyfunction]
+#[pyo3(signature = (circ1, circ2, tol = 1e-9))]
+fn equivalent(circ1: &Bound<PyAny>, circ2: &Bound<PyAny>, tol: f64) -> PyResult<bool> {
+    let u1 = try_with_circ(circ1, |circ, _| tket2::sim::unitary::unitary(&circ))?;
+    let u2 = try_with_circ(circ2, |circ, _| tket2::sim::unitary::unitary(&circ))?;
+    Ok(equal_up_to_phase(&u1, &u2, tol))
+}
+
+/// Import the numpy module.
+fn numpy(py: Python<'_>) -> PyResult<Bound<'_, PyModule>> {
+    py.import_bound("numpy")
+}
+
+/// Build a complex numpy array with the given shape from its entries in
+/// row-major order.
+fn complex_array<'py>(
+    py: Python<'py>,
+    entries: impl IntoIterator<Item = (f64, f64)>,
+    shape: &[usize],
+) -> PyResult<Bound<'py, PyAny>> {
+    let entries = entries
+        .into_iter()
+        .map(|(re, im)| PyComplex::from_doubles_bound(py, re, im))
+        .collect_vec();
+    let list = PyList::new_bound(py, entries);
+    numpy(py)?
+        .call_method1("array", (list, "complex128"))?
+        .call_method1("reshape", (PyTuple::new_bound(py, shape),))
+}
@@ -1,6 +1,11 @@
 //! Utility functions for the python interface.
 
 use itertools::Itertools;
+use pyo3::prelude::*;
+use tket2::error::Diagnostic;
+
+use crate::circuit::PyNode;
+
 /// A trait for types wrapping rust errors that may be converted into python exception.
 ///
 /// In addition to raw errors, this is implemented for wrapper types such as `Result`.
@@ -44,9 +49,44 @@ macro_rules! create_py_exception {
             }
         }
     };
+    // Exceptions for tket2 errors, reported as a [`tket2::error::Diagnostic`].
+    (diagnostic $err:path, $py_err:ident, $doc:expr) => {
+        pyo3::create_exception!(tket2, $py_err, $crate::utils::PyTket2Error, $doc);
+
+        impl $crate::utils::ConvertPyErr for $err {
+            type Output = pyo3::PyErr;
+
+            fn convert_pyerrs(self) -> Self::Output {
+                let diagnostic = tket2::Tket2Error::from(self).diagnostic();
+                pyo3::Python::with_gil(|py| {
+                    let err = $py_err::new_err(diagnostic.to_string());
+                    $crate::utils::set_diagnostic_attrs(py, &err, diagnostic);
+                    err
+                })
+            }
+        }
+    };
 }
 pub(crate) use create_py_exception;
 
+pyo3::create_exception!(
+    tket2,
+    PyTket2Error,
+    pyo3::exceptions::PyException,
+    "Base class for errors raised by tket2 operations.\n\nThe `code`, `node` and `op` attributes identify the kind of error and the operation that caused it."
+);
+
+/// Store the code and location of a diagnostic in the attributes of a python
+/// exception.
+pub(crate) fn set_diagnostic_attrs(py: Python, err: &PyErr, diagnostic: Diagnostic) {
+    let value = err.value_bound(py);
+    let node = diagnostic.span.node.map(PyNode::from);
+    // Setting attributes on a freshly created exception cannot fail.
+    let _ = value.setattr("code", diagnostic.code.to_string());
+    let _ = value.setattr("node", node.map(|n| n.into_py(py)));
+    let _ = value.setattr("op", diagnostic.span.op);
+}
+
 /// Convert an iterator of one type into vector of another type.
 pub fn into_vec<T, S: From<T>>(v: impl IntoIterator<Item = T>) -> Vec<S> {
     v.into_iter().map_into().collect()
@@ -3,6 +3,7 @@ from dataclasses import dataclass
 from pytket._tket.circuit import Circuit
 
 from tket2.circuit import (
+    Qubit,
     Tk2Circuit,
     render_circuit_dot,
 )
@@ -40,6 +41,13 @@ def test_hash():
     assert hash(circA) == hash(circC)
 
 
+def test_qubits():
+    circ = Tk2Circuit(Circuit(3).CX(0, 2).H(1))
+
+    assert circ.qubits() == [Qubit(0), Qubit(1), Qubit(2)]
+    assert [q.index for q in circ.qubits()] == [0, 1, 2]
+
+
 def test_conversion():
     tk1 = Circuit(4).CX(0, 2).CX(1, 2).CX(1, 3)
     tk1_dot = render_circuit_dot(tk1)
@@ -1,5 +1,7 @@
+import pytest
+
 import tket2
-from tket2.ops import Tk2Op, Pauli
+from tket2.ops import Tk2Op, Pauli, controlled, ControlError
 
 
 def test_ops_roundtrip():
@@ -16,3 +18,15 @@ def test_pauli_roundtrip():
 
     for pauli in tket2._tket2.ops.Pauli.values():
         assert Pauli._from_rs(pauli)._to_rs() == pauli
+
+
+def test_controlled():
+    circ = controlled(Tk2Op.H, 2)
+    assert len(circ.qubits()) == 3
+    assert circ.num_operations() > 0
+
+    circ = controlled(Tk2Op.RzF64, 1, [0.5])
+    assert len(circ.qubits()) == 2
+
+    with pytest.raises(ControlError):
+        controlled(Tk2Op.Measure, 1)
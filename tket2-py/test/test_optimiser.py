@@ -1,4 +1,5 @@
 from pytket import Circuit
+from tket2.ops import Tk2Op
 from tket2.optimiser import BadgerOptimiser
 
 
@@ -12,3 +13,51 @@ def test_simple_optimiser():
     exp_c = Circuit(3).CX(1, 2)
 
     assert cc == exp_c
+
+
+def test_optimiser_callback():
+    """the callbacks report the new best circuits"""
+    c = Circuit(3).CX(0, 1).CX(0, 1).CX(1, 2)
+    opt = BadgerOptimiser.compile_eccs("test_files/cx_cx_eccs.json")
+
+    class Callback:
+        def __init__(self):
+            self.costs = []
+
+        def on_new_best(self, circ, cost):
+            self.costs.append(cost)
+
+    callback = Callback()
+    cc = opt.optimise(c, 3, callback=callback)
+
+    assert cc == Circuit(3).CX(1, 2)
+    assert callback.costs == [3, 1]
+
+
+def test_optimiser_cost_fn():
+    """a python cost function replaces the CX count"""
+    c = Circuit(3).CX(0, 1).CX(0, 1).CX(1, 2)
+    calls = []
+
+    def cost_fn(op: Tk2Op) -> int:
+        calls.append(op)
+        return 10 if op == Tk2Op.CX else 0
+
+    opt = BadgerOptimiser.compile_eccs("test_files/cx_cx_eccs.json", cost_fn)
+    # The cost function is evaluated once per operation type.
+    n_calls = len(calls)
+    assert n_calls == len({op.name for op in calls})
+
+    class Callback:
+        def __init__(self):
+            self.costs = []
+
+        def on_new_best(self, circ, cost):
+            self.costs.append(cost)
+
+    callback = Callback()
+    cc = opt.optimise(c, 3, callback=callback)
+
+    assert cc == Circuit(3).CX(1, 2)
+    assert callback.costs == [30, 10]
+    assert len(calls) == n_calls
@@ -1,5 +1,6 @@
 from pytket import Circuit
 from pytket.qasm import circuit_from_qasm
+from tket2.circuit import Tk2Circuit
 from tket2.pattern import CircuitPattern, PatternMatcher
 
 
@@ -42,3 +43,28 @@ def test_larger_matching():
     matcher = PatternMatcher(iter([p1, p2, p3, p4]))
 
     assert len(matcher.find_matches(c)) == 6
+
+
+def test_step_by_step_rewrite():
+    """find matches, pick one and apply it"""
+    circ = Tk2Circuit(Circuit(2).H(0).H(0).CX(0, 1).H(1).H(1))
+
+    matcher = PatternMatcher(iter([CircuitPattern(Circuit(1).H(0).H(0))]))
+    assert matcher.n_patterns() == 1
+
+    matches = matcher.find_matches(circ)
+    assert len(matches) == 2
+    assert all(len(m.nodes()) == 2 for m in matches)
+
+    # Apply the first rewrite, then look for matches again.
+    rewrite = matches[0].to_rewrite(circ, Circuit(1))
+    assert rewrite.node_count_delta() == -2
+    assert set(rewrite.subcircuit().nodes()) == set(matches[0].nodes())
+    rewrite.apply(circ)
+    assert circ.num_operations() == 3
+
+    matches = matcher.find_matches(circ)
+    assert len(matches) == 1
+    matches[0].to_rewrite(circ, Circuit(1)).apply(circ)
+    assert circ.num_operations() == 1
+    assert matcher.find_matches(circ) == []
@@ -0,0 +1,38 @@
+from pytket import Circuit
+
+from tket2.circuit import Tk2Circuit
+from tket2.pattern import Rule
+from tket2.rewrite import RuleRewriter
+
+
+def test_rule_rewriter():
+    """rules are authored from pytket circuits"""
+    hh = Rule(Circuit(1).H(0).H(0), Circuit(1))
+    cx_flip = Rule(Circuit(2).H(0).H(1).CX(0, 1).H(0).H(1), Circuit(2).CX(1, 0))
+    rewriter = RuleRewriter([hh, cx_flip], names=["hh", "cx_flip"])
+
+    assert len(rewriter) == 2
+    assert rewriter.rule_names() == ["hh", "cx_flip"]
+    assert rewriter.matcher().n_patterns() == 2
+
+    circ = Tk2Circuit(Circuit(2).H(0).H(0).CX(0, 1))
+    rewrites = rewriter.get_rewrites(circ)
+    assert len(rewrites) == 1
+    rewrites[0].apply(circ)
+    assert circ.num_operations() == 1
+
+
+def test_rule_pack(tmp_path):
+    """rule packs can be saved and loaded"""
+    rewriter = RuleRewriter.from_text("hh: H 0; H 0 =>\ncx_cancel: CX 0 1; CX 0 1 =>")
+    path = rewriter.save_binary(tmp_path / "rules")
+    assert path.suffix == ".rwp"
+
+    loaded = RuleRewriter.load_binary(path)
+    assert loaded.rule_names() == ["hh", "cx_cancel"]
+
+    circ = Tk2Circuit(Circuit(2).CX(0, 1).CX(0, 1).H(1).H(1))
+    assert len(loaded.get_rewrites(circ)) == 2
+
+    matcher_path = loaded.matcher().save_binary(tmp_path / "matcher")
+    assert matcher_path.suffix == ".bin"
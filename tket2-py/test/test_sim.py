@@ -0,0 +1,74 @@
+import numpy as np
+import pytest
+from pytket import Circuit
+
+from tket2 import Tket2Error
+from tket2.circuit import Tk2Circuit
+from tket2.sim import (
+    SimulationError,
+    equivalent,
+    probabilities,
+    sample,
+    statevector,
+    unitary,
+)
+
+
+def test_sample_bell():
+    circ = Circuit(2, 2).H(0).CX(0, 1).Measure(0, 0).Measure(1, 1)
+
+    counts = sample(circ, 100, seed=1)
+    assert sum(counts.values()) == 100
+    assert set(counts.keys()) <= {(0, 0), (1, 1)}
+    assert counts == sample(circ, 100, seed=1)
+
+
+def test_sample_mid_circuit():
+    circ = Circuit(1, 2).X(0).Measure(0, 0).X(0).Measure(0, 1)
+
+    assert sample(circ, 10) == {(1, 0): 10}
+
+
+def test_statevector_bell():
+    circ = Tk2Circuit(Circuit(2).H(0).CX(0, 1))
+
+    state = statevector(circ)
+    assert state.shape == (4,)
+    assert np.allclose(state, [1 / np.sqrt(2), 0, 0, 1 / np.sqrt(2)])
+    assert np.allclose(probabilities(circ), [0.5, 0, 0, 0.5])
+
+
+def test_statevector_little_endian():
+    # Qubit 0 is the least significant bit of the index.
+    state = statevector(Circuit(2).X(0))
+    assert np.allclose(state, [0, 1, 0, 0])
+
+
+def test_unitary():
+    u = unitary(Circuit(1).H(0))
+    assert u.shape == (2, 2)
+    assert np.allclose(u, np.array([[1, 1], [1, -1]]) / np.sqrt(2))
+
+    cx = Circuit(2).CX(0, 1)
+    assert np.allclose(unitary(cx) @ unitary(cx), np.eye(4))
+
+
+def test_equivalent():
+    flipped = Circuit(2).H(0).H(1).CX(0, 1).H(0).H(1)
+    assert equivalent(flipped, Circuit(2).CX(1, 0))
+    assert not equivalent(flipped, Circuit(2).CX(0, 1))
+    # Equal up to a global phase.
+    assert equivalent(Circuit(1).Z(0).X(0), Circuit(1).Y(0))
+
+
+def test_simulation_error_diagnostic():
+    circ = Circuit(1, 1).H(0).Measure(0, 0)
+
+    with pytest.raises(SimulationError) as exc_info:
+        unitary(circ)
+    err = exc_info.value
+    assert isinstance(err, Tket2Error)
+    assert err.code == "E0300"
+    assert err.op is not None
+    assert err.node is not None
+    assert str(err).startswith("error[E0300]:")
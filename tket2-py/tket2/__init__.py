@@ -1,3 +1,13 @@
-from . import circuit, ops, optimiser, passes, pattern, rewrite
+from . import circuit, ops, optimiser, passes, pattern, rewrite, sim
+from ._tket2 import Tket2Error
 
-__all__ = ["circuit", "ops", "optimiser", "passes", "pattern", "rewrite"]
+__all__ = [
+    "circuit",
+    "ops",
+    "optimiser",
+    "passes",
+    "pattern",
+    "rewrite",
+    "sim",
+    "Tket2Error",
+]
@@ -0,0 +1,13 @@
+from tket2._tket2.circuit import Node
+
+class Tket2Error(Exception):
+    """Base class for errors raised by tket2 operations."""
+
+    code: str
+    """Stable identifier of the kind of error, e.g. `E0300`."""
+
+    node: Node | None
+    """The node where the error originated, if known."""
+
+    op: str | None
+    """The name of the operation that caused the error, if known."""
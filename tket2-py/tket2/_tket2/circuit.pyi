@@ -1,6 +1,7 @@
 from typing import Any, Callable
 from pytket._tket.circuit import Circuit as Tk1Circuit
 
+from tket2._tket2 import Tket2Error
 from tket2._tket2.ops import Tk2Op
 
 class Tk2Circuit:
@@ -32,6 +33,9 @@ class Tk2Circuit:
         Nested circuits are traversed to count their operations.
         """
 
+    def qubits(self) -> list[Qubit]:
+        """The qubits of the circuit, in input order."""
+
     def node_op(self, node: Node) -> bytes:
         """If the node corresponds to a custom op, return it. Otherwise, raise an error."""
 
@@ -86,6 +90,16 @@ class Wire:
     def port(self) -> int:
         """Source port of wire."""
 
+class Qubit:
+    """The index of a qubit in a circuit."""
+
+    def __init__(self, index: int) -> None:
+        """Create a new qubit handle."""
+
+    @property
+    def index(self) -> int:
+        """Index of the qubit."""
+
 class CircuitCost:
     """A cost function for circuits."""
 
@@ -102,4 +116,4 @@ class HugrError(Exception): ...
 class BuildError(Exception): ...
 class ValidationError(Exception): ...
 class HUGRSerializationError(Exception): ...
-class TK1ConvertError(Exception): ...
+class TK1ConvertError(Tket2Error): ...
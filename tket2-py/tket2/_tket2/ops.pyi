@@ -1,6 +1,8 @@
 from enum import Enum
 from typing import Any, Iterable
 
+from tket2._tket2 import Tket2Error
+from tket2._tket2.circuit import Tk2Circuit
 from tket2._tket2.types import HugrType
 
 class Tk2Op(Enum):
@@ -82,3 +84,13 @@ class CustomOp:
     @property
     def name(self) -> str:
         """Fully qualified (including extension) name of the operation."""
+
+class ControlError(Tket2Error):
+    """Error while building a controlled operation."""
+
+def controlled(op: Tk2Op, n_controls: int, params: list[float] = []) -> Tk2Circuit:
+    """Build a circuit applying an operation controlled by `n_controls` qubits.
+
+    The circuit acts on the control qubits followed by the qubits of the
+    operation. Angle parameters are given in radians.
+    """
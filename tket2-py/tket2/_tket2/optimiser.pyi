@@ -1,5 +1,6 @@
-from typing import TypeVar
+from typing import Any, Callable, TypeVar
 from .circuit import Tk2Circuit
+from .ops import Tk2Op
 from pytket._tket.circuit import Circuit
 
 from pathlib import Path
@@ -8,12 +9,26 @@ CircuitClass = TypeVar("CircuitClass", Circuit, Tk2Circuit)
 
 class BadgerOptimiser:
     @staticmethod
-    def load_precompiled(filename: Path) -> BadgerOptimiser:
-        """Load a precompiled rewriter from a file."""
+    def load_precompiled(
+        filename: Path, cost_fn: Callable[[Tk2Op], int] | None = None
+    ) -> BadgerOptimiser:
+        """Load a precompiled rewriter from a file.
+
+        :param cost_fn: A function mapping each operation to a non-negative integer
+            cost, minimised instead of the CX count. It is called once per operation
+            type when the optimiser is created.
+        """
 
     @staticmethod
-    def compile_eccs(filename: Path) -> BadgerOptimiser:
-        """Compile a set of ECCs and create a new rewriter ."""
+    def compile_eccs(
+        filename: Path, cost_fn: Callable[[Tk2Op], int] | None = None
+    ) -> BadgerOptimiser:
+        """Compile a set of ECCs and create a new rewriter.
+
+        :param cost_fn: A function mapping each operation to a non-negative integer
+            cost, minimised instead of the CX count. It is called once per operation
+            type when the optimiser is created.
+        """
 
     def optimise(
         self,
@@ -22,8 +37,18 @@ class BadgerOptimiser:
         progress_timeout: int | None = None,
         n_threads: int | None = None,
         split_circ: bool = False,
+        split_overlap: int | None = None,
+        split_rounds: int | None = None,
+        split_offsets: int | None = None,
         queue_size: int | None = None,
         log_progress: Path | None = None,
+        match_radius: int | None = None,
+        compact_queue: bool | None = None,
+        max_seen_memory: int | None = None,
+        canonical_hashing: bool | None = None,
+        seed: int | None = None,
+        profile: Path | None = None,
+        callback: Any | None = None,
     ) -> CircuitClass:
         """Optimise a circuit.
 
@@ -32,6 +57,18 @@ class BadgerOptimiser:
         :param progress_timeout: Maximum time to wait between new best results.
         :param n_threads: Number of threads to use.
         :param split_circ: Split the circuit into subcircuits and optimise them separately.
+        :param split_overlap: Number of operations on each side of a subcircuit boundary to re-optimise after merging.
+        :param split_rounds: Maximum number of rounds of split optimisation, shifting the subcircuit boundaries each round.
+        :param split_offsets: Number of distinct subcircuit boundary offsets to cycle through over the rounds.
         :param queue_size: Maximum number of circuits to keep in the queue of candidates.
         :param log_progress: Log progress to a CSV file.
+        :param match_radius: Only re-match rewrites within this radius of the nodes modified by a rewrite.
+        :param compact_queue: Store the pure quantum circuits of the queue in a compact form, replacing their angle computations by constants.
+        :param max_seen_memory: Maximum memory in bytes used to record seen circuits, after which they are tracked approximately.
+        :param canonical_hashing: Identify circuits that only differ by the order of commuting gates. Slower to compute.
+        :param seed: Explore the rewrites of each circuit in a random order drawn from this seed.
+        :param profile: A JSON rewrite profile of previous runs. The rewrites of the rule classes and regions that led to their best circuits are explored first.
+        :param callback: An object notified of the progress of the optimisation, defining any of the methods
+            `on_new_best(circ, cost)`, `on_progress(circuits_processed, circuits_seen, queue_length, elapsed)`
+            and `on_timeout(circ, cost)`. Exceptions raised by the callbacks are re-raised after the optimisation.
         """
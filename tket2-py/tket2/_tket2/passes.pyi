@@ -1,6 +1,7 @@
 from pathlib import Path
 from typing import TypeVar
 
+from . import Tket2Error
 from .optimiser import BadgerOptimiser
 from .circuit import Tk2Circuit
 from pytket._tket.circuit import Circuit
@@ -17,7 +18,7 @@ class CircuitChunks:
     def update_circuit(self, index: int, circ: Circuit | Tk2Circuit) -> None:
         """Replace a circuit chunk with a new version."""
 
-class PullForwardError(Exception):
+class PullForwardError(Tket2Error):
     """Error from a `PullForward` operation."""
 
 def greedy_depth_reduce(circ: CircuitClass) -> tuple[CircuitClass, int]:
@@ -1,6 +1,7 @@
+from pathlib import Path
 from typing import Iterator
 from .circuit import Node, Tk2Circuit
-from .rewrite import CircuitRewrite
+from .rewrite import CircuitRewrite, Subcircuit
 from pytket._tket.circuit import Circuit as Tk1Circuit
 
 class Rule:
@@ -55,6 +56,29 @@ class PatternMatcher:
     def find_matches(self, circ: Tk2Circuit) -> list[PatternMatch]:
         """Find all matches of the patterns in the circuit."""
 
+    def find_matches_at(self, circ: Tk2Circuit, roots: list[Node]) -> list[PatternMatch]:
+        """Find all matches of the patterns in the circuit rooted at one of the given nodes."""
+
+    def n_patterns(self) -> int:
+        """The number of patterns in the matcher."""
+
+    def get_pattern(self, id: PatternID) -> CircuitPattern | None:
+        """Get a pattern by its id."""
+
+    def save_binary(self, path: Path) -> Path:
+        """Save the compiled matcher as a binary file.
+
+        The file extension is set to `.bin`. Returns the path of the new file.
+        """
+
+    @staticmethod
+    def load_binary(path: Path) -> PatternMatcher:
+        """Load a matcher saved with `save_binary`."""
+
+    @staticmethod
+    def migrate_binary(path: Path) -> None:
+        """Rewrite a matcher file saved by an earlier release in the current format."""
+
 class PatternMatch:
     """A convex pattern match in a circuit"""
 
@@ -64,6 +88,20 @@ class PatternMatch:
     def root(self) -> Node:
         """The root node for the pattern in the matched circuit."""
 
+    def nodes(self) -> list[Node]:
+        """The nodes of the circuit matched by the pattern."""
+
+    def subcircuit(self) -> Subcircuit:
+        """The subcircuit matched by the pattern."""
+
+    def to_rewrite(
+        self, circ: Tk2Circuit, replacement: Tk1Circuit | Tk2Circuit
+    ) -> CircuitRewrite:
+        """Construct a rewrite replacing the match with a new circuit.
+
+        `circ` must be the circuit the match was found in.
+        """
+
 class PatternID:
     """An identifier for a pattern in a pattern matcher."""
 
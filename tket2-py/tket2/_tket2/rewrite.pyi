@@ -1,5 +1,7 @@
 from pathlib import Path
+from tket2._tket2 import Tket2Error
 from tket2._tket2.circuit import Node, Tk2Circuit
+from tket2._tket2.pattern import PatternMatcher, Rule
 
 class ECCRewriter:
     @staticmethod
@@ -9,6 +11,51 @@ class ECCRewriter:
     def get_rewrites(self, circ: Tk2Circuit) -> list[CircuitRewrite]:
         """Get rewrites for a circuit."""
 
+class RuleRewriter:
+    """A rewriter applying a set of user-defined rewrite rules."""
+
+    def __init__(self, rules: list[Rule], names: list[str] | None = None) -> None:
+        """Compile a list of rules into a rewriter.
+
+        Rules are named `rule_{i}` unless `names` are given.
+        """
+
+    @staticmethod
+    def from_text(text: str) -> RuleRewriter:
+        """Parse rules in the text format."""
+
+    @staticmethod
+    def load(path: Path) -> RuleRewriter:
+        """Load rules from a text or JSON file."""
+
+    @staticmethod
+    def load_binary(path: Path) -> RuleRewriter:
+        """Load a binary rule pack saved with `save_binary`."""
+
+    def save_binary(self, path: Path) -> Path:
+        """Save the rules and their compiled matcher as a binary rule pack.
+
+        The file extension is set to `.rwp`. Returns the path of the new file.
+        """
+
+    def rules(self) -> list[Rule]:
+        """The rules of the rewriter."""
+
+    def rule_names(self) -> list[str]:
+        """The names of the rules of the rewriter."""
+
+    def matcher(self) -> PatternMatcher:
+        """The compiled matcher for the left-hand sides of the rules.
+
+        The id of each pattern is the index of its rule.
+        """
+
+    def get_rewrites(self, circ: Tk2Circuit) -> list[CircuitRewrite]:
+        """Get rewrites for a circuit."""
+
+    def __len__(self) -> int:
+        """The number of rules."""
+
 class CircuitRewrite:
     """A rewrite rule for circuits."""
 
@@ -29,8 +76,36 @@ class CircuitRewrite:
     def replacement(self) -> Tk2Circuit:
         """The replacement circuit."""
 
+    def subcircuit(self) -> Subcircuit:
+        """The subcircuit that is replaced."""
+
+    def invalidation_set(self) -> list[Node]:
+        """The nodes referenced by the rewrite.
+
+        Two rewrites can be applied one after the other if their invalidation
+        sets are disjoint.
+        """
+
+    def apply(self, circ: Tk2Circuit) -> None:
+        """Apply the rewrite to a circuit, in place.
+
+        The circuit must be the one the rewrite was created for.
+        """
+
 class Subcircuit:
     """A subcircuit of a circuit."""
 
     def __init__(self, nodes: list[Node], circ: Tk2Circuit) -> None:
         """Create a new subcircuit."""
+
+    def nodes(self) -> list[Node]:
+        """The nodes of the subcircuit."""
+
+    def node_count(self) -> int:
+        """Number of nodes in the subcircuit."""
+
+class RuleError(Tket2Error):
+    """An error occurred while loading rewrite rules."""
+
+class SimpleReplacementError(Exception):
+    """An error occurred while applying a rewrite to a circuit."""
@@ -0,0 +1,45 @@
+from typing import TypeVar
+
+import numpy as np
+from . import Tket2Error
+from .circuit import Tk2Circuit
+from pytket._tket.circuit import Circuit
+
+CircuitClass = TypeVar("CircuitClass", Circuit, Tk2Circuit)
+
+class SimulationError(Tket2Error):
+    """Errors that can occur while simulating a circuit."""
+
+def sample(
+    circ: CircuitClass, shots: int, seed: int | None = None
+) -> dict[tuple[int, ...], int]:
+    """Run a circuit `shots` times and count the observed classical outputs.
+
+    Returns a dictionary from tuples of bit values to the number of times they
+    were observed, in the same format as pytket's `BackendResult.get_counts`.
+    If no `seed` is given, a random one is used.
+    """
+
+def statevector(circ: CircuitClass) -> np.ndarray:
+    """Compute the final statevector of a pure circuit.
+
+    Returns a one-dimensional array of `2^n` complex amplitudes. Basis states are
+    indexed in little-endian order: qubit `i` corresponds to the `i`-th least
+    significant bit of the index.
+    """
+
+def probabilities(circ: CircuitClass) -> np.ndarray:
+    """Compute the probability of each basis state at the end of a pure circuit.
+
+    The probabilities are indexed as in `statevector`.
+    """
+
+def unitary(circ: CircuitClass) -> np.ndarray:
+    """Compute the unitary matrix implemented by a pure circuit.
+
+    Entry `(i, j)` is the amplitude of basis state `i` after applying the circuit
+    to basis state `j`, with basis states indexed as in `statevector`.
+    """
+
+def equivalent(circ1: CircuitClass, circ2: CircuitClass, tol: float = 1e-9) -> bool:
+    """Check whether two pure circuits implement the same unitary, up to a global phase."""
@@ -4,6 +4,7 @@ from .._tket2.circuit import (
     Tk2Circuit,
     Node,
     Wire,
+    Qubit,
     CircuitCost,
     validate_circuit,
     render_circuit_dot,
@@ -25,6 +26,7 @@ __all__ = [
     "Tk2Circuit",
     "Node",
     "Wire",
+    "Qubit",
     "CircuitCost",
     "validate_circuit",
     "render_circuit_dot",
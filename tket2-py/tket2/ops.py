@@ -5,10 +5,11 @@ from typing import Protocol
 
 import tket2
 
-from tket2._tket2.ops import CustomOp
+from tket2._tket2.ops import CustomOp, ControlError
+from tket2.circuit import Tk2Circuit
 from tket2.types import QB_T
 
-__all__ = ["CustomOp", "ToCustomOp", "Tk2Op", "Pauli"]
+__all__ = ["CustomOp", "ToCustomOp", "Tk2Op", "Pauli", "controlled", "ControlError"]
 
 
 class ToCustomOp(Protocol):
@@ -51,6 +52,10 @@ class Tk2Op(Enum):
     QAlloc = auto()
     QFree = auto()
     Reset = auto()
+    XXPhase = auto()
+    YYPhase = auto()
+    CCX = auto()
+    CCZ = auto()
 
     def to_custom(self) -> CustomOp:
         """Convert to a custom operation."""
@@ -110,3 +115,14 @@ class Pauli(Enum):
         elif isinstance(other, str):
             return self.name == other
         return False
+
+
+def controlled(
+    op: Tk2Op, n_controls: int, params: list[float] | None = None
+) -> Tk2Circuit:
+    """Build a circuit applying `op` controlled by `n_controls` qubits.
+
+    The circuit acts on the control qubits followed by the qubits of the
+    operation. Angle parameters are given in radians.
+    """
+    return tket2._tket2.ops.controlled(op._to_rs(), n_controls, params or [])
@@ -1,10 +1,11 @@
 from pathlib import Path
-from typing import Optional
+from typing import Callable, Optional
 
 from pytket import Circuit
 from pytket.passes import CustomPass, BasePass
 
 from tket2 import optimiser
+from tket2.ops import Tk2Op
 
 # Re-export native bindings
 from ._tket2.passes import (
@@ -37,6 +38,7 @@ def badger_pass(
     max_circuit_count: Optional[int] = None,
     log_dir: Optional[Path] = None,
     rebase: bool = False,
+    cost_fn: Optional[Callable[[Tk2Op], int]] = None,
 ) -> BasePass:
     """Construct a Badger pass.
 
@@ -46,7 +48,10 @@ def badger_pass(
 
     The arguments `max_threads`, `timeout`, `progress_timeout`, `max_circuit_count`,
     `log_dir` and `rebase` are optional and will be passed on to the Badger
-    optimiser if provided."""
+    optimiser if provided.
+
+    If `cost_fn` is given, it maps each operation to an integer cost that is
+    minimised instead of the CX count."""
     if rewriter is None:
         try:
             import tket2_eccs
@@ -56,7 +61,7 @@ def badger_pass(
             )
 
         rewriter = tket2_eccs.nam_6_3()
-    opt = optimiser.BadgerOptimiser.load_precompiled(rewriter)
+    opt = optimiser.BadgerOptimiser.load_precompiled(rewriter, cost_fn)
 
     def apply(circuit: Circuit) -> Circuit:
         """Apply Badger optimisation to the circuit."""
@@ -1,13 +1,23 @@
 # Re-export native bindings
-from ._tket2.rewrite import ECCRewriter, CircuitRewrite, Subcircuit
+from ._tket2.rewrite import (
+    ECCRewriter,
+    RuleRewriter,
+    CircuitRewrite,
+    Subcircuit,
+    RuleError,
+    SimpleReplacementError,
+)
 
 __all__ = [
     "default_ecc_rewriter",
     # Bindings.
     # TODO: Wrap these in Python classes.
     "ECCRewriter",
+    "RuleRewriter",
     "CircuitRewrite",
     "Subcircuit",
+    "RuleError",
+    "SimpleReplacementError",
 ]
 
 
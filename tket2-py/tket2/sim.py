@@ -0,0 +1,18 @@
+# Re-export native bindings
+from ._tket2.sim import (
+    sample,
+    statevector,
+    probabilities,
+    unitary,
+    equivalent,
+    SimulationError,
+)
+
+__all__ = [
+    "sample",
+    "statevector",
+    "probabilities",
+    "unitary",
+    "equivalent",
+    "SimulationError",
+]
@@ -0,0 +1,400 @@
+//! Execution backends for quantum circuits.
+//!
+//! A [`Backend`] describes a target that can run circuits: the gate set it
+//! supports natively, the number of qubits it provides, and an asynchronous
+//! job interface to submit circuits and poll for their results. Hardware
+//! clients implement the trait in downstream crates, and [`LocalSimulator`]
+//! provides an implementation running circuits on the statevector simulator
+//! of [`crate::sim`].
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use hugr::ops::{NamedOp, OpType};
+use hugr::Node;
+use strum::IntoEnumIterator;
+use thiserror::Error;
+
+use crate::passes::rebase::{rebase, GateSet, NativeGate, RebaseError};
+use crate::seed::{self, Seed};
+use crate::sim::{sample, Counts, SimulationError};
+use crate::{Circuit, Tk2Op};
+
+/// The interval between two polls of a job in [`Backend::run`].
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An identifier for a job submitted to a [`Backend`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JobHandle(String);
+
+impl JobHandle {
+    /// Create a handle from a backend-specific job identifier.
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    /// The backend-specific job identifier.
+    pub fn id(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for JobHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The state of a job submitted to a [`Backend`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum JobStatus {
+    /// The job is waiting to be run.
+    Queued,
+    /// The job is running.
+    Running,
+    /// The job finished, and its results are available.
+    Completed,
+    /// The job failed, with a message from the backend.
+    Failed(String),
+}
+
+/// A target that can run quantum circuits.
+///
+/// Circuits are prepared for the backend with [`Backend::compile`], checked
+/// with [`Backend::validate`], and submitted with [`Backend::submit`]. The
+/// returned [`JobHandle`] is used to poll the [`JobStatus`] of the job and to
+/// retrieve its measurement counts.
+pub trait Backend {
+    /// The name of the backend.
+    fn name(&self) -> &str;
+
+    /// The gate set supported natively by the backend.
+    fn gate_set(&self) -> &GateSet;
+
+    /// The number of qubits provided by the backend.
+    fn max_qubits(&self) -> usize;
+
+    /// Submit a circuit to be run `shots` times.
+    ///
+    /// The circuit should have been compiled for the backend.
+    fn submit(&mut self, circ: &Circuit, shots: usize) -> Result<JobHandle, BackendError>;
+
+    /// The status of a submitted job.
+    fn status(&self, job: &JobHandle) -> Result<JobStatus, BackendError>;
+
+    /// The measurement counts of a job, or `None` if it has not completed yet.
+    ///
+    /// See [`crate::sim::Counts`] for the format of the counts.
+    fn results(&self, job: &JobHandle) -> Result<Option<Counts>, BackendError>;
+
+    /// Compile a circuit for the backend.
+    ///
+    /// By default, rebases the circuit to the gate set of the backend.
+    fn compile(&self, circ: &mut Circuit) -> Result<(), BackendError> {
+        rebase(circ, self.gate_set())?;
+        Ok(())
+    }
+
+    /// Check that a circuit can be submitted to the backend.
+    ///
+    /// By default, checks the number of qubits of the circuit and that all its
+    /// gates are native.
+    fn validate(&self, circ: &Circuit) -> Result<(), BackendError> {
+        let qubits = circ.qubit_count();
+        if qubits > self.max_qubits() {
+            return Err(BackendError::TooManyQubits {
+                qubits,
+                max: self.max_qubits(),
+            });
+        }
+        for cmd in circ.commands() {
+            let Some(gate) = NativeGate::from_optype(cmd.optype()) else {
+                continue;
+            };
+            if !self.gate_set().is_native(&gate) {
+                return Err(BackendError::UnsupportedGate {
+                    optype: cmd.optype().clone(),
+                    node: cmd.node(),
+                    backend: self.name().to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile, validate and submit a circuit, then wait for its results.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the steps fails, or if the job fails.
+    fn run(&mut self, circ: &Circuit, shots: usize) -> Result<Counts, BackendError> {
+        let mut circ = circ.clone();
+        self.compile(&mut circ)?;
+        self.validate(&circ)?;
+        let job = self.submit(&circ, shots)?;
+        loop {
+            if let JobStatus::Failed(message) = self.status(&job)? {
+                return Err(BackendError::JobFailed { job, message });
+            }
+            if let Some(counts) = self.results(&job)? {
+                return Ok(counts);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// A backend running circuits on the local statevector simulator.
+///
+/// Jobs are run synchronously on submission. Each job is seeded with a seed
+/// [derived](crate::seed::derive) from the seed of the simulator and the index
+/// of the job, so the results of a sequence of submissions are reproducible.
+#[derive(Debug, Clone)]
+pub struct LocalSimulator {
+    gate_set: GateSet,
+    max_qubits: usize,
+    seed: Seed,
+    jobs: BTreeMap<JobHandle, Result<Counts, SimulationError>>,
+}
+
+impl LocalSimulator {
+    /// The default number of qubits of the simulator.
+    pub const DEFAULT_MAX_QUBITS: usize = 20;
+
+    /// Create a simulator supporting all the quantum TKET2 operations.
+    pub fn new() -> Self {
+        let native = Tk2Op::iter().filter(Tk2Op::is_quantum);
+        Self {
+            gate_set: GateSet::new("local", native),
+            max_qubits: Self::DEFAULT_MAX_QUBITS,
+            seed: 0,
+            jobs: BTreeMap::new(),
+        }
+    }
+
+    /// Set the number of qubits of the simulator.
+    ///
+    /// The memory used by the simulator grows exponentially with the number
+    /// of qubits.
+    pub fn with_max_qubits(mut self, max_qubits: usize) -> Self {
+        self.max_qubits = max_qubits;
+        self
+    }
+
+    /// Set the seed used to sample the measurement outcomes.
+    pub fn with_seed(mut self, seed: Seed) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+impl Default for LocalSimulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for LocalSimulator {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn gate_set(&self) -> &GateSet {
+        &self.gate_set
+    }
+
+    fn max_qubits(&self) -> usize {
+        self.max_qubits
+    }
+
+    fn submit(&mut self, circ: &Circuit, shots: usize) -> Result<JobHandle, BackendError> {
+        self.validate(circ)?;
+        let index = self.jobs.len() as u64;
+        let job = JobHandle::new(format!("local-{index}"));
+        let counts = sample(circ, shots, seed::derive(self.seed, index));
+        self.jobs.insert(job.clone(), counts);
+        Ok(job)
+    }
+
+    fn status(&self, job: &JobHandle) -> Result<JobStatus, BackendError> {
+        match self.jobs.get(job) {
+            Some(Ok(_)) => Ok(JobStatus::Completed),
+            Some(Err(e)) => Ok(JobStatus::Failed(e.to_string())),
+            None => Err(BackendError::UnknownJob(job.clone())),
+        }
+    }
+
+    fn results(&self, job: &JobHandle) -> Result<Option<Counts>, BackendError> {
+        match self.jobs.get(job) {
+            Some(Ok(counts)) => Ok(Some(counts.clone())),
+            Some(Err(e)) => Err(e.clone().into()),
+            None => Err(BackendError::UnknownJob(job.clone())),
+        }
+    }
+}
+
+/// Errors that can occur when running circuits on a [`Backend`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum BackendError {
+    /// The circuit uses more qubits than the backend provides.
+    #[error("The circuit uses {qubits} qubits, but the backend only provides {max}.")]
+    TooManyQubits {
+        /// The number of qubits of the circuit.
+        qubits: usize,
+        /// The number of qubits of the backend.
+        max: usize,
+    },
+    /// The circuit contains a gate that is not native to the backend.
+    #[error("Operation {} in {node} is not supported by backend {backend}.", optype.name())]
+    UnsupportedGate {
+        /// The unsupported operation.
+        optype: OpType,
+        /// The node.
+        node: Node,
+        /// The name of the backend.
+        backend: String,
+    },
+    /// No job was submitted with the handle.
+    #[error("Unknown job {0}.")]
+    UnknownJob(JobHandle),
+    /// The job failed on the backend.
+    #[error("Job {job} failed: {message}")]
+    JobFailed {
+        /// The failed job.
+        job: JobHandle,
+        /// The message reported by the backend.
+        message: String,
+    },
+    /// The circuit could not be compiled to the gate set of the backend.
+    #[error(transparent)]
+    Compilation(#[from] RebaseError),
+    /// The circuit could not be simulated.
+    #[error(transparent)]
+    Simulation(#[from] SimulationError),
+    /// An error reported by the client of a hardware backend.
+    #[error("Backend client error: {0}")]
+    Client(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test::tk1_circuit;
+    const MEASURED_BELL: &str = r#"
+        {"args": [["q", [0]]], "op": {"type": "H"}},
+        {"args": [["q", [0]], ["q", [1]]], "op": {"type": "CX"}},
+        {"args": [["q", [0]], ["c", [0]]], "op": {"type": "Measure"}},
+        {"args": [["q", [1]], ["c", [1]]], "op": {"type": "Measure"}}"#;
+
+    #[test]
+    fn run_on_local_simulator() {
+        let circ = tk1_circuit(2, 2, MEASURED_BELL);
+        let mut backend = LocalSimulator::new().with_seed(7);
+
+        let counts = backend.run(&circ, 100).unwrap();
+        assert_eq!(counts.values().sum::<usize>(), 100);
+        assert!(counts
+            .keys()
+            .all(|outcome| outcome == &vec![false, false] || outcome == &vec![true, true]));
+
+        // The same sequence of jobs gives the same results.
+        let mut other = LocalSimulator::new().with_seed(7);
+        assert_eq!(other.run(&circ, 100).unwrap(), counts);
+    }
+
+    #[test]
+    fn submit_and_poll() {
+        let circ = tk1_circuit(2, 2, MEASURED_BELL);
+        let mut backend = LocalSimulator::new();
+
+        let job = backend.submit(&circ, 10).unwrap();
+        assert_eq!(backend.status(&job).unwrap(), JobStatus::Completed);
+        let counts = backend.results(&job).unwrap().unwrap();
+        assert_eq!(counts.values().sum::<usize>(), 10);
+
+        let unknown = JobHandle::new("missing");
+        assert!(matches!(
+            backend.status(&unknown),
+            Err(BackendError::UnknownJob(_))
+        ));
+    }
+
+    #[test]
+    fn failed_job() {
+        // Symbolic angles cannot be simulated.
+        let circ = tk1_circuit(
+            2,
+            2,
+            r#"{"args": [["q", [0]]], "op": {"type": "Rz", "params": ["a"]}},
+               {"args": [["q", [0]], ["c", [0]]], "op": {"type": "Measure"}}"#,
+        );
+        let mut backend = LocalSimulator::new();
+
+        let job = backend.submit(&circ, 10).unwrap();
+        assert!(matches!(
+            backend.status(&job).unwrap(),
+            JobStatus::Failed(_)
+        ));
+        assert!(matches!(
+            backend.run(&circ, 10),
+            Err(BackendError::JobFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn too_many_qubits() {
+        let circ = tk1_circuit(3, 2, MEASURED_BELL);
+        let mut backend = LocalSimulator::new().with_max_qubits(2);
+        assert!(matches!(
+            backend.run(&circ, 10),
+            Err(BackendError::TooManyQubits { qubits: 3, max: 2 })
+        ));
+    }
+
+    #[test]
+    fn compile_to_gate_set() {
+        let circ = tk1_circuit(2, 2, MEASURED_BELL);
+        let mut backend = QuantinuumLike(LocalSimulator::new(), GateSet::quantinuum());
+
+        assert!(matches!(
+            backend.validate(&circ),
+            Err(BackendError::UnsupportedGate { .. })
+        ));
+        let counts = backend.run(&circ, 50).unwrap();
+        assert_eq!(counts.values().sum::<usize>(), 50);
+    }
+
+    /// A simulator with a restricted gate set.
+    struct QuantinuumLike(LocalSimulator, GateSet);
+
+    impl Backend for QuantinuumLike {
+        fn name(&self) -> &str {
+            "quantinuum-like"
+        }
+
+        fn gate_set(&self) -> &GateSet {
+            &self.1
+        }
+
+        fn max_qubits(&self) -> usize {
+            self.0.max_qubits()
+        }
+
+        fn submit(&mut self, circ: &Circuit, shots: usize) -> Result<JobHandle, BackendError> {
+            self.validate(circ)?;
+            self.0.submit(circ, shots)
+        }
+
+        fn status(&self, job: &JobHandle) -> Result<JobStatus, BackendError> {
+            self.0.status(job)
+        }
+
+        fn results(&self, job: &JobHandle) -> Result<Option<Counts>, BackendError> {
+            self.0.results(job)
+        }
+    }
+}
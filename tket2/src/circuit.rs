@@ -1,17 +1,36 @@
 //! Quantum circuit representation and operations.
 
+pub mod analysis;
+pub mod chunks;
 pub mod command;
+pub mod compact;
 pub mod cost;
+pub mod dagger;
+pub mod edit;
+pub mod extract;
 mod extract_dfg;
+pub mod frozen;
+pub mod generators;
 mod hash;
+mod isomorphism;
+pub mod opgroup;
+pub mod parameters;
+pub mod permutation;
+pub mod phase;
+pub mod slices;
+pub(crate) mod text_diagram;
 pub mod units;
+pub mod validate;
+pub mod watermark;
 
 use std::iter::Sum;
 
-pub use command::{Command, CommandIterator};
-pub use hash::CircuitHash;
+pub use chunks::CircuitChunks;
+pub use command::{Command, CommandIterator, OwnedCommand};
+pub use hash::{CircuitHash, HashError};
 use hugr::hugr::views::{DescendantsGraph, ExtractHugr, HierarchyView};
 use itertools::Either::{Left, Right};
+pub use slices::Moment;
 
 use hugr::hugr::hugrmut::HugrMut;
 use hugr::ops::dataflow::IOTrait;
@@ -20,6 +39,8 @@ use hugr::types::{PolyFuncType, Signature};
 use hugr::{Hugr, PortIndex};
 use hugr::{HugrView, OutgoingPort};
 use itertools::Itertools;
+use ndarray::Array2;
+use num_complex::Complex64;
 use thiserror::Error;
 
 pub use hugr::ops::OpType;
@@ -27,6 +48,7 @@ pub use hugr::types::{EdgeKind, Type, TypeRow};
 pub use hugr::{Node, Port, Wire};
 
 use self::units::{filter, LinearUnit, Units};
+use crate::sim::SimulationError;
 
 /// A quantum circuit, represented as a function in a HUGR.
 #[derive(Debug, Clone, PartialEq)]
@@ -237,6 +259,22 @@ impl<T: HugrView> Circuit<T> {
         CommandIterator::new(self)
     }
 
+    /// Returns a snapshot of the commands in the circuit, in some topological
+    /// order.
+    ///
+    /// Unlike [`Circuit::commands`], the returned iterator does not borrow the
+    /// circuit, so the circuit can be modified while iterating. The commands
+    /// are collected eagerly, and do not reflect later changes to the circuit.
+    pub fn commands_owned(&self) -> std::vec::IntoIter<OwnedCommand>
+    where
+        Self: Sized,
+    {
+        self.commands()
+            .map(OwnedCommand::from)
+            .collect_vec()
+            .into_iter()
+    }
+
     /// Returns the top-level operations in the circuit, in some topological
     /// order.
     ///
@@ -253,6 +291,21 @@ impl<T: HugrView> Circuit<T> {
         self.commands().filter(|cmd| cmd.optype().is_custom_op())
     }
 
+    /// Compute the dense unitary matrix implemented by the circuit.
+    ///
+    /// See [`crate::sim::unitary`] for the matrix conventions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the circuit contains non-unitary operations, or
+    /// operations that cannot be simulated.
+    pub fn unitary(&self) -> Result<Array2<Complex64>, SimulationError>
+    where
+        Self: Sized,
+    {
+        crate::sim::unitary::unitary(self)
+    }
+
     /// Compute the cost of the circuit based on a per-operation cost function.
     #[inline]
     pub fn circuit_cost<F, C>(&self, op_cost: F) -> C
@@ -278,6 +331,20 @@ impl<T: HugrView> Circuit<T> {
             .sum()
     }
 
+    /// Returns `true` if the two circuits have the same structure.
+    ///
+    /// Checks whether there is a graph isomorphism between the operations of
+    /// both circuits' regions that preserves the operation types and the port
+    /// offsets of every wire. In particular, the linear units must be in the
+    /// same order in both circuits.
+    ///
+    /// Unlike comparing [`CircuitHash::circuit_hash`]es, this check has no
+    /// false positives. Nested regions are not compared, only the operations
+    /// of the container nodes.
+    pub fn equal_structure(&self, other: &Circuit<impl HugrView>) -> bool {
+        isomorphism::equal_structure(self, other)
+    }
+
     /// Return the graphviz representation of the underlying graph and hierarchy side by side.
     ///
     /// For a simpler representation, use the [`Circuit::mermaid_string`] format instead.
@@ -604,7 +671,7 @@ mod tests {
     };
 
     use super::*;
-    use crate::serialize::load_tk1_json_str;
+    use crate::serialize::{load_tk1_json_str, DecodeOptions};
     use crate::utils::{build_module_with_circuit, build_simple_circuit};
     use crate::Tk2Op;
 
@@ -623,6 +690,7 @@ mod tests {
             ],
             "implicit_permutation": [[["q", [0]], ["q", [0]]], [["q", [1]], ["q", [1]]]]
         }"#,
+            DecodeOptions::default(),
         )
         .unwrap()
     }
@@ -684,6 +752,36 @@ mod tests {
         assert_eq!(circ.qubits().count(), qubits);
     }
 
+    #[test]
+    fn equal_structure() {
+        let reordered = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::T, [1])?;
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::T, [1])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        let flipped = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::T, [1])?;
+            circ.append(Tk2Op::CX, [1, 0])?;
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(circ.equal_structure(&reordered));
+        assert!(!circ.equal_structure(&flipped));
+        assert!(!circ.equal_structure(&simple_circuit()));
+        assert!(simple_circuit().equal_structure(&simple_module()));
+    }
+
     #[test]
     fn remove_qubit() {
         let mut circ = build_simple_circuit(2, |circ| {
@@ -701,6 +799,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn qubit_units() {
+        use self::units::Qubit;
+
+        let (q0, q1) = (Qubit::new(0), Qubit::new(1));
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append_and_consume(Tk2Op::CX, [q1, q0])?;
+            Ok(())
+        })
+        .unwrap();
+
+        let cmd = circ.commands().next().unwrap();
+        let qubits: Vec<Qubit> = cmd.input_qubits().map(|(u, _, _)| u.into()).collect();
+        assert_eq!(qubits, [q1, q0]);
+        assert_eq!(q1.to_string(), "q[1]");
+    }
+
     #[test]
     fn test_invalid_parent() {
         let hugr = Hugr::default();
@@ -0,0 +1,244 @@
+//! Timing analysis of circuits.
+//!
+//! Given a duration for each operation, [`critical_path`] computes the
+//! earliest and latest start time of every command, and the longest chain of
+//! dependent commands that determines the total duration of the circuit.
+//! Commands on the critical path have zero slack; delaying any of them delays
+//! the whole circuit, while other commands can be delayed by their slack
+//! without consequences.
+
+use std::collections::HashMap;
+
+use hugr::ops::OpType;
+use hugr::{HugrView, Node};
+use itertools::Itertools;
+
+use super::Circuit;
+
+/// The timing of a command in a circuit, see [`critical_path`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommandTiming {
+    /// The duration of the command.
+    pub duration: f64,
+    /// The earliest time at which the command can start, once all the commands
+    /// it depends on have finished.
+    pub earliest_start: f64,
+    /// The latest time at which the command can start without increasing the
+    /// duration of the circuit.
+    pub latest_start: f64,
+}
+
+impl CommandTiming {
+    /// The earliest time at which the command can finish.
+    #[inline]
+    pub fn earliest_finish(&self) -> f64 {
+        self.earliest_start + self.duration
+    }
+
+    /// The latest time at which the command can finish without increasing the
+    /// duration of the circuit.
+    #[inline]
+    pub fn latest_finish(&self) -> f64 {
+        self.latest_start + self.duration
+    }
+
+    /// The time by which the command can be delayed without increasing the
+    /// duration of the circuit.
+    #[inline]
+    pub fn slack(&self) -> f64 {
+        (self.latest_start - self.earliest_start).max(0.)
+    }
+}
+
+/// The critical path of a circuit and the timing of its commands.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CriticalPath {
+    /// The commands in topological order, with their timing.
+    commands: Vec<(Node, CommandTiming)>,
+    /// The position of each command in `commands`.
+    index: HashMap<Node, usize>,
+    /// The positions of the commands on the critical path, in order.
+    path: Vec<usize>,
+    /// The total duration of the circuit.
+    duration: f64,
+}
+
+impl CriticalPath {
+    /// The total duration of the circuit, i.e. the length of the critical
+    /// path.
+    #[inline]
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    /// The commands on the critical path, in execution order.
+    pub fn path(&self) -> impl Iterator<Item = Node> + '_ {
+        self.path.iter().map(|&i| self.commands[i].0)
+    }
+
+    /// The timing of a command, if it is part of the circuit.
+    pub fn timing(&self, node: Node) -> Option<CommandTiming> {
+        self.index.get(&node).map(|&i| self.commands[i].1)
+    }
+
+    /// The slack of a command, if it is part of the circuit. See
+    /// [`CommandTiming::slack`].
+    pub fn slack(&self, node: Node) -> Option<f64> {
+        self.timing(node).map(|t| t.slack())
+    }
+
+    /// The timing of every command, in topological order.
+    pub fn timings(&self) -> impl Iterator<Item = (Node, CommandTiming)> + '_ {
+        self.commands.iter().copied()
+    }
+}
+
+/// Compute the critical path of a circuit, and the earliest and latest start
+/// times of its commands.
+///
+/// `durations` gives the duration of each operation, which must be
+/// non-negative. Operations that should not be accounted for, such as purely
+/// classical ones, can be given a duration of zero. A command depends on all
+/// the commands it is connected to by an incoming edge, including order edges.
+///
+/// When several chains of commands have the maximal duration, one of them is
+/// returned.
+pub fn critical_path<T: HugrView>(
+    circ: &Circuit<T>,
+    durations: impl Fn(&OpType) -> f64,
+) -> CriticalPath {
+    let hugr = circ.hugr();
+    let nodes = circ.commands().map(|cmd| cmd.node()).collect_vec();
+    let index: HashMap<Node, usize> = nodes.iter().enumerate().map(|(i, &n)| (n, i)).collect();
+    let predecessors = nodes
+        .iter()
+        .map(|&node| {
+            hugr.input_neighbours(node)
+                .filter_map(|n| index.get(&n).copied())
+                .unique()
+                .collect_vec()
+        })
+        .collect_vec();
+    let node_durations = nodes
+        .iter()
+        .map(|&node| durations(hugr.get_optype(node)))
+        .collect_vec();
+
+    // Forward pass, in topological order.
+    let mut earliest_start = vec![0.; nodes.len()];
+    for i in 0..nodes.len() {
+        earliest_start[i] = predecessors[i]
+            .iter()
+            .map(|&p| earliest_start[p] + node_durations[p])
+            .fold(0., f64::max);
+    }
+    let earliest_finish = |i: usize| earliest_start[i] + node_durations[i];
+    let duration = (0..nodes.len()).map(earliest_finish).fold(0., f64::max);
+
+    // Backward pass, in reverse topological order.
+    let mut latest_finish = vec![duration; nodes.len()];
+    for i in (0..nodes.len()).rev() {
+        let latest_start = latest_finish[i] - node_durations[i];
+        for &p in &predecessors[i] {
+            latest_finish[p] = latest_finish[p].min(latest_start);
+        }
+    }
+
+    // Walk back from a command finishing last, through predecessors finishing
+    // exactly when the current command starts.
+    let mut path = Vec::new();
+    let mut current = (0..nodes.len())
+        .rev()
+        .find(|&i| earliest_finish(i) == duration);
+    while let Some(i) = current {
+        path.push(i);
+        current = predecessors[i]
+            .iter()
+            .copied()
+            .find(|&p| earliest_finish(p) == earliest_start[i]);
+    }
+    path.reverse();
+
+    let commands = nodes
+        .into_iter()
+        .enumerate()
+        .map(|(i, node)| {
+            let timing = CommandTiming {
+                duration: node_durations[i],
+                earliest_start: earliest_start[i],
+                latest_start: latest_finish[i] - node_durations[i],
+            };
+            (node, timing)
+        })
+        .collect();
+    CriticalPath {
+        commands,
+        index,
+        path,
+        duration,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    fn durations(op: &OpType) -> f64 {
+        match Tk2Op::try_from(op) {
+            Ok(Tk2Op::H | Tk2Op::T) => 1.,
+            Ok(Tk2Op::CX) => 2.,
+            Ok(Tk2Op::X | Tk2Op::Z) => 3.,
+            _ => 0.,
+        }
+    }
+
+    /// The node of the only command with a given operation.
+    fn find_op(circ: &Circuit, op: Tk2Op) -> Node {
+        circ.commands()
+            .filter(|cmd| Tk2Op::try_from(cmd.optype()) == Ok(op))
+            .exactly_one()
+            .ok()
+            .unwrap()
+            .node()
+    }
+
+    #[test]
+    fn slack() {
+        let circ = build_simple_circuit(3, |c| {
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::X, [1])?;
+            c.append(Tk2Op::Z, [2])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            c.append(Tk2Op::T, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        let [h, x, z, cx, t] =
+            [Tk2Op::H, Tk2Op::X, Tk2Op::Z, Tk2Op::CX, Tk2Op::T].map(|op| find_op(&circ, op));
+
+        let cp = critical_path(&circ, durations);
+        assert_eq!(cp.duration(), 6.);
+        assert_eq!(cp.path().collect_vec(), [x, cx, t]);
+
+        let timing = cp.timing(h).unwrap();
+        assert_eq!(timing.earliest_start, 0.);
+        assert_eq!(timing.latest_start, 2.);
+        assert_eq!(timing.slack(), 2.);
+        assert_eq!(cp.slack(z), Some(3.));
+        assert_eq!(cp.timing(cx).unwrap().earliest_finish(), 5.);
+        for node in [x, cx, t] {
+            assert_eq!(cp.slack(node), Some(0.));
+        }
+        assert_eq!(cp.timings().count(), 5);
+    }
+
+    #[test]
+    fn empty_circuit() {
+        let circ = build_simple_circuit(2, |_| Ok(())).unwrap();
+        let cp = critical_path(&circ, durations);
+        assert_eq!(cp.duration(), 0.);
+        assert_eq!(cp.path().count(), 0);
+    }
+}
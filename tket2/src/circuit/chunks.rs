@@ -1,8 +1,38 @@
-//! This module provides a utility to split a circuit into chunks, and reassemble them afterwards.
+//! Splitting circuits into chunks, and reassembling them afterwards.
 //!
-//! See [`CircuitChunks`] for more information.
+//! A [`CircuitChunks`] owns the chunks of a circuit, each an independent
+//! [`Circuit`] that can be modified or replaced, for example by optimising
+//! the chunks in parallel or on different machines. Circuits can be split by
+//! gate count with [`CircuitChunks::split`], by a custom cost with
+//! [`CircuitChunks::split_with_cost`], or by depth with
+//! [`CircuitChunks::split_by_depth`]. The modified chunks must keep their
+//! signature.
+//!
+//! # Example
+//!
+#![cfg_attr(not(miri), doc = "```")] // this doctest reads from the filesystem, so it fails with miri
+#![cfg_attr(miri, doc = "```ignore")]
+//! use tket2::circuit::chunks::CircuitChunks;
+//! use tket2::serialize::{load_tk1_json_file, DecodeOptions};
+//! use tket2::Circuit;
+//!
+//! let circ: Circuit =
+//!     load_tk1_json_file("../test_files/barenco_tof_5.json", DecodeOptions::default()).unwrap();
+//!
+//! // Split the circuit into chunks of at most 50 operations.
+//! let mut chunks = CircuitChunks::split(&circ, 50);
+//! assert_eq!(chunks.len(), 4);
+//!
+//! // Process each chunk independently.
+//! for chunk in chunks.iter_mut() {
+//!     tket2::passes::apply_greedy_commutation(chunk).unwrap();
+//! }
+//!
+//! let reassembled = chunks.reassemble().unwrap();
+//! assert_eq!(reassembled.num_operations(), circ.num_operations());
+//! ```
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::mem;
 use std::ops::{Index, IndexMut};
 
@@ -21,9 +51,11 @@ use portgraph::algorithms::ConvexChecker;
 use rayon::iter::{IntoParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
 use rayon::slice::ParallelSliceMut;
 
+use crate::rewrite::Subcircuit;
 use crate::Circuit;
 
 use crate::circuit::cost::{CircuitCost, CostDelta};
+use crate::circuit::phase::GlobalPhase;
 
 /// An identifier for the connection between chunks.
 ///
@@ -153,6 +185,7 @@ impl Chunk {
         ChunkInsertResult {
             incoming_connections: input_map,
             outgoing_connections: output_map,
+            nodes: node_map.into_values().collect(),
         }
     }
 
@@ -194,6 +227,7 @@ impl Chunk {
         ChunkInsertResult {
             incoming_connections: input_map,
             outgoing_connections: output_map,
+            nodes: Vec::new(),
         }
     }
 }
@@ -207,6 +241,8 @@ struct ChunkInsertResult {
     pub incoming_connections: HashMap<ChunkConnection, Vec<ConnectionTarget>>,
     /// A map from outgoing connections from a chunk, to the new node and outgoing port target.
     pub outgoing_connections: HashMap<ChunkConnection, ConnectionTarget>,
+    /// The nodes inserted in the circuit.
+    pub nodes: Vec<Node>,
 }
 
 /// The target of a chunk connection in a reassembled circuit.
@@ -227,6 +263,10 @@ enum ConnectionTarget {
 /// Circuits can be split into [`CircuitChunks`] with [`CircuitChunks::split`]
 /// or [`CircuitChunks::split_with_cost`], and reassembled with
 /// [`CircuitChunks::reassemble`].
+///
+/// Since the chunks are processed independently, simplifications spanning two
+/// chunks are missed. [`CircuitChunks::reassemble_with_overlap`] re-optimises
+/// the region around each boundary after reassembling the circuit.
 #[derive(Debug, Clone)]
 pub struct CircuitChunks {
     /// The original circuit's signature.
@@ -260,6 +300,72 @@ impl CircuitChunks {
         circ: &Circuit,
         max_cost: C,
         op_cost: impl Fn(&OpType) -> C,
+    ) -> Self {
+        Self::split_with_offset(circ, max_cost, C::default(), op_cost)
+    }
+
+    /// Split a circuit into chunks, shifting the chunk boundaries.
+    ///
+    /// The circuit is split into chunks of at most `max_cost`, using the
+    /// provided cost function, except for the first chunk that is shortened
+    /// by `offset`. Splitting the same circuit with different offsets yields
+    /// different boundaries between the chunks.
+    pub fn split_with_offset<C: CircuitCost>(
+        circ: &Circuit,
+        max_cost: C,
+        offset: C,
+        op_cost: impl Fn(&OpType) -> C,
+    ) -> Self {
+        let hugr = circ.hugr();
+        let mut running_cost = offset;
+        let mut current_group = 0;
+        let groups = circ.commands().map(|cmd| cmd.node()).chunk_by(|&node| {
+            let new_cost = running_cost.clone() + op_cost(hugr.get_optype(node));
+            if new_cost.sub_cost(&max_cost).as_isize() > 0 {
+                running_cost = C::default();
+                current_group += 1;
+            } else {
+                running_cost = new_cost;
+            }
+            current_group
+        });
+        Self::from_groups(circ, groups.into_iter().map(|(_, nodes)| nodes))
+    }
+
+    /// Split a circuit into chunks of at most `max_depth` layers.
+    ///
+    /// The depth of an operation is the number of operations on the longest
+    /// path from the circuit inputs to it, including itself. The first chunk
+    /// contains the operations with depth up to `max_depth`, the second one
+    /// the following `max_depth` layers, and so on.
+    pub fn split_by_depth(circ: &Circuit, max_depth: usize) -> Self {
+        let hugr = circ.hugr();
+        let max_depth = max_depth.max(1);
+        let mut depths: HashMap<Node, usize> = HashMap::new();
+        let mut groups: BTreeMap<usize, Vec<Node>> = BTreeMap::new();
+        for node in circ.commands().map(|cmd| cmd.node()) {
+            let depth = hugr
+                .input_neighbours(node)
+                .filter_map(|n| depths.get(&n).copied())
+                .max()
+                .unwrap_or(0)
+                + 1;
+            depths.insert(node, depth);
+            groups
+                .entry((depth - 1) / max_depth)
+                .or_default()
+                .push(node);
+        }
+        Self::from_groups(circ, groups.into_values())
+    }
+
+    /// Split a circuit into chunks with the given nodes.
+    ///
+    /// The groups must be given in topological order, so that no wire goes
+    /// from a chunk to a previous one.
+    fn from_groups(
+        circ: &Circuit,
+        groups: impl IntoIterator<Item = impl IntoIterator<Item = Node>>,
     ) -> Self {
         let hugr = circ.hugr();
         let root_meta = hugr.get_node_metadata(circ.parent()).cloned();
@@ -276,22 +382,11 @@ impl CircuitChunks {
             .map(|(n, p)| Wire::new(n, p).into())
             .collect();
 
-        let mut chunks = Vec::new();
         let convex_checker = TopoConvexChecker::new(circ.hugr());
-        let mut running_cost = C::default();
-        let mut current_group = 0;
-        for (_, commands) in &circ.commands().map(|cmd| cmd.node()).chunk_by(|&node| {
-            let new_cost = running_cost.clone() + op_cost(hugr.get_optype(node));
-            if new_cost.sub_cost(&max_cost).as_isize() > 0 {
-                running_cost = C::default();
-                current_group += 1;
-            } else {
-                running_cost = new_cost;
-            }
-            current_group
-        }) {
-            chunks.push(Chunk::extract(circ, commands, &convex_checker));
-        }
+        let chunks = groups
+            .into_iter()
+            .map(|nodes| Chunk::extract(circ, nodes, &convex_checker))
+            .collect();
         Self {
             signature,
             root_meta,
@@ -302,7 +397,117 @@ impl CircuitChunks {
     }
 
     /// Reassemble the chunks into a circuit.
+    ///
+    /// The global phase of the circuit is updated with the phases of the
+    /// chunks.
     pub fn reassemble(self) -> Result<Circuit, HugrError> {
+        Ok(self.reassemble_chunks()?.0)
+    }
+
+    /// Reassemble the chunks into a circuit, and re-optimise the operations
+    /// around the boundaries between consecutive chunks.
+    ///
+    /// After reassembling the circuit, a window is taken around each boundary
+    /// with up to `overlap` operations from the end of the previous chunk and
+    /// up to `overlap` operations from the start of the next one. Windows
+    /// around different boundaries never share an operation. The windows are
+    /// optimised in parallel with `optimise`, and replaced in the circuit.
+    ///
+    /// `optimise` must return a circuit with the same signature as its input.
+    /// Windows that cannot be replaced are left unchanged.
+    pub fn reassemble_with_overlap(
+        self,
+        overlap: usize,
+        optimise: impl Fn(Circuit) -> Circuit + Sync,
+    ) -> Result<Circuit, HugrError> {
+        let (mut circ, chunk_nodes) = self.reassemble_chunks()?;
+        if overlap == 0 || chunk_nodes.len() < 2 {
+            return Ok(circ);
+        }
+
+        // Order the nodes of each chunk as in the reassembled circuit, and
+        // select the prefix and suffix to include in the windows. A chunk's
+        // prefix takes at most half of its operations, except for the last
+        // chunk that has no suffix.
+        let position: HashMap<Node, usize> = circ
+            .commands()
+            .enumerate()
+            .map(|(i, cmd)| (cmd.node(), i))
+            .collect();
+        let last = chunk_nodes.len() - 1;
+        let (prefixes, suffixes): (Vec<_>, Vec<_>) = chunk_nodes
+            .into_iter()
+            .enumerate()
+            .map(|(i, nodes)| {
+                let mut nodes = nodes
+                    .into_iter()
+                    .filter_map(|n| Some((*position.get(&n)?, n)))
+                    .sorted_unstable()
+                    .map(|(_, n)| n)
+                    .collect_vec();
+                let prefix_len = match i {
+                    0 => 0,
+                    i if i == last => nodes.len(),
+                    _ => nodes.len().div_ceil(2),
+                }
+                .min(overlap);
+                let suffix_len = match i {
+                    i if i == last => 0,
+                    _ => (nodes.len() - prefix_len).min(overlap),
+                };
+                let suffix = nodes.split_off(nodes.len() - suffix_len);
+                nodes.truncate(prefix_len);
+                (nodes, suffix)
+            })
+            .unzip();
+
+        // Each window is convex, as the chunks are topologically ordered.
+        let windows = suffixes
+            .into_iter()
+            .zip(prefixes.into_iter().skip(1))
+            .map(|(suffix, prefix)| suffix.into_iter().chain(prefix).collect_vec())
+            .filter(|nodes| !nodes.is_empty())
+            .collect_vec();
+        let extracted = windows
+            .iter()
+            .map(|nodes| {
+                let subcirc = Subcircuit::try_from_nodes(nodes.clone(), &circ).ok()?;
+                Some(
+                    subcirc
+                        .subgraph
+                        .extract_subgraph(circ.hugr(), "Chunk")
+                        .into(),
+                )
+            })
+            .collect_vec();
+        let optimised: Vec<Option<Circuit>> = extracted
+            .into_par_iter()
+            .map(|window| window.map(&optimise))
+            .collect();
+
+        // The replacements are applied one at a time, recomputing the
+        // boundary of each window as its neighbours may have been replaced.
+        for (nodes, window) in windows.into_iter().zip(optimised) {
+            let Some(mut window) = window else {
+                continue;
+            };
+            window.set_phase_delta(window.global_phase());
+            let Ok(subcirc) = Subcircuit::try_from_nodes(nodes, &circ) else {
+                continue;
+            };
+            if let Ok(rewrite) = subcirc.create_rewrite(&circ, window) {
+                // The rewrite is checked against the circuit when created.
+                rewrite.apply(&mut circ).unwrap();
+            }
+        }
+
+        Ok(circ)
+    }
+
+    /// Reassemble the chunks into a circuit.
+    ///
+    /// Returns the circuit and the nodes inserted from each chunk.
+    fn reassemble_chunks(self) -> Result<(Circuit, Vec<Vec<Node>>), HugrError> {
         let name = self
             .root_meta
             .as_ref()
@@ -349,12 +554,17 @@ impl CircuitChunks {
             sources.insert(connection, (reassembled_input, port));
         }
 
+        let mut chunk_nodes = Vec::with_capacity(self.chunks.len());
+        let mut phase = Some(GlobalPhase::default());
         for chunk in self.chunks {
+            phase = phase.zip(chunk.circ.global_phase()).map(|(p, q)| p + q);
             // Insert the chunk circuit without its input/output nodes.
             let ChunkInsertResult {
                 incoming_connections,
                 outgoing_connections,
+                nodes,
             } = chunk.insert(&mut reassembled, root);
+            chunk_nodes.push(nodes);
             // Associate the chunk's inserted inputs and outputs to the
             // `ChunkConnection` identifiers, so we can re-connect everything
             // afterwards.
@@ -421,8 +631,10 @@ impl CircuitChunks {
         }
 
         reassembled.overwrite_node_metadata(root, self.root_meta);
+        let mut reassembled: Circuit = reassembled.into();
+        reassembled.add_global_phase(phase);
 
-        Ok(reassembled.into())
+        Ok((reassembled, chunk_nodes))
     }
 
     /// Returns a list of references to the split circuits.
@@ -489,6 +701,7 @@ impl<'data> IntoParallelRefMutIterator<'data> for CircuitChunks {
 mod test {
     use crate::circuit::CircuitHash;
     use crate::extension::REGISTRY;
+    use crate::ops::op_matches;
     use crate::utils::build_simple_circuit;
     use crate::Tk2Op;
 
@@ -518,6 +731,81 @@ mod test {
         assert_eq!(circ.circuit_hash(), reassembled.circuit_hash());
     }
 
+    #[test]
+    fn split_with_offset() {
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::T, [1])?;
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+
+        let chunks = CircuitChunks::split_with_offset(&circ, 2, 1, |_| 1);
+        let chunk_sizes = chunks.iter().map(|c| c.num_operations()).collect_vec();
+        assert_eq!(chunk_sizes, [1, 3, 1]);
+
+        let mut reassembled = chunks.reassemble().unwrap();
+        reassembled.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert_eq!(circ.circuit_hash(), reassembled.circuit_hash());
+    }
+
+    #[test]
+    fn split_by_depth() {
+        let circ = build_simple_circuit(3, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::H, [1])?;
+            circ.append(Tk2Op::H, [2])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::T, [2])?;
+            circ.append(Tk2Op::CX, [1, 2])?;
+            Ok(())
+        })
+        .unwrap();
+
+        let chunks = CircuitChunks::split_by_depth(&circ, 2);
+        let chunk_sizes = chunks.iter().map(|c| c.num_operations()).collect_vec();
+        assert_eq!(chunk_sizes, [5, 1]);
+
+        let mut reassembled = chunks.reassemble().unwrap();
+        reassembled.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert_eq!(circ.circuit_hash(), reassembled.circuit_hash());
+    }
+
+    #[test]
+    fn reassemble_with_overlap() {
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::T, [1])?;
+            Ok(())
+        })
+        .unwrap();
+
+        let chunks = CircuitChunks::split(&circ, 3);
+        assert_eq!(chunks.len(), 2);
+
+        // Cancel the pair of CX gates spanning the boundary, with a phase.
+        let mut reassembled = chunks
+            .reassemble_with_overlap(1, |window| {
+                assert_eq!(window.num_operations(), 2);
+                let mut id = build_simple_circuit(2, |_| Ok(())).unwrap();
+                id.set_global_phase(Some(0.25.into()));
+                id
+            })
+            .unwrap();
+
+        reassembled.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert_eq!(reassembled.num_operations(), 2);
+        assert!(reassembled
+            .commands()
+            .all(|cmd| !op_matches(cmd.optype(), Tk2Op::CX)));
+        assert_eq!(reassembled.global_phase(), Some(0.25.into()));
+    }
+
     #[test]
     fn reassemble_empty() {
         let circ = build_simple_circuit(3, |circ| {
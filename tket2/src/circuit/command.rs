@@ -230,6 +230,126 @@ impl<'circ, T: HugrView> std::hash::Hash for Command<'circ, T> {
     }
 }
 
+/// An owned snapshot of a [`Command`].
+///
+/// Unlike [`Command`], this does not borrow the circuit, so it can be kept
+/// around while the circuit is modified. The recorded units and operation are
+/// not updated when the circuit changes.
+///
+/// See [`Circuit::commands_owned`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedCommand {
+    /// The operation node.
+    node: Node,
+    /// The operation at the time of the snapshot.
+    optype: OpType,
+    /// The units assigned to the node's input ports.
+    inputs: Vec<(CircuitUnit, IncomingPort)>,
+    /// The units assigned to the node's output ports.
+    outputs: Vec<(CircuitUnit, OutgoingPort)>,
+}
+
+impl OwnedCommand {
+    /// Returns the node corresponding to this command.
+    #[inline]
+    pub fn node(&self) -> Node {
+        self.node
+    }
+
+    /// Returns the [`OpType`] of the command.
+    #[inline]
+    pub fn optype(&self) -> &OpType {
+        &self.optype
+    }
+
+    /// Returns the input units of this command.
+    #[inline]
+    pub fn inputs(&self) -> impl Iterator<Item = (CircuitUnit, IncomingPort)> + '_ {
+        self.inputs.iter().copied()
+    }
+
+    /// Returns the output units of this command.
+    #[inline]
+    pub fn outputs(&self) -> impl Iterator<Item = (CircuitUnit, OutgoingPort)> + '_ {
+        self.outputs.iter().copied()
+    }
+
+    /// Returns the units of this command in a given direction.
+    #[inline]
+    pub fn units(&self, direction: Direction) -> impl Iterator<Item = (CircuitUnit, Port)> + '_ {
+        match direction {
+            Direction::Incoming => Either::Left(self.inputs().map(|(u, p)| (u, p.into()))),
+            Direction::Outgoing => Either::Right(self.outputs().map(|(u, p)| (u, p.into()))),
+        }
+    }
+
+    /// Returns the linear units of this command in a given direction.
+    #[inline]
+    pub fn linear_units(
+        &self,
+        direction: Direction,
+    ) -> impl Iterator<Item = (LinearUnit, Port)> + '_ {
+        self.units(direction)
+            .filter_map(|(u, p)| Some((LinearUnit::try_from(u).ok()?, p)))
+    }
+
+    /// Returns the linear input units of this command.
+    #[inline]
+    pub fn linear_inputs(&self) -> impl Iterator<Item = (LinearUnit, IncomingPort)> + '_ {
+        self.inputs()
+            .filter_map(|(u, p)| Some((LinearUnit::try_from(u).ok()?, p)))
+    }
+
+    /// Returns the linear output units of this command.
+    #[inline]
+    pub fn linear_outputs(&self) -> impl Iterator<Item = (LinearUnit, OutgoingPort)> + '_ {
+        self.outputs()
+            .filter_map(|(u, p)| Some((LinearUnit::try_from(u).ok()?, p)))
+    }
+
+    /// Returns the port in the command given a linear unit.
+    #[inline]
+    pub fn linear_unit_port(&self, unit: LinearUnit, direction: Direction) -> Option<Port> {
+        self.linear_units(direction)
+            .find(|(lu, _)| *lu == unit)
+            .map(|(_, port)| port)
+    }
+
+    /// Returns the command with its linear units relabelled.
+    ///
+    /// This is useful to keep track of commands moved across qubit
+    /// permutations without going back to the circuit.
+    pub fn relabel_linear_units(&self, label: impl Fn(LinearUnit) -> LinearUnit) -> Self {
+        let relabel = |u: CircuitUnit| match LinearUnit::try_from(u) {
+            Ok(lu) => label(lu).into(),
+            Err(_) => u,
+        };
+        Self {
+            node: self.node,
+            optype: self.optype.clone(),
+            inputs: self.inputs().map(|(u, p)| (relabel(u), p)).collect(),
+            outputs: self.outputs().map(|(u, p)| (relabel(u), p)).collect(),
+        }
+    }
+}
+
+impl<'circ, T: HugrView> From<&Command<'circ, T>> for OwnedCommand {
+    fn from(command: &Command<'circ, T>) -> Self {
+        Self {
+            node: command.node(),
+            optype: command.optype().clone(),
+            inputs: command.inputs().map(|(u, p, _)| (u, p)).collect(),
+            outputs: command.outputs().map(|(u, p, _)| (u, p)).collect(),
+        }
+    }
+}
+
+impl<'circ, T: HugrView> From<Command<'circ, T>> for OwnedCommand {
+    fn from(command: Command<'circ, T>) -> Self {
+        Self::from(&command)
+    }
+}
+
 /// A non-borrowing topological walker over the nodes of a circuit.
 type NodeWalker = pv::Topo<Node, HashSet<Node>>;
 
@@ -733,4 +853,36 @@ mod test {
 
         Ok(())
     }
+
+    #[rstest]
+    fn owned_commands(simple_circuit: Circuit) {
+        let mut circ = simple_circuit;
+        let owned = circ.commands_owned().collect_vec();
+        assert_eq!(
+            owned.iter().map(|cmd| cmd.node()).collect_vec(),
+            circ.commands().map(|cmd| cmd.node()).collect_vec()
+        );
+
+        let cx = &owned[1];
+        assert_eq!(cx.optype(), &Tk2Op::CX.into());
+        assert_eq_iter!(cx.linear_inputs().map(|(u, _)| u.index()), [0, 1]);
+        assert_eq!(
+            cx.linear_unit_port(LinearUnit::new(1), Direction::Outgoing),
+            Some(OutgoingPort::from(1).into())
+        );
+        let swapped = cx.relabel_linear_units(|u| LinearUnit::new(1 - u.index()));
+        assert_eq_iter!(swapped.linear_outputs().map(|(u, _)| u.index()), [1, 0]);
+
+        // The circuit can be modified while iterating.
+        for cmd in circ.commands_owned() {
+            if cmd.optype() == &Tk2Op::T.into() {
+                let t = cmd.node();
+                let (src, src_port) = circ.hugr().single_linked_output(t, 0).unwrap();
+                let (dst, dst_port) = circ.hugr().single_linked_input(t, 0).unwrap();
+                circ.hugr_mut().remove_node(t);
+                circ.hugr_mut().connect(src, src_port, dst, dst_port);
+            }
+        }
+        assert_eq!(circ.commands().count(), 2);
+    }
 }
@@ -0,0 +1,409 @@
+//! Compact representation of pure quantum circuits.
+//!
+//! A [`CompactCircuit`] stores the commands of a circuit made only of
+//! [`Tk2Op`] gates on qubits with constant angles, as flat arrays of
+//! operation codes, qubit indices and angles. It takes a fraction of the
+//! memory of the equivalent HUGR, and can be converted back to a [`Circuit`]
+//! when needed.
+
+use hugr::extension::prelude::QB_T;
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::hugr::NodeMetadataMap;
+use hugr::ops::dataflow::IOTrait;
+use hugr::ops::{Input, NamedOp, OpTrait, OpType, Output};
+use hugr::types::Signature;
+use hugr::{CircuitUnit, Hugr, HugrView, Node, OutgoingPort};
+use itertools::Itertools;
+use thiserror::Error;
+
+use super::Circuit;
+use crate::utils::{float_wire_value, load_float};
+use crate::Tk2Op;
+
+/// A pure quantum circuit, stored as flat arrays of operations, qubit indices
+/// and angles.
+///
+/// Only circuits whose inputs and outputs are qubits, made of [`Tk2Op`] gates
+/// with constant angles, can be represented. The computation of the angles
+/// is not kept, each angle is loaded as a new constant when converting back to
+/// a [`Circuit`]. The metadata of the circuit is kept, but operations may not
+/// have any metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactCircuit {
+    /// The operation of each command, in order.
+    ops: Vec<Tk2Op>,
+    /// The qubits of each command, concatenated.
+    qubits: Vec<u32>,
+    /// The angles of each command, concatenated.
+    params: Vec<f64>,
+    /// The number of qubits of the circuit.
+    num_qubits: u32,
+    /// The parent operation of the circuit, with its metadata.
+    parent: (OpType, Option<NodeMetadataMap>),
+    /// The metadata of the module root containing the circuit, if the circuit
+    /// is a function in a module.
+    module: Option<Option<NodeMetadataMap>>,
+}
+
+impl CompactCircuit {
+    /// Returns the number of qubits of the circuit.
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits as usize
+    }
+
+    /// Returns the number of operations in the circuit.
+    pub fn num_operations(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns the commands of the circuit, as operations along with their
+    /// qubits and angles.
+    pub fn commands(&self) -> impl Iterator<Item = (Tk2Op, &[u32], &[f64])> + '_ {
+        let mut qubits = &self.qubits[..];
+        let mut params = &self.params[..];
+        self.ops.iter().map(move |&op| {
+            let (num_qubits, num_params) = arity(op);
+            let (op_qubits, rest) = qubits.split_at(num_qubits);
+            qubits = rest;
+            let (op_params, rest) = params.split_at(num_params);
+            params = rest;
+            (op, op_qubits, op_params)
+        })
+    }
+
+    /// Returns the circuit as a HUGR.
+    pub fn to_circuit(&self) -> Circuit {
+        let (parent_op, parent_metadata) = &self.parent;
+        let (mut hugr, parent) = match &self.module {
+            Some(module_metadata) => {
+                let mut hugr = Hugr::default();
+                let root = hugr.root();
+                hugr.overwrite_node_metadata(root, module_metadata.clone());
+                let parent = hugr.add_node_with_parent(root, parent_op.clone());
+                (hugr, parent)
+            }
+            None => {
+                let hugr = Hugr::new(parent_op.clone());
+                let root = hugr.root();
+                (hugr, root)
+            }
+        };
+        hugr.overwrite_node_metadata(parent, parent_metadata.clone());
+
+        let qubit_row = vec![QB_T; self.num_qubits()];
+        let input = hugr.add_node_with_parent(parent, Input::new(qubit_row.clone()));
+        let output = hugr.add_node_with_parent(parent, Output::new(qubit_row));
+        // The last wire of each qubit.
+        let mut wires = (0..self.num_qubits())
+            .map(|q| (input, OutgoingPort::from(q)))
+            .collect_vec();
+        for (op, qubits, params) in self.commands() {
+            let node = hugr.add_node_with_parent(parent, op);
+            for (port, &q) in qubits.iter().enumerate() {
+                let (src, src_port) = wires[q as usize];
+                hugr.connect(src, src_port, node, port);
+                wires[q as usize] = (node, OutgoingPort::from(port));
+            }
+            for (i, &angle) in params.iter().enumerate() {
+                let load = load_float(&mut hugr, parent, angle);
+                hugr.connect(load, 0, node, qubits.len() + i);
+            }
+        }
+        for (q, (src, src_port)) in wires.into_iter().enumerate() {
+            hugr.connect(src, src_port, output, q);
+        }
+        Circuit::new(hugr, parent)
+    }
+}
+
+/// The number of qubits and angles taken by an operation.
+///
+/// The qubit inputs of [`Tk2Op`] gates always come before their angles.
+fn arity(op: Tk2Op) -> (usize, usize) {
+    let signature = OpType::from(op).dataflow_signature().unwrap_or_default();
+    let num_qubits = signature
+        .input_types()
+        .iter()
+        .filter(|ty| **ty == QB_T)
+        .count();
+    (num_qubits, signature.input_count() - num_qubits)
+}
+
+impl<T: HugrView> TryFrom<&Circuit<T>> for CompactCircuit {
+    type Error = CompactCircuitError;
+
+    fn try_from(circ: &Circuit<T>) -> Result<Self, Self::Error> {
+        let hugr = circ.hugr();
+        let parent = circ.parent();
+        let root = hugr.root();
+        let module = match parent == root {
+            true => None,
+            false => {
+                let is_single_function = matches!(hugr.get_optype(root), OpType::Module(_))
+                    && hugr.children(root).exactly_one().ok() == Some(parent);
+                if !is_single_function {
+                    return Err(CompactCircuitError::NestedCircuit { parent });
+                }
+                Some(hugr.get_node_metadata(root).cloned())
+            }
+        };
+        let signature = circ.circuit_signature();
+        if signature.input_types().iter().any(|ty| ty != &QB_T)
+            || signature.input_types() != signature.output_types()
+        {
+            return Err(CompactCircuitError::UnsupportedSignature { signature });
+        }
+
+        let [input, output] = circ.io_nodes();
+        let mut compact = Self {
+            ops: Vec::new(),
+            qubits: Vec::new(),
+            params: Vec::new(),
+            num_qubits: signature.input_count() as u32,
+            parent: (
+                hugr.get_optype(parent).clone(),
+                hugr.get_node_metadata(parent).cloned(),
+            ),
+            module,
+        };
+        // The last wire of each qubit.
+        let mut wires = (0..compact.num_qubits())
+            .map(|q| (input, OutgoingPort::from(q)))
+            .collect_vec();
+        for cmd in circ.commands() {
+            let node = cmd.node();
+            let optype = cmd.optype();
+            if cmd.linear_inputs().next().is_none() && cmd.linear_outputs().next().is_none() {
+                // Angle computations are replaced by constants.
+                continue;
+            }
+            if hugr
+                .get_node_metadata(node)
+                .is_some_and(|meta| !meta.is_empty())
+            {
+                return Err(CompactCircuitError::NodeMetadata { node });
+            }
+            let op = Tk2Op::try_from(optype).ok().filter(|&op| {
+                let (num_qubits, _) = arity(op);
+                num_qubits > 0
+                    && cmd.output_count() == num_qubits
+                    && cmd.linear_outputs().count() == num_qubits
+            });
+            let Some(op) = op else {
+                return Err(CompactCircuitError::UnsupportedOperation {
+                    optype: optype.clone(),
+                    node,
+                });
+            };
+            for (unit, _, _) in cmd.inputs() {
+                match unit {
+                    CircuitUnit::Linear(q) => compact.qubits.push(q as u32),
+                    CircuitUnit::Wire(wire) => {
+                        let Some(angle) = float_wire_value(hugr, wire) else {
+                            return Err(CompactCircuitError::NonConstantParameter {
+                                optype: optype.clone(),
+                                node,
+                            });
+                        };
+                        compact.params.push(angle);
+                    }
+                }
+            }
+            for (unit, port, _) in cmd.linear_outputs() {
+                wires[unit.index()] = (node, port);
+            }
+            compact.ops.push(op);
+        }
+
+        // Each qubit must end at its own output.
+        for (q, &wire) in wires.iter().enumerate() {
+            if hugr.single_linked_output(output, q) != Some(wire) {
+                return Err(CompactCircuitError::PermutedOutputs);
+            }
+        }
+        Ok(compact)
+    }
+}
+
+impl From<&CompactCircuit> for Circuit {
+    fn from(compact: &CompactCircuit) -> Self {
+        compact.to_circuit()
+    }
+}
+
+/// Errors that can occur when converting a circuit to a [`CompactCircuit`].
+#[derive(Debug, Clone, Error, PartialEq)]
+#[non_exhaustive]
+pub enum CompactCircuitError {
+    /// The circuit is neither the root of its HUGR, nor the only function in
+    /// a module.
+    #[error("The circuit at {parent} is nested in a larger HUGR.")]
+    NestedCircuit {
+        /// The parent node of the circuit.
+        parent: Node,
+    },
+    /// The circuit has inputs or outputs other than qubits.
+    #[error(
+        "Only circuits on qubits can be made compact, but the circuit has signature {signature}."
+    )]
+    UnsupportedSignature {
+        /// The signature of the circuit.
+        signature: Signature,
+    },
+    /// The circuit contains an operation that is not a quantum gate.
+    #[error("Unsupported {} operation at {node}.", optype.name())]
+    UnsupportedOperation {
+        /// The operation.
+        optype: OpType,
+        /// The node of the operation.
+        node: Node,
+    },
+    /// The angle of a gate is not a constant.
+    #[error("The angle of the {} operation at {node} is not constant.", optype.name())]
+    NonConstantParameter {
+        /// The operation.
+        optype: OpType,
+        /// The node of the operation.
+        node: Node,
+    },
+    /// An operation has metadata, which cannot be stored.
+    #[error("The operation at {node} has metadata.")]
+    NodeMetadata {
+        /// The node of the operation.
+        node: Node,
+    },
+    /// The qubits do not end at their own outputs.
+    #[error("The circuit permutes its qubits.")]
+    PermutedOutputs,
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::std_extensions::arithmetic::float_types::{ConstF64, FLOAT64_TYPE};
+    use rstest::rstest;
+
+    use super::*;
+    use crate::circuit::phase::GlobalPhase;
+    use crate::extension::REGISTRY;
+    use crate::utils::build_simple_circuit;
+
+    /// A circuit with rotations, defined by a function.
+    fn rotations() -> Circuit {
+        let mut circ = build_simple_circuit(3, |c| {
+            let angle = c.add_constant(ConstF64::new(0.25));
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::CX, [0, 2])?;
+            c.append_and_consume(
+                Tk2Op::RzF64,
+                [CircuitUnit::Linear(1), CircuitUnit::Wire(angle)],
+            )?;
+            c.append_and_consume(
+                Tk2Op::ZZPhase,
+                [
+                    CircuitUnit::Linear(2),
+                    CircuitUnit::Linear(1),
+                    CircuitUnit::Wire(angle),
+                ],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        circ.set_global_phase(Some(GlobalPhase::new(0.5)));
+        circ
+    }
+
+    /// The same circuit, as a dataflow graph.
+    fn rotations_dfg() -> Circuit {
+        rotations().extract_dfg().unwrap()
+    }
+
+    /// The same circuit, as the only function in a module.
+    fn rotations_module() -> Circuit {
+        let mut hugr = Hugr::default();
+        let root = hugr.root();
+        hugr.set_metadata(root, "name", "module");
+        let func = hugr.insert_hugr(root, rotations().into_hugr()).new_root;
+        Circuit::new(hugr, func)
+    }
+
+    #[rstest]
+    #[case::function(rotations())]
+    #[case::dfg(rotations_dfg())]
+    #[case::module(rotations_module())]
+    fn roundtrip(#[case] circ: Circuit) {
+        let compact = CompactCircuit::try_from(&circ).unwrap();
+        assert_eq!(compact.num_qubits(), 3);
+        assert_eq!(compact.num_operations(), 4);
+        let (op, qubits, params) = compact.commands().last().unwrap();
+        assert_eq!(
+            (op, qubits, params),
+            (Tk2Op::ZZPhase, &[2, 1][..], &[0.25][..])
+        );
+
+        let roundtrip = compact.to_circuit();
+        roundtrip.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(
+            roundtrip.hugr().get_optype(roundtrip.hugr().root()),
+            circ.hugr().get_optype(circ.hugr().root())
+        );
+        assert_eq!(
+            roundtrip.hugr().get_node_metadata(roundtrip.hugr().root()),
+            circ.hugr().get_node_metadata(circ.hugr().root())
+        );
+        assert_eq!(roundtrip.name(), circ.name());
+        assert_eq!(roundtrip.global_phase(), circ.global_phase());
+        assert_eq!(roundtrip.num_operations(), circ.num_operations());
+        assert_eq!(roundtrip.unitary(), circ.unitary());
+        assert_eq!(CompactCircuit::try_from(&roundtrip), Ok(compact));
+    }
+
+    #[test]
+    fn unsupported() {
+        let measure = build_simple_circuit(1, |c| {
+            c.append(Tk2Op::Measure, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(matches!(
+            CompactCircuit::try_from(&measure),
+            Err(CompactCircuitError::UnsupportedOperation { .. })
+        ));
+
+        let mut circ = rotations();
+        let node = circ
+            .commands()
+            .find(|cmd| cmd.optype() == &Tk2Op::H.into())
+            .unwrap()
+            .node();
+        circ.hugr_mut().set_metadata(node, "label", "h");
+        assert_eq!(
+            CompactCircuit::try_from(&circ),
+            Err(CompactCircuitError::NodeMetadata { node })
+        );
+
+        let mut dfg =
+            DFGBuilder::new(Signature::new(vec![QB_T, FLOAT64_TYPE], vec![QB_T])).unwrap();
+        let [q, angle] = dfg.input_wires_arr();
+        let [q] = dfg
+            .add_dataflow_op(Tk2Op::RzF64, [q, angle])
+            .unwrap()
+            .outputs_arr();
+        let circ: Circuit = dfg.finish_hugr_with_outputs([q], &REGISTRY).unwrap().into();
+        assert!(matches!(
+            CompactCircuit::try_from(&circ),
+            Err(CompactCircuitError::UnsupportedSignature { .. })
+        ));
+
+        let dfg = DFGBuilder::new(Signature::new_endo(vec![QB_T, QB_T])).unwrap();
+        let [a, b] = dfg.input_wires_arr();
+        let circ: Circuit = dfg
+            .finish_hugr_with_outputs([b, a], &REGISTRY)
+            .unwrap()
+            .into();
+        assert_eq!(
+            CompactCircuit::try_from(&circ),
+            Err(CompactCircuitError::PermutedOutputs)
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! Adjoint of a circuit.
+//!
+//! The adjoint of a unitary circuit undoes its effect, and is used to
+//! uncompute ancillas or to check the equivalence of two circuits.
+
+use std::collections::HashMap;
+use std::f64::consts::FRAC_PI_2;
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::hugr::views::ExtractHugr;
+use hugr::ops::{NamedOp, OpType};
+use hugr::{HugrView, IncomingPort, Node, OutgoingPort, Wire};
+use thiserror::Error;
+
+use super::units::LinearUnit;
+use super::{Circuit, CircuitMutError};
+use crate::utils::{float_wire_value, load_float, remove_unused_param};
+use crate::Tk2Op;
+
+/// A float parameter of an inverted operation, computed from the input ports
+/// of the original operation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Param {
+    /// The same parameter as the original input port.
+    Keep(usize),
+    /// The negation of the original input port, which must be constant.
+    Negate(usize),
+    /// A constant angle, in radians.
+    Const(f64),
+}
+
+/// Returns the inverse of an operation, along with its float parameters.
+///
+/// Returns `None` for non-unitary operations.
+fn inverse(op: Tk2Op) -> Option<(Tk2Op, Vec<Param>)> {
+    use Tk2Op::*;
+    let inverse = match op {
+        H | X | Y | Z | CX | CZ | CCX | CCZ => (op, vec![]),
+        S => (Sdg, vec![]),
+        Sdg => (S, vec![]),
+        T => (Tdg, vec![]),
+        Tdg => (T, vec![]),
+        RzF64 | RxF64 => (op, vec![Param::Negate(1)]),
+        ZZPhase | XXPhase | YYPhase => (op, vec![Param::Negate(2)]),
+        // PhasedX(θ, φ) = Rz(φ) Rx(θ) Rz(-φ)
+        PhasedX => (op, vec![Param::Negate(1), Param::Keep(2)]),
+        // TK1(a, b, c) = Rz(a) Rx(b) Rz(c)
+        TK1 => (
+            op,
+            vec![Param::Negate(3), Param::Negate(2), Param::Negate(1)],
+        ),
+        ZZMax => (ZZPhase, vec![Param::Const(-FRAC_PI_2)]),
+        Measure | Reset | QAlloc | QFree | AngleAdd => return None,
+    };
+    Some(inverse)
+}
+
+impl<T: HugrView> Circuit<T> {
+    /// Returns the adjoint of the circuit.
+    ///
+    /// The commands are applied in reverse order, each one replaced by its
+    /// inverse, and the global phase is negated. Purely classical operations,
+    /// such as the ones computing rotation angles, are kept unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the circuit contains non-unitary operations such
+    /// as measurements, or rotations by angles that are not constant.
+    pub fn dagger(&self) -> Result<Circuit, DaggerError>
+    where
+        T: ExtractHugr,
+    {
+        let mut circ = self.extract_dfg()?;
+        let parent = circ.parent();
+
+        // The linear ports visited by each unit, in order.
+        let mut paths: HashMap<LinearUnit, Vec<(Node, IncomingPort, OutgoingPort)>> =
+            HashMap::new();
+        let mut inverted = Vec::new();
+        for cmd in circ.commands_owned() {
+            let node = cmd.node();
+            let num_linear = cmd.linear_inputs().count();
+            if num_linear == 0 && cmd.linear_outputs().next().is_none() {
+                continue;
+            }
+            if !matches!(cmd.optype(), OpType::Noop(_)) {
+                let Some((op, params)) = Tk2Op::try_from(cmd.optype()).ok().and_then(inverse)
+                else {
+                    return Err(DaggerError::NonInvertibleOperation {
+                        optype: cmd.optype().clone(),
+                        node,
+                    });
+                };
+                inverted.push((node, op, num_linear, params));
+            }
+            for ((unit, in_port), (_, out_port)) in cmd.linear_inputs().zip(cmd.linear_outputs()) {
+                paths
+                    .entry(unit)
+                    .or_default()
+                    .push((node, in_port, out_port));
+            }
+        }
+
+        let hugr = circ.hugr_mut();
+        let mut replacements = HashMap::new();
+        let mut param_sources = Vec::new();
+        for (node, op, num_linear, params) in inverted {
+            let new_node = hugr.add_node_with_parent(parent, op);
+            for (i, param) in params.into_iter().enumerate() {
+                let port = num_linear + i;
+                let (src, src_port) = match param {
+                    Param::Keep(p) => hugr.single_linked_output(node, p).unwrap(),
+                    Param::Negate(p) => {
+                        let (src, src_port) = hugr.single_linked_output(node, p).unwrap();
+                        let Some(value) = float_wire_value(hugr, Wire::new(src, src_port)) else {
+                            return Err(DaggerError::NonConstantParameter {
+                                optype: op.into(),
+                                node,
+                            });
+                        };
+                        param_sources.push(src);
+                        (load_float(hugr, parent, -value), OutgoingPort::from(0))
+                    }
+                    Param::Const(value) => (load_float(hugr, parent, value), OutgoingPort::from(0)),
+                };
+                hugr.connect(src, src_port, new_node, port);
+            }
+            let metadata = hugr.get_node_metadata(node).cloned();
+            hugr.overwrite_node_metadata(new_node, metadata);
+            replacements.insert(node, new_node);
+        }
+
+        // Reverse the path of each unit, connecting the circuit input to the
+        // inverse of its last operation.
+        let mut links = Vec::new();
+        for path in paths.values() {
+            let (first, first_in, _) = path[0];
+            let (last, _, last_out) = path[path.len() - 1];
+            let (src, src_port) = hugr.single_linked_output(first, first_in).unwrap();
+            let (dst, dst_port) = hugr.linked_inputs(last, last_out).next().unwrap();
+            let new = |n: Node| replacements.get(&n).copied().unwrap_or(n);
+            let mut prev = (src, src_port);
+            for &(node, in_port, out_port) in path.iter().rev() {
+                links.push((prev, (new(node), in_port)));
+                prev = (new(node), out_port);
+            }
+            links.push((prev, (dst, dst_port)));
+        }
+        for &(node, in_port, out_port) in paths.values().flatten() {
+            hugr.disconnect(node, in_port);
+            hugr.disconnect(node, out_port);
+        }
+        for ((src, src_port), (dst, dst_port)) in links {
+            hugr.connect(src, src_port, dst, dst_port);
+        }
+        for &node in replacements.keys() {
+            hugr.remove_node(node);
+        }
+        for node in param_sources {
+            remove_unused_param(hugr, node);
+        }
+
+        if let Some(phase) = circ.global_phase().filter(|phase| !phase.is_zero()) {
+            circ.set_global_phase(Some(-phase));
+        }
+        Ok(circ)
+    }
+}
+
+/// Errors that can occur when computing the adjoint of a circuit.
+#[derive(Debug, Clone, Error, PartialEq)]
+#[non_exhaustive]
+pub enum DaggerError {
+    /// The circuit contains an operation without an inverse.
+    #[error("Cannot invert the non-unitary {} operation at {node}.", optype.name())]
+    NonInvertibleOperation {
+        /// The operation.
+        optype: OpType,
+        /// The node of the operation.
+        node: Node,
+    },
+    /// The angle of a rotation is not a constant, so it cannot be negated.
+    #[error("Cannot invert the {} operation at {node}, as its angle is not constant.", optype.name())]
+    NonConstantParameter {
+        /// The operation.
+        optype: OpType,
+        /// The node of the operation.
+        node: Node,
+    },
+    /// The circuit could not be extracted from its HUGR.
+    #[error(transparent)]
+    Extraction(#[from] CircuitMutError),
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::extension::prelude::QB_T;
+    use hugr::std_extensions::arithmetic::float_types::{ConstF64, FLOAT64_TYPE};
+    use hugr::types::Signature;
+    use hugr::CircuitUnit;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::circuit::phase::GlobalPhase;
+    use crate::extension::REGISTRY;
+    use crate::utils::build_simple_circuit;
+
+    /// A two-qubit circuit using every kind of unitary operation.
+    fn rotations() -> Circuit {
+        let mut dfg = DFGBuilder::new(Signature::new_endo(vec![QB_T, QB_T])).unwrap();
+        let angles = [0.3, -1.2, 0.7].map(|a| dfg.add_load_value(ConstF64::new(a)));
+        let [a, b, c] = angles.map(CircuitUnit::Wire);
+        let qbs = dfg.input_wires();
+        let mut circ = dfg.as_circuit(qbs);
+        let ops: [(Tk2Op, Vec<CircuitUnit>); 9] = [
+            (Tk2Op::H, vec![CircuitUnit::Linear(0)]),
+            (Tk2Op::T, vec![CircuitUnit::Linear(1)]),
+            (
+                Tk2Op::CX,
+                vec![CircuitUnit::Linear(0), CircuitUnit::Linear(1)],
+            ),
+            (Tk2Op::RzF64, vec![CircuitUnit::Linear(0), a]),
+            (Tk2Op::S, vec![CircuitUnit::Linear(0)]),
+            (Tk2Op::PhasedX, vec![CircuitUnit::Linear(1), b, c]),
+            (
+                Tk2Op::ZZMax,
+                vec![CircuitUnit::Linear(0), CircuitUnit::Linear(1)],
+            ),
+            (Tk2Op::TK1, vec![CircuitUnit::Linear(1), a, b, c]),
+            (
+                Tk2Op::XXPhase,
+                vec![CircuitUnit::Linear(1), CircuitUnit::Linear(0), c],
+            ),
+        ];
+        for (op, units) in ops {
+            circ.append_and_consume(op, units).unwrap();
+        }
+        let qbs = circ.finish();
+        let mut circ: Circuit = dfg.finish_hugr_with_outputs(qbs, &REGISTRY).unwrap().into();
+        circ.set_global_phase(Some(GlobalPhase::new(0.25)));
+        circ
+    }
+
+    #[test]
+    fn dagger_unitary() {
+        let circ = rotations();
+        let dagger = circ.dagger().unwrap();
+        dagger.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(dagger.num_operations(), circ.num_operations());
+        assert_eq!(dagger.global_phase(), Some(GlobalPhase::new(-0.25)));
+
+        let expected = circ.unitary().unwrap().t().mapv(|x| x.conj());
+        let unitary = dagger.unitary().unwrap();
+        assert!(
+            (&unitary - &expected).iter().all(|x| x.norm() < 1e-10),
+            "{unitary} != {expected}"
+        );
+    }
+
+    #[rstest]
+    #[case::measure(Tk2Op::Measure)]
+    #[case::reset(Tk2Op::Reset)]
+    fn non_invertible(#[case] op: Tk2Op) {
+        let circ = build_simple_circuit(1, |c| {
+            c.append(Tk2Op::H, [0])?;
+            c.append_and_consume(op, [CircuitUnit::Linear(0)])?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(matches!(
+            circ.dagger(),
+            Err(DaggerError::NonInvertibleOperation { .. })
+        ));
+    }
+
+    #[test]
+    fn symbolic_angle() {
+        let mut dfg =
+            DFGBuilder::new(Signature::new(vec![QB_T, FLOAT64_TYPE], vec![QB_T])).unwrap();
+        let [q, angle] = dfg.input_wires_arr();
+        let [q] = dfg
+            .add_dataflow_op(Tk2Op::RzF64, [q, angle])
+            .unwrap()
+            .outputs_arr();
+        let circ: Circuit = dfg.finish_hugr_with_outputs([q], &REGISTRY).unwrap().into();
+        assert!(matches!(
+            circ.dagger(),
+            Err(DaggerError::NonConstantParameter { .. })
+        ));
+    }
+}
@@ -0,0 +1,421 @@
+//! In-place insertion and removal of commands.
+//!
+//! Inserting a command splices it into the wires of the linear units it acts
+//! on, and removing a command reconnects the wires it was spliced into. Both
+//! operations keep the HUGR valid.
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::ops::{NamedOp, OpTrait, OpType};
+use hugr::{CircuitUnit, Direction, IncomingPort, Node, OutgoingPort};
+use itertools::Either::{Left, Right};
+use itertools::Itertools;
+use thiserror::Error;
+
+use super::units::{filter, DefaultUnitLabeller, LinearUnit, UnitTracker, Units};
+use super::Circuit;
+use crate::utils::{remove_unused_param, type_is_linear};
+
+/// The position at which a command is inserted along the wires of its linear
+/// units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InsertPosition {
+    /// At the start of the circuit, right after its input.
+    Start,
+    /// At the end of the circuit, right before its output.
+    End,
+    /// Right before a command acting on all the linear units.
+    Before(Node),
+    /// Right after a command acting on all the linear units.
+    After(Node),
+}
+
+impl<T: HugrMut> Circuit<T> {
+    /// Insert an operation acting on some units of the circuit.
+    ///
+    /// The units are given in the order of the operation's inputs, as in
+    /// [`CircuitBuilder::append_with_outputs`]: linear inputs take a
+    /// [`CircuitUnit::Linear`] and are spliced into the wire of that unit at
+    /// `position`, while other inputs take a [`CircuitUnit::Wire`] and are
+    /// connected to it. The wires must not be computed from the outputs of
+    /// the commands following `position`. Non-linear outputs of the operation
+    /// are left unconnected.
+    ///
+    /// Returns the new node.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the units do not match the inputs of the
+    /// operation, or if the command at `position` does not act on all the
+    /// linear units.
+    ///
+    /// [`CircuitBuilder::append_with_outputs`]: hugr::builder::CircuitBuilder::append_with_outputs
+    pub fn insert_command(
+        &mut self,
+        op: impl Into<OpType>,
+        units: impl IntoIterator<Item = CircuitUnit>,
+        position: InsertPosition,
+    ) -> Result<Node, CommandEditError> {
+        let op: OpType = op.into();
+        let units = units.into_iter().collect_vec();
+        let signature = op.dataflow_signature().unwrap_or_default();
+        let count_linear = |types: &[_]| types.iter().filter(|ty| type_is_linear(ty)).count();
+        if count_linear(signature.input_types()) != count_linear(signature.output_types()) {
+            return Err(CommandEditError::UnpairedLinearPorts { optype: op });
+        }
+        let matches_inputs = units.len() == signature.input_count()
+            && units
+                .iter()
+                .zip(signature.input_types())
+                .all(|(unit, ty)| unit.is_linear() == type_is_linear(ty))
+            && units.iter().filter(|unit| unit.is_linear()).all_unique();
+        if !matches_inputs {
+            return Err(CommandEditError::InvalidUnits { optype: op, units });
+        }
+
+        // Find the wire of each linear unit to splice the command into.
+        let (position_node, direction) = match position {
+            InsertPosition::Start => (self.input_node(), Direction::Outgoing),
+            InsertPosition::End => (self.output_node(), Direction::Incoming),
+            InsertPosition::Before(node) => (node, Direction::Incoming),
+            InsertPosition::After(node) => (node, Direction::Outgoing),
+        };
+        let tracker = UnitTracker::new(self);
+        let mut targets = Vec::new();
+        for &unit in &units {
+            let CircuitUnit::Linear(index) = unit else {
+                continue;
+            };
+            let unit = LinearUnit::new(index);
+            let Some(port) = self
+                .hugr()
+                .node_ports(position_node, direction)
+                .find(|&port| tracker.linear_unit(position_node, port) == Some(unit))
+            else {
+                return Err(CommandEditError::UnitNotAtPosition { unit, position });
+            };
+            let target = match port.as_directed() {
+                Left(in_port) => (position_node, in_port),
+                Right(out_port) => self
+                    .hugr()
+                    .linked_inputs(position_node, out_port)
+                    .exactly_one()
+                    .ok()
+                    .unwrap(),
+            };
+            targets.push(target);
+        }
+
+        let parent = self.parent();
+        let hugr = self.hugr_mut();
+        let node = hugr.add_node_with_parent(parent, op);
+        let mut targets = targets.into_iter();
+        let mut linear_outputs = signature
+            .output_types()
+            .iter()
+            .positions(type_is_linear)
+            .map(OutgoingPort::from);
+        for (port, unit) in units.into_iter().enumerate() {
+            match unit {
+                CircuitUnit::Linear(_) => {
+                    let out_port = linear_outputs.next().unwrap();
+                    let target = targets.next().unwrap();
+                    splice_in(hugr, node, port.into(), out_port, target);
+                }
+                CircuitUnit::Wire(wire) => hugr.connect(wire.node(), wire.source(), node, port),
+            }
+        }
+        Ok(node)
+    }
+
+    /// Remove a command from the circuit, connecting the wires of its linear
+    /// units across it.
+    ///
+    /// Operations computing the non-linear inputs of the command, such as
+    /// rotation angles, are also removed if they are no longer used.
+    ///
+    /// Returns the operation of the removed command.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the node is not a command of the circuit, if it
+    /// does not have matching linear inputs and outputs, or if any of its
+    /// non-linear outputs are used.
+    pub fn remove_command(&mut self, node: Node) -> Result<OpType, CommandEditError> {
+        let is_command = self.hugr().contains_node(node)
+            && self.hugr().get_parent(node) == Some(self.parent())
+            && !self.io_nodes().contains(&node);
+        if !is_command {
+            return Err(CommandEditError::NotACommand { node });
+        }
+        let optype = self.hugr().get_optype(node).clone();
+        let linear_inputs = Units::new_incoming(self, node, DefaultUnitLabeller)
+            .filter_map(filter::filter_linear)
+            .map(|(_, port, _)| port)
+            .collect_vec();
+        let linear_outputs = Units::new_outgoing(self, node, DefaultUnitLabeller)
+            .filter_map(filter::filter_linear)
+            .map(|(_, port, _)| port)
+            .collect_vec();
+        if linear_inputs.len() != linear_outputs.len() {
+            return Err(CommandEditError::UnpairedLinearPorts { optype });
+        }
+        let hugr = self.hugr_mut();
+        let outputs_used = hugr
+            .node_outputs(node)
+            .filter(|port| !linear_outputs.contains(port))
+            .any(|port| hugr.is_linked(node, port));
+        if outputs_used {
+            return Err(CommandEditError::UsedOutputs { optype, node });
+        }
+
+        for (in_port, out_port) in linear_inputs.into_iter().zip(linear_outputs) {
+            splice_out(hugr, node, in_port, out_port);
+        }
+        let params = hugr.input_neighbours(node).collect_vec();
+        hugr.remove_node(node);
+        for param in params {
+            remove_unused_param(hugr, param);
+        }
+        Ok(optype)
+    }
+}
+
+/// Splice a pair of linear ports of a node into the wire ending at `target`.
+pub(crate) fn splice_in(
+    hugr: &mut impl HugrMut,
+    node: Node,
+    in_port: IncomingPort,
+    out_port: OutgoingPort,
+    (dst, dst_port): (Node, IncomingPort),
+) {
+    let (src, src_port) = hugr.single_linked_output(dst, dst_port).unwrap();
+    hugr.disconnect(dst, dst_port);
+    hugr.connect(src, src_port, node, in_port);
+    hugr.connect(node, out_port, dst, dst_port);
+}
+
+/// Splice a pair of linear ports of a node out of their wire, connecting its
+/// source to its target directly.
+pub(crate) fn splice_out(
+    hugr: &mut impl HugrMut,
+    node: Node,
+    in_port: IncomingPort,
+    out_port: OutgoingPort,
+) {
+    let (src, src_port) = hugr.single_linked_output(node, in_port).unwrap();
+    let (dst, dst_port) = hugr
+        .linked_inputs(node, out_port)
+        .exactly_one()
+        .ok()
+        .unwrap();
+    hugr.disconnect(node, in_port);
+    hugr.disconnect(node, out_port);
+    hugr.connect(src, src_port, dst, dst_port);
+}
+
+/// Errors that can occur when inserting or removing commands.
+#[derive(Debug, Clone, Error, PartialEq)]
+#[non_exhaustive]
+pub enum CommandEditError {
+    /// The units do not match the inputs of the operation.
+    #[error("Units {units:?} do not match the inputs of the {} operation.", optype.name())]
+    InvalidUnits {
+        /// The operation.
+        optype: OpType,
+        /// The units given for its inputs.
+        units: Vec<CircuitUnit>,
+    },
+    /// The operation does not have as many linear outputs as linear inputs.
+    #[error("Cannot splice the {} operation, as it does not have matching linear inputs and outputs.", optype.name())]
+    UnpairedLinearPorts {
+        /// The operation.
+        optype: OpType,
+    },
+    /// A linear unit does not go through the insertion position.
+    #[error("Linear unit {} does not go through {position:?}.", unit.index())]
+    UnitNotAtPosition {
+        /// The linear unit.
+        unit: LinearUnit,
+        /// The insertion position.
+        position: InsertPosition,
+    },
+    /// The node is not a command of the circuit.
+    #[error("{node} is not a command of the circuit.")]
+    NotACommand {
+        /// The node.
+        node: Node,
+    },
+    /// The non-linear outputs of the command are used by other operations.
+    #[error("Cannot remove the {} operation at {node}, as its outputs are used.", optype.name())]
+    UsedOutputs {
+        /// The operation.
+        optype: OpType,
+        /// The node of the operation.
+        node: Node,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::extension::prelude::BOOL_T;
+    use hugr::ops::{Const, LoadConstant, Noop};
+    use hugr::std_extensions::arithmetic::float_types::{ConstF64, FLOAT64_TYPE};
+    use hugr::{HugrView, Wire};
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    /// `H(0); CX(0, 1)`, along with the nodes of its two commands.
+    fn circuit() -> (Circuit, Node, Node) {
+        let circ = build_simple_circuit(2, |c| {
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        let [h, cx] = circ.commands().map(|cmd| cmd.node()).collect_vec()[..] else {
+            panic!("Expected two commands");
+        };
+        (circ, h, cx)
+    }
+
+    #[rstest]
+    #[case::start(|_, _| InsertPosition::Start, [Tk2Op::T, Tk2Op::H, Tk2Op::CX])]
+    #[case::end(|_, _| InsertPosition::End, [Tk2Op::H, Tk2Op::CX, Tk2Op::T])]
+    #[case::before(|_, cx| InsertPosition::Before(cx), [Tk2Op::H, Tk2Op::T, Tk2Op::CX])]
+    #[case::after(|h, _| InsertPosition::After(h), [Tk2Op::H, Tk2Op::T, Tk2Op::CX])]
+    fn insert_and_remove(
+        #[case] position: fn(Node, Node) -> InsertPosition,
+        #[case] expected: [Tk2Op; 3],
+    ) {
+        let (mut circ, h, cx) = circuit();
+        let t = circ
+            .insert_command(Tk2Op::T, [CircuitUnit::Linear(0)], position(h, cx))
+            .unwrap();
+        circ.hugr().validate(&REGISTRY).unwrap();
+
+        let ops = circ
+            .commands()
+            .map(|cmd| Tk2Op::try_from(cmd.optype()).unwrap())
+            .collect_vec();
+        assert_eq!(ops, expected);
+        let expected = build_simple_circuit(2, |c| {
+            for op in expected {
+                match op {
+                    Tk2Op::CX => c.append(op, [0, 1])?,
+                    op => c.append(op, [0])?,
+                };
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(circ.unitary(), expected.unitary());
+
+        // Removing the command restores the original circuit.
+        assert_eq!(circ.remove_command(t), Ok(Tk2Op::T.into()));
+        circ.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(circ.unitary(), circuit().0.unitary());
+    }
+
+    #[test]
+    fn parametric() {
+        let (mut circ, _, cx) = circuit();
+        let num_nodes = circ.hugr().node_count();
+        let parent = circ.parent();
+        let hugr = circ.hugr_mut();
+        let constant = hugr.add_node_with_parent(parent, Const::new(ConstF64::new(0.5).into()));
+        let load = hugr.add_node_with_parent(
+            parent,
+            LoadConstant {
+                datatype: FLOAT64_TYPE,
+            },
+        );
+        hugr.connect(constant, 0, load, 0);
+
+        let rz = circ
+            .insert_command(
+                Tk2Op::RzF64,
+                [
+                    CircuitUnit::Linear(1),
+                    CircuitUnit::Wire(Wire::new(load, 0)),
+                ],
+                InsertPosition::Before(cx),
+            )
+            .unwrap();
+        circ.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(circ.num_operations(), 3);
+
+        // The angle computation is removed along with the command.
+        circ.remove_command(rz).unwrap();
+        circ.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(circ.hugr().node_count(), num_nodes);
+    }
+
+    #[test]
+    fn invalid_insertions() {
+        let (mut circ, h, _) = circuit();
+        assert!(matches!(
+            circ.insert_command(Tk2Op::CX, [CircuitUnit::Linear(0)], InsertPosition::End),
+            Err(CommandEditError::InvalidUnits { .. })
+        ));
+        assert!(matches!(
+            circ.insert_command(
+                Tk2Op::CX,
+                [CircuitUnit::Linear(1), CircuitUnit::Linear(1)],
+                InsertPosition::End
+            ),
+            Err(CommandEditError::InvalidUnits { .. })
+        ));
+        assert_eq!(
+            circ.insert_command(
+                Tk2Op::CX,
+                [CircuitUnit::Linear(0), CircuitUnit::Linear(1)],
+                InsertPosition::After(h)
+            ),
+            Err(CommandEditError::UnitNotAtPosition {
+                unit: LinearUnit::new(1),
+                position: InsertPosition::After(h)
+            })
+        );
+        assert!(matches!(
+            circ.insert_command(Tk2Op::QAlloc, [], InsertPosition::Start),
+            Err(CommandEditError::UnpairedLinearPorts { .. })
+        ));
+        assert_eq!(circ.num_operations(), 2);
+    }
+
+    #[test]
+    fn invalid_removals() {
+        let (mut circ, _, _) = circuit();
+        let input = circ.input_node();
+        assert_eq!(
+            circ.remove_command(input),
+            Err(CommandEditError::NotACommand { node: input })
+        );
+
+        let measure = circ
+            .insert_command(
+                Tk2Op::Measure,
+                [CircuitUnit::Linear(0)],
+                InsertPosition::End,
+            )
+            .unwrap();
+        let parent = circ.parent();
+        let noop = circ
+            .hugr_mut()
+            .add_node_with_parent(parent, Noop::new(BOOL_T));
+        circ.hugr_mut().connect(measure, 1, noop, 0);
+        assert!(matches!(
+            circ.remove_command(measure),
+            Err(CommandEditError::UsedOutputs { .. })
+        ));
+
+        // An unused measurement result does not prevent the removal.
+        circ.hugr_mut().remove_node(noop);
+        circ.remove_command(measure).unwrap();
+        circ.hugr().validate(&REGISTRY).unwrap();
+    }
+}
@@ -0,0 +1,209 @@
+//! Extraction of regions of a circuit into standalone circuits.
+//!
+//! An extracted region can be inspected or resynthesised on its own, and then
+//! put back into the original circuit with [`Subcircuit::create_rewrite`].
+
+use std::collections::HashSet;
+use std::ops::RangeBounds;
+
+use hugr::hugr::views::sibling_subgraph::InvalidSubgraph;
+use hugr::{HugrView, Node};
+use itertools::Itertools;
+
+use super::slices::command_units;
+use super::units::{LinearUnit, Qubit, UnitTracker};
+use super::Circuit;
+use crate::rewrite::Subcircuit;
+
+/// A region of a circuit, extracted into a standalone circuit.
+#[derive(Debug, Clone)]
+pub struct ExtractedSubcircuit {
+    /// The extracted circuit.
+    pub circuit: Circuit,
+    /// The region in the original circuit.
+    ///
+    /// Its boundary ports map the inputs and outputs of the extracted circuit
+    /// to the original circuit, see [`Subcircuit::incoming_ports`] and
+    /// [`Subcircuit::outgoing_ports`].
+    pub subcircuit: Subcircuit,
+    /// The linear unit of the original circuit carried by each input of the
+    /// extracted circuit, or `None` for non-linear inputs.
+    pub input_units: Vec<Option<LinearUnit>>,
+    /// The linear unit of the original circuit carried by each output of the
+    /// extracted circuit, or `None` for non-linear outputs.
+    pub output_units: Vec<Option<LinearUnit>>,
+}
+
+impl<T: HugrView> Circuit<T> {
+    /// Extract the region of the circuit induced by a set of nodes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the nodes do not form a convex region of the
+    /// circuit, or if the set is empty.
+    pub fn extract_subcircuit(
+        &self,
+        nodes: impl IntoIterator<Item = Node>,
+    ) -> Result<ExtractedSubcircuit, InvalidSubgraph> {
+        let nodes: Vec<Node> = nodes.into_iter().collect();
+        let subcircuit = Subcircuit::try_from_nodes(nodes, self)?;
+        let circuit: Circuit = subcircuit
+            .subgraph
+            .extract_subgraph(self.hugr(), self.name().unwrap_or_default())
+            .into();
+
+        let tracker = UnitTracker::new(self);
+        let input_units = subcircuit
+            .incoming_ports()
+            .iter()
+            .map(|ports| {
+                let &(node, port) = ports.first()?;
+                tracker.linear_unit(node, port)
+            })
+            .collect();
+        let output_units = subcircuit
+            .outgoing_ports()
+            .iter()
+            .map(|&(node, port)| tracker.linear_unit(node, port))
+            .collect();
+        Ok(ExtractedSubcircuit {
+            circuit,
+            subcircuit,
+            input_units,
+            output_units,
+        })
+    }
+
+    /// Extract the commands acting only on some qubits, within a range of
+    /// depths.
+    ///
+    /// The depth of a command is the index of its [moment](Circuit::moments),
+    /// starting from zero.
+    /// Purely classical operations, such as the ones computing rotation
+    /// angles, are never included; their values become inputs of the
+    /// extracted circuit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the selected commands do not form a convex region
+    /// of the circuit, for example when a command acting on other qubits is
+    /// applied between two of them, or if no commands are selected.
+    pub fn extract_window(
+        &self,
+        qubits: impl IntoIterator<Item = Qubit>,
+        depths: impl RangeBounds<usize>,
+    ) -> Result<ExtractedSubcircuit, InvalidSubgraph>
+    where
+        Self: Sized,
+    {
+        let qubits: HashSet<LinearUnit> = qubits.into_iter().map(LinearUnit::from).collect();
+        let nodes = self
+            .moments()
+            .into_iter()
+            .enumerate()
+            .filter(|(depth, _)| depths.contains(depth))
+            .flat_map(|(_, moment)| moment.into_commands())
+            .filter(|cmd| command_units(cmd).iter().all(|u| qubits.contains(u)))
+            .map(|cmd| cmd.node())
+            .collect_vec();
+        self.extract_subcircuit(nodes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    /// A three-qubit circuit with depth 4. The depths of the commands are
+    /// `H: 0`, `CX(0, 1): 1`, `CX(1, 2): 2`, `T: 2` and `S: 3`.
+    fn circuit() -> Circuit {
+        build_simple_circuit(3, |c| {
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            c.append(Tk2Op::CX, [1, 2])?;
+            c.append(Tk2Op::S, [1])?;
+            c.append(Tk2Op::T, [0])?;
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    fn ops(circ: &Circuit) -> Vec<Tk2Op> {
+        circ.commands()
+            .map(|cmd| Tk2Op::try_from(cmd.optype()).unwrap())
+            .collect()
+    }
+
+    #[rstest]
+    #[case::first_layers([0, 1], 0..2, vec![Tk2Op::H, Tk2Op::CX], 2)]
+    #[case::single_qubit([0], 0..1, vec![Tk2Op::H], 1)]
+    #[case::tail([1, 2], 2.., vec![Tk2Op::CX, Tk2Op::S], 2)]
+    #[case::all([0, 1, 2], .., vec![Tk2Op::H, Tk2Op::CX, Tk2Op::T, Tk2Op::CX, Tk2Op::S], 3)]
+    fn windows(
+        #[case] qubits: impl IntoIterator<Item = usize>,
+        #[case] depths: impl RangeBounds<usize>,
+        #[case] expected: Vec<Tk2Op>,
+        #[case] num_qubits: usize,
+    ) {
+        let circ = circuit();
+        let extracted = circ
+            .extract_window(qubits.into_iter().map(Qubit::new), depths)
+            .unwrap();
+        assert_eq!(
+            ops(&extracted.circuit).into_iter().sorted().collect_vec(),
+            expected.into_iter().sorted().collect_vec()
+        );
+        assert_eq!(extracted.circuit.qubit_count(), num_qubits);
+        // The boundary ports are not necessarily in the same order.
+        assert_eq!(
+            extracted.input_units.iter().sorted().collect_vec(),
+            extracted.output_units.iter().sorted().collect_vec()
+        );
+    }
+
+    #[test]
+    fn boundary() {
+        let circ = circuit();
+        let extracted = circ.extract_window([1, 2].map(Qubit::new), 2..3).unwrap();
+        assert_eq!(
+            extracted.input_units.iter().sorted().collect_vec(),
+            [&Some(LinearUnit::new(1)), &Some(LinearUnit::new(2))]
+        );
+        let [(cx, _)] = extracted.subcircuit.outgoing_ports()[..1] else {
+            panic!("Expected an output port");
+        };
+        assert_eq!(extracted.subcircuit.nodes(), [cx]);
+        assert_eq!(extracted.subcircuit.incoming_ports().len(), 2);
+    }
+
+    #[test]
+    fn resynthesise() {
+        let mut circ = circuit();
+        let extracted = circ.extract_window([1, 2].map(Qubit::new), 2..).unwrap();
+        // Replace the final `CX; S` with the same gates.
+        let rewrite = extracted
+            .subcircuit
+            .create_rewrite(&circ, extracted.circuit.clone())
+            .unwrap();
+        rewrite.apply(&mut circ).unwrap();
+        assert_eq!(circ.unitary(), circuit().unitary());
+    }
+
+    #[test]
+    fn non_convex() {
+        let circ = circuit();
+        // The CX on qubits 1 and 2 is applied between the two selected gates
+        // on qubit 1, but is not selected.
+        let nodes = circ
+            .commands()
+            .filter(|cmd| [Tk2Op::CX, Tk2Op::S].contains(&Tk2Op::try_from(cmd.optype()).unwrap()))
+            .map(|cmd| cmd.node())
+            .collect_vec();
+        assert!(circ.extract_subcircuit([nodes[0], nodes[2]]).is_err());
+        assert!(circ.extract_window([0, 1].map(Qubit::new), ..).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! Freezing regions of a circuit so that passes leave them untouched.
+//!
+//! Frozen nodes are marked with the [`METADATA_FROZEN`] metadata key. This lets
+//! users recompile only the parts of a large program that changed, keeping
+//! previously compiled regions intact.
+//!
+//! The following passes skip frozen nodes:
+//! - Pattern matching, and hence the rewriters and optimisers built on it.
+//! - [`crate::passes::apply_greedy_commutation`], which neither moves frozen
+//!   commands nor commutes other commands through them.
+//! - [`crate::passes::apply_list_scheduling_commutation`], with the same
+//!   restrictions.
+//! - [`crate::passes::cx_cancellation()`], which neither removes frozen CX gates
+//!   nor commutes CX gates through frozen commands.
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::hugr::NodeMetadata;
+use hugr::{HugrView, Node};
+
+use crate::rewrite::Subcircuit;
+use crate::Circuit;
+
+/// Metadata key marking a node as frozen.
+pub const METADATA_FROZEN: &str = "TKET2.frozen";
+
+impl<T: HugrView> Circuit<T> {
+    /// Returns `true` if the node has been frozen.
+    #[inline]
+    pub fn is_frozen(&self, node: Node) -> bool {
+        self.hugr().get_metadata(node, METADATA_FROZEN) == Some(&NodeMetadata::Bool(true))
+    }
+
+    /// Returns the frozen commands of the circuit, in topological order.
+    pub fn frozen_nodes(&self) -> impl Iterator<Item = Node> + '_
+    where
+        Self: Sized,
+    {
+        self.commands()
+            .map(|cmd| cmd.node())
+            .filter(|&node| self.is_frozen(node))
+    }
+}
+
+impl<T: HugrMut> Circuit<T> {
+    /// Freeze a set of nodes, so that subsequent passes skip them.
+    pub fn freeze_nodes(&mut self, nodes: impl IntoIterator<Item = Node>) {
+        for node in nodes {
+            self.hugr_mut()
+                .set_metadata(node, METADATA_FROZEN, NodeMetadata::Bool(true));
+        }
+    }
+
+    /// Freeze all the nodes in a subcircuit.
+    pub fn freeze(&mut self, subcircuit: &Subcircuit) {
+        self.freeze_nodes(subcircuit.nodes().iter().copied());
+    }
+
+    /// Unfreeze a set of nodes, making them available to passes again.
+    pub fn unfreeze_nodes(&mut self, nodes: impl IntoIterator<Item = Node>) {
+        let hugr = self.hugr_mut();
+        for node in nodes {
+            let Some(mut meta) = hugr.take_node_metadata(node) else {
+                continue;
+            };
+            meta.remove(METADATA_FROZEN);
+            let meta = (!meta.is_empty()).then_some(meta);
+            hugr.overwrite_node_metadata(node, meta);
+        }
+    }
+
+    /// Unfreeze every node in the circuit.
+    pub fn unfreeze_all(&mut self)
+    where
+        Self: Sized,
+    {
+        let frozen: Vec<_> = self.frozen_nodes().collect();
+        self.unfreeze_nodes(frozen);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    #[test]
+    fn freeze_and_unfreeze() {
+        let mut circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::T, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        let nodes = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        assert_eq!(circ.frozen_nodes().count(), 0);
+
+        let subcirc = Subcircuit::try_from_nodes(nodes[..2].to_vec(), &circ).unwrap();
+        circ.freeze(&subcirc);
+        assert_eq!(circ.frozen_nodes().collect_vec(), nodes[..2]);
+        assert!(!circ.is_frozen(nodes[2]));
+
+        circ.unfreeze_nodes([nodes[0]]);
+        assert_eq!(circ.frozen_nodes().collect_vec(), [nodes[1]]);
+
+        circ.unfreeze_all();
+        assert_eq!(circ.frozen_nodes().count(), 0);
+    }
+}
@@ -0,0 +1,417 @@
+//! Generators for common benchmark circuits.
+//!
+//! These build standard families of circuits directly, so passes can be
+//! benchmarked and tested without loading external files. The randomised
+//! generators are deterministic for a given seed, and have `_with_rng`
+//! variants drawing from a user-provided random number generator.
+
+use std::f64::consts::{PI, TAU};
+
+use hugr::builder::{BuildError, CircuitBuilder, Dataflow};
+use hugr::extension::prelude::QB_T;
+use hugr::ops::{OpTrait, OpType};
+use hugr::std_extensions::arithmetic::float_types::ConstF64;
+use hugr::CircuitUnit;
+use itertools::Itertools;
+use rand::seq::{index, SliceRandom};
+use rand::Rng;
+
+use super::phase::GlobalPhase;
+use crate::seed::{self, Seed};
+use crate::utils::build_simple_circuit;
+use crate::{Circuit, Tk2Op};
+
+/// The gates sampled by [`random_clifford_t`].
+const CLIFFORD_T_GATES: [Tk2Op; 9] = [
+    Tk2Op::H,
+    Tk2Op::S,
+    Tk2Op::Sdg,
+    Tk2Op::T,
+    Tk2Op::Tdg,
+    Tk2Op::X,
+    Tk2Op::Y,
+    Tk2Op::Z,
+    Tk2Op::CX,
+];
+
+/// The quantum Fourier transform on `num_qubits` qubits.
+///
+/// The first qubit is the most significant bit of the input and output
+/// registers. The controlled phase rotations are built from `Rz` and
+/// `ZZPhase` gates, and the final qubit reversal from `CX` gates, so the
+/// circuit is exactly the QFT once its global phase is taken into account.
+pub fn qft(num_qubits: usize) -> Circuit {
+    let mut phase = 0.;
+    let mut circ = build_simple_circuit(num_qubits, |c| {
+        for j in 0..num_qubits {
+            c.append(Tk2Op::H, [j])?;
+            for k in j + 1..num_qubits {
+                let theta = PI / (1 << (k - j)) as f64;
+                phase += controlled_phase(c, k, j, theta)?;
+            }
+        }
+        for j in 0..num_qubits / 2 {
+            let k = num_qubits - 1 - j;
+            c.append(Tk2Op::CX, [j, k])?;
+            c.append(Tk2Op::CX, [k, j])?;
+            c.append(Tk2Op::CX, [j, k])?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    circ.add_global_phase(Some(GlobalPhase::new(phase / PI)));
+    circ
+}
+
+/// A circuit preparing the `num_qubits`-qubit GHZ state from `|0…0⟩`, with a
+/// Hadamard gate followed by a chain of `CX` gates.
+pub fn ghz(num_qubits: usize) -> Circuit {
+    build_simple_circuit(num_qubits, |c| {
+        if num_qubits > 0 {
+            c.append(Tk2Op::H, [0])?;
+        }
+        for (a, b) in (0..num_qubits).tuple_windows() {
+            c.append(Tk2Op::CX, [a, b])?;
+        }
+        Ok(())
+    })
+    .unwrap()
+}
+
+/// A quantum volume model circuit with `depth` layers.
+///
+/// Each layer applies a random two-qubit unitary to every pair of a random
+/// pairing of the qubits. The unitaries are given in their canonical form,
+/// with `TK1` gates around `XXPhase`, `YYPhase` and `ZZPhase` interactions,
+/// and uniformly random angles.
+pub fn quantum_volume(num_qubits: usize, depth: usize, seed: Seed) -> Circuit {
+    quantum_volume_with_rng(num_qubits, depth, &mut seed::rng(seed))
+}
+
+/// A quantum volume model circuit with `depth` layers, drawing from `rng`.
+///
+/// See [`quantum_volume`].
+pub fn quantum_volume_with_rng(num_qubits: usize, depth: usize, rng: &mut impl Rng) -> Circuit {
+    build_simple_circuit(num_qubits, |c| {
+        let mut qubits = (0..num_qubits).collect_vec();
+        for _ in 0..depth {
+            qubits.shuffle(rng);
+            for (&a, &b) in qubits.iter().tuples() {
+                for q in [a, b] {
+                    append_rotation(c, Tk2Op::TK1, &[q], &random_angles::<3>(rng))?;
+                }
+                for op in [Tk2Op::XXPhase, Tk2Op::YYPhase, Tk2Op::ZZPhase] {
+                    append_rotation(c, op, &[a, b], &random_angles::<1>(rng))?;
+                }
+                for q in [a, b] {
+                    append_rotation(c, Tk2Op::TK1, &[q], &random_angles::<3>(rng))?;
+                }
+            }
+        }
+        Ok(())
+    })
+    .unwrap()
+}
+
+/// A random circuit of `num_gates` gates drawn uniformly from the Clifford+T
+/// gates `H`, `S`, `Sdg`, `T`, `Tdg`, `X`, `Y`, `Z` and `CX`.
+///
+/// `CX` gates act on two distinct random qubits, and are only drawn when the
+/// circuit has at least two qubits.
+pub fn random_clifford_t(num_qubits: usize, num_gates: usize, seed: Seed) -> Circuit {
+    random_clifford_t_with_rng(num_qubits, num_gates, &mut seed::rng(seed))
+}
+
+/// A random Clifford+T circuit of `num_gates` gates, drawing from `rng`.
+///
+/// See [`random_clifford_t`].
+pub fn random_clifford_t_with_rng(
+    num_qubits: usize,
+    num_gates: usize,
+    rng: &mut impl Rng,
+) -> Circuit {
+    let gates = match num_qubits {
+        0 => &[][..],
+        1 => &CLIFFORD_T_GATES[..CLIFFORD_T_GATES.len() - 1],
+        _ => &CLIFFORD_T_GATES[..],
+    };
+    build_simple_circuit(num_qubits, |c| {
+        for _ in 0..num_gates {
+            let Some(&op) = gates.choose(rng) else {
+                break;
+            };
+            match op {
+                Tk2Op::CX => c.append(op, index::sample(rng, num_qubits, 2).into_vec())?,
+                _ => c.append(op, [rng.gen_range(0..num_qubits)])?,
+            };
+        }
+        Ok(())
+    })
+    .unwrap()
+}
+
+/// The constraints on the circuits built by [`random_circuit`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomCircuitSpec {
+    /// The number of qubits in the circuit.
+    ///
+    /// Defaults to `3`.
+    pub num_qubits: usize,
+    /// The number of gate layers.
+    ///
+    /// Defaults to `10`.
+    pub depth: usize,
+    /// The gates to sample from. Operations that are not unitary quantum
+    /// gates, such as [`Tk2Op::Measure`], are ignored.
+    ///
+    /// Defaults to `H`, `T`, `Tdg`, `S`, `Sdg`, `X`, `Y`, `Z`, `RzF64`,
+    /// `RxF64`, `CX`, `CZ` and `ZZPhase`.
+    pub gate_set: Vec<Tk2Op>,
+    /// The probability of placing a multi-qubit gate on each group of free
+    /// qubits in a layer, between `0` and `1`.
+    ///
+    /// Defaults to `0.3`.
+    pub two_qubit_density: f64,
+}
+
+impl Default for RandomCircuitSpec {
+    fn default() -> Self {
+        use Tk2Op::*;
+        Self {
+            num_qubits: 3,
+            depth: 10,
+            gate_set: vec![H, T, Tdg, S, Sdg, X, Y, Z, RzF64, RxF64, CX, CZ, ZZPhase],
+            two_qubit_density: 0.3,
+        }
+    }
+}
+
+/// A random circuit satisfying the constraints of `spec`.
+///
+/// Each layer visits the qubits in a random order. With probability
+/// `spec.two_qubit_density` the next free qubits get a random multi-qubit gate
+/// from the gate set, otherwise the next qubit gets a random single-qubit
+/// gate. Parameterised gates use uniformly random constant angles.
+///
+/// Layers may leave some qubits idle when the gate set has no gate of the
+/// right size. The circuit has no global phase.
+pub fn random_circuit(spec: &RandomCircuitSpec, seed: Seed) -> Circuit {
+    random_circuit_with_rng(spec, &mut seed::rng(seed))
+}
+
+/// A random circuit satisfying the constraints of `spec`, drawing from `rng`.
+///
+/// See [`random_circuit`].
+pub fn random_circuit_with_rng(spec: &RandomCircuitSpec, rng: &mut impl Rng) -> Circuit {
+    let (single, multi): (Vec<_>, Vec<_>) = spec
+        .gate_set
+        .iter()
+        .filter(|op| op.is_quantum())
+        .map(|&op| (op, gate_arity(op)))
+        .partition(|&(_, (qubits, _))| qubits == 1);
+    let density = spec.two_qubit_density.clamp(0., 1.);
+    let num_qubits = spec.num_qubits;
+
+    build_simple_circuit(num_qubits, |c| {
+        let mut qubits = (0..num_qubits).collect_vec();
+        for _ in 0..spec.depth {
+            qubits.shuffle(rng);
+            let mut free = &qubits[..];
+            while !free.is_empty() {
+                let fitting = multi
+                    .iter()
+                    .filter(|(_, (n, _))| *n <= free.len())
+                    .collect_vec();
+                let gate = match fitting.is_empty() || !rng.gen_bool(density) {
+                    true => single.choose(rng),
+                    false => fitting.choose(rng).copied(),
+                };
+                let Some(&(op, (n, params))) = gate else {
+                    free = &free[1..];
+                    continue;
+                };
+                let angles = (0..params).map(|_| rng.gen_range(0. ..TAU)).collect_vec();
+                append_rotation(c, op, &free[..n], &angles)?;
+                free = &free[n..];
+            }
+        }
+        Ok(())
+    })
+    .unwrap()
+}
+
+/// The number of qubits and angle parameters of a gate.
+fn gate_arity(op: Tk2Op) -> (usize, usize) {
+    let sig = OpType::from(op)
+        .dataflow_signature()
+        .expect("Tk2Ops have a dataflow signature.");
+    let qubits = sig.input_types().iter().filter(|t| **t == QB_T).count();
+    (qubits, sig.input_count() - qubits)
+}
+
+/// Append a controlled phase gate `diag(1, 1, 1, e^{iθ})`.
+///
+/// It is built as `Rz(θ/2) ⊗ Rz(θ/2) · ZZPhase(-θ/2)`, which differs from the
+/// gate by a global phase. Returns the phase, in radians.
+fn controlled_phase<T: Dataflow>(
+    c: &mut CircuitBuilder<T>,
+    a: usize,
+    b: usize,
+    theta: f64,
+) -> Result<f64, BuildError> {
+    append_rotation(c, Tk2Op::RzF64, &[a], &[theta / 2.])?;
+    append_rotation(c, Tk2Op::RzF64, &[b], &[theta / 2.])?;
+    append_rotation(c, Tk2Op::ZZPhase, &[a, b], &[-theta / 2.])?;
+    Ok(theta / 4.)
+}
+
+/// Append a gate with constant angle parameters, in radians.
+fn append_rotation<T: Dataflow>(
+    c: &mut CircuitBuilder<T>,
+    op: Tk2Op,
+    qubits: &[usize],
+    angles: &[f64],
+) -> Result<(), BuildError> {
+    let angles = angles
+        .iter()
+        .map(|&angle| CircuitUnit::Wire(c.add_constant(ConstF64::new(angle))))
+        .collect_vec();
+    let inputs = qubits.iter().map(|&q| CircuitUnit::Linear(q)).chain(angles);
+    c.append_and_consume(op, inputs)?;
+    Ok(())
+}
+
+fn random_angles<const N: usize>(rng: &mut impl Rng) -> [f64; N] {
+    [(); N].map(|_| rng.gen_range(0. ..TAU))
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::Array2;
+    use num_complex::Complex64;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::sim::simulate_statevector;
+
+    fn gate_count(circ: &Circuit, op: Tk2Op) -> usize {
+        circ.commands()
+            .filter(|cmd| Tk2Op::try_from(cmd.optype()) == Ok(op))
+            .count()
+    }
+
+    #[rstest]
+    #[case(1)]
+    #[case(2)]
+    #[case(3)]
+    fn qft_unitary(#[case] num_qubits: usize) {
+        let circ = qft(num_qubits);
+        let half_turns = circ.global_phase().unwrap().constant();
+        let phase = Complex64::from_polar(1., half_turns * PI);
+        let unitary = circ.unitary().unwrap().mapv(|x| x * phase);
+
+        // The simulator uses the first qubit as the least significant bit.
+        let reverse = |i: usize| (0..num_qubits).fold(0, |r, q| (r << 1) | ((i >> q) & 1));
+        let dim = 1 << num_qubits;
+        let expected = Array2::from_shape_fn((dim, dim), |(i, j)| {
+            let angle = TAU * (reverse(i) * reverse(j)) as f64 / dim as f64;
+            Complex64::from_polar(1. / (dim as f64).sqrt(), angle)
+        });
+        assert!((&unitary - &expected).iter().all(|x| x.norm() < 1e-9));
+    }
+
+    #[test]
+    fn ghz_state() {
+        let circ = ghz(4);
+        let state = simulate_statevector(&circ).unwrap();
+        let probabilities = state.probabilities();
+        assert!((probabilities[0] - 0.5).abs() < 1e-9);
+        assert!((probabilities[15] - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn quantum_volume_layers() {
+        let circ = quantum_volume(5, 3, 7);
+        circ.hugr().validate(&REGISTRY).unwrap();
+        // Two qubit pairs per layer.
+        assert_eq!(gate_count(&circ, Tk2Op::ZZPhase), 6);
+        assert_eq!(gate_count(&circ, Tk2Op::TK1), 24);
+        assert!(circ.unitary().is_ok());
+
+        let same_seed = quantum_volume(5, 3, 7);
+        assert_eq!(circ.unitary().unwrap(), same_seed.unitary().unwrap());
+    }
+
+    #[rstest]
+    #[case(1, 20)]
+    #[case(4, 50)]
+    fn random_clifford_t_gates(#[case] num_qubits: usize, #[case] num_gates: usize) {
+        let circ = random_clifford_t(num_qubits, num_gates, 3);
+        circ.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(circ.qubit_count(), num_qubits);
+        assert_eq!(circ.num_operations(), num_gates);
+        for cmd in circ.commands() {
+            let op = Tk2Op::try_from(cmd.optype()).unwrap();
+            assert!(CLIFFORD_T_GATES.contains(&op));
+        }
+
+        let other_seed = random_clifford_t(num_qubits, num_gates, 4);
+        assert_ne!(
+            circ.commands()
+                .map(|cmd| cmd.optype().clone())
+                .collect_vec(),
+            other_seed
+                .commands()
+                .map(|cmd| cmd.optype().clone())
+                .collect_vec()
+        );
+    }
+
+    #[rstest]
+    #[case(0.)]
+    #[case(0.5)]
+    #[case(1.)]
+    fn random_circuit_spec(#[case] density: f64) {
+        let spec = RandomCircuitSpec {
+            num_qubits: 4,
+            depth: 6,
+            gate_set: vec![Tk2Op::H, Tk2Op::RzF64, Tk2Op::CX, Tk2Op::Measure],
+            two_qubit_density: density,
+        };
+        let circ = random_circuit(&spec, 11);
+        circ.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(circ.qubit_count(), 4);
+        assert!(circ.unitary().is_ok());
+
+        let two_qubit = gate_count(&circ, Tk2Op::CX);
+        let one_qubit = gate_count(&circ, Tk2Op::H) + gate_count(&circ, Tk2Op::RzF64);
+        assert_eq!(one_qubit + 2 * two_qubit, 4 * 6);
+        match density {
+            0. => assert_eq!(two_qubit, 0),
+            1. => assert_eq!(two_qubit, 2 * 6),
+            _ => assert!(two_qubit > 0 && one_qubit > 0),
+        }
+
+        let same_seed = random_circuit(&spec, 11);
+        assert_eq!(circ.unitary().unwrap(), same_seed.unitary().unwrap());
+    }
+
+    #[test]
+    fn random_circuit_three_qubit_gates() {
+        let spec = RandomCircuitSpec {
+            num_qubits: 3,
+            depth: 4,
+            gate_set: vec![Tk2Op::CCX],
+            two_qubit_density: 1.,
+        };
+        let circ = random_circuit(&spec, 0);
+        assert_eq!(gate_count(&circ, Tk2Op::CCX), 4);
+
+        let spec = RandomCircuitSpec {
+            num_qubits: 2,
+            ..spec
+        };
+        assert_eq!(random_circuit(&spec, 0).num_operations(), 0);
+    }
+}
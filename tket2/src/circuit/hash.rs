@@ -5,11 +5,15 @@ use std::hash::{Hash, Hasher};
 use fxhash::{FxHashMap, FxHasher64};
 use hugr::hugr::views::{HierarchyView, SiblingGraph};
 use hugr::ops::{NamedOp, OpType};
-use hugr::{HugrView, Node};
+use hugr::{HugrView, Node, OutgoingPort, PortIndex};
+use itertools::Itertools;
 use petgraph::visit::{self as pg, Walker};
 use thiserror::Error;
 
+use super::units::LinearUnit;
 use super::Circuit;
+use crate::ops::Pauli;
+use crate::Tk2Op;
 
 /// Circuit hashing utilities.
 pub trait CircuitHash {
@@ -42,26 +46,185 @@ where
         let Some([_, output_node]) = self.get_io(self.root()) else {
             return Err(HashError::NotADfg);
         };
+        let node_hashes = hash_nodes(self)?;
+        // If the output node has no hash, the topological sort failed due to a cycle.
+        node_hashes
+            .node_hash(output_node)
+            .ok_or(HashError::CyclicCircuit)
+    }
+}
 
-        let mut node_hashes = HashState::default();
+impl<T: HugrView> Circuit<T> {
+    /// Compute a hash of the circuit that does not depend on the order of
+    /// commuting operations.
+    ///
+    /// [`CircuitHash::circuit_hash`] distinguishes circuits that only differ
+    /// by the order of two commuting gates on a shared qubit, such as an `Rz`
+    /// before or after the control of a `CX`. Here, the commands are first put
+    /// in a canonical order: at each step, among the commands that can be
+    /// moved to the front by commuting them with the remaining ones, we pick
+    /// the one acting on the smallest qubit indices. Two operations commute
+    /// on a qubit if they act on it in the same Pauli basis, according to
+    /// the commutation data of [`Tk2Op`]. The hash is computed from this
+    /// sequence and the final qubit permutation.
+    ///
+    /// This is slower than [`CircuitHash::circuit_hash`], with a worst-case
+    /// running time quadratic in the number of commands.
+    pub fn canonical_hash(&self) -> Result<u64, HashError> {
+        let container: SiblingGraph = SiblingGraph::try_new(self.hugr(), self.parent()).unwrap();
+        let node_hashes = hash_nodes(&container)?;
+        let hugr = self.hugr();
+        let [input, output] = self.io_nodes();
+        let wire_hash = |node: Node, port: OutgoingPort| -> Result<u64, HashError> {
+            let hash = node_hashes
+                .node_hash(node)
+                .ok_or(HashError::CyclicCircuit)?;
+            Ok(fxhash::hash64(&(hash, port)))
+        };
+
+        // The qubit commands, and the linear unit carried by each wire.
+        let mut commands: Vec<CanonicalCommand> = Vec::new();
+        let mut units: FxHashMap<(Node, OutgoingPort), LinearUnit> = self
+            .linear_units()
+            .map(|(unit, port, _)| ((input, port), unit))
+            .collect();
+        // The qubit commands each node depends on through non-linear wires.
+        let mut command_deps: FxHashMap<Node, Vec<usize>> = FxHashMap::default();
+        for cmd in self.commands() {
+            let node = cmd.node();
+            let mut op_hasher = FxHasher64::default();
+            hashable_op(cmd.optype()).hash(&mut op_hasher);
+            let mut deps = Vec::new();
+            for (_, port, _) in cmd.inputs().filter(|(u, _, _)| u.is_wire()) {
+                let Some((src, src_port)) = hugr.single_linked_output(node, port) else {
+                    continue;
+                };
+                (port, wire_hash(src, src_port)?).hash(&mut op_hasher);
+                deps.extend(command_deps.get(&src).into_iter().flatten().copied());
+            }
+            for (unit, port, _) in cmd.linear_outputs() {
+                units.insert((node, port), unit);
+            }
 
-        for node in pg::Topo::new(&self.as_petgraph())
-            .iter(&self.as_petgraph())
-            .filter(|&n| n != self.root())
-        {
-            let hash = hash_node(self, node, &mut node_hashes)?;
-            if node_hashes.set_hash(node, hash).is_some() {
-                panic!("Hash already set for node {node}");
+            let commutation = Tk2Op::try_from(cmd.optype())
+                .map(|op| op.qubit_commutation())
+                .unwrap_or_default();
+            let mut qubits = cmd
+                .linear_inputs()
+                .map(|(unit, port, _)| {
+                    let pauli = commutation
+                        .iter()
+                        .find_map(|&(i, p)| (i == port.index()).then_some(p));
+                    (unit.index(), pauli)
+                })
+                .collect_vec();
+            // Allocated qubits.
+            for (unit, _, _) in cmd.linear_outputs() {
+                if qubits.iter().all(|&(q, _)| q != unit.index()) {
+                    qubits.push((unit.index(), None));
+                }
             }
+            if qubits.is_empty() {
+                deps.sort_unstable();
+                deps.dedup();
+                command_deps.insert(node, deps);
+                continue;
+            }
+            command_deps.insert(node, vec![commands.len()]);
+            commands.push(CanonicalCommand {
+                key: (qubits.iter().map(|&(q, _)| q).collect(), op_hasher.finish()),
+                qubits,
+                deps,
+            });
         }
 
-        // If the output node has no hash, the topological sort failed due to a cycle.
-        node_hashes
-            .node_hash(output_node)
-            .ok_or(HashError::CyclicCircuit)
+        let mut hasher = FxHasher64::default();
+        for i in canonical_order(&commands) {
+            commands[i].key.hash(&mut hasher);
+        }
+        // Hash the qubit permutation and non-linear outputs.
+        for port in hugr.node_inputs(output) {
+            let Some((src, src_port)) = hugr.single_linked_output(output, port) else {
+                continue;
+            };
+            match units.get(&(src, src_port)) {
+                Some(unit) => unit.index().hash(&mut hasher),
+                None => wire_hash(src, src_port)?.hash(&mut hasher),
+            }
+        }
+        Ok(hasher.finish())
     }
 }
 
+/// A command with qubits, for the canonical ordering in
+/// [`Circuit::canonical_hash`].
+#[derive(Clone, Debug)]
+struct CanonicalCommand {
+    /// The qubit indices and a hash of the operation and its non-linear
+    /// inputs, used to pick the next command.
+    key: (Vec<usize>, u64),
+    /// The qubit indices, with the Pauli basis the command commutes with on
+    /// each of them, if any.
+    qubits: Vec<(usize, Option<Pauli>)>,
+    /// The earlier commands this command depends on through non-linear wires.
+    deps: Vec<usize>,
+}
+
+/// Returns the canonical order of a topologically sorted list of commands.
+fn canonical_order(commands: &[CanonicalCommand]) -> Vec<usize> {
+    let mut order = Vec::with_capacity(commands.len());
+    let mut emitted = vec![false; commands.len()];
+    let mut remaining = (0..commands.len()).collect_vec();
+    while !remaining.is_empty() {
+        // For each qubit, the basis all the preceding remaining commands
+        // commute with, or `None` if there are none.
+        let mut bases: FxHashMap<usize, Option<Pauli>> = FxHashMap::default();
+        let mut best: Option<usize> = None;
+        for (pos, &i) in remaining.iter().enumerate() {
+            let cmd = &commands[i];
+            let available = cmd.deps.iter().all(|&d| emitted[d])
+                && cmd.qubits.iter().all(|(q, p)| match (bases.get(q), p) {
+                    (None, _) => true,
+                    (Some(Some(b)), Some(p)) => p.commutes_with(*b),
+                    _ => false,
+                });
+            if available && best.map_or(true, |b| cmd.key < commands[remaining[b]].key) {
+                best = Some(pos);
+            }
+            for &(q, p) in &cmd.qubits {
+                bases
+                    .entry(q)
+                    .and_modify(|b| {
+                        if *b != p {
+                            *b = None
+                        }
+                    })
+                    .or_insert(p);
+            }
+        }
+        // The first remaining command is always available.
+        let i = remaining.remove(best.unwrap());
+        emitted[i] = true;
+        order.push(i);
+    }
+    order
+}
+
+/// Compute the hash of each node in a dataflow region.
+fn hash_nodes(hugr: &impl HugrView) -> Result<HashState, HashError> {
+    let mut node_hashes = HashState::default();
+    for node in pg::Topo::new(&hugr.as_petgraph())
+        .iter(&hugr.as_petgraph())
+        .filter(|&n| n != hugr.root())
+    {
+        let hash = hash_node(hugr, node, &mut node_hashes)?;
+        if node_hashes.set_hash(node, hash).is_some() {
+            panic!("Hash already set for node {node}");
+        }
+    }
+    Ok(node_hashes)
+}
+
 /// Auxiliary data for circuit hashing.
 ///
 /// Contains previously computed hashes.
@@ -195,6 +358,80 @@ mod test {
         assert_ne!(hash1, hash3);
     }
 
+    #[test]
+    fn canonical_hash_commutation() {
+        let t_cx = |t_first: bool, x_first: bool| {
+            build_simple_circuit(2, |circ| {
+                if t_first {
+                    circ.append(Tk2Op::T, [0])?;
+                }
+                if x_first {
+                    circ.append(Tk2Op::X, [1])?;
+                }
+                circ.append(Tk2Op::CX, [0, 1])?;
+                if !t_first {
+                    circ.append(Tk2Op::T, [0])?;
+                }
+                if !x_first {
+                    circ.append(Tk2Op::X, [1])?;
+                }
+                Ok(())
+            })
+            .unwrap()
+        };
+        let circ = t_cx(true, true);
+        for (t_first, x_first) in [(true, false), (false, true), (false, false)] {
+            let other = t_cx(t_first, x_first);
+            assert_ne!(circ.circuit_hash(), other.circuit_hash());
+            assert_eq!(circ.canonical_hash(), other.canonical_hash());
+        }
+
+        // `H` does not commute with the control of the `CX`.
+        let h_cx = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        let cx_h = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::H, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        assert_ne!(h_cx.canonical_hash(), cx_h.canonical_hash());
+
+        // A `T` on the target of the `CX` does not commute either.
+        let t_target = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::T, [1])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        let target_t = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::T, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        assert_ne!(t_target.canonical_hash(), target_t.canonical_hash());
+    }
+
+    #[test]
+    fn canonical_hash_parameters() {
+        let c_str = |angle: &str| {
+            format!(
+                r#"{{"bits": [], "commands": [{{"args": [["q", [0]]], "op": {{"params": ["{angle}"], "type": "Rz"}}}}, {{"args": [["q", [0]], ["q", [1]]], "op": {{"type": "CX"}}}}], "created_qubits": [], "discarded_qubits": [], "implicit_permutation": [[["q", [0]], ["q", [0]]], [["q", [1]], ["q", [1]]]], "phase": "0.0", "qubits": [["q", [0]], ["q", [1]]]}}"#
+            )
+        };
+        let hashes = ["0.5", "1.0"].map(|angle| {
+            let ser: circuit_json::SerialCircuit = serde_json::from_str(&c_str(angle)).unwrap();
+            let circ: Circuit = ser.decode().unwrap();
+            circ.canonical_hash().unwrap()
+        });
+        assert_ne!(hashes[0], hashes[1]);
+    }
+
     #[test]
     fn hash_constants() {
         let c_str = r#"{"bits": [], "commands": [{"args": [["q", [0]]], "op": {"params": ["0.5"], "type": "Rz"}}], "created_qubits": [], "discarded_qubits": [], "implicit_permutation": [[["q", [0]], ["q", [0]]]], "phase": "0.0", "qubits": [["q", [0]]]}"#;
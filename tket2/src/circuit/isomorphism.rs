@@ -0,0 +1,66 @@
+//! Structural equality of circuits.
+
+use fxhash::FxHashMap;
+use hugr::{Direction, HugrView, Node, Port, PortIndex};
+use petgraph::algo::is_isomorphic_matching;
+use petgraph::graph::{DiGraph, NodeIndex};
+
+use super::{Circuit, OpType};
+
+/// A node in the graph representation of a circuit, used to check isomorphisms.
+///
+/// Each port is represented as a separate node so that parallel wires between
+/// two operations do not result in a multigraph, and so that the port offsets
+/// are preserved by the isomorphism.
+#[derive(Debug, Clone, PartialEq)]
+enum GraphNode<'a> {
+    /// An operation in the circuit.
+    Op(&'a OpType),
+    /// A port of an operation.
+    Port(Direction, usize),
+}
+
+/// Returns `true` if the two circuits are isomorphic.
+///
+/// See [`Circuit::equal_structure`].
+pub(super) fn equal_structure(a: &Circuit<impl HugrView>, b: &Circuit<impl HugrView>) -> bool {
+    let graph_a = port_graph(a);
+    let graph_b = port_graph(b);
+    is_isomorphic_matching(&graph_a, &graph_b, PartialEq::eq, |(), ()| true)
+}
+
+/// Build a graph of the operations in the circuit's region and their ports.
+fn port_graph<T: HugrView>(circ: &Circuit<T>) -> DiGraph<GraphNode<'_>, ()> {
+    let hugr = circ.hugr();
+    let mut graph = DiGraph::new();
+    let mut port_indices: FxHashMap<(Node, Port), NodeIndex> = FxHashMap::default();
+
+    for node in hugr.children(circ.parent()) {
+        let op = graph.add_node(GraphNode::Op(hugr.get_optype(node)));
+        for port in hugr.all_node_ports(node) {
+            let p = graph.add_node(GraphNode::Port(port.direction(), port.index()));
+            match port.direction() {
+                Direction::Incoming => graph.add_edge(p, op, ()),
+                Direction::Outgoing => graph.add_edge(op, p, ()),
+            };
+            port_indices.insert((node, port), p);
+        }
+    }
+
+    for node in hugr.children(circ.parent()) {
+        for (out_port, tgt, in_port) in hugr.node_outputs(node).flat_map(|p| {
+            hugr.linked_inputs(node, p)
+                .map(move |(tgt, in_port)| (p, tgt, in_port))
+        }) {
+            // Edges leaving the region are ignored.
+            let (Some(&src), Some(&tgt)) = (
+                port_indices.get(&(node, out_port.into())),
+                port_indices.get(&(tgt, in_port.into())),
+            ) else {
+                continue;
+            };
+            graph.add_edge(src, tgt, ());
+        }
+    }
+    graph
+}
@@ -0,0 +1,136 @@
+//! Operation groups, used to address gates across transformations.
+//!
+//! Pytket commands may carry an `opgroup` label, used for example to
+//! substitute the parameters of a set of gates later on. The label is stored
+//! in the [`METADATA_OPGROUP`] metadata of the decoded nodes and restored when
+//! encoding the circuit.
+//!
+//! Rewrites created with [`Subcircuit::create_rewrite`] or
+//! [`CircuitRewrite::try_new`] keep the group of each operation that survives
+//! the rewrite unchanged, so the grouped gates can still be found after
+//! optimisation.
+//!
+//! [`Subcircuit::create_rewrite`]: crate::rewrite::Subcircuit::create_rewrite
+//! [`CircuitRewrite::try_new`]: crate::rewrite::CircuitRewrite::try_new
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::hugr::NodeMetadata;
+use hugr::{Hugr, HugrView, Node};
+use itertools::Itertools;
+
+use crate::Circuit;
+
+/// Metadata key with the operation group of a node.
+pub const METADATA_OPGROUP: &str = "TKET1.opgroup";
+
+impl<T: HugrView> Circuit<T> {
+    /// Returns the operation group of a node, if it has one.
+    pub fn op_group(&self, node: Node) -> Option<&str> {
+        self.hugr().get_metadata(node, METADATA_OPGROUP)?.as_str()
+    }
+
+    /// Returns the commands in an operation group, in topological order.
+    pub fn ops_in_group<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Node> + 'a
+    where
+        Self: Sized,
+    {
+        self.commands()
+            .map(|cmd| cmd.node())
+            .filter(move |&node| self.op_group(node) == Some(name))
+    }
+}
+
+impl<T: HugrMut> Circuit<T> {
+    /// Add a node to an operation group, replacing any previous group.
+    pub fn set_op_group(&mut self, node: Node, name: impl Into<String>) {
+        self.hugr_mut()
+            .set_metadata(node, METADATA_OPGROUP, NodeMetadata::String(name.into()));
+    }
+}
+
+/// Copy the operation groups of the `removed` nodes onto the replacement
+/// nodes with the same operation.
+///
+/// Each replacement node receives the group of at most one removed node, and
+/// nodes are matched in order.
+pub(crate) fn transfer_op_groups(
+    circ: &Circuit<impl HugrView>,
+    removed: &[Node],
+    replacement: &mut Hugr,
+) {
+    let grouped = removed
+        .iter()
+        .filter_map(|&node| Some((node, circ.hugr().get_metadata(node, METADATA_OPGROUP)?)))
+        .collect_vec();
+    if grouped.is_empty() {
+        return;
+    }
+
+    let mut candidates = replacement
+        .children(replacement.root())
+        .filter(|&node| replacement.get_metadata(node, METADATA_OPGROUP).is_none())
+        .collect_vec();
+    for (node, group) in grouped {
+        let optype = circ.hugr().get_optype(node);
+        let Some(position) = candidates
+            .iter()
+            .position(|&n| replacement.get_optype(n) == optype)
+        else {
+            continue;
+        };
+        let target = candidates.remove(position);
+        replacement.set_metadata(target, METADATA_OPGROUP, group.clone());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rewrite::Subcircuit;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    #[test]
+    fn groups_survive_rewrites() {
+        let mut circ = build_simple_circuit(2, |c| {
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            c.append(Tk2Op::T, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        let [h, cx, t] = circ.commands().map(|cmd| cmd.node()).collect_vec()[..] else {
+            panic!("Expected three commands");
+        };
+        circ.set_op_group(h, "rotations");
+        circ.set_op_group(cx, "entangling");
+        circ.set_op_group(t, "rotations");
+        assert_eq!(circ.ops_in_group("rotations").collect_vec(), [h, t]);
+        assert_eq!(circ.op_group(cx), Some("entangling"));
+
+        // Replace `CX; T` with `S; CX; T; Sdg`. The CX and T survive the rewrite.
+        let replacement = build_simple_circuit(2, |c| {
+            c.append(Tk2Op::S, [1])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            c.append(Tk2Op::T, [1])?;
+            c.append(Tk2Op::Sdg, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        let subcirc = Subcircuit::try_from_nodes([cx, t], &circ).unwrap();
+        subcirc
+            .create_rewrite(&circ, replacement)
+            .unwrap()
+            .apply(&mut circ)
+            .unwrap();
+
+        let ops = |group| {
+            circ.ops_in_group(group)
+                .map(|node| Tk2Op::try_from(circ.hugr().get_optype(node)).unwrap())
+                .collect_vec()
+        };
+        assert_eq!(ops("rotations"), [Tk2Op::H, Tk2Op::T]);
+        assert_eq!(ops("entangling"), [Tk2Op::CX]);
+        assert_eq!(circ.ops_in_group("missing").count(), 0);
+    }
+}
@@ -0,0 +1,235 @@
+//! Parametric circuits and their instantiation.
+//!
+//! The free symbols of the symbolic angles of a circuit are its parameters. A
+//! parametric circuit can be compiled once, as a template, and then
+//! instantiated with [`Circuit::instantiate`] for many concrete parameter
+//! values without redoing the optimisation.
+//!
+//! Passes may merge symbolic angles into expressions of several symbols, or
+//! remove symbols whose rotations cancel out. To keep a stable ordering of
+//! the parameters across passes, the list of parameters can be declared in the
+//! circuit's metadata with [`Circuit::declare_parameters`] before compiling
+//! the template.
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::{HugrView, Node};
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::ops::match_symbolic_expr;
+use crate::utils::load_float;
+use crate::{Circuit, SymbolicExpr};
+
+/// Metadata key for the declared parameters of a circuit.
+pub const METADATA_PARAMETERS: &str = "TKET2.parameters";
+
+impl<T: HugrView> Circuit<T> {
+    /// The parameters of the circuit, in the order expected by
+    /// [`Circuit::instantiate`].
+    ///
+    /// These are the parameters declared with [`Circuit::declare_parameters`],
+    /// if any. Otherwise, they are the free symbols of the symbolic angles of
+    /// the circuit, sorted by name. Opaque sub-expressions that cannot be
+    /// parsed are parameters too, see [`crate::SymbolicExpr`].
+    pub fn parameters(&self) -> Vec<String> {
+        if let Some(declared) = self
+            .hugr()
+            .get_metadata(self.parent(), METADATA_PARAMETERS)
+            .and_then(|meta| serde_json::from_value(meta.clone()).ok())
+        {
+            return declared;
+        }
+        self.symbolic_angles()
+            .flat_map(|(_, expr)| expr.free_symbols().map(str::to_string).collect_vec())
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// The symbolic angles of the circuit, along with their nodes.
+    fn symbolic_angles(&self) -> impl Iterator<Item = (Node, SymbolicExpr)> + '_ {
+        self.hugr()
+            .children(self.parent())
+            .filter_map(|node| Some((node, match_symbolic_expr(self.hugr().get_optype(node))?)))
+    }
+}
+
+impl<T: HugrMut> Circuit<T> {
+    /// Declare the parameters of the circuit, in the order expected by
+    /// [`Circuit::instantiate`].
+    ///
+    /// The declaration is kept in the circuit's metadata, so it survives the
+    /// passes that remove or merge symbolic angles.
+    pub fn declare_parameters(&mut self, names: impl IntoIterator<Item = impl Into<String>>) {
+        let names = names.into_iter().map_into::<String>().collect_vec();
+        let parent = self.parent();
+        self.hugr_mut().set_metadata(
+            parent,
+            METADATA_PARAMETERS,
+            serde_json::to_value(names).unwrap(),
+        );
+    }
+}
+
+impl Circuit {
+    /// Create a copy of the circuit with concrete values for its parameters.
+    ///
+    /// The values are given in half-turns, in the order of
+    /// [`Circuit::parameters`]. Each symbolic angle is replaced by a constant,
+    /// and the rest of the circuit is copied unchanged, so instantiating a
+    /// compiled template does not redo its optimisation. The instance has no
+    /// parameters.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of values does not match the number of
+    /// parameters, or if a symbolic angle depends on symbols that are not
+    /// parameters.
+    pub fn instantiate(&self, values: &[f64]) -> Result<Circuit, InstantiationError> {
+        let parameters = self.parameters();
+        if parameters.len() != values.len() {
+            return Err(InstantiationError::WrongParameterCount {
+                expected: parameters.len(),
+                found: values.len(),
+            });
+        }
+        let value = |symbol: &str| {
+            let index = parameters.iter().position(|p| p == symbol)?;
+            Some(values[index])
+        };
+
+        let mut circ = self.clone();
+        let parent = circ.parent();
+        let angles = circ.symbolic_angles().collect_vec();
+        let hugr = circ.hugr_mut();
+        for (node, expr) in angles {
+            let half_turns =
+                expr.evaluate(value)
+                    .ok_or_else(|| InstantiationError::UnresolvedExpression {
+                        expr: expr.to_string(),
+                        node,
+                    })?;
+            let targets = hugr.linked_inputs(node, 0).collect_vec();
+            hugr.remove_node(node);
+            let load = load_float(hugr, parent, half_turns * std::f64::consts::PI);
+            for (target, port) in targets {
+                hugr.connect(load, 0, target, port);
+            }
+        }
+        circ.declare_parameters(Vec::<String>::new());
+        Ok(circ)
+    }
+}
+
+/// Errors that can occur when instantiating a parametric circuit.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum InstantiationError {
+    /// The number of values does not match the number of parameters.
+    #[error("Expected {expected} parameter values, but found {found}.")]
+    WrongParameterCount {
+        /// The number of parameters of the circuit.
+        expected: usize,
+        /// The number of values given.
+        found: usize,
+    },
+    /// A symbolic angle depends on symbols that are not parameters.
+    #[error("The symbolic angle {expr} in {node} depends on undeclared parameters.")]
+    UnresolvedExpression {
+        /// The symbolic expression.
+        expr: String,
+        /// The node defining the angle.
+        node: Node,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::passes::{squash_single_qubit_gates, EulerBasis};
+    use crate::sim::unitary::equal_up_to_phase;
+    use crate::utils::test::tk1_circuit;
+
+    /// A circuit with rotations by the angles `a` and `b`.
+    fn parametric_circuit(a: &str, b: &str) -> Circuit {
+        tk1_circuit(
+            2,
+            0,
+            &format!(
+                r#"
+            {{"args": [["q", [0]]], "op": {{"type": "Rz", "params": ["{a}"]}}}},
+            {{"args": [["q", [0]]], "op": {{"type": "Rz", "params": ["{a}"]}}}},
+            {{"args": [["q", [0]], ["q", [1]]], "op": {{"type": "CX"}}}},
+            {{"args": [["q", [1]]], "op": {{"type": "Rx", "params": ["{b}"]}}}},
+            {{"args": [["q", [1]]], "op": {{"type": "H"}}}},
+            {{"args": [["q", [1]]], "op": {{"type": "H"}}}}"#
+            ),
+        )
+    }
+
+    #[rstest]
+    #[case(0.5, 0.25)]
+    #[case(-1.2, 0.7)]
+    fn instantiate_compiled_template(#[case] a: f64, #[case] b: f64) {
+        let mut template = parametric_circuit("a", "2*b");
+        assert_eq!(template.parameters(), ["a", "b"]);
+        squash_single_qubit_gates(&mut template, EulerBasis::ZXZ);
+        assert_eq!(template.parameters(), ["a", "b"]);
+
+        let mut instance = template.instantiate(&[a, b]).unwrap();
+        instance.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert!(instance.parameters().is_empty());
+        let gates = |c: &Circuit| {
+            c.commands()
+                .filter_map(|cmd| crate::Tk2Op::try_from(cmd.optype()).ok())
+                .collect_vec()
+        };
+        assert_eq!(gates(&instance), gates(&template));
+
+        let expected = parametric_circuit(&a.to_string(), &(2. * b).to_string());
+        assert!(equal_up_to_phase(
+            &instance.unitary().unwrap(),
+            &expected.unitary().unwrap(),
+            1e-9
+        ));
+    }
+
+    #[test]
+    fn declared_parameters() {
+        let mut template = parametric_circuit("a", "b");
+        template.declare_parameters(["b", "unused", "a"]);
+        let mut merged = template.clone();
+        squash_single_qubit_gates(&mut merged, EulerBasis::ZXZ);
+        assert_eq!(merged.parameters(), ["b", "unused", "a"]);
+
+        let instance = template.instantiate(&[0.25, 1.0, 0.5]).unwrap();
+        let expected = parametric_circuit("0.5", "0.25");
+        assert!(equal_up_to_phase(
+            &instance.unitary().unwrap(),
+            &expected.unitary().unwrap(),
+            1e-9
+        ));
+    }
+
+    #[test]
+    fn instantiation_errors() {
+        let template = parametric_circuit("a", "b");
+        assert_eq!(
+            template.instantiate(&[0.5]).unwrap_err(),
+            InstantiationError::WrongParameterCount {
+                expected: 2,
+                found: 1
+            }
+        );
+
+        let mut template = template;
+        template.declare_parameters(["a"]);
+        assert!(matches!(
+            template.instantiate(&[0.5]),
+            Err(InstantiationError::UnresolvedExpression { expr, .. }) if expr == "b"
+        ));
+    }
+}
@@ -0,0 +1,264 @@
+//! Relabelling and permuting the qubits of a circuit.
+//!
+//! Permutations are given as a slice of [`Qubit`]s where entry `i` is the new
+//! qubit of qubit `i`, counting only the qubit inputs and outputs of the
+//! circuit.
+
+use hugr::extension::prelude::QB_T;
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::{Direction, IncomingPort, OutgoingPort, Port};
+use itertools::Itertools;
+use thiserror::Error;
+use tket_json_rs::optype::OpType as SerialOpType;
+
+use super::units::Qubit;
+use super::Circuit;
+use crate::passes::NativeGate;
+use crate::serialize::pytket::permute_implicit_permutation;
+
+impl<T: HugrMut> Circuit<T> {
+    /// Relabel the qubits of the circuit, so that qubit `i` becomes qubit
+    /// `permutation[i]`.
+    ///
+    /// The operations of the circuit are unchanged, only the order of its
+    /// qubit inputs and outputs is modified. Any implicit qubit permutation
+    /// of a circuit decoded from pytket is relabelled accordingly.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `permutation` is not a permutation of the qubits
+    /// of the circuit, or if the circuit has a different number of qubit
+    /// inputs and outputs.
+    pub fn permute_qubits(&mut self, permutation: &[Qubit]) -> Result<(), PermutationError> {
+        let [input, output] = self.io_nodes();
+        let input_ports = self.qubit_ports(Direction::Outgoing);
+        let output_ports = self.qubit_ports(Direction::Incoming);
+        check_permutation(permutation, input_ports.len(), output_ports.len())?;
+
+        let relabel = |node, port: Port| {
+            let ports = match node {
+                n if n == input => &input_ports,
+                n if n == output => &output_ports,
+                _ => return port,
+            };
+            match ports.iter().position(|&p| p == port) {
+                Some(i) => ports[permutation[i].index()],
+                None => port,
+            }
+        };
+        let hugr = self.hugr_mut();
+        let links = input_ports
+            .iter()
+            .map(|&port| {
+                let port = port.as_outgoing().unwrap();
+                let (dst, dst_port) = hugr.linked_inputs(input, port).exactly_one().ok().unwrap();
+                ((input, port), (dst, dst_port))
+            })
+            .chain(output_ports.iter().filter_map(|&port| {
+                let port = port.as_incoming().unwrap();
+                let (src, src_port) = hugr.single_linked_output(output, port)?;
+                // Wires connecting the input to the output are already listed.
+                (src != input).then_some(((src, src_port), (output, port)))
+            }))
+            .collect_vec();
+        for &((src, src_port), (dst, dst_port)) in &links {
+            hugr.disconnect(src, src_port);
+            hugr.disconnect(dst, dst_port);
+        }
+        for ((src, src_port), (dst, dst_port)) in links {
+            let src_port = relabel(src, src_port.into()).as_outgoing().unwrap();
+            let dst_port = relabel(dst, dst_port.into()).as_incoming().unwrap();
+            hugr.connect(src, src_port, dst, dst_port);
+        }
+
+        permute_implicit_permutation(self, permutation);
+        Ok(())
+    }
+
+    /// Move the state of each qubit `i` onto qubit `permutation[i]` at the
+    /// end of the circuit, by appending pytket `SWAP` gates.
+    ///
+    /// The `SWAP` gates can be decomposed with
+    /// [`rebase`](crate::passes::rebase()).
+    ///
+    /// Returns the number of `SWAP` gates added.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `permutation` is not a permutation of the qubit
+    /// outputs of the circuit.
+    pub fn apply_final_permutation(
+        &mut self,
+        permutation: &[Qubit],
+    ) -> Result<usize, PermutationError> {
+        let output = self.output_node();
+        let parent = self.parent();
+        let qubit_ports = self.qubit_ports(Direction::Incoming);
+        check_permutation(permutation, qubit_ports.len(), qubit_ports.len())?;
+
+        // `current[u]` is the qubit whose state is currently on qubit `u`.
+        let mut current = (0..permutation.len()).collect_vec();
+        let mut swaps = 0;
+        for (source, target) in permutation.iter().map(|q| q.index()).enumerate() {
+            let position = current.iter().position(|&q| q == source).unwrap();
+            if position == target {
+                continue;
+            }
+            let port_a: IncomingPort = qubit_ports[position].as_incoming().unwrap();
+            let port_b: IncomingPort = qubit_ports[target].as_incoming().unwrap();
+            let hugr = self.hugr_mut();
+            let (node_a, out_a) = hugr.single_linked_output(output, port_a).unwrap();
+            let (node_b, out_b) = hugr.single_linked_output(output, port_b).unwrap();
+            hugr.disconnect(output, port_a);
+            hugr.disconnect(output, port_b);
+            let swap = hugr
+                .add_node_with_parent(parent, NativeGate::Pytket(SerialOpType::SWAP).to_optype());
+            hugr.connect(node_a, out_a, swap, 0);
+            hugr.connect(node_b, out_b, swap, 1);
+            hugr.connect(swap, OutgoingPort::from(0), output, port_a);
+            hugr.connect(swap, OutgoingPort::from(1), output, port_b);
+            current.swap(position, target);
+            swaps += 1;
+        }
+        Ok(swaps)
+    }
+
+    /// The qubit ports of the input node, or of the output node.
+    fn qubit_ports(&self, direction: Direction) -> Vec<Port> {
+        let node = match direction {
+            Direction::Outgoing => self.input_node(),
+            Direction::Incoming => self.output_node(),
+        };
+        let hugr = self.hugr();
+        hugr.value_types(node, direction)
+            .filter(|(_, ty)| ty == &QB_T)
+            .map(|(port, _)| port)
+            .collect()
+    }
+}
+
+/// Check that `permutation` is a permutation of the qubit inputs and outputs.
+fn check_permutation(
+    permutation: &[Qubit],
+    num_inputs: usize,
+    num_outputs: usize,
+) -> Result<(), PermutationError> {
+    let is_permutation = permutation.iter().all_unique()
+        && permutation.iter().all(|q| q.index() < permutation.len())
+        && permutation.len() == num_inputs
+        && num_inputs == num_outputs;
+    match is_permutation {
+        true => Ok(()),
+        false => Err(PermutationError::InvalidPermutation {
+            permutation: permutation.to_vec(),
+            num_inputs,
+            num_outputs,
+        }),
+    }
+}
+
+/// Errors that can occur when permuting the qubits of a circuit.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PermutationError {
+    /// The permutation does not match the qubits of the circuit.
+    #[error("{permutation:?} is not a permutation of a circuit with {num_inputs} qubit inputs and {num_outputs} qubit outputs.")]
+    InvalidPermutation {
+        /// The invalid permutation.
+        permutation: Vec<Qubit>,
+        /// The number of qubit inputs of the circuit.
+        num_inputs: usize,
+        /// The number of qubit outputs of the circuit.
+        num_outputs: usize,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::passes::{rebase, GateSet};
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    /// A circuit acting differently on each of its three qubits.
+    fn circuit() -> Circuit {
+        build_simple_circuit(3, |c| {
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            c.append(Tk2Op::T, [2])?;
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    /// Move bit `i` of a basis state index to bit `permutation[i]`.
+    fn basis_permutation(index: usize, permutation: &[Qubit]) -> usize {
+        (0..permutation.len())
+            .filter(|&q| index >> q & 1 == 1)
+            .map(|q| 1 << permutation[q].index())
+            .sum()
+    }
+
+    /// The qubits with the given indices.
+    fn qubits<const N: usize>(indices: [usize; N]) -> Vec<Qubit> {
+        indices.map(Qubit::new).to_vec()
+    }
+
+    #[rstest]
+    #[case::identity(qubits([0, 1, 2]))]
+    #[case::swap(qubits([1, 0, 2]))]
+    #[case::cycle(qubits([1, 2, 0]))]
+    fn permute_qubits(#[case] permutation: Vec<Qubit>) {
+        let circ = circuit();
+        let mut permuted = circ.clone();
+        permuted.permute_qubits(&permutation).unwrap();
+        permuted.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(permuted.num_operations(), circ.num_operations());
+
+        // Relabelling the qubits permutes the rows and columns of the unitary.
+        let u = circ.unitary().unwrap();
+        let v = permuted.unitary().unwrap();
+        for ((row, col), &x) in u.indexed_iter() {
+            let (r, c) = (
+                basis_permutation(row, &permutation),
+                basis_permutation(col, &permutation),
+            );
+            assert!((v[(r, c)] - x).norm() < 1e-10);
+        }
+    }
+
+    #[rstest]
+    #[case::identity(qubits([0, 1, 2]), 0)]
+    #[case::swap(qubits([1, 0, 2]), 1)]
+    #[case::cycle(qubits([1, 2, 0]), 2)]
+    fn final_permutation(#[case] permutation: Vec<Qubit>, #[case] expected_swaps: usize) {
+        let mut circ = build_simple_circuit(3, |_| Ok(())).unwrap();
+        let swaps = circ.apply_final_permutation(&permutation).unwrap();
+        circ.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(swaps, expected_swaps);
+
+        // The SWAPs move bit `i` of each basis state to bit `permutation[i]`.
+        rebase(&mut circ, &GateSet::clifford_t()).unwrap();
+        let u = circ.unitary().unwrap();
+        for col in 0..u.ncols() {
+            let row = basis_permutation(col, &permutation);
+            assert!((u[(row, col)] - 1.).norm() < 1e-10);
+        }
+    }
+
+    #[rstest]
+    #[case::short(qubits([0, 1]))]
+    #[case::repeated(qubits([0, 0, 1]))]
+    #[case::out_of_range(qubits([0, 1, 3]))]
+    fn invalid_permutation(#[case] permutation: Vec<Qubit>) {
+        let mut circ = circuit();
+        assert!(matches!(
+            circ.permute_qubits(&permutation),
+            Err(PermutationError::InvalidPermutation { .. })
+        ));
+        assert!(circ.apply_final_permutation(&permutation).is_err());
+    }
+}
@@ -0,0 +1,399 @@
+//! Tracking of the global phase of circuits.
+//!
+//! The global phase of a circuit is stored in the metadata of its parent node,
+//! under the [`METADATA_PHASE`] key, as a pytket-compatible expression in
+//! half-turns. Phases may be symbolic, in which case they are kept as
+//! expressions and only their constant parts are folded together.
+//!
+//! Rewrites between circuits that are only equivalent up to a global phase
+//! record the phase they introduce in their replacement, see
+//! [`CircuitRewrite::phase_delta`]. Applying such a rewrite updates the phase
+//! of the circuit. When the phase of a rewrite is not known, the circuit's
+//! phase becomes unknown as well.
+//!
+//! A global phase is unobservable, unless the circuit is later controlled on
+//! another qubit, turning it into a relative phase on the control (phase
+//! kickback). Circuits in this situation can be marked as
+//! [phase-sensitive](Circuit::is_phase_sensitive), and rewriters must not
+//! apply rewrites with an unknown phase to them.
+//!
+//! [`CircuitRewrite::phase_delta`]: crate::rewrite::CircuitRewrite::phase_delta
+
+use std::fmt;
+use std::ops::{Add, Neg, Sub};
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::HugrView;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::utils::TOLERANCE;
+use crate::Circuit;
+
+/// Metadata key for the global phase of a circuit.
+///
+/// The value is either a string expression in half-turns, or `null` if the
+/// phase is unknown. A missing value means a zero phase.
+pub const METADATA_PHASE: &str = "TKET1.phase";
+
+/// Metadata key for the phase introduced by a rewrite, stored on the root of
+/// its replacement. See [`METADATA_PHASE`] for the encoding.
+pub const METADATA_PHASE_DELTA: &str = "TKET2.phase_delta";
+
+/// Metadata key marking a circuit whose global phase is observable.
+pub const METADATA_PHASE_SENSITIVE: &str = "TKET2.phase_sensitive";
+
+/// A global phase, in half-turns.
+///
+/// The phase is the sum of a constant, taken modulo 2, and a list of symbolic
+/// terms.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
+pub struct GlobalPhase {
+    /// The constant part of the phase, in the range `[0, 2)`.
+    constant: f64,
+    /// The symbolic terms of the phase, as pytket expressions.
+    symbols: Vec<String>,
+}
+
+impl GlobalPhase {
+    /// A constant phase, in half-turns.
+    pub fn new(half_turns: f64) -> Self {
+        let mut constant = half_turns.rem_euclid(2.);
+        if constant < TOLERANCE || 2. - constant < TOLERANCE {
+            constant = 0.;
+        }
+        Self {
+            constant,
+            symbols: Vec::new(),
+        }
+    }
+
+    /// A symbolic phase, given as a pytket expression in half-turns.
+    pub fn symbolic(expr: impl Into<String>) -> Self {
+        Self {
+            constant: 0.,
+            symbols: vec![expr.into()],
+        }
+    }
+
+    /// Parse a pytket phase expression.
+    ///
+    /// The expression is split into its top-level sum terms. Numbers and
+    /// fractions are folded into the constant, other terms are kept as
+    /// symbolic expressions.
+    pub fn parse(expr: &str) -> Self {
+        split_terms(expr.trim())
+            .into_iter()
+            .map(|term| match parse_constant(term) {
+                Some(c) => Self::new(c),
+                None if is_parenthesised(term) => Self::symbolic(&term[1..term.len() - 1]),
+                None => Self::symbolic(term),
+            })
+            .fold(Self::default(), Add::add)
+    }
+
+    /// The constant part of the phase, in the range `[0, 2)`.
+    pub fn constant(&self) -> f64 {
+        self.constant
+    }
+
+    /// The symbolic terms of the phase.
+    pub fn symbols(&self) -> &[String] {
+        &self.symbols
+    }
+
+    /// Returns `true` if the phase has symbolic terms.
+    pub fn is_symbolic(&self) -> bool {
+        !self.symbols.is_empty()
+    }
+
+    /// Returns `true` if the phase is zero.
+    pub fn is_zero(&self) -> bool {
+        self.constant == 0. && self.symbols.is_empty()
+    }
+
+    /// Decode a phase stored in metadata, returning `None` if it is unknown.
+    fn from_metadata(value: Option<&Value>) -> Option<Self> {
+        match value {
+            None => Some(Self::default()),
+            Some(Value::String(expr)) => Some(Self::parse(expr)),
+            Some(Value::Number(n)) => n.as_f64().map(Self::new),
+            Some(_) => None,
+        }
+    }
+
+    /// Encode a possibly unknown phase as metadata.
+    fn to_metadata(phase: Option<&Self>) -> Value {
+        match phase {
+            Some(phase) => Value::String(phase.to_string()),
+            None => Value::Null,
+        }
+    }
+}
+
+/// Parse a number or a fraction of integers.
+fn parse_constant(expr: &str) -> Option<f64> {
+    if let Ok(c) = expr.parse::<f64>() {
+        return Some(c);
+    }
+    let (num, den) = expr.split_once('/')?;
+    let num: i64 = num.trim().parse().ok()?;
+    let den: i64 = den.trim().parse().ok()?;
+    (den != 0).then(|| num as f64 / den as f64)
+}
+
+/// Split an expression into the terms of its top-level sum.
+fn split_terms(expr: &str) -> Vec<&str> {
+    let mut terms = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in expr.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ' ' if depth == 0 && expr[i..].starts_with(" + ") => {
+                terms.push(&expr[start..i]);
+                start = i + 3;
+            }
+            _ => {}
+        }
+    }
+    terms.push(&expr[start..]);
+    terms
+}
+
+/// Returns `true` if the parenthesis opening an expression closes at its end.
+fn is_parenthesised(expr: &str) -> bool {
+    if !expr.starts_with('(') || !expr.ends_with(')') {
+        return false;
+    }
+    let mut depth = 0;
+    for (i, c) in expr.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return i == expr.len() - 1;
+        }
+    }
+    false
+}
+
+impl Add for GlobalPhase {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self {
+        self.symbols.extend(rhs.symbols);
+        Self {
+            symbols: self.symbols,
+            ..Self::new(self.constant + rhs.constant)
+        }
+    }
+}
+
+impl Neg for GlobalPhase {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        let symbols = self
+            .symbols
+            .into_iter()
+            .map(|s| match s.strip_prefix('-') {
+                Some(inner) if is_parenthesised(inner) => inner[1..inner.len() - 1].to_string(),
+                _ => format!("-({s})"),
+            })
+            .collect();
+        Self {
+            symbols,
+            ..Self::new(-self.constant)
+        }
+    }
+}
+
+impl Sub for GlobalPhase {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self + -rhs
+    }
+}
+
+impl fmt::Display for GlobalPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut terms = self
+            .symbols
+            .iter()
+            .map(
+                |s| match s.contains([' ', '+', '-', '*', '/']) && !is_parenthesised(s) {
+                    true => format!("({s})"),
+                    false => s.clone(),
+                },
+            )
+            .collect::<Vec<_>>();
+        if self.constant != 0. || terms.is_empty() {
+            terms.push(self.constant.to_string());
+        }
+        write!(f, "{}", terms.join(" + "))
+    }
+}
+
+impl From<GlobalPhase> for String {
+    fn from(phase: GlobalPhase) -> Self {
+        phase.to_string()
+    }
+}
+
+impl From<String> for GlobalPhase {
+    fn from(expr: String) -> Self {
+        Self::parse(&expr)
+    }
+}
+
+impl From<f64> for GlobalPhase {
+    fn from(half_turns: f64) -> Self {
+        Self::new(half_turns)
+    }
+}
+
+impl<T: HugrView> Circuit<T> {
+    /// The global phase of the circuit, in half-turns.
+    ///
+    /// Returns `None` if the phase is unknown, for example after applying a
+    /// rewrite whose phase could not be determined.
+    pub fn global_phase(&self) -> Option<GlobalPhase> {
+        GlobalPhase::from_metadata(self.hugr().get_metadata(self.parent(), METADATA_PHASE))
+    }
+
+    /// The phase introduced by a rewrite with this circuit as replacement.
+    ///
+    /// See [`METADATA_PHASE_DELTA`].
+    pub(crate) fn phase_delta(&self) -> Option<GlobalPhase> {
+        GlobalPhase::from_metadata(
+            self.hugr()
+                .get_metadata(self.parent(), METADATA_PHASE_DELTA),
+        )
+    }
+
+    /// Returns `true` if the global phase of the circuit is observable.
+    ///
+    /// This is the case when the circuit is to be controlled on other qubits,
+    /// so that its global phase is kicked back as a relative phase on the
+    /// controls.
+    pub fn is_phase_sensitive(&self) -> bool {
+        self.hugr()
+            .get_metadata(self.parent(), METADATA_PHASE_SENSITIVE)
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+    }
+}
+
+impl<T: HugrMut> Circuit<T> {
+    /// Set the global phase of the circuit, in half-turns.
+    ///
+    /// Pass `None` to mark the phase as unknown.
+    pub fn set_global_phase(&mut self, phase: Option<GlobalPhase>) {
+        let parent = self.parent();
+        self.hugr_mut().set_metadata(
+            parent,
+            METADATA_PHASE,
+            GlobalPhase::to_metadata(phase.as_ref()),
+        );
+    }
+
+    /// Add a phase to the global phase of the circuit.
+    ///
+    /// If either phase is unknown, the resulting phase is unknown.
+    pub fn add_global_phase(&mut self, delta: Option<GlobalPhase>) {
+        if delta.as_ref().is_some_and(GlobalPhase::is_zero) {
+            return;
+        }
+        let phase = self.global_phase().zip(delta).map(|(p, d)| p + d);
+        self.set_global_phase(phase);
+    }
+
+    /// Set the phase introduced by a rewrite with this circuit as
+    /// replacement.
+    ///
+    /// See [`METADATA_PHASE_DELTA`].
+    pub(crate) fn set_phase_delta(&mut self, delta: Option<GlobalPhase>) {
+        let parent = self.parent();
+        self.hugr_mut().set_metadata(
+            parent,
+            METADATA_PHASE_DELTA,
+            GlobalPhase::to_metadata(delta.as_ref()),
+        );
+    }
+
+    /// Mark the global phase of the circuit as observable, or not.
+    ///
+    /// See [`Circuit::is_phase_sensitive`].
+    pub fn set_phase_sensitive(&mut self, sensitive: bool) {
+        let parent = self.parent();
+        self.hugr_mut()
+            .set_metadata(parent, METADATA_PHASE_SENSITIVE, sensitive);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    #[rstest]
+    #[case::zero("0", 0., &[])]
+    #[case::float("0.25", 0.25, &[])]
+    #[case::fraction("1/2", 0.5, &[])]
+    #[case::negative("-1/2", 1.5, &[])]
+    #[case::wrapped("4.5", 0.5, &[])]
+    #[case::symbolic("a + 1/2", 0.5, &["a"])]
+    #[case::nested("(b + c) + 2*a", 0., &["b + c", "2*a"])]
+    fn parse(#[case] expr: &str, #[case] constant: f64, #[case] symbols: &[&str]) {
+        let phase = GlobalPhase::parse(expr);
+        assert_eq!(phase.constant(), constant);
+        assert_eq!(phase.symbols(), symbols);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = GlobalPhase::symbolic("a");
+        let sum = a.clone() + GlobalPhase::new(1.5) + GlobalPhase::new(0.75);
+        assert_eq!(sum.constant(), 0.25);
+        assert_eq!(sum.to_string(), "a + 0.25");
+
+        let diff = sum.clone() - GlobalPhase::symbolic("b + c");
+        assert_eq!(diff.to_string(), "a + (-(b + c)) + 0.25");
+        assert_eq!(-(-diff.clone()), diff);
+        assert_eq!(GlobalPhase::parse(&diff.to_string()), diff);
+        assert!((GlobalPhase::new(0.5) - GlobalPhase::new(0.5)).is_zero());
+    }
+
+    #[test]
+    fn circuit_phase() {
+        let mut circ = build_simple_circuit(1, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(circ.global_phase(), Some(GlobalPhase::default()));
+        assert!(!circ.is_phase_sensitive());
+
+        circ.add_global_phase(Some(GlobalPhase::parse("1/4")));
+        circ.add_global_phase(Some(GlobalPhase::symbolic("x")));
+        let phase = circ.global_phase().unwrap();
+        assert_eq!(phase.to_string(), "x + 0.25");
+
+        circ.add_global_phase(None);
+        assert_eq!(circ.global_phase(), None);
+        circ.add_global_phase(Some(GlobalPhase::new(1.)));
+        assert_eq!(circ.global_phase(), None);
+
+        circ.set_phase_sensitive(true);
+        assert!(circ.is_phase_sensitive());
+    }
+}
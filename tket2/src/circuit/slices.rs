@@ -0,0 +1,227 @@
+//! Layers of parallel commands.
+//!
+//! A [`Moment`] is a set of commands acting on disjoint linear units, which
+//! can be applied at the same time. [`Circuit::moments`] splits a circuit into
+//! moments by scheduling every command as early as possible, and the number of
+//! moments is the [depth](Circuit::depth) of the circuit.
+
+use hugr::{Direction, HugrView};
+use itertools::Itertools;
+
+use super::units::LinearUnit;
+use super::{Circuit, Command};
+
+/// A layer of commands acting on disjoint linear units.
+///
+/// The commands are generic, so that both borrowed [`Command`]s and
+/// [`OwnedCommand`](super::OwnedCommand)s can be scheduled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Moment<C> {
+    /// The commands of the moment, in the order they were scheduled.
+    commands: Vec<C>,
+    /// The position in `commands` of the command acting on each linear unit,
+    /// indexed by the unit index.
+    unit_commands: Vec<Option<usize>>,
+}
+
+impl<C> Moment<C> {
+    /// Returns the commands of the moment, in topological order.
+    #[inline]
+    pub fn commands(&self) -> &[C] {
+        &self.commands
+    }
+
+    /// Returns the commands of the moment, in topological order.
+    #[inline]
+    pub fn into_commands(self) -> Vec<C> {
+        self.commands
+    }
+
+    /// Returns the command acting on a linear unit, if any.
+    #[inline]
+    pub fn command_on(&self, unit: LinearUnit) -> Option<&C> {
+        let pos = (*self.unit_commands.get(unit.index())?)?;
+        Some(&self.commands[pos])
+    }
+
+    /// Returns the linear units the commands of the moment act on.
+    pub fn units(&self) -> impl Iterator<Item = LinearUnit> + '_ {
+        self.unit_commands
+            .iter()
+            .positions(Option::is_some)
+            .map(LinearUnit::new)
+    }
+
+    /// Returns the number of commands in the moment.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Returns `true` if the moment contains no commands.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+impl<C> IntoIterator for Moment<C> {
+    type Item = C;
+    type IntoIter = std::vec::IntoIter<C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.commands.into_iter()
+    }
+}
+
+impl<T: HugrView> Circuit<T> {
+    /// Split the circuit into moments of parallel commands.
+    ///
+    /// Every command acting on linear units is placed in the first moment
+    /// after all the commands it depends on through these units. Purely
+    /// classical operations, such as the ones computing rotation angles, are
+    /// not included.
+    pub fn moments(&self) -> Vec<Moment<Command<'_, T>>> {
+        schedule(self.commands(), command_units)
+    }
+
+    /// The depth of the circuit, i.e. its number of [moments](Circuit::moments).
+    pub fn depth(&self) -> usize {
+        self.moments().len()
+    }
+}
+
+/// The linear units a command acts on, as inputs or outputs.
+pub(crate) fn command_units<T: HugrView>(cmd: &Command<'_, T>) -> Vec<LinearUnit> {
+    cmd.linear_units(Direction::Incoming)
+        .chain(cmd.linear_units(Direction::Outgoing))
+        .map(|(unit, _, _)| unit)
+        .unique()
+        .collect()
+}
+
+/// Schedule commands into moments, as early as possible.
+///
+/// The commands must be in topological order. Commands without any units are
+/// skipped.
+pub(crate) fn schedule<C>(
+    commands: impl IntoIterator<Item = C>,
+    units: impl Fn(&C) -> Vec<LinearUnit>,
+) -> Vec<Moment<C>> {
+    let mut moments: Vec<Moment<C>> = Vec::new();
+    // The first moment in which each unit is free.
+    let mut unit_free: Vec<usize> = Vec::new();
+    for command in commands {
+        let units = units(&command);
+        let Some(max_index) = units.iter().map(|u| u.index()).max() else {
+            continue;
+        };
+        if max_index >= unit_free.len() {
+            unit_free.resize(max_index + 1, 0);
+        }
+        let depth = units.iter().map(|u| unit_free[u.index()]).max().unwrap();
+        if depth == moments.len() {
+            moments.push(Moment {
+                commands: Vec::new(),
+                unit_commands: Vec::new(),
+            });
+        }
+        let moment = &mut moments[depth];
+        if max_index >= moment.unit_commands.len() {
+            moment.unit_commands.resize(max_index + 1, None);
+        }
+        for unit in units {
+            unit_free[unit.index()] = depth + 1;
+            moment.unit_commands[unit.index()] = Some(moment.commands.len());
+        }
+        moment.commands.push(command);
+    }
+    moments
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::extension::prelude::QB_T;
+    use hugr::std_extensions::arithmetic::float_types::{self, ConstF64, FLOAT64_TYPE};
+    use hugr::types::Signature;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    /// The operations of a moment, sorted.
+    fn ops<T: HugrView>(moment: &Moment<Command<'_, T>>) -> Vec<Tk2Op> {
+        moment
+            .commands()
+            .iter()
+            .map(|cmd| Tk2Op::try_from(cmd.optype()).unwrap())
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn moments() {
+        let circ = build_simple_circuit(3, |c| {
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            c.append(Tk2Op::X, [2])?;
+            c.append(Tk2Op::CX, [1, 2])?;
+            c.append(Tk2Op::T, [0])?;
+            Ok(())
+        })
+        .unwrap();
+
+        let moments = circ.moments();
+        assert_eq!(circ.depth(), 3);
+        assert_eq!(
+            ops(&moments[0]),
+            [Tk2Op::H, Tk2Op::X].into_iter().sorted().collect_vec()
+        );
+        assert_eq!(ops(&moments[1]), [Tk2Op::CX]);
+        assert_eq!(
+            ops(&moments[2]),
+            [Tk2Op::T, Tk2Op::CX].into_iter().sorted().collect_vec()
+        );
+
+        let cx = moments[2].command_on(LinearUnit::new(1)).unwrap();
+        assert_eq!(moments[2].command_on(LinearUnit::new(2)), Some(cx));
+        assert_eq!(moments[1].command_on(LinearUnit::new(2)), None);
+        assert_eq!(
+            moments[2].units().collect_vec(),
+            (0..3).map(LinearUnit::new).collect_vec()
+        );
+    }
+
+    #[test]
+    fn empty_circuit() {
+        let circ = build_simple_circuit(2, |_| Ok(())).unwrap();
+        assert!(circ.moments().is_empty());
+        assert_eq!(circ.depth(), 0);
+    }
+
+    #[test]
+    fn classical_ops_skipped() {
+        let mut h = DFGBuilder::new(
+            Signature::new(vec![QB_T, FLOAT64_TYPE], vec![QB_T])
+                .with_extension_delta(float_types::EXTENSION_ID),
+        )
+        .unwrap();
+        let [q, angle] = h.input_wires_arr();
+        let half = h.add_load_value(ConstF64::new(0.5));
+        let [angle] = h
+            .add_dataflow_op(Tk2Op::AngleAdd, [angle, half])
+            .unwrap()
+            .outputs_arr();
+        let [q] = h
+            .add_dataflow_op(Tk2Op::RzF64, [q, angle])
+            .unwrap()
+            .outputs_arr();
+        let circ: Circuit = h.finish_hugr_with_outputs([q], &REGISTRY).unwrap().into();
+
+        let moments = circ.moments();
+        assert_eq!(moments.len(), 1);
+        assert_eq!(ops(&moments[0]), [Tk2Op::RzF64]);
+    }
+}
@@ -0,0 +1,270 @@
+//! Plain text rendering of circuits.
+
+use std::f64::consts::PI;
+
+use hugr::ops::{NamedOp, OpType};
+use hugr::std_extensions::arithmetic::float_types::FLOAT64_TYPE;
+use hugr::{CircuitUnit, HugrView};
+use itertools::Itertools;
+
+use super::slices::command_units;
+use super::Circuit;
+use crate::extension::ControlledOp;
+use crate::serialize::pytket::OpaqueTk1Op;
+use crate::utils::float_wire_value;
+use crate::Tk2Op;
+
+impl<T: HugrView> Circuit<T> {
+    /// Render the circuit as a text diagram, with a horizontal timeline for
+    /// each qubit.
+    ///
+    /// Operations are laid out [moment](Circuit::moments) by moment, each in
+    /// the leftmost column where all the qubits it spans are free. Multi-qubit operations are connected by vertical lines,
+    /// with `●` marking the controls of controlled gates. Constant angle
+    /// parameters are shown in multiples of π, and other parameters as `?`.
+    /// Purely classical operations are not shown.
+    ///
+    /// ```text
+    /// q0: ─H──●────────────
+    ///         │
+    /// q1: ────X──Rz(0.5π)─
+    /// ```
+    ///
+    /// This is meant for inspecting small circuits. Use
+    /// [`Circuit::mermaid_string`] or [`Circuit::dot_string`] to see the
+    /// underlying HUGR.
+    pub fn to_text_diagram(&self) -> String {
+        let mut num_rows = self.qubit_count();
+        let mut row_free = vec![0; num_rows];
+        let mut gates: Vec<Gate> = Vec::new();
+
+        for cmd in self.moments().into_iter().flatten() {
+            let qubits = command_units(&cmd)
+                .into_iter()
+                .map(|unit| unit.index())
+                .collect_vec();
+            let params = cmd
+                .inputs()
+                .filter(|(_, _, ty)| ty == &FLOAT64_TYPE)
+                .map(|(unit, _, _)| match unit {
+                    CircuitUnit::Wire(wire) => float_wire_value(self.hugr(), wire),
+                    CircuitUnit::Linear(_) => None,
+                })
+                .collect_vec();
+            let labels = qubit_labels(cmd.optype(), &params, qubits.len());
+
+            let (lo, hi) = qubits.iter().minmax().into_option().unwrap();
+            let (lo, hi) = (*lo, *hi);
+            if hi >= num_rows {
+                num_rows = hi + 1;
+                row_free.resize(num_rows, 0);
+            }
+            let column = row_free[lo..=hi].iter().copied().max().unwrap_or(0);
+            row_free[lo..=hi].fill(column + 1);
+            gates.push(Gate {
+                column,
+                lo,
+                hi,
+                labels: qubits.into_iter().zip(labels).collect(),
+            });
+        }
+
+        let num_columns = row_free.iter().copied().max().unwrap_or(0);
+        let mut widths = vec![1; num_columns];
+        for gate in &gates {
+            for (_, label) in &gate.labels {
+                widths[gate.column] = widths[gate.column].max(label.chars().count());
+            }
+        }
+
+        let names = (0..num_rows).map(|row| format!("q{row}: ")).collect_vec();
+        let name_width = names.iter().map(String::len).max().unwrap_or(0);
+        let mut wires = names
+            .iter()
+            .map(|name| format!("{name:<name_width$}"))
+            .collect_vec();
+        let mut gaps = vec![" ".repeat(name_width); num_rows.saturating_sub(1)];
+        let mut columns = vec![Vec::new(); num_columns];
+        for gate in &gates {
+            columns[gate.column].push(gate);
+        }
+
+        for (gates, &width) in columns.iter().zip(&widths) {
+            for (row, wire) in wires.iter_mut().enumerate() {
+                let gate = gates.iter().find(|g| g.lo <= row && row <= g.hi);
+                let label = gate.map(|g| {
+                    g.labels
+                        .iter()
+                        .find(|(q, _)| *q == row)
+                        .map_or("┼", |(_, label)| label.as_str())
+                });
+                wire.push('─');
+                wire.push_str(&centred(label.unwrap_or("─"), width, '─'));
+                wire.push('─');
+            }
+            for (row, gap) in gaps.iter_mut().enumerate() {
+                let spanned = gates.iter().any(|g| g.lo <= row && row < g.hi);
+                gap.push(' ');
+                gap.push_str(&centred(if spanned { "│" } else { " " }, width, ' '));
+                gap.push(' ');
+            }
+        }
+
+        let gaps = gaps.into_iter().map(|gap| gap.trim_end().to_string());
+        wires.into_iter().interleave(gaps).join("\n")
+    }
+}
+
+/// An operation placed in the diagram.
+#[derive(Debug, Clone)]
+struct Gate {
+    /// The column of the operation.
+    column: usize,
+    /// The first row spanned by the operation.
+    lo: usize,
+    /// The last row spanned by the operation.
+    hi: usize,
+    /// The label to draw on each of the qubits of the operation.
+    labels: Vec<(usize, String)>,
+}
+
+/// The labels to draw on each of the qubits of an operation.
+fn qubit_labels(optype: &OpType, params: &[Option<f64>], num_qubits: usize) -> Vec<String> {
+    let control = "●".to_string();
+    let pauli = |name: &str| {
+        let mut labels = vec![control.clone(); num_qubits - 1];
+        labels.push(name.to_string());
+        labels
+    };
+    if let Ok(op) = Tk2Op::try_from(optype) {
+        match op {
+            Tk2Op::CX | Tk2Op::CCX => return pauli("X"),
+            Tk2Op::CZ | Tk2Op::CCZ => return pauli("●"),
+            _ => {}
+        }
+    }
+    if let Some(op) = ControlledOp::from_optype(optype) {
+        return pauli(&op.target().to_string());
+    }
+
+    let label = match params.is_empty() {
+        true => op_name(optype),
+        false => format!(
+            "{}({})",
+            op_name(optype),
+            params.iter().map(|&p| format_angle(p)).join(", ")
+        ),
+    };
+    let symmetric = matches!(
+        Tk2Op::try_from(optype),
+        Ok(Tk2Op::ZZMax | Tk2Op::ZZPhase | Tk2Op::XXPhase | Tk2Op::YYPhase)
+    );
+    match num_qubits {
+        1 => vec![label],
+        _ if symmetric => vec![label; num_qubits],
+        _ => (0..num_qubits).map(|i| format!("{label}:{i}")).collect(),
+    }
+}
+
+/// A short name for an operation.
+pub(crate) fn op_name(optype: &OpType) -> String {
+    if let Ok(op) = Tk2Op::try_from(optype) {
+        return match op {
+            Tk2Op::RzF64 => "Rz".to_string(),
+            Tk2Op::RxF64 => "Rx".to_string(),
+            op => <&str>::from(op).to_string(),
+        };
+    }
+    if let Ok(Some(tk1op)) = OpaqueTk1Op::try_from_tket2(optype) {
+        return format!("{:?}", tk1op.serialised_op().op_type);
+    }
+    let name = optype.name();
+    name.rsplit('.').next().unwrap_or(&name).to_string()
+}
+
+/// Format an angle in radians as a multiple of π.
+pub(crate) fn format_angle(angle: Option<f64>) -> String {
+    let Some(angle) = angle else {
+        return "?".to_string();
+    };
+    let half_turns = format!("{:.4}", angle / PI);
+    let half_turns = half_turns.trim_end_matches('0').trim_end_matches('.');
+    match half_turns {
+        "0" | "-0" => "0".to_string(),
+        "1" => "π".to_string(),
+        "-1" => "-π".to_string(),
+        _ => format!("{half_turns}π"),
+    }
+}
+
+/// Centre a label in a cell of the given width.
+fn centred(label: &str, width: usize, fill: char) -> String {
+    let padding = width.saturating_sub(label.chars().count());
+    let left = padding / 2;
+    let fill = |n| std::iter::repeat(fill).take(n).collect::<String>();
+    format!("{}{label}{}", fill(left), fill(padding - left))
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::FRAC_PI_2;
+
+    use hugr::std_extensions::arithmetic::float_types::ConstF64;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::utils::build_simple_circuit;
+
+    #[test]
+    fn simple_diagram() {
+        let circ = build_simple_circuit(3, |c| {
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::CX, [0, 2])?;
+            let angle = c.add_constant(ConstF64::new(FRAC_PI_2));
+            c.append_and_consume(
+                Tk2Op::RzF64,
+                [CircuitUnit::Linear(1), CircuitUnit::Wire(angle)],
+            )?;
+            c.append(Tk2Op::T, [2])?;
+            Ok(())
+        })
+        .unwrap();
+        let expected = [
+            "q0: ────H──────●────",
+            "               │",
+            "q1: ─Rz(0.5π)──┼────",
+            "               │",
+            "q2: ───────────X──T─",
+        ];
+        assert_eq!(circ.to_text_diagram(), expected.join("\n"));
+    }
+
+    #[test]
+    fn multi_qubit_gates() {
+        let circ = build_simple_circuit(3, |c| {
+            c.append(Tk2Op::CCX, [2, 0, 1])?;
+            c.append(Tk2Op::ZZMax, [0, 2])?;
+            c.append(Tk2Op::CZ, [1, 2])?;
+            Ok(())
+        })
+        .unwrap();
+        let expected = [
+            "q0: ─●──ZZMax────",
+            "     │    │",
+            "q1: ─X────┼────●─",
+            "     │    │    │",
+            "q2: ─●──ZZMax──●─",
+        ];
+        assert_eq!(circ.to_text_diagram(), expected.join("\n"));
+    }
+
+    #[rstest]
+    #[case(Some(0.), "0")]
+    #[case(Some(PI), "π")]
+    #[case(Some(-PI / 4.), "-0.25π")]
+    #[case(Some(1.), "0.3183π")]
+    #[case(None, "?")]
+    fn angles(#[case] angle: Option<f64>, #[case] expected: &str) {
+        assert_eq!(format_angle(angle), expected);
+    }
+}
@@ -10,13 +10,26 @@
 //! The [`Units`] iterator defined in this module yields all the input or output
 //! units of a node. See [`Circuit::units`] and [`Command`] for more details.
 //!
+//! The [`UnitTracker`] caches the linear unit of every port of a circuit, for
+//! passes that need to query them repeatedly.
+//!
+//! Linear units of a quantum type are reported as qubits, see
+//! [`is_quantum_type`]. With the `qudits` feature, this includes the qudit
+//! types of the tket2 extension and any type registered with
+//! [`register_quantum_type`]. With the `bosonic` feature, it includes the
+//! bosonic modes of the `tket2.bosonic` extension.
+//!
 //! [`Command`]: super::command::Command
 
 pub mod filter;
+mod tracker;
+
+pub use tracker::UnitTracker;
 
 use std::iter::FusedIterator;
 use std::marker::PhantomData;
 
+use hugr::extension::prelude::QB_T;
 use hugr::types::{EdgeKind, Type, TypeRow};
 use hugr::{CircuitUnit, HugrView, IncomingPort, OutgoingPort};
 use hugr::{Direction, Node, Port, Wire};
@@ -57,6 +70,124 @@ impl TryFrom<CircuitUnit> for LinearUnit {
         }
     }
 }
+
+/// The index of a qubit in a circuit.
+///
+/// Qubits are numbered by the [`LinearUnit`] they are assigned to, so this
+/// wraps the same index. Using a dedicated type avoids mixing up qubit indices
+/// with the indices of bits or other values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Qubit(usize);
+
+impl Qubit {
+    /// Creates a new [`Qubit`].
+    pub const fn new(index: usize) -> Self {
+        Self(index)
+    }
+    /// Returns the index of this [`Qubit`].
+    pub const fn index(&self) -> usize {
+        self.0
+    }
+}
+
+impl std::fmt::Display for Qubit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "q[{}]", self.0)
+    }
+}
+
+impl From<usize> for Qubit {
+    fn from(index: usize) -> Self {
+        Self(index)
+    }
+}
+
+impl From<Qubit> for usize {
+    fn from(qb: Qubit) -> Self {
+        qb.0
+    }
+}
+
+impl From<Qubit> for LinearUnit {
+    fn from(qb: Qubit) -> Self {
+        LinearUnit(qb.0)
+    }
+}
+
+impl From<LinearUnit> for Qubit {
+    fn from(lu: LinearUnit) -> Self {
+        Self(lu.0)
+    }
+}
+
+impl From<Qubit> for CircuitUnit {
+    fn from(qb: Qubit) -> Self {
+        CircuitUnit::Linear(qb.0)
+    }
+}
+
+/// Returns `true` if values of the type are quantum units.
+///
+/// This is always the case for qubits. With the `qudits` feature, qudits of
+/// any dimension and the types registered with [`register_quantum_type`] are
+/// also quantum units. With the `bosonic` feature, so are bosonic modes.
+///
+/// Quantum units are counted as qubits by the circuit machinery, e.g. by
+/// [`Circuit::qubits`] and [`Command::input_qubits`].
+///
+/// [`Command::input_qubits`]: super::Command::input_qubits
+pub fn is_quantum_type(typ: &Type) -> bool {
+    if typ == &QB_T {
+        return true;
+    }
+    #[cfg(feature = "bosonic")]
+    if typ == &crate::extension::bosonic::MODE_T {
+        return true;
+    }
+    #[cfg(feature = "qudits")]
+    {
+        if crate::extension::qudit::qudit_dimension(typ).is_some() {
+            return true;
+        }
+        return quantum_types::QUANTUM_TYPES.read().unwrap().contains(typ);
+    }
+    #[allow(unreachable_code)]
+    false
+}
+
+/// Register a linear type defined outside of tket2 as a quantum unit.
+///
+/// Circuits on the type can then be processed like qubit circuits. See
+/// [`is_quantum_type`].
+///
+/// # Panics
+///
+/// Panics if the type is not linear.
+#[cfg(feature = "qudits")]
+pub fn register_quantum_type(typ: Type) {
+    assert!(
+        type_is_linear(&typ),
+        "Only linear types can be quantum units, but {typ} is copyable."
+    );
+    let mut types = quantum_types::QUANTUM_TYPES.write().unwrap();
+    if !types.contains(&typ) {
+        types.push(typ);
+    }
+}
+
+#[cfg(feature = "qudits")]
+mod quantum_types {
+    use std::sync::RwLock;
+
+    use hugr::types::Type;
+    use lazy_static::lazy_static;
+
+    lazy_static! {
+        /// The user-registered quantum types.
+        pub(super) static ref QUANTUM_TYPES: RwLock<Vec<Type>> = RwLock::new(Vec::new());
+    }
+}
+
 /// An iterator over the units in the input or output boundary of a [Node].
 #[derive(Clone, Debug)]
 pub struct Units<P, UL = DefaultUnitLabeller> {
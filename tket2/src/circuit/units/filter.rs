@@ -5,12 +5,11 @@
 //!
 //! [`Units`]: crate::circuit::units::Units
 
-use hugr::extension::prelude;
 use hugr::types::Type;
 use hugr::CircuitUnit;
 use hugr::Wire;
 
-use super::LinearUnit;
+use super::{is_quantum_type, LinearUnit};
 
 /// A unit filter that return only linear units.
 pub fn filter_linear<P>(item: (CircuitUnit, P, Type)) -> Option<(LinearUnit, P, Type)> {
@@ -21,9 +20,22 @@ pub fn filter_linear<P>(item: (CircuitUnit, P, Type)) -> Option<(LinearUnit, P,
 }
 
 /// A unit filter that return only qubits, a subset of [`filter_linear`].
+///
+/// Any quantum unit is considered a qubit, see [`is_quantum_type`].
 pub fn filter_qubit<P>(item: (CircuitUnit, P, Type)) -> Option<(LinearUnit, P, Type)> {
     match item {
-        (CircuitUnit::Linear(unit), port, typ) if typ == prelude::QB_T => {
+        (CircuitUnit::Linear(unit), port, typ) if is_quantum_type(&typ) => {
+            Some((LinearUnit::new(unit), port, typ))
+        }
+        _ => None,
+    }
+}
+
+/// A unit filter that return only bosonic modes, a subset of [`filter_qubit`].
+#[cfg(feature = "bosonic")]
+pub fn filter_mode<P>(item: (CircuitUnit, P, Type)) -> Option<(LinearUnit, P, Type)> {
+    match item {
+        (CircuitUnit::Linear(unit), port, typ) if typ == crate::extension::bosonic::MODE_T => {
             Some((LinearUnit::new(unit), port, typ))
         }
         _ => None,
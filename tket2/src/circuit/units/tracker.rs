@@ -0,0 +1,238 @@
+//! Cached lookup of the linear units carried by each port of a circuit.
+
+use std::collections::{HashMap, HashSet};
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::hugr::SimpleReplacementError;
+use hugr::{HugrView, Node, OutgoingPort, Port, Wire};
+
+use super::{filter, DefaultUnitLabeller, LinearUnit, Units};
+use crate::rewrite::CircuitRewrite;
+use crate::Circuit;
+
+/// A map from the ports of a circuit to the linear units they carry.
+///
+/// Computing the linear unit of a port with the [`Units`] iterators requires
+/// traversing the circuit from its inputs. The tracker does this once, and
+/// answers each query in constant time afterwards. It also keeps track of the
+/// current wire of each linear unit, i.e. the last wire carrying it before
+/// the circuit's output.
+///
+/// The tracker can be kept up to date across modifications of the circuit,
+/// either by applying rewrites with [`UnitTracker::apply_rewrite`], or by
+/// calling [`UnitTracker::invalidate`] before modifying a set of nodes and
+/// [`UnitTracker::update`] afterwards.
+///
+/// Linear units are matched across a node in the same way as in
+/// [`Circuit::commands`]: the nth linear input is matched against the nth
+/// linear output.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct UnitTracker {
+    /// The linear unit carried by each linear port.
+    ports: HashMap<(Node, Port), LinearUnit>,
+    /// The last wire carrying each linear unit.
+    wires: HashMap<LinearUnit, Wire>,
+    /// Output ports whose linked nodes have been invalidated, from which
+    /// the units need to be propagated again.
+    pending: Vec<(Node, OutgoingPort)>,
+}
+
+impl UnitTracker {
+    /// Compute the linear units of every port in a circuit.
+    pub fn new(circ: &Circuit<impl HugrView>) -> Self {
+        let mut tracker = Self::default();
+        let input = circ.input_node();
+        for (unit, port, _) in circ.linear_units() {
+            tracker.ports.insert((input, port.into()), unit);
+            tracker.wires.insert(unit, Wire::new(input, port));
+        }
+        for cmd in circ.commands() {
+            for (unit, port, _) in cmd.linear_inputs() {
+                tracker.ports.insert((cmd.node(), port.into()), unit);
+                tracker.wires.remove(&unit);
+            }
+            for (unit, port, _) in cmd.linear_outputs() {
+                tracker.ports.insert((cmd.node(), port.into()), unit);
+                tracker.wires.insert(unit, Wire::new(cmd.node(), port));
+            }
+        }
+        let output = circ.output_node();
+        for (_, port, _) in
+            Units::new_incoming(circ, output, DefaultUnitLabeller).filter_map(filter::filter_linear)
+        {
+            let Some((src, src_port)) = circ.hugr().single_linked_output(output, port) else {
+                continue;
+            };
+            if let Some(&unit) = tracker.ports.get(&(src, src_port.into())) {
+                tracker.ports.insert((output, port.into()), unit);
+            }
+        }
+        tracker
+    }
+
+    /// Returns the linear unit carried by a port, if it is a tracked linear
+    /// port.
+    #[inline]
+    pub fn linear_unit(&self, node: Node, port: impl Into<Port>) -> Option<LinearUnit> {
+        self.ports.get(&(node, port.into())).copied()
+    }
+
+    /// Returns the current wire of a linear unit.
+    ///
+    /// This is the last wire carrying the unit, connected to the circuit's
+    /// output. Returns `None` if the unit is not tracked, or if it is
+    /// consumed by an operation.
+    #[inline]
+    pub fn wire(&self, unit: LinearUnit) -> Option<Wire> {
+        self.wires.get(&unit).copied()
+    }
+
+    /// Forget the units of a set of nodes that are about to be modified or
+    /// removed.
+    ///
+    /// Call [`UnitTracker::update`] after modifying the circuit to assign
+    /// units to the nodes that replaced them.
+    pub fn invalidate(
+        &mut self,
+        circ: &Circuit<impl HugrView>,
+        nodes: impl IntoIterator<Item = Node>,
+    ) {
+        let nodes: HashSet<Node> = nodes.into_iter().collect();
+        for &node in &nodes {
+            for port in circ.hugr().all_node_ports(node) {
+                if self.ports.remove(&(node, port)).is_none() {
+                    continue;
+                }
+                let Ok(in_port) = port.as_incoming() else {
+                    continue;
+                };
+                if let Some((src, src_port)) = circ.hugr().single_linked_output(node, in_port) {
+                    if !nodes.contains(&src) {
+                        self.pending.push((src, src_port));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Assign units to the nodes connected to the invalidated ones, after
+    /// modifying the circuit.
+    ///
+    /// Units are propagated from the ports that were connected to the
+    /// invalidated nodes, until they reach nodes whose units are unchanged.
+    /// Operations with linear outputs but no linear inputs are not reached,
+    /// so qubits allocated by the new nodes are not tracked.
+    pub fn update(&mut self, circ: &Circuit<impl HugrView>) {
+        let output = circ.output_node();
+        let mut stack = std::mem::take(&mut self.pending);
+        while let Some((src, src_port)) = stack.pop() {
+            let Some(&unit) = self.ports.get(&(src, src_port.into())) else {
+                continue;
+            };
+            for (dst, dst_port) in circ.hugr().linked_inputs(src, src_port) {
+                if dst == output {
+                    self.ports.insert((dst, dst_port.into()), unit);
+                    self.wires.insert(unit, Wire::new(src, src_port));
+                    continue;
+                }
+                if self.ports.insert((dst, dst_port.into()), unit) == Some(unit) {
+                    // The rest of the unit's path is unchanged.
+                    continue;
+                }
+                let linear_inputs = Units::new_incoming(circ, dst, DefaultUnitLabeller)
+                    .filter_map(filter::filter_linear)
+                    .map(|(_, port, _)| port);
+                let linear_outputs = Units::new_outgoing(circ, dst, DefaultUnitLabeller)
+                    .filter_map(filter::filter_linear)
+                    .map(|(_, port, _)| port);
+                let Some(out_port) = linear_inputs
+                    .zip(linear_outputs)
+                    .find_map(|(i, o)| (i == dst_port).then_some(o))
+                else {
+                    // The unit is consumed by the operation.
+                    self.wires.remove(&unit);
+                    continue;
+                };
+                self.ports.insert((dst, out_port.into()), unit);
+                stack.push((dst, out_port));
+            }
+        }
+    }
+
+    /// Apply a rewrite to the circuit, updating the tracked units.
+    pub fn apply_rewrite(
+        &mut self,
+        circ: &mut Circuit<impl HugrMut>,
+        rewrite: CircuitRewrite,
+    ) -> Result<(), SimpleReplacementError> {
+        self.invalidate(circ, rewrite.invalidation_set().collect::<Vec<_>>());
+        let result = rewrite.apply(circ);
+        self.update(circ);
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::extension::prelude::QB_T;
+    use hugr::types::Signature;
+    use hugr::{IncomingPort, OutgoingPort};
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::rewrite::Subcircuit;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    #[test]
+    fn track_rewrites() {
+        let mut circ = build_simple_circuit(2, |c| {
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            c.append(Tk2Op::T, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        let [_, cx, t] = circ.commands().map(|cmd| cmd.node()).collect_vec()[..] else {
+            panic!("Expected three commands");
+        };
+        let mut tracker = UnitTracker::new(&circ);
+        assert_eq!(
+            tracker.linear_unit(cx, IncomingPort::from(1)),
+            Some(LinearUnit::new(1))
+        );
+        assert_eq!(
+            tracker.wire(LinearUnit::new(1)),
+            Some(Wire::new(t, OutgoingPort::from(0)))
+        );
+
+        // Replace the CX with a CX followed by crossed wires, so the T gate
+        // now acts on the first qubit.
+        let replacement = {
+            let mut dfg = DFGBuilder::new(Signature::new_endo(vec![QB_T, QB_T])).unwrap();
+            let [a, b] = dfg.input_wires_arr();
+            let [a, b] = dfg
+                .add_dataflow_op(Tk2Op::CX, [a, b])
+                .unwrap()
+                .outputs_arr();
+            dfg.finish_hugr_with_outputs([b, a], &REGISTRY).unwrap()
+        };
+        let rewrite = Subcircuit::try_from_nodes([cx], &circ)
+            .unwrap()
+            .create_rewrite(&circ, Circuit::from(replacement))
+            .unwrap();
+        tracker.apply_rewrite(&mut circ, rewrite).unwrap();
+
+        assert_eq!(
+            tracker.linear_unit(t, IncomingPort::from(0)),
+            Some(LinearUnit::new(0))
+        );
+        assert_eq!(
+            tracker.wire(LinearUnit::new(0)),
+            Some(Wire::new(t, OutgoingPort::from(0)))
+        );
+        assert_eq!(tracker, UnitTracker::new(&circ));
+    }
+}
@@ -0,0 +1,378 @@
+//! Validation of quantum-specific circuit invariants.
+//!
+//! HUGR validation ensures that a circuit is a well-formed dataflow graph.
+//! The checks here additionally ensure that the quantum operations in the
+//! circuit are used consistently with their definitions in the tket2
+//! extension.
+
+use hugr::extension::prelude::{BOOL_T, QB_T};
+use hugr::ops::{NamedOp, OpTrait, OpType};
+use hugr::std_extensions::arithmetic::float_types::FLOAT64_TYPE;
+use hugr::types::{Signature, Type};
+use hugr::{HugrView, IncomingPort, Node, OutgoingPort};
+use itertools::Itertools;
+use thiserror::Error;
+
+use super::Circuit;
+use crate::Tk2Op;
+
+impl<T: HugrView> Circuit<T> {
+    /// Check quantum-specific invariants of the circuit, beyond HUGR
+    /// validation.
+    ///
+    /// The following is checked for each operation in the circuit:
+    ///
+    /// - No linear value, such as a qubit, is used more than once by a single
+    ///   command, or by several commands.
+    /// - Operations from the tket2 extension have the signature given by
+    ///   their definition. In particular, measurements return a qubit and a
+    ///   boolean.
+    /// - Angle inputs to tket2 operations are `float64` values.
+    ///
+    /// This does not run the HUGR validation itself, which should be done
+    /// separately if the circuit may be malformed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first violation found, pointing to the offending node and
+    /// port.
+    pub fn validate_quantum(&self) -> Result<(), QuantumValidationError> {
+        let hugr = self.hugr();
+        for node in hugr.children(self.parent()) {
+            check_linear_outputs(hugr, node)?;
+            if let Ok(op) = Tk2Op::try_from(hugr.get_optype(node)) {
+                check_tk2_op(hugr, node, op)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check that the linear outputs of a node are used at most once.
+fn check_linear_outputs(hugr: &impl HugrView, node: Node) -> Result<(), QuantumValidationError> {
+    for (port, typ) in hugr.out_value_types(node) {
+        if typ.copyable() {
+            continue;
+        }
+        let targets = hugr.linked_inputs(node, port).collect_vec();
+        if targets.len() <= 1 {
+            continue;
+        }
+        if let Some(&(target, _)) = targets.iter().duplicates_by(|(n, _)| *n).next() {
+            return Err(QuantumValidationError::RepeatedLinearInput {
+                optype: hugr.get_optype(target).clone(),
+                node: target,
+                source_node: node,
+                source_port: port,
+            });
+        }
+        return Err(QuantumValidationError::ClonedLinearWire {
+            optype: hugr.get_optype(node).clone(),
+            node,
+            port,
+            uses: targets.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Check that a tket2 operation is used according to its definition.
+fn check_tk2_op(hugr: &impl HugrView, node: Node, op: Tk2Op) -> Result<(), QuantumValidationError> {
+    let optype = hugr.get_optype(node);
+    let expected = OpType::from(op)
+        .dataflow_signature()
+        .expect("Tk2Ops have a dataflow signature");
+    let found = optype.dataflow_signature();
+
+    if op == Tk2Op::Measure {
+        debug_assert_eq!(expected.output_types(), &[QB_T, BOOL_T]);
+        let outputs = found.as_ref().map_or(&[][..], Signature::output_types);
+        for (port, typ) in outputs.iter().enumerate() {
+            let expected_typ = expected.out_port_type(port);
+            if expected_typ.is_some_and(|t| t != typ) {
+                return Err(QuantumValidationError::MeasurementOutput {
+                    optype: optype.clone(),
+                    node,
+                    port: port.into(),
+                    typ: typ.clone(),
+                    expected: expected_typ.unwrap().clone(),
+                });
+            }
+        }
+    }
+
+    let angle_ports = expected
+        .input_ports()
+        .filter(|&port| expected.in_port_type(port) == Some(&FLOAT64_TYPE));
+    for port in angle_ports {
+        let Some(typ) = found.as_ref().and_then(|s| s.in_port_type(port)) else {
+            continue;
+        };
+        if typ != &FLOAT64_TYPE {
+            return Err(QuantumValidationError::AngleType {
+                optype: optype.clone(),
+                node,
+                port,
+                typ: typ.clone(),
+            });
+        }
+        // The wire must also carry a float, even if the port has the right type.
+        let source_typ = hugr
+            .single_linked_output(node, port)
+            .and_then(|(src, src_port)| {
+                let sig = hugr.get_optype(src).dataflow_signature()?;
+                sig.out_port_type(src_port).cloned()
+            });
+        if let Some(source_typ) = source_typ.filter(|t| t != &FLOAT64_TYPE) {
+            return Err(QuantumValidationError::AngleType {
+                optype: optype.clone(),
+                node,
+                port,
+                typ: source_typ,
+            });
+        }
+    }
+
+    let matches = found.as_ref().is_some_and(|s| {
+        s.input_types() == expected.input_types() && s.output_types() == expected.output_types()
+    });
+    if !matches {
+        return Err(QuantumValidationError::InvalidSignature {
+            optype: optype.clone(),
+            node,
+            expected,
+            found,
+        });
+    }
+    Ok(())
+}
+
+/// Errors that can occur when checking the quantum invariants of a circuit.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum QuantumValidationError {
+    /// A command receives the same linear value in several of its inputs.
+    #[error("Operation {} in {node} uses the linear value from {source_node} port {source_port} more than once.", optype.name())]
+    RepeatedLinearInput {
+        /// The command using the value.
+        optype: OpType,
+        /// The node of the command.
+        node: Node,
+        /// The node producing the value.
+        source_node: Node,
+        /// The output port producing the value.
+        source_port: OutgoingPort,
+    },
+    /// A linear output is connected to several inputs.
+    #[error("The linear output {port} of operation {} in {node} is used {uses} times.", optype.name())]
+    ClonedLinearWire {
+        /// The operation producing the value.
+        optype: OpType,
+        /// The node producing the value.
+        node: Node,
+        /// The cloned output port.
+        port: OutgoingPort,
+        /// The number of times the value is used.
+        uses: usize,
+    },
+    /// A measurement output has the wrong type.
+    #[error("Output {port} of measurement {} in {node} has type {typ}, but {expected} was expected.", optype.name())]
+    MeasurementOutput {
+        /// The measurement operation.
+        optype: OpType,
+        /// The node of the measurement.
+        node: Node,
+        /// The offending output port.
+        port: OutgoingPort,
+        /// The type of the output.
+        typ: Type,
+        /// The expected type of the output.
+        expected: Type,
+    },
+    /// An angle input is not a `float64` value.
+    #[error("Angle input {port} of operation {} in {node} has type {typ}, but angles must be float64 values.", optype.name())]
+    AngleType {
+        /// The operation taking the angle.
+        optype: OpType,
+        /// The node of the operation.
+        node: Node,
+        /// The offending input port.
+        port: IncomingPort,
+        /// The type of the angle.
+        typ: Type,
+    },
+    /// A tket2 operation does not have the signature of its definition.
+    #[error("Operation {} in {node} does not match the signature of its definition.", optype.name())]
+    InvalidSignature {
+        /// The operation.
+        optype: OpType,
+        /// The node of the operation.
+        node: Node,
+        /// The signature of the operation definition.
+        expected: Signature,
+        /// The signature of the operation in the circuit.
+        found: Option<Signature>,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::extension::prelude::USIZE_T;
+    use hugr::hugr::hugrmut::HugrMut;
+    use hugr::ops::custom::OpaqueOp;
+    use hugr::ops::dataflow::IOTrait;
+    use hugr::types::Signature;
+    use hugr_core::hugr::internal::HugrMutInternals;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::{REGISTRY, TKET2_EXTENSION_ID};
+    use crate::utils::build_simple_circuit;
+
+    /// An opaque tket2 operation with a custom signature.
+    fn opaque(op: Tk2Op, signature: Signature) -> OpType {
+        OpaqueOp::new(TKET2_EXTENSION_ID, op.name(), String::new(), [], signature).into()
+    }
+
+    fn find_op(circ: &Circuit, op: Tk2Op) -> Node {
+        circ.operations()
+            .find(|cmd| Tk2Op::try_from(cmd.optype()) == Ok(op))
+            .unwrap()
+            .node()
+    }
+
+    fn rotation() -> Circuit {
+        let mut dfg =
+            DFGBuilder::new(Signature::new(vec![QB_T, FLOAT64_TYPE], vec![QB_T])).unwrap();
+        let [q, angle] = dfg.input_wires_arr();
+        let [q] = dfg
+            .add_dataflow_op(Tk2Op::RzF64, [q, angle])
+            .unwrap()
+            .outputs_arr();
+        dfg.finish_hugr_with_outputs([q], &REGISTRY).unwrap().into()
+    }
+
+    #[test]
+    fn valid_circuits() {
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::Measure, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(circ.validate_quantum(), Ok(()));
+        assert_eq!(rotation().validate_quantum(), Ok(()));
+    }
+
+    #[test]
+    fn repeated_qubit() {
+        let mut circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        let cx = find_op(&circ, Tk2Op::CX);
+        let input = circ.input_node();
+        let hugr = circ.hugr_mut();
+        hugr.disconnect(cx, IncomingPort::from(1));
+        hugr.connect(input, 0, cx, 1);
+
+        assert_eq!(
+            circ.validate_quantum(),
+            Err(QuantumValidationError::RepeatedLinearInput {
+                optype: Tk2Op::CX.into(),
+                node: cx,
+                source_node: input,
+                source_port: 0.into(),
+            })
+        );
+    }
+
+    #[test]
+    fn cloned_qubit() {
+        let mut circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::X, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        let x = find_op(&circ, Tk2Op::X);
+        let input = circ.input_node();
+        let hugr = circ.hugr_mut();
+        hugr.disconnect(x, IncomingPort::from(0));
+        hugr.connect(input, 0, x, 0);
+
+        let err = circ.validate_quantum().unwrap_err();
+        assert!(matches!(
+            err,
+            QuantumValidationError::ClonedLinearWire { node, port, uses: 2, .. }
+                if node == input && port == OutgoingPort::from(0)
+        ));
+    }
+
+    #[test]
+    fn measurement_output() {
+        let mut circ = build_simple_circuit(1, |circ| {
+            circ.append(Tk2Op::Measure, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        let measure = find_op(&circ, Tk2Op::Measure);
+        let signature = Signature::new(vec![QB_T], vec![QB_T, USIZE_T]);
+        circ.hugr_mut()
+            .replace_op(measure, opaque(Tk2Op::Measure, signature))
+            .unwrap();
+
+        assert!(matches!(
+            circ.validate_quantum(),
+            Err(QuantumValidationError::MeasurementOutput { node, port, typ, expected, .. })
+                if node == measure && port == OutgoingPort::from(1) && typ == USIZE_T && expected == BOOL_T
+        ));
+    }
+
+    #[rstest]
+    #[case::port_type(true)]
+    #[case::wire_type(false)]
+    fn angle_type(#[case] replace_op: bool) {
+        let mut circ = rotation();
+        let rz = find_op(&circ, Tk2Op::RzF64);
+        if replace_op {
+            let signature = Signature::new(vec![QB_T, USIZE_T], vec![QB_T]);
+            circ.hugr_mut()
+                .replace_op(rz, opaque(Tk2Op::RzF64, signature))
+                .unwrap();
+        } else {
+            let input = circ.input_node();
+            circ.hugr_mut()
+                .replace_op(input, hugr::ops::Input::new(vec![QB_T, USIZE_T]))
+                .unwrap();
+        }
+
+        assert!(matches!(
+            circ.validate_quantum(),
+            Err(QuantumValidationError::AngleType { node, port, typ, .. })
+                if node == rz && port == IncomingPort::from(1) && typ == USIZE_T
+        ));
+    }
+
+    #[test]
+    fn invalid_signature() {
+        let mut circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        let cx = find_op(&circ, Tk2Op::CX);
+        let signature = Signature::new(vec![QB_T, QB_T], vec![QB_T, QB_T, BOOL_T]);
+        circ.hugr_mut()
+            .replace_op(cx, opaque(Tk2Op::CX, signature))
+            .unwrap();
+
+        assert!(matches!(
+            circ.validate_quantum(),
+            Err(QuantumValidationError::InvalidSignature { node, .. }) if node == cx
+        ));
+    }
+}
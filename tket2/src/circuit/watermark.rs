@@ -0,0 +1,291 @@
+//! Watermarking circuits to track their provenance.
+//!
+//! A [`Watermark`] can be attached to a circuit in two ways:
+//!
+//! - As metadata on the circuit's root node, under the [`METADATA_WATERMARK`]
+//!   key. This is lightweight, but is lost by toolchains that drop HUGR
+//!   metadata.
+//! - As a sequence of identity gadgets appended to one of the qubits, see
+//!   [`Circuit::embed_watermark_gadgets`]. Each gadget is a pair of
+//!   self-inverse gates, so the circuit's semantics are unchanged. The gadget
+//!   nodes are [frozen](super::frozen) so that optimisation passes do not
+//!   cancel them, and they survive conversion to and from pytket.
+//!
+//! [`Circuit::detect_watermark`] recovers the watermark from either encoding.
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::{HugrView, IncomingPort, Node, OutgoingPort};
+use itertools::Itertools;
+use thiserror::Error;
+
+use super::units::Qubit;
+use crate::{Circuit, Tk2Op};
+
+/// Metadata key for the watermark of a circuit.
+pub const METADATA_WATERMARK: &str = "TKET2.watermark";
+
+/// The gates marking the start of a watermark gadget sequence.
+const HEADER: [Tk2Op; 2] = [Tk2Op::S, Tk2Op::Sdg];
+
+/// The self-inverse gate encoding each 2-bit symbol of the watermark.
+const SYMBOLS: [Tk2Op; 4] = [Tk2Op::X, Tk2Op::Y, Tk2Op::Z, Tk2Op::H];
+
+/// The number of gates in a full gadget sequence, including the header.
+const GADGET_LEN: usize = HEADER.len() + 2 * (u32::BITS as usize / 2);
+
+/// A 32-bit identifier embedded in a circuit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Watermark(u32);
+
+impl Watermark {
+    /// Creates a new [`Watermark`].
+    pub const fn new(value: u32) -> Self {
+        Self(value)
+    }
+
+    /// Returns the value of this [`Watermark`].
+    pub const fn value(&self) -> u32 {
+        self.0
+    }
+
+    /// The sequence of gates encoding the watermark, starting with the header.
+    ///
+    /// Each symbol is encoded as a pair of identical self-inverse gates, from
+    /// the most significant bits to the least significant ones.
+    fn gadget_ops(&self) -> Vec<Tk2Op> {
+        let symbols = (0..u32::BITS / 2)
+            .rev()
+            .map(|i| SYMBOLS[(self.0 >> (2 * i)) as usize & 0b11]);
+        HEADER
+            .into_iter()
+            .chain(symbols.flat_map(|op| [op, op]))
+            .collect()
+    }
+
+    /// Decode a watermark from a sequence of gates, if it is a valid gadget
+    /// sequence.
+    fn from_gadget_ops(ops: &[Tk2Op]) -> Option<Self> {
+        let (header, body) = ops.split_at(HEADER.len());
+        if header != HEADER || body.len() != GADGET_LEN - HEADER.len() {
+            return None;
+        }
+        body.iter()
+            .tuples()
+            .try_fold(0, |value, (a, b)| {
+                let symbol = SYMBOLS.iter().position(|s| s == a)?;
+                (a == b).then_some((value << 2) | symbol as u32)
+            })
+            .map(Self)
+    }
+}
+
+impl std::fmt::Display for Watermark {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:08x}", self.0)
+    }
+}
+
+impl From<u32> for Watermark {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+/// Errors that can occur when watermarking a circuit.
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum WatermarkError {
+    /// The qubit does not exist in the circuit.
+    #[error("Qubit {0} does not exist in the circuit")]
+    InvalidQubit(Qubit),
+}
+
+impl<T: HugrView> Circuit<T> {
+    /// Returns the watermark stored in the circuit's metadata, if any.
+    pub fn watermark(&self) -> Option<Watermark> {
+        let meta = self
+            .hugr()
+            .get_metadata(self.parent(), METADATA_WATERMARK)?;
+        let value = meta.as_u64()?.try_into().ok()?;
+        Some(Watermark(value))
+    }
+
+    /// Returns the watermark encoded as identity gadgets on the circuit's
+    /// qubits, if any.
+    ///
+    /// If several qubits carry gadgets, the one on the lowest qubit is
+    /// returned.
+    pub fn watermark_from_gadgets(&self) -> Option<Watermark>
+    where
+        Self: Sized,
+    {
+        (0..self.qubit_count()).find_map(|q| {
+            let ops = self.single_qubit_ops(Qubit::new(q));
+            // Other gates may have been appended after the gadgets, so look for
+            // the last valid sequence on the qubit.
+            (0..ops.len())
+                .rev()
+                .filter_map(|start| ops.get(start..start + GADGET_LEN))
+                .find_map(|window| {
+                    let window = window.iter().copied().collect::<Option<Vec<_>>>()?;
+                    Watermark::from_gadget_ops(&window)
+                })
+        })
+    }
+
+    /// Detect the watermark of a circuit, either from its metadata or from
+    /// the identity gadgets on its qubits.
+    pub fn detect_watermark(&self) -> Option<Watermark>
+    where
+        Self: Sized,
+    {
+        self.watermark().or_else(|| self.watermark_from_gadgets())
+    }
+
+    /// The operations applied to a qubit, in order.
+    ///
+    /// Operations that are not single-qubit [`Tk2Op`]s are returned as `None`.
+    fn single_qubit_ops(&self, qubit: Qubit) -> Vec<Option<Tk2Op>>
+    where
+        Self: Sized,
+    {
+        self.commands()
+            .filter(|cmd| {
+                cmd.input_qubits()
+                    .any(|(unit, _, _)| Qubit::from(unit) == qubit)
+            })
+            .map(|cmd| {
+                let op = Tk2Op::try_from(cmd.optype()).ok()?;
+                (cmd.input_qubits().count() == 1).then_some(op)
+            })
+            .collect()
+    }
+}
+
+impl<T: HugrMut> Circuit<T> {
+    /// Store a watermark in the circuit's metadata.
+    pub fn set_watermark(&mut self, watermark: Watermark) {
+        let parent = self.parent();
+        self.hugr_mut()
+            .set_metadata(parent, METADATA_WATERMARK, watermark.value());
+    }
+
+    /// Append identity gadgets encoding a watermark at the end of a qubit.
+    ///
+    /// The gadgets do not change the semantics of the circuit. Their nodes
+    /// are frozen, so that optimisation passes leave them untouched.
+    pub fn embed_watermark_gadgets(
+        &mut self,
+        watermark: Watermark,
+        qubit: Qubit,
+    ) -> Result<(), WatermarkError>
+    where
+        Self: Sized,
+    {
+        let (mut node, mut port) = self.qubit_end(qubit)?;
+        let parent = self.parent();
+        let hugr = self.hugr_mut();
+        let (target, target_port) = hugr
+            .linked_inputs(node, port)
+            .exactly_one()
+            .ok()
+            .expect("Qubit wires must be linear.");
+        hugr.disconnect(node, port);
+
+        let mut gadgets = Vec::with_capacity(GADGET_LEN);
+        for op in watermark.gadget_ops() {
+            let new_node = hugr.add_node_with_parent(parent, op);
+            hugr.connect(node, port, new_node, IncomingPort::from(0));
+            (node, port) = (new_node, OutgoingPort::from(0));
+            gadgets.push(new_node);
+        }
+        hugr.connect(node, port, target, target_port);
+        self.freeze_nodes(gadgets);
+        Ok(())
+    }
+
+    /// The last outgoing port of a qubit wire before the circuit's output.
+    fn qubit_end(&self, qubit: Qubit) -> Result<(Node, OutgoingPort), WatermarkError>
+    where
+        Self: Sized,
+    {
+        let (_, port, _) = self
+            .qubits()
+            .find(|(unit, _, _)| Qubit::from(*unit) == qubit)
+            .ok_or(WatermarkError::InvalidQubit(qubit))?;
+        let mut end = (self.input_node(), port);
+        for cmd in self.commands() {
+            if let Some((_, port, _)) = cmd
+                .output_qubits()
+                .find(|(unit, _, _)| Qubit::from(*unit) == qubit)
+            {
+                end = (cmd.node(), port);
+            }
+        }
+        Ok(end)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::serialize::TKETDecode;
+    use crate::utils::build_simple_circuit;
+    use tket_json_rs::circuit_json::SerialCircuit;
+
+    #[fixture]
+    fn circ() -> Circuit {
+        build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::T, [1])?;
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case::zero(0)]
+    #[case::max(u32::MAX)]
+    #[case::mixed(0xdead_beef)]
+    fn gadget_roundtrip(#[case] value: u32) {
+        let watermark = Watermark::new(value);
+        let ops = watermark.gadget_ops();
+        assert_eq!(ops.len(), GADGET_LEN);
+        assert_eq!(Watermark::from_gadget_ops(&ops), Some(watermark));
+    }
+
+    #[rstest]
+    fn metadata_watermark(mut circ: Circuit) {
+        assert_eq!(circ.detect_watermark(), None);
+        circ.set_watermark(Watermark::new(42));
+        assert_eq!(circ.watermark(), Some(Watermark::new(42)));
+        assert_eq!(circ.detect_watermark(), Some(Watermark::new(42)));
+    }
+
+    #[rstest]
+    fn gadget_watermark(mut circ: Circuit) {
+        let watermark = Watermark::new(0x1234_abcd);
+        let n_ops = circ.num_operations();
+        circ.embed_watermark_gadgets(watermark, Qubit::new(1))
+            .unwrap();
+        circ.hugr().validate(&crate::extension::REGISTRY).unwrap();
+
+        assert_eq!(circ.num_operations(), n_ops + GADGET_LEN);
+        assert_eq!(circ.frozen_nodes().count(), GADGET_LEN);
+        assert_eq!(circ.watermark(), None);
+        assert_eq!(circ.detect_watermark(), Some(watermark));
+
+        // The gadgets survive a roundtrip through pytket, which drops the
+        // frozen markers.
+        let ser = SerialCircuit::encode(&circ).unwrap();
+        let decoded = ser.decode().unwrap();
+        assert_eq!(decoded.detect_watermark(), Some(watermark));
+
+        assert_eq!(
+            circ.embed_watermark_gadgets(watermark, Qubit::new(2)),
+            Err(WatermarkError::InvalidQubit(Qubit::new(2)))
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Caching of compiled circuits.
+//!
+//! A [`CompilationCache`] stores the outputs of a compilation pipeline on
+//! disk, keyed by a hash of the input circuit and of the pipeline
+//! configuration. Compiling a circuit that was already compiled with the same
+//! configuration returns the stored result, which avoids repeating expensive
+//! optimisations when the same circuits are compiled many times, e.g. in
+//! parameter sweeps.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::serialize::hugr_file::HugrFileError;
+use crate::Circuit;
+
+/// The extension of the files storing compiled circuits.
+const CACHE_EXTENSION: &str = "hugr.json";
+
+/// The key of a compiled circuit in a [`CompilationCache`].
+///
+/// It is a hash of the input circuit in the HUGR JSON format, the
+/// configuration of the pipeline, and the version of this crate, so that
+/// results compiled by a different version are not reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CacheKey(u64);
+
+impl CacheKey {
+    /// Compute the key of a circuit compiled with the given configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the circuit or the configuration cannot be
+    /// serialised.
+    pub fn new(circ: &Circuit, config: &impl Serialize) -> Result<Self, CompilationCacheError> {
+        let mut circuit = Vec::new();
+        circ.save_hugr_writer(&mut circuit)?;
+        let config = serde_json::to_vec(config).map_err(CompilationCacheError::Config)?;
+        Ok(Self(fxhash::hash64(&(crate::VERSION, circuit, config))))
+    }
+}
+
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// The number of lookups answered by a [`CompilationCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    /// The number of compilations answered from the cache.
+    pub hits: usize,
+    /// The number of compilations that had to be run.
+    pub misses: usize,
+}
+
+/// An on-disk cache of compiled circuits.
+///
+/// Each compiled circuit is stored as a HUGR JSON file named after its
+/// [`CacheKey`] in the cache directory. Entries that cannot be loaded, e.g.
+/// because they were corrupted, are treated as missing and overwritten.
+#[derive(Debug, Clone)]
+pub struct CompilationCache {
+    dir: PathBuf,
+    stats: CacheStats,
+}
+
+impl CompilationCache {
+    /// Open a cache in the given directory, creating it if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, CompilationCacheError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            stats: CacheStats::default(),
+        })
+    }
+
+    /// The directory of the cache.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The hits and misses of the cache since it was opened.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// The compiled circuit stored with the given key, if any.
+    pub fn get(&self, key: CacheKey) -> Result<Option<Circuit>, CompilationCacheError> {
+        match Circuit::load_hugr_file(self.path(key)) {
+            Ok(circ) => Ok(Some(circ)),
+            Err(HugrFileError::Io(e)) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Store a compiled circuit with the given key, replacing any previous
+    /// one.
+    ///
+    /// The file is written under a temporary name first, so that concurrent
+    /// readers never see a partially written entry.
+    pub fn insert(&self, key: CacheKey, circ: &Circuit) -> Result<(), CompilationCacheError> {
+        let path = self.path(key);
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(format!(".{}.tmp", std::process::id()));
+        circ.save_hugr_file(&tmp_path)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Remove all the entries of the cache.
+    pub fn clear(&self) -> Result<(), CompilationCacheError> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.to_string_lossy().ends_with(CACHE_EXTENSION) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile a circuit with the given configuration, or return the cached
+    /// result of a previous compilation.
+    ///
+    /// The `compile` function must be deterministic: its result may only
+    /// depend on the circuit and the configuration. Its result is stored in
+    /// the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `compile`, or cache errors converted to the same
+    /// error type.
+    pub fn compile<C: Serialize, E: From<CompilationCacheError>>(
+        &mut self,
+        circ: &Circuit,
+        config: &C,
+        compile: impl FnOnce(Circuit, &C) -> Result<Circuit, E>,
+    ) -> Result<Circuit, E> {
+        let key = CacheKey::new(circ, config)?;
+        if let Some(compiled) = self.get(key)? {
+            self.stats.hits += 1;
+            return Ok(compiled);
+        }
+        self.stats.misses += 1;
+        let compiled = compile(circ.clone(), config)?;
+        self.insert(key, &compiled)?;
+        Ok(compiled)
+    }
+
+    /// Compile a batch of circuits with the same configuration, reusing the
+    /// cached results.
+    ///
+    /// See [`CompilationCache::compile`]. Repeated circuits in the batch are
+    /// only compiled once.
+    pub fn compile_batch<'c, C: Serialize, E: From<CompilationCacheError>>(
+        &mut self,
+        circs: impl IntoIterator<Item = &'c Circuit>,
+        config: &C,
+        compile: impl Fn(Circuit, &C) -> Result<Circuit, E>,
+    ) -> Result<Vec<Circuit>, E> {
+        circs
+            .into_iter()
+            .map(|circ| self.compile(circ, config, &compile))
+            .collect()
+    }
+
+    /// The path of the file storing an entry.
+    fn path(&self, key: CacheKey) -> PathBuf {
+        self.dir.join(format!("{key}.{CACHE_EXTENSION}"))
+    }
+}
+
+/// Errors that can occur when using a [`CompilationCache`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum CompilationCacheError {
+    /// Cannot access the cache directory.
+    #[error("Unable to access the compilation cache. {0}")]
+    Io(#[from] io::Error),
+    /// A circuit could not be stored in the cache.
+    #[error(transparent)]
+    HugrFile(#[from] HugrFileError),
+    /// The configuration could not be serialised.
+    #[error("Unable to serialise the compilation configuration. {0}")]
+    Config(#[source] serde_json::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use crate::passes::rebase::{rebase, GateSet, RebaseError};
+    use crate::sim::unitary::equal_up_to_phase;
+    use crate::utils::test::tk1_circuit;
+    use crate::{Tk2Op, Tket2Error};
+
+    /// An `H` and a `CX`, followed by an `Rz(angle)`.
+    fn h_cx_rz(angle: f64) -> Circuit {
+        tk1_circuit(
+            2,
+            0,
+            &format!(
+                r#"
+            {{"args": [["q", [0]]], "op": {{"type": "H"}}}},
+            {{"args": [["q", [0]], ["q", [1]]], "op": {{"type": "CX"}}}},
+            {{"args": [["q", [1]]], "op": {{"type": "Rz", "params": ["{angle}"]}}}}"#
+            ),
+        )
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tket2_compilation_{name}_{}", std::process::id()))
+    }
+
+    /// Rebase to the gate set with the given name, counting the calls.
+    fn compile_with(
+        calls: &mut usize,
+    ) -> impl FnMut(Circuit, &String) -> Result<Circuit, Tket2Error> + '_ {
+        move |mut circ, name| {
+            *calls += 1;
+            let gate_set = match name.as_str() {
+                "quantinuum" => GateSet::quantinuum(),
+                _ => GateSet::ibm(),
+            };
+            rebase(&mut circ, &gate_set)?;
+            Ok(circ)
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Opening files is not supported in (isolated) miri
+    fn cached_compilation() {
+        let dir = cache_dir("cached");
+        let mut cache = CompilationCache::new(&dir).unwrap();
+        let circ = h_cx_rz(0.25);
+        let config = "quantinuum".to_string();
+        let mut calls = 0;
+
+        let compiled = cache
+            .compile(&circ, &config, compile_with(&mut calls))
+            .unwrap();
+        let cached = cache
+            .compile(&circ, &config, compile_with(&mut calls))
+            .unwrap();
+        assert_eq!(calls, 1);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1 });
+        assert_eq!(cached.num_operations(), compiled.num_operations());
+        assert_eq!(cached.global_phase(), compiled.global_phase());
+        assert!(equal_up_to_phase(
+            &cached.unitary().unwrap(),
+            &circ.unitary().unwrap(),
+            1e-9
+        ));
+
+        // A different configuration or circuit is compiled again.
+        cache
+            .compile(&circ, &"ibm".to_string(), compile_with(&mut calls))
+            .unwrap();
+        cache
+            .compile(&h_cx_rz(PI), &config, compile_with(&mut calls))
+            .unwrap();
+        assert_eq!(calls, 3);
+
+        // The cache persists across instances.
+        let mut reopened = CompilationCache::new(&dir).unwrap();
+        reopened
+            .compile(&circ, &config, compile_with(&mut calls))
+            .unwrap();
+        assert_eq!(calls, 3);
+        assert_eq!(reopened.stats().hits, 1);
+
+        cache.clear().unwrap();
+        assert!(cache
+            .get(CacheKey::new(&circ, &config).unwrap())
+            .unwrap()
+            .is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Opening files is not supported in (isolated) miri
+    fn batch_compilation() {
+        let dir = cache_dir("batch");
+        let mut cache = CompilationCache::new(&dir).unwrap();
+        let circs = [h_cx_rz(0.5), h_cx_rz(1.5), h_cx_rz(0.5)];
+        let calls = std::cell::Cell::new(0);
+
+        let compiled = cache
+            .compile_batch(&circs, &GateSet::quantinuum().name(), |mut circ, _| {
+                calls.set(calls.get() + 1);
+                rebase(&mut circ, &GateSet::quantinuum())?;
+                Ok::<_, Tket2Error>(circ)
+            })
+            .unwrap();
+        assert_eq!(compiled.len(), 3);
+        assert_eq!(calls.get(), 2);
+        assert!(compiled[0]
+            .commands()
+            .all(|cmd| Tk2Op::try_from(cmd.optype()) != Ok(Tk2Op::H)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Opening files is not supported in (isolated) miri
+    fn corrupted_entry() {
+        let dir = cache_dir("corrupted");
+        let mut cache = CompilationCache::new(&dir).unwrap();
+        let circ = h_cx_rz(0.25);
+        let key = CacheKey::new(&circ, &()).unwrap();
+        fs::write(cache.path(key), b"not a circuit").unwrap();
+
+        assert!(cache.get(key).unwrap().is_none());
+        let compiled = cache
+            .compile(&circ, &(), |circ, _| Ok::<_, Tket2Error>(circ))
+            .unwrap();
+        assert_eq!(compiled.num_operations(), circ.num_operations());
+        assert!(cache.get(key).unwrap().is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Opening files is not supported in (isolated) miri
+    fn compilation_errors() {
+        let dir = cache_dir("errors");
+        let mut cache = CompilationCache::new(&dir).unwrap();
+        let err = cache
+            .compile(&h_cx_rz(0.25), &(), |_, _| {
+                Err::<Circuit, _>(Tket2Error::from(RebaseError::UnknownGateSet(
+                    "missing".to_string(),
+                )))
+            })
+            .unwrap_err();
+        assert!(matches!(err, Tket2Error::Rebase(_)));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
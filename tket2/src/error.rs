@@ -0,0 +1,554 @@
+//! Crate-wide error type and diagnostics.
+//!
+//! Each module defines its own error enum describing the failures specific to
+//! it. [`Tket2Error`] collects all of them in a single type, assigning each a
+//! stable [`ErrorCode`] and extracting the location in the circuit where the
+//! failure happened. Downstream tools can render a [`Diagnostic`] to report the
+//! failure to users.
+
+use std::fmt;
+use std::io;
+
+use hugr::ops::{NamedOp, OpType};
+use hugr::Node;
+use thiserror::Error;
+
+use crate::backend::BackendError;
+use crate::circuit::compact::CompactCircuitError;
+use crate::circuit::dagger::DaggerError;
+use crate::circuit::edit::CommandEditError;
+use crate::circuit::parameters::InstantiationError;
+use crate::circuit::permutation::PermutationError;
+use crate::circuit::validate::QuantumValidationError;
+use crate::circuit::watermark::WatermarkError;
+use crate::circuit::HashError;
+use crate::compile::CompilationCacheError;
+use crate::gradient::GradientError;
+#[cfg(feature = "distributed")]
+use crate::optimiser::badger::DistributedError;
+use crate::optimiser::badger::EqCircClassError;
+use crate::passes::pulse::LoweringError;
+use crate::passes::pytket::PytketLoweringError;
+use crate::passes::qubit_remap::QubitRemapError;
+use crate::passes::t_schedule::TSchedulingError;
+use crate::passes::{PullForwardError, RebaseError};
+#[cfg(feature = "portmatching")]
+use crate::portmatching::matcher::MatcherSerialisationError;
+use crate::resource::BudgetExceeded;
+#[cfg(feature = "portmatching")]
+use crate::rewrite::ecc_rewriter::RewriterSerialisationError;
+#[cfg(feature = "portmatching")]
+use crate::rewrite::rules::RuleError;
+use crate::rewrite::{BatchRewriteError, RewriteBuildError};
+use crate::serialize::guppy::CircuitLoadError;
+use crate::serialize::hugr_file::HugrFileError;
+use crate::serialize::pytket::{OpConvertError, TK1ConvertError};
+use crate::serialize::qiskit::QiskitConvertError;
+use crate::sim::SimulationError;
+use crate::synthesis::SynthesisError;
+use crate::verify::SoundnessError;
+use crate::{CircuitError, CircuitMutError, ControlError};
+
+/// A stable identifier for a kind of [`Tket2Error`].
+///
+/// Codes are grouped by the area of the crate that produces them:
+///
+/// - `E01xx`: circuit construction and manipulation.
+/// - `E02xx`: operation definitions.
+/// - `E03xx`: simulation and verification.
+/// - `E04xx`: compilation passes and resource budgets.
+/// - `E05xx`: circuit serialisation.
+/// - `E06xx`: rewrite rules and rewriters.
+/// - `E07xx`: execution backends.
+/// - `E08xx`: optimisers.
+/// - `E09xx`: I/O.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ErrorCode(u16);
+
+impl ErrorCode {
+    /// The numeric value of the code.
+    #[inline]
+    pub const fn as_u16(self) -> u16 {
+        self.0
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "E{:04}", self.0)
+    }
+}
+
+/// The location in a circuit where an error originated.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ErrorSpan {
+    /// The offending node, if known.
+    pub node: Option<Node>,
+    /// The name of the offending operation, if known.
+    pub op: Option<String>,
+}
+
+impl ErrorSpan {
+    /// A span pointing at a node.
+    pub fn node(node: Node) -> Self {
+        Self {
+            node: Some(node),
+            op: None,
+        }
+    }
+
+    /// A span pointing at an operation in a node.
+    pub fn op(optype: &OpType, node: Node) -> Self {
+        Self {
+            node: Some(node),
+            op: Some(optype.name().to_string()),
+        }
+    }
+
+    /// A span pointing at an operation, without a known location.
+    pub fn optype(optype: &OpType) -> Self {
+        Self {
+            node: None,
+            op: Some(optype.name().to_string()),
+        }
+    }
+
+    /// Returns `true` if the span carries no location information.
+    pub fn is_empty(&self) -> bool {
+        self.node.is_none() && self.op.is_none()
+    }
+}
+
+impl fmt::Display for ErrorSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.node, &self.op) {
+            (Some(node), Some(op)) => write!(f, "{node} ({op})"),
+            (Some(node), None) => write!(f, "{node}"),
+            (None, Some(op)) => write!(f, "{op}"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Any error produced by the tket2 crate.
+///
+/// Wraps the error types defined by each module. Use [`Tket2Error::code`] and
+/// [`Tket2Error::span`] to inspect the failure, or
+/// [`Tket2Error::diagnostic`] to report it.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+#[allow(missing_docs)]
+pub enum Tket2Error {
+    #[error(transparent)]
+    Circuit(#[from] CircuitError),
+    #[error(transparent)]
+    CircuitMut(#[from] CircuitMutError),
+    #[error(transparent)]
+    CommandEdit(#[from] CommandEditError),
+    #[error(transparent)]
+    Dagger(#[from] DaggerError),
+    #[error(transparent)]
+    Permutation(#[from] PermutationError),
+    #[error(transparent)]
+    CompactCircuit(#[from] CompactCircuitError),
+    #[error(transparent)]
+    QuantumValidation(#[from] QuantumValidationError),
+    #[error(transparent)]
+    Instantiation(#[from] InstantiationError),
+    #[error(transparent)]
+    Watermark(#[from] WatermarkError),
+    #[error(transparent)]
+    Hash(#[from] HashError),
+    #[error(transparent)]
+    Control(#[from] ControlError),
+    #[error(transparent)]
+    Simulation(#[from] SimulationError),
+    #[error(transparent)]
+    Soundness(#[from] SoundnessError),
+    #[error(transparent)]
+    Gradient(#[from] GradientError),
+    #[error(transparent)]
+    Rebase(#[from] RebaseError),
+    #[error(transparent)]
+    PullForward(#[from] PullForwardError),
+    #[error(transparent)]
+    PytketLowering(#[from] PytketLoweringError),
+    #[error(transparent)]
+    QubitRemap(#[from] QubitRemapError),
+    #[error(transparent)]
+    TScheduling(#[from] TSchedulingError),
+    #[error(transparent)]
+    ResourceBudget(#[from] BudgetExceeded),
+    #[error(transparent)]
+    Synthesis(#[from] SynthesisError),
+    #[error(transparent)]
+    Lowering(#[from] LoweringError),
+    #[error(transparent)]
+    CompilationCache(#[from] CompilationCacheError),
+    #[error(transparent)]
+    OpConvert(#[from] OpConvertError),
+    #[error(transparent)]
+    TK1Convert(#[from] TK1ConvertError),
+    #[error(transparent)]
+    QiskitConvert(#[from] QiskitConvertError),
+    #[error(transparent)]
+    HugrFile(#[from] HugrFileError),
+    #[error(transparent)]
+    CircuitLoad(#[from] CircuitLoadError),
+    #[cfg(feature = "portmatching")]
+    #[error(transparent)]
+    Rule(#[from] RuleError),
+    #[cfg(feature = "portmatching")]
+    #[error(transparent)]
+    RewriterSerialisation(#[from] RewriterSerialisationError),
+    #[error(transparent)]
+    BatchRewrite(#[from] BatchRewriteError),
+    #[error(transparent)]
+    RewriteBuild(#[from] RewriteBuildError),
+    #[error(transparent)]
+    EqCircClass(#[from] EqCircClassError),
+    #[cfg(feature = "portmatching")]
+    #[error(transparent)]
+    MatcherSerialisation(#[from] MatcherSerialisationError),
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+    #[cfg(feature = "distributed")]
+    #[error(transparent)]
+    Distributed(#[from] DistributedError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl Tket2Error {
+    /// The stable code identifying the kind of error.
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode(match self {
+            Tket2Error::Circuit(_) => 100,
+            Tket2Error::CircuitMut(_) => 101,
+            Tket2Error::CommandEdit(_) => 102,
+            Tket2Error::Dagger(_) => 103,
+            Tket2Error::Permutation(_) => 104,
+            Tket2Error::CompactCircuit(_) => 105,
+            Tket2Error::QuantumValidation(_) => 106,
+            Tket2Error::Instantiation(_) => 107,
+            Tket2Error::Watermark(_) => 108,
+            Tket2Error::Hash(_) => 109,
+            Tket2Error::Control(_) => 200,
+            Tket2Error::Simulation(_) => 300,
+            Tket2Error::Soundness(_) => 301,
+            Tket2Error::Gradient(_) => 302,
+            Tket2Error::Rebase(_) => 400,
+            Tket2Error::PullForward(_) => 401,
+            Tket2Error::PytketLowering(_) => 402,
+            Tket2Error::QubitRemap(_) => 403,
+            Tket2Error::TScheduling(_) => 404,
+            Tket2Error::ResourceBudget(_) => 405,
+            Tket2Error::Synthesis(_) => 406,
+            Tket2Error::Lowering(_) => 407,
+            Tket2Error::CompilationCache(_) => 408,
+            Tket2Error::OpConvert(_) => 500,
+            Tket2Error::TK1Convert(_) => 501,
+            Tket2Error::QiskitConvert(_) => 502,
+            Tket2Error::HugrFile(_) => 503,
+            Tket2Error::CircuitLoad(_) => 504,
+            #[cfg(feature = "portmatching")]
+            Tket2Error::Rule(_) => 600,
+            #[cfg(feature = "portmatching")]
+            Tket2Error::RewriterSerialisation(_) => 601,
+            Tket2Error::BatchRewrite(_) => 602,
+            Tket2Error::RewriteBuild(_) => 603,
+            Tket2Error::EqCircClass(_) => 604,
+            #[cfg(feature = "portmatching")]
+            Tket2Error::MatcherSerialisation(_) => 605,
+            Tket2Error::Backend(_) => 700,
+            #[cfg(feature = "distributed")]
+            Tket2Error::Distributed(_) => 800,
+            Tket2Error::Io(_) => 900,
+        })
+    }
+
+    /// The location in the circuit where the error originated.
+    ///
+    /// Returns an empty span if the error is not tied to a specific operation.
+    pub fn span(&self) -> ErrorSpan {
+        match self {
+            Tket2Error::Circuit(e) => circuit_span(e),
+            Tket2Error::CircuitMut(e) => circuit_mut_span(e),
+            Tket2Error::CommandEdit(e) => match e {
+                CommandEditError::InvalidUnits { optype, .. }
+                | CommandEditError::UnpairedLinearPorts { optype } => ErrorSpan::optype(optype),
+                CommandEditError::NotACommand { node } => ErrorSpan::node(*node),
+                CommandEditError::UsedOutputs { optype, node } => ErrorSpan::op(optype, *node),
+                _ => ErrorSpan::default(),
+            },
+            Tket2Error::Dagger(e) => match e {
+                DaggerError::NonInvertibleOperation { optype, node }
+                | DaggerError::NonConstantParameter { optype, node } => {
+                    ErrorSpan::op(optype, *node)
+                }
+                DaggerError::Extraction(e) => circuit_mut_span(e),
+            },
+            Tket2Error::CompactCircuit(e) => match e {
+                CompactCircuitError::NestedCircuit { parent } => ErrorSpan::node(*parent),
+                CompactCircuitError::UnsupportedOperation { optype, node }
+                | CompactCircuitError::NonConstantParameter { optype, node } => {
+                    ErrorSpan::op(optype, *node)
+                }
+                CompactCircuitError::NodeMetadata { node } => ErrorSpan::node(*node),
+                _ => ErrorSpan::default(),
+            },
+            Tket2Error::QuantumValidation(e) => match e {
+                QuantumValidationError::RepeatedLinearInput { optype, node, .. }
+                | QuantumValidationError::ClonedLinearWire { optype, node, .. }
+                | QuantumValidationError::MeasurementOutput { optype, node, .. }
+                | QuantumValidationError::AngleType { optype, node, .. }
+                | QuantumValidationError::InvalidSignature { optype, node, .. } => {
+                    ErrorSpan::op(optype, *node)
+                }
+            },
+            Tket2Error::Instantiation(InstantiationError::UnresolvedExpression {
+                node, ..
+            }) => ErrorSpan::node(*node),
+            Tket2Error::Simulation(e) => match e {
+                SimulationError::UnsupportedOperation { optype, node }
+                | SimulationError::UnresolvedParameter { optype, node }
+                | SimulationError::NonUnitaryOperation { optype, node }
+                | SimulationError::UnresolvedBitOutput { optype, node } => {
+                    ErrorSpan::op(optype, *node)
+                }
+            },
+            Tket2Error::Gradient(e) => match e {
+                GradientError::UnsupportedOperation { optype, node, .. }
+                | GradientError::UnsupportedExpression { optype, node } => {
+                    ErrorSpan::op(optype, *node)
+                }
+            },
+            Tket2Error::Rebase(RebaseError::UnsupportedGate { optype, node, .. }) => {
+                ErrorSpan::op(optype, *node)
+            }
+            Tket2Error::Lowering(
+                LoweringError::UnsupportedQubits { optype, node, .. }
+                | LoweringError::MissingLowering { optype, node, .. },
+            ) => ErrorSpan::op(optype, *node),
+            Tket2Error::PytketLowering(PytketLoweringError::OpConversionError(e)) => {
+                op_convert_span(e)
+            }
+            Tket2Error::BatchRewrite(BatchRewriteError::Overlap { node }) => ErrorSpan::node(*node),
+            Tket2Error::Backend(BackendError::UnsupportedGate { optype, node, .. }) => {
+                ErrorSpan::op(optype, *node)
+            }
+            Tket2Error::OpConvert(e) => op_convert_span(e),
+            Tket2Error::TK1Convert(e) => tk1_convert_span(e),
+            Tket2Error::QiskitConvert(QiskitConvertError::Decode(e)) => tk1_convert_span(e),
+            Tket2Error::HugrFile(HugrFileError::InvalidCircuit(e)) => circuit_span(e),
+            Tket2Error::CircuitLoad(CircuitLoadError::CircuitLoadError(e)) => circuit_span(e),
+            _ => ErrorSpan::default(),
+        }
+    }
+
+    /// A report of the error, suitable for displaying to users.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let message = self.to_string();
+        let mut causes: Vec<String> = Vec::new();
+        let mut source = std::error::Error::source(self);
+        while let Some(err) = source {
+            let cause = err.to_string();
+            // Many errors already include their source in their message.
+            let last = causes.last().unwrap_or(&message);
+            if !last.contains(&cause) {
+                causes.push(cause);
+            }
+            source = err.source();
+        }
+        Diagnostic {
+            code: self.code(),
+            message,
+            span: self.span(),
+            causes,
+        }
+    }
+}
+
+fn circuit_span(e: &CircuitError) -> ErrorSpan {
+    match e {
+        CircuitError::MissingParentNode { parent } => ErrorSpan::node(*parent),
+        CircuitError::ParametricSignature { parent, optype, .. }
+        | CircuitError::InvalidParentOp { parent, optype } => ErrorSpan::op(optype, *parent),
+    }
+}
+
+fn circuit_mut_span(e: &CircuitMutError) -> ErrorSpan {
+    match e {
+        CircuitMutError::CircuitError(e) => circuit_span(e),
+        _ => ErrorSpan::default(),
+    }
+}
+
+fn op_convert_span(e: &OpConvertError) -> ErrorSpan {
+    match e {
+        OpConvertError::UnsupportedOpSerialization(optype)
+        | OpConvertError::MissingSerialisedParams { optype, .. }
+        | OpConvertError::MissingSerialisedArguments { optype, .. } => ErrorSpan::optype(optype),
+        OpConvertError::UnsupportedInputType { optype, node, .. }
+        | OpConvertError::UnsupportedOutputType { optype, node, .. }
+        | OpConvertError::UnresolvedParamInput { optype, node, .. }
+        | OpConvertError::TooManyOutputQubits { optype, node, .. } => ErrorSpan::op(optype, *node),
+        _ => ErrorSpan::default(),
+    }
+}
+
+fn tk1_convert_span(e: &TK1ConvertError) -> ErrorSpan {
+    match e {
+        TK1ConvertError::OpConversionError(e) => op_convert_span(e),
+        TK1ConvertError::UnsupportedRegion { node } => ErrorSpan::node(*node),
+        _ => ErrorSpan::default(),
+    }
+}
+
+/// A user-facing report of a [`Tket2Error`].
+///
+/// Rendered as
+///
+/// ```text
+/// error[E0300]: Operation Measure in Node(4) is not unitary.
+///   --> Node(4) (Measure)
+/// ```
+///
+/// followed by one `caused by:` line for each underlying error not already
+/// included in the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The error code.
+    pub code: ErrorCode,
+    /// The error message.
+    pub message: String,
+    /// The location of the error in the circuit.
+    pub span: ErrorSpan,
+    /// Messages of the underlying errors, from outermost to innermost.
+    pub causes: Vec<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error[{}]: {}", self.code, self.message)?;
+        if !self.span.is_empty() {
+            write!(f, "\n  --> {}", self.span)?;
+        }
+        for cause in &self.causes {
+            write!(f, "\n  caused by: {cause}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<E: Into<Tket2Error>> From<E> for Diagnostic {
+    fn from(err: E) -> Self {
+        err.into().diagnostic()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::hugr::HugrError;
+    use hugr::ops::OpType;
+    use hugr::Direction;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::passes::t_schedule::TSchedulingError;
+    use crate::Tk2Op;
+
+    fn measure_at(index: usize) -> (OpType, Node) {
+        (
+            Tk2Op::Measure.into(),
+            portgraph::NodeIndex::new(index).into(),
+        )
+    }
+
+    #[test]
+    fn simulation_diagnostic() {
+        let (optype, node) = measure_at(4);
+        let err: Tket2Error = SimulationError::NonUnitaryOperation {
+            optype: optype.clone(),
+            node,
+        }
+        .into();
+
+        assert_eq!(err.code().to_string(), "E0300");
+        assert_eq!(err.span(), ErrorSpan::op(&optype, node));
+
+        let diagnostic = err.diagnostic();
+        assert_eq!(diagnostic.message, err.to_string());
+        assert!(diagnostic.causes.is_empty());
+        assert_eq!(
+            diagnostic.to_string(),
+            format!("error[E0300]: {}\n  --> {node} ({})", err, optype.name())
+        );
+    }
+
+    #[test]
+    fn nested_span() {
+        let (optype, node) = measure_at(7);
+        let err: Tket2Error = TK1ConvertError::from(OpConvertError::UnsupportedInputType {
+            typ: hugr::extension::prelude::USIZE_T,
+            optype: optype.clone(),
+            node,
+        })
+        .into();
+        assert_eq!(err.code(), ErrorCode(501));
+        assert_eq!(err.span(), ErrorSpan::op(&optype, node));
+    }
+
+    #[rstest]
+    #[case::scheduling(TSchedulingError::InvalidProductionRate(-1.).into(), 404)]
+    #[case::io(io::Error::new(io::ErrorKind::NotFound, "missing").into(), 900)]
+    #[case::pull_forward(PullForwardError::NoQbInCommand(2).into(), 401)]
+    #[case::watermark(WatermarkError::InvalidQubit(3.into()).into(), 108)]
+    #[case::hash(HashError::NotADfg.into(), 109)]
+    #[case::eq_circ_class(EqCircClassError::NoRepresentative.into(), 604)]
+    fn unlocated_errors(#[case] err: Tket2Error, #[case] code: u16) {
+        assert_eq!(err.code().as_u16(), code);
+        assert!(err.span().is_empty());
+        assert_eq!(
+            err.diagnostic().to_string(),
+            format!("error[E{code:04}]: {err}")
+        );
+    }
+
+    #[cfg(feature = "portmatching")]
+    #[test]
+    fn matcher_version_error() {
+        let err: Tket2Error = MatcherSerialisationError::UnsupportedVersion {
+            version: 3,
+            supported: 2,
+        }
+        .into();
+        assert_eq!(err.code().as_u16(), 605);
+        assert!(err.span().is_empty());
+    }
+
+    #[cfg(feature = "distributed")]
+    #[test]
+    fn distributed_error() {
+        let err: Tket2Error = DistributedError::UnexpectedResponse {
+            expected: 1,
+            got: 2,
+        }
+        .into();
+        assert_eq!(err.code().as_u16(), 800);
+        assert!(err.span().is_empty());
+    }
+
+    #[test]
+    fn diagnostic_causes() {
+        let hugr_err = HugrError::InvalidPortDirection(Direction::Incoming);
+        let err = DaggerError::from(CircuitMutError::from(hugr_err.clone()));
+        let diagnostic = Diagnostic::from(err);
+        assert_eq!(diagnostic.code.as_u16(), 103);
+        assert!(diagnostic.span.is_empty());
+        assert_eq!(diagnostic.causes, vec![hugr_err.to_string()]);
+        assert!(diagnostic
+            .to_string()
+            .ends_with(&format!("\n  caused by: {hugr_err}")));
+    }
+}
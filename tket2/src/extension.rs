@@ -18,6 +18,18 @@ use smol_str::SmolStr;
 /// Definition for Angle ops and types.
 pub mod angle;
 
+/// Definition of the multi-controlled Pauli operation.
+pub mod controlled;
+pub use controlled::ControlledOp;
+
+/// Definition of bosonic modes and Gaussian operations.
+#[cfg(feature = "bosonic")]
+pub mod bosonic;
+
+/// Definition of the qudit types.
+#[cfg(feature = "qudits")]
+pub mod qudit;
+
 /// The ID of the TKET1 extension.
 pub const TKET1_EXTENSION_ID: ExtensionId = IdentList::new_unchecked("TKET1");
 
@@ -47,11 +59,15 @@ pub static ref TKET1_EXTENSION: Extension = {
 };
 
 /// Extension registry including the prelude, TKET1 and Tk2Ops extensions.
+///
+/// With the `bosonic` feature, this also includes the [`bosonic`] extension.
 pub static ref REGISTRY: ExtensionRegistry = ExtensionRegistry::try_new([
     TKET1_EXTENSION.clone(),
     PRELUDE.clone(),
     TKET2_EXTENSION.clone(),
     FLOAT_EXTENSION.clone(),
+    #[cfg(feature = "bosonic")]
+    bosonic::EXTENSION.clone(),
 ]).unwrap();
 
 
@@ -112,6 +128,9 @@ pub static ref TKET2_EXTENSION: Extension = {
     .unwrap();
 
     angle::add_to_extension(&mut e);
+    controlled::add_to_extension(&mut e);
+    #[cfg(feature = "qudits")]
+    qudit::add_to_extension(&mut e);
     e
 };
 }
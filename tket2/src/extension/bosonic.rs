@@ -0,0 +1,318 @@
+//! This module defines the Hugr extension used to represent continuous-variable
+//! circuits on bosonic modes.
+//!
+//! Modes are linear values, like qubits, and are treated as quantum units by
+//! the circuit machinery. The extension provides the Gaussian operations
+//! commonly used in photonic circuits, parametrised by `float64` values.
+use hugr::{
+    builder::{BuildError, Dataflow},
+    extension::{
+        prelude::PRELUDE,
+        simple_op::{try_from_name, MakeOpDef, MakeRegisteredOp, OpLoadError},
+        ExtensionId, ExtensionRegistry, OpDef, SignatureFunc,
+    },
+    ops::{NamedOp as _, OpType},
+    std_extensions::arithmetic::float_types::{EXTENSION as FLOAT_EXTENSION, FLOAT64_TYPE},
+    type_row,
+    types::{CustomType, Signature, Type, TypeBound},
+    Extension, Wire,
+};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use strum_macros::{EnumIter, EnumString, IntoStaticStr};
+
+/// The ID of the `tket2.bosonic` extension.
+pub const EXTENSION_ID: ExtensionId = ExtensionId::new_unchecked("tket2.bosonic");
+
+/// The name of the bosonic mode type.
+pub const MODE_TYPE_ID: SmolStr = SmolStr::new_inline("mode");
+
+/// The bosonic mode [CustomType].
+pub const MODE_CUSTOM_TYPE: CustomType =
+    CustomType::new_simple(MODE_TYPE_ID, EXTENSION_ID, TypeBound::Any);
+
+/// The bosonic mode [Type].
+pub const MODE_T: Type = Type::new_extension(MODE_CUSTOM_TYPE);
+
+lazy_static! {
+    /// The "tket2.bosonic" extension.
+    pub static ref EXTENSION: Extension = {
+        let mut ext = Extension::new(EXTENSION_ID);
+        ext.add_type(
+            MODE_TYPE_ID,
+            vec![],
+            "a bosonic mode".into(),
+            TypeBound::Any.into(),
+        )
+        .unwrap();
+        GaussianOp::load_all_ops(&mut ext).unwrap();
+        ext
+    };
+
+    /// Extension registry including the "tket2.bosonic" extension and
+    /// dependencies.
+    pub static ref REGISTRY: ExtensionRegistry = ExtensionRegistry::try_new([
+        PRELUDE.to_owned(),
+        FLOAT_EXTENSION.to_owned(),
+        EXTENSION.to_owned(),
+    ]).unwrap();
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Serialize,
+    Deserialize,
+    Hash,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    IntoStaticStr,
+    EnumString,
+)]
+#[non_exhaustive]
+/// Gaussian operations on bosonic modes.
+pub enum GaussianOp {
+    /// Prepare a mode in the vacuum state.
+    Vacuum,
+    /// Discard a mode.
+    Discard,
+    /// Displacement by a complex amplitude, given as a magnitude and a phase.
+    Displace,
+    /// Single-mode squeezing, given as a magnitude and a phase.
+    Squeeze,
+    /// Phase-space rotation by an angle.
+    Rotate,
+    /// Beam splitter on two modes, given as a transmissivity angle and a phase.
+    BeamSplitter,
+    /// Two-mode squeezing, given as a magnitude and a phase.
+    TwoModeSqueeze,
+    /// Homodyne measurement of the quadrature at the given angle.
+    MeasureHomodyne,
+}
+
+impl GaussianOp {
+    /// The number of modes the operation acts on.
+    pub fn mode_count(&self) -> usize {
+        match self {
+            GaussianOp::BeamSplitter | GaussianOp::TwoModeSqueeze => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl MakeOpDef for GaussianOp {
+    fn signature(&self) -> SignatureFunc {
+        use GaussianOp::*;
+        let one_mode_row = type_row![MODE_T];
+        let two_mode_row = type_row![MODE_T, MODE_T];
+        match self {
+            Vacuum => Signature::new(type_row![], one_mode_row),
+            Discard => Signature::new(one_mode_row, type_row![]),
+            Displace | Squeeze => {
+                Signature::new(type_row![MODE_T, FLOAT64_TYPE, FLOAT64_TYPE], one_mode_row)
+            }
+            Rotate => Signature::new(type_row![MODE_T, FLOAT64_TYPE], one_mode_row),
+            BeamSplitter | TwoModeSqueeze => Signature::new(
+                type_row![MODE_T, MODE_T, FLOAT64_TYPE, FLOAT64_TYPE],
+                two_mode_row,
+            ),
+            MeasureHomodyne => Signature::new(
+                type_row![MODE_T, FLOAT64_TYPE],
+                type_row![MODE_T, FLOAT64_TYPE],
+            ),
+        }
+        .into()
+    }
+
+    fn from_def(op_def: &OpDef) -> Result<Self, OpLoadError> {
+        try_from_name(op_def.name(), &EXTENSION_ID)
+    }
+
+    fn extension(&self) -> ExtensionId {
+        EXTENSION_ID
+    }
+}
+
+impl MakeRegisteredOp for GaussianOp {
+    fn extension_id(&self) -> ExtensionId {
+        EXTENSION_ID
+    }
+
+    fn registry<'s, 'r: 's>(&'s self) -> &'r ExtensionRegistry {
+        &REGISTRY
+    }
+}
+
+impl TryFrom<&OpType> for GaussianOp {
+    type Error = OpLoadError;
+    fn try_from(value: &OpType) -> Result<Self, Self::Error> {
+        Self::from_op(
+            value
+                .as_custom_op()
+                .ok_or(OpLoadError::NotMember(value.name().into()))?,
+        )
+    }
+}
+
+/// An extension trait for [Dataflow] providing methods to add
+/// "tket2.bosonic" operations.
+pub trait GaussianOpBuilder: Dataflow {
+    /// Add a "tket2.bosonic.Vacuum" op.
+    fn add_vacuum(&mut self) -> Result<Wire, BuildError> {
+        Ok(self.add_dataflow_op(GaussianOp::Vacuum, [])?.out_wire(0))
+    }
+
+    /// Add a "tket2.bosonic.Discard" op.
+    fn add_discard(&mut self, mode: Wire) -> Result<(), BuildError> {
+        self.add_dataflow_op(GaussianOp::Discard, [mode])?;
+        Ok(())
+    }
+
+    /// Add a "tket2.bosonic.Displace" op.
+    fn add_displace(&mut self, mode: Wire, r: Wire, phi: Wire) -> Result<Wire, BuildError> {
+        Ok(self
+            .add_dataflow_op(GaussianOp::Displace, [mode, r, phi])?
+            .out_wire(0))
+    }
+
+    /// Add a "tket2.bosonic.Squeeze" op.
+    fn add_squeeze(&mut self, mode: Wire, r: Wire, phi: Wire) -> Result<Wire, BuildError> {
+        Ok(self
+            .add_dataflow_op(GaussianOp::Squeeze, [mode, r, phi])?
+            .out_wire(0))
+    }
+
+    /// Add a "tket2.bosonic.Rotate" op.
+    fn add_rotate(&mut self, mode: Wire, phi: Wire) -> Result<Wire, BuildError> {
+        Ok(self
+            .add_dataflow_op(GaussianOp::Rotate, [mode, phi])?
+            .out_wire(0))
+    }
+
+    /// Add a "tket2.bosonic.BeamSplitter" op.
+    fn add_beam_splitter(
+        &mut self,
+        modes: [Wire; 2],
+        theta: Wire,
+        phi: Wire,
+    ) -> Result<[Wire; 2], BuildError> {
+        let [a, b] = modes;
+        Ok(self
+            .add_dataflow_op(GaussianOp::BeamSplitter, [a, b, theta, phi])?
+            .outputs_arr())
+    }
+
+    /// Add a "tket2.bosonic.TwoModeSqueeze" op.
+    fn add_two_mode_squeeze(
+        &mut self,
+        modes: [Wire; 2],
+        r: Wire,
+        phi: Wire,
+    ) -> Result<[Wire; 2], BuildError> {
+        let [a, b] = modes;
+        Ok(self
+            .add_dataflow_op(GaussianOp::TwoModeSqueeze, [a, b, r, phi])?
+            .outputs_arr())
+    }
+
+    /// Add a "tket2.bosonic.MeasureHomodyne" op.
+    ///
+    /// Returns the mode and the measured quadrature value.
+    fn add_measure_homodyne(&mut self, mode: Wire, phi: Wire) -> Result<[Wire; 2], BuildError> {
+        Ok(self
+            .add_dataflow_op(GaussianOp::MeasureHomodyne, [mode, phi])?
+            .outputs_arr())
+    }
+}
+
+impl<D: Dataflow> GaussianOpBuilder for D {}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{DFGBuilder, DataflowHugr};
+    use hugr::ops::OpTrait;
+    use hugr::std_extensions::arithmetic::float_types::ConstF64;
+    use itertools::Itertools;
+    use strum::IntoEnumIterator as _;
+
+    use super::*;
+    use crate::circuit::units::filter;
+    use crate::utils::type_is_linear;
+    use crate::Circuit;
+
+    #[test]
+    fn create_extension() {
+        assert_eq!(EXTENSION.name(), &EXTENSION_ID);
+        assert!(type_is_linear(&MODE_T));
+
+        for o in GaussianOp::iter() {
+            let def = EXTENSION.get_op(&o.name()).unwrap();
+            assert_eq!(GaussianOp::from_def(def), Ok(o));
+
+            let op: OpType = o.to_extension_op().unwrap().into();
+            assert_eq!(GaussianOp::try_from(&op), Ok(o));
+            let sig = op.dataflow_signature().unwrap();
+            let modes = |row: &[Type]| row.iter().filter(|t| *t == &MODE_T).count();
+            let mode_count = modes(sig.input_types()).max(modes(sig.output_types()));
+            assert_eq!(mode_count, o.mode_count());
+        }
+    }
+
+    /// A two-mode squeezed state, mixed on a beam splitter and measured.
+    fn photonic_circuit() -> Circuit {
+        let mut dfg = DFGBuilder::new(Signature::new(
+            type_row![MODE_T, MODE_T],
+            type_row![MODE_T, FLOAT64_TYPE],
+        ))
+        .unwrap();
+        let [a, b] = dfg.input_wires_arr();
+        let r = dfg.add_load_value(ConstF64::new(0.5));
+        let phi = dfg.add_load_value(ConstF64::new(0.0));
+        let theta = dfg.add_load_value(ConstF64::new(std::f64::consts::FRAC_PI_4));
+
+        let [a, b] = dfg.add_two_mode_squeeze([a, b], r, phi).unwrap();
+        let a = dfg.add_rotate(a, theta).unwrap();
+        let [a, b] = dfg.add_beam_splitter([a, b], theta, phi).unwrap();
+        let [b, x] = dfg.add_measure_homodyne(b, phi).unwrap();
+        dfg.add_discard(b).unwrap();
+        let c = dfg.add_vacuum().unwrap();
+        let [a, c] = dfg.add_beam_splitter([a, c], theta, phi).unwrap();
+        dfg.add_discard(c).unwrap();
+
+        dfg.finish_hugr_with_outputs([a, x], &REGISTRY)
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn mode_units() {
+        let circ = photonic_circuit();
+        assert_eq!(circ.qubit_count(), 2);
+        assert_eq!(
+            circ.units()
+                .filter_map(filter::filter_mode)
+                .map(|(u, _, _)| u.index())
+                .collect_vec(),
+            [0, 1]
+        );
+
+        let ops = circ
+            .commands()
+            .filter_map(|cmd| GaussianOp::try_from(cmd.optype()).ok())
+            .collect_vec();
+        assert_eq!(ops.len(), 8);
+
+        let splitters = circ
+            .commands()
+            .filter(|cmd| GaussianOp::try_from(cmd.optype()) == Ok(GaussianOp::BeamSplitter))
+            .map(|cmd| cmd.input_qubits().map(|(u, _, _)| u.index()).collect_vec())
+            .collect_vec();
+        // The second beam splitter acts on the mode allocated by `Vacuum`.
+        assert_eq!(splitters, [vec![0, 1], vec![0, 2]]);
+    }
+}
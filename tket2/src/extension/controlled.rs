@@ -0,0 +1,171 @@
+use std::str::FromStr;
+
+use hugr::extension::prelude::QB_T;
+use hugr::extension::{SignatureError, SignatureFromArgs};
+use hugr::ops::custom::ExtensionOp;
+use hugr::ops::{CustomOp, OpType};
+use hugr::types::type_param::TypeParam;
+use hugr::types::{PolyFuncType, PolyFuncTypeRV, Signature, TypeArg};
+use hugr::Extension;
+use smol_str::SmolStr;
+
+use super::{REGISTRY, TKET2_EXTENSION, TKET2_EXTENSION_ID};
+use crate::Pauli;
+
+/// The name of the multi-controlled Pauli operation.
+pub const CONTROLLED_OP_ID: SmolStr = SmolStr::new_inline("controlled");
+
+/// A Pauli gate controlled by any number of qubits.
+///
+/// The operation acts on the control qubits followed by the target qubit, and
+/// applies the target Pauli when all the controls are in the `|1⟩` state. With
+/// two controls, an `X` target is a [`Tk2Op::CCX`] and a `Z` target is a
+/// [`Tk2Op::CCZ`].
+///
+/// It is defined in the tket2 extension with the number of controls and the
+/// name of the target Pauli as type arguments. Use
+/// [`decompose_controlled_gates`] to convert it into Clifford+T gates.
+///
+/// [`Tk2Op::CCX`]: crate::Tk2Op::CCX
+/// [`Tk2Op::CCZ`]: crate::Tk2Op::CCZ
+/// [`decompose_controlled_gates`]: crate::passes::decompose_controlled_gates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ControlledOp {
+    controls: usize,
+    target: Pauli,
+}
+
+impl ControlledOp {
+    /// Create a new Pauli gate with the given number of controls.
+    pub fn new(controls: usize, target: Pauli) -> Self {
+        Self { controls, target }
+    }
+
+    /// The number of control qubits.
+    pub fn controls(&self) -> usize {
+        self.controls
+    }
+
+    /// The Pauli applied to the target qubit.
+    pub fn target(&self) -> Pauli {
+        self.target
+    }
+
+    /// The number of qubits the operation acts on, including the target.
+    pub fn num_qubits(&self) -> usize {
+        self.controls + 1
+    }
+
+    /// Wraps the operation in an [`ExtensionOp`].
+    pub fn to_extension_op(&self) -> ExtensionOp {
+        let args = vec![
+            TypeArg::BoundedNat {
+                n: self.controls as u64,
+            },
+            TypeArg::String {
+                arg: self.target.to_string(),
+            },
+        ];
+        TKET2_EXTENSION
+            .instantiate_extension_op(&CONTROLLED_OP_ID, args, &REGISTRY)
+            .expect("Failed to convert to extension op.")
+    }
+
+    /// Match a controlled operation, either resolved or opaque.
+    pub fn from_optype(op: &OpType) -> Option<Self> {
+        let OpType::CustomOp(custom_op) = op else {
+            return None;
+        };
+        let args = match custom_op {
+            CustomOp::Extension(e)
+                if e.def().name() == &CONTROLLED_OP_ID
+                    && e.def().extension() == &TKET2_EXTENSION_ID =>
+            {
+                e.args()
+            }
+            CustomOp::Opaque(e)
+                if e.name() == &CONTROLLED_OP_ID && e.extension() == &TKET2_EXTENSION_ID =>
+            {
+                e.args()
+            }
+            _ => return None,
+        };
+        parse_args(args)
+    }
+}
+
+impl From<ControlledOp> for OpType {
+    fn from(op: ControlledOp) -> Self {
+        CustomOp::new_extension(op.to_extension_op()).into()
+    }
+}
+
+/// Read the number of controls and the target of a controlled operation from
+/// its type arguments.
+fn parse_args(args: &[TypeArg]) -> Option<ControlledOp> {
+    let [TypeArg::BoundedNat { n }, TypeArg::String { arg }] = args else {
+        return None;
+    };
+    let target = Pauli::from_str(arg).ok()?;
+    Some(ControlledOp::new(*n as usize, target))
+}
+
+struct ControlledSignature;
+
+const PARAMS: &[TypeParam] = &[TypeParam::max_nat(), TypeParam::String];
+
+impl SignatureFromArgs for ControlledSignature {
+    fn compute_signature(&self, arg_values: &[TypeArg]) -> Result<PolyFuncTypeRV, SignatureError> {
+        let op = parse_args(arg_values).ok_or(SignatureError::InvalidTypeArgs)?;
+        let qubits = vec![QB_T; op.num_qubits()];
+        let poly_func: PolyFuncType = Signature::new(qubits.clone(), qubits).into();
+        Ok(poly_func.into())
+    }
+
+    fn static_params(&self) -> &[TypeParam] {
+        PARAMS
+    }
+}
+
+pub(super) fn add_to_extension(extension: &mut Extension) {
+    extension
+        .add_op(
+            CONTROLLED_OP_ID,
+            "A Pauli gate controlled by any number of qubits.".to_owned(),
+            ControlledSignature,
+        )
+        .unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::ops::OpTrait;
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(0, Pauli::X)]
+    #[case(2, Pauli::Z)]
+    #[case(5, Pauli::Y)]
+    fn controlled_op_roundtrip(#[case] controls: usize, #[case] target: Pauli) {
+        let op = ControlledOp::new(controls, target);
+        let optype: OpType = op.into();
+
+        let sig = optype.dataflow_signature().unwrap();
+        assert_eq!(sig.input_count(), controls + 1);
+        assert_eq!(sig.input_types(), sig.output_types());
+        assert_eq!(ControlledOp::from_optype(&optype), Some(op));
+    }
+
+    #[test]
+    fn invalid_target() {
+        let args = vec![
+            TypeArg::BoundedNat { n: 2 },
+            TypeArg::String { arg: "H".into() },
+        ];
+        assert!(TKET2_EXTENSION
+            .instantiate_extension_op(&CONTROLLED_OP_ID, args, &REGISTRY)
+            .is_err());
+    }
+}
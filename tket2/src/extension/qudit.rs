@@ -0,0 +1,233 @@
+//! Qudit types for circuits on higher-dimensional quantum systems.
+//!
+//! The tket2 extension defines a linear `qudit` type, parametrised by its
+//! dimension. Qudits are treated like qubits by the circuit machinery, so
+//! commands, slices and rewrites can be used on qudit circuits with
+//! operations defined in a user extension.
+//!
+//! Other linear types can be treated as quantum units with
+//! [`register_quantum_type`].
+//!
+//! [`register_quantum_type`]: crate::circuit::units::register_quantum_type
+
+use hugr::extension::prelude::QB_T;
+use hugr::types::type_param::TypeParam;
+use hugr::types::{CustomType, Type, TypeArg, TypeBound, TypeEnum};
+use hugr::Extension;
+use smol_str::SmolStr;
+
+use super::{TKET2_EXTENSION, TKET2_EXTENSION_ID};
+
+/// Identifier for the qudit type.
+pub const QUDIT_TYPE_ID: SmolStr = SmolStr::new_inline("qudit");
+
+/// Type parameter for the dimension of a qudit.
+pub const DIMENSION_TYPE_PARAM: TypeParam = TypeParam::max_nat();
+
+/// The qudit type with the given dimension.
+///
+/// # Panics
+///
+/// Panics if the dimension is smaller than 2.
+pub fn qudit_custom_type(dimension: u64) -> CustomType {
+    assert!(dimension >= 2, "Qudits must have at least two levels.");
+    TKET2_EXTENSION
+        .get_type(&QUDIT_TYPE_ID)
+        .unwrap()
+        .instantiate([TypeArg::BoundedNat { n: dimension }])
+        .unwrap()
+}
+
+/// The qudit type with the given dimension, as a [`Type`].
+///
+/// # Panics
+///
+/// Panics if the dimension is smaller than 2.
+pub fn qudit_type(dimension: u64) -> Type {
+    Type::new_extension(qudit_custom_type(dimension))
+}
+
+/// The qutrit type, a qudit with three levels.
+pub fn qutrit_type() -> Type {
+    qudit_type(3)
+}
+
+/// Returns the dimension of a quantum type, if it is a qubit or a qudit.
+pub fn qudit_dimension(typ: &Type) -> Option<u64> {
+    if typ == &QB_T {
+        return Some(2);
+    }
+    let TypeEnum::Extension(custom) = typ.as_type_enum() else {
+        return None;
+    };
+    if custom.extension() != &TKET2_EXTENSION_ID || custom.name() != &QUDIT_TYPE_ID {
+        return None;
+    }
+    match custom.args() {
+        [TypeArg::BoundedNat { n }] => Some(*n),
+        _ => None,
+    }
+}
+
+pub(super) fn add_to_extension(extension: &mut Extension) {
+    extension
+        .add_type(
+            QUDIT_TYPE_ID,
+            vec![DIMENSION_TYPE_PARAM],
+            "a quantum system with the given number of levels".to_owned(),
+            TypeBound::Any.into(),
+        )
+        .unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::extension::prelude::PRELUDE;
+    use hugr::extension::{ExtensionId, ExtensionRegistry};
+    use hugr::ops::custom::ExtensionOp;
+    use hugr::ops::OpType;
+    use hugr::std_extensions::arithmetic::float_types::EXTENSION as FLOAT_EXTENSION;
+    use hugr::types::Signature;
+    use itertools::Itertools;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::circuit::units::{is_quantum_type, register_quantum_type};
+    use crate::rewrite::Subcircuit;
+    use crate::utils::type_is_linear;
+    use crate::Circuit;
+
+    const QUTRIT_EXT: ExtensionId = ExtensionId::new_unchecked("test.qutrit");
+
+    fn qutrit_extension() -> Extension {
+        let mut ext = Extension::new(QUTRIT_EXT);
+        let qt = qutrit_type();
+        ext.add_op(
+            "X01".into(),
+            "Swap levels 0 and 1".to_owned(),
+            Signature::new_endo(vec![qt.clone()]),
+        )
+        .unwrap();
+        ext.add_op(
+            "CSUM".into(),
+            "Controlled sum".to_owned(),
+            Signature::new_endo(vec![qt.clone(), qt]),
+        )
+        .unwrap();
+        ext
+    }
+
+    fn registry() -> ExtensionRegistry {
+        ExtensionRegistry::try_new([
+            PRELUDE.clone(),
+            FLOAT_EXTENSION.clone(),
+            TKET2_EXTENSION.clone(),
+            qutrit_extension(),
+        ])
+        .unwrap()
+    }
+
+    fn qutrit_op(name: &str) -> OpType {
+        let ext = qutrit_extension();
+        let def = ext.get_op(name).unwrap().clone();
+        ExtensionOp::new(def, [], &registry()).unwrap().into()
+    }
+
+    /// A circuit applying `X01` to both qutrits, then `CSUM`.
+    fn qutrit_circuit() -> Circuit {
+        let qt = qutrit_type();
+        let mut dfg = DFGBuilder::new(Signature::new_endo(vec![qt.clone(), qt])).unwrap();
+        let [a, b] = dfg.input_wires_arr();
+        let [a] = dfg
+            .add_dataflow_op(qutrit_op("X01"), [a])
+            .unwrap()
+            .outputs_arr();
+        let [b] = dfg
+            .add_dataflow_op(qutrit_op("X01"), [b])
+            .unwrap()
+            .outputs_arr();
+        let [a, b] = dfg
+            .add_dataflow_op(qutrit_op("CSUM"), [a, b])
+            .unwrap()
+            .outputs_arr();
+        dfg.finish_hugr_with_outputs([a, b], &registry())
+            .unwrap()
+            .into()
+    }
+
+    #[rstest]
+    #[case(QB_T, Some(2))]
+    #[case(qutrit_type(), Some(3))]
+    #[case(qudit_type(5), Some(5))]
+    #[case(hugr::extension::prelude::BOOL_T, None)]
+    fn dimension(#[case] typ: Type, #[case] expected: Option<u64>) {
+        assert_eq!(qudit_dimension(&typ), expected);
+        assert_eq!(is_quantum_type(&typ), expected.is_some());
+    }
+
+    #[test]
+    fn qudits_are_linear() {
+        assert!(type_is_linear(&qutrit_type()));
+    }
+
+    #[test]
+    fn registered_type() {
+        let spin = Type::new_extension(CustomType::new("spin", [], QUTRIT_EXT, TypeBound::Any));
+        assert!(!is_quantum_type(&spin));
+        register_quantum_type(spin.clone());
+        assert!(is_quantum_type(&spin));
+        assert_eq!(qudit_dimension(&spin), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "copyable")]
+    fn register_copyable_type() {
+        register_quantum_type(hugr::extension::prelude::BOOL_T);
+    }
+
+    #[test]
+    fn qutrit_commands() {
+        let circ = qutrit_circuit();
+        assert_eq!(circ.qubit_count(), 2);
+
+        let commands = circ.commands().collect_vec();
+        assert_eq!(commands.len(), 3);
+        let units = commands
+            .iter()
+            .map(|cmd| cmd.input_qubits().map(|(u, _, _)| u.index()).collect_vec())
+            .collect_vec();
+        assert_eq!(
+            units.iter().sorted().collect_vec(),
+            [&[0], &[0, 1][..], &[1]]
+        );
+    }
+
+    #[test]
+    fn qutrit_rewrite() {
+        let mut circ = qutrit_circuit();
+        let x01 = circ
+            .commands()
+            .filter(|cmd| cmd.input_qubits().count() == 1)
+            .map(|cmd| cmd.node())
+            .collect_vec();
+
+        // Remove the `X01` on the first qutrit.
+        let qt = qutrit_type();
+        let identity = DFGBuilder::new(Signature::new_endo(vec![qt])).unwrap();
+        let [q] = identity.input_wires_arr();
+        let identity: Circuit = identity
+            .finish_hugr_with_outputs([q], &registry())
+            .unwrap()
+            .into();
+        let subcirc = Subcircuit::try_from_nodes([x01[0]], &circ).unwrap();
+        subcirc
+            .create_rewrite(&circ, identity)
+            .unwrap()
+            .apply(&mut circ)
+            .unwrap();
+
+        assert_eq!(circ.commands().count(), 2);
+        assert_eq!(circ.qubit_count(), 2);
+    }
+}
@@ -0,0 +1,497 @@
+//! Gradients of parametric circuits with the parameter-shift rule.
+//!
+//! The free parameters of a circuit are its float inputs and the symbolic
+//! constants defined with [`symbolic_constant_op`]. [`parameter_shift`] finds
+//! every rotation whose angle depends on a parameter, and produces a
+//! [`GradientPlan`] listing the shifted circuits needed to compute the
+//! gradient of an expectation value.
+//!
+//! For a rotation `exp(-iθ/2 P)` by a Pauli operator `P`, the derivative of
+//! an expectation value `E` with respect to the angle is
+//! `(E(θ + π/2) - E(θ - π/2)) / 2`. The derivative with respect to a
+//! parameter sums this over each rotation depending on it, following the
+//! chain rule through [`Tk2Op::AngleAdd`] operations.
+//!
+//! [`symbolic_constant_op`]: crate::symbolic_constant_op
+
+use std::collections::BTreeMap;
+use std::f64::consts::FRAC_PI_2;
+use std::fmt;
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::ops::{NamedOp, OpTrait, OpType};
+use hugr::std_extensions::arithmetic::float_types::FLOAT64_TYPE;
+use hugr::{HugrView, IncomingPort, Node, PortIndex, Wire};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::ops::match_symb_const_op;
+use crate::utils::load_float;
+use crate::{Circuit, Tk2Op};
+
+/// The shift applied to a rotation angle, in radians.
+pub const PARAMETER_SHIFT: f64 = FRAC_PI_2;
+
+/// A free parameter of a circuit.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Parameter {
+    /// A float input of the circuit, given by its input port index.
+    Input(usize),
+    /// A symbolic constant, given by its expression.
+    Symbol(String),
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Parameter::Input(index) => write!(f, "input {index}"),
+            Parameter::Symbol(symbol) => write!(f, "{symbol}"),
+        }
+    }
+}
+
+/// The circuits required to compute the gradient of an expectation value.
+#[derive(Debug, Clone, Default)]
+pub struct GradientPlan {
+    /// The gradient terms of each free parameter, sorted by parameter.
+    pub gradients: Vec<ParameterGradient>,
+}
+
+impl GradientPlan {
+    /// The free parameters of the circuit, in the order of [`Self::gradients`].
+    pub fn parameters(&self) -> impl Iterator<Item = &Parameter> + '_ {
+        self.gradients.iter().map(|g| &g.parameter)
+    }
+
+    /// The gradient terms of a parameter, if it is a free parameter of the
+    /// circuit.
+    pub fn get(&self, parameter: &Parameter) -> Option<&ParameterGradient> {
+        self.gradients.iter().find(|g| &g.parameter == parameter)
+    }
+
+    /// The total number of shifted circuits to evaluate.
+    pub fn circuit_count(&self) -> usize {
+        self.gradients.iter().map(|g| g.terms.len()).sum()
+    }
+
+    /// Compute the gradient, given a function evaluating the expectation
+    /// value of a circuit.
+    ///
+    /// Returns the partial derivative for each parameter, in the order of
+    /// [`Self::gradients`].
+    pub fn evaluate<E>(
+        &self,
+        mut expectation: impl FnMut(&Circuit) -> Result<f64, E>,
+    ) -> Result<Vec<f64>, E> {
+        self.gradients
+            .iter()
+            .map(|g| {
+                g.terms
+                    .iter()
+                    .map(|term| Ok(term.coefficient * expectation(&term.circuit)?))
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// The gradient terms for a single parameter.
+#[derive(Debug, Clone)]
+pub struct ParameterGradient {
+    /// The free parameter.
+    pub parameter: Parameter,
+    /// The shifted circuits, whose weighted expectation values sum to the
+    /// partial derivative.
+    pub terms: Vec<ShiftedCircuit>,
+}
+
+/// A copy of the circuit with a single rotation angle shifted.
+#[derive(Debug, Clone)]
+pub struct ShiftedCircuit {
+    /// The rotation in the original circuit.
+    pub node: Node,
+    /// The angle input of the rotation.
+    pub port: IncomingPort,
+    /// The shift added to the angle, in radians.
+    pub shift: f64,
+    /// The weight of the circuit's expectation value in the derivative.
+    pub coefficient: f64,
+    /// The shifted circuit.
+    ///
+    /// Nodes of the original circuit keep their indices.
+    pub circuit: Circuit,
+}
+
+/// Compute the shifted circuits required to differentiate a circuit with
+/// respect to its free parameters.
+///
+/// # Errors
+///
+/// Returns an error if a parameter is used by an operation that does not
+/// follow the parameter-shift rule, or in an expression other than a sum of
+/// angles.
+pub fn parameter_shift(circ: &Circuit) -> Result<GradientPlan, GradientError> {
+    let mut gradients: BTreeMap<Parameter, Vec<ShiftedCircuit>> = BTreeMap::new();
+    for cmd in circ.commands() {
+        if cmd.input_qubits().next().is_none() {
+            // Classical operations are followed from the rotations using them.
+            continue;
+        }
+        let node = cmd.node();
+        let optype = cmd.optype();
+        let Some(sig) = optype.dataflow_signature() else {
+            continue;
+        };
+        for (port, typ) in sig.input_types().iter().enumerate() {
+            if typ != &FLOAT64_TYPE {
+                continue;
+            }
+            let port = IncomingPort::from(port);
+            let Some((src, src_port)) = circ.hugr().single_linked_output(node, port) else {
+                continue;
+            };
+            let parameters = parameter_sources(circ, Wire::new(src, src_port))?;
+            if parameters.is_empty() {
+                continue;
+            }
+            let shiftable = Tk2Op::try_from(optype).is_ok_and(|op| is_shiftable(op, port));
+            if !shiftable {
+                return Err(GradientError::UnsupportedOperation {
+                    optype: optype.clone(),
+                    node,
+                    port,
+                });
+            }
+            for (parameter, multiplicity) in parameters {
+                let terms = gradients.entry(parameter).or_default();
+                for sign in [1., -1.] {
+                    let shift = sign * PARAMETER_SHIFT;
+                    terms.push(ShiftedCircuit {
+                        node,
+                        port,
+                        shift,
+                        coefficient: sign * multiplicity as f64 / 2.,
+                        circuit: shifted_circuit(circ, node, port, shift),
+                    });
+                }
+            }
+        }
+    }
+    let gradients = gradients
+        .into_iter()
+        .map(|(parameter, terms)| ParameterGradient { parameter, terms })
+        .collect();
+    Ok(GradientPlan { gradients })
+}
+
+/// Returns `true` if the angle input of an operation is the angle of a
+/// Pauli rotation `exp(-iθ/2 P)`.
+fn is_shiftable(op: Tk2Op, port: IncomingPort) -> bool {
+    use Tk2Op::*;
+    match op {
+        RzF64 | RxF64 => port.index() == 1,
+        ZZPhase | XXPhase | YYPhase => port.index() == 2,
+        // PhasedX(θ, φ) = Rz(φ) Rx(θ) Rz(-φ), the phase is used twice.
+        PhasedX => port.index() == 1,
+        // TK1(a, b, c) = Rz(a) Rx(b) Rz(c)
+        TK1 => (1..=3).contains(&port.index()),
+        _ => false,
+    }
+}
+
+/// The free parameters a float wire depends on, with the number of times each
+/// one is added to its value.
+fn parameter_sources(
+    circ: &Circuit,
+    wire: Wire,
+) -> Result<BTreeMap<Parameter, usize>, GradientError> {
+    let hugr = circ.hugr();
+    let node = wire.node();
+    let optype = hugr.get_optype(node);
+    if node == circ.input_node() {
+        return Ok(BTreeMap::from([(
+            Parameter::Input(wire.source().index()),
+            1,
+        )]));
+    }
+    if let Some(symbol) = match_symb_const_op(optype) {
+        return Ok(BTreeMap::from([(Parameter::Symbol(symbol), 1)]));
+    }
+
+    let mut parameters: BTreeMap<Parameter, usize> = BTreeMap::new();
+    let Some(sig) = optype.dataflow_signature() else {
+        return Ok(parameters);
+    };
+    for (port, typ) in sig.input_types().iter().enumerate() {
+        if typ != &FLOAT64_TYPE {
+            continue;
+        }
+        let Some((src, src_port)) = hugr.single_linked_output(node, port) else {
+            continue;
+        };
+        for (parameter, count) in parameter_sources(circ, Wire::new(src, src_port))? {
+            *parameters.entry(parameter).or_default() += count;
+        }
+    }
+    let is_sum = matches!(optype, OpType::LoadConstant(_)) || op_is(optype, Tk2Op::AngleAdd);
+    if !is_sum && !parameters.is_empty() {
+        return Err(GradientError::UnsupportedExpression {
+            optype: optype.clone(),
+            node,
+        });
+    }
+    Ok(parameters)
+}
+
+fn op_is(optype: &OpType, op: Tk2Op) -> bool {
+    Tk2Op::try_from(optype) == Ok(op)
+}
+
+/// Copy the circuit, adding `shift` to the angle input of a rotation.
+fn shifted_circuit(circ: &Circuit, node: Node, port: IncomingPort, shift: f64) -> Circuit {
+    let mut shifted = circ.clone();
+    let parent = shifted.parent();
+    let hugr = shifted.hugr_mut();
+    let (src, src_port) = hugr.single_linked_output(node, port).unwrap();
+    let offset = load_float(hugr, parent, shift);
+    let add = hugr.add_node_with_parent(parent, Tk2Op::AngleAdd);
+    hugr.disconnect(node, port);
+    hugr.connect(src, src_port, add, 0);
+    hugr.connect(offset, 0, add, 1);
+    hugr.connect(add, 0, node, port);
+    shifted
+}
+
+/// Error from computing a [`GradientPlan`].
+#[derive(Debug, Clone, Error, PartialEq)]
+#[non_exhaustive]
+pub enum GradientError {
+    /// A parameter is used by an operation that does not follow the
+    /// parameter-shift rule.
+    #[error("Cannot differentiate the parameter on port {port} of {} at {node}.", optype.name())]
+    UnsupportedOperation {
+        /// The operation.
+        optype: OpType,
+        /// The node.
+        node: Node,
+        /// The angle input depending on a parameter.
+        port: IncomingPort,
+    },
+    /// A parameter is used in an expression that is not a sum of angles.
+    #[error("Cannot differentiate a parameter through {} at {node}.", optype.name())]
+    UnsupportedExpression {
+        /// The operation computing the expression.
+        optype: OpType,
+        /// The node.
+        node: Node,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::extension::prelude::QB_T;
+    use hugr::extension::ExtensionRegistry;
+    use hugr::std_extensions::arithmetic::float_ops::{self, FloatOps};
+    use hugr::std_extensions::arithmetic::float_types::ConstF64;
+    use hugr::types::Signature;
+    use hugr::{CircuitUnit, HugrView};
+    use itertools::Itertools;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::sim::simulate_statevector;
+    use crate::symbolic_constant_op;
+    use crate::utils::build_simple_circuit;
+
+    /// Replace the symbolic constants of a circuit by the given value.
+    fn bind(circ: &Circuit, value: f64) -> Circuit {
+        let mut circ = circ.clone();
+        let parent = circ.parent();
+        let symbols = circ
+            .hugr()
+            .children(parent)
+            .filter(|&n| match_symb_const_op(circ.hugr().get_optype(n)).is_some())
+            .collect_vec();
+        let hugr = circ.hugr_mut();
+        for symbol in symbols {
+            let targets = hugr.linked_inputs(symbol, 0).collect_vec();
+            hugr.remove_node(symbol);
+            let load = load_float(hugr, parent, value);
+            for (target, port) in targets {
+                hugr.connect(load, 0, target, port);
+            }
+        }
+        circ
+    }
+
+    /// The expectation value of `Z` on the first qubit.
+    fn expect_z(circ: &Circuit) -> f64 {
+        let state = simulate_statevector(circ).unwrap();
+        1. - 2. * state.probability_one(0.into())
+    }
+
+    /// `Rx(θ) Rz(c) Rx(θ + θ)` on a single qubit, with a symbolic `θ`.
+    fn symbolic_circuit(c: f64) -> Circuit {
+        let mut dfg = DFGBuilder::new(Signature::new_endo(vec![QB_T])).unwrap();
+        let [q] = dfg.input_wires_arr();
+        let [theta] = dfg
+            .add_dataflow_op(symbolic_constant_op("θ".to_string()), [])
+            .unwrap()
+            .outputs_arr();
+        let c = dfg.add_load_value(ConstF64::new(c));
+        let [double] = dfg
+            .add_dataflow_op(Tk2Op::AngleAdd, [theta, theta])
+            .unwrap()
+            .outputs_arr();
+        let [q] = dfg
+            .add_dataflow_op(Tk2Op::RxF64, [q, theta])
+            .unwrap()
+            .outputs_arr();
+        let [q] = dfg
+            .add_dataflow_op(Tk2Op::RzF64, [q, c])
+            .unwrap()
+            .outputs_arr();
+        let [q] = dfg
+            .add_dataflow_op(Tk2Op::RxF64, [q, double])
+            .unwrap()
+            .outputs_arr();
+        dfg.finish_hugr_with_outputs([q], &REGISTRY).unwrap().into()
+    }
+
+    #[rstest]
+    #[case(0.3, 0.)]
+    #[case(-1.2, 0.)]
+    #[case(0.7, 0.4)]
+    fn symbolic_gradient(#[case] theta: f64, #[case] c: f64) {
+        let circ = symbolic_circuit(c);
+        let plan = parameter_shift(&circ).unwrap();
+        assert_eq!(
+            plan.parameters().collect_vec(),
+            [&Parameter::Symbol("θ".to_string())]
+        );
+        assert_eq!(plan.circuit_count(), 4);
+
+        let [gradient] = plan
+            .evaluate(|c| Ok::<_, Infallible>(expect_z(&bind(c, theta))))
+            .unwrap()[..]
+        else {
+            panic!("Expected a single parameter.")
+        };
+
+        // Central finite difference on the original circuit.
+        let eps = 1e-6;
+        let expected = (expect_z(&bind(&circ, theta + eps)) - expect_z(&bind(&circ, theta - eps)))
+            / (2. * eps);
+        assert!(
+            (gradient - expected).abs() < 1e-6,
+            "{gradient} != {expected}"
+        );
+    }
+
+    #[test]
+    fn input_parameters() {
+        let mut dfg = DFGBuilder::new(Signature::new(
+            vec![QB_T, QB_T, FLOAT64_TYPE, FLOAT64_TYPE],
+            vec![QB_T, QB_T],
+        ))
+        .unwrap();
+        let [a, b, x, y] = dfg.input_wires_arr();
+        let [a, b] = dfg
+            .add_dataflow_op(Tk2Op::ZZPhase, [a, b, y])
+            .unwrap()
+            .outputs_arr();
+        let [a] = dfg
+            .add_dataflow_op(Tk2Op::TK1, [a, x, y, x])
+            .unwrap()
+            .outputs_arr();
+        let circ: Circuit = dfg
+            .finish_hugr_with_outputs([a, b], &REGISTRY)
+            .unwrap()
+            .into();
+
+        let plan = parameter_shift(&circ).unwrap();
+        assert_eq!(
+            plan.parameters().collect_vec(),
+            [&Parameter::Input(2), &Parameter::Input(3)]
+        );
+        assert_eq!(plan.get(&Parameter::Input(2)).unwrap().terms.len(), 4);
+        assert_eq!(plan.get(&Parameter::Input(3)).unwrap().terms.len(), 4);
+
+        let term = &plan.gradients[0].terms[0];
+        assert_eq!(term.shift, PARAMETER_SHIFT);
+        assert_eq!(term.coefficient, 0.5);
+        let shifted = &term.circuit;
+        assert_eq!(shifted.hugr().validate(&REGISTRY), Ok(()));
+        let (src, _) = shifted
+            .hugr()
+            .single_linked_output(term.node, term.port)
+            .unwrap();
+        assert!(op_is(shifted.hugr().get_optype(src), Tk2Op::AngleAdd));
+    }
+
+    #[test]
+    fn no_parameters() {
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            let angle = circ.add_constant(ConstF64::new(0.5));
+            circ.append_and_consume(
+                Tk2Op::RzF64,
+                [CircuitUnit::Linear(1), CircuitUnit::Wire(angle)],
+            )?;
+            Ok(())
+        })
+        .unwrap();
+        let plan = parameter_shift(&circ).unwrap();
+        assert_eq!(plan.circuit_count(), 0);
+        assert_eq!(plan.evaluate(|_| Err(())), Ok(vec![]));
+    }
+
+    #[test]
+    fn unsupported_operation() {
+        let mut dfg =
+            DFGBuilder::new(Signature::new(vec![QB_T, FLOAT64_TYPE], vec![QB_T])).unwrap();
+        let [q, phi] = dfg.input_wires_arr();
+        let theta = dfg.add_load_value(ConstF64::new(0.5));
+        let [q] = dfg
+            .add_dataflow_op(Tk2Op::PhasedX, [q, theta, phi])
+            .unwrap()
+            .outputs_arr();
+        let circ: Circuit = dfg.finish_hugr_with_outputs([q], &REGISTRY).unwrap().into();
+        assert!(matches!(
+            parameter_shift(&circ),
+            Err(GradientError::UnsupportedOperation { port, .. }) if port.index() == 2
+        ));
+    }
+
+    #[test]
+    fn unsupported_expression() {
+        let registry = ExtensionRegistry::try_new(
+            REGISTRY
+                .iter()
+                .map(|(_, ext)| ext.clone())
+                .chain([float_ops::EXTENSION.clone()]),
+        )
+        .unwrap();
+        let mut dfg =
+            DFGBuilder::new(Signature::new(vec![QB_T, FLOAT64_TYPE], vec![QB_T])).unwrap();
+        let [q, theta] = dfg.input_wires_arr();
+        let [theta] = dfg
+            .add_dataflow_op(FloatOps::fneg, [theta])
+            .unwrap()
+            .outputs_arr();
+        let [q] = dfg
+            .add_dataflow_op(Tk2Op::RzF64, [q, theta])
+            .unwrap()
+            .outputs_arr();
+        let circ: Circuit = dfg.finish_hugr_with_outputs([q], &registry).unwrap().into();
+        assert!(matches!(
+            parameter_shift(&circ),
+            Err(GradientError::UnsupportedExpression { .. })
+        ));
+    }
+}
@@ -20,11 +20,13 @@
 //!
 #![cfg_attr(not(miri), doc = "```")] // this doctest reads from the filesystem, so it fails with miri
 #![cfg_attr(miri, doc = "```ignore")]
+//! use tket2::serialize::{load_tk1_json_file, DecodeOptions};
 //! use tket2::Circuit;
 //! use hugr::HugrView;
 //!
 //! // Load a tket1 circuit.
-//! let mut circ: Circuit = tket2::serialize::load_tk1_json_file("../test_files/barenco_tof_5.json").unwrap();
+//! let mut circ: Circuit =
+//!     load_tk1_json_file("../test_files/barenco_tof_5.json", DecodeOptions::default()).unwrap();
 //!
 //! assert_eq!(circ.qubit_count(), 9);
 //! assert_eq!(circ.num_operations(), 170);
@@ -43,13 +45,25 @@
 //!
 //! [quantinuum-hugr]: https://lib.rs/crates/quantinuum-hugr
 
+pub mod backend;
 pub mod circuit;
+pub mod compile;
+pub mod error;
 pub mod extension;
+pub mod gradient;
+pub mod memory;
 pub(crate) mod ops;
 pub mod optimiser;
 pub mod passes;
+pub mod resource;
 pub mod rewrite;
+pub mod seed;
 pub mod serialize;
+pub mod sim;
+pub mod synthesis;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod verify;
 
 #[cfg(feature = "portmatching")]
 pub mod portmatching;
@@ -57,5 +71,13 @@ pub mod portmatching;
 mod utils;
 
 pub use circuit::{Circuit, CircuitError, CircuitMutError};
+pub use error::Tket2Error;
 pub use hugr::Hugr;
-pub use ops::{op_matches, symbolic_constant_op, Pauli, Tk2Op};
+pub use ops::{
+    append_controlled, controlled, op_commutation, op_matches, set_op_commutation,
+    symbolic_constant_op, symbolic_expr_op, ControlError, Pauli, SymbolicExpr, Tk2Op,
+    COMMUTATION_KEY,
+};
+
+/// The version of the `tket2` crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
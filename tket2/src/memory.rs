@@ -0,0 +1,219 @@
+//! Peak memory accounting for the phases of an optimisation run.
+//!
+//! Memory usage is only measured when [`TrackingAllocator`] is installed as the
+//! global allocator of the final binary:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOC: tket2::memory::TrackingAllocator = tket2::memory::TrackingAllocator::system();
+//! ```
+//!
+//! The library then records the highest memory usage reached while building
+//! pattern matchers, searching for optimised circuits, and serialising
+//! results. These high-water marks are collected in a [`MemoryReport`], see
+//! [`memory_report`].
+//!
+//! Without the tracking allocator, no measurements are taken and the report is
+//! empty.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The number of bytes currently allocated through the [`TrackingAllocator`].
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+/// The highest value of [`CURRENT`] since the last reset.
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+/// The high-water marks of each phase.
+static REPORT: Mutex<MemoryReport> = Mutex::new(MemoryReport {
+    phases: BTreeMap::new(),
+});
+
+/// A global allocator wrapper that keeps track of the current and peak memory
+/// usage.
+///
+/// Wraps another allocator, [`System`] by default.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TrackingAllocator<A = System> {
+    inner: A,
+}
+
+impl TrackingAllocator {
+    /// Track the allocations of the system allocator.
+    pub const fn system() -> Self {
+        Self { inner: System }
+    }
+}
+
+impl<A> TrackingAllocator<A> {
+    /// Track the allocations of another allocator.
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+
+    #[inline]
+    fn add(&self, size: usize) {
+        let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(current, Ordering::Relaxed);
+    }
+
+    #[inline]
+    fn sub(&self, size: usize) {
+        CURRENT.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.add(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.add(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.sub(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            self.sub(layout.size());
+            self.add(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// The number of bytes currently allocated.
+///
+/// Always returns `0` if the [`TrackingAllocator`] is not installed.
+pub fn current_usage() -> usize {
+    CURRENT.load(Ordering::Relaxed)
+}
+
+/// The peak number of bytes allocated since the start of the program.
+///
+/// Always returns `0` if the [`TrackingAllocator`] is not installed.
+pub fn peak_usage() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+/// A phase of an optimisation run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Phase {
+    /// Building or loading a pattern matcher.
+    MatcherBuild,
+    /// Searching for optimised circuits.
+    Search,
+    /// Serialising rewriters or circuits.
+    Serialisation,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::MatcherBuild => write!(f, "matcher build"),
+            Phase::Search => write!(f, "search"),
+            Phase::Serialisation => write!(f, "serialisation"),
+        }
+    }
+}
+
+/// The peak memory usage of each phase of a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    phases: BTreeMap<Phase, usize>,
+}
+
+impl MemoryReport {
+    /// The peak number of bytes allocated during a phase, if it was measured.
+    pub fn peak(&self, phase: Phase) -> Option<usize> {
+        self.phases.get(&phase).copied()
+    }
+
+    /// The measured phases and their peak number of allocated bytes.
+    pub fn phases(&self) -> impl Iterator<Item = (Phase, usize)> + '_ {
+        self.phases.iter().map(|(&p, &b)| (p, b))
+    }
+
+    /// Returns `true` if no phase was measured.
+    pub fn is_empty(&self) -> bool {
+        self.phases.is_empty()
+    }
+
+    fn record(&mut self, phase: Phase, bytes: usize) {
+        let peak = self.phases.entry(phase).or_default();
+        *peak = (*peak).max(bytes);
+    }
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (phase, bytes) in self.phases() {
+            let mb = bytes as f64 / (1024.0 * 1024.0);
+            writeln!(f, "Peak memory usage ({phase}): {mb:.2} MB")?;
+        }
+        Ok(())
+    }
+}
+
+/// The peak memory usage of the phases measured so far.
+pub fn memory_report() -> MemoryReport {
+    REPORT.lock().unwrap().clone()
+}
+
+/// Clear the measurements collected so far.
+pub fn reset_memory_report() {
+    *REPORT.lock().unwrap() = MemoryReport::default();
+}
+
+/// Run `f`, recording the peak memory usage reached while it runs as part of
+/// `phase`.
+///
+/// Phases may be nested, in which case the allocations of the inner phase
+/// count towards both phases.
+pub(crate) fn track_phase<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    // Temporarily lower the peak to the current usage, so that we can measure
+    // the highest usage reached by `f`.
+    let outer_peak = PEAK.swap(current_usage(), Ordering::Relaxed);
+    let result = f();
+    let phase_peak = PEAK.fetch_max(outer_peak, Ordering::Relaxed);
+    if phase_peak > 0 {
+        REPORT.lock().unwrap().record(phase, phase_peak);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report_keeps_highest_peak() {
+        let mut report = MemoryReport::default();
+        assert!(report.is_empty());
+        report.record(Phase::Search, 10);
+        report.record(Phase::Search, 5);
+        report.record(Phase::MatcherBuild, 3);
+        assert_eq!(report.peak(Phase::Search), Some(10));
+        assert_eq!(report.peak(Phase::Serialisation), None);
+        assert_eq!(
+            report.phases().collect::<Vec<_>>(),
+            [(Phase::MatcherBuild, 3), (Phase::Search, 10)]
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use crate::extension::{
     SYM_OP_ID, TKET2_EXTENSION as EXTENSION, TKET2_EXTENSION_ID as EXTENSION_ID,
 };
+use hugr::hugr::hugrmut::HugrMut;
 use hugr::ops::custom::ExtensionOp;
 use hugr::ops::NamedOp;
 use hugr::{
@@ -14,6 +15,7 @@ use hugr::{
     type_row,
     types::{type_param::TypeArg, Signature},
 };
+use hugr::{HugrView, Node};
 
 use serde::{Deserialize, Serialize};
 
@@ -22,6 +24,12 @@ use thiserror::Error;
 
 use crate::extension::REGISTRY;
 
+mod controlled;
+pub use controlled::{append_controlled, controlled, ControlError};
+
+mod symbolic;
+pub use symbolic::SymbolicExpr;
+
 #[derive(
     Clone,
     Copy,
@@ -62,6 +70,10 @@ pub enum Tk2Op {
     QAlloc,
     QFree,
     Reset,
+    XXPhase,
+    YYPhase,
+    CCX,
+    CCZ,
 }
 
 impl Tk2Op {
@@ -112,12 +124,16 @@ impl MakeOpDef for Tk2Op {
         use Tk2Op::*;
         let one_qb_row = type_row![QB_T];
         let two_qb_row = type_row![QB_T, QB_T];
+        let three_qb_row = type_row![QB_T, QB_T, QB_T];
         match self {
             H | T | S | X | Y | Z | Tdg | Sdg | Reset => {
                 Signature::new(one_qb_row.clone(), one_qb_row)
             }
             CX | ZZMax | CZ => Signature::new(two_qb_row.clone(), two_qb_row),
-            ZZPhase => Signature::new(type_row![QB_T, QB_T, FLOAT64_TYPE], two_qb_row),
+            CCX | CCZ => Signature::new(three_qb_row.clone(), three_qb_row),
+            ZZPhase | XXPhase | YYPhase => {
+                Signature::new(type_row![QB_T, QB_T, FLOAT64_TYPE], two_qb_row)
+            }
             Measure => Signature::new(one_qb_row, type_row![QB_T, BOOL_T]),
             RzF64 | RxF64 => Signature::new(type_row![QB_T, FLOAT64_TYPE], one_qb_row),
             PhasedX => Signature::new(type_row![QB_T, FLOAT64_TYPE, FLOAT64_TYPE], one_qb_row),
@@ -141,7 +157,7 @@ impl MakeOpDef for Tk2Op {
 
     fn post_opdef(&self, def: &mut OpDef) {
         def.add_misc(
-            "commutation",
+            COMMUTATION_KEY,
             serde_json::to_value(self.qubit_commutation()).unwrap(),
         );
     }
@@ -171,6 +187,10 @@ impl Tk2Op {
             T | Z | S | Tdg | Sdg | RzF64 | Measure => vec![(0, Pauli::Z)],
             CX => vec![(0, Pauli::Z), (1, Pauli::X)],
             ZZMax | ZZPhase | CZ => vec![(0, Pauli::Z), (1, Pauli::Z)],
+            XXPhase => vec![(0, Pauli::X), (1, Pauli::X)],
+            YYPhase => vec![(0, Pauli::Y), (1, Pauli::Y)],
+            CCX => vec![(0, Pauli::Z), (1, Pauli::Z), (2, Pauli::X)],
+            CCZ => vec![(0, Pauli::Z), (1, Pauli::Z), (2, Pauli::Z)],
             // by default, no commutation
             _ => vec![],
         }
@@ -181,12 +201,58 @@ impl Tk2Op {
         use Tk2Op::*;
         match self {
             H | CX | T | S | X | Y | Z | Tdg | Sdg | ZZMax | RzF64 | RxF64 | PhasedX | ZZPhase
-            | CZ | TK1 => true,
+            | CZ | TK1 | XXPhase | YYPhase | CCX | CCZ => true,
             AngleAdd | Measure | QAlloc | QFree | Reset => false,
         }
     }
 }
 
+/// The key under which an operation declares its qubit commutation, either in
+/// the metadata of its node or in the miscellaneous data of its [`OpDef`].
+///
+/// The value is a list of `[port, pauli]` pairs, such as `[[0, "Z"], [1, "X"]]`
+/// for a CX gate. See [`op_commutation`].
+pub const COMMUTATION_KEY: &str = "commutation";
+
+/// Returns the qubit commutation of the operation at `node`, if it is known.
+///
+/// Lists the Pauli operator that each qubit input port commutes with. The
+/// qubit ports not listed do not commute with anything.
+///
+/// The commutation is read from, in order of precedence:
+/// - the [`COMMUTATION_KEY`] metadata of the node, see [`set_op_commutation`],
+/// - the [`Tk2Op`] definitions,
+/// - the [`COMMUTATION_KEY`] miscellaneous data of the operation's [`OpDef`],
+///   for resolved extension operations.
+pub fn op_commutation(hugr: &impl HugrView, node: Node) -> Option<Vec<(usize, Pauli)>> {
+    if let Some(comm) = hugr.get_metadata(node, COMMUTATION_KEY) {
+        return serde_json::from_value(comm.clone()).ok();
+    }
+    let op = hugr.get_optype(node);
+    if let Ok(tk2op) = Tk2Op::try_from(op) {
+        return Some(tk2op.qubit_commutation());
+    }
+    // `OpDef` does not expose its miscellaneous data, so we read it from the
+    // serialized definition.
+    let def = op.as_custom_op()?.as_extension_op()?.def();
+    let misc = serde_json::to_value(def).ok()?.get_mut("misc")?.take();
+    serde_json::from_value(misc.get(COMMUTATION_KEY)?.clone()).ok()
+}
+
+/// Declare the qubit commutation of the operation at `node` in its metadata.
+///
+/// This lets passes such as [`apply_greedy_commutation`] commute custom
+/// operations that are not [`Tk2Op`]s. See [`op_commutation`].
+///
+/// [`apply_greedy_commutation`]: crate::passes::apply_greedy_commutation
+pub fn set_op_commutation(hugr: &mut impl HugrMut, node: Node, commutation: &[(usize, Pauli)]) {
+    hugr.set_metadata(
+        node,
+        COMMUTATION_KEY,
+        serde_json::to_value(commutation).unwrap(),
+    );
+}
+
 /// Initialize a new custom symbolic expression constant op from a string.
 pub fn symbolic_constant_op(arg: String) -> OpType {
     EXTENSION
@@ -195,6 +261,17 @@ pub fn symbolic_constant_op(arg: String) -> OpType {
         .into()
 }
 
+/// Initialize a new custom symbolic expression constant op from a
+/// [`SymbolicExpr`].
+pub fn symbolic_expr_op(expr: &SymbolicExpr) -> OpType {
+    symbolic_constant_op(expr.to_string())
+}
+
+/// Match against a symbolic constant, parsing its expression.
+pub(crate) fn match_symbolic_expr(op: &OpType) -> Option<SymbolicExpr> {
+    match_symb_const_op(op).map(|symbol| SymbolicExpr::parse(&symbol))
+}
+
 /// match against a symbolic constant
 pub(crate) fn match_symb_const_op(op: &OpType) -> Option<String> {
     // Extract the symbol for a symbolic operation node.
@@ -0,0 +1,427 @@
+//! Construction of controlled versions of [`Tk2Op`] gates.
+
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use hugr::builder::{BuildError, CircuitBuilder, DFGBuilder, Dataflow, DataflowHugr};
+use hugr::extension::prelude::QB_T;
+use hugr::ops::{OpTrait, OpType};
+use hugr::std_extensions::arithmetic::float_types::{self, ConstF64, FLOAT64_TYPE};
+use hugr::types::Signature;
+use hugr::CircuitUnit;
+use itertools::Itertools;
+use num_complex::Complex64;
+use thiserror::Error;
+
+use crate::circuit::phase::GlobalPhase;
+use crate::extension::{ControlledOp, REGISTRY};
+use crate::sim::gate_matrix;
+use crate::utils::TOLERANCE;
+use crate::{Circuit, Pauli, Tk2Op};
+
+/// Errors that can occur when building a controlled gate.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum ControlError {
+    /// The operation is not a unitary gate, and cannot be controlled.
+    #[error("{0:?} is not a unitary gate.")]
+    NotUnitary(Tk2Op),
+    /// The number of angle parameters does not match the operation.
+    #[error("{op:?} expects {expected} parameters, but {given} were given.")]
+    InvalidParameters {
+        /// The operation.
+        op: Tk2Op,
+        /// The number of angle inputs of the operation.
+        expected: usize,
+        /// The number of parameters given.
+        given: usize,
+    },
+    /// The number of target qubits does not match the operation.
+    #[error("{op:?} acts on {expected} qubits, but {given} targets were given.")]
+    InvalidTargets {
+        /// The operation.
+        op: Tk2Op,
+        /// The number of qubits of the operation.
+        expected: usize,
+        /// The number of target qubits given.
+        given: usize,
+    },
+    /// An error occurred while appending the gates to the circuit.
+    #[error(transparent)]
+    Build(#[from] BuildError),
+}
+
+/// Build a circuit applying `op` controlled by `n_controls` qubits.
+///
+/// The circuit acts on the control qubits followed by the qubits of the
+/// operation, and the angle parameters of the operation are given in radians.
+/// See [`append_controlled`] for the constructions used.
+///
+/// The unitary of the circuit is exactly the controlled unitary once its
+/// global phase, stored in the circuit metadata, is taken into account.
+pub fn controlled(op: Tk2Op, params: &[f64], n_controls: usize) -> Result<Circuit, ControlError> {
+    let num_qubits = n_controls + op_qubits(op);
+    let qubits = vec![QB_T; num_qubits];
+    let signature =
+        Signature::new(qubits.clone(), qubits).with_extension_delta(float_types::EXTENSION_ID);
+    let mut h = DFGBuilder::new(signature)?;
+    let inputs = h.input_wires().collect_vec();
+    let mut circ = h.as_circuit(inputs);
+    let controls = (0..n_controls).collect_vec();
+    let targets = (n_controls..num_qubits).collect_vec();
+    let phase = append_controlled(&mut circ, op, params, &controls, &targets)?;
+    let outputs = circ.finish();
+    let mut circ: Circuit = h.finish_hugr_with_outputs(outputs, &REGISTRY)?.into();
+    circ.add_global_phase(Some(phase));
+    Ok(circ)
+}
+
+/// Append the controlled version of `op` to a circuit being built.
+///
+/// `controls` and `targets` are indices of the qubits tracked by the builder,
+/// and the angle parameters of the operation are given in radians.
+///
+/// The gates are built with standard constructions:
+/// - Pauli gates, including `CX`, `CZ`, `CCX` and `CCZ`, become
+///   multi-controlled Paulis. Gates with more than two controls are added as
+///   [`ControlledOp`]s.
+/// - Two-qubit rotations only control their central `Rz` rotation, since the
+///   conjugating gates cancel out when the controls are not set.
+/// - Other single-qubit gates use the `A X B X C` decomposition of a
+///   controlled unitary, and gates with more than one control are built
+///   recursively from controlled square roots.
+///
+/// The appended gates implement the controlled unitary up to a global phase,
+/// which is returned so it can be added to the circuit.
+pub fn append_controlled<T: Dataflow + ?Sized>(
+    circ: &mut CircuitBuilder<T>,
+    op: Tk2Op,
+    params: &[f64],
+    controls: &[usize],
+    targets: &[usize],
+) -> Result<GlobalPhase, ControlError> {
+    let num_params = OpType::from(op)
+        .dataflow_signature()
+        .unwrap()
+        .input_types()
+        .iter()
+        .filter(|&ty| ty == &FLOAT64_TYPE)
+        .count();
+    if num_params != params.len() {
+        return Err(ControlError::InvalidParameters {
+            op,
+            expected: num_params,
+            given: params.len(),
+        });
+    }
+    let Some(matrix) = gate_matrix(op, params).filter(|_| op.is_quantum()) else {
+        return Err(ControlError::NotUnitary(op));
+    };
+    if op_qubits(op) != targets.len() {
+        return Err(ControlError::InvalidTargets {
+            op,
+            expected: op_qubits(op),
+            given: targets.len(),
+        });
+    }
+
+    let mut builder = Builder { circ };
+    let phase = match (op, targets) {
+        (Tk2Op::X, &[t]) => builder.pauli(controls, t, Pauli::X).map(|_| 0.)?,
+        (Tk2Op::Y, &[t]) => builder.pauli(controls, t, Pauli::Y).map(|_| 0.)?,
+        (Tk2Op::Z, &[t]) => builder.pauli(controls, t, Pauli::Z).map(|_| 0.)?,
+        (Tk2Op::CX | Tk2Op::CZ | Tk2Op::CCX | Tk2Op::CCZ, &[.., t]) => {
+            let pauli = match op {
+                Tk2Op::CX | Tk2Op::CCX => Pauli::X,
+                _ => Pauli::Z,
+            };
+            let (_, op_controls) = targets.split_last().unwrap();
+            let controls = [controls, op_controls].concat();
+            builder.pauli(&controls, t, pauli).map(|_| 0.)?
+        }
+        (Tk2Op::ZZMax | Tk2Op::ZZPhase | Tk2Op::XXPhase | Tk2Op::YYPhase, &[a, b]) => {
+            let theta = params.first().copied().unwrap_or(FRAC_PI_2);
+            builder.two_qubit_rotation(op, controls, [a, b], theta)?
+        }
+        (_, &[t]) => builder.unitary(controls, t, to_array(&matrix))?,
+        _ => unreachable!("Quantum operations act on at most three qubits."),
+    };
+    Ok(GlobalPhase::new(phase / PI))
+}
+
+/// The number of qubits of an operation.
+fn op_qubits(op: Tk2Op) -> usize {
+    OpType::from(op)
+        .dataflow_signature()
+        .unwrap()
+        .input_types()
+        .iter()
+        .filter(|&ty| ty == &QB_T)
+        .count()
+}
+
+/// A single-qubit unitary, in row-major order.
+type Matrix = [Complex64; 4];
+
+fn to_array(matrix: &[Complex64]) -> Matrix {
+    matrix.try_into().unwrap()
+}
+
+/// The conjugate transpose of a single-qubit unitary.
+fn adjoint(u: &Matrix) -> Matrix {
+    [u[0].conj(), u[2].conj(), u[1].conj(), u[3].conj()]
+}
+
+/// A square root of a single-qubit unitary.
+fn sqrt(u: &Matrix) -> Matrix {
+    // Write `u = e^{iα} w` with `w` in SU(2), choosing the sign of `w` so that
+    // its trace is non-negative. Then `(w + I)² = (tr(w) + 2) w`.
+    let mut alpha = (u[0] * u[3] - u[1] * u[2]).arg() / 2.;
+    let mut w = u.map(|x| x * Complex64::from_polar(1., -alpha));
+    if (w[0] + w[3]).re < 0. {
+        w = w.map(|x| -x);
+        alpha += PI;
+    }
+    let norm = (w[0] + w[3]).re + 2.;
+    let scale = Complex64::from_polar(1. / norm.sqrt(), alpha / 2.);
+    [w[0] + 1., w[1], w[2], w[3] + 1.].map(|x| x * scale)
+}
+
+/// Decompose a single-qubit unitary as `e^{iα} Rz(β) Ry(γ) Rz(δ)`.
+///
+/// Returns the angles `[α, β, γ, δ]`.
+fn zyz_angles(u: &Matrix) -> [f64; 4] {
+    let alpha = (u[0] * u[3] - u[1] * u[2]).arg() / 2.;
+    let phase = Complex64::from_polar(1., -alpha);
+    let (a, b) = (u[0] * phase, u[2] * phase);
+    let arg = |x: Complex64| if x.norm() < TOLERANCE { 0. } else { x.arg() };
+    let gamma = 2. * b.norm().atan2(a.norm());
+    // `a = e^{-i(β+δ)/2} cos(γ/2)` and `b = e^{i(β-δ)/2} sin(γ/2)`.
+    let (sum, diff) = (-2. * arg(a), 2. * arg(b));
+    [alpha, (sum + diff) / 2., gamma, (sum - diff) / 2.]
+}
+
+/// Helper appending gates with constant angles to a circuit builder.
+struct Builder<'c, 'a, T: ?Sized> {
+    circ: &'c mut CircuitBuilder<'a, T>,
+}
+
+impl<'c, 'a, T: Dataflow + ?Sized> Builder<'c, 'a, T> {
+    fn gate(&mut self, op: impl Into<OpType>, qubits: &[usize]) -> Result<(), BuildError> {
+        self.circ.append(op, qubits.iter().copied())?;
+        Ok(())
+    }
+
+    /// Append a rotation, skipping it if the angle is zero.
+    fn rotation(&mut self, op: Tk2Op, q: usize, angles: &[f64]) -> Result<(), BuildError> {
+        if angles[0].abs() < TOLERANCE {
+            return Ok(());
+        }
+        let inputs = [CircuitUnit::Linear(q)]
+            .into_iter()
+            .chain(
+                angles
+                    .iter()
+                    .map(|&angle| self.circ.add_constant(ConstF64::new(angle)).into()),
+            )
+            .collect_vec();
+        self.circ.append_and_consume(op, inputs)?;
+        Ok(())
+    }
+
+    fn rz(&mut self, q: usize, angle: f64) -> Result<(), BuildError> {
+        self.rotation(Tk2Op::RzF64, q, &[angle])
+    }
+
+    /// `Ry(θ) = PhasedX(θ, π/2)`.
+    fn ry(&mut self, q: usize, angle: f64) -> Result<(), BuildError> {
+        self.rotation(Tk2Op::PhasedX, q, &[angle, FRAC_PI_2])
+    }
+
+    /// Append a Pauli gate controlled by any number of qubits.
+    fn pauli(&mut self, controls: &[usize], t: usize, pauli: Pauli) -> Result<(), BuildError> {
+        use Tk2Op::*;
+        let qubits = [controls, &[t]].concat();
+        match (pauli, controls.len()) {
+            (Pauli::I, _) => Ok(()),
+            (_, n) if n > 2 => self.gate(ControlledOp::new(n, pauli), &qubits),
+            (Pauli::X, 0) => self.gate(X, &qubits),
+            (Pauli::X, 1) => self.gate(CX, &qubits),
+            (Pauli::X, _) => self.gate(CCX, &qubits),
+            (Pauli::Z, 0) => self.gate(Z, &qubits),
+            (Pauli::Z, 1) => self.gate(CZ, &qubits),
+            (Pauli::Z, _) => self.gate(CCZ, &qubits),
+            // `Y = S X Sdg`.
+            (Pauli::Y, _) => {
+                self.gate(Sdg, &[t])?;
+                self.pauli(controls, t, Pauli::X)?;
+                self.gate(S, &[t])
+            }
+        }
+    }
+
+    /// Append a controlled two-qubit Pauli rotation, as a controlled `Rz`
+    /// conjugated by uncontrolled gates.
+    ///
+    /// Returns the global phase of the construction, in radians.
+    fn two_qubit_rotation(
+        &mut self,
+        op: Tk2Op,
+        controls: &[usize],
+        [a, b]: [usize; 2],
+        theta: f64,
+    ) -> Result<f64, BuildError> {
+        // `ZZPhase(θ) = CX (I ⊗ Rz(θ)) CX`.
+        self.rotate_to_z(op, [a, b], false)?;
+        self.gate(Tk2Op::CX, &[a, b])?;
+        let rz = to_array(&gate_matrix(Tk2Op::RzF64, &[theta]).unwrap());
+        let phase = self.unitary(controls, b, rz)?;
+        self.gate(Tk2Op::CX, &[a, b])?;
+        self.rotate_to_z(op, [a, b], true)?;
+        Ok(phase)
+    }
+
+    /// Rotate the axis of a two-qubit rotation onto the Z axis, or back if
+    /// `inverse` is set.
+    fn rotate_to_z(
+        &mut self,
+        op: Tk2Op,
+        qubits: [usize; 2],
+        inverse: bool,
+    ) -> Result<(), BuildError> {
+        for q in qubits {
+            match op {
+                Tk2Op::XXPhase => self.gate(Tk2Op::H, &[q])?,
+                // `Rx(-π/2) Y Rx(π/2) = Z`.
+                Tk2Op::YYPhase => {
+                    let angle = if inverse { FRAC_PI_2 } else { -FRAC_PI_2 };
+                    self.rotation(Tk2Op::RxF64, q, &[angle])?
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Append a single-qubit unitary controlled by any number of qubits.
+    ///
+    /// Returns the global phase of the construction, in radians.
+    fn unitary(&mut self, controls: &[usize], t: usize, u: Matrix) -> Result<f64, BuildError> {
+        let Some((&c, rest)) = controls.split_last() else {
+            let [alpha, beta, gamma, delta] = zyz_angles(&u);
+            self.rz(t, delta)?;
+            self.ry(t, gamma)?;
+            self.rz(t, beta)?;
+            return Ok(alpha);
+        };
+        if !rest.is_empty() {
+            // `C^n(U) = C^{n-1}(V) · C^{n-1}(X) C(V†) C^{n-1}(X) · C(V)`,
+            // with `V² = U`, controlling the `X` gates on the last control.
+            let v = sqrt(&u);
+            let mut phase = self.unitary(&[c], t, v)?;
+            self.pauli(rest, c, Pauli::X)?;
+            phase += self.unitary(&[c], t, adjoint(&v))?;
+            self.pauli(rest, c, Pauli::X)?;
+            phase += self.unitary(rest, t, v)?;
+            return Ok(phase);
+        }
+
+        // `U = e^{iα} A X B X C` with `A B C = I`.
+        let [alpha, beta, gamma, delta] = zyz_angles(&u);
+        // C = Rz((δ - β)/2)
+        self.rz(t, (delta - beta) / 2.)?;
+        self.gate(Tk2Op::CX, &[c, t])?;
+        // B = Ry(-γ/2) Rz(-(δ + β)/2)
+        self.rz(t, -(delta + beta) / 2.)?;
+        self.ry(t, -gamma / 2.)?;
+        self.gate(Tk2Op::CX, &[c, t])?;
+        // A = Rz(β) Ry(γ/2)
+        self.ry(t, gamma / 2.)?;
+        self.rz(t, beta)?;
+        // The phase `e^{iα}` on the control is `e^{iα/2} Rz(α)`.
+        self.rz(c, alpha)?;
+        Ok(alpha / 2.)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::CircuitUnit;
+    use ndarray::Array2;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::utils::build_simple_circuit;
+
+    /// The unitary of an operation, with the first qubit as the least
+    /// significant bit.
+    fn op_unitary(op: Tk2Op, params: &[f64]) -> Array2<Complex64> {
+        let circ = build_simple_circuit(op_qubits(op), |c| {
+            let inputs = (0..op_qubits(op))
+                .map(CircuitUnit::Linear)
+                .chain(
+                    params
+                        .iter()
+                        .map(|&p| c.add_constant(ConstF64::new(p)).into()),
+                )
+                .collect_vec();
+            c.append_and_consume(op, inputs)?;
+            Ok(())
+        })
+        .unwrap();
+        circ.unitary().unwrap()
+    }
+
+    #[rstest]
+    #[case(Tk2Op::H, &[], 1)]
+    #[case(Tk2Op::T, &[], 2)]
+    #[case(Tk2Op::Sdg, &[], 3)]
+    #[case(Tk2Op::X, &[], 1)]
+    #[case(Tk2Op::Y, &[], 2)]
+    #[case(Tk2Op::RzF64, &[0.3], 1)]
+    #[case(Tk2Op::RxF64, &[-1.7], 2)]
+    #[case(Tk2Op::PhasedX, &[0.4, 1.1], 3)]
+    #[case(Tk2Op::TK1, &[0.1, 2.9, -0.6], 2)]
+    #[case(Tk2Op::CX, &[], 1)]
+    #[case(Tk2Op::CZ, &[], 1)]
+    #[case(Tk2Op::ZZMax, &[], 1)]
+    #[case(Tk2Op::ZZPhase, &[0.7], 2)]
+    #[case(Tk2Op::XXPhase, &[0.5], 1)]
+    #[case(Tk2Op::YYPhase, &[0.9], 1)]
+    #[case(Tk2Op::Z, &[], 0)]
+    fn controlled_unitary(#[case] op: Tk2Op, #[case] params: &[f64], #[case] n_controls: usize) {
+        let circ = controlled(op, params, n_controls).unwrap();
+        let half_turns = circ.global_phase().unwrap().constant();
+        let phase = Complex64::from_polar(1., half_turns * PI);
+        let unitary = circ.unitary().unwrap().mapv(|x| x * phase);
+
+        let u = op_unitary(op, params);
+        let mask = (1 << n_controls) - 1;
+        let expected = Array2::from_shape_fn(unitary.dim(), |(i, j)| {
+            if i & mask != j & mask {
+                Complex64::new(0., 0.)
+            } else if j & mask == mask {
+                u[[i >> n_controls, j >> n_controls]]
+            } else {
+                Complex64::new(if i == j { 1. } else { 0. }, 0.)
+            }
+        });
+        assert!((&unitary - &expected).iter().all(|x| x.norm() < 1e-9));
+    }
+
+    #[test]
+    fn many_controls() {
+        let circ = controlled(Tk2Op::CX, &[], 3).unwrap();
+        let controlled_ops = circ
+            .commands()
+            .filter_map(|cmd| ControlledOp::from_optype(cmd.optype()))
+            .collect_vec();
+        assert_eq!(controlled_ops, [ControlledOp::new(4, Pauli::X)]);
+    }
+
+    #[rstest]
+    #[case(Tk2Op::Measure, &[], ControlError::NotUnitary(Tk2Op::Measure))]
+    #[case(Tk2Op::RzF64, &[], ControlError::InvalidParameters { op: Tk2Op::RzF64, expected: 1, given: 0 })]
+    fn invalid_operations(#[case] op: Tk2Op, #[case] params: &[f64], #[case] err: ControlError) {
+        assert_eq!(controlled(op, params, 1).unwrap_err(), err);
+    }
+}
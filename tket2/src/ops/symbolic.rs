@@ -0,0 +1,482 @@
+//! Symbolic angle expressions.
+//!
+//! The symbolic constants created with [`symbolic_constant_op`] hold pytket
+//! parameter expressions, in half-turns. [`SymbolicExpr`] parses these into
+//! polynomials over the free symbols with rational coefficients, so that a
+//! rational constant `r` denotes the angle `rπ`. Expressions are kept in a
+//! canonical form, combining like terms, so that passes can merge symbolic
+//! rotations and detect when they cancel out.
+//!
+//! Sub-expressions outside of this fragment, such as function applications,
+//! are kept as opaque atoms.
+//!
+//! [`symbolic_constant_op`]: crate::symbolic_constant_op
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+use itertools::Itertools;
+use num_rational::Rational64;
+use serde::{Deserialize, Serialize};
+
+/// The largest exponent expanded when parsing a power.
+const MAX_EXPONENT: i64 = 16;
+
+/// A product of atoms, sorted.
+///
+/// The empty product is the constant term.
+type Monomial = Vec<String>;
+
+/// A symbolic angle expression, in half-turns.
+///
+/// The expression is a sum of products of atoms with rational coefficients.
+/// Atoms are symbol names, or opaque sub-expressions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
+pub struct SymbolicExpr {
+    /// The non-zero coefficient of each monomial.
+    terms: BTreeMap<Monomial, Rational64>,
+}
+
+impl SymbolicExpr {
+    /// A constant expression.
+    pub fn constant(value: impl Into<Rational64>) -> Self {
+        Self::from_terms([(Vec::new(), value.into())])
+    }
+
+    /// A free symbol.
+    pub fn symbol(name: impl Into<String>) -> Self {
+        Self::from_terms([(vec![name.into()], Rational64::from_integer(1))])
+    }
+
+    /// Parse a pytket parameter expression.
+    ///
+    /// Sums, differences, products, integer powers and divisions by constants
+    /// are expanded. If the expression cannot be parsed, it is kept as a
+    /// single opaque atom.
+    pub fn parse(expr: &str) -> Self {
+        let expr = expr.trim();
+        Parser::new(expr)
+            .and_then(|mut parser| parser.parse())
+            .unwrap_or_else(|| match is_atom(expr) {
+                true => Self::symbol(expr),
+                false => Self::symbol(format!("({expr})")),
+            })
+    }
+
+    /// The value of the expression, if it has no free symbols.
+    pub fn as_constant(&self) -> Option<Rational64> {
+        match self.terms.len() {
+            0 => Some(Rational64::default()),
+            1 => self.terms.get(&Vec::new()).copied(),
+            _ => None,
+        }
+    }
+
+    /// The constant term of the expression.
+    pub fn constant_term(&self) -> Rational64 {
+        self.terms.get(&Vec::new()).copied().unwrap_or_default()
+    }
+
+    /// Returns `true` if the expression is zero.
+    pub fn is_zero(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Returns `true` if the expression has no free symbols.
+    pub fn is_constant(&self) -> bool {
+        self.as_constant().is_some()
+    }
+
+    /// The atoms appearing in the expression, sorted.
+    pub fn free_symbols(&self) -> impl Iterator<Item = &str> + '_ {
+        self.terms
+            .keys()
+            .flatten()
+            .map(String::as_str)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+    }
+
+    /// Evaluate the expression, given the value of each atom.
+    ///
+    /// Returns `None` if an atom has no value.
+    pub fn evaluate(&self, value: impl Fn(&str) -> Option<f64>) -> Option<f64> {
+        self.terms
+            .iter()
+            .map(|(monomial, coeff)| {
+                let product = monomial
+                    .iter()
+                    .map(|atom| value(atom))
+                    .product::<Option<f64>>()?;
+                Some(product * *coeff.numer() as f64 / *coeff.denom() as f64)
+            })
+            .sum()
+    }
+
+    /// Build an expression from a list of terms, combining like terms.
+    fn from_terms(terms: impl IntoIterator<Item = (Monomial, Rational64)>) -> Self {
+        let mut expr = Self::default();
+        for (monomial, coeff) in terms {
+            *expr.terms.entry(monomial).or_default() += coeff;
+        }
+        expr.terms
+            .retain(|_, coeff| *coeff != Rational64::default());
+        expr
+    }
+}
+
+impl From<Rational64> for SymbolicExpr {
+    fn from(value: Rational64) -> Self {
+        Self::constant(value)
+    }
+}
+
+impl From<i64> for SymbolicExpr {
+    fn from(value: i64) -> Self {
+        Self::constant(value)
+    }
+}
+
+impl From<String> for SymbolicExpr {
+    fn from(expr: String) -> Self {
+        Self::parse(&expr)
+    }
+}
+
+impl From<SymbolicExpr> for String {
+    fn from(expr: SymbolicExpr) -> Self {
+        expr.to_string()
+    }
+}
+
+impl Add for SymbolicExpr {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::from_terms(self.terms.into_iter().chain(rhs.terms))
+    }
+}
+
+impl Neg for SymbolicExpr {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::from_terms(self.terms.into_iter().map(|(m, c)| (m, -c)))
+    }
+}
+
+impl Sub for SymbolicExpr {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self + -rhs
+    }
+}
+
+impl Mul for SymbolicExpr {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::from_terms(self.terms.iter().cartesian_product(&rhs.terms).map(
+            |((m1, c1), (m2, c2))| {
+                let monomial = m1.iter().chain(m2).cloned().sorted().collect();
+                (monomial, c1 * c2)
+            },
+        ))
+    }
+}
+
+impl fmt::Display for SymbolicExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+        // Print the terms by decreasing degree, as sympy does.
+        let terms = self
+            .terms
+            .iter()
+            .sorted_by_key(|(m, _)| std::cmp::Reverse(m.len()));
+        for (i, (monomial, coeff)) in terms.enumerate() {
+            let negative = *coeff < Rational64::default();
+            let sign = match (i, negative) {
+                (0, false) => "",
+                (0, true) => "-",
+                (_, false) => " + ",
+                (_, true) => " - ",
+            };
+            let coeff = if negative { -coeff } else { *coeff };
+            let mut factors = monomial
+                .iter()
+                .dedup_with_count()
+                .map(|(n, atom)| match n {
+                    1 => atom.clone(),
+                    n => format!("{atom}**{n}"),
+                })
+                .collect_vec();
+            if factors.is_empty() || *coeff.numer() != 1 {
+                factors.insert(0, coeff.numer().to_string());
+            }
+            write!(f, "{sign}{}", factors.join("*"))?;
+            if *coeff.denom() != 1 {
+                write!(f, "/{}", coeff.denom())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns `true` if an expression is a single name, or a parenthesised
+/// sub-expression or function application that can be used as an atom.
+fn is_atom(expr: &str) -> bool {
+    let Some(first) = expr.chars().next() else {
+        return false;
+    };
+    let name_end = expr.find(|c: char| !is_name_char(c)).unwrap_or(expr.len());
+    let (name, rest) = expr.split_at(name_end);
+    let name_ok = name.is_empty() || is_name_start(first);
+    name_ok && (rest.is_empty() || closing_paren(rest, 0) == Some(rest.len() - 1))
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The index of the parenthesis closing the one at `open`.
+fn closing_paren(expr: &str, open: usize) -> Option<usize> {
+    if !expr[open..].starts_with('(') {
+        return None;
+    }
+    let mut depth = 0;
+    for (i, c) in expr[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return Some(open + i);
+        }
+    }
+    None
+}
+
+/// A token of a parameter expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Token<'a> {
+    Number(Rational64),
+    /// A name, or a function application.
+    Atom(&'a str),
+    Op(&'static str),
+}
+
+/// A recursive-descent parser for parameter expressions.
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(expr: &'a str) -> Option<Self> {
+        let mut tokens = Vec::new();
+        let mut rest = expr.trim_start();
+        while let Some(c) = rest.chars().next() {
+            let len = if let Some(op) = ["**", "+", "-", "*", "/", "(", ")"]
+                .into_iter()
+                .find(|op| rest.starts_with(op))
+            {
+                tokens.push(Token::Op(op));
+                op.len()
+            } else if c.is_ascii_digit() || c == '.' {
+                let len = number_len(rest);
+                tokens.push(Token::Number(parse_number(&rest[..len])?));
+                len
+            } else if is_name_start(c) {
+                let mut len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+                // Function applications are kept as opaque atoms.
+                if let Some(close) = closing_paren(rest, len) {
+                    len = close + 1;
+                }
+                tokens.push(Token::Atom(&rest[..len]));
+                len
+            } else {
+                return None;
+            };
+            rest = rest[len..].trim_start();
+        }
+        Some(Self { tokens, pos: 0 })
+    }
+
+    /// Parse the whole expression.
+    fn parse(&mut self) -> Option<SymbolicExpr> {
+        let expr = self.sum()?;
+        (self.pos == self.tokens.len()).then_some(expr)
+    }
+
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Consume the next token if it is the given operator.
+    fn eat(&mut self, op: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Op(o)) if *o == op);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn sum(&mut self) -> Option<SymbolicExpr> {
+        let mut expr = self.product()?;
+        loop {
+            if self.eat("+") {
+                expr = expr + self.product()?;
+            } else if self.eat("-") {
+                expr = expr - self.product()?;
+            } else {
+                return Some(expr);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Option<SymbolicExpr> {
+        let mut expr = self.unary()?;
+        loop {
+            if self.eat("*") {
+                expr = expr * self.unary()?;
+            } else if self.eat("/") {
+                let divisor = self.unary()?.as_constant()?;
+                if divisor == Rational64::default() {
+                    return None;
+                }
+                expr = expr * SymbolicExpr::constant(divisor.recip());
+            } else {
+                return Some(expr);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Option<SymbolicExpr> {
+        if self.eat("-") {
+            Some(-self.unary()?)
+        } else if self.eat("+") {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Option<SymbolicExpr> {
+        let base = self.primary()?;
+        if !self.eat("**") {
+            return Some(base);
+        }
+        let exponent = self.unary()?.as_constant()?;
+        if !exponent.is_integer() || !(0..=MAX_EXPONENT).contains(exponent.numer()) {
+            return None;
+        }
+        Some((0..*exponent.numer()).fold(SymbolicExpr::constant(1), |acc, _| acc * base.clone()))
+    }
+
+    fn primary(&mut self) -> Option<SymbolicExpr> {
+        let token = self.peek()?.clone();
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Some(SymbolicExpr::constant(n)),
+            Token::Atom(name) => Some(SymbolicExpr::symbol(name)),
+            Token::Op("(") => {
+                let expr = self.sum()?;
+                self.eat(")").then_some(expr)
+            }
+            Token::Op(_) => None,
+        }
+    }
+}
+
+/// The length of the number literal at the start of an expression.
+fn number_len(expr: &str) -> usize {
+    let mut len = expr
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(expr.len());
+    let rest = &expr[len..];
+    if rest.starts_with(['e', 'E']) {
+        let sign = usize::from(rest[1..].starts_with(['+', '-']));
+        let digits = rest[1 + sign..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len() - 1 - sign);
+        if digits > 0 {
+            len += 1 + sign + digits;
+        }
+    }
+    len
+}
+
+/// Parse a number literal as a rational.
+///
+/// Decimal literals are parsed exactly, others are approximated.
+fn parse_number(literal: &str) -> Option<Rational64> {
+    let exact = || {
+        let (int, frac) = literal.split_once('.').unwrap_or((literal, ""));
+        let digits: i64 = format!("{int}{frac}").parse().ok()?;
+        let denom = 10i64.checked_pow(frac.len() as u32)?;
+        Some(Rational64::new(digits, denom))
+    };
+    exact().or_else(|| Rational64::approximate_float(literal.parse::<f64>().ok()?))
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("a", "a")]
+    #[case("a + b - a", "b")]
+    #[case("0.5 + a/2 + 1/4", "a/2 + 3/4")]
+    #[case("2*(a + b) - b", "2*a + b")]
+    #[case("-(a - 1)", "-a + 1")]
+    #[case("a*b*a + a**2*b", "2*a**2*b")]
+    #[case("(a + 1)**2", "a**2 + 2*a + 1")]
+    #[case("sin(a) + sin(a)", "2*sin(a)")]
+    #[case("1e-1 + a", "a + 1/10")]
+    #[case("a - a", "0")]
+    #[case("a/b", "(a/b)")]
+    fn parse_simplified(#[case] expr: &str, #[case] expected: &str) {
+        let parsed = SymbolicExpr::parse(expr);
+        assert_eq!(parsed.to_string(), expected);
+        assert_eq!(SymbolicExpr::parse(&parsed.to_string()), parsed);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = SymbolicExpr::symbol("a");
+        let b = SymbolicExpr::symbol("b");
+        let expr = (a.clone() + b.clone()) * (a.clone() - b.clone());
+        assert_eq!(expr, SymbolicExpr::parse("a**2 - b**2"));
+        assert_eq!(expr.free_symbols().collect_vec(), ["a", "b"]);
+
+        let half = SymbolicExpr::constant(Rational64::new(1, 2));
+        let sum = a.clone() + half.clone() - a;
+        assert_eq!(sum.as_constant(), Some(Rational64::new(1, 2)));
+        assert!(!expr.is_constant());
+        assert!((sum - half).is_zero());
+    }
+
+    #[test]
+    fn evaluate() {
+        let expr = SymbolicExpr::parse("2*a*b + 1/2");
+        let value = |atom: &str| match atom {
+            "a" => Some(0.5),
+            "b" => Some(3.),
+            _ => None,
+        };
+        assert_eq!(expr.evaluate(value), Some(3.5));
+        assert_eq!(SymbolicExpr::parse("c + a").evaluate(value), None);
+    }
+}
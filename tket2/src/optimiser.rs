@@ -1,9 +1,12 @@
 //! Optimisers for circuit rewriting.
 //!
-//! Currently, the only optimiser is Badger
+//! Currently, the only optimiser is Badger. The [`explorer`] module exposes
+//! the space of circuits visited during a search.
 
 pub mod badger;
+pub mod explorer;
 
 #[cfg(feature = "portmatching")]
 pub use badger::DefaultBadgerOptimiser;
-pub use badger::{BadgerLogger, BadgerOptimiser};
+pub use badger::{BadgerLogger, BadgerOptimiser, OptimiserCallback};
+pub use explorer::{ExplorerOptions, RewriteDag, RewriteExplorer};
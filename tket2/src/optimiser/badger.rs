@@ -11,37 +11,61 @@
 //! detect and ignore duplicates. The priority queue is truncated whenever
 //! it gets too large.
 
+pub mod callback;
+#[cfg(feature = "distributed")]
+pub mod distributed;
 mod eq_circ_class;
-mod hugr_pchannel;
+pub mod event_log;
+pub mod frontier;
 mod hugr_pqueue;
 pub mod log;
 mod qtz_circuit;
+mod seen_hashes;
+mod sharded_pqueue;
+mod snapshot;
 mod worker;
 
+pub use callback::{BadgerProgress, OptimiserCallback};
 use crossbeam_channel::select;
-pub use eq_circ_class::{load_eccs_json_file, EqCircClass};
-use fxhash::FxHashSet;
+#[cfg(feature = "distributed")]
+pub use distributed::{DistributedError, WorkerConnection};
+pub use eq_circ_class::{
+    load_eccs_json_file, load_eccs_json_files, merge_eccs, EqCircClass, EqCircClassError,
+};
+pub use event_log::{BadgerEvent, BadgerEventKind, RunLog};
+pub use frontier::{FrontierRequest, FrontierSnapshot};
+use fxhash::FxHashMap;
 use hugr::hugr::HugrError;
 use hugr::HugrView;
 pub use log::BadgerLogger;
+use rand::seq::SliceRandom;
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 
 use std::num::NonZeroUsize;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use std::{mem, thread};
+use std::{iter, mem, thread};
 
 use crate::circuit::cost::CircuitCost;
+use crate::circuit::CircuitChunks;
 use crate::circuit::CircuitHash;
-use crate::optimiser::badger::hugr_pchannel::{HugrPriorityChannel, PriorityChannelLog};
+use crate::memory::{track_phase, Phase};
 use crate::optimiser::badger::hugr_pqueue::{Entry, HugrPQ};
+use crate::optimiser::badger::seen_hashes::SeenHashes;
+use crate::optimiser::badger::sharded_pqueue::{PriorityQueueLog, ShardedHugrPQ};
+use crate::optimiser::badger::snapshot::CircuitSnapshot;
 use crate::optimiser::badger::worker::BadgerWorker;
-use crate::passes::CircuitChunks;
+use crate::rewrite::incremental::{update_rewrites, ModifiedRegion};
+use crate::rewrite::profile::{node_qubits, ProfiledRewrite, RewritePath, RewriteProfile};
 use crate::rewrite::strategy::RewriteStrategy;
-use crate::rewrite::Rewriter;
+use crate::rewrite::{CircuitRewrite, Rewriter};
+use crate::seed::{self, Seed};
 use crate::Circuit;
 
 /// Configuration options for the Badger optimiser.
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BadgerOptions {
     /// The maximum time (in seconds) to run the optimiser.
     ///
@@ -50,6 +74,9 @@ pub struct BadgerOptions {
     /// The maximum time (in seconds) to search for new improvements to the
     /// circuit. If no progress is made in this time, the optimiser will stop.
     ///
+    /// The time is measured from the last improvement of the best circuit, or
+    /// from the start of the optimisation.
+    ///
     /// Defaults to `None`, which means no timeout.
     pub progress_timeout: Option<u64>,
     /// The maximum number of circuits to process before stopping the optimisation.
@@ -73,10 +100,103 @@ pub struct BadgerOptions {
     ///
     /// Defaults to `false`.
     pub split_circuit: bool,
+    /// When splitting the circuit, the number of operations on each side of
+    /// a boundary between chunks to re-optimise after reassembling the
+    /// circuit.
+    ///
+    /// The windows around the boundaries are optimised with the same options
+    /// as the chunks, so the optimisation may take up to twice the `timeout`.
+    /// See [`CircuitChunks::reassemble_with_overlap`].
+    ///
+    /// Defaults to `None`, which means the boundaries are not re-optimised.
+    pub split_overlap: Option<usize>,
+    /// When splitting the circuit, the maximum number of rounds of chunked
+    /// optimisation.
+    ///
+    /// Each round splits the result of the previous one with the chunk
+    /// boundaries shifted by a fraction of the chunk size, cycling through
+    /// [`BadgerOptions::split_offsets`] offsets, so that the simplifications
+    /// missed at the boundaries of a round can be found by the next. The
+    /// optimisation stops early once a full cycle of offsets brings no
+    /// improvement. Each round runs with the given `timeout`.
+    ///
+    /// Defaults to `1`.
+    pub split_rounds: usize,
+    /// The number of distinct chunk offsets to cycle through when running
+    /// multiple [`BadgerOptions::split_rounds`].
+    ///
+    /// Defaults to `2`, alternating between chunk boundaries aligned with the
+    /// first round and halfway between them.
+    pub split_offsets: usize,
     /// The maximum size of the circuit candidates priority queue.
     ///
+    /// When running parallel searches on the whole circuit, the capacity is
+    /// split evenly between the workers' queue shards.
+    ///
     /// Defaults to `20`.
     pub queue_size: usize,
+    /// The maximum memory (in bytes) used to record the hashes of the circuits
+    /// seen so far.
+    ///
+    /// Once the budget is reached, the exact set of hashes is replaced by a
+    /// Bloom filter of the same size. Its memory no longer grows, but some new
+    /// circuits may be mistaken for already seen ones and skipped.
+    ///
+    /// For data parallel multi-threading, (split_circuit=true), applies on a
+    /// per-thread basis, otherwise applies globally.
+    ///
+    /// Defaults to `None`, which means no limit.
+    pub max_seen_memory: Option<usize>,
+    /// Update the rewrites of each circuit incrementally from the rewrites of
+    /// the circuit it was obtained from, only re-matching within this radius
+    /// of the modified nodes.
+    ///
+    /// The radius should be at least the size of the largest pattern of the
+    /// rewriter, see [`crate::rewrite::incremental`]. Only used by the
+    /// single-threaded optimiser. Setting it disables
+    /// [`BadgerOptions::compact_queue`].
+    ///
+    /// Defaults to `None`, which means every circuit is rescanned in full.
+    pub match_radius: Option<usize>,
+    /// Store the pure quantum circuits of the priority queue as
+    /// [`CompactCircuit`](crate::circuit::compact::CompactCircuit)s, to reduce
+    /// the memory of the queue.
+    ///
+    /// The compact form replaces the computation of each angle by its
+    /// constant value and does not preserve the node indices, so the search
+    /// may find a different circuit than without it.
+    ///
+    /// Defaults to `false`.
+    pub compact_queue: bool,
+    /// Identify the circuits already seen with [`Circuit::canonical_hash`],
+    /// so that circuits that only differ by the order of commuting gates are
+    /// only processed once.
+    ///
+    /// The canonical hash is slower to compute than the default
+    /// [`CircuitHash::circuit_hash`].
+    ///
+    /// Defaults to `false`.
+    pub canonical_hashing: bool,
+    /// Explore the rewrites of each circuit in a random order, drawn from a
+    /// generator seeded with this value.
+    ///
+    /// The rewrite strategy combines the rewrites in the order it receives
+    /// them, so different seeds explore different candidates. The search is
+    /// reproducible for a given seed when running on a single thread. Each
+    /// worker of a parallel search uses a seed
+    /// [derived](crate::seed::derive) from this one.
+    ///
+    /// Defaults to `None`, which keeps the order of the rewriter.
+    pub seed: Option<Seed>,
+    /// Explore first the rewrites of the rule classes and regions that led to
+    /// the best circuits of previous runs, as recorded in a profile.
+    ///
+    /// Profiles are recorded with [`BadgerLogger::with_profile_log`]. The
+    /// rewrites are ordered after being shuffled by [`BadgerOptions::seed`].
+    ///
+    /// Defaults to `None`, which keeps the order of the rewriter.
+    #[serde(skip)]
+    pub profile: Option<Arc<RewriteProfile>>,
 }
 
 impl Default for BadgerOptions {
@@ -86,8 +206,17 @@ impl Default for BadgerOptions {
             progress_timeout: Default::default(),
             n_threads: NonZeroUsize::new(1).unwrap(),
             split_circuit: Default::default(),
+            split_overlap: None,
+            split_rounds: 1,
+            split_offsets: 2,
             queue_size: 20,
+            max_seen_memory: None,
             max_circuit_count: None,
+            match_radius: None,
+            compact_queue: false,
+            canonical_hashing: false,
+            seed: None,
+            profile: None,
         }
     }
 }
@@ -140,37 +269,42 @@ where
     ///
     /// A timeout (in seconds) can be provided.
     pub fn optimise(&self, circ: &Circuit<impl HugrView>, options: BadgerOptions) -> Circuit {
-        self.optimise_with_log(circ, Default::default(), options)
+        self.optimise_with_log(circ, Default::default(), (), options)
     }
 
     /// Run the Badger optimiser on a circuit with logging activated.
     ///
+    /// The `callback` hooks are called as the optimisation progresses, see
+    /// [`OptimiserCallback`]. Pass `()` to disable them.
+    ///
     /// A timeout (in seconds) can be provided.
     pub fn optimise_with_log(
         &self,
         circ: &Circuit<impl HugrView>,
         log_config: BadgerLogger,
+        callback: impl OptimiserCallback<S::Cost>,
         options: BadgerOptions,
     ) -> Circuit {
-        match options.n_threads.get() {
-            1 => self.badger(circ, log_config, options),
+        track_phase(Phase::Search, || match options.n_threads.get() {
+            1 => self.badger(circ, log_config, callback, options),
             _ => {
                 if options.split_circuit {
-                    self.badger_split_multithreaded(circ, log_config, options)
+                    self.badger_split_multithreaded(circ, log_config, callback, options)
                         .unwrap()
                 } else {
-                    self.badger_multithreaded(circ, log_config, options)
+                    self.badger_multithreaded(circ, log_config, callback, options)
                 }
             }
-        }
+        })
     }
 
     /// Run the Badger optimiser on a circuit, using a single thread.
-    #[tracing::instrument(target = "badger::metrics", skip(self, circ, logger))]
+    #[tracing::instrument(target = "badger::metrics", skip(self, circ, logger, callback))]
     fn badger(
         &self,
         circ: &Circuit<impl HugrView>,
         mut logger: BadgerLogger,
+        mut callback: impl OptimiserCallback<S::Cost>,
         opt: BadgerOptions,
     ) -> Circuit {
         let start_time = Instant::now();
@@ -179,12 +313,12 @@ where
         let circ = circ.to_owned();
         let mut best_circ = circ.clone();
         let mut best_circ_cost = self.cost(&circ);
-        let num_rewrites = best_circ.rewrite_trace().map(|rs| rs.len());
-        logger.log_best(&best_circ_cost, num_rewrites);
+        logger.log_best(&best_circ, &best_circ_cost, None);
+        callback.on_new_best(&best_circ, &best_circ_cost);
 
         // Hash of seen circuits. Dot not store circuits as this map gets huge
-        let hash = circ.circuit_hash().unwrap();
-        let mut seen_hashes = FxHashSet::default();
+        let hash = seen_hash(&circ, opt.canonical_hashing).unwrap();
+        let mut seen_hashes = SeenHashes::new(opt.max_seen_memory);
         seen_hashes.insert(hash);
 
         // The priority queue of circuits to be processed (this should not get big)
@@ -195,36 +329,67 @@ where
         let cost = (cost_fn)(&circ);
 
         let mut pq = HugrPQ::new(cost_fn, opt.queue_size);
-        pq.push_unchecked(circ.to_owned(), hash, cost);
+        pq.push_unchecked(circ.into(), hash, cost);
+
+        // The rewrites of the parent of each queued circuit, and the region
+        // modified to obtain it, used for incremental matching.
+        let mut incremental: FxHashMap<u64, (Arc<Vec<CircuitRewrite>>, ModifiedRegion)> =
+            Default::default();
+
+        // The rewrites leading to each queued circuit, if a profile of the
+        // rewrites leading to the best circuit is recorded.
+        let mut paths: FxHashMap<u64, RewritePath> = Default::default();
+        let mut best_path = RewritePath::new();
+
+        let mut rng = opt.seed.map(seed::rng);
 
         let mut circ_cnt = 0;
         let mut timeout_flag = false;
-        while let Some(Entry { circ, cost, .. }) = pq.pop() {
+        while let Some(Entry { circ, cost, hash }) = pq.pop() {
+            // The candidates obtained from the circuit share it as their base.
+            let circ = Arc::new(circ.into_circuit());
+            let path = paths.remove(&hash).unwrap_or_default();
             if cost < best_circ_cost {
-                best_circ = circ.clone();
+                best_circ = (*circ).clone();
                 best_circ_cost = cost.clone();
-                let num_rewrites = best_circ.rewrite_trace().map(|rs| rs.len());
-                logger.log_best(&best_circ_cost, num_rewrites);
+                best_path = path.clone();
+                logger.log_best(&best_circ, &best_circ_cost, None);
+                callback.on_new_best(&best_circ, &best_circ_cost);
                 last_best_time = Instant::now();
             }
             circ_cnt += 1;
 
-            let rewrites = self.rewriter.get_rewrites(&circ);
+            let mut rewrites = match (opt.match_radius, incremental.remove(&hash)) {
+                (Some(radius), Some((parent_rewrites, modified))) => {
+                    update_rewrites(&self.rewriter, &circ, &parent_rewrites, &modified, radius)
+                }
+                _ => self.rewriter.get_rewrites(&circ),
+            };
+            if let Some(rng) = &mut rng {
+                rewrites.shuffle(rng);
+            }
+            if let Some(profile) = &opt.profile {
+                rewrites = profile.order_rewrites(rewrites, &circ);
+            }
             logger.register_branching_factor(rewrites.len());
-
+            let shared_rewrites = opt
+                .match_radius
+                .is_some()
+                .then(|| Arc::new(rewrites.clone()));
+            let qubits = logger.records_profile().then(|| node_qubits(&circ));
             // Get combinations of rewrites that can be applied to the circuit,
             // and filter them to keep only the ones that
             //
             // - Don't have a worse cost than the last candidate in the priority queue.
             // - Do not invalidate the circuit by creating a loop.
             // - We haven't seen yet.
-            for r in self.strategy.apply_rewrites(rewrites, &circ) {
+            for mut r in self.strategy.apply_rewrites(rewrites, &circ) {
                 let new_circ_cost = cost.add_delta(&r.cost_delta);
                 if !pq.check_accepted(&new_circ_cost) {
                     continue;
                 }
 
-                let Ok(new_circ_hash) = r.circ.circuit_hash() else {
+                let Some(new_circ_hash) = seen_hash(&r.circ, opt.canonical_hashing) else {
                     // The composed rewrites produced a loop.
                     //
                     // See [https://github.com/CQCL/tket2/discussions/242]
@@ -236,8 +401,37 @@ where
                     continue;
                 }
 
-                pq.push_unchecked(r.circ, new_circ_hash, new_circ_cost);
-                logger.log_progress(circ_cnt, Some(pq.len()), seen_hashes.len());
+                if let (Some(shared), Some(modified)) = (&shared_rewrites, r.modified.take()) {
+                    incremental.insert(new_circ_hash, (shared.clone(), modified));
+                }
+                if let (Some(qubits), Some(rewrites)) = (&qubits, &r.rewrites) {
+                    let rewrites = rewrites
+                        .iter()
+                        .map(|rw| ProfiledRewrite::new(rw, &circ, qubits))
+                        .collect();
+                    paths.insert(new_circ_hash, path.extend(rewrites));
+                }
+                pq.push_unchecked(
+                    CircuitSnapshot::from_result(&circ, r, &opt),
+                    new_circ_hash,
+                    new_circ_cost,
+                );
+                if logger.log_progress(circ_cnt, Some(pq.len()), seen_hashes.len()) {
+                    callback.on_progress(&BadgerProgress {
+                        circuits_processed: circ_cnt,
+                        circuits_seen: seen_hashes.len(),
+                        queue_length: pq.len(),
+                        elapsed: start_time.elapsed(),
+                    });
+                }
+            }
+            // Forget the circuits that were dropped from the queue.
+            incremental.retain(|&hash, _| pq.contains(hash));
+            paths.retain(|&hash, _| pq.contains(hash));
+
+            if let Some(n) = logger.take_frontier_request() {
+                let snapshot = FrontierSnapshot::new(circ_cnt, start_time.elapsed(), pq.top(n));
+                logger.log_frontier(&snapshot);
             }
 
             if let Some(timeout) = opt.timeout {
@@ -247,7 +441,7 @@ where
                 }
             }
             if let Some(p_timeout) = opt.progress_timeout {
-                if last_best_time.elapsed().as_secs() > p_timeout {
+                if last_best_time.elapsed() >= Duration::from_secs(p_timeout) {
                     timeout_flag = true;
                     break;
                 }
@@ -260,6 +454,16 @@ where
             }
         }
 
+        if seen_hashes.is_probabilistic() {
+            logger
+                .warn("The seen circuits exceeded the memory budget. Their count is approximate.");
+        }
+        if timeout_flag {
+            callback.on_timeout(&best_circ, &best_circ_cost);
+        }
+        if logger.records_profile() {
+            logger.log_profile(&best_path.to_profile());
+        }
         logger.log_processing_end(
             circ_cnt,
             Some(seen_hashes.len()),
@@ -273,45 +477,69 @@ where
 
     /// Run the Badger optimiser on a circuit, using multiple threads.
     ///
-    /// This is the multi-threaded version of [`Self::badger`], using a single
-    /// priority queue and multiple workers to process the circuits in parallel.
-    #[tracing::instrument(target = "badger::metrics", skip(self, circ, logger))]
+    /// This is the multi-threaded version of [`Self::badger`], using a priority
+    /// queue sharded between multiple workers to process the circuits in
+    /// parallel. Workers steal circuits from each other's shards when their own
+    /// is empty, and the optimisation stops once all the shards are exhausted.
+    #[tracing::instrument(target = "badger::metrics", skip(self, circ, logger, callback))]
     fn badger_multithreaded(
         &self,
         circ: &Circuit<impl HugrView>,
         mut logger: BadgerLogger,
+        mut callback: impl OptimiserCallback<S::Cost>,
         opt: BadgerOptions,
     ) -> Circuit {
         let start_time = Instant::now();
         let n_threads: usize = opt.n_threads.get();
         let circ = circ.to_owned();
 
-        // multi-consumer priority channel for queuing circuits to be processed by the workers
+        // Sharded priority queue of circuits to be processed by the workers,
+        // and a channel for logging results and statistics to the main thread.
         let cost_fn = {
             let strategy = self.strategy.clone();
             move |circ: &'_ Circuit| strategy.circuit_cost(circ)
         };
-        let (pq, rx_log) = HugrPriorityChannel::init(cost_fn.clone(), opt.queue_size);
+        let (tx_log, rx_log) = crossbeam_channel::unbounded();
+        let pq = Arc::new(ShardedHugrPQ::new(
+            cost_fn,
+            opt.queue_size,
+            opt.max_seen_memory,
+            n_threads,
+            tx_log,
+        ));
 
-        let initial_circ_hash = circ.circuit_hash().unwrap();
+        let initial_circ_hash = seen_hash(&circ, opt.canonical_hashing).unwrap();
         let mut best_circ = circ.clone();
         let mut best_circ_cost = self.cost(&best_circ);
 
-        // Initialise the work channels and send the initial circuit.
-        pq.send(vec![Work {
-            cost: best_circ_cost.clone(),
-            hash: initial_circ_hash,
-            circ,
-        }])
-        .unwrap();
+        // Queue the initial circuit.
+        pq.push(
+            0,
+            vec![Work {
+                cost: best_circ_cost.clone(),
+                hash: initial_circ_hash,
+                circ: circ.into(),
+            }],
+        );
 
-        // Each worker waits for circuits to scan for rewrites using all the
-        // patterns and sends the results back to main.
+        // Each worker processes the circuits of its own queue shard, stealing
+        // from the other shards when it runs out of work. The `done` channel is
+        // disconnected once all the workers have terminated.
+        let (tx_done, rx_done) = crossbeam_channel::bounded::<()>(0);
         let joins: Vec<_> = (0..n_threads)
             .map(|i| {
-                BadgerWorker::spawn(i, pq.clone(), self.rewriter.clone(), self.strategy.clone())
+                BadgerWorker::spawn(
+                    i,
+                    pq.clone(),
+                    self.rewriter.clone(),
+                    self.strategy.clone(),
+                    opt.clone(),
+                    opt.seed.map(|seed| seed::derive(seed, i as u64)),
+                    tx_done.clone(),
+                )
             })
             .collect();
+        drop(tx_done);
 
         // Deadline for the optimisation timeout
         let timeout_event = match opt.timeout {
@@ -325,83 +553,109 @@ where
             Some(t) => crossbeam_channel::at(Instant::now() + Duration::from_secs(t)),
         };
 
+        // Periodic check for requested snapshots of the queue
+        let frontier_event = match logger.logs_frontier() {
+            false => crossbeam_channel::never(),
+            true => crossbeam_channel::tick(Duration::from_millis(100)),
+        };
+
         // Main loop: log best circuits as they come in from the priority queue,
-        // until the timeout is reached.
+        // until the timeout is reached or the workers run out of circuits.
         let mut timeout_flag = false;
-        let mut processed_count = 0;
-        let mut seen_count = 0;
         loop {
             select! {
                 recv(rx_log) -> msg => {
                     match msg {
-                        Ok(PriorityChannelLog::NewBestCircuit(circ, cost)) => {
+                        Ok(PriorityQueueLog::NewBestCircuit(circ, cost, worker)) => {
                             if cost < best_circ_cost {
                                 best_circ = circ;
                                 best_circ_cost = cost;
-                                let num_rewrites = best_circ.rewrite_trace().map(|rs| rs.len());
-                                logger.log_best(&best_circ_cost, num_rewrites);
+                                logger.log_best(&best_circ, &best_circ_cost, Some(worker));
+                                callback.on_new_best(&best_circ, &best_circ_cost);
                                 if let Some(t) = opt.progress_timeout {
                                     progress_timeout_event = crossbeam_channel::at(Instant::now() + Duration::from_secs(t));
                                 }
                             }
                         },
-                        Ok(PriorityChannelLog::CircuitCount{processed_count: proc, seen_count: seen, queue_length}) => {
-                            processed_count = proc;
-                            seen_count = seen;
+                        Ok(PriorityQueueLog::CircuitCount{processed_count, seen_count, queue_length}) => {
                             if let Some(max_circuit_count) = opt.max_circuit_count {
                                 if seen_count > max_circuit_count {
                                     timeout_flag = true;
                                     // Signal the workers to stop.
-                                    let _ = pq.close();
+                                    pq.close();
                                     break;
                                 }
                             }
-                            logger.log_progress(processed_count, Some(queue_length), seen_count);
+                            if logger.log_progress(processed_count, Some(queue_length), seen_count) {
+                                callback.on_progress(&BadgerProgress {
+                                    circuits_processed: processed_count,
+                                    circuits_seen: seen_count,
+                                    queue_length,
+                                    elapsed: start_time.elapsed(),
+                                });
+                            }
                         }
                         Err(crossbeam_channel::RecvError) => {
-                            logger.log("The priority channel panicked. Stopping Badger optimisation.");
-                            let _ = pq.close();
-                            break;
+                            unreachable!("The queue keeps the log channel open.")
                         }
                     }
                 }
+                recv(rx_done) -> _ => {
+                    // All the workers terminated.
+                    break;
+                }
                 recv(timeout_event) -> _ => {
                     timeout_flag = true;
                     // Signal the workers to stop.
-                    let _ = pq.close();
+                    pq.close();
                     break;
                 }
                 recv(progress_timeout_event) -> _ => {
                     timeout_flag = true;
                     // Signal the workers to stop.
-                    let _ = pq.close();
+                    pq.close();
                     break;
                 }
+                recv(frontier_event) -> _ => {
+                    if let Some(n) = logger.take_frontier_request() {
+                        let snapshot = FrontierSnapshot::new(pq.processed_count(), start_time.elapsed(), pq.top(n));
+                        logger.log_frontier(&snapshot);
+                    }
+                }
             }
         }
 
-        // Empty the log from the priority queue and store final circuit count.
-        while let Ok(log) = rx_log.recv() {
-            match log {
-                PriorityChannelLog::NewBestCircuit(circ, cost) => {
-                    if cost < best_circ_cost {
-                        best_circ = circ;
-                        best_circ_cost = cost;
-                        let num_rewrites = best_circ.rewrite_trace().map(|rs| rs.len());
-                        logger.log_best(&best_circ_cost, num_rewrites);
-                    }
-                }
-                PriorityChannelLog::CircuitCount {
-                    processed_count: proc,
-                    seen_count: seen,
-                    queue_length,
-                } => {
-                    processed_count = proc;
-                    seen_count = seen;
-                    logger.log_progress(processed_count, Some(queue_length), seen_count);
+        // Wait for the workers to finish their current circuit, and read the
+        // remaining logs.
+        joins.into_iter().for_each(|j| j.join().unwrap());
+        for log in rx_log.try_iter() {
+            if let PriorityQueueLog::NewBestCircuit(circ, cost, worker) = log {
+                if cost < best_circ_cost {
+                    best_circ = circ;
+                    best_circ_cost = cost;
+                    logger.log_best(&best_circ, &best_circ_cost, Some(worker));
+                    callback.on_new_best(&best_circ, &best_circ_cost);
                 }
             }
         }
+        let processed_count = pq.processed_count();
+        let seen_count = pq.seen_count();
+        let queue_length = pq.len();
+        if logger.log_progress(processed_count, Some(queue_length), seen_count) {
+            callback.on_progress(&BadgerProgress {
+                circuits_processed: processed_count,
+                circuits_seen: seen_count,
+                queue_length,
+                elapsed: start_time.elapsed(),
+            });
+        }
+        if pq.seen_is_probabilistic() {
+            logger
+                .warn("The seen circuits exceeded the memory budget. Their count is approximate.");
+        }
+        if timeout_flag {
+            callback.on_timeout(&best_circ, &best_circ_cost);
+        }
         logger.log_processing_end(
             processed_count,
             Some(seen_count),
@@ -411,34 +665,92 @@ where
             start_time.elapsed(),
         );
 
-        joins.into_iter().for_each(|j| j.join().unwrap());
-
         best_circ
     }
 
     /// Run the Badger optimiser on a circuit, with data parallel multithreading.
     ///
     /// Split the circuit into chunks and process each in a separate thread.
-    #[tracing::instrument(target = "badger::metrics", skip(self, circ, logger))]
+    /// With [`BadgerOptions::split_rounds`], the result is re-split with
+    /// shifted chunk boundaries and optimised again, until a full cycle of
+    /// offsets brings no improvement.
+    #[tracing::instrument(target = "badger::metrics", skip(self, circ, logger, callback))]
     fn badger_split_multithreaded(
         &self,
         circ: &Circuit<impl HugrView>,
         mut logger: BadgerLogger,
+        mut callback: impl OptimiserCallback<S::Cost>,
         opt: BadgerOptions,
     ) -> Result<Circuit, HugrError> {
         let start_time = Instant::now();
-        let circ = circ.to_owned();
-        let circ_cost = self.cost(&circ);
-        let max_chunk_cost = circ_cost.clone().div_cost(opt.n_threads);
+        let mut best_circ = circ.to_owned();
+        let mut best_circ_cost = self.cost(&best_circ);
+        logger.log_best(&best_circ, best_circ_cost.clone(), None);
+        callback.on_new_best(&best_circ, &best_circ_cost);
+
+        let n_offsets = opt.split_offsets.max(1);
+        let mut rounds = 0;
+        let mut rounds_without_improvement = 0;
+        while rounds < opt.split_rounds.max(1) && rounds_without_improvement < n_offsets {
+            // Shift the chunk boundaries by a fraction of the chunk size.
+            let max_chunk_cost = best_circ_cost.div_cost(opt.n_threads);
+            let offset_step = max_chunk_cost.div_cost(NonZeroUsize::new(n_offsets).unwrap());
+            let offset = iter::repeat(offset_step).take(rounds % n_offsets).sum();
+            if opt.split_rounds > 1 {
+                logger.log(format!("Round {rounds}, with chunk offset {offset:?}."));
+            }
+
+            let circ = self.optimise_chunks(&best_circ, max_chunk_cost, offset, &logger, &opt)?;
+            let circ_cost = self.cost(&circ);
+            rounds += 1;
+            if circ_cost < best_circ_cost {
+                logger.log_best(&circ, circ_cost.clone(), None);
+                callback.on_new_best(&circ, &circ_cost);
+                best_circ = circ;
+                best_circ_cost = circ_cost;
+                rounds_without_improvement = 0;
+            } else {
+                rounds_without_improvement += 1;
+            }
+        }
+
+        logger.log_processing_end(
+            opt.n_threads.get() * rounds,
+            None,
+            best_circ_cost,
+            true,
+            false,
+            start_time.elapsed(),
+        );
+
+        Ok(best_circ)
+    }
+
+    /// Split the circuit into chunks of at most `max_chunk_cost`, optimise each
+    /// in a separate thread, and reassemble the result.
+    ///
+    /// The first chunk is shortened by `offset`, see
+    /// [`CircuitChunks::split_with_offset`].
+    fn optimise_chunks(
+        &self,
+        circ: &Circuit,
+        max_chunk_cost: S::Cost,
+        offset: S::Cost,
+        logger: &BadgerLogger,
+        opt: &BadgerOptions,
+    ) -> Result<Circuit, HugrError> {
         logger.log(format!(
             "Splitting circuit with cost {:?} into chunks of at most {max_chunk_cost:?}.",
-            circ_cost.clone()
+            self.cost(circ)
         ));
-        let mut chunks =
-            CircuitChunks::split_with_cost(&circ, max_chunk_cost, |op| self.strategy.op_cost(op));
-
-        let num_rewrites = circ.rewrite_trace().map(|rs| rs.len());
-        logger.log_best(circ_cost.clone(), num_rewrites);
+        let mut chunks = CircuitChunks::split_with_offset(circ, max_chunk_cost, offset, |op| {
+            self.strategy.op_cost(op)
+        });
+        let chunk_opt = BadgerOptions {
+            n_threads: NonZeroUsize::new(1).unwrap(),
+            split_circuit: false,
+            ..opt.clone()
+        };
 
         let (joins, rx_work): (Vec<_>, Vec<_>) = chunks
             .par_iter_mut()
@@ -447,19 +759,13 @@ where
                 let (tx, rx) = crossbeam_channel::unbounded();
                 let badger = self.clone();
                 let chunk = mem::take(chunk);
+                let chunk_opt = chunk_opt.clone();
                 let chunk_cx_cost = chunk.circuit_cost(|op| self.strategy.op_cost(op));
                 logger.log(format!("Chunk {i} has {chunk_cx_cost:?} CX gates",));
                 let join = thread::Builder::new()
                     .name(format!("chunk-{}", i))
                     .spawn(move || {
-                        let res = badger.optimise(
-                            &chunk,
-                            BadgerOptions {
-                                n_threads: NonZeroUsize::new(1).unwrap(),
-                                split_circuit: false,
-                                ..opt
-                            },
-                        );
+                        let res = badger.optimise(&chunk, chunk_opt);
                         tx.send(res).unwrap();
                     })
                     .unwrap();
@@ -473,25 +779,30 @@ where
                 .unwrap_or_else(|_| panic!("Worker thread panicked"));
             chunks[i] = res;
         }
+        joins.into_iter().for_each(|j| j.join().unwrap());
 
-        let best_circ = chunks.reassemble()?;
-        let best_circ_cost = self.cost(&best_circ);
-        if best_circ_cost.clone() < circ_cost {
-            let num_rewrites = best_circ.rewrite_trace().map(|rs| rs.len());
-            logger.log_best(best_circ_cost.clone(), num_rewrites);
+        match opt.split_overlap {
+            Some(overlap) => {
+                logger.log(format!(
+                    "Re-optimising {overlap} operations around each chunk boundary."
+                ));
+                chunks.reassemble_with_overlap(overlap, |window| {
+                    self.optimise(&window, chunk_opt.clone())
+                })
+            }
+            None => chunks.reassemble(),
         }
+    }
+}
 
-        logger.log_processing_end(
-            opt.n_threads.get(),
-            None,
-            best_circ_cost,
-            true,
-            false,
-            start_time.elapsed(),
-        );
-        joins.into_iter().for_each(|j| j.join().unwrap());
-
-        Ok(best_circ)
+/// Hash a circuit to identify the circuits already seen.
+///
+/// Returns `None` if the circuit is not valid. See
+/// [`BadgerOptions::canonical_hashing`].
+fn seen_hash(circ: &Circuit, canonical: bool) -> Option<u64> {
+    match canonical {
+        true => circ.canonical_hash().ok(),
+        false => circ.circuit_hash().ok(),
     }
 }
 
@@ -502,9 +813,10 @@ mod badger_default {
 
     use hugr::ops::OpType;
 
+    #[cfg(feature = "binary-eccs")]
     use crate::rewrite::ecc_rewriter::RewriterSerialisationError;
     use crate::rewrite::strategy::{ExhaustiveGreedyStrategy, LexicographicCostFunction};
-    use crate::rewrite::ECCRewriter;
+    use crate::rewrite::{ECCPruneOptions, ECCRewriter};
 
     use super::*;
 
@@ -522,6 +834,31 @@ mod badger_default {
             Ok(BadgerOptimiser::new(rewriter, strategy))
         }
 
+        /// A sane default optimiser using the merged ECC sets of several
+        /// JSON files.
+        ///
+        /// See [`ECCRewriter::try_from_eccs_json_files`].
+        pub fn default_with_eccs_json_files(
+            eccs_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        ) -> io::Result<Self> {
+            let rewriter = ECCRewriter::try_from_eccs_json_files(eccs_paths)?;
+            let strategy = LexicographicCostFunction::default_cx();
+            Ok(BadgerOptimiser::new(rewriter, strategy))
+        }
+
+        /// A sane default optimiser using the merged ECC sets of several
+        /// JSON files, keeping only the circuits accepted by `options`.
+        ///
+        /// See [`ECCRewriter::try_from_eccs_json_files_pruned`].
+        pub fn default_with_eccs_json_files_pruned(
+            eccs_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+            options: &ECCPruneOptions,
+        ) -> io::Result<Self> {
+            let rewriter = ECCRewriter::try_from_eccs_json_files_pruned(eccs_paths, options)?;
+            let strategy = LexicographicCostFunction::default_cx();
+            Ok(BadgerOptimiser::new(rewriter, strategy))
+        }
+
         /// A sane default optimiser using a precompiled binary rewriter.
         #[cfg(feature = "binary-eccs")]
         pub fn default_with_rewriter_binary(
@@ -531,12 +868,34 @@ mod badger_default {
             let strategy = LexicographicCostFunction::default_cx();
             Ok(BadgerOptimiser::new(rewriter, strategy))
         }
+
+        /// A sane default optimiser using the merged ECC sets of several
+        /// JSON files, caching the compiled rewriter at `compiled_path`.
+        ///
+        /// See [`ECCRewriter::try_from_eccs_json_files_cached`].
+        #[cfg(feature = "binary-eccs")]
+        pub fn default_with_compiled_rewriter(
+            eccs_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+            compiled_path: impl AsRef<Path>,
+        ) -> Result<Self, RewriterSerialisationError> {
+            let rewriter = ECCRewriter::try_from_eccs_json_files_cached(eccs_paths, compiled_path)?;
+            let strategy = LexicographicCostFunction::default_cx();
+            Ok(BadgerOptimiser::new(rewriter, strategy))
+        }
+
+        /// Drop the rewrite rules that act on more qubits than `circ` has.
+        ///
+        /// See [`ECCRewriter::specialise_to_qubits`].
+        pub fn specialise_to_circuit(mut self, circ: &Circuit<impl HugrView>) -> Self {
+            self.rewriter = self.rewriter.specialise_to_qubits(circ.qubit_count());
+            self
+        }
     }
 }
 #[cfg(feature = "portmatching")]
 pub use badger_default::DefaultBadgerOptimiser;
 
-use self::hugr_pchannel::Work;
+use self::sharded_pqueue::Work;
 
 #[cfg(test)]
 #[cfg(feature = "portmatching")]
@@ -549,8 +908,16 @@ mod tests {
     };
     use rstest::{fixture, rstest};
 
-    use crate::optimiser::badger::BadgerOptions;
-    use crate::serialize::load_tk1_json_str;
+    use std::fmt::Debug;
+    use std::sync::Arc;
+
+    use crate::circuit::CircuitHash;
+    use crate::optimiser::badger::{
+        BadgerEventKind, BadgerLogger, BadgerOptions, FrontierRequest, FrontierSnapshot,
+        OptimiserCallback, RunLog,
+    };
+    use crate::rewrite::profile::RewriteProfile;
+    use crate::serialize::{load_tk1_json_str, DecodeOptions};
     use crate::{extension::REGISTRY, Circuit, Tk2Op};
 
     use super::{BadgerOptimiser, DefaultBadgerOptimiser};
@@ -602,7 +969,7 @@ mod tests {
     /// A circuit that would trigger non-composable rewrites, if we applied them blindly from nam_6_3 matches.
     #[fixture]
     fn non_composable_rw_hugr() -> Circuit {
-        load_tk1_json_str(NON_COMPOSABLE).unwrap()
+        load_tk1_json_str(NON_COMPOSABLE, DecodeOptions::default()).unwrap()
     }
 
     /// A badger optimiser using a reduced set of rewrite rules.
@@ -641,6 +1008,228 @@ mod tests {
         assert_eq!(gates(&opt_rz), vec![Tk2Op::AngleAdd, Tk2Op::RzF64]);
     }
 
+    #[rstest]
+    fn rz_rz_cancellation_incremental(rz_rz: Circuit, badger_opt_json: DefaultBadgerOptimiser) {
+        let mut opt_rz = badger_opt_json.optimise(
+            &rz_rz,
+            BadgerOptions {
+                queue_size: 4,
+                match_radius: Some(4),
+                ..Default::default()
+            },
+        );
+        opt_rz.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert_eq!(gates(&opt_rz), vec![Tk2Op::AngleAdd, Tk2Op::RzF64]);
+    }
+
+    #[rstest]
+    #[case::single_thread(1)]
+    #[case::parallel(2)]
+    fn rz_rz_cancellation_bounded_seen(
+        rz_rz: Circuit,
+        badger_opt_json: DefaultBadgerOptimiser,
+        #[case] n_threads: usize,
+    ) {
+        let mut opt_rz = badger_opt_json.optimise(
+            &rz_rz,
+            BadgerOptions {
+                queue_size: 4,
+                // Track the seen circuits with a Bloom filter from the start.
+                max_seen_memory: Some(0),
+                n_threads: n_threads.try_into().unwrap(),
+                ..Default::default()
+            },
+        );
+        opt_rz.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert_eq!(gates(&opt_rz), vec![Tk2Op::AngleAdd, Tk2Op::RzF64]);
+    }
+
+    #[rstest]
+    #[case::single_thread(1)]
+    #[case::parallel(2)]
+    fn rz_rz_cancellation_seeded(
+        rz_rz: Circuit,
+        badger_opt_json: DefaultBadgerOptimiser,
+        #[case] n_threads: usize,
+    ) {
+        let options = BadgerOptions {
+            queue_size: 4,
+            n_threads: n_threads.try_into().unwrap(),
+            seed: Some(42),
+            ..Default::default()
+        };
+        let mut opt_rz = badger_opt_json.optimise(&rz_rz, options.clone());
+        opt_rz.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert_eq!(gates(&opt_rz), vec![Tk2Op::AngleAdd, Tk2Op::RzF64]);
+
+        if n_threads == 1 {
+            let again = badger_opt_json.optimise(&rz_rz, options);
+            assert_eq!(
+                again.circuit_hash().unwrap(),
+                opt_rz.circuit_hash().unwrap()
+            );
+        }
+    }
+
+    #[rstest]
+    #[case::single_thread(1)]
+    #[case::parallel(2)]
+    fn rz_rz_cancellation_canonical_hash(
+        rz_rz: Circuit,
+        badger_opt_json: DefaultBadgerOptimiser,
+        #[case] n_threads: usize,
+    ) {
+        let mut opt_rz = badger_opt_json.optimise(
+            &rz_rz,
+            BadgerOptions {
+                queue_size: 4,
+                canonical_hashing: true,
+                n_threads: n_threads.try_into().unwrap(),
+                ..Default::default()
+            },
+        );
+        opt_rz.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert_eq!(gates(&opt_rz), vec![Tk2Op::AngleAdd, Tk2Op::RzF64]);
+    }
+
+    #[rstest]
+    fn frontier_snapshot(rz_rz: Circuit, badger_opt_json: DefaultBadgerOptimiser) {
+        let request = FrontierRequest::new();
+        let mut frontier_log = Vec::new();
+        let logger =
+            BadgerLogger::default().with_frontier_log(request.clone(), 2, &mut frontier_log);
+
+        // Handled after processing the first circuit.
+        request.request();
+        badger_opt_json.optimise_with_log(
+            &rz_rz,
+            logger,
+            (),
+            BadgerOptions {
+                queue_size: 4,
+                ..Default::default()
+            },
+        );
+
+        let snapshots = FrontierSnapshot::<String>::load_json(frontier_log.as_slice()).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].circuits_processed, 1);
+        assert!(!snapshots[0].entries.is_empty());
+        assert!(snapshots[0].entries.len() <= 2);
+    }
+
+    #[rstest]
+    fn profile_guided(rz_rz: Circuit, badger_opt_json: DefaultBadgerOptimiser) {
+        let mut profile_log = Vec::new();
+        let logger = BadgerLogger::default().with_profile_log(&mut profile_log);
+        let options = BadgerOptions {
+            queue_size: 4,
+            ..Default::default()
+        };
+        badger_opt_json.optimise_with_log(&rz_rz, logger, (), options.clone());
+
+        // Only the rewrites leading to the best circuit are recorded.
+        let profile: RewriteProfile = serde_json::from_slice(&profile_log).unwrap();
+        assert!(!profile.is_empty());
+        assert_eq!(profile.region_stats("0").map(|stats| stats.count), Some(1));
+
+        let mut opt_rz = badger_opt_json.optimise(
+            &rz_rz,
+            BadgerOptions {
+                profile: Some(Arc::new(profile)),
+                ..options
+            },
+        );
+        opt_rz.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert_eq!(gates(&opt_rz), vec![Tk2Op::AngleAdd, Tk2Op::RzF64]);
+    }
+
+    #[rstest]
+    #[case(1)]
+    #[case(2)]
+    fn event_log(
+        rz_rz: Circuit,
+        badger_opt_json: DefaultBadgerOptimiser,
+        #[case] n_threads: usize,
+    ) {
+        let mut event_log = Vec::new();
+        let logger = BadgerLogger::default().with_event_log(&mut event_log);
+        let opt_rz = badger_opt_json.optimise_with_log(
+            &rz_rz,
+            logger,
+            (),
+            BadgerOptions {
+                n_threads: n_threads.try_into().unwrap(),
+                queue_size: 4,
+                ..Default::default()
+            },
+        );
+
+        // Costs are serialised as strings.
+        let run = RunLog::<String>::load_json(event_log.as_slice()).unwrap();
+        let opt_cost = format!("{:?}", badger_opt_json.cost(&opt_rz));
+        assert_eq!(run.best_costs().last(), Some(&opt_cost));
+        let Some(BadgerEventKind::End { cost, timeout, .. }) = run.end().map(|e| &e.kind) else {
+            panic!("Missing end event");
+        };
+        assert_eq!(cost, &opt_cost);
+        assert!(!timeout);
+    }
+
+    /// Records the calls to the optimiser callbacks.
+    #[derive(Default)]
+    struct RecordCallback {
+        best_costs: Vec<String>,
+        timeouts: usize,
+    }
+
+    impl<C: Debug> OptimiserCallback<C> for RecordCallback {
+        fn on_new_best(&mut self, _circ: &Circuit, cost: &C) {
+            self.best_costs.push(format!("{cost:?}"));
+        }
+
+        fn on_timeout(&mut self, _best_circ: &Circuit, _best_cost: &C) {
+            self.timeouts += 1;
+        }
+    }
+
+    #[rstest]
+    #[case(1)]
+    #[case(2)]
+    fn callback_new_best(
+        rz_rz: Circuit,
+        badger_opt_json: DefaultBadgerOptimiser,
+        #[case] n_threads: usize,
+    ) {
+        let mut callback = RecordCallback::default();
+        let opt_rz = badger_opt_json.optimise_with_log(
+            &rz_rz,
+            BadgerLogger::default(),
+            &mut callback,
+            BadgerOptions {
+                n_threads: n_threads.try_into().unwrap(),
+                queue_size: 4,
+                ..Default::default()
+            },
+        );
+        let opt_cost = format!("{:?}", badger_opt_json.cost(&opt_rz));
+        assert_eq!(callback.best_costs.last(), Some(&opt_cost));
+        assert_eq!(callback.timeouts, 0);
+    }
+
+    #[rstest]
+    #[case::max_circuit_count(BadgerOptions { max_circuit_count: Some(1), ..Default::default() })]
+    #[case::progress_timeout(BadgerOptions { progress_timeout: Some(0), ..Default::default() })]
+    fn callback_timeout(
+        rz_rz: Circuit,
+        badger_opt_json: DefaultBadgerOptimiser,
+        #[case] options: BadgerOptions,
+    ) {
+        let mut callback = RecordCallback::default();
+        badger_opt_json.optimise_with_log(&rz_rz, BadgerLogger::default(), &mut callback, options);
+        assert_eq!(callback.timeouts, 1);
+    }
+
     #[rstest]
     #[case::compiled(badger_opt_compiled())]
     #[case::json(badger_opt_json())]
@@ -657,12 +1246,33 @@ mod tests {
         opt_rz.hugr_mut().update_validate(&REGISTRY).unwrap();
     }
 
+    #[rstest]
+    #[case::compiled(badger_opt_compiled())]
+    #[case::json(badger_opt_json())]
+    fn rz_rz_cancellation_parallel_exhaustive(
+        rz_rz: Circuit,
+        #[case] badger_opt: DefaultBadgerOptimiser,
+    ) {
+        // Without a timeout, the workers stop once the queue is exhausted.
+        let mut opt_rz = badger_opt.optimise(
+            &rz_rz,
+            BadgerOptions {
+                n_threads: 2.try_into().unwrap(),
+                queue_size: 4,
+                ..Default::default()
+            },
+        );
+        opt_rz.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert_eq!(opt_rz.commands().count(), 2);
+    }
+
     #[rstest]
     #[case::compiled(badger_opt_compiled())]
     #[case::json(badger_opt_json())]
     fn rz_rz_cancellation_split_parallel(
         rz_rz: Circuit,
         #[case] badger_opt: DefaultBadgerOptimiser,
+        #[values(None, Some(2))] split_overlap: Option<usize>,
     ) {
         let mut opt_rz = badger_opt.optimise(
             &rz_rz,
@@ -671,6 +1281,24 @@ mod tests {
                 n_threads: 2.try_into().unwrap(),
                 queue_size: 4,
                 split_circuit: true,
+                split_overlap,
+                ..Default::default()
+            },
+        );
+        opt_rz.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert_eq!(opt_rz.commands().count(), 2);
+    }
+
+    #[rstest]
+    fn rz_rz_cancellation_split_rounds(rz_rz: Circuit, badger_opt_json: DefaultBadgerOptimiser) {
+        let mut opt_rz = badger_opt_json.optimise(
+            &rz_rz,
+            BadgerOptions {
+                timeout: Some(0),
+                n_threads: 2.try_into().unwrap(),
+                queue_size: 4,
+                split_circuit: true,
+                split_rounds: 3,
                 ..Default::default()
             },
         );
@@ -678,6 +1306,36 @@ mod tests {
         assert_eq!(opt_rz.commands().count(), 2);
     }
 
+    #[cfg(feature = "distributed")]
+    #[rstest]
+    fn rz_rz_cancellation_distributed(rz_rz: Circuit, badger_opt_json: DefaultBadgerOptimiser) {
+        use crate::optimiser::badger::distributed::{serve_tcp, WorkerConnection};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let worker_opt = badger_opt_json.clone();
+        std::thread::spawn(move || serve_tcp(&worker_opt, listener));
+
+        let mut workers = [
+            WorkerConnection::connect(addr).unwrap(),
+            WorkerConnection::connect(addr).unwrap(),
+        ];
+        let mut opt_rz = badger_opt_json
+            .optimise_distributed(
+                &rz_rz,
+                &mut workers,
+                BadgerOptions {
+                    timeout: Some(0),
+                    queue_size: 4,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        opt_rz.hugr_mut().update_validate(&REGISTRY).unwrap();
+        assert_eq!(opt_rz.commands().count(), 2);
+    }
+
     #[rstest]
     #[ignore = "Loading the ECC set is really slow (~5 seconds)"]
     fn non_composable_rewrites(
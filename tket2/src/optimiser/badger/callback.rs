@@ -0,0 +1,81 @@
+//! Callback hooks reporting the progress of the Badger optimiser.
+
+use std::time::Duration;
+
+use crate::Circuit;
+
+/// Statistics on the progress of an optimisation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BadgerProgress {
+    /// The number of circuits processed so far.
+    pub circuits_processed: usize,
+    /// The number of distinct circuits seen so far.
+    pub circuits_seen: usize,
+    /// The number of circuits waiting in the queue.
+    pub queue_length: usize,
+    /// The time elapsed since the start of the optimisation.
+    pub elapsed: Duration,
+}
+
+/// Hooks called by the Badger optimiser as the optimisation progresses.
+///
+/// This lets library users stream the progress of an optimisation, for
+/// example to a user interface, without parsing the logs. All methods do
+/// nothing by default.
+///
+/// The hooks are always called from the thread running the optimisation,
+/// so they do not need to be thread-safe. When the circuit is split between
+/// threads (see [`BadgerOptions::split_circuit`]), only the initial and final
+/// circuits are reported.
+///
+/// [`BadgerOptions::split_circuit`]: super::BadgerOptions::split_circuit
+pub trait OptimiserCallback<C> {
+    /// Called when a circuit with a lower cost than the previous best is found.
+    fn on_new_best(&mut self, _circ: &Circuit, _cost: &C) {}
+
+    /// Called periodically with statistics on the optimisation, at most once
+    /// a second.
+    fn on_progress(&mut self, _progress: &BadgerProgress) {}
+
+    /// Called when the optimisation is stopped by a timeout or by reaching the
+    /// maximum circuit count, with the best circuit found.
+    fn on_timeout(&mut self, _best_circ: &Circuit, _best_cost: &C) {}
+}
+
+/// No callbacks.
+impl<C> OptimiserCallback<C> for () {}
+
+/// Optional callbacks.
+impl<C, T: OptimiserCallback<C>> OptimiserCallback<C> for Option<T> {
+    fn on_new_best(&mut self, circ: &Circuit, cost: &C) {
+        if let Some(callback) = self {
+            callback.on_new_best(circ, cost)
+        }
+    }
+
+    fn on_progress(&mut self, progress: &BadgerProgress) {
+        if let Some(callback) = self {
+            callback.on_progress(progress)
+        }
+    }
+
+    fn on_timeout(&mut self, best_circ: &Circuit, best_cost: &C) {
+        if let Some(callback) = self {
+            callback.on_timeout(best_circ, best_cost)
+        }
+    }
+}
+
+impl<C, T: OptimiserCallback<C> + ?Sized> OptimiserCallback<C> for &mut T {
+    fn on_new_best(&mut self, circ: &Circuit, cost: &C) {
+        (**self).on_new_best(circ, cost)
+    }
+
+    fn on_progress(&mut self, progress: &BadgerProgress) {
+        (**self).on_progress(progress)
+    }
+
+    fn on_timeout(&mut self, best_circ: &Circuit, best_cost: &C) {
+        (**self).on_timeout(best_circ, best_cost)
+    }
+}
@@ -0,0 +1,292 @@
+//! Distributed optimisation over multiple processes or machines.
+//!
+//! [`BadgerOptimiser::optimise_distributed`] splits a circuit into chunks,
+//! sends each chunk to a worker, and reassembles the optimised results. Each
+//! worker runs [`run_worker`], reading requests from a stream and writing
+//! back the optimised chunks, either over the standard input and output of a
+//! child process (see [`WorkerConnection::spawn`]) or over a TCP socket (see
+//! [`serve_tcp`] and [`WorkerConnection::connect`]).
+//!
+//! Messages are exchanged as lines of JSON. The workers must use the same
+//! rewriter and strategy as the dispatching optimiser.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+
+use hugr::hugr::{HugrError, ValidationError};
+use hugr::{Hugr, HugrView};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{BadgerOptimiser, BadgerOptions};
+use crate::circuit::cost::CircuitCost;
+use crate::circuit::CircuitChunks;
+use crate::extension::REGISTRY;
+use crate::rewrite::strategy::RewriteStrategy;
+use crate::rewrite::Rewriter;
+use crate::Circuit;
+
+/// A request to optimise a chunk, sent to a worker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkRequest {
+    /// The index of the chunk.
+    pub id: usize,
+    /// The chunk to optimise.
+    pub circuit: Hugr,
+    /// The options for the optimisation.
+    pub options: BadgerOptions,
+}
+
+/// The optimised chunk, returned by a worker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkResponse {
+    /// The index of the chunk.
+    pub id: usize,
+    /// The optimised chunk.
+    pub circuit: Hugr,
+}
+
+/// Errors that can occur during a distributed optimisation.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum DistributedError {
+    /// An IO error occurred while communicating with a worker.
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    /// A message could not be encoded or decoded.
+    #[error("Invalid message: {0}")]
+    Message(#[from] serde_json::Error),
+    /// A received circuit is not valid.
+    #[error("Invalid circuit: {0}")]
+    InvalidCircuit(#[from] ValidationError),
+    /// A worker closed the connection before returning all its results.
+    #[error("A worker disconnected before returning its results")]
+    Disconnected,
+    /// A worker returned the result of an unexpected chunk.
+    #[error("Expected the result of chunk {expected}, but got chunk {got}")]
+    UnexpectedResponse {
+        /// The index of the expected chunk.
+        expected: usize,
+        /// The index of the returned chunk.
+        got: usize,
+    },
+    /// The optimised chunks could not be reassembled.
+    #[error("Could not reassemble the circuit: {0}")]
+    Reassemble(#[from] HugrError),
+    /// No workers were given.
+    #[error("No workers available")]
+    NoWorkers,
+}
+
+/// A connection to an optimisation worker.
+pub struct WorkerConnection {
+    reader: Box<dyn BufRead + Send>,
+    writer: Box<dyn Write + Send>,
+    /// The worker process, if it was spawned by this connection.
+    child: Option<Child>,
+}
+
+impl WorkerConnection {
+    /// Create a connection from the streams to and from a worker.
+    pub fn new(reader: impl Read + Send + 'static, writer: impl Write + Send + 'static) -> Self {
+        Self {
+            reader: Box::new(BufReader::new(reader)),
+            writer: Box::new(writer),
+            child: None,
+        }
+    }
+
+    /// Spawn a worker process, communicating over its standard input and
+    /// output.
+    ///
+    /// The process must run [`run_worker`] on its standard streams. It is
+    /// waited for when the connection is dropped.
+    pub fn spawn(command: &mut Command) -> io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let mut connection = Self::new(stdout, stdin);
+        connection.child = Some(child);
+        Ok(connection)
+    }
+
+    /// Connect to a worker listening on a TCP socket, see [`serve_tcp`].
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Self::new(stream.try_clone()?, stream))
+    }
+
+    /// Send a request, and wait for the response.
+    fn process(&mut self, request: &WorkRequest) -> Result<Circuit, DistributedError> {
+        serde_json::to_writer(&mut self.writer, request)?;
+        writeln!(self.writer)?;
+        self.writer.flush()?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(DistributedError::Disconnected);
+        }
+        let response: WorkResponse = serde_json::from_str(&line)?;
+        if response.id != request.id {
+            return Err(DistributedError::UnexpectedResponse {
+                expected: request.id,
+                got: response.id,
+            });
+        }
+        load_circuit(response.circuit)
+    }
+}
+
+impl Drop for WorkerConnection {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            // Close the worker's input so it terminates.
+            drop(mem::replace(&mut self.writer, Box::new(io::sink())));
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Resolve the operations of a deserialised circuit.
+fn load_circuit(mut hugr: Hugr) -> Result<Circuit, DistributedError> {
+    hugr.update_validate(&REGISTRY)?;
+    Ok(hugr.into())
+}
+
+/// Optimise the chunks requested on `reader`, writing the results to
+/// `writer`, until the end of the input.
+pub fn run_worker<R, S>(
+    optimiser: &BadgerOptimiser<R, S>,
+    reader: impl BufRead,
+    mut writer: impl Write,
+) -> Result<(), DistributedError>
+where
+    R: Rewriter + Send + Clone + Sync + 'static,
+    S: RewriteStrategy + Send + Sync + Clone + 'static,
+    S::Cost: serde::Serialize + Send + Sync,
+{
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: WorkRequest = serde_json::from_str(&line)?;
+        let circ = load_circuit(request.circuit)?;
+        let circ = optimiser.optimise(&circ, request.options);
+        let response = WorkResponse {
+            id: request.id,
+            circuit: circ.into_hugr(),
+        };
+        serde_json::to_writer(&mut writer, &response)?;
+        writeln!(writer)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Accept connections on a TCP socket, and run a worker for each of them in
+/// a separate thread.
+///
+/// This function only returns if accepting a connection fails.
+pub fn serve_tcp<R, S>(optimiser: &BadgerOptimiser<R, S>, listener: TcpListener) -> io::Result<()>
+where
+    R: Rewriter + Send + Clone + Sync + 'static,
+    S: RewriteStrategy + Send + Sync + Clone + 'static,
+    S::Cost: serde::Serialize + Send + Sync,
+{
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let optimiser = optimiser.clone();
+        thread::spawn(move || {
+            let reader = BufReader::new(stream.try_clone()?);
+            if let Err(e) = run_worker(&optimiser, reader, stream) {
+                tracing::warn!("Worker connection closed: {e}");
+            }
+            io::Result::Ok(())
+        });
+    }
+    Ok(())
+}
+
+impl<R, S> BadgerOptimiser<R, S>
+where
+    R: Rewriter + Send + Clone + Sync + 'static,
+    S: RewriteStrategy + Send + Sync + Clone + 'static,
+    S::Cost: serde::Serialize + Send + Sync,
+{
+    /// Run the Badger optimiser on a circuit, distributing chunks of the
+    /// circuit to workers.
+    ///
+    /// The circuit is split into one chunk per worker, and each worker
+    /// optimises its chunk with the given `options`. If
+    /// [`BadgerOptions::split_overlap`] is set, the boundaries between chunks
+    /// are re-optimised locally after reassembling the circuit.
+    pub fn optimise_distributed(
+        &self,
+        circ: &Circuit<impl HugrView>,
+        workers: &mut [WorkerConnection],
+        options: BadgerOptions,
+    ) -> Result<Circuit, DistributedError> {
+        let n_workers = workers
+            .len()
+            .try_into()
+            .map_err(|_| DistributedError::NoWorkers)?;
+        let circ = circ.to_owned();
+        let max_chunk_cost = self.cost(&circ).div_cost(n_workers);
+        let mut chunks =
+            CircuitChunks::split_with_cost(&circ, max_chunk_cost, |op| self.strategy.op_cost(op));
+        let chunk_opt = BadgerOptions {
+            split_circuit: false,
+            ..options
+        };
+
+        // Assign the chunks to the workers in turn. Each worker processes its
+        // chunks sequentially, in a separate thread.
+        let mut requests: Vec<Vec<WorkRequest>> = workers.iter().map(|_| Vec::new()).collect();
+        for (id, chunk) in chunks.iter().enumerate() {
+            requests[id % workers.len()].push(WorkRequest {
+                id,
+                circuit: chunk.hugr().clone(),
+                options: chunk_opt.clone(),
+            });
+        }
+        let results = thread::scope(|s| {
+            let handles: Vec<_> = workers
+                .iter_mut()
+                .zip(requests)
+                .map(|(worker, requests)| {
+                    s.spawn(move || {
+                        requests
+                            .iter()
+                            .map(|req| Ok((req.id, worker.process(req)?)))
+                            .collect::<Result<Vec<_>, DistributedError>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("Worker thread panicked"))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        for (id, chunk) in results.into_iter().flatten() {
+            chunks[id] = chunk;
+        }
+
+        Ok(match options.split_overlap {
+            Some(overlap) => chunks.reassemble_with_overlap(overlap, |window| {
+                let window_opt = BadgerOptions {
+                    n_threads: 1.try_into().unwrap(),
+                    ..chunk_opt.clone()
+                };
+                self.optimise(&window, window_opt)
+            })?,
+            None => chunks.reassemble()?,
+        })
+    }
+}
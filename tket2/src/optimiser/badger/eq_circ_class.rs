@@ -1,15 +1,25 @@
+use std::collections::HashMap;
+use std::fmt::Write;
 use std::io;
 use std::path::Path;
 
-use hugr::Hugr;
+use hugr::ops::NamedOp;
+use hugr::{CircuitUnit, Hugr, HugrView, Node, PortIndex, Wire};
 use itertools::Itertools;
+use thiserror::Error;
 
+use crate::circuit::phase::GlobalPhase;
 use crate::circuit::Circuit;
+use crate::utils::float_wire_value;
 
 use super::qtz_circuit::load_ecc_set;
 
-#[derive(Debug, Clone)]
+/// Errors that can occur while building an equivalence class.
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum EqCircClassError {
+    /// The class has no circuits to choose a representative from.
+    #[error("An equivalence class needs at least one circuit")]
     NoRepresentative,
 }
 
@@ -93,3 +103,100 @@ pub fn load_eccs_json_file(path: impl AsRef<Path>) -> io::Result<Vec<EqCircClass
         .collect::<Result<Vec<_>, _>>()
         .unwrap())
 }
+
+/// Load the equivalence classes of several JSON files, merging them with
+/// [`merge_eccs`].
+pub fn load_eccs_json_files(
+    paths: impl IntoIterator<Item = impl AsRef<Path>>,
+) -> io::Result<Vec<EqCircClass>> {
+    let eccs = paths
+        .into_iter()
+        .map(load_eccs_json_file)
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(merge_eccs(eccs.into_iter().flatten()))
+}
+
+/// Merge sets of equivalence classes, such as the ones loaded from different
+/// ECC files.
+///
+/// Circuits appearing in several classes are only kept once, so that each
+/// rewrite rule is only generated once. A class sharing a circuit with a
+/// previous class is merged into it, and the global phases of its circuits
+/// are shifted to be relative to the circuits of that class.
+pub fn merge_eccs(eccs: impl IntoIterator<Item = EqCircClass>) -> Vec<EqCircClass> {
+    let mut classes: Vec<Vec<Circuit>> = Vec::new();
+    // The class and phase of each circuit seen so far.
+    let mut seen: HashMap<CircuitKey, (usize, Option<GlobalPhase>)> = HashMap::new();
+    for ecc in eccs {
+        let circs = ecc
+            .into_circuits()
+            .map(|hugr| {
+                let circ = Circuit::from(hugr);
+                (circuit_key(&circ), circ)
+            })
+            .collect_vec();
+        // Merge into the class of the first circuit seen before, if any.
+        let shared = circs.iter().find_map(|(key, circ)| {
+            let &(class, ref phase) = seen.get(key)?;
+            let offset = phase.clone().zip(circ.global_phase()).map(|(a, b)| a - b);
+            Some((class, offset))
+        });
+        let (class, offset) = shared.unwrap_or_else(|| {
+            classes.push(Vec::new());
+            (classes.len() - 1, Some(GlobalPhase::default()))
+        });
+        for (key, mut circ) in circs {
+            if seen.contains_key(&key) {
+                continue;
+            }
+            let phase = circ.global_phase().zip(offset.clone()).map(|(a, b)| a + b);
+            circ.set_global_phase(phase.clone());
+            seen.insert(key, (class, phase));
+            classes[class].push(circ);
+        }
+    }
+    classes
+        .into_iter()
+        .map(|circs| EqCircClass::from_circuits(circs).unwrap())
+        .collect()
+}
+
+/// A description of a circuit, equal for circuits with the same commands on
+/// the same wires.
+type CircuitKey = String;
+
+/// The key identifying a circuit.
+fn circuit_key(circ: &Circuit) -> CircuitKey {
+    let hugr = circ.hugr();
+    let [input, output] = circ.io_nodes();
+    let mut indices: HashMap<Node, usize> = HashMap::new();
+    let wire_key = |key: &mut String, wire: Wire, indices: &HashMap<Node, usize>| {
+        match float_wire_value(hugr, wire) {
+            Some(value) => write!(key, " {value}"),
+            None if wire.node() == input => write!(key, " in.{}", wire.source().index()),
+            None => match indices.get(&wire.node()) {
+                Some(i) => write!(key, " {i}.{}", wire.source().index()),
+                None => write!(key, " ?"),
+            },
+        }
+        .unwrap()
+    };
+    let mut key = circ.circuit_signature().to_string();
+    for (i, cmd) in circ.commands().enumerate() {
+        write!(key, "; {}", cmd.optype().name()).unwrap();
+        for (unit, _, _) in cmd.inputs() {
+            match unit {
+                CircuitUnit::Linear(q) => write!(key, " q{q}").unwrap(),
+                CircuitUnit::Wire(wire) => wire_key(&mut key, wire, &indices),
+            }
+        }
+        indices.insert(cmd.node(), i);
+    }
+    key.push_str("; out");
+    for port in hugr.node_inputs(output) {
+        if let Some((node, src_port)) = hugr.single_linked_output(output, port) {
+            wire_key(&mut key, Wire::new(node, src_port), &indices);
+        }
+    }
+    key
+}
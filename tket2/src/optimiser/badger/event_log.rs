@@ -0,0 +1,169 @@
+//! Structured event logs of Badger optimisation runs.
+//!
+//! When configured with [`BadgerLogger::with_event_log`], the optimiser writes
+//! one line of JSON per [`BadgerEvent`]: each new best circuit, the periodic
+//! progress reports, and the end of the optimisation. A log can be read back
+//! for analysis with [`RunLog::load_json`].
+//!
+//! [`BadgerLogger::with_event_log`]: super::BadgerLogger::with_event_log
+
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+/// An event of an optimisation run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BadgerEvent<P> {
+    /// The time of the event, in RFC 3339 format.
+    pub time: String,
+    /// The index of the worker thread the event originates from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread: Option<usize>,
+    /// The event data.
+    #[serde(flatten)]
+    pub kind: BadgerEventKind<P>,
+}
+
+/// The data of an [`BadgerEvent`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BadgerEventKind<P> {
+    /// A circuit with a lower cost than the previous best was found.
+    NewBest {
+        /// The hash of the circuit.
+        hash: Option<u64>,
+        /// The cost of the circuit.
+        cost: P,
+        /// The number of rewrites applied to obtain the circuit, if they are
+        /// traced.
+        num_rewrites: Option<usize>,
+    },
+    /// A periodic progress report.
+    Progress {
+        /// The number of circuits processed so far.
+        circuits_processed: usize,
+        /// The number of distinct circuits seen so far.
+        circuits_seen: usize,
+        /// The number of circuits waiting in the queue.
+        queue_size: Option<usize>,
+    },
+    /// The end of the optimisation.
+    End {
+        /// The number of circuits processed.
+        circuits_processed: usize,
+        /// The number of distinct circuits seen.
+        circuits_seen: Option<usize>,
+        /// The cost of the best circuit found.
+        cost: P,
+        /// Whether the optimisation was stopped by a timeout.
+        timeout: bool,
+        /// The duration of the optimisation, in seconds.
+        elapsed_secs: f64,
+    },
+}
+
+impl<P> BadgerEvent<P> {
+    /// Create a new event, timestamped with the current time.
+    pub fn now(thread: Option<usize>, kind: BadgerEventKind<P>) -> Self {
+        Self {
+            time: chrono::Local::now().to_rfc3339(),
+            thread,
+            kind,
+        }
+    }
+
+    /// Write the event as a single line of JSON.
+    pub fn save_json(&self, mut writer: impl io::Write) -> io::Result<()>
+    where
+        P: Serialize,
+    {
+        serde_json::to_writer(&mut writer, self)?;
+        writeln!(writer)
+    }
+}
+
+/// The events of an optimisation run, in the order they were logged.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RunLog<P> {
+    /// The events of the run.
+    pub events: Vec<BadgerEvent<P>>,
+}
+
+impl<P> RunLog<P> {
+    /// Read an event log.
+    ///
+    /// The optimiser serialises costs as strings, so logs written by it can
+    /// be read back as `RunLog<String>`.
+    pub fn load_json(reader: impl io::BufRead) -> io::Result<Self>
+    where
+        P: for<'de> Deserialize<'de>,
+    {
+        let events = reader
+            .lines()
+            .filter(|line| !line.as_ref().is_ok_and(|l| l.trim().is_empty()))
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect::<io::Result<_>>()?;
+        Ok(Self { events })
+    }
+
+    /// The successive best costs found during the run.
+    pub fn best_costs(&self) -> impl Iterator<Item = &P> + '_ {
+        self.events.iter().filter_map(|e| match &e.kind {
+            BadgerEventKind::NewBest { cost, .. } => Some(cost),
+            _ => None,
+        })
+    }
+
+    /// The final event of the run, if the optimisation finished.
+    pub fn end(&self) -> Option<&BadgerEvent<P>> {
+        self.events
+            .iter()
+            .rev()
+            .find(|e| matches!(e.kind, BadgerEventKind::End { .. }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn event_log_roundtrip() {
+        let events = [
+            BadgerEvent::now(
+                None,
+                BadgerEventKind::NewBest {
+                    hash: Some(7),
+                    cost: 10,
+                    num_rewrites: None,
+                },
+            ),
+            BadgerEvent::now(
+                Some(1),
+                BadgerEventKind::Progress {
+                    circuits_processed: 4,
+                    circuits_seen: 12,
+                    queue_size: Some(3),
+                },
+            ),
+            BadgerEvent::now(
+                None,
+                BadgerEventKind::End {
+                    circuits_processed: 5,
+                    circuits_seen: Some(14),
+                    cost: 10,
+                    timeout: false,
+                    elapsed_secs: 0.5,
+                },
+            ),
+        ];
+        let mut log = Vec::new();
+        for event in &events {
+            event.save_json(&mut log).unwrap();
+        }
+        let loaded = RunLog::<usize>::load_json(log.as_slice()).unwrap();
+        assert_eq!(loaded.events, events);
+        assert_eq!(loaded.best_costs().collect::<Vec<_>>(), [&10]);
+        assert_eq!(loaded.end(), Some(&events[2]));
+    }
+}
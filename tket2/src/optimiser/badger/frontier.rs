@@ -0,0 +1,138 @@
+//! Snapshots of the Badger optimiser's priority queue.
+//!
+//! Long optimisation runs can be inspected without stopping them by requesting
+//! a snapshot of the best candidate circuits in the queue through a
+//! [`FrontierRequest`] handle. The optimiser writes the snapshot to the
+//! frontier log configured with [`BadgerLogger::with_frontier_log`], as one
+//! line of JSON per [`FrontierSnapshot`].
+//!
+//! Snapshots are not available when the circuit is split into chunks, see
+//! [`BadgerOptions::split_circuit`].
+//!
+//! [`BadgerLogger::with_frontier_log`]: super::BadgerLogger::with_frontier_log
+//! [`BadgerOptions::split_circuit`]: super::BadgerOptions::split_circuit
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// A handle to request snapshots of the optimiser's queue while it runs.
+///
+/// Requests are cheap and may be issued from any thread, or from a signal
+/// handler. Several requests made before the optimiser handles them result in
+/// a single snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct FrontierRequest(Arc<AtomicBool>);
+
+impl FrontierRequest {
+    /// Create a new request handle.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request a snapshot of the queue.
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if a snapshot was requested, clearing the request.
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// A candidate circuit in the optimiser's queue.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrontierEntry<P> {
+    /// The hash of the circuit.
+    pub hash: u64,
+    /// The cost of the circuit.
+    pub cost: P,
+}
+
+/// The best candidate circuits in the optimiser's queue at some point of the
+/// optimisation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrontierSnapshot<P> {
+    /// The number of circuits processed when the snapshot was taken.
+    pub circuits_processed: usize,
+    /// The time elapsed since the start of the optimisation, in seconds.
+    pub elapsed_secs: f64,
+    /// The candidates in the queue, in ascending cost order.
+    pub entries: Vec<FrontierEntry<P>>,
+}
+
+impl<P> FrontierSnapshot<P> {
+    /// Create a new snapshot from the `(hash, cost)` pairs of the queue.
+    pub fn new(
+        circuits_processed: usize,
+        elapsed: Duration,
+        entries: impl IntoIterator<Item = (u64, P)>,
+    ) -> Self {
+        Self {
+            circuits_processed,
+            elapsed_secs: elapsed.as_secs_f64(),
+            entries: entries
+                .into_iter()
+                .map(|(hash, cost)| FrontierEntry { hash, cost })
+                .collect(),
+        }
+    }
+
+    /// Write the snapshot as a single line of JSON.
+    pub fn save_json(&self, mut writer: impl io::Write) -> io::Result<()>
+    where
+        P: Serialize,
+    {
+        serde_json::to_writer(&mut writer, self)?;
+        writeln!(writer)
+    }
+
+    /// Read all the snapshots of a frontier log.
+    ///
+    /// The optimiser serialises costs as strings, so snapshots written by it
+    /// can be read back as `FrontierSnapshot<String>`.
+    pub fn load_json(reader: impl io::BufRead) -> io::Result<Vec<Self>>
+    where
+        P: for<'de> Deserialize<'de>,
+    {
+        reader
+            .lines()
+            .filter(|line| !line.as_ref().is_ok_and(|l| l.trim().is_empty()))
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn request_is_cleared() {
+        let request = FrontierRequest::new();
+        assert!(!request.take());
+        request.clone().request();
+        request.request();
+        assert!(request.take());
+        assert!(!request.take());
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let snapshots = [
+            FrontierSnapshot::new(3, Duration::from_millis(1500), [(1, 10), (7, 12)]),
+            FrontierSnapshot::new(8, Duration::from_secs(4), []),
+        ];
+        let mut log = Vec::new();
+        for snapshot in &snapshots {
+            snapshot.save_json(&mut log).unwrap();
+        }
+        let loaded = FrontierSnapshot::<usize>::load_json(log.as_slice()).unwrap();
+        assert_eq!(loaded, snapshots);
+        assert_eq!(loaded[0].elapsed_secs, 1.5);
+    }
+}
@@ -1,18 +1,21 @@
 use delegate::delegate;
 use fxhash::FxHashMap;
+use itertools::Itertools;
 use priority_queue::DoublePriorityQueue;
 
 use crate::circuit::CircuitHash;
 use crate::Circuit;
 
+use super::snapshot::CircuitSnapshot;
+
 /// A min-priority queue for Hugrs.
 ///
 /// The cost function provided will be used as the priority of the Hugrs.
-/// Uses hashes internally to store the Hugrs.
+/// Uses hashes internally to store the Hugrs, as [`CircuitSnapshot`]s.
 #[derive(Debug, Clone, Default)]
 pub struct HugrPQ<P: Ord, C> {
     queue: DoublePriorityQueue<u64, P>,
-    hash_lookup: FxHashMap<u64, Circuit>,
+    hash_lookup: FxHashMap<u64, CircuitSnapshot>,
     cost_fn: C,
     max_size: usize,
 }
@@ -36,7 +39,7 @@ impl<P: Ord, C> HugrPQ<P, C> {
 
     /// Reference to the minimal circuit in the queue.
     #[allow(unused)]
-    pub fn peek(&self) -> Option<Entry<&Circuit, &P, u64>> {
+    pub fn peek(&self) -> Option<Entry<&CircuitSnapshot, &P, u64>> {
         let (hash, cost) = self.queue.peek_min()?;
         let circ = self.hash_lookup.get(hash)?;
         Some(Entry {
@@ -56,7 +59,7 @@ impl<P: Ord, C> HugrPQ<P, C> {
     {
         let hash = circ.circuit_hash().unwrap();
         let cost = (self.cost_fn)(&circ);
-        self.push_unchecked(circ, hash, cost);
+        self.push_unchecked(circ.into(), hash, cost);
     }
 
     /// Push a circuit into the queue with a precomputed hash and cost.
@@ -67,7 +70,7 @@ impl<P: Ord, C> HugrPQ<P, C> {
     /// This does not check that the hash is valid.
     ///
     /// If the queue is full, the most last will be dropped.
-    pub fn push_unchecked(&mut self, circ: Circuit, hash: u64, cost: P)
+    pub fn push_unchecked(&mut self, circ: CircuitSnapshot, hash: u64, cost: P)
     where
         C: Fn(&Circuit) -> P,
     {
@@ -82,14 +85,14 @@ impl<P: Ord, C> HugrPQ<P, C> {
     }
 
     /// Pop the minimal circuit from the queue.
-    pub fn pop(&mut self) -> Option<Entry<Circuit, P, u64>> {
+    pub fn pop(&mut self) -> Option<Entry<CircuitSnapshot, P, u64>> {
         let (hash, cost) = self.queue.pop_min()?;
         let circ = self.hash_lookup.remove(&hash)?;
         Some(Entry { circ, cost, hash })
     }
 
     /// Pop the maximal circuit from the queue.
-    pub fn pop_max(&mut self) -> Option<Entry<Circuit, P, u64>> {
+    pub fn pop_max(&mut self) -> Option<Entry<CircuitSnapshot, P, u64>> {
         let (hash, cost) = self.queue.pop_max()?;
         let circ = self.hash_lookup.remove(&hash)?;
         Some(Entry { circ, cost, hash })
@@ -106,6 +109,20 @@ impl<P: Ord, C> HugrPQ<P, C> {
         }
     }
 
+    /// The hashes and costs of the `n` cheapest circuits in the queue, in
+    /// ascending cost order.
+    pub fn top(&self, n: usize) -> Vec<(u64, P)>
+    where
+        P: Clone,
+    {
+        self.queue
+            .iter()
+            .sorted_by(|(h1, c1), (h2, c2)| c1.cmp(c2).then(h1.cmp(h2)))
+            .take(n)
+            .map(|(&hash, cost)| (hash, cost.clone()))
+            .collect()
+    }
+
     /// The cost function used by the queue.
     #[allow(unused)]
     pub fn cost_fn(&self) -> &C {
@@ -131,6 +148,11 @@ impl<P: Ord, C> HugrPQ<P, C> {
         cost < self.max_cost().unwrap()
     }
 
+    /// Returns `true` if a circuit with the given hash is in the queue.
+    pub fn contains(&self, hash: u64) -> bool {
+        self.hash_lookup.contains_key(&hash)
+    }
+
     /// Returns `true` is the queue is at capacity.
     pub fn is_full(&self) -> bool {
         self.queue.len() >= self.max_size
@@ -139,7 +161,6 @@ impl<P: Ord, C> HugrPQ<P, C> {
     delegate! {
         to self.queue {
             pub fn len(&self) -> usize;
-            pub fn is_empty(&self) -> bool;
         }
     }
 }
@@ -3,12 +3,28 @@
 use std::time::{Duration, Instant};
 use std::{fmt::Debug, io};
 
+use super::event_log::{BadgerEvent, BadgerEventKind};
+use super::frontier::{FrontierRequest, FrontierSnapshot};
+use crate::circuit::CircuitHash;
+use crate::rewrite::profile::RewriteProfile;
+use crate::Circuit;
+
 /// Logging configuration for the Badger optimiser.
 pub struct BadgerLogger<'w> {
     circ_candidates_csv: Option<csv::Writer<Box<dyn io::Write + Send + Sync + 'w>>>,
     last_circ_processed: usize,
     last_progress_time: Instant,
     branching_factor: UsizeAverage,
+    frontier: Option<FrontierLog<'w>>,
+    events: Option<Box<dyn io::Write + Send + Sync + 'w>>,
+    profile: Option<Box<dyn io::Write + Send + Sync + 'w>>,
+}
+
+/// Where and when to write snapshots of the optimiser's queue.
+struct FrontierLog<'w> {
+    request: FrontierRequest,
+    size: usize,
+    writer: Box<dyn io::Write + Send + Sync + 'w>,
 }
 
 impl<'w> Default for BadgerLogger<'w> {
@@ -19,6 +35,9 @@ impl<'w> Default for BadgerLogger<'w> {
             // Ensure the first progress message is printed.
             last_progress_time: Instant::now() - Duration::from_secs(60),
             branching_factor: UsizeAverage::new(),
+            frontier: None,
+            events: None,
+            profile: None,
         }
     }
 }
@@ -50,13 +69,104 @@ impl<'w> BadgerLogger<'w> {
         }
     }
 
-    /// Log a new best candidate
+    /// Write snapshots of the optimiser's queue to a writer when requested.
+    ///
+    /// Each time a snapshot is requested through `request`, the hashes and
+    /// costs of the `size` best circuits in the queue are written to
+    /// `frontier_writer`. See [`FrontierSnapshot`] for the format.
+    pub fn with_frontier_log(
+        mut self,
+        request: FrontierRequest,
+        size: usize,
+        frontier_writer: impl io::Write + Send + Sync + 'w,
+    ) -> Self {
+        self.frontier = Some(FrontierLog {
+            request,
+            size,
+            writer: Box::new(frontier_writer),
+        });
+        self
+    }
+
+    /// Write a structured log of the optimisation events to a writer.
+    ///
+    /// Each event is written as a single line of JSON, see
+    /// [`BadgerEvent`] for the format.
+    pub fn with_event_log(mut self, event_writer: impl io::Write + Send + Sync + 'w) -> Self {
+        self.events = Some(Box::new(event_writer));
+        self
+    }
+
+    /// Write a [`RewriteProfile`] of the rewrites leading to the best circuit
+    /// to a writer, at the end of the optimisation.
+    ///
+    /// The profile is written as JSON, and can be used to guide later runs
+    /// with [`BadgerOptions::profile`](super::BadgerOptions::profile). It is
+    /// only recorded by the single-threaded optimiser.
+    pub fn with_profile_log(mut self, profile_writer: impl io::Write + Send + Sync + 'w) -> Self {
+        self.profile = Some(Box::new(profile_writer));
+        self
+    }
+
+    /// Returns `true` if a profile of the rewrites should be recorded.
+    #[inline]
+    pub fn records_profile(&self) -> bool {
+        self.profile.is_some()
+    }
+
+    /// Log the profile of the rewrites leading to the best circuit.
+    pub fn log_profile(&mut self, profile: &RewriteProfile) {
+        let Some(writer) = self.profile.as_mut() else {
+            return;
+        };
+        let res = serde_json::to_writer_pretty(&mut *writer, profile)
+            .map_err(io::Error::from)
+            .and_then(|()| writer.flush());
+        if let Err(e) = res {
+            self.warn(format!("Could not save the rewrite profile: {e}"));
+        }
+    }
+
+    /// Returns `true` if snapshots of the queue may be requested.
+    #[inline]
+    pub fn logs_frontier(&self) -> bool {
+        self.frontier.is_some()
+    }
+
+    /// If a snapshot of the queue was requested, returns the number of
+    /// circuits to include in it and clears the request.
+    #[inline]
+    pub fn take_frontier_request(&self) -> Option<usize> {
+        let frontier = self.frontier.as_ref()?;
+        frontier.request.take().then_some(frontier.size)
+    }
+
+    /// Log a snapshot of the optimiser's queue.
+    pub fn log_frontier<C: serde::Serialize>(&mut self, snapshot: &FrontierSnapshot<C>) {
+        let Some(frontier) = self.frontier.as_mut() else {
+            return;
+        };
+        let res = snapshot
+            .save_json(&mut frontier.writer)
+            .and_then(|()| frontier.writer.flush());
+        match res {
+            Ok(()) => self.log(format!(
+                "Saved a snapshot of the {} best queued circuits.",
+                snapshot.entries.len()
+            )),
+            Err(e) => self.warn(format!("Could not save the queue snapshot: {e}")),
+        }
+    }
+
+    /// Log a new best candidate, found by the worker thread `thread` if any.
     #[inline]
     pub fn log_best<C: Debug + serde::Serialize>(
         &mut self,
+        best_circ: &Circuit,
         best_cost: C,
-        num_rewrites: Option<usize>,
+        thread: Option<usize>,
     ) {
+        let num_rewrites = best_circ.rewrite_trace().map(|rs| rs.len());
         match num_rewrites {
             Some(rs) => self.log(format!(
                 "new best of size {best_cost:?} after {rs} rewrites"
@@ -64,15 +174,26 @@ impl<'w> BadgerLogger<'w> {
             None => self.log(format!("new best of size {:?}", best_cost)),
         }
         if let Some(csv_writer) = self.circ_candidates_csv.as_mut() {
-            csv_writer.serialize(BestCircSer::new(best_cost)).unwrap();
+            csv_writer.serialize(BestCircSer::new(&best_cost)).unwrap();
             csv_writer.flush().unwrap();
         };
+        if self.events.is_some() {
+            let hash = best_circ.circuit_hash().ok();
+            self.log_event(
+                thread,
+                BadgerEventKind::NewBest {
+                    hash,
+                    cost: best_cost,
+                    num_rewrites,
+                },
+            );
+        }
     }
 
     /// Log the final optimised circuit
     #[inline]
-    pub fn log_processing_end<C: Debug>(
-        &self,
+    pub fn log_processing_end<C: Debug + serde::Serialize>(
+        &mut self,
         circuits_processed: usize,
         circuits_seen: Option<usize>,
         best_cost: C,
@@ -95,19 +216,32 @@ impl<'w> BadgerLogger<'w> {
         }
         self.log_avg_branching_factor();
         self.log(format!("---- END RESULT: {:?} ----", best_cost));
+        self.log_event(
+            None,
+            BadgerEventKind::End {
+                circuits_processed,
+                circuits_seen,
+                cost: best_cost,
+                timeout,
+                elapsed_secs: elapsed_time.as_secs_f64(),
+            },
+        );
         if needs_joining {
             self.log("Joining worker threads.");
         }
     }
 
     /// Log the progress of the optimisation.
+    ///
+    /// Progress is reported at most once a second. Returns `true` if it was
+    /// reported.
     #[inline(always)]
     pub fn log_progress(
         &mut self,
         circuits_processed: usize,
         workqueue_len: Option<usize>,
         seen_hashes: usize,
-    ) {
+    ) -> bool {
         if circuits_processed > self.last_circ_processed
             && Instant::now() - self.last_progress_time > Duration::from_secs(1)
         {
@@ -119,6 +253,30 @@ impl<'w> BadgerLogger<'w> {
                 self.progress(format!("Queue size: {workqueue_len} circuits."));
             }
             self.progress(format!("Total seen: {} circuits.", seen_hashes));
+            self.log_event::<()>(
+                None,
+                BadgerEventKind::Progress {
+                    circuits_processed,
+                    circuits_seen: seen_hashes,
+                    queue_size: workqueue_len,
+                },
+            );
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Write an event to the structured event log, if any.
+    fn log_event<C: serde::Serialize>(&mut self, thread: Option<usize>, kind: BadgerEventKind<C>) {
+        let Some(writer) = self.events.as_mut() else {
+            return;
+        };
+        let res = BadgerEvent::now(thread, kind)
+            .save_json(&mut *writer)
+            .and_then(|()| writer.flush());
+        if let Err(e) = res {
+            self.warn(format!("Could not write to the event log: {e}"));
         }
     }
 
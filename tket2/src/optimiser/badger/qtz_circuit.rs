@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::f64::consts::PI;
 use std::io;
 use std::path::Path;
 
@@ -11,6 +12,7 @@ use hugr::{CircuitUnit, Hugr};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
+use crate::circuit::phase::GlobalPhase;
 use crate::{Circuit, Tk2Op};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -121,14 +123,48 @@ pub(super) fn load_ecc_set(
         .into_values()
         .map(|datmap| {
             let id = datmap[0].meta.id[0].clone();
-            let circs = datmap.into_iter().map(|rcd| rcd.into()).collect();
+            let first_fingerprint = datmap[0].meta.fingerprint.clone();
+            let circs = datmap
+                .into_iter()
+                .map(|rcd| {
+                    let phase = relative_phase(&rcd.meta, &first_fingerprint);
+                    let mut circ: Circuit<Hugr> = rcd.into();
+                    circ.set_global_phase(phase);
+                    circ
+                })
+                .collect();
             (id, circs)
         })
         .collect())
 }
 
+/// The global phase of a circuit relative to the first circuit of its class.
+///
+/// Quartz fingerprints are the same complex-valued function of the circuits'
+/// unitaries, so circuits equal up to a phase `φ` have fingerprints that
+/// differ by a factor `e^{iφ}`. Returns `None` if the phase is non-zero for a
+/// parametric circuit, as it may then depend on the parameters.
+fn relative_phase(meta: &MetaData, first_fingerprint: &[f64]) -> Option<GlobalPhase> {
+    let (&[re, im], &[first_re, first_im]) = (&meta.fingerprint[..], first_fingerprint) else {
+        // Fingerprints without a phase.
+        return Some(GlobalPhase::default());
+    };
+    // arg(first / fingerprint), in half-turns.
+    let phase = (first_im * re - first_re * im).atan2(first_re * re + first_im * im) / PI;
+    let phase = GlobalPhase::new(phase);
+    if phase.is_zero() {
+        Some(GlobalPhase::default())
+    } else if meta.n_input_param > 0 {
+        None
+    } else {
+        Some(phase)
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use num_complex::Complex64;
+
     use super::*;
 
     fn load_representative_set(path: &str) -> HashMap<String, Circuit<Hugr>> {
@@ -159,4 +195,28 @@ mod tests {
         //     .flatten()
         //     .for_each(|c| check_soundness(c).unwrap());
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Opening files is not supported in (isolated) miri
+    fn test_read_phases() {
+        let ecc = load_ecc_set("../test_files/T_Tdg_H_X_CX_complete_ECC_set.json").unwrap();
+
+        let mut n_phases = 0;
+        for circs in ecc.values() {
+            let first = circs[0].unitary().unwrap();
+            assert_eq!(circs[0].global_phase(), Some(GlobalPhase::default()));
+            for circ in circs {
+                let phase = circ.global_phase().unwrap();
+                n_phases += usize::from(!phase.is_zero());
+                // All circuits in the class implement the same operator.
+                let rotation = Complex64::from_polar(1., phase.constant() * PI);
+                let unitary = circ.unitary().unwrap() * rotation;
+                assert!(first
+                    .iter()
+                    .zip(unitary.iter())
+                    .all(|(x, y)| (x - y).norm() < 1e-8));
+            }
+        }
+        assert!(n_phases > 0);
+    }
 }
@@ -0,0 +1,176 @@
+//! A memory-bounded set of seen circuit hashes.
+//!
+//! The optimiser records the hash of every circuit it generates to avoid
+//! processing duplicates. On long runs over large circuits this set can grow
+//! to millions of entries. [`SeenHashes`] keeps an exact set of hashes until
+//! it reaches a memory budget, and then replaces it with a fixed-size Bloom
+//! filter.
+//!
+//! The filter never forgets a hash, but it may report an unseen circuit as
+//! seen. Such circuits are skipped by the optimiser, trading a small loss of
+//! search coverage for bounded memory.
+
+use std::mem;
+
+use fxhash::FxHashSet;
+
+/// The number of bits set in the Bloom filter for each hash.
+const NUM_HASHES: u64 = 4;
+
+/// A set of circuit hashes, with an optional memory budget.
+#[derive(Debug, Clone, Default)]
+pub struct SeenHashes {
+    /// The exact set of hashes, used until the memory budget is reached.
+    exact: FxHashSet<u64>,
+    /// The Bloom filter replacing the exact set once the budget is reached.
+    filter: Option<BloomFilter>,
+    /// The memory budget, in bytes.
+    max_memory: Option<usize>,
+    /// The number of distinct hashes inserted.
+    ///
+    /// Once the Bloom filter is in use, hashes that are false positives of
+    /// the filter are not counted.
+    count: usize,
+}
+
+impl SeenHashes {
+    /// Create a new set of hashes, using at most `max_memory` bytes.
+    ///
+    /// If `max_memory` is `None`, the set is exact and unbounded.
+    pub fn new(max_memory: Option<usize>) -> Self {
+        Self {
+            max_memory,
+            ..Default::default()
+        }
+    }
+
+    /// Record a hash as seen.
+    ///
+    /// Returns `false` if it had (probably) already been seen.
+    pub fn insert(&mut self, hash: u64) -> bool {
+        let inserted = match &mut self.filter {
+            Some(filter) => filter.insert(hash),
+            None => self.exact.insert(hash),
+        };
+        if inserted {
+            self.count += 1;
+            self.check_memory();
+        }
+        inserted
+    }
+
+    /// The number of distinct hashes seen.
+    ///
+    /// This is an underestimate once the set has been replaced by a Bloom
+    /// filter.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns `true` if the set has been replaced by a Bloom filter.
+    pub fn is_probabilistic(&self) -> bool {
+        self.filter.is_some()
+    }
+
+    /// The approximate memory used by the set, in bytes.
+    pub fn memory(&self) -> usize {
+        match &self.filter {
+            Some(filter) => filter.memory(),
+            // Each bucket of the set stores the hash and a control byte.
+            None => self.exact.capacity() * (mem::size_of::<u64>() + 1),
+        }
+    }
+
+    /// Replace the exact set by a Bloom filter if it exceeds the memory
+    /// budget.
+    fn check_memory(&mut self) {
+        let Some(max_memory) = self.max_memory else {
+            return;
+        };
+        if self.filter.is_some() || self.memory() <= max_memory {
+            return;
+        }
+        let mut filter = BloomFilter::with_memory(max_memory);
+        for hash in mem::take(&mut self.exact) {
+            filter.insert(hash);
+        }
+        self.filter = Some(filter);
+    }
+}
+
+/// A fixed-size Bloom filter of 64-bit hashes.
+#[derive(Debug, Clone)]
+struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// Create an empty filter using `bytes` bytes of memory.
+    fn with_memory(bytes: usize) -> Self {
+        let words = (bytes / mem::size_of::<u64>()).max(1);
+        Self {
+            bits: vec![0; words],
+        }
+    }
+
+    /// Insert a hash, returning `true` if it was not already in the filter.
+    fn insert(&mut self, hash: u64) -> bool {
+        let num_bits = (self.bits.len() * u64::BITS as usize) as u64;
+        // Derive the bit indices by double hashing. The second hash is odd,
+        // so that the indices differ for every `i`.
+        let h1 = splitmix64(hash);
+        let h2 = splitmix64(h1) | 1;
+        let mut inserted = false;
+        for i in 0..NUM_HASHES {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % num_bits;
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+            inserted |= self.bits[word] & mask == 0;
+            self.bits[word] |= mask;
+        }
+        inserted
+    }
+
+    /// The memory used by the filter, in bytes.
+    fn memory(&self) -> usize {
+        self.bits.len() * mem::size_of::<u64>()
+    }
+}
+
+/// Mix the bits of a hash, so that similar inputs map to unrelated outputs.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unbounded() {
+        let mut seen = SeenHashes::new(None);
+        assert!(seen.insert(1));
+        assert!(seen.insert(2));
+        assert!(!seen.insert(1));
+        assert_eq!(seen.len(), 2);
+        assert!(!seen.is_probabilistic());
+    }
+
+    #[test]
+    fn bounded() {
+        const MAX_MEMORY: usize = 1 << 14;
+        let mut seen = SeenHashes::new(Some(MAX_MEMORY));
+        for hash in 0..10_000 {
+            seen.insert(hash);
+        }
+        assert!(seen.is_probabilistic());
+        assert_eq!(seen.memory(), MAX_MEMORY);
+        // The filter never forgets a hash.
+        assert!((0..10_000).all(|hash| !seen.insert(hash)));
+        // Some hashes may be lost as false positives.
+        assert!(seen.len() <= 10_000);
+        assert!(seen.len() > 9_900);
+    }
+}
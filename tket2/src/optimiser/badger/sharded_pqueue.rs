@@ -0,0 +1,304 @@
+//! A sharded min-priority queue of circuits with work stealing, shared by the
+//! multithreaded Badger workers.
+//!
+//! Each worker owns a shard of the queue, holding at most a fraction of the
+//! total queue capacity. Workers push the circuits they generate to their own
+//! shard and pop from it, only locking the other shards to steal work when
+//! their own shard is empty. This avoids serialising the distribution of
+//! candidates through a single thread.
+//!
+//! The set of seen circuit hashes is partitioned by hash, so that workers
+//! rarely contend on it. Its memory budget is split evenly between the
+//! partitions.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use itertools::Itertools;
+
+use crate::circuit::cost::CircuitCost;
+use crate::Circuit;
+
+use super::hugr_pqueue::{Entry, HugrPQ};
+use super::seen_hashes::SeenHashes;
+use super::snapshot::CircuitSnapshot;
+
+/// A unit of work for a worker, consisting of a circuit to process, along its
+/// hash and cost.
+pub type Work<P> = Entry<CircuitSnapshot, P, u64>;
+
+/// The minimum time between two progress logs.
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Logging information from the queue.
+#[derive(Debug, Clone)]
+pub enum PriorityQueueLog<P> {
+    /// A new best circuit, found by the worker of the given shard.
+    NewBestCircuit(Circuit, P, usize),
+    CircuitCount {
+        processed_count: usize,
+        seen_count: usize,
+        queue_length: usize,
+    },
+}
+
+/// A sharded priority queue for circuits.
+///
+/// Queues circuits using a cost function `C` that produces priority values `P`.
+#[derive(Debug)]
+pub struct ShardedHugrPQ<P: Ord, C> {
+    /// The queue shards, one per worker.
+    shards: Vec<Mutex<HugrPQ<P, C>>>,
+    /// The hashes of the circuits seen so far, partitioned by hash.
+    seen_hashes: Vec<Mutex<SeenHashes>>,
+    /// The number of circuits either queued or being processed by a worker.
+    ///
+    /// The search is exhausted once this drops to zero.
+    pending: AtomicUsize,
+    /// The number of circuits processed.
+    processed: AtomicUsize,
+    /// Set when the workers should stop.
+    stop: AtomicBool,
+    /// The minimum cost we've seen.
+    min_cost: Mutex<Option<P>>,
+    /// Outbound channel to log to main thread.
+    log: Sender<PriorityQueueLog<P>>,
+    /// Timestamp of the last progress log.
+    last_progress_log: Mutex<Instant>,
+}
+
+impl<P, C> ShardedHugrPQ<P, C>
+where
+    P: CircuitCost,
+    C: Fn(&Circuit) -> P + Clone,
+{
+    /// Create a new queue with `n_shards` shards.
+    ///
+    /// The total capacity of `queue_size` circuits is split evenly between the
+    /// shards, with at least one circuit per shard. Likewise, the memory used
+    /// to record seen circuits is bounded by `max_seen_memory` bytes in total.
+    pub fn new(
+        cost_fn: C,
+        queue_size: usize,
+        max_seen_memory: Option<usize>,
+        n_shards: usize,
+        log: Sender<PriorityQueueLog<P>>,
+    ) -> Self {
+        let shard_size = |i: usize| {
+            let size = queue_size / n_shards + usize::from(i < queue_size % n_shards);
+            size.max(1)
+        };
+        Self {
+            shards: (0..n_shards)
+                .map(|i| Mutex::new(HugrPQ::new(cost_fn.clone(), shard_size(i))))
+                .collect(),
+            seen_hashes: (0..n_shards)
+                .map(|_| Mutex::new(SeenHashes::new(max_seen_memory.map(|m| m / n_shards))))
+                .collect(),
+            pending: AtomicUsize::new(0),
+            processed: AtomicUsize::new(0),
+            stop: AtomicBool::new(false),
+            min_cost: Mutex::new(None),
+            log,
+            // Ensure we log the first progress.
+            last_progress_log: Mutex::new(Instant::now() - PROGRESS_LOG_INTERVAL),
+        }
+    }
+
+    /// Add circuits to a shard, ignoring the ones that have already been seen.
+    #[tracing::instrument(target = "badger::metrics", skip(self, circs))]
+    pub fn push(&self, shard: usize, circs: Vec<Work<P>>) {
+        let mut pq = self.shards[shard].lock().unwrap();
+        let len_before = pq.len();
+        for Work { cost, hash, circ } in circs {
+            if !self.insert_seen(hash) || !pq.check_accepted(&cost) {
+                continue;
+            }
+            self.update_min_cost(&circ, &cost, shard);
+            pq.push_unchecked(circ, hash, cost);
+        }
+        // Circuits evicted from a full shard are replaced one for one, so
+        // the shard never shrinks.
+        self.pending
+            .fetch_add(pq.len() - len_before, Ordering::SeqCst);
+    }
+
+    /// Pop the best circuit of a shard, stealing from the other shards if it
+    /// is empty.
+    ///
+    /// The circuit must be marked as processed with [`ShardedHugrPQ::done`]
+    /// after its results have been pushed.
+    pub fn pop(&self, shard: usize) -> Option<Work<P>> {
+        let n = self.shards.len();
+        (0..n).find_map(|i| self.shards[(shard + i) % n].lock().unwrap().pop())
+    }
+
+    /// Mark a popped circuit as processed.
+    pub fn done(&self) {
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+        let processed_count = self.processed.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let Ok(mut last_log) = self.last_progress_log.try_lock() else {
+            // Another worker is logging.
+            return;
+        };
+        if last_log.elapsed() > PROGRESS_LOG_INTERVAL {
+            *last_log = Instant::now();
+            let _ = self.log.send(PriorityQueueLog::CircuitCount {
+                processed_count,
+                seen_count: self.seen_count(),
+                queue_length: self.len(),
+            });
+        }
+    }
+
+    /// The maximum cost accepted by a shard, if it is full.
+    pub fn max_cost(&self, shard: usize) -> Option<P> {
+        let pq = self.shards[shard].lock().unwrap();
+        match pq.is_full() {
+            true => pq.max_cost().cloned(),
+            false => None,
+        }
+    }
+
+    /// The hashes and costs of the `n` cheapest circuits in the queue, in
+    /// ascending cost order.
+    pub fn top(&self, n: usize) -> Vec<(u64, P)> {
+        self.shards
+            .iter()
+            .flat_map(|pq| pq.lock().unwrap().top(n))
+            .sorted_by(|(h1, c1), (h2, c2)| c1.cmp(c2).then(h1.cmp(h2)))
+            .take(n)
+            .collect()
+    }
+
+    /// Signal the workers to stop.
+    pub fn close(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if the workers should stop, either because
+    /// [`ShardedHugrPQ::close`] was called or because there are no circuits
+    /// left to process.
+    pub fn is_finished(&self) -> bool {
+        self.stop.load(Ordering::SeqCst) || self.pending.load(Ordering::SeqCst) == 0
+    }
+
+    /// The number of circuits processed so far.
+    pub fn processed_count(&self) -> usize {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    /// The number of distinct circuits seen so far.
+    pub fn seen_count(&self) -> usize {
+        self.seen_hashes
+            .iter()
+            .map(|seen| seen.lock().unwrap().len())
+            .sum()
+    }
+
+    /// Returns `true` if the seen circuits are tracked approximately, after
+    /// exceeding their memory budget.
+    pub fn seen_is_probabilistic(&self) -> bool {
+        self.seen_hashes
+            .iter()
+            .any(|seen| seen.lock().unwrap().is_probabilistic())
+    }
+
+    /// The number of circuits in the queue.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|pq| pq.lock().unwrap().len()).sum()
+    }
+
+    /// Record a hash as seen. Returns `false` if it had already been seen.
+    fn insert_seen(&self, hash: u64) -> bool {
+        let partition = (hash % self.seen_hashes.len() as u64) as usize;
+        self.seen_hashes[partition].lock().unwrap().insert(hash)
+    }
+
+    /// Log a new best circuit found by the worker of `shard`, if `cost` is the
+    /// lowest seen so far.
+    fn update_min_cost(&self, circ: &CircuitSnapshot, cost: &P, shard: usize) {
+        let mut min_cost = self.min_cost.lock().unwrap();
+        if min_cost.as_ref().map_or(true, |min| cost < min) {
+            *min_cost = Some(cost.clone());
+            let _ = self.log.send(PriorityQueueLog::NewBestCircuit(
+                circ.to_circuit(),
+                cost.clone(),
+                shard,
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    fn work(n_gates: usize) -> Work<usize> {
+        let circ = build_simple_circuit(1, |circ| {
+            for _ in 0..n_gates {
+                circ.append(Tk2Op::H, [0])?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        Work {
+            cost: n_gates,
+            hash: n_gates as u64,
+            circ: circ.into(),
+        }
+    }
+
+    #[test]
+    fn steal_and_finish() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let pq = ShardedHugrPQ::new(|c: &Circuit| c.num_operations(), 3, None, 2, tx);
+        assert!(pq.is_finished());
+
+        pq.push(0, vec![work(2), work(1), work(3), work(1)]);
+        // The shard keeps at most 2 circuits, and duplicates are ignored.
+        assert_eq!(pq.len(), 2);
+        assert_eq!(pq.seen_count(), 3);
+        assert_eq!(pq.top(5).into_iter().map(|(h, _)| h).collect_vec(), [1, 2]);
+        assert!(!pq.is_finished());
+
+        // Shard 1 is empty, so it steals from shard 0.
+        let first = pq.pop(1).unwrap();
+        assert_eq!(first.cost, 1);
+        pq.push(1, vec![work(4)]);
+        pq.done();
+        assert_eq!(pq.processed_count(), 1);
+
+        let rest = [pq.pop(0).unwrap(), pq.pop(0).unwrap()];
+        assert_eq!(rest.map(|w| w.cost), [2, 4]);
+        assert!(pq.pop(0).is_none());
+        assert!(!pq.is_finished());
+        pq.done();
+        pq.done();
+        assert!(pq.is_finished());
+
+        let bests = rx
+            .try_iter()
+            .filter_map(|log| match log {
+                PriorityQueueLog::NewBestCircuit(_, cost, _) => Some(cost),
+                _ => None,
+            })
+            .collect_vec();
+        assert_eq!(bests, [2, 1]);
+    }
+
+    #[test]
+    fn close() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let pq = ShardedHugrPQ::new(|c: &Circuit| c.num_operations(), 4, None, 2, tx);
+        pq.push(0, vec![work(1)]);
+        assert!(!pq.is_finished());
+        pq.close();
+        assert!(pq.is_finished());
+    }
+}
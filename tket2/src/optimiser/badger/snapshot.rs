@@ -0,0 +1,234 @@
+//! Copy-on-write snapshots of the circuits queued by the optimiser.
+//!
+//! Every circuit processed by the optimiser produces many candidates, each
+//! differing from it by a few rewrites. Instead of storing a full copy of each
+//! candidate, the queue stores the processed circuit once, shared between all
+//! of its candidates, along with the rewrites producing each of them. If
+//! [`BadgerOptions::compact_queue`] is set, pure quantum circuits are instead
+//! stored as a [`CompactCircuit`]. A candidate is only converted back to a
+//! full circuit when it is popped from the queue.
+
+use std::sync::Arc;
+
+use crate::circuit::compact::CompactCircuit;
+use crate::circuit::cost::CircuitCost;
+use crate::optimiser::badger::BadgerOptions;
+use crate::rewrite::strategy::RewriteResult;
+use crate::rewrite::trace::RewriteTrace;
+use crate::rewrite::CircuitRewrite;
+use crate::Circuit;
+
+/// A circuit stored in the optimiser's queue.
+#[derive(Debug, Clone)]
+pub struct CircuitSnapshot {
+    storage: Storage,
+}
+
+/// The representation of a [`CircuitSnapshot`].
+#[derive(Debug, Clone)]
+enum Storage {
+    /// A set of rewrites over a base circuit.
+    Rewrites {
+        /// The base circuit, shared with the other snapshots derived from it.
+        base: Arc<Circuit>,
+        /// The rewrites to apply to the base circuit, in order.
+        rewrites: Vec<CircuitRewrite>,
+        /// The rewrite traces registered by the rewrites.
+        traces: Vec<RewriteTrace>,
+    },
+    /// A compact copy of the circuit.
+    Compact(CompactCircuit),
+}
+
+impl CircuitSnapshot {
+    /// A snapshot holding a full circuit.
+    pub fn new(circ: Circuit) -> Self {
+        Self {
+            storage: Storage::Rewrites {
+                base: Arc::new(circ),
+                rewrites: Vec::new(),
+                traces: Vec::new(),
+            },
+        }
+    }
+
+    /// A snapshot of a circuit obtained by a rewrite strategy from `base`.
+    ///
+    /// If [`BadgerOptions::compact_queue`] is set, and the circuit can be
+    /// represented as a [`CompactCircuit`], the compact form is stored. This
+    /// is never the case with [`BadgerOptions::match_radius`], as incremental
+    /// matching relies on the node indices of the circuit.
+    ///
+    /// Otherwise, only the rewrites are stored if the strategy reported them,
+    /// see [`RewriteResult::rewrites`], and the rewritten circuit is stored if
+    /// it did not.
+    pub fn from_result<C: CircuitCost>(
+        base: &Arc<Circuit>,
+        result: RewriteResult<C>,
+        options: &BadgerOptions,
+    ) -> Self {
+        if options.compact_queue && options.match_radius.is_none() {
+            if let Ok(circ) = CompactCircuit::try_from(&result.circ) {
+                return Self {
+                    storage: Storage::Compact(circ),
+                };
+            }
+        }
+        let Some(rewrites) = result.rewrites else {
+            return Self::new(result.circ);
+        };
+        let num_base_traces = base.rewrite_trace().map_or(0, |traces| traces.len());
+        let traces = result
+            .circ
+            .rewrite_trace()
+            .map_or_else(Vec::new, |traces| traces[num_base_traces..].to_vec());
+        Self {
+            storage: Storage::Rewrites {
+                base: base.clone(),
+                rewrites,
+                traces,
+            },
+        }
+    }
+
+    /// The number of rewrites applied to the base circuit.
+    #[allow(unused)]
+    pub fn num_rewrites(&self) -> usize {
+        match &self.storage {
+            Storage::Rewrites { rewrites, .. } => rewrites.len(),
+            Storage::Compact(_) => 0,
+        }
+    }
+
+    /// Whether the circuit is stored as a [`CompactCircuit`].
+    #[allow(unused)]
+    pub fn is_compact(&self) -> bool {
+        matches!(self.storage, Storage::Compact(_))
+    }
+
+    /// Returns a copy of the circuit.
+    pub fn to_circuit(&self) -> Circuit {
+        match &self.storage {
+            Storage::Compact(circ) => circ.to_circuit(),
+            Storage::Rewrites { .. } => self.clone().into_circuit(),
+        }
+    }
+
+    /// Returns the circuit, applying the rewrites to a copy of the base.
+    ///
+    /// The base circuit is not copied if no other snapshot shares it.
+    pub fn into_circuit(self) -> Circuit {
+        let (base, rewrites, traces) = match self.storage {
+            Storage::Compact(circ) => return circ.to_circuit(),
+            Storage::Rewrites {
+                base,
+                rewrites,
+                traces,
+            } => (base, rewrites, traces),
+        };
+        let mut circ = Arc::try_unwrap(base).unwrap_or_else(|base| (*base).clone());
+        for rewrite in rewrites {
+            rewrite
+                .apply_notrace(&mut circ)
+                .expect("The rewrites of a snapshot apply to its base circuit.");
+        }
+        for trace in traces {
+            circ.add_rewrite_trace(trace);
+        }
+        circ
+    }
+}
+
+impl From<Circuit> for CircuitSnapshot {
+    fn from(circ: Circuit) -> Self {
+        Self::new(circ)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::circuit::CircuitHash;
+    use crate::rewrite::strategy::{
+        GammaStrategyCost, GreedyRewriteStrategy, LexicographicCostFunction, RewriteStrategy,
+    };
+    use crate::rewrite::Subcircuit;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    /// `CX(0, 1); CX(0, 1); H(1); H(1)`
+    fn circ() -> Circuit {
+        build_simple_circuit(2, |c| {
+            c.append(Tk2Op::CX, [0, 1])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            c.append(Tk2Op::H, [1])?;
+            c.append(Tk2Op::H, [1])?;
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    /// Rewrites cancelling each pair of gates.
+    fn rewrites(circ: &Circuit) -> Vec<CircuitRewrite> {
+        let nodes = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        [(2, &nodes[..2]), (1, &nodes[2..])]
+            .into_iter()
+            .map(|(num_qubits, nodes)| {
+                let identity = build_simple_circuit(num_qubits, |_| Ok(())).unwrap();
+                Subcircuit::try_from_nodes(nodes.to_vec(), circ)
+                    .unwrap()
+                    .create_rewrite(circ, identity)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn check_snapshots<S: RewriteStrategy>(strategy: S, compact: bool) {
+        let base = Arc::new(circ());
+        let results = strategy
+            .apply_rewrites(rewrites(&base), &base)
+            .collect_vec();
+        assert!(!results.is_empty());
+        for result in results {
+            let expected = result.circ.clone();
+            let options = BadgerOptions {
+                compact_queue: compact,
+                ..Default::default()
+            };
+            let snapshot = CircuitSnapshot::from_result(&base, result, &options);
+            assert_eq!(snapshot.is_compact(), compact);
+            assert_eq!(snapshot.num_rewrites() > 0, !compact);
+            let circ = snapshot.into_circuit();
+            assert_eq!(circ.circuit_hash(), expected.circuit_hash());
+            assert_eq!(circ.rewrite_trace(), expected.rewrite_trace());
+        }
+        // The base circuit is unchanged.
+        assert_eq!(base.num_operations(), 4);
+    }
+
+    #[rstest]
+    #[case::rewrites(false)]
+    #[case::compact(true)]
+    fn greedy_snapshots(#[case] compact: bool) {
+        check_snapshots(GreedyRewriteStrategy, compact);
+    }
+
+    #[rstest]
+    #[case::rewrites(false)]
+    #[case::compact(true)]
+    fn exhaustive_snapshots(#[case] compact: bool) {
+        check_snapshots(LexicographicCostFunction::default_cx(), compact);
+        check_snapshots(GammaStrategyCost::exhaustive_cx(), compact);
+    }
+
+    #[test]
+    fn full_snapshot() {
+        let snapshot = CircuitSnapshot::new(circ());
+        assert_eq!(snapshot.num_rewrites(), 0);
+        assert_eq!(snapshot.to_circuit(), circ());
+        assert_eq!(snapshot.into_circuit(), circ());
+    }
+}
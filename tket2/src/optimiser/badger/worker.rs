@@ -1,40 +1,61 @@
 //! Distributed workers for the badger optimiser.
 
+use std::sync::Arc;
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crossbeam_channel::Sender;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
 
 use crate::circuit::cost::CircuitCost;
-use crate::circuit::CircuitHash;
 use crate::rewrite::strategy::RewriteStrategy;
 use crate::rewrite::Rewriter;
+use crate::seed::{self, Seed};
+
+use crate::Circuit;
 
-use super::hugr_pchannel::{PriorityChannelCommunication, Work};
+use super::sharded_pqueue::{ShardedHugrPQ, Work};
+use super::snapshot::CircuitSnapshot;
+use super::{seen_hash, BadgerOptions};
+
+/// How long an idle worker waits before trying to steal work again.
+const IDLE_BACKOFF: Duration = Duration::from_millis(1);
 
 /// A worker that processes circuits for the Badger optimiser.
-pub struct BadgerWorker<R, S, P: Ord> {
-    /// The worker ID.
-    #[allow(unused)]
+pub struct BadgerWorker<R, S, P: Ord, C> {
+    /// The worker ID, also the index of its queue shard.
     id: usize,
-    /// The channel to send and receive work from.
-    priority_channel: PriorityChannelCommunication<P>,
+    /// The shared queue to take work from and push results to.
+    pq: Arc<ShardedHugrPQ<P, C>>,
     /// The rewriter to use.
     rewriter: R,
     /// The rewrite strategy to use.
     strategy: S,
+    /// The options of the optimiser.
+    options: BadgerOptions,
+    /// The generator shuffling the rewrites, if any.
+    rng: Option<StdRng>,
 }
 
-impl<R, S, P> BadgerWorker<R, S, P>
+impl<R, S, P, C> BadgerWorker<R, S, P, C>
 where
     R: Rewriter + Send + 'static,
     S: RewriteStrategy<Cost = P> + Send + 'static,
     P: CircuitCost + Send + Sync + 'static,
+    C: Fn(&Circuit) -> P + Clone + Send + 'static,
 {
     /// Spawn a new worker thread.
-    #[allow(clippy::too_many_arguments)]
+    ///
+    /// The `done` sender is dropped when the worker terminates.
     pub fn spawn(
         id: usize,
-        priority_channel: PriorityChannelCommunication<P>,
+        pq: Arc<ShardedHugrPQ<P, C>>,
         rewriter: R,
         strategy: S,
+        options: BadgerOptions,
+        seed: Option<Seed>,
+        done: Sender<()>,
     ) -> JoinHandle<()> {
         let name = format!("BadgerWorker-{id}");
         thread::Builder::new()
@@ -42,28 +63,40 @@ where
             .spawn(move || {
                 let mut worker = Self {
                     id,
-                    priority_channel,
+                    pq,
                     rewriter,
                     strategy,
+                    options,
+                    rng: seed.map(seed::rng),
                 };
-                worker.run_loop()
+                worker.run_loop();
+                drop(done);
             })
             .unwrap()
     }
 
     /// Main loop of the worker.
     ///
-    /// Processes work until the main thread closes the channel send or receive
-    /// channel.
+    /// Processes work until the main thread closes the queue, or there are
+    /// no circuits left to process.
     #[tracing::instrument(target = "badger::metrics", skip(self))]
     fn run_loop(&mut self) {
-        loop {
-            let Ok(Work { circ, cost, .. }) = self.priority_channel.recv() else {
-                break;
+        while !self.pq.is_finished() {
+            let Some(Work { circ, cost, .. }) = self.pq.pop(self.id) else {
+                // Other workers may still produce new circuits.
+                thread::sleep(IDLE_BACKOFF);
+                continue;
             };
 
-            let rewrites = self.rewriter.get_rewrites(&circ);
-            let max_cost = self.priority_channel.max_cost();
+            let circ = Arc::new(circ.into_circuit());
+            let mut rewrites = self.rewriter.get_rewrites(&circ);
+            if let Some(rng) = &mut self.rng {
+                rewrites.shuffle(rng);
+            }
+            if let Some(profile) = &self.options.profile {
+                rewrites = profile.order_rewrites(rewrites, &circ);
+            }
+            let max_cost = self.pq.max_cost(self.id);
             let new_circs = self
                 .strategy
                 .apply_rewrites(rewrites, &circ)
@@ -73,7 +106,7 @@ where
                         return None;
                     }
 
-                    let Ok(hash) = r.circ.circuit_hash() else {
+                    let Some(hash) = seen_hash(&r.circ, self.options.canonical_hashing) else {
                         // The composed rewrites were not valid.
                         //
                         // See [https://github.com/CQCL/tket2/discussions/242]
@@ -83,17 +116,14 @@ where
                     Some(Work {
                         cost: new_cost,
                         hash,
-                        circ: r.circ,
+                        circ: CircuitSnapshot::from_result(&circ, r, &self.options),
                     })
                 })
                 .collect();
 
-            let send = tracing::trace_span!(target: "badger::metrics", "BadgerWorker::send_result")
-                .in_scope(|| self.priority_channel.send(new_circs));
-            if send.is_err() {
-                // Terminating
-                break;
-            }
+            tracing::trace_span!(target: "badger::metrics", "BadgerWorker::send_result")
+                .in_scope(|| self.pq.push(self.id, new_circs));
+            self.pq.done();
         }
     }
 }
@@ -0,0 +1,373 @@
+//! Exploration of the space of circuits reachable by rewrites.
+//!
+//! Unlike the [`BadgerOptimiser`](super::BadgerOptimiser), which only returns
+//! the best circuit found, the [`RewriteExplorer`] records every circuit it
+//! visits in a [`RewriteDag`]. Circuits are deduplicated using their
+//! [`CircuitHash`], and an edge links each circuit to the circuits obtained by
+//! rewriting it.
+//!
+//! The DAG can then be queried for the `k` best circuits seen, and to
+//! backtrack from any circuit to the sequence of circuits that produced it.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::Instant;
+
+use fxhash::FxHashMap;
+use hugr::HugrView;
+
+use crate::circuit::cost::CircuitCost;
+use crate::circuit::CircuitHash;
+use crate::rewrite::strategy::RewriteStrategy;
+use crate::rewrite::Rewriter;
+use crate::Circuit;
+
+/// Configuration options for the [`RewriteExplorer`].
+#[derive(Copy, Clone, Debug)]
+pub struct ExplorerOptions {
+    /// The maximum number of distinct circuits to record in the DAG.
+    ///
+    /// Defaults to `1000`.
+    pub max_circuits: usize,
+    /// The maximum number of rewrite steps from the input circuit.
+    ///
+    /// Defaults to `None`, which means no limit.
+    pub max_depth: Option<usize>,
+    /// The maximum time (in seconds) to explore for.
+    ///
+    /// Defaults to `None`, which means no timeout.
+    pub timeout: Option<u64>,
+}
+
+impl Default for ExplorerOptions {
+    fn default() -> Self {
+        Self {
+            max_circuits: 1000,
+            max_depth: None,
+            timeout: None,
+        }
+    }
+}
+
+/// The index of a circuit in a [`RewriteDag`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DagNodeId(usize);
+
+/// A circuit recorded in a [`RewriteDag`].
+#[derive(Debug, Clone)]
+struct DagNode<C> {
+    circ: Circuit,
+    hash: u64,
+    cost: C,
+    depth: usize,
+    parents: Vec<DagNodeId>,
+    children: Vec<DagNodeId>,
+}
+
+/// A DAG of circuits, linked by the rewrites that transform one into another.
+///
+/// Nodes are indexed in the order in which they were discovered, starting with
+/// the input circuit at the [`RewriteDag::root`].
+#[derive(Debug, Clone)]
+pub struct RewriteDag<C> {
+    nodes: Vec<DagNode<C>>,
+    hash_lookup: FxHashMap<u64, DagNodeId>,
+}
+
+impl<C: CircuitCost> RewriteDag<C> {
+    fn new(circ: Circuit, hash: u64, cost: C) -> Self {
+        let root = DagNode {
+            circ,
+            hash,
+            cost,
+            depth: 0,
+            parents: Vec::new(),
+            children: Vec::new(),
+        };
+        Self {
+            nodes: vec![root],
+            hash_lookup: [(hash, DagNodeId(0))].into_iter().collect(),
+        }
+    }
+
+    /// The input circuit of the exploration.
+    pub fn root(&self) -> DagNodeId {
+        DagNodeId(0)
+    }
+
+    /// The number of distinct circuits in the DAG.
+    ///
+    /// The DAG always contains the input circuit, so this is at least `1`.
+    pub fn n_circuits(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The circuits in the DAG, in discovery order.
+    pub fn nodes(&self) -> impl Iterator<Item = DagNodeId> {
+        (0..self.nodes.len()).map(DagNodeId)
+    }
+
+    /// The circuit at a node.
+    pub fn circuit(&self, node: DagNodeId) -> &Circuit {
+        &self.nodes[node.0].circ
+    }
+
+    /// The hash of the circuit at a node.
+    pub fn hash(&self, node: DagNodeId) -> u64 {
+        self.nodes[node.0].hash
+    }
+
+    /// The cost of the circuit at a node.
+    pub fn cost(&self, node: DagNodeId) -> &C {
+        &self.nodes[node.0].cost
+    }
+
+    /// The number of rewrite steps through which a node was first discovered.
+    pub fn depth(&self, node: DagNodeId) -> usize {
+        self.nodes[node.0].depth
+    }
+
+    /// The circuits from which a node was obtained by rewriting.
+    pub fn parents(&self, node: DagNodeId) -> &[DagNodeId] {
+        &self.nodes[node.0].parents
+    }
+
+    /// The circuits obtained by rewriting a node.
+    pub fn children(&self, node: DagNodeId) -> &[DagNodeId] {
+        &self.nodes[node.0].children
+    }
+
+    /// Find the node of a circuit, given its hash.
+    pub fn find(&self, hash: u64) -> Option<DagNodeId> {
+        self.hash_lookup.get(&hash).copied()
+    }
+
+    /// The `k` best circuits seen, in ascending cost order.
+    ///
+    /// Circuits with equal cost are ordered by discovery.
+    pub fn best(&self, k: usize) -> Vec<DagNodeId> {
+        let mut nodes: Vec<_> = self.nodes().collect();
+        nodes.sort_by(|&a, &b| self.cost(a).cmp(self.cost(b)).then(a.cmp(&b)));
+        nodes.truncate(k);
+        nodes
+    }
+
+    /// The sequence of rewritten circuits through which `node` was first
+    /// discovered, from the input circuit to `node` included.
+    ///
+    /// The first parent of each node is the circuit it was discovered from.
+    pub fn path_from_root(&self, node: DagNodeId) -> Vec<DagNodeId> {
+        let mut path = vec![node];
+        let mut curr = node;
+        while let Some(&parent) = self.parents(curr).first() {
+            path.push(parent);
+            curr = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Add a circuit obtained by rewriting `parent`.
+    ///
+    /// Returns the new node, or `None` if the circuit had already been seen.
+    fn add_child(
+        &mut self,
+        parent: DagNodeId,
+        circ: Circuit,
+        hash: u64,
+        cost: C,
+    ) -> Option<DagNodeId> {
+        if let Some(existing) = self.find(hash) {
+            // Only record the edge if it does not create a cycle.
+            if existing != parent
+                && !self.is_reachable(existing, parent)
+                && !self.children(parent).contains(&existing)
+            {
+                self.nodes[parent.0].children.push(existing);
+                self.nodes[existing.0].parents.push(parent);
+            }
+            return None;
+        }
+        let id = DagNodeId(self.nodes.len());
+        self.nodes.push(DagNode {
+            circ,
+            hash,
+            cost,
+            depth: self.depth(parent) + 1,
+            parents: vec![parent],
+            children: Vec::new(),
+        });
+        self.nodes[parent.0].children.push(id);
+        self.hash_lookup.insert(hash, id);
+        Some(id)
+    }
+
+    /// Whether `to` can be reached from `from` by following child edges.
+    fn is_reachable(&self, from: DagNodeId, to: DagNodeId) -> bool {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack = vec![from];
+        while let Some(node) = stack.pop() {
+            if node == to {
+                return true;
+            }
+            if std::mem::replace(&mut visited[node.0], true) {
+                continue;
+            }
+            stack.extend_from_slice(self.children(node));
+        }
+        false
+    }
+}
+
+/// An explorer of the circuits reachable by rewriting.
+///
+/// Circuits are explored best-first: at each step the unexplored circuit with
+/// the lowest cost is rewritten, using the rewrite strategy to generate new
+/// circuits from the rewrites of the rewriter.
+#[derive(Clone, Debug)]
+pub struct RewriteExplorer<R, S> {
+    rewriter: R,
+    strategy: S,
+}
+
+impl<R, S> RewriteExplorer<R, S> {
+    /// Create a new explorer.
+    pub fn new(rewriter: R, strategy: S) -> Self {
+        Self { rewriter, strategy }
+    }
+}
+
+impl<R, S> RewriteExplorer<R, S>
+where
+    R: Rewriter,
+    S: RewriteStrategy,
+{
+    /// Explore the circuits reachable from `circ`.
+    pub fn explore(
+        &self,
+        circ: &Circuit<impl HugrView>,
+        options: ExplorerOptions,
+    ) -> RewriteDag<S::Cost> {
+        let start_time = Instant::now();
+        let circ = circ.to_owned();
+        let hash = circ.circuit_hash().unwrap();
+        let cost = self.strategy.circuit_cost(&circ);
+        let mut dag = RewriteDag::new(circ, hash, cost.clone());
+
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((cost, dag.root())));
+        while let Some(Reverse((cost, node))) = queue.pop() {
+            if options.max_depth.is_some_and(|max| dag.depth(node) >= max) {
+                continue;
+            }
+            let circ = dag.circuit(node);
+            let rewrites = self.rewriter.get_rewrites(circ);
+            let results = self
+                .strategy
+                .apply_rewrites(rewrites, circ)
+                .collect::<Vec<_>>();
+            for r in results {
+                if dag.n_circuits() >= options.max_circuits {
+                    return dag;
+                }
+                let Ok(new_hash) = r.circ.circuit_hash() else {
+                    // The composed rewrites produced a loop.
+                    continue;
+                };
+                let new_cost = cost.add_delta(&r.cost_delta);
+                if let Some(child) = dag.add_child(node, r.circ, new_hash, new_cost.clone()) {
+                    queue.push(Reverse((new_cost, child)));
+                }
+            }
+            if let Some(timeout) = options.timeout {
+                if start_time.elapsed().as_secs() > timeout {
+                    break;
+                }
+            }
+        }
+        dag
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "portmatching")]
+mod test {
+    use itertools::Itertools;
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::rewrite::strategy::{ExhaustiveGreedyStrategy, LexicographicCostFunction};
+    use crate::rewrite::ECCRewriter;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    type TestExplorer = RewriteExplorer<
+        ECCRewriter,
+        ExhaustiveGreedyStrategy<LexicographicCostFunction<fn(&hugr::ops::OpType) -> usize, 2>>,
+    >;
+
+    #[fixture]
+    fn explorer() -> TestExplorer {
+        let rewriter =
+            ECCRewriter::try_from_eccs_json_file("../test_files/eccs/small_eccs.json").unwrap();
+        RewriteExplorer::new(rewriter, LexicographicCostFunction::default_cx().into())
+    }
+
+    fn circ() -> Circuit {
+        build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::X, [0])?;
+            circ.append(Tk2Op::Tdg, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::T, [1])?;
+            circ.append(Tk2Op::X, [1])?;
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    #[rstest]
+    fn explore_dag(explorer: TestExplorer) {
+        let dag = explorer.explore(&circ(), ExplorerOptions::default());
+        assert!(dag.n_circuits() > 1);
+        assert!(dag.parents(dag.root()).is_empty());
+
+        // Circuits are deduplicated by hash.
+        for node in dag.nodes() {
+            let hash = dag.circuit(node).circuit_hash().unwrap();
+            assert_eq!(dag.hash(node), hash);
+            assert_eq!(dag.find(hash), Some(node));
+        }
+
+        // The best circuits are sorted by cost.
+        let best = dag.best(3);
+        assert_eq!(best.len(), 3.min(dag.n_circuits()));
+        assert!(best
+            .iter()
+            .tuple_windows()
+            .all(|(&a, &b)| dag.cost(a) <= dag.cost(b)));
+
+        // Every circuit can be traced back to the input.
+        for node in dag.nodes() {
+            let path = dag.path_from_root(node);
+            assert_eq!(path[0], dag.root());
+            assert_eq!(path.last(), Some(&node));
+            assert_eq!(path.len(), dag.depth(node) + 1);
+        }
+    }
+
+    #[rstest]
+    fn explore_limits(explorer: TestExplorer) {
+        let options = ExplorerOptions {
+            max_circuits: 2,
+            ..Default::default()
+        };
+        assert_eq!(explorer.explore(&circ(), options).n_circuits(), 2);
+
+        let options = ExplorerOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let dag = explorer.explore(&circ(), options);
+        assert!(dag.nodes().all(|n| dag.depth(n) <= 1));
+    }
+}
@@ -1,13 +1,60 @@
 //! Optimisation passes and related utilities for circuits.
 
 mod commutation;
-pub use commutation::{apply_greedy_commutation, PullForwardError};
+pub use commutation::{
+    apply_greedy_commutation, apply_list_scheduling_commutation, CommutationReport,
+    PullForwardError, SchedulingPriority,
+};
 
-pub mod chunks;
-pub use chunks::CircuitChunks;
+/// Circuit chunking, re-exported from its previous location.
+///
+/// See [`crate::circuit::chunks`].
+pub use crate::circuit::chunks;
+pub use crate::circuit::chunks::CircuitChunks;
+
+pub mod angle_normalisation;
+pub use angle_normalisation::{
+    normalise_angles, AngleNormalisationConfig, AngleNormalisationReport,
+};
+
+pub mod clifford_t;
+pub use clifford_t::{clifford_t_synthesis, CliffordTReport};
+
+pub mod controlled;
+pub use controlled::{controlled_decomposition, decompose_controlled_gates};
+
+pub mod cx_cancellation;
+pub use cx_cancellation::{cx_cancellation, CxCancellationConfig, CxCancellationReport};
+
+pub mod dce;
+pub use dce::{eliminate_dead_code, DceReport};
+
+pub mod known_state;
+pub use known_state::{simplify_known_states, KnownStateConfig, KnownStateReport};
 
 pub mod pytket;
 pub use pytket::lower_to_pytket;
 
+pub mod pulse;
+pub use pulse::{lower_to_pulses, LoweringError, LoweringTable};
+
+pub mod qubit_remap;
+pub use qubit_remap::{plan_qubit_remap, remap_qubit_segments, NoiseProfile, QubitRemap};
+
+pub mod redundancy;
+pub use redundancy::{cancel_commuting_inverses, remove_redundancies, RedundancyReport};
+
+pub mod resynth;
+pub use resynth::{peephole, PeepholeReport};
+
+pub mod rebase;
+pub use rebase::{rebase, GateSet, NativeGate, RebaseError, RebaseRegistry};
+
+pub mod squash;
+pub use squash::{squash_single_qubit_gates, EulerBasis, SquashReport};
+
+pub mod t_schedule;
+pub use t_schedule::{plan_t_schedule, schedule_t_gates, MagicStateConfig, TGateSchedule};
+
 pub mod tuple_unpack;
 pub use tuple_unpack::find_tuple_unpack_rewrites;
@@ -0,0 +1,385 @@
+//! Normalisation of rotation angles.
+//!
+//! [`normalise_angles`] brings the constant angles of rotation gates into the
+//! range `(-π, π]`, replaces rotations by special angles with the equivalent
+//! named gates, and removes rotations by angles close to zero. The global
+//! phase of the circuit is updated to account for the changes.
+
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI, TAU};
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::{HugrView, IncomingPort, Node, PortIndex, Wire};
+use num_rational::Rational64;
+
+use crate::circuit::phase::GlobalPhase;
+use crate::ops::match_symbolic_expr;
+use crate::sim::gate_matrix;
+use crate::sim::unitary::relative_phase;
+use crate::utils::TOLERANCE;
+use crate::utils::{float_wire_value, load_float, remove_unused_param};
+use crate::{symbolic_expr_op, Circuit, SymbolicExpr, Tk2Op};
+
+/// Configuration for [`normalise_angles`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AngleNormalisationConfig {
+    /// Replace Z and X rotations by multiples of `π/4` with the equivalent
+    /// named gates, such as `Rz(π/2)` with `S`.
+    ///
+    /// Defaults to `true`.
+    pub named_gates: bool,
+    /// Remove the rotations whose angles are all within
+    /// [`AngleNormalisationConfig::tolerance`] of zero.
+    ///
+    /// Defaults to `true`.
+    pub remove_identities: bool,
+    /// Absolute tolerance, in radians, used when comparing angles to zero and
+    /// to the angles of named gates.
+    ///
+    /// Defaults to `1e-9`.
+    pub tolerance: f64,
+}
+
+impl Default for AngleNormalisationConfig {
+    fn default() -> Self {
+        Self {
+            named_gates: true,
+            remove_identities: true,
+            tolerance: TOLERANCE,
+        }
+    }
+}
+
+/// The changes made by [`normalise_angles`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AngleNormalisationReport {
+    /// The number of angles brought into the range `(-π, π]`.
+    pub normalised_angles: usize,
+    /// The number of rotations replaced by named gates.
+    pub named_gates: usize,
+    /// The number of rotations removed.
+    pub removed_rotations: usize,
+}
+
+/// Normalise the angles of the rotation gates in a circuit.
+///
+/// Constant angles are reduced modulo `2π` into the range `(-π, π]`. The
+/// constant terms of symbolic angles are reduced in the same way. Then, if
+/// [`AngleNormalisationConfig::named_gates`] is set, Z and X rotations by
+/// special angles are replaced by named gates: `Rz(π)` by `Z`, `Rz(±π/2)` by
+/// `S` or `Sdg`, `Rz(±π/4)` by `T` or `Tdg`, and `Rx(π)` by `X`. If
+/// [`AngleNormalisationConfig::remove_identities`] is set, rotations by
+/// angles close to zero are removed.
+///
+/// Frozen gates are left untouched.
+pub fn normalise_angles(
+    circ: &mut Circuit,
+    config: AngleNormalisationConfig,
+) -> AngleNormalisationReport {
+    let mut report = AngleNormalisationReport::default();
+    let mut phase = 0.;
+    let gates: Vec<(Node, Tk2Op)> = circ
+        .commands()
+        .filter(|cmd| !circ.is_frozen(cmd.node()))
+        .filter_map(|cmd| Some((cmd.node(), Tk2Op::try_from(cmd.optype()).ok()?)))
+        .collect();
+    for (node, op) in gates {
+        let ports = angle_ports(op);
+        if ports.is_empty() {
+            continue;
+        }
+        let mut angles = Vec::with_capacity(ports.len());
+        for &(port, periodic) in ports {
+            let port = IncomingPort::from(port);
+            let (angle, turns) = normalise_angle(circ, node, port);
+            if turns != 0 {
+                report.normalised_angles += 1;
+                // A shift by an odd number of turns negates the gate.
+                if !periodic && turns % 2 != 0 {
+                    phase += PI;
+                }
+            }
+            angles.push(angle);
+        }
+        let Some(angles) = angles.into_iter().collect::<Option<Vec<f64>>>() else {
+            continue;
+        };
+
+        if config.remove_identities && is_identity(op, &angles, config.tolerance) {
+            circ.remove_command(node)
+                .expect("Rotations can always be removed.");
+            report.removed_rotations += 1;
+        } else if let Some(named) = config
+            .named_gates
+            .then(|| named_gate(op, &angles, config.tolerance))
+            .flatten()
+        {
+            phase += relative_phase(
+                &gate_matrix(op, &angles).unwrap(),
+                &gate_matrix(named, &[]).unwrap(),
+            );
+            replace_gate(circ, node, named);
+            report.named_gates += 1;
+        }
+    }
+    if phase.rem_euclid(TAU) > config.tolerance && TAU - phase.rem_euclid(TAU) > config.tolerance {
+        circ.add_global_phase(Some(GlobalPhase::new(phase / PI)));
+    }
+    report
+}
+
+/// The angle inputs of a rotation gate.
+///
+/// Each port is paired with `true` if shifting its angle by `2π` leaves the
+/// gate unchanged, and `false` if it negates it.
+fn angle_ports(op: Tk2Op) -> &'static [(usize, bool)] {
+    match op {
+        Tk2Op::RzF64 | Tk2Op::RxF64 => &[(1, false)],
+        Tk2Op::ZZPhase | Tk2Op::XXPhase | Tk2Op::YYPhase => &[(2, false)],
+        // PhasedX(θ, φ) = Rz(φ) Rx(θ) Rz(-φ)
+        Tk2Op::PhasedX => &[(1, false), (2, true)],
+        Tk2Op::TK1 => &[(1, false), (2, false), (3, false)],
+        _ => &[],
+    }
+}
+
+/// Reduce an angle input of a gate into the range `(-π, π]`.
+///
+/// Returns the new angle if it is a constant, and the number of turns
+/// removed from it.
+fn normalise_angle(circ: &mut Circuit, node: Node, port: IncomingPort) -> (Option<f64>, i64) {
+    let parent = circ.parent();
+    let hugr = circ.hugr_mut();
+    let Some((src, src_port)) = hugr.single_linked_output(node, port) else {
+        return (None, 0);
+    };
+    if let Some(value) = float_wire_value(hugr, Wire::new(src, src_port)) {
+        let turns = ((value + PI) / TAU).ceil() as i64 - 1;
+        if turns != 0 {
+            let load = load_float(hugr, parent, value - turns as f64 * TAU);
+            reconnect_angle(hugr, node, port, load);
+        }
+        return (Some(value - turns as f64 * TAU), turns);
+    }
+    if let Some(expr) = match_symbolic_expr(hugr.get_optype(src)) {
+        // Symbolic expressions are in half-turns.
+        let constant = expr.constant_term();
+        let turns = ((constant + 1) / 2).ceil().to_integer() - 1;
+        if turns != 0 {
+            let expr = expr - SymbolicExpr::constant(Rational64::from_integer(2 * turns));
+            let symbol = hugr.add_node_with_parent(parent, symbolic_expr_op(&expr));
+            reconnect_angle(hugr, node, port, symbol);
+        }
+        return (None, turns);
+    }
+    (None, 0)
+}
+
+/// Connect the output of `source` to an angle input, removing the previous
+/// angle computation if it is no longer used.
+fn reconnect_angle(hugr: &mut impl HugrMut, node: Node, port: IncomingPort, source: Node) {
+    let (old, _) = hugr.single_linked_output(node, port).unwrap();
+    hugr.disconnect(node, port);
+    hugr.connect(source, 0, node, port);
+    remove_unused_param(hugr, old);
+}
+
+/// Returns `true` if a rotation with normalised angles is the identity.
+fn is_identity(op: Tk2Op, angles: &[f64], tolerance: f64) -> bool {
+    let is_zero = |angle: &f64| angle.abs() < tolerance;
+    match op {
+        // The phase of a PhasedX gate is irrelevant when it does not rotate.
+        Tk2Op::PhasedX => is_zero(&angles[0]),
+        _ => angles.iter().all(is_zero),
+    }
+}
+
+/// The named gate equal to a rotation with normalised angles, up to a global
+/// phase.
+fn named_gate(op: Tk2Op, angles: &[f64], tolerance: f64) -> Option<Tk2Op> {
+    let candidates: &[(f64, Tk2Op)] = match op {
+        Tk2Op::RzF64 => &[
+            (PI, Tk2Op::Z),
+            (FRAC_PI_2, Tk2Op::S),
+            (-FRAC_PI_2, Tk2Op::Sdg),
+            (FRAC_PI_4, Tk2Op::T),
+            (-FRAC_PI_4, Tk2Op::Tdg),
+        ],
+        Tk2Op::RxF64 => &[(PI, Tk2Op::X)],
+        _ => return None,
+    };
+    candidates.iter().find_map(|&(target, named)| {
+        // Angles close to -π are equivalent to π.
+        let diff = (angles[0] - target).rem_euclid(TAU);
+        (diff < tolerance || TAU - diff < tolerance).then_some(named)
+    })
+}
+
+/// Replace a single-qubit rotation by a gate without parameters.
+fn replace_gate(circ: &mut Circuit, node: Node, op: Tk2Op) {
+    let parent = circ.parent();
+    let hugr = circ.hugr_mut();
+    let (src, src_port) = hugr.single_linked_output(node, 0).unwrap();
+    let (dst, dst_port) = hugr.single_linked_input(node, 0).unwrap();
+    let params: Vec<Node> = hugr.input_neighbours(node).filter(|&n| n != src).collect();
+    hugr.remove_node(node);
+    let new = hugr.add_node_with_parent(parent, op);
+    hugr.connect(src, src_port, new, 0);
+    hugr.connect(new, 0, dst, dst_port.index());
+    for param in params {
+        remove_unused_param(hugr, param);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use num_complex::Complex64;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::sim::unitary::equal_up_to_phase;
+    use crate::utils::test::gates;
+    use crate::utils::test::tk1_circuit;
+    /// The constant angles of the gates in a circuit, in radians.
+    fn angles(circ: &Circuit) -> Vec<f64> {
+        circ.commands()
+            .filter(|cmd| Tk2Op::try_from(cmd.optype()).is_ok())
+            .flat_map(|cmd| {
+                let node = cmd.node();
+                cmd.inputs()
+                    .filter_map(|(_, port, _)| circ.hugr().single_linked_output(node, port))
+                    .filter_map(|(src, src_port)| {
+                        float_wire_value(circ.hugr(), Wire::new(src, src_port))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case::rz_pi("Rz", "1", Tk2Op::Z)]
+    #[case::rz_minus_pi("Rz", "-1", Tk2Op::Z)]
+    #[case::s("Rz", "0.5", Tk2Op::S)]
+    #[case::sdg("Rz", "3.5", Tk2Op::Sdg)]
+    #[case::t("Rz", "0.25", Tk2Op::T)]
+    #[case::tdg("Rz", "-0.25", Tk2Op::Tdg)]
+    #[case::x("Rx", "-3", Tk2Op::X)]
+    fn named_gates(#[case] gate: &str, #[case] angle: &str, #[case] expected: Tk2Op) {
+        let mut circ = tk1_circuit(
+            2,
+            0,
+            &format!(
+                r#"{{"args": [["q", [0]]], "op": {{"type": "{gate}", "params": ["{angle}"]}}}}"#
+            ),
+        );
+        let unitary = circ.unitary().unwrap();
+
+        let report = normalise_angles(&mut circ, AngleNormalisationConfig::default());
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.named_gates, 1);
+        assert_eq!(gates(&circ), [expected]);
+        // The angle constant is removed.
+        assert_eq!(circ.hugr().children(circ.parent()).count(), 3);
+        let phase = Complex64::from_polar(1., circ.global_phase().unwrap().constant() * PI);
+        let new_unitary = circ.unitary().unwrap().mapv(|x| x * phase);
+        assert!((new_unitary - unitary).iter().all(|x| x.norm() < 1e-9));
+    }
+
+    #[test]
+    fn normalise_range() {
+        let mut circ = tk1_circuit(
+            2,
+            0,
+            r#"{"args": [["q", [0]]], "op": {"type": "Rz", "params": ["2.3"]}},
+               {"args": [["q", [0]], ["q", [1]]], "op": {"type": "ZZPhase", "params": ["-1.7"]}},
+               {"args": [["q", [1]]], "op": {"type": "PhasedX", "params": ["0.3", "4.1"]}},
+               {"args": [["q", [1]]], "op": {"type": "Rx", "params": ["0.6"]}}"#,
+        );
+        let unitary = circ.unitary().unwrap();
+
+        let report = normalise_angles(&mut circ, AngleNormalisationConfig::default());
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.normalised_angles, 3);
+        assert_eq!(report.named_gates, 0);
+        let angles = angles(&circ);
+        let expected = [0.3, 0.3, 0.3, 0.1, 0.6].map(|x| x * PI);
+        assert_eq!(angles.len(), expected.len());
+        for (angle, expected) in angles.iter().zip(expected) {
+            assert!((angle - expected).abs() < 1e-9, "{angle} != {expected}");
+        }
+        // `Rz(θ + 2π) = -Rz(θ)`, and the two shifts cancel out.
+        assert_eq!(circ.global_phase(), Some(GlobalPhase::default()));
+        assert!(equal_up_to_phase(&circ.unitary().unwrap(), &unitary, 1e-9));
+    }
+
+    #[rstest]
+    #[case::remove(true, 3, [Tk2Op::PhasedX])]
+    #[case::keep(false, 0, [Tk2Op::RzF64, Tk2Op::PhasedX, Tk2Op::ZZPhase, Tk2Op::PhasedX])]
+    fn identities(
+        #[case] remove_identities: bool,
+        #[case] removed: usize,
+        #[case] expected: impl AsRef<[Tk2Op]>,
+    ) {
+        let mut circ = tk1_circuit(
+            2,
+            0,
+            r#"{"args": [["q", [0]]], "op": {"type": "Rz", "params": ["1e-12"]}},
+               {"args": [["q", [0]]], "op": {"type": "PhasedX", "params": ["0.5", "0.2"]}},
+               {"args": [["q", [0]], ["q", [1]]], "op": {"type": "ZZPhase", "params": ["2"]}},
+               {"args": [["q", [1]]], "op": {"type": "PhasedX", "params": ["0", "0.2"]}}"#,
+        );
+        let config = AngleNormalisationConfig {
+            remove_identities,
+            ..Default::default()
+        };
+
+        let report = normalise_angles(&mut circ, config);
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.removed_rotations, removed);
+        assert_eq!(gates(&circ), expected.as_ref());
+    }
+
+    #[test]
+    fn symbolic_constant_term() {
+        let mut circ = tk1_circuit(
+            2,
+            0,
+            r#"{"args": [["q", [0]]], "op": {"type": "Rz", "params": ["a + 3"]}}"#,
+        );
+
+        let report = normalise_angles(&mut circ, AngleNormalisationConfig::default());
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.normalised_angles, 1);
+        let symbols: Vec<_> = circ
+            .commands()
+            .filter_map(|cmd| match_symbolic_expr(cmd.optype()))
+            .collect();
+        assert_eq!(symbols, [SymbolicExpr::parse("a + 1")]);
+        let phase = circ.global_phase().unwrap();
+        assert!((phase.constant() - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn frozen_gates() {
+        let mut circ = tk1_circuit(
+            2,
+            0,
+            r#"{"args": [["q", [0]]], "op": {"type": "Rz", "params": ["2.5"]}}"#,
+        );
+        let node = circ
+            .commands()
+            .find(|cmd| Tk2Op::try_from(cmd.optype()) == Ok(Tk2Op::RzF64))
+            .unwrap()
+            .node();
+        circ.freeze_nodes([node]);
+
+        let report = normalise_angles(&mut circ, AngleNormalisationConfig::default());
+
+        assert_eq!(report, AngleNormalisationReport::default());
+        assert_eq!(gates(&circ), [Tk2Op::RzF64]);
+    }
+}
@@ -0,0 +1,190 @@
+//! Compilation of single-qubit rotations to the Clifford+T gate set.
+//!
+//! [`clifford_t_synthesis`] replaces each single-qubit rotation with constant
+//! angles by a Clifford+T circuit approximating it, using
+//! [`approximate_unitary`].
+
+use std::f64::consts::PI;
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::{HugrView, Node, Wire};
+use ndarray::Array2;
+
+use crate::circuit::phase::GlobalPhase;
+use crate::sim::gate_matrix;
+use crate::synthesis::{approximate_rz, approximate_unitary, SynthesisError};
+use crate::utils::{constant_params, remove_unused_param};
+use crate::{Circuit, Tk2Op};
+
+/// The changes made by [`clifford_t_synthesis`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CliffordTReport {
+    /// The number of rotations replaced.
+    pub replaced_rotations: usize,
+    /// The number of rotations left in the circuit because their angles are
+    /// not constant.
+    pub symbolic_rotations: usize,
+    /// The total number of T gates added.
+    pub t_count: usize,
+    /// The largest approximation error of a single rotation.
+    pub max_error: f64,
+}
+
+/// Replace the single-qubit rotations of a circuit by Clifford+T circuits.
+///
+/// Each `Rz`, `Rx`, `PhasedX` and `TK1` gate with constant angles is
+/// approximated within `epsilon`, up to a global phase. The global phase of
+/// the circuit is updated with the phases of the approximations. Rotations by
+/// symbolic angles and frozen gates are left untouched.
+///
+/// The errors of the individual rotations add up, so `epsilon` should be
+/// divided by the number of rotations to bound the error of the whole
+/// circuit.
+///
+/// # Errors
+///
+/// Returns an error if `epsilon` is not positive, or if a rotation cannot be
+/// approximated within `epsilon`. The circuit is left unchanged in that case.
+pub fn clifford_t_synthesis(
+    circ: &mut Circuit,
+    epsilon: f64,
+) -> Result<CliffordTReport, SynthesisError> {
+    let mut report = CliffordTReport::default();
+    let mut replacements = Vec::new();
+    for cmd in circ.commands() {
+        let node = cmd.node();
+        let Ok(op) = Tk2Op::try_from(cmd.optype()) else {
+            continue;
+        };
+        if circ.is_frozen(node)
+            || !matches!(
+                op,
+                Tk2Op::RzF64 | Tk2Op::RxF64 | Tk2Op::PhasedX | Tk2Op::TK1
+            )
+        {
+            continue;
+        }
+        let Some(params) = constant_params(circ.hugr(), node) else {
+            report.symbolic_rotations += 1;
+            continue;
+        };
+        let approx = match op {
+            Tk2Op::RzF64 => approximate_rz(params[0], epsilon)?,
+            _ => {
+                let u = gate_matrix(op, &params).unwrap();
+                approximate_unitary(&Array2::from_shape_vec((2, 2), u).unwrap(), epsilon)?
+            }
+        };
+        report.replaced_rotations += 1;
+        report.t_count += approx.t_count();
+        report.max_error = report.max_error.max(approx.error);
+        replacements.push((node, approx));
+    }
+
+    let mut phase = 0.;
+    for (node, approx) in replacements {
+        phase += approx.phase;
+        replace_rotation(circ, node, &approx.gates);
+    }
+    circ.add_global_phase(Some(GlobalPhase::new(phase / PI)));
+    Ok(report)
+}
+
+/// Replace a single-qubit rotation by a sequence of gates without parameters.
+fn replace_rotation(circ: &mut Circuit, node: Node, gates: &[Tk2Op]) {
+    let parent = circ.parent();
+    let hugr = circ.hugr_mut();
+    let (src, src_port) = hugr.single_linked_output(node, 0).unwrap();
+    let (dst, dst_port) = hugr.single_linked_input(node, 0).unwrap();
+    let params: Vec<Node> = hugr.input_neighbours(node).filter(|&n| n != src).collect();
+    hugr.remove_node(node);
+
+    let mut prev = Wire::new(src, src_port);
+    for &op in gates {
+        let new = hugr.add_node_with_parent(parent, op);
+        hugr.connect(prev.node(), prev.source(), new, 0);
+        prev = Wire::new(new, 0);
+    }
+    hugr.connect(prev.node(), prev.source(), dst, dst_port);
+    for param in params {
+        remove_unused_param(hugr, param);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::utils::test::tk1_circuit;
+    #[test]
+    fn compile_to_clifford_t() {
+        let mut circ = tk1_circuit(
+            2,
+            0,
+            r#"{"args": [["q", [0]]], "op": {"type": "Rz", "params": ["0.123"]}},
+               {"args": [["q", [0]], ["q", [1]]], "op": {"type": "CX"}},
+               {"args": [["q", [1]]], "op": {"type": "TK1", "params": ["0.1", "0.7", "-0.3"]}},
+               {"args": [["q", [0]]], "op": {"type": "Rx", "params": ["0.25"]}},
+               {"args": [["q", [1]]], "op": {"type": "Rz", "params": ["a"]}}"#,
+        );
+        let mut expected = circ.clone();
+
+        let report = clifford_t_synthesis(&mut circ, 1e-3).unwrap();
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.replaced_rotations, 3);
+        assert_eq!(report.symbolic_rotations, 1);
+        assert!(report.max_error <= 1e-3);
+        let gates: Vec<Tk2Op> = circ
+            .commands()
+            .filter_map(|cmd| cmd.optype().try_into().ok())
+            .collect();
+        assert!(gates.iter().all(|op| matches!(
+            op,
+            Tk2Op::H | Tk2Op::S | Tk2Op::Sdg | Tk2Op::T | Tk2Op::Tdg | Tk2Op::Z | Tk2Op::CX
+        ) || op == &Tk2Op::RzF64));
+        assert_eq!(
+            gates
+                .iter()
+                .filter(|op| matches!(op, Tk2Op::T | Tk2Op::Tdg))
+                .count(),
+            report.t_count
+        );
+
+        // Compare the unitaries, including the global phase, once the symbolic
+        // rotation is removed from both circuits.
+        for c in [&mut circ, &mut expected] {
+            let rz = c
+                .commands()
+                .find(|cmd| {
+                    Tk2Op::try_from(cmd.optype()) == Ok(Tk2Op::RzF64)
+                        && constant_params(c.hugr(), cmd.node()).is_none()
+                })
+                .unwrap()
+                .node();
+            c.remove_command(rz).unwrap();
+        }
+        let phase = |c: &Circuit| {
+            num_complex::Complex64::from_polar(1., c.global_phase().unwrap().constant() * PI)
+        };
+        let u = circ.unitary().unwrap().mapv(|x| x * phase(&circ));
+        let v = expected.unitary().unwrap().mapv(|x| x * phase(&expected));
+        let diff = (u - v).iter().map(|x| x.norm()).fold(0., f64::max);
+        assert!(diff < 1e-2, "{diff}");
+    }
+
+    #[test]
+    fn precision_not_reached() {
+        let mut circ = tk1_circuit(
+            2,
+            0,
+            r#"{"args": [["q", [0]]], "op": {"type": "Rz", "params": ["0.3"]}}"#,
+        );
+        let before = circ.clone();
+        assert!(matches!(
+            clifford_t_synthesis(&mut circ, 1e-300),
+            Err(SynthesisError::PrecisionNotReached { .. })
+        ));
+        assert_eq!(circ.hugr().node_count(), before.hugr().node_count());
+    }
+}
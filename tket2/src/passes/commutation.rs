@@ -1,107 +1,81 @@
 use std::{collections::HashMap, rc::Rc};
 
 use hugr::hugr::{hugrmut::HugrMut, HugrError, Rewrite};
-use hugr::{CircuitUnit, Direction, HugrView, Node, Port, PortIndex};
+use hugr::{Direction, HugrView, Node, Port, PortIndex};
+use hugr_core::hugr::internal::HugrMutInternals;
 use itertools::Itertools;
-use portgraph::PortOffset;
+use tket_json_rs::optype::OpType as Tk1OpType;
 
+use crate::circuit::edit::{splice_in, splice_out};
+use crate::circuit::slices::schedule;
+use crate::serialize::pytket::OpaqueTk1Op;
 use crate::Circuit;
 use crate::{
-    circuit::command::Command,
-    ops::{Pauli, Tk2Op},
+    circuit::command::OwnedCommand,
+    ops::{op_commutation, Pauli, Tk2Op},
 };
 
 use thiserror::Error;
 
-type Qb = crate::circuit::units::LinearUnit;
+mod list_scheduling;
+pub use list_scheduling::{apply_list_scheduling_commutation, SchedulingPriority};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-// remove once https://github.com/CQCL-DEV/tket2/issues/126 is resolved
-struct ComCommand {
-    /// The operation node.
-    node: Node,
-    /// An assignment of linear units to the node's ports.
-    //
-    // We'll need something more complex if `follow_linear_port` stops being a
-    // direct map from input to output.
-    inputs: Vec<CircuitUnit>,
-}
+type Qb = crate::circuit::units::LinearUnit;
 
-impl<'c, T: HugrView> From<Command<'c, T>> for ComCommand {
-    fn from(com: Command<'c, T>) -> Self {
-        ComCommand {
-            node: com.node(),
-            inputs: com.inputs().map(|(c, _, _)| c).collect(),
-        }
-    }
-}
-impl ComCommand {
-    fn node(&self) -> Node {
-        self.node
-    }
-    fn qubits(&self) -> impl Iterator<Item = Qb> + '_ {
-        self.inputs.iter().filter_map(|u| {
-            let CircuitUnit::Linear(i) = u else {
-                return None;
-            };
-            Some(Qb::new(*i))
-        })
-    }
-    fn port_of_qb(&self, qb: Qb, direction: Direction) -> Option<Port> {
-        self.inputs
-            .iter()
-            .position(|cu| {
-                let q_cu: CircuitUnit = qb.into();
-                cu == &q_cu
-            })
-            .map(|i| PortOffset::new(direction, i).into())
-    }
+/// Returns the linear units a command acts on.
+fn qubits(command: &OwnedCommand) -> impl Iterator<Item = Qb> + '_ {
+    command.linear_inputs().map(|(q, _)| q)
 }
 
-type Slice = Vec<Option<Rc<ComCommand>>>;
+type Slice = Vec<Option<Rc<OwnedCommand>>>;
 type SliceVec = Vec<Slice>;
+/// For each qubit of a command, the qubit it acts on after being moved and the
+/// command it is moved in front of.
+type NewNexts = HashMap<Qb, (Qb, Rc<OwnedCommand>)>;
 
-fn add_to_slice(slice: &mut Slice, com: Rc<ComCommand>) {
-    for q in com.qubits() {
+fn add_to_slice(slice: &mut Slice, com: Rc<OwnedCommand>) {
+    for q in qubits(&com) {
         slice[q.index()] = Some(com.clone());
     }
 }
 
+/// Split the circuit into slices of parallel commands, see
+/// [`Circuit::moments`]. Only the commands passing [`is_slice_op`] are
+/// included.
 fn load_slices(circ: &Circuit<impl HugrView>) -> SliceVec {
-    let mut slices = vec![];
-
-    let n_qbs = circ.qubit_count();
-    let mut qubit_free_slice = vec![0; n_qbs];
-
-    for command in circ
-        .commands()
-        .filter(|c| is_slice_op(circ.hugr(), c.node()))
-    {
-        let command: ComCommand = command.into();
-        let free_slice = command
-            .qubits()
-            .map(|qb| qubit_free_slice[qb.index()])
-            .max()
-            .unwrap();
-
-        for q in command.qubits() {
-            qubit_free_slice[q.index()] = free_slice + 1;
-        }
-        if free_slice >= slices.len() {
-            debug_assert!(free_slice == slices.len());
-            slices.push(vec![None; n_qbs]);
-        }
-        let command = Rc::new(command);
-        add_to_slice(&mut slices[free_slice], command);
-    }
-
-    slices
+    let n_qbs = circ.linear_units().count();
+    let commands = circ
+        .commands_owned()
+        .filter(|c| is_slice_op(circ.hugr(), c.node()));
+    schedule(commands, |c| qubits(c).collect())
+        .into_iter()
+        .map(|moment| {
+            let mut slice = vec![None; n_qbs];
+            for command in moment {
+                add_to_slice(&mut slice, Rc::new(command));
+            }
+            slice
+        })
+        .collect()
 }
 
 /// check if node is one we want to put in to a slice.
+///
+/// Other than [`Tk2Op`]s, this includes the custom operations that declare
+/// their qubit commutation.
 fn is_slice_op(h: &impl HugrView, node: Node) -> bool {
     let op: Result<Tk2Op, _> = h.get_optype(node).try_into();
-    op.is_ok()
+    op.is_ok() || op_commutation(h, node).is_some_and(|comm| !comm.is_empty()) || is_swap(h, node)
+}
+
+/// check if node is a SWAP gate.
+///
+/// Commands commute through SWAPs by moving to the other qubit of the SWAP.
+fn is_swap(h: &impl HugrView, node: Node) -> bool {
+    OpaqueTk1Op::try_from_tket2(h.get_optype(node))
+        .ok()
+        .flatten()
+        .is_some_and(|op| op.serialised_op().op_type == Tk1OpType::SWAP)
 }
 
 /// Starting from starting_index, work back along slices to check for the
@@ -110,14 +84,17 @@ fn available_slice(
     circ: &Circuit,
     slice_vec: &[Slice],
     starting_index: usize,
-    command: &Rc<ComCommand>,
-) -> Option<(usize, HashMap<Qb, Rc<ComCommand>>)> {
+    command: &Rc<OwnedCommand>,
+) -> Option<(usize, NewNexts)> {
     let mut available = None;
-    let mut prev_nodes: HashMap<Qb, Rc<ComCommand>> = HashMap::new();
+    let mut prev_nodes: NewNexts = HashMap::new();
+    // the qubit each of the command's qubits is moved to by the SWAPs commuted
+    // through so far.
+    let mut labels: HashMap<Qb, Qb> = qubits(command).map(|q| (q, q)).collect();
     for slice_index in (0..=starting_index).rev() {
         // if all qubit slots are empty here the command can be moved here
-        if command
-            .qubits()
+        if labels
+            .values()
             .all(|q| slice_vec[slice_index][q.index()].is_none())
         {
             available = Some((slice_index, prev_nodes.clone()));
@@ -126,7 +103,8 @@ fn available_slice(
         } else {
             // if command commutes with all ports here it can be moved past,
             // otherwise stop
-            if let Some(new_prev_nodes) = commutes_at_slice(command, &slice_vec[slice_index], circ)
+            if let Some(new_prev_nodes) =
+                commutes_at_slice(command, &mut labels, &slice_vec[slice_index], circ)
             {
                 prev_nodes.extend(new_prev_nodes);
             } else {
@@ -140,40 +118,55 @@ fn available_slice(
 
 // If a command commutes back through this slice return a map from the qubits of
 // the command to the commands in this slice acting on those qubits.
+//
+// `labels` maps the qubits of the command to the qubits it currently acts on,
+// and is updated when commuting through a SWAP.
 fn commutes_at_slice(
-    command: &Rc<ComCommand>,
+    command: &Rc<OwnedCommand>,
+    labels: &mut HashMap<Qb, Qb>,
     slice: &Slice,
     circ: &Circuit,
-) -> Option<HashMap<Qb, Rc<ComCommand>>> {
+) -> Option<NewNexts> {
     // map from qubit to node it is connected to immediately after the free slice.
-    let mut prev_nodes: HashMap<Qb, Rc<ComCommand>> = HashMap::new();
+    let mut prev_nodes: NewNexts = HashMap::new();
+    let mut new_labels = labels.clone();
 
-    for q in command.qubits() {
+    for (&q, &label) in labels.iter() {
         // if slot is empty, continue checking.
-        let Some(other_com) = &slice[q.index()] else {
+        let Some(other_com) = &slice[label.index()] else {
             continue;
         };
+        // Frozen commands must not be modified by commuting through them.
+        if circ.is_frozen(other_com.node()) {
+            return None;
+        }
+
+        if other_com != command && is_swap(circ.hugr(), other_com.node()) {
+            // a command acting on one qubit after a SWAP acts on the other
+            // qubit before it.
+            let other_label = qubits(other_com).find(|&qb| qb != label)?;
+            new_labels.insert(q, other_label);
+            prev_nodes.insert(q, (other_label, other_com.clone()));
+            continue;
+        }
 
-        let port = command.port_of_qb(q, Direction::Incoming)?;
+        let port = command.linear_unit_port(q, Direction::Incoming)?;
 
-        let op: Tk2Op = circ.hugr().get_optype(command.node()).try_into().ok()?;
-        // TODO: if not tk2op, might still have serialized commutation data we
-        // can use.
-        let pauli = commutation_on_port(&op.qubit_commutation(), port)?;
+        let pauli = commutation_on_port(&op_commutation(circ.hugr(), command.node())?, port)?;
 
-        let other_op: Tk2Op = circ.hugr().get_optype(other_com.node()).try_into().ok()?;
         let other_pauli = commutation_on_port(
-            &other_op.qubit_commutation(),
-            other_com.port_of_qb(q, Direction::Outgoing)?,
+            &op_commutation(circ.hugr(), other_com.node())?,
+            other_com.linear_unit_port(label, Direction::Outgoing)?,
         )?;
 
         if pauli.commutes_with(other_pauli) {
-            prev_nodes.insert(q, other_com.clone());
+            prev_nodes.insert(q, (label, other_com.clone()));
         } else {
             return None;
         }
     }
 
+    *labels = new_labels;
     Some(prev_nodes)
 }
 
@@ -183,6 +176,38 @@ fn commutation_on_port(comms: &[(usize, Pauli)], port: Port) -> Option<Pauli> {
         .find_map(|(i, p)| (*i == port.index()).then_some(*p))
 }
 
+/// Summary of the changes made by a commutation pass.
+///
+/// The depth of a circuit is its number of slices, where each slice contains
+/// commands acting on disjoint qubits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CommutationReport {
+    /// The number of commands moved.
+    pub moves: u32,
+    /// The depth of the circuit before the pass.
+    pub depth_before: usize,
+    /// The depth of the circuit after the pass.
+    pub depth_after: usize,
+}
+
+/// Remove the slices left empty by a pass, by reordering the operations of
+/// the circuit's region to follow the slices. Returns the resulting depth.
+fn compact_slices(circ: &mut Circuit) -> usize {
+    let slices = load_slices(circ);
+    let mut prev = circ.output_node();
+    for node in slices
+        .iter()
+        .flatten()
+        .flatten()
+        .map(|com| com.node())
+        .unique()
+    {
+        circ.hugr_mut().move_after_sibling(node, prev);
+        prev = node;
+    }
+    slices.len()
+}
+
 /// Error from a `PullForward` operation.
 #[derive(Debug, Clone, Error, PartialEq, Eq)]
 #[allow(missing_docs)]
@@ -199,8 +224,8 @@ pub enum PullForwardError {
 }
 
 struct PullForward {
-    command: Rc<ComCommand>,
-    new_nexts: HashMap<Qb, Rc<ComCommand>>,
+    command: Rc<OwnedCommand>,
+    new_nexts: NewNexts,
 }
 
 impl Rewrite for PullForward {
@@ -217,59 +242,35 @@ impl Rewrite for PullForward {
     fn apply(self, h: &mut impl HugrMut) -> Result<Self::ApplyResult, Self::Error> {
         let Self { command, new_nexts } = self;
 
-        let qb_port = |command: &ComCommand, qb, direction| {
+        let qb_port = |command: &OwnedCommand, qb, direction| {
             command
-                .port_of_qb(qb, direction)
+                .linear_unit_port(qb, direction)
                 .ok_or(PullForwardError::NoQbInCommand(qb.index()))
         };
         // for each qubit, disconnect node and reconnect at destination.
-        for qb in command.qubits() {
+        for qb in qubits(&command) {
             let out_port = qb_port(&command, qb, Direction::Outgoing)?;
             let in_port = qb_port(&command, qb, Direction::Incoming)?;
 
-            let (src, src_port) = h
-                .linked_ports(command.node(), in_port)
-                .exactly_one()
-                .ok() // PortLinks don't implement Debug
-                .unwrap();
-            let (dst, dst_port) = h
-                .linked_ports(command.node(), out_port)
-                .exactly_one()
-                .ok()
-                .unwrap();
-
-            let Some(new_neighbour_com) = new_nexts.get(&qb) else {
+            let Some((new_qb, new_neighbour_com)) = new_nexts.get(&qb) else {
                 return Err(PullForwardError::NoCommandForQb(qb.index()));
             };
             if new_neighbour_com == &command {
                 // do not need to commute along this qubit.
                 continue;
             }
-            h.disconnect(command.node(), in_port);
-            h.disconnect(command.node(), out_port);
-            // connect old source and destination - identity operation.
-            h.connect(src, src_port.index(), dst, dst_port.index());
-
-            let new_dst_port = qb_port(new_neighbour_com, qb, Direction::Incoming)?;
-            let (new_src, new_src_port) = h
-                .linked_ports(new_neighbour_com.node(), new_dst_port)
-                .exactly_one()
-                .ok()
-                .unwrap();
-            // disconnect link which we will insert in to.
-            h.disconnect(new_neighbour_com.node(), new_dst_port);
-
-            h.connect(
-                new_src,
-                new_src_port.index(),
-                command.node(),
-                in_port.index(),
-            );
-            h.connect(
+            let new_dst_port = qb_port(new_neighbour_com, *new_qb, Direction::Incoming)?;
+            let in_port = in_port.as_incoming().unwrap();
+            let out_port = out_port.as_outgoing().unwrap();
+            let new_dst_port = new_dst_port.as_incoming().unwrap();
+            // move the node to the link entering its new neighbour.
+            splice_out(h, command.node(), in_port, out_port);
+            splice_in(
+                h,
                 command.node(),
-                out_port.index(),
-                new_neighbour_com.node(),
-                new_dst_port.index(),
+                in_port,
+                out_port,
+                (new_neighbour_com.node(), new_dst_port),
             );
         }
         Ok(())
@@ -281,26 +282,30 @@ impl Rewrite for PullForward {
         // This is done in the Rewrite trait of hugr so once that version
         // is released, it can be updated here
         let mut nodes = vec![self.command.node()];
-        let next_nodes = self.new_nexts.values().map(|c| c.node());
+        let next_nodes = self.new_nexts.values().map(|(_, c)| c.node());
         nodes.extend(next_nodes);
         nodes.into_iter()
     }
 }
 
 /// Pass which greedily commutes operations forwards in order to reduce depth.
-pub fn apply_greedy_commutation(circ: &mut Circuit) -> Result<u32, PullForwardError> {
+pub fn apply_greedy_commutation(circ: &mut Circuit) -> Result<CommutationReport, PullForwardError> {
     let mut count = 0;
     let mut slice_vec = load_slices(circ);
+    let depth_before = slice_vec.len();
 
     for slice_index in 0..slice_vec.len() {
         let slice_commands: Vec<_> = slice_vec[slice_index]
             .iter()
             .flatten()
-            .unique()
+            .unique_by(|com| com.node())
             .cloned()
             .collect();
 
         for command in slice_commands {
+            if circ.is_frozen(command.node()) {
+                continue;
+            }
             let Some((destination, new_nexts)) =
                 available_slice(circ, &slice_vec, slice_index, &command)
             else {
@@ -311,28 +316,44 @@ pub fn apply_greedy_commutation(circ: &mut Circuit) -> Result<u32, PullForwardEr
                 destination < slice_index,
                 "Avoid mutating slices we haven't got to yet."
             );
-            for q in command.qubits() {
-                let com = slice_vec[slice_index][q.index()].take();
-                slice_vec[destination][q.index()] = com;
+            // commuting through SWAPs may move the command to other qubits.
+            let new_qb = |q: Qb| new_nexts.get(&q).map_or(q, |(new_q, _)| *new_q);
+            let moved = Rc::new(command.relabel_linear_units(new_qb));
+            for q in qubits(&command) {
+                slice_vec[slice_index][q.index()] = None;
+                slice_vec[destination][new_qb(q).index()] = Some(moved.clone());
             }
             let rewrite = PullForward { command, new_nexts };
             circ.hugr_mut().apply_rewrite(rewrite)?;
             count += 1;
         }
     }
-    Ok(count)
+
+    let depth_after = match count {
+        0 => depth_before,
+        _ => compact_slices(circ),
+    };
+    Ok(CommutationReport {
+        moves: count,
+        depth_before,
+        depth_after,
+    })
 }
 
 #[cfg(test)]
 mod test {
 
+    use crate::set_op_commutation;
+    use crate::utils::test::tk1_circuit;
     use crate::{extension::REGISTRY, ops::test::t2_bell_circuit, utils::build_simple_circuit};
+    use hugr::ops::{custom::OpaqueOp, CustomOp};
     use hugr::{
         builder::{DFGBuilder, Dataflow, DataflowHugr},
         extension::prelude::{BOOL_T, QB_T},
         std_extensions::arithmetic::float_types::FLOAT64_TYPE,
         type_row,
         types::Signature,
+        CircuitUnit,
     };
     use rstest::{fixture, rstest};
 
@@ -488,7 +509,7 @@ mod test {
         .unwrap()
     }
     fn slice_from_command(
-        commands: &[ComCommand],
+        commands: &[OwnedCommand],
         n_qbs: usize,
         slice_arr: &[&[usize]],
     ) -> SliceVec {
@@ -509,7 +530,7 @@ mod test {
     #[rstest]
     fn test_load_slices_cx(example_cx: Circuit) {
         let circ = example_cx;
-        let commands: Vec<ComCommand> = circ.commands().map_into().collect();
+        let commands: Vec<OwnedCommand> = circ.commands_owned().collect();
         let slices = load_slices(&circ);
         let correct = slice_from_command(&commands, 4, &[&[0], &[1], &[2]]);
 
@@ -519,7 +540,7 @@ mod test {
     #[rstest]
     fn test_load_slices_cx_better(example_cx_better: Circuit) {
         let circ = example_cx_better;
-        let commands: Vec<ComCommand> = circ.commands().map_into().collect();
+        let commands: Vec<OwnedCommand> = circ.commands_owned().collect();
 
         let slices = load_slices(&circ);
         let correct = slice_from_command(&commands, 4, &[&[0, 1], &[2]]);
@@ -530,7 +551,7 @@ mod test {
     #[rstest]
     fn test_load_slices_bell(t2_bell_circuit: Circuit) {
         let circ = t2_bell_circuit;
-        let commands: Vec<ComCommand> = circ.commands().map_into().collect();
+        let commands: Vec<OwnedCommand> = circ.commands_owned().collect();
 
         let slices = load_slices(&circ);
         let correct = slice_from_command(&commands, 2, &[&[0], &[1]]);
@@ -547,7 +568,7 @@ mod test {
         assert_eq!(found, 0);
 
         assert_eq!(
-            *prev_nodes.get(&Qb::new(1)).unwrap(),
+            prev_nodes.get(&Qb::new(1)).unwrap().1,
             slices[1][1].as_ref().unwrap().clone()
         );
 
@@ -564,12 +585,12 @@ mod test {
             available_slice(&circ, &slices, 3, slices[4][1].as_ref().unwrap()).unwrap();
         assert_eq!(found, 1);
         assert_eq!(
-            *prev_nodes.get(&Qb::new(1)).unwrap(),
+            prev_nodes.get(&Qb::new(1)).unwrap().1,
             slices[2][1].as_ref().unwrap().clone()
         );
 
         assert_eq!(
-            *prev_nodes.get(&Qb::new(2)).unwrap(),
+            prev_nodes.get(&Qb::new(2)).unwrap().1,
             slices[2][2].as_ref().unwrap().clone()
         );
         // hadamard can't commute past anything
@@ -599,7 +620,8 @@ mod test {
     ) {
         let node_count = case.hugr().node_count();
         let depth_before = depth(&case);
-        let move_count = apply_greedy_commutation(&mut case).unwrap();
+        let report = apply_greedy_commutation(&mut case).unwrap();
+        let move_count = report.moves;
         case.hugr_mut().update_validate(&REGISTRY).unwrap();
 
         assert_eq!(
@@ -607,6 +629,8 @@ mod test {
             "Number of commutations did not match expected."
         );
         let depth_after = depth(&case);
+        assert_eq!(report.depth_before, depth_before);
+        assert_eq!(report.depth_after, depth_after);
 
         if should_reduce {
             assert!(depth_after < depth_before, "Depth should have decreased..");
@@ -623,4 +647,71 @@ mod test {
             "depth optimisation should not change the number of nodes."
         )
     }
+    #[rstest]
+    // Z(q1) after SWAP(q0, q1) is Z(q0) before it.
+    #[case::single_qubit(
+        r#"{"args": [["q", [1]], ["q", [2]]], "op": {"type": "CX"}},
+           {"args": [["q", [0]], ["q", [1]]], "op": {"type": "SWAP"}},
+           {"args": [["q", [1]]], "op": {"type": "Z"}}"#,
+        r#"{"args": [["q", [1]], ["q", [2]]], "op": {"type": "CX"}},
+           {"args": [["q", [0]]], "op": {"type": "Z"}},
+           {"args": [["q", [0]], ["q", [1]]], "op": {"type": "SWAP"}}"#
+    )]
+    // CZ(q0, q2) after SWAP(q0, q1) is CZ(q1, q2) before it.
+    #[case::two_qubit(
+        r#"{"args": [["q", [0]]], "op": {"type": "H"}},
+           {"args": [["q", [0]], ["q", [1]]], "op": {"type": "SWAP"}},
+           {"args": [["q", [0]], ["q", [2]]], "op": {"type": "CZ"}}"#,
+        r#"{"args": [["q", [0]]], "op": {"type": "H"}},
+           {"args": [["q", [1]], ["q", [2]]], "op": {"type": "CZ"}},
+           {"args": [["q", [0]], ["q", [1]]], "op": {"type": "SWAP"}}"#
+    )]
+    fn commute_through_swap(#[case] commands: &str, #[case] expected: &str) {
+        let mut circ = tk1_circuit(3, 0, commands);
+        let depth_before = depth(&circ);
+        let move_count = apply_greedy_commutation(&mut circ).unwrap().moves;
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(move_count, 1);
+        assert!(depth(&circ) < depth_before);
+        assert!(circ.equal_structure(&tk1_circuit(3, 0, expected)));
+    }
+
+    #[rstest]
+    #[case::declared(true, 1)]
+    #[case::undeclared(false, 0)]
+    fn custom_op_commutation(#[case] declared: bool, #[case] expected_moves: u32) {
+        let custom_z = CustomOp::new_opaque(OpaqueOp::new(
+            "test.ext".try_into().unwrap(),
+            "CustomZ",
+            String::new(),
+            [],
+            Signature::new_endo(type_row![QB_T]),
+        ));
+        let mut circ = build_simple_circuit(3, |circ| {
+            circ.append(Tk2Op::H, [1])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(custom_z, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        if declared {
+            let node = circ.commands().last().unwrap().node();
+            set_op_commutation(circ.hugr_mut(), node, &[(0, Pauli::Z)]);
+        }
+
+        let move_count = apply_greedy_commutation(&mut circ).unwrap().moves;
+        assert_eq!(move_count, expected_moves);
+    }
+
+    #[rstest]
+    #[case::moved_command(2)]
+    #[case::blocking_command(1)]
+    fn frozen_commands_are_skipped(mut example_cx: Circuit, #[case] frozen: usize) {
+        let node = example_cx.commands().nth(frozen).unwrap().node();
+        example_cx.freeze_nodes([node]);
+
+        let move_count = apply_greedy_commutation(&mut example_cx).unwrap().moves;
+        assert_eq!(move_count, 0);
+    }
 }
@@ -0,0 +1,354 @@
+//! Commutation of operations by list scheduling.
+//!
+//! Unlike [`apply_greedy_commutation`], which pulls each command back as far
+//! as it can one at a time, this pass assigns every command to a slice at
+//! once. Runs of consecutive commands that commute on a qubit can be executed
+//! in any order, and the commands ready to be executed are placed in each slice
+//! by order of priority.
+//!
+//! [`apply_greedy_commutation`]: super::apply_greedy_commutation
+
+use std::collections::{HashMap, HashSet};
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::{Direction, HugrView, Node, PortIndex};
+use itertools::Itertools;
+
+use super::{
+    commutation_on_port, compact_slices, load_slices, qubits, CommutationReport, OwnedCommand,
+    PullForwardError, Qb,
+};
+use crate::ops::op_commutation;
+use crate::Circuit;
+
+/// The priority used to choose between the commands ready to be placed in a
+/// slice, see [`apply_list_scheduling_commutation`].
+///
+/// Ties are broken by the original order of the commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum SchedulingPriority {
+    /// Prefer the commands with the longest chain of dependent commands.
+    #[default]
+    CriticalPath,
+    /// Prefer the commands with the most direct dependents.
+    Dependents,
+    /// Keep the original order of the commands.
+    InputOrder,
+}
+
+/// Pass which commutes operations to reduce depth, by assigning them to slices
+/// with list scheduling.
+///
+/// Consecutive commands on a qubit that commute with each other may be
+/// reordered. Slices are filled one at a time with the commands whose
+/// dependencies have all been scheduled, chosen according to the `priority`.
+///
+/// The circuit is only modified if the resulting depth is lower than the
+/// current one. Frozen commands are neither moved nor commuted through.
+///
+pub fn apply_list_scheduling_commutation(
+    circ: &mut Circuit,
+    priority: SchedulingPriority,
+) -> Result<CommutationReport, PullForwardError> {
+    let depth_before = load_slices(circ).len();
+    let unchanged = CommutationReport {
+        moves: 0,
+        depth_before,
+        depth_after: depth_before,
+    };
+    let commands: Vec<OwnedCommand> = circ
+        .commands_owned()
+        .filter(|com| qubits(com).next().is_some())
+        .collect();
+    let index: HashMap<Node, usize> = commands
+        .iter()
+        .enumerate()
+        .map(|(i, com)| (com.node(), i))
+        .collect();
+
+    // Split the sequence of commands on each qubit into runs of commuting
+    // commands.
+    let mut runs: HashMap<Qb, Vec<Vec<usize>>> = HashMap::new();
+    let mut run_basis: HashMap<Qb, _> = HashMap::new();
+    for (i, com) in commands.iter().enumerate() {
+        let comms = match circ.is_frozen(com.node()) {
+            true => None,
+            false => op_commutation(circ.hugr(), com.node()),
+        };
+        for q in qubits(com) {
+            let basis = comms.as_ref().and_then(|comms| {
+                commutation_on_port(comms, com.linear_unit_port(q, Direction::Incoming)?)
+            });
+            let qb_runs = runs.entry(q).or_default();
+            match (basis, run_basis.insert(q, basis)) {
+                (Some(basis), Some(Some(prev))) if basis == prev => {
+                    qb_runs.last_mut().unwrap().push(i)
+                }
+                _ => qb_runs.push(vec![i]),
+            }
+        }
+    }
+
+    // Each command must be executed after the previous run on each of its
+    // qubits, and after the commands producing its classical inputs.
+    let mut deps: Vec<HashSet<usize>> = vec![HashSet::new(); commands.len()];
+    for qb_runs in runs.values() {
+        for (prev, run) in qb_runs.iter().tuple_windows() {
+            for &i in run {
+                deps[i].extend(prev);
+            }
+        }
+    }
+    for (i, com) in commands.iter().enumerate() {
+        deps[i].extend(classical_deps(circ, com.node(), &index));
+    }
+
+    let priorities = command_priorities(&deps, priority);
+    let Some(slices) = list_schedule(&commands, &deps, &priorities) else {
+        return Ok(unchanged);
+    };
+    if slices.len() >= depth_before {
+        return Ok(unchanged);
+    }
+    let slice_of: HashMap<usize, usize> = slices
+        .iter()
+        .enumerate()
+        .flat_map(|(s, slice)| slice.iter().map(move |&i| (i, s)))
+        .collect();
+
+    // Reorder the commands in each run according to their slice.
+    let mut moved = HashSet::new();
+    for (&q, qb_runs) in &runs {
+        for run in qb_runs.iter().filter(|run| run.len() > 1) {
+            let new_order = run
+                .iter()
+                .copied()
+                .sorted_by_key(|i| slice_of[i])
+                .collect_vec();
+            if &new_order == run {
+                continue;
+            }
+            moved.extend(
+                run.iter()
+                    .zip(&new_order)
+                    .filter(|(a, b)| a != b)
+                    .map(|(a, _)| *a),
+            );
+            reorder_run(
+                circ.hugr_mut(),
+                q,
+                run.iter().map(|&i| &commands[i]).collect(),
+                new_order.iter().map(|&i| &commands[i]).collect(),
+            )?;
+        }
+    }
+    Ok(CommutationReport {
+        moves: moved.len() as u32,
+        depth_before,
+        depth_after: compact_slices(circ),
+    })
+}
+
+/// The scheduled commands that must be executed before `node` because they
+/// produce its classical inputs, possibly through unscheduled operations.
+fn classical_deps(circ: &Circuit, node: Node, index: &HashMap<Node, usize>) -> HashSet<usize> {
+    let hugr = circ.hugr();
+    let mut deps = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut stack = vec![node];
+    while let Some(n) = stack.pop() {
+        for port in hugr.node_inputs(n) {
+            let is_linear = hugr
+                .signature(n)
+                .and_then(|sig| sig.in_port_type(port).cloned())
+                .is_some_and(|ty| !ty.copyable());
+            if n == node && is_linear {
+                continue;
+            }
+            for (src, _) in hugr.linked_outputs(n, port) {
+                if hugr.get_parent(src) != Some(circ.parent()) || !visited.insert(src) {
+                    continue;
+                }
+                match index.get(&src) {
+                    Some(&i) => {
+                        deps.insert(i);
+                    }
+                    None => stack.push(src),
+                }
+            }
+        }
+    }
+    deps
+}
+
+/// Compute the priority of each command, higher values being scheduled first.
+fn command_priorities(deps: &[HashSet<usize>], priority: SchedulingPriority) -> Vec<usize> {
+    let n = deps.len();
+    match priority {
+        SchedulingPriority::CriticalPath => {
+            // Dependencies always come earlier in the topological order.
+            let mut path = vec![1; n];
+            for i in (0..n).rev() {
+                for &d in &deps[i] {
+                    path[d] = path[d].max(path[i] + 1);
+                }
+            }
+            path
+        }
+        SchedulingPriority::Dependents => {
+            let mut dependents = vec![0; n];
+            for &d in deps.iter().flatten() {
+                dependents[d] += 1;
+            }
+            dependents
+        }
+        SchedulingPriority::InputOrder => vec![0; n],
+    }
+}
+
+/// Assign the commands to slices, filling each slice in order of priority with
+/// the commands whose dependencies are in earlier slices.
+///
+/// Returns `None` if the dependencies are cyclic.
+fn list_schedule(
+    commands: &[OwnedCommand],
+    deps: &[HashSet<usize>],
+    priorities: &[usize],
+) -> Option<Vec<Vec<usize>>> {
+    let mut scheduled = vec![false; commands.len()];
+    let mut remaining = (0..commands.len()).collect_vec();
+    let mut slices = Vec::new();
+    while !remaining.is_empty() {
+        let ready = remaining
+            .iter()
+            .copied()
+            .filter(|&i| deps[i].iter().all(|&d| scheduled[d]))
+            .sorted_by_key(|&i| (std::cmp::Reverse(priorities[i]), i))
+            .collect_vec();
+        if ready.is_empty() {
+            return None;
+        }
+        let mut used_qubits = HashSet::new();
+        let mut slice = Vec::new();
+        for i in ready {
+            if qubits(&commands[i]).all(|q| !used_qubits.contains(&q)) {
+                used_qubits.extend(qubits(&commands[i]));
+                slice.push(i);
+            }
+        }
+        for &i in &slice {
+            scheduled[i] = true;
+        }
+        remaining.retain(|i| !scheduled[*i]);
+        slices.push(slice);
+    }
+    Some(slices)
+}
+
+/// Rewire a run of consecutive commands on qubit `q` into a new order.
+fn reorder_run(
+    h: &mut impl HugrMut,
+    q: Qb,
+    run: Vec<&OwnedCommand>,
+    new_order: Vec<&OwnedCommand>,
+) -> Result<(), PullForwardError> {
+    let qb_port = |command: &OwnedCommand, direction| {
+        command
+            .linear_unit_port(q, direction)
+            .ok_or(PullForwardError::NoQbInCommand(q.index()))
+    };
+    let first = run.first().unwrap();
+    let last = run.last().unwrap();
+    let (src, src_port) = h
+        .linked_ports(first.node(), qb_port(first, Direction::Incoming)?)
+        .exactly_one()
+        .ok() // PortLinks don't implement Debug
+        .unwrap();
+    let (dst, dst_port) = h
+        .linked_ports(last.node(), qb_port(last, Direction::Outgoing)?)
+        .exactly_one()
+        .ok()
+        .unwrap();
+
+    for com in &run {
+        h.disconnect(com.node(), qb_port(com, Direction::Incoming)?);
+        h.disconnect(com.node(), qb_port(com, Direction::Outgoing)?);
+    }
+    let (mut prev, mut prev_port) = (src, src_port.index());
+    for com in new_order {
+        let in_port = qb_port(com, Direction::Incoming)?;
+        h.connect(prev, prev_port, com.node(), in_port.index());
+        (prev, prev_port) = (com.node(), qb_port(com, Direction::Outgoing)?.index());
+    }
+    h.connect(prev, prev_port, dst, dst_port.index());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::ops::op_matches;
+    use crate::sim::unitary::equal_up_to_phase;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    /// A circuit where the greedy pass cannot reduce the depth, but starting
+    /// with the second CZ shortens the critical path.
+    fn busy_circuit() -> Circuit {
+        build_simple_circuit(3, |circ| {
+            circ.append(Tk2Op::CZ, [1, 2])?;
+            circ.append(Tk2Op::CZ, [0, 1])?;
+            for _ in 0..4 {
+                circ.append(Tk2Op::H, [0])?;
+            }
+            for _ in 0..3 {
+                circ.append(Tk2Op::H, [2])?;
+            }
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case::critical_path(SchedulingPriority::CriticalPath, 2, 5)]
+    #[case::dependents(SchedulingPriority::Dependents, 0, 6)]
+    #[case::input_order(SchedulingPriority::InputOrder, 0, 6)]
+    fn list_scheduling(
+        #[case] priority: SchedulingPriority,
+        #[case] expected_moves: u32,
+        #[case] expected_depth: usize,
+    ) {
+        let mut circ = busy_circuit();
+        let unitary = circ.unitary().unwrap();
+        assert_eq!(load_slices(&circ).len(), 6);
+
+        let report = apply_list_scheduling_commutation(&mut circ, priority).unwrap();
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.moves, expected_moves);
+        assert_eq!(report.depth_before, 6);
+        assert_eq!(report.depth_after, expected_depth);
+        assert_eq!(load_slices(&circ).len(), expected_depth);
+        assert!(equal_up_to_phase(&circ.unitary().unwrap(), &unitary, 1e-10));
+    }
+
+    #[test]
+    fn frozen_commands_are_not_reordered() {
+        let mut circ = busy_circuit();
+        let cz = circ
+            .commands()
+            .find(|cmd| op_matches(cmd.optype(), Tk2Op::CZ))
+            .unwrap()
+            .node();
+        circ.freeze_nodes([cz]);
+
+        let report =
+            apply_list_scheduling_commutation(&mut circ, SchedulingPriority::CriticalPath).unwrap();
+        assert_eq!(report.moves, 0);
+        assert_eq!(report.depth_after, report.depth_before);
+    }
+}
@@ -0,0 +1,332 @@
+//! Decomposition of multi-controlled gates into the Clifford+T gate set.
+//!
+//! [`decompose_controlled_gates`] replaces the [`Tk2Op::CCX`] and
+//! [`Tk2Op::CCZ`] gates, and the [`ControlledOp`]s with any number of
+//! controls, by circuits of `H`, `S`, `Sdg`, `T`, `Tdg`, `X`, `Y`, `Z` and
+//! `CX` gates. The decompositions are exact, without any global phase.
+//!
+//! Gates with more than two controls are computed with a chain of Toffoli
+//! gates on clean ancilla qubits. The ancillas are allocated with
+//! [`Tk2Op::QAlloc`] and released with [`Tk2Op::QFree`] once they have been
+//! uncomputed.
+
+use hugr::builder::{BuildError, CircuitBuilder, DFGBuilder};
+use hugr::ops::OpType;
+use hugr::{CircuitUnit, Hugr};
+use itertools::Itertools;
+
+use super::rebase::rule;
+use crate::extension::controlled::ControlledOp;
+use crate::rewrite::Subcircuit;
+use crate::{Circuit, Pauli, Tk2Op};
+
+/// Replace every multi-controlled gate in a circuit by its Clifford+T
+/// decomposition.
+///
+/// Frozen gates are left untouched. See [`controlled_decomposition`] for the
+/// replacement circuits.
+///
+/// Returns the number of gates decomposed.
+pub fn decompose_controlled_gates(circ: &mut Circuit) -> usize {
+    decompose_matching(circ, as_controlled_op)
+}
+
+/// Decompose only the [`ControlledOp`]s in a circuit, leaving the `CCX` and
+/// `CCZ` gates untouched.
+pub(super) fn decompose_controlled_ops(circ: &mut Circuit) -> usize {
+    decompose_matching(circ, ControlledOp::from_optype)
+}
+
+/// Decompose the gates recognised by `matcher`.
+fn decompose_matching(
+    circ: &mut Circuit,
+    matcher: impl Fn(&OpType) -> Option<ControlledOp>,
+) -> usize {
+    let targets = circ
+        .commands()
+        .filter(|cmd| !circ.is_frozen(cmd.node()))
+        .filter_map(|cmd| Some((cmd.node(), matcher(cmd.optype())?)))
+        .collect_vec();
+    for &(node, op) in &targets {
+        let subcircuit = Subcircuit::try_from_nodes([node], circ).unwrap();
+        subcircuit
+            .create_rewrite(circ, controlled_decomposition(op))
+            .expect("Decompositions have the signature of their gate.")
+            .apply(circ)
+            .unwrap();
+    }
+    targets.len()
+}
+
+/// The Clifford+T decomposition of a multi-controlled Pauli gate.
+///
+/// The circuit acts on the control qubits followed by the target, and
+/// allocates `controls - 2` ancilla qubits if the gate has more than two
+/// controls. Its unitary is exactly the one of the gate.
+pub fn controlled_decomposition(op: ControlledOp) -> Circuit {
+    rule(op.num_qubits(), |c, []| {
+        let controls = (0..op.controls()).collect_vec();
+        append_controlled(c, &controls, op.controls(), op.target())
+    })
+}
+
+/// Interpret an operation as a multi-controlled Pauli gate.
+pub(crate) fn as_controlled_op(op: &OpType) -> Option<ControlledOp> {
+    match Tk2Op::try_from(op) {
+        Ok(Tk2Op::CCX) => Some(ControlledOp::new(2, Pauli::X)),
+        Ok(Tk2Op::CCZ) => Some(ControlledOp::new(2, Pauli::Z)),
+        Ok(_) => None,
+        Err(_) => ControlledOp::from_optype(op),
+    }
+}
+
+/// Append a Pauli gate on `target`, controlled by the `controls` qubits.
+fn append_controlled(
+    c: &mut CircuitBuilder<DFGBuilder<Hugr>>,
+    controls: &[usize],
+    target: usize,
+    pauli: Pauli,
+) -> Result<(), BuildError> {
+    use Tk2Op::*;
+    match (pauli, controls) {
+        (Pauli::I, _) => {}
+        (Pauli::X, []) => {
+            c.append(X, [target])?;
+        }
+        (Pauli::Y, []) => {
+            c.append(Y, [target])?;
+        }
+        (Pauli::Z, []) => {
+            c.append(Z, [target])?;
+        }
+        (Pauli::X, &[a]) => {
+            c.append(CX, [a, target])?;
+        }
+        // `Y = S X Sdg` and `Z = H X H`.
+        (Pauli::Y, &[a]) => {
+            c.append(Sdg, [target])?;
+            c.append(CX, [a, target])?;
+            c.append(S, [target])?;
+        }
+        (Pauli::Z, &[a]) => {
+            c.append(H, [target])?;
+            c.append(CX, [a, target])?;
+            c.append(H, [target])?;
+        }
+        // `X = H Z H` and `Y = S H Z H Sdg`.
+        (Pauli::X, _) => {
+            c.append(H, [target])?;
+            append_multi_cz(c, controls, target)?;
+            c.append(H, [target])?;
+        }
+        (Pauli::Y, _) => {
+            c.append(Sdg, [target])?;
+            c.append(H, [target])?;
+            append_multi_cz(c, controls, target)?;
+            c.append(H, [target])?;
+            c.append(S, [target])?;
+        }
+        (Pauli::Z, _) => append_multi_cz(c, controls, target)?,
+    }
+    Ok(())
+}
+
+/// Append a `Z` gate controlled by two or more qubits.
+///
+/// With more than two controls, the conjunction of the controls is computed
+/// into ancilla qubits by a chain of Toffoli gates, and uncomputed afterwards.
+fn append_multi_cz(
+    c: &mut CircuitBuilder<DFGBuilder<Hugr>>,
+    controls: &[usize],
+    target: usize,
+) -> Result<(), BuildError> {
+    let k = controls.len();
+    assert!(k >= 2, "A multi-controlled Z needs at least two controls.");
+    if k == 2 {
+        return append_ccz(c, [controls[0], controls[1], target]);
+    }
+
+    let ancillas = (0..k - 2)
+        .map(|_| {
+            let empty: [CircuitUnit; 0] = [];
+            let ancilla = c.append_with_outputs(Tk2Op::QAlloc, empty)?[0];
+            Ok(c.track_wire(ancilla))
+        })
+        .collect::<Result<Vec<_>, BuildError>>()?;
+    // The ancilla `i` holds the conjunction of the first `i + 2` controls.
+    let toffolis = [[controls[0], controls[1], ancillas[0]]]
+        .into_iter()
+        .chain((1..k - 2).map(|i| [controls[i + 1], ancillas[i - 1], ancillas[i]]))
+        .collect_vec();
+
+    for &qubits in &toffolis {
+        append_toffoli(c, qubits)?;
+    }
+    append_ccz(c, [controls[k - 1], ancillas[k - 3], target])?;
+    for &qubits in toffolis.iter().rev() {
+        append_toffoli(c, qubits)?;
+    }
+    for ancilla in ancillas {
+        let wire = c.untrack_wire(ancilla)?;
+        c.append_and_consume(Tk2Op::QFree, [wire])?;
+    }
+    Ok(())
+}
+
+/// Append a Toffoli gate, with the target as the last qubit.
+fn append_toffoli(
+    c: &mut CircuitBuilder<DFGBuilder<Hugr>>,
+    qubits: [usize; 3],
+) -> Result<(), BuildError> {
+    c.append(Tk2Op::H, [qubits[2]])?;
+    append_ccz(c, qubits)?;
+    c.append(Tk2Op::H, [qubits[2]])?;
+    Ok(())
+}
+
+/// Append the 7 T-gate decomposition of a CCZ gate.
+fn append_ccz(
+    c: &mut CircuitBuilder<DFGBuilder<Hugr>>,
+    [a, b, t]: [usize; 3],
+) -> Result<(), BuildError> {
+    use Tk2Op::*;
+    c.append(CX, [b, t])?;
+    c.append(Tdg, [t])?;
+    c.append(CX, [a, t])?;
+    c.append(T, [t])?;
+    c.append(CX, [b, t])?;
+    c.append(Tdg, [t])?;
+    c.append(CX, [a, t])?;
+    c.append(T, [b])?;
+    c.append(T, [t])?;
+    c.append(CX, [a, b])?;
+    c.append(T, [a])?;
+    c.append(Tdg, [b])?;
+    c.append(CX, [a, b])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{Dataflow, DataflowHugr, FunctionBuilder};
+    use hugr::extension::prelude::{BOOL_T, QB_T};
+    use hugr::types::Signature;
+    use ndarray::Array2;
+    use num_complex::Complex64;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::sim::{gate_matrix, sample, Counts};
+    use crate::utils::build_simple_circuit;
+
+    /// The unitary of a controlled Pauli gate, with the target as the most
+    /// significant qubit.
+    fn controlled_unitary(op: ControlledOp) -> Array2<Complex64> {
+        let pauli = match op.target() {
+            Pauli::I => return Array2::eye(1 << op.num_qubits()),
+            Pauli::X => Tk2Op::X,
+            Pauli::Y => Tk2Op::Y,
+            Pauli::Z => Tk2Op::Z,
+        };
+        let pauli = Array2::from_shape_vec((2, 2), gate_matrix(pauli, &[]).unwrap()).unwrap();
+        let n = op.controls();
+        let all_controls = (1 << n) - 1;
+        let mut unitary = Array2::eye(1 << (n + 1));
+        for t in 0..2 {
+            for out in 0..2 {
+                unitary[[(out << n) | all_controls, (t << n) | all_controls]] = pauli[[out, t]];
+            }
+        }
+        unitary
+    }
+
+    #[rstest]
+    #[case(0, Pauli::X)]
+    #[case(1, Pauli::X)]
+    #[case(1, Pauli::Y)]
+    #[case(1, Pauli::Z)]
+    #[case(2, Pauli::X)]
+    #[case(2, Pauli::Y)]
+    #[case(2, Pauli::Z)]
+    #[case(2, Pauli::I)]
+    fn exact_decompositions(#[case] controls: usize, #[case] target: Pauli) {
+        let op = ControlledOp::new(controls, target);
+        let circ = controlled_decomposition(op);
+
+        let unitary = circ.unitary().unwrap();
+        let expected = controlled_unitary(op);
+        assert!((&unitary - &expected).iter().all(|x| x.norm() < 1e-9));
+    }
+
+    #[test]
+    fn decompose_toffoli_gates() {
+        let mut circ = build_simple_circuit(3, |c| {
+            c.append(Tk2Op::CCX, [2, 0, 1])?;
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::CCZ, [0, 1, 2])?;
+            Ok(())
+        })
+        .unwrap();
+        let unitary = circ.unitary().unwrap();
+
+        assert_eq!(decompose_controlled_gates(&mut circ), 2);
+        assert_eq!(decompose_controlled_gates(&mut circ), 0);
+        let t_count = circ
+            .commands()
+            .filter(|cmd| matches!(Tk2Op::try_from(cmd.optype()), Ok(Tk2Op::T | Tk2Op::Tdg)))
+            .count();
+        assert_eq!(t_count, 14);
+        assert!((&circ.unitary().unwrap() - &unitary)
+            .iter()
+            .all(|x| x.norm() < 1e-9));
+    }
+
+    /// Flip the `inputs` qubits, apply an `X` gate controlled by all the
+    /// qubits but the last, and measure every qubit.
+    #[rstest]
+    #[case::all_controls(3, &[0, 1, 2], &[true, true, true, true])]
+    #[case::missing_control(3, &[0, 2], &[true, false, true, false])]
+    #[case::four_controls(4, &[0, 1, 2, 3, 4], &[true, true, true, true, false])]
+    fn ancilla_chain(#[case] controls: usize, #[case] inputs: &[usize], #[case] expected: &[bool]) {
+        let num_qubits = controls + 1;
+        let mut circ: Circuit = {
+            let signature = Signature::new(vec![QB_T; num_qubits], vec![BOOL_T; num_qubits]);
+            let mut h = FunctionBuilder::new("main", signature).unwrap();
+            let mut qubits = h.input_wires().collect_vec();
+            for &i in inputs {
+                qubits[i] = h
+                    .add_dataflow_op(Tk2Op::X, [qubits[i]])
+                    .unwrap()
+                    .out_wire(0);
+            }
+            let op = ControlledOp::new(controls, Pauli::X);
+            let qubits = h
+                .add_dataflow_op(op, qubits)
+                .unwrap()
+                .outputs()
+                .collect_vec();
+            let mut bits = Vec::new();
+            for q in qubits {
+                let [q, b] = h
+                    .add_dataflow_op(Tk2Op::Measure, [q])
+                    .unwrap()
+                    .outputs_arr();
+                h.add_dataflow_op(Tk2Op::QFree, [q]).unwrap();
+                bits.push(b);
+            }
+            h.finish_hugr_with_outputs(bits, &REGISTRY).unwrap().into()
+        };
+
+        assert_eq!(decompose_controlled_gates(&mut circ), 1);
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+        let allocs = circ
+            .commands()
+            .filter(|cmd| matches!(Tk2Op::try_from(cmd.optype()), Ok(Tk2Op::QAlloc)))
+            .count();
+        assert_eq!(allocs, controls - 2);
+
+        let counts = sample(&circ, 1, 0).unwrap();
+        assert_eq!(counts, Counts::from([(expected.to_vec(), 1)]));
+    }
+}
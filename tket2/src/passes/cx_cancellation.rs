@@ -0,0 +1,302 @@
+//! Cheap template-based cancellation of CX gates.
+//!
+//! [`cx_cancellation`] removes pairs of CX gates that cancel out, possibly
+//! after commuting one of them through the gates that separate them, and
+//! replaces chains of three alternating CX gates with a SWAP. Unlike the
+//! optimisers based on equivalence classes of circuits, it only needs a few
+//! traversals of the circuit and is suitable as a quick cleanup pass.
+
+use std::collections::HashSet;
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::{HugrView, IncomingPort, Node, OutgoingPort, PortIndex};
+use itertools::Itertools;
+use tket_json_rs::circuit_json;
+use tket_json_rs::optype::OpType as Tk1OpType;
+
+use crate::ops::{op_commutation, op_matches};
+use crate::serialize::pytket::OpaqueTk1Op;
+use crate::{Circuit, Pauli, Tk2Op};
+
+/// Configuration for [`cx_cancellation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CxCancellationConfig {
+    /// Cancel CX pairs separated by gates that commute with them: gates acting
+    /// on the control qubit in the Z basis, such as Rz, and gates acting on the
+    /// target qubit in the X basis, such as Rx.
+    ///
+    /// Defaults to `true`.
+    pub commute: bool,
+    /// Replace three consecutive CX gates with alternating directions by a
+    /// SWAP gate.
+    ///
+    /// Defaults to `true`.
+    pub recognise_swaps: bool,
+}
+
+impl Default for CxCancellationConfig {
+    fn default() -> Self {
+        Self {
+            commute: true,
+            recognise_swaps: true,
+        }
+    }
+}
+
+/// The changes made by [`cx_cancellation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CxCancellationReport {
+    /// The number of pairs of CX gates removed.
+    pub cancelled_pairs: usize,
+    /// The number of CX chains replaced by a SWAP gate.
+    pub swaps: usize,
+}
+
+/// Cancel redundant CX gates in a circuit.
+///
+/// Repeatedly removes pairs of CX gates with the same control and target
+/// qubits that are adjacent, or only separated by gates commuting with them
+/// if [`CxCancellationConfig::commute`] is set. Then, if
+/// [`CxCancellationConfig::recognise_swaps`] is set, replaces each sequence
+/// `CX(a, b) CX(b, a) CX(a, b)` by a pytket SWAP gate.
+///
+/// Frozen gates are neither removed nor commuted through.
+pub fn cx_cancellation(circ: &mut Circuit, config: CxCancellationConfig) -> CxCancellationReport {
+    let mut report = CxCancellationReport::default();
+    loop {
+        let cancelled = cancel_cx_pairs(circ, config.commute);
+        if cancelled == 0 {
+            break;
+        }
+        report.cancelled_pairs += cancelled;
+    }
+    if config.recognise_swaps {
+        report.swaps = replace_cx_swaps(circ);
+    }
+    report
+}
+
+/// A single pass over the CX gates of the circuit, removing the pairs that
+/// cancel out. Returns the number of pairs removed.
+fn cancel_cx_pairs(circ: &mut Circuit, commute: bool) -> usize {
+    let mut removed = HashSet::new();
+    for cx in cx_nodes(circ) {
+        if removed.contains(&cx) {
+            continue;
+        }
+        let Some(other) = find_cancelling_cx(circ, cx, commute) else {
+            continue;
+        };
+        remove_cx(circ, cx);
+        remove_cx(circ, other);
+        removed.extend([cx, other]);
+    }
+    removed.len() / 2
+}
+
+/// Find the next CX gate on the same control and target qubits that cancels
+/// with `cx`, if any.
+fn find_cancelling_cx(circ: &Circuit, cx: Node, commute: bool) -> Option<Node> {
+    if circ.is_frozen(cx) {
+        return None;
+    }
+    // The CX gates reachable on the target qubit, whose target is that qubit.
+    let targets: HashSet<Node> = wire_successors(circ, cx, 1, commute.then_some(Pauli::X))
+        .filter(|&(n, port)| port.index() == 1 && is_cx(circ, n))
+        .map(|(n, _)| n)
+        .collect();
+    wire_successors(circ, cx, 0, commute.then_some(Pauli::Z))
+        .find(|&(n, port)| port.index() == 0 && targets.contains(&n))
+        .map(|(n, _)| n)
+        .filter(|&n| !circ.is_frozen(n))
+}
+
+/// Iterate over the gates following `node` on the qubit wire at port `offset`.
+///
+/// The iteration continues past the gates acting on the wire in the `basis`,
+/// if any, and stops after the first gate that does not.
+pub(super) fn wire_successors(
+    circ: &Circuit,
+    node: Node,
+    offset: usize,
+    basis: Option<Pauli>,
+) -> impl Iterator<Item = (Node, IncomingPort)> + '_ {
+    let hugr = circ.hugr();
+    let mut next = hugr.single_linked_input(node, OutgoingPort::from(offset));
+    std::iter::from_fn(move || {
+        let (n, port) = next?;
+        let commutes = basis.is_some_and(|basis| {
+            !circ.is_frozen(n)
+                && op_commutation(hugr, n)
+                    .is_some_and(|comms| comms.contains(&(port.index(), basis)))
+        });
+        next = match commutes {
+            true => hugr.single_linked_input(n, OutgoingPort::from(port.index())),
+            false => None,
+        };
+        Some((n, port))
+    })
+}
+
+/// Replace the sequences `CX(a, b) CX(b, a) CX(a, b)` by SWAP gates. Returns
+/// the number of replaced sequences.
+fn replace_cx_swaps(circ: &mut Circuit) -> usize {
+    let mut removed = HashSet::new();
+    for cx in cx_nodes(circ) {
+        if removed.contains(&cx) {
+            continue;
+        }
+        let Some([first, second, third]) = cx_swap_chain(circ, cx) else {
+            continue;
+        };
+        let hugr = circ.hugr_mut();
+        let inputs = [0, 1].map(|i| hugr.single_linked_output(first, i).unwrap());
+        let outputs = [0, 1].map(|i| hugr.single_linked_input(third, i).unwrap());
+        let swap = hugr.add_node_after(first, swap_op());
+        for node in [first, second, third] {
+            hugr.remove_node(node);
+        }
+        for (i, ((src, src_port), (dst, dst_port))) in inputs.into_iter().zip(outputs).enumerate() {
+            hugr.connect(src, src_port, swap, i);
+            hugr.connect(swap, i, dst, dst_port);
+        }
+        removed.extend([first, second, third]);
+    }
+    removed.len() / 3
+}
+
+/// Check whether `cx` starts a sequence `CX(a, b) CX(b, a) CX(a, b)` of
+/// directly connected gates.
+fn cx_swap_chain(circ: &Circuit, cx: Node) -> Option<[Node; 3]> {
+    let hugr = circ.hugr();
+    // Returns the gate that follows `node` on both of its qubits, with the
+    // qubits swapped.
+    let flipped_successor = |node: Node| {
+        let (next, port) = hugr.single_linked_input(node, 0)?;
+        (port.index() == 1
+            && is_cx(circ, next)
+            && hugr.single_linked_input(node, 1) == Some((next, 0.into())))
+        .then_some(next)
+    };
+    let second = flipped_successor(cx)?;
+    let third = flipped_successor(second)?;
+    let chain = [cx, second, third];
+    chain.iter().all(|&n| !circ.is_frozen(n)).then_some(chain)
+}
+
+/// The CX gates of the circuit, in topological order.
+fn cx_nodes(circ: &Circuit) -> Vec<Node> {
+    circ.commands()
+        .filter(|cmd| op_matches(cmd.optype(), Tk2Op::CX))
+        .map(|cmd| cmd.node())
+        .collect_vec()
+}
+
+fn is_cx(circ: &Circuit, node: Node) -> bool {
+    op_matches(circ.hugr().get_optype(node), Tk2Op::CX)
+}
+
+/// Remove a CX gate, connecting its qubit inputs directly to its outputs.
+fn remove_cx(circ: &mut Circuit, cx: Node) {
+    let hugr = circ.hugr_mut();
+    let links = [0, 1].map(|i| {
+        (
+            hugr.single_linked_output(cx, i).unwrap(),
+            hugr.single_linked_input(cx, i).unwrap(),
+        )
+    });
+    hugr.remove_node(cx);
+    for ((src, src_port), (dst, dst_port)) in links {
+        hugr.connect(src, src_port, dst, dst_port);
+    }
+}
+
+/// A pytket SWAP gate.
+fn swap_op() -> hugr::ops::OpType {
+    let op = circuit_json::Operation::from_optype(Tk1OpType::SWAP);
+    OpaqueTk1Op::new_from_op(op, 2, 0).as_custom_op().into()
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::sim::unitary::equal_up_to_phase;
+    use crate::utils::test::{circuit_from_gates, gates};
+
+    #[rstest]
+    #[case::adjacent(vec![(Tk2Op::CX, vec![0, 1]), (Tk2Op::CX, vec![0, 1])], true, 1, 0)]
+    #[case::commuting(
+        vec![(Tk2Op::CX, vec![0, 1]), (Tk2Op::T, vec![0]), (Tk2Op::X, vec![1]), (Tk2Op::CX, vec![0, 1])],
+        true,
+        1,
+        2
+    )]
+    #[case::no_commute(
+        vec![(Tk2Op::CX, vec![0, 1]), (Tk2Op::T, vec![0]), (Tk2Op::CX, vec![0, 1])],
+        false,
+        0,
+        3
+    )]
+    #[case::blocked(
+        vec![(Tk2Op::CX, vec![0, 1]), (Tk2Op::H, vec![0]), (Tk2Op::CX, vec![0, 1])],
+        true,
+        0,
+        3
+    )]
+    #[case::wrong_basis(
+        vec![(Tk2Op::CX, vec![0, 1]), (Tk2Op::Z, vec![1]), (Tk2Op::CX, vec![0, 1])],
+        true,
+        0,
+        3
+    )]
+    #[case::reversed(vec![(Tk2Op::CX, vec![0, 1]), (Tk2Op::CX, vec![1, 0])], true, 0, 2)]
+    #[case::nested(
+        vec![(Tk2Op::CX, vec![0, 1]), (Tk2Op::CX, vec![0, 1]), (Tk2Op::CX, vec![0, 1]), (Tk2Op::CX, vec![0, 1])],
+        true,
+        2,
+        0
+    )]
+    fn cancellation(
+        #[case] ops: Vec<(Tk2Op, Vec<usize>)>,
+        #[case] commute: bool,
+        #[case] cancelled_pairs: usize,
+        #[case] remaining_gates: usize,
+    ) {
+        let mut circ = circuit_from_gates(2, &ops);
+        let unitary = circ.unitary().unwrap();
+        let config = CxCancellationConfig {
+            commute,
+            recognise_swaps: false,
+        };
+
+        let report = cx_cancellation(&mut circ, config);
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.cancelled_pairs, cancelled_pairs);
+        assert_eq!(circ.num_operations(), remaining_gates);
+        assert!(equal_up_to_phase(&circ.unitary().unwrap(), &unitary, 1e-10));
+    }
+
+    #[test]
+    fn swap_recognition() {
+        let mut circ = circuit_from_gates(
+            2,
+            &[
+                (Tk2Op::H, vec![0]),
+                (Tk2Op::CX, vec![0, 1]),
+                (Tk2Op::CX, vec![1, 0]),
+                (Tk2Op::CX, vec![0, 1]),
+            ],
+        );
+
+        let report = cx_cancellation(&mut circ, CxCancellationConfig::default());
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.swaps, 1);
+        assert_eq!(gates(&circ), [Tk2Op::H]);
+        assert_eq!(circ.num_operations(), 2);
+    }
+}
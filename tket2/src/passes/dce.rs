@@ -0,0 +1,293 @@
+//! Dead-code elimination of discarded qubits and unused classical values.
+//!
+//! Circuits imported from generic HUGRs often carry operations with no
+//! observable effect: gates applied to qubits that are discarded right after,
+//! ancillas allocated and freed without being measured, and classical
+//! computations whose results are never read. [`eliminate_dead_code`] removes
+//! them.
+
+use std::collections::VecDeque;
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::ops::OpType;
+use hugr::{HugrView, Node};
+use itertools::Itertools;
+
+use crate::ops::op_matches;
+use crate::{Circuit, Tk2Op};
+
+/// The changes made by [`eliminate_dead_code`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DceReport {
+    /// The number of quantum operations removed, net of the QFree operations
+    /// added.
+    pub quantum_ops: usize,
+    /// The number of classical operations and constants removed.
+    pub classical_ops: usize,
+}
+
+/// Remove the operations of a circuit whose outputs are discarded.
+///
+/// Two kinds of operations are removed, until none remain:
+/// - Measurement-free quantum operations whose qubit outputs are all freed.
+///   The operation is removed along with the `QFree` operations, and its input
+///   qubits are freed instead. A qubit allocated and freed without being
+///   measured is removed entirely. Only unitary gates, resets and allocations
+///   are removed this way.
+/// - Operations with classical inputs and outputs, and constants, whose
+///   outputs are not used. Operations without outputs are kept, as they are
+///   assumed to have side effects, such as recording a result.
+///
+/// Only the operations in the top-level region of the circuit are considered.
+/// Frozen operations and operations with order edges are never removed.
+pub fn eliminate_dead_code(circ: &mut Circuit) -> DceReport {
+    DceReport {
+        quantum_ops: eliminate_discarded_qubits(circ),
+        classical_ops: eliminate_unused_values(circ),
+    }
+}
+
+/// Remove the quantum operations whose qubit outputs are all freed. Returns
+/// the number of operations removed, net of the QFree operations added.
+fn eliminate_discarded_qubits(circ: &mut Circuit) -> usize {
+    let parent = circ.parent();
+    let mut removed = 0;
+    let mut frees: VecDeque<Node> = circ
+        .hugr()
+        .children(parent)
+        .filter(|&n| is_removable_free(circ, n))
+        .collect();
+    while let Some(free) = frees.pop_front() {
+        if !circ.hugr().valid_node(free) {
+            continue;
+        }
+        let Some((node, _)) = circ.hugr().single_linked_output(free, 0) else {
+            continue;
+        };
+        let Some(node_frees) = discarded_outputs(circ, node) else {
+            continue;
+        };
+        let qubit_inputs = linear_inputs(circ, node)
+            .map(|port| circ.hugr().single_linked_output(node, port).unwrap())
+            .collect_vec();
+
+        let hugr = circ.hugr_mut();
+        removed += 1 + node_frees.len() - qubit_inputs.len();
+        for n in node_frees {
+            hugr.remove_node(n);
+        }
+        for (src, src_port) in qubit_inputs {
+            let new_free = hugr.add_node_after(node, Tk2Op::QFree);
+            hugr.connect(src, src_port, new_free, 0);
+            frees.push_back(new_free);
+        }
+        hugr.remove_node(node);
+    }
+    removed
+}
+
+/// Remove the classical operations and constants whose outputs are not used.
+/// Returns the number of nodes removed.
+fn eliminate_unused_values(circ: &mut Circuit) -> usize {
+    let parent = circ.parent();
+    let mut removed = 0;
+    let mut candidates: VecDeque<Node> = circ.hugr().children(parent).collect();
+    while let Some(node) = candidates.pop_front() {
+        if !circ.hugr().valid_node(node) || !is_unused_value(circ, node) {
+            continue;
+        }
+        let sources = circ
+            .hugr()
+            .all_linked_outputs(node)
+            .map(|(src, _)| src)
+            .unique()
+            .collect_vec();
+        circ.hugr_mut().remove_node(node);
+        candidates.extend(sources);
+        removed += 1;
+    }
+    removed
+}
+
+/// Check whether a node is a QFree operation that can be removed.
+fn is_removable_free(circ: &Circuit, node: Node) -> bool {
+    op_matches(circ.hugr().get_optype(node), Tk2Op::QFree) && !circ.is_frozen(node)
+}
+
+/// If `node` is a removable quantum operation whose qubit outputs are all
+/// freed, returns the QFree operations.
+fn discarded_outputs(circ: &Circuit, node: Node) -> Option<Vec<Node>> {
+    let hugr = circ.hugr();
+    let op = Tk2Op::try_from(hugr.get_optype(node)).ok()?;
+    if !(op.is_quantum() || matches!(op, Tk2Op::Reset | Tk2Op::QAlloc)) || circ.is_frozen(node) {
+        return None;
+    }
+    // All the linked outputs must be qubits going to QFree operations, which
+    // excludes order edges.
+    let targets = hugr.all_linked_inputs(node).map(|(n, _)| n).collect_vec();
+    let n_qubits = hugr.signature(node)?.output_count();
+    (targets.len() == n_qubits && targets.iter().all(|&n| is_removable_free(circ, n)))
+        .then_some(targets)
+}
+
+/// The input ports of a node with a linear type.
+fn linear_inputs(circ: &Circuit, node: Node) -> impl Iterator<Item = usize> {
+    let sig = circ.hugr().signature(node);
+    let types = sig.map_or_else(Vec::new, |sig| sig.input_types().to_vec());
+    types
+        .into_iter()
+        .enumerate()
+        .filter(|(_, ty)| !ty.copyable())
+        .map(|(port, _)| port)
+}
+
+/// Check whether a node computes classical values that are not used.
+fn is_unused_value(circ: &Circuit, node: Node) -> bool {
+    let hugr = circ.hugr();
+    let op = hugr.get_optype(node);
+    let has_outputs = match op {
+        OpType::Const(_) => true,
+        OpType::LoadConstant(_)
+        | OpType::LoadFunction(_)
+        | OpType::CustomOp(_)
+        | OpType::Noop(_)
+        | OpType::MakeTuple(_)
+        | OpType::UnpackTuple(_)
+        | OpType::Tag(_)
+        | OpType::Lift(_) => op.value_output_count() > 0,
+        _ => false,
+    };
+    let classical = hugr.signature(node).map_or(true, |sig| {
+        sig.input_types()
+            .iter()
+            .chain(sig.output_types())
+            .all(|ty| ty.copyable())
+    });
+    has_outputs
+        && classical
+        && !circ.is_frozen(node)
+        && hugr.all_linked_inputs(node).next().is_none()
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::extension::prelude::{BOOL_T, QB_T};
+    use hugr::std_extensions::arithmetic::float_types::{self, ConstF64, FLOAT64_TYPE};
+    use hugr::types::Signature;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::utils::build_simple_circuit;
+
+    #[test]
+    fn discarded_ancilla() {
+        let mut circ = build_simple_circuit(1, |circ| {
+            let [ancilla] = circ.append_with_outputs_arr(Tk2Op::QAlloc, [] as [usize; 0])?;
+            let [ancilla] =
+                circ.append_with_outputs_arr(Tk2Op::H, [hugr::CircuitUnit::Wire(ancilla)])?;
+            circ.append_and_consume(Tk2Op::QFree, [ancilla])?;
+            circ.append(Tk2Op::X, [0])?;
+            Ok(())
+        })
+        .unwrap();
+
+        let report = eliminate_dead_code(&mut circ);
+        assert_eq!(report.quantum_ops, 3);
+        assert_eq!(circ.num_operations(), 1);
+    }
+
+    /// A qubit entangled with a discarded one, and optionally measured.
+    #[rstest]
+    #[case::unmeasured(false, 1, 2)]
+    #[case::measured(true, 0, 4)]
+    fn discarded_qubit(
+        #[case] measure: bool,
+        #[case] quantum_ops: usize,
+        #[case] n_operations: usize,
+    ) {
+        let mut h = DFGBuilder::new(Signature::new(vec![QB_T, QB_T], vec![QB_T])).unwrap();
+        let [q0, q1] = h.input_wires_arr();
+        let [q0, q1] = h
+            .add_dataflow_op(Tk2Op::CX, [q0, q1])
+            .unwrap()
+            .outputs_arr();
+        let [q1] = h.add_dataflow_op(Tk2Op::H, [q1]).unwrap().outputs_arr();
+        let q1 = match measure {
+            true => h.add_dataflow_op(Tk2Op::Measure, [q1]).unwrap().out_wire(0),
+            false => q1,
+        };
+        h.add_dataflow_op(Tk2Op::QFree, [q1]).unwrap();
+        let mut circ: Circuit = h.finish_hugr_with_outputs([q0], &REGISTRY).unwrap().into();
+
+        let report = eliminate_dead_code(&mut circ);
+        assert_eq!(report.quantum_ops, quantum_ops);
+        assert_eq!(circ.num_operations(), n_operations);
+        if !measure {
+            // The CX is kept, with a QFree on its second output.
+            let ops = circ
+                .commands()
+                .map(|cmd| Tk2Op::try_from(cmd.optype()).unwrap())
+                .collect_vec();
+            assert_eq!(ops, [Tk2Op::CX, Tk2Op::QFree]);
+        }
+    }
+
+    #[test]
+    fn unused_classical_values() {
+        let mut h = DFGBuilder::new(
+            Signature::new(vec![QB_T, FLOAT64_TYPE], vec![QB_T, BOOL_T])
+                .with_extension_delta(float_types::EXTENSION_ID),
+        )
+        .unwrap();
+        let [q, angle] = h.input_wires_arr();
+        // An unused sum of angles.
+        let half = h.add_load_value(ConstF64::new(0.5));
+        h.add_dataflow_op(Tk2Op::AngleAdd, [angle, half]).unwrap();
+        // A used angle.
+        let quarter = h.add_load_value(ConstF64::new(0.25));
+        let [q] = h
+            .add_dataflow_op(Tk2Op::RzF64, [q, quarter])
+            .unwrap()
+            .outputs_arr();
+        let [q, bit] = h
+            .add_dataflow_op(Tk2Op::Measure, [q])
+            .unwrap()
+            .outputs_arr();
+        let mut circ: Circuit = h
+            .finish_hugr_with_outputs([q, bit], &REGISTRY)
+            .unwrap()
+            .into();
+        let n_nodes = circ.hugr().node_count();
+
+        let report = eliminate_dead_code(&mut circ);
+        // The AngleAdd, and the constant and its load.
+        assert_eq!(report.classical_ops, 3);
+        assert_eq!(report.quantum_ops, 0);
+        assert_eq!(circ.hugr().node_count(), n_nodes - 3);
+        circ.hugr().validate(&REGISTRY).unwrap();
+    }
+
+    #[test]
+    fn frozen_gates() {
+        let mut circ = build_simple_circuit(1, |circ| {
+            let [ancilla] = circ.append_with_outputs_arr(Tk2Op::QAlloc, [] as [usize; 0])?;
+            let [ancilla] =
+                circ.append_with_outputs_arr(Tk2Op::H, [hugr::CircuitUnit::Wire(ancilla)])?;
+            circ.append_and_consume(Tk2Op::QFree, [ancilla])?;
+            Ok(())
+        })
+        .unwrap();
+        let h = circ
+            .commands()
+            .find(|cmd| op_matches(cmd.optype(), Tk2Op::H))
+            .unwrap()
+            .node();
+        circ.freeze_nodes([h]);
+
+        let report = eliminate_dead_code(&mut circ);
+        assert_eq!(report, DceReport::default());
+        assert_eq!(circ.num_operations(), 3);
+    }
+}
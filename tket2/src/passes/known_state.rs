@@ -0,0 +1,434 @@
+//! Simplification of gates acting on qubits in known basis states.
+//!
+//! [`simplify_known_states`] tracks the qubits known to be in a computational
+//! basis state, such as freshly allocated or reset qubits, through the
+//! circuit. Gates whose effect is determined by these states are removed or
+//! replaced by cheaper ones, and the outcomes of measurements of such qubits
+//! are replaced by constants. This shrinks state-preparation heavy circuits,
+//! where many gates act on ancillas before they are entangled.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use hugr::extension::prelude::{BOOL_T, QB_T};
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::ops::{Const, LoadConstant, Value};
+use hugr::{HugrView, IncomingPort, Node, OutgoingPort, Wire};
+use itertools::Itertools;
+use num_complex::Complex64;
+
+use crate::circuit::phase::GlobalPhase;
+use crate::sim::gate_matrix;
+use crate::utils::TOLERANCE;
+use crate::utils::{constant_params, remove_unused_param};
+use crate::{Circuit, Tk2Op};
+
+/// Configuration for [`simplify_known_states`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KnownStateConfig {
+    /// Assume that the qubit inputs of the circuit are in the |0⟩ state.
+    ///
+    /// Otherwise, only the qubits allocated or reset in the circuit are in a
+    /// known state. Defaults to `false`.
+    pub zero_inputs: bool,
+}
+
+/// The changes made by [`simplify_known_states`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KnownStateReport {
+    /// The number of gates and resets removed, as they act trivially on the
+    /// states of their qubits.
+    pub removed_gates: usize,
+    /// The number of multi-qubit gates replaced by single-qubit gates.
+    pub replaced_gates: usize,
+    /// The number of measurements replaced by their known outcome.
+    pub measurements: usize,
+}
+
+/// Simplify the gates acting on qubits in known computational basis states.
+///
+/// Qubits are known to be in the |0⟩ state after a `QAlloc` or a `Reset`, and
+/// at the circuit inputs if [`KnownStateConfig::zero_inputs`] is set. Their
+/// states are propagated through the gates mapping basis states to basis
+/// states, such as X gates and CX gates with known controls, and lost after
+/// any other operation. Then:
+/// - Gates acting as the identity up to a global phase on the known states are
+///   removed, such as Z rotations, or CX gates with a control in |0⟩. Resets
+///   of qubits in |0⟩ are removed too.
+/// - Multi-qubit gates acting as single-qubit X or Z gates given the known
+///   states are replaced by them, such as CX gates with a control in |1⟩.
+/// - Measurements of qubits in known states are removed, and their outcome is
+///   replaced by a constant.
+///
+/// Only [`Tk2Op`] gates with constant angles are simplified, and frozen
+/// operations are kept, although the states are propagated through them. The
+/// global phase of the removed gates is added to the circuit's
+/// [global phase](Circuit::global_phase).
+pub fn simplify_known_states(circ: &mut Circuit, config: KnownStateConfig) -> KnownStateReport {
+    let mut report = KnownStateReport::default();
+    // The known states of the qubit wires.
+    let mut states: HashMap<Wire, bool> = HashMap::new();
+    if config.zero_inputs {
+        let input = circ.input_node();
+        let types = circ.circuit_signature().input_types().to_vec();
+        for (port, ty) in types.iter().enumerate() {
+            if *ty == QB_T {
+                states.insert(Wire::new(input, port), false);
+            }
+        }
+    }
+
+    let nodes = circ.commands().map(|cmd| cmd.node()).collect_vec();
+    for node in nodes {
+        let Ok(op) = Tk2Op::try_from(circ.hugr().get_optype(node)) else {
+            continue;
+        };
+        let frozen = circ.is_frozen(node);
+        let inputs = qubit_input_states(circ, node, &states);
+        match op {
+            Tk2Op::QAlloc => {
+                states.insert(Wire::new(node, 0), false);
+            }
+            Tk2Op::Reset if inputs == [Some(false)] && !frozen => {
+                replace_gate(circ, node, &[None]);
+                report.removed_gates += 1;
+            }
+            Tk2Op::Reset => {
+                states.insert(Wire::new(node, 0), false);
+            }
+            Tk2Op::Measure => match inputs[..] {
+                [Some(outcome)] if !frozen => {
+                    replace_measurement(circ, node, outcome);
+                    report.measurements += 1;
+                }
+                _ => (),
+            },
+            _ => {
+                let Some(action) = analyse_gate(circ, node, op, &inputs) else {
+                    continue;
+                };
+                let replacement = action.replacement.filter(|(gates, _)| {
+                    // Single-qubit gates are only removed, as replacing them
+                    // does not simplify the circuit.
+                    !frozen && (inputs.len() > 1 || gates.iter().all(Option::is_none))
+                });
+                let Some((gates, phase)) = replacement else {
+                    for (port, state) in action.outputs.iter().enumerate() {
+                        if let Some(state) = state {
+                            states.insert(Wire::new(node, port), *state);
+                        }
+                    }
+                    continue;
+                };
+                let removed = gates.iter().all(Option::is_none);
+                let new_gates = replace_gate(circ, node, &gates);
+                circ.add_global_phase(Some(GlobalPhase::new(phase / PI)));
+                // The wires passing through keep their known state, and the
+                // new X gates flip it.
+                for (gate, state) in new_gates.into_iter().zip(&action.outputs) {
+                    if let (Some(gate), Some(state)) = (gate, state) {
+                        states.insert(Wire::new(gate, 0), *state);
+                    }
+                }
+                match removed {
+                    true => report.removed_gates += 1,
+                    false => report.replaced_gates += 1,
+                }
+            }
+        }
+    }
+    report
+}
+
+/// The known states of the qubit inputs of a node, in port order.
+fn qubit_input_states(
+    circ: &Circuit,
+    node: Node,
+    states: &HashMap<Wire, bool>,
+) -> Vec<Option<bool>> {
+    let hugr = circ.hugr();
+    let Some(sig) = hugr.signature(node) else {
+        return Vec::new();
+    };
+    sig.input_types()
+        .iter()
+        .enumerate()
+        .filter(|(_, ty)| **ty == QB_T)
+        .map(|(port, _)| {
+            let (src, src_port) = hugr.single_linked_output(node, port)?;
+            states.get(&Wire::new(src, src_port)).copied()
+        })
+        .collect()
+}
+
+/// The effect of a gate given the known states of its qubits.
+#[derive(Debug, Clone, PartialEq)]
+struct GateAction {
+    /// The known output state of each qubit.
+    outputs: Vec<Option<bool>>,
+    /// If the gate acts as single-qubit X and Z gates given the known states,
+    /// the gate on each qubit, or `None` for the qubits left unchanged, and
+    /// the global phase in radians.
+    replacement: Option<(Vec<Option<Tk2Op>>, f64)>,
+}
+
+/// Compute the effect of a gate with constant angles given the known states of
+/// its qubits.
+///
+/// Returns `None` if the gate does not map the known states to known states
+/// independently of the unknown ones, in which case all its outputs are
+/// unknown.
+fn analyse_gate(
+    circ: &Circuit,
+    node: Node,
+    op: Tk2Op,
+    inputs: &[Option<bool>],
+) -> Option<GateAction> {
+    if inputs.iter().all(Option::is_none) {
+        return None;
+    }
+    let params = constant_params(circ.hugr(), node)?;
+    let matrix = gate_matrix(op, &params)?;
+    let n = inputs.len();
+    let dim = 1 << n;
+    if matrix.len() != dim * dim {
+        return None;
+    }
+    // Multi-qubit indices take the first qubit as the most significant bit.
+    let bit = |index: usize, q: usize, n: usize| (index >> (n - 1 - q)) & 1 == 1;
+    let unknown = (0..n).filter(|&q| inputs[q].is_none()).collect_vec();
+    let m = unknown.len();
+
+    // The matrix restricted to the known input states, acting on the unknown
+    // qubits, and the output states of the known qubits.
+    let sub_dim = 1 << m;
+    let mut sub_matrix = vec![Complex64::new(0., 0.); sub_dim * sub_dim];
+    let mut known_outputs: Option<Vec<bool>> = None;
+    for col in 0..sub_dim {
+        let mut unknown_bits = (0..m).map(|j| bit(col, j, m));
+        let index = (0..n).fold(0, |index, q| {
+            let b = inputs[q].unwrap_or_else(|| unknown_bits.next().unwrap());
+            (index << 1) | usize::from(b)
+        });
+        for row in 0..dim {
+            let entry = matrix[row * dim + index];
+            if entry.norm() < TOLERANCE {
+                continue;
+            }
+            let row_bits = (0..n).map(|q| bit(row, q, n)).collect_vec();
+            let outputs = known_outputs.get_or_insert_with(|| row_bits.clone());
+            if (0..n).any(|q| inputs[q].is_some() && outputs[q] != row_bits[q]) {
+                return None;
+            }
+            let sub_row = unknown
+                .iter()
+                .fold(0, |acc, &q| (acc << 1) | usize::from(row_bits[q]));
+            sub_matrix[sub_row * sub_dim + col] = entry;
+        }
+    }
+    let known_outputs = known_outputs?;
+    let outputs = (0..n)
+        .map(|q| inputs[q].map(|_| known_outputs[q]))
+        .collect_vec();
+
+    // The gate applied to the qubits in unknown states, if it is a Pauli X or
+    // Z gate on a single qubit or the identity.
+    let zero = Complex64::new(0., 0.);
+    let one = Complex64::new(1., 0.);
+    let unknown_gate = match proportional_phase(&sub_matrix, &identity(sub_dim)) {
+        Some(phase) => Some((None, phase)),
+        None if m == 1 => [
+            (Tk2Op::X, [zero, one, one, zero]),
+            (Tk2Op::Z, [one, zero, zero, -one]),
+        ]
+        .into_iter()
+        .find_map(|(op, pauli)| {
+            proportional_phase(&sub_matrix, &pauli).map(|phase| (Some((unknown[0], op)), phase))
+        }),
+        None => None,
+    };
+    let replacement = unknown_gate.map(|(unknown_gate, phase)| {
+        let gates = (0..n)
+            .map(|q| match (inputs[q], outputs[q]) {
+                (Some(before), Some(after)) => (before != after).then_some(Tk2Op::X),
+                _ => unknown_gate.and_then(|(u, op)| (u == q).then_some(op)),
+            })
+            .collect_vec();
+        (gates, phase)
+    });
+    Some(GateAction {
+        outputs,
+        replacement,
+    })
+}
+
+/// The identity matrix of dimension `dim`, in row-major order.
+fn identity(dim: usize) -> Vec<Complex64> {
+    (0..dim * dim)
+        .map(|k| match k % (dim + 1) {
+            0 => Complex64::new(1., 0.),
+            _ => Complex64::new(0., 0.),
+        })
+        .collect()
+}
+
+/// If `matrix` is `target` up to a global phase, returns the phase in
+/// radians.
+fn proportional_phase(matrix: &[Complex64], target: &[Complex64]) -> Option<f64> {
+    let (reference, &t) = target.iter().find_position(|t| t.norm() > TOLERANCE)?;
+    let phase = matrix[reference] / t;
+    let proportional = (phase.norm() - 1.).abs() < TOLERANCE
+        && matrix
+            .iter()
+            .zip(target)
+            .all(|(&m, &t)| (m - phase * t).norm() < TOLERANCE);
+    proportional.then(|| phase.arg())
+}
+
+/// Replace a gate by single-qubit gates on each of its qubits, or by a plain
+/// wire for the qubits mapped to `None`. Returns the new gates.
+fn replace_gate(circ: &mut Circuit, node: Node, gates: &[Option<Tk2Op>]) -> Vec<Option<Node>> {
+    let hugr = circ.hugr_mut();
+    let links = (0..gates.len())
+        .map(|q| {
+            (
+                hugr.single_linked_output(node, IncomingPort::from(q))
+                    .unwrap(),
+                hugr.single_linked_input(node, OutgoingPort::from(q))
+                    .unwrap(),
+            )
+        })
+        .collect_vec();
+    let param_sources = hugr
+        .input_neighbours(node)
+        .unique()
+        .filter(|&src| !links.iter().any(|&((n, _), _)| n == src))
+        .collect_vec();
+    let new_gates = gates
+        .iter()
+        .zip(links)
+        .map(|(gate, ((src, src_port), (dst, dst_port)))| match gate {
+            Some(op) => {
+                let new = hugr.add_node_after(node, *op);
+                hugr.connect(src, src_port, new, 0);
+                hugr.connect(new, 0, dst, dst_port);
+                Some(new)
+            }
+            None => {
+                hugr.connect(src, src_port, dst, dst_port);
+                None
+            }
+        })
+        .collect_vec();
+    hugr.remove_node(node);
+    for src in param_sources {
+        remove_unused_param(hugr, src);
+    }
+    new_gates
+}
+
+/// Remove a measurement of a qubit in a known state, replacing its outcome
+/// with a constant.
+fn replace_measurement(circ: &mut Circuit, node: Node, outcome: bool) {
+    let parent = circ.parent();
+    let bit_targets = circ.hugr().linked_inputs(node, 1).collect_vec();
+    replace_gate(circ, node, &[None]);
+    if bit_targets.is_empty() {
+        return;
+    }
+    let hugr = circ.hugr_mut();
+    let constant = hugr.add_node_with_parent(parent, Const::new(Value::from_bool(outcome)));
+    let load = hugr.add_node_with_parent(parent, LoadConstant { datatype: BOOL_T });
+    hugr.connect(constant, 0, load, 0);
+    for (dst, dst_port) in bit_targets {
+        hugr.connect(load, 0, dst, dst_port);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::types::Signature;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::utils::build_simple_circuit;
+    use crate::utils::test::gates;
+
+    /// A circuit preparing and measuring an ancilla, interacting with a
+    /// qubit in an unknown state.
+    fn ancilla_circuit() -> Circuit {
+        let mut h = DFGBuilder::new(Signature::new(vec![QB_T], vec![QB_T, BOOL_T])).unwrap();
+        let [q] = h.input_wires_arr();
+        let [a] = h.add_dataflow_op(Tk2Op::QAlloc, []).unwrap().outputs_arr();
+        let [a, q] = h.add_dataflow_op(Tk2Op::CX, [a, q]).unwrap().outputs_arr();
+        let [a] = h.add_dataflow_op(Tk2Op::X, [a]).unwrap().outputs_arr();
+        let [a, q] = h.add_dataflow_op(Tk2Op::CX, [a, q]).unwrap().outputs_arr();
+        let [a] = h.add_dataflow_op(Tk2Op::Z, [a]).unwrap().outputs_arr();
+        let [a, bit] = h
+            .add_dataflow_op(Tk2Op::Measure, [a])
+            .unwrap()
+            .outputs_arr();
+        h.add_dataflow_op(Tk2Op::QFree, [a]).unwrap();
+        h.finish_hugr_with_outputs([q, bit], &REGISTRY)
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn ancilla() {
+        let mut circ = ancilla_circuit();
+
+        let report = simplify_known_states(&mut circ, KnownStateConfig::default());
+        assert_eq!(
+            report,
+            KnownStateReport {
+                removed_gates: 2,
+                replaced_gates: 1,
+                measurements: 1,
+            }
+        );
+        let ops = gates(&circ).into_iter().counts();
+        assert_eq!(ops.len(), 3);
+        assert_eq!(ops[&Tk2Op::X], 2);
+        // Z|1⟩ = -|1⟩
+        assert_eq!(circ.global_phase(), Some(GlobalPhase::new(1.)));
+        circ.hugr().validate(&REGISTRY).unwrap();
+
+        // The measurement outcome is a constant.
+        let [_, bit] = circ.hugr().get_io(circ.parent()).unwrap();
+        let (load, _) = circ.hugr().single_linked_output(bit, 1).unwrap();
+        let (constant, _) = circ.hugr().single_linked_output(load, 0).unwrap();
+        let value = circ.hugr().get_optype(constant).as_const().unwrap().value();
+        assert_eq!(value, &Value::true_val());
+    }
+
+    #[rstest]
+    #[case::unknown_inputs(false, vec![Tk2Op::CX, Tk2Op::H, Tk2Op::CX, Tk2Op::Reset])]
+    #[case::zero_inputs(true, vec![Tk2Op::H, Tk2Op::CX, Tk2Op::Reset])]
+    fn input_states(#[case] zero_inputs: bool, #[case] expected: Vec<Tk2Op>) {
+        let mut circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::Reset, [1])?;
+            circ.append(Tk2Op::Reset, [1])?;
+            Ok(())
+        })
+        .unwrap();
+
+        simplify_known_states(&mut circ, KnownStateConfig { zero_inputs });
+        assert_eq!(gates(&circ), expected);
+    }
+
+    #[test]
+    fn frozen_gates() {
+        let mut circ = ancilla_circuit();
+        let nodes = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        circ.freeze_nodes(nodes);
+
+        let report = simplify_known_states(&mut circ, KnownStateConfig::default());
+        assert_eq!(report, KnownStateReport::default());
+    }
+}
@@ -0,0 +1,366 @@
+//! Lowering of circuits to device-specific pulse-level operations.
+//!
+//! Hardware backends usually execute a gate as a sequence of device-specific
+//! instructions, such as frame changes and drive pulses, whose calibration
+//! depends on the qubits the gate acts on. A [`LoweringTable`] records these
+//! sequences as replacement circuits built from custom operations, and
+//! [`lower_to_pulses`] applies them to every quantum operation of a circuit
+//! placed on an [`Architecture`].
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use hugr::hugr::views::sibling_subgraph::InvalidReplacement;
+use hugr::ops::{NamedOp, OpType};
+use hugr::Node;
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::circuit::units::Qubit;
+use crate::rewrite::architecture::Architecture;
+use crate::rewrite::Subcircuit;
+use crate::{Circuit, Tk2Op};
+
+/// The pulse-level lowerings of the operations supported by a device.
+///
+/// Each operation can have a default lowering, used on any qubits, and
+/// calibrated lowerings for specific qubits, which take precedence over the
+/// default one. A lowering is a circuit with the same signature as the
+/// operation: its inputs are the qubits followed by the angle parameters, and
+/// it usually contains operations from a device-specific extension.
+///
+/// Operations that the backend executes directly can be kept in the lowered
+/// circuit with [`LoweringTable::keep`].
+#[derive(Debug, Clone, Default)]
+pub struct LoweringTable {
+    lowerings: BTreeMap<Tk2Op, Circuit>,
+    calibrations: BTreeMap<(Tk2Op, Vec<Qubit>), Circuit>,
+    kept: BTreeSet<Tk2Op>,
+}
+
+impl LoweringTable {
+    /// Create an empty lowering table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the default lowering of an operation, replacing any previous one.
+    pub fn with_lowering(mut self, op: Tk2Op, lowering: Circuit) -> Self {
+        self.lowerings.insert(op, lowering);
+        self
+    }
+
+    /// Add the lowering of an operation acting on specific qubits, replacing
+    /// any previous one.
+    ///
+    /// The qubits are given in the order of the operation's inputs, so a CX
+    /// calibrated for `[0, 1]` is not calibrated for `[1, 0]`.
+    pub fn with_calibration(
+        mut self,
+        op: Tk2Op,
+        qubits: impl IntoIterator<Item = impl Into<Qubit>>,
+        lowering: Circuit,
+    ) -> Self {
+        let qubits = qubits.into_iter().map(Into::into).collect();
+        self.calibrations.insert((op, qubits), lowering);
+        self
+    }
+
+    /// Keep an operation unchanged in the lowered circuit.
+    pub fn keep(mut self, op: Tk2Op) -> Self {
+        self.kept.insert(op);
+        self
+    }
+
+    /// Returns `true` if the operation is kept unchanged.
+    pub fn is_kept(&self, op: Tk2Op) -> bool {
+        self.kept.contains(&op)
+    }
+
+    /// The lowering of an operation acting on the given qubits, if any.
+    pub fn lowering(&self, op: Tk2Op, qubits: &[Qubit]) -> Option<&Circuit> {
+        self.calibrations
+            .get(&(op, qubits.to_vec()))
+            .or_else(|| self.lowerings.get(&op))
+    }
+}
+
+/// Errors that can occur when lowering a circuit to pulse-level operations.
+#[derive(Debug, Clone, Error)]
+#[non_exhaustive]
+pub enum LoweringError {
+    /// An operation acts on qubits that do not respect the architecture.
+    #[error("Operation {} in {node} acts on qubits [{}], which are not supported by the architecture.", optype.name(), qubits.iter().join(", "))]
+    UnsupportedQubits {
+        /// The operation.
+        optype: OpType,
+        /// The node.
+        node: Node,
+        /// The qubits.
+        qubits: Vec<Qubit>,
+    },
+    /// An operation has no lowering for the qubits it acts on, and is not
+    /// kept.
+    #[error("Operation {} in {node} has no lowering on qubits [{}].", optype.name(), qubits.iter().join(", "))]
+    MissingLowering {
+        /// The operation.
+        optype: OpType,
+        /// The node.
+        node: Node,
+        /// The qubits.
+        qubits: Vec<Qubit>,
+    },
+    /// A lowering does not match the signature of its operation.
+    #[error("Invalid lowering of {}: {source}", op.exposed_name())]
+    InvalidLowering {
+        /// The lowered operation.
+        op: Tk2Op,
+        /// The replacement error.
+        source: InvalidReplacement,
+    },
+}
+
+/// Lower the quantum operations of a circuit to pulse-level operations.
+///
+/// Every TKET2 operation acting on qubits is replaced by its lowering in the
+/// table for the qubits it acts on, unless it is kept. Qubits are identified by
+/// their index in the circuit inputs, as in the [`Architecture`]. Frozen
+/// operations are lowered too, since the backend could not execute them
+/// otherwise. Other operations, such as angle arithmetic and qubit
+/// allocations, are copied unchanged.
+///
+/// Returns a new circuit, leaving the input unchanged.
+///
+/// # Errors
+///
+/// Returns an error if an operation does not respect the architecture, has no
+/// lowering, or if its lowering does not match its signature.
+pub fn lower_to_pulses(
+    circ: &Circuit,
+    arch: &Architecture,
+    table: &LoweringTable,
+) -> Result<Circuit, LoweringError> {
+    let mut targets = Vec::new();
+    for cmd in circ.commands() {
+        let Ok(op) = Tk2Op::try_from(cmd.optype()) else {
+            continue;
+        };
+        let qubits = cmd
+            .input_qubits()
+            .map(|(q, _, _)| Qubit::from(q))
+            .collect_vec();
+        if qubits.is_empty() || table.is_kept(op) {
+            continue;
+        }
+        let node = cmd.node();
+        if qubits.iter().any(|q| q.index() >= arch.num_qubits())
+            || !arch.supports_op(cmd.optype(), &qubits)
+        {
+            return Err(LoweringError::UnsupportedQubits {
+                optype: cmd.optype().clone(),
+                node,
+                qubits,
+            });
+        }
+        let Some(lowering) = table.lowering(op, &qubits) else {
+            return Err(LoweringError::MissingLowering {
+                optype: cmd.optype().clone(),
+                node,
+                qubits,
+            });
+        };
+        targets.push((node, op, lowering));
+    }
+
+    let mut lowered = circ.clone();
+    for (node, op, lowering) in targets {
+        let subcircuit = Subcircuit::try_from_nodes([node], &lowered).unwrap();
+        let rewrite = subcircuit
+            .create_rewrite(&lowered, lowering.clone())
+            .map_err(|source| LoweringError::InvalidLowering { op, source })?;
+        rewrite.apply(&mut lowered).unwrap();
+    }
+    Ok(lowered)
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{BuildError, DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::extension::prelude::{PRELUDE, QB_T};
+    use hugr::extension::{ExtensionId, ExtensionRegistry, ExtensionSet};
+    use hugr::ops::custom::ExtensionOp;
+    use hugr::std_extensions::arithmetic::float_types::{
+        self, EXTENSION as FLOAT_EXTENSION, FLOAT64_TYPE,
+    };
+    use hugr::types::Signature;
+    use hugr::{Extension, HugrView};
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::TKET2_EXTENSION;
+    use crate::utils::test::tk1_circuit;
+
+    const DEVICE_EXT: ExtensionId = ExtensionId::new_unchecked("test.device");
+
+    fn device_extension() -> Extension {
+        let mut ext = Extension::new(DEVICE_EXT);
+        ext.add_op(
+            "frame_change".into(),
+            "Shift the frame of a qubit".to_owned(),
+            Signature::new(vec![QB_T, FLOAT64_TYPE], vec![QB_T]),
+        )
+        .unwrap();
+        ext.add_op(
+            "drive".into(),
+            "Drive a qubit with a calibrated pulse".to_owned(),
+            Signature::new_endo(vec![QB_T]),
+        )
+        .unwrap();
+        ext.add_op(
+            "cross_resonance".into(),
+            "Drive a qubit at the frequency of another".to_owned(),
+            Signature::new_endo(vec![QB_T, QB_T]),
+        )
+        .unwrap();
+        ext
+    }
+
+    fn registry() -> ExtensionRegistry {
+        ExtensionRegistry::try_new([
+            PRELUDE.clone(),
+            FLOAT_EXTENSION.clone(),
+            TKET2_EXTENSION.clone(),
+            device_extension(),
+        ])
+        .unwrap()
+    }
+
+    fn device_op(name: &str) -> ExtensionOp {
+        let def = device_extension().get_op(name).unwrap().clone();
+        ExtensionOp::new(def, [], &registry()).unwrap()
+    }
+
+    /// A lowering with `num_qubits` qubits and `num_params` angle inputs,
+    /// applying the device operations in order. `frame_change` consumes the
+    /// next angle input.
+    fn lowering(num_qubits: usize, num_params: usize, ops: &[(&str, &[usize])]) -> Circuit {
+        let build = || {
+            let qubits = vec![QB_T; num_qubits];
+            let inputs = [qubits.clone(), vec![FLOAT64_TYPE; num_params]].concat();
+            let delta = ExtensionSet::from_iter([float_types::EXTENSION_ID, DEVICE_EXT]);
+            let mut h =
+                DFGBuilder::new(Signature::new(inputs, qubits).with_extension_delta(delta))?;
+            let mut wires = h.input_wires().collect_vec();
+            let mut params = wires.split_off(num_qubits).into_iter();
+            for &(name, qs) in ops {
+                let mut ins = qs.iter().map(|&q| wires[q]).collect_vec();
+                if name == "frame_change" {
+                    ins.push(params.next().unwrap());
+                }
+                let outs = h.add_dataflow_op(device_op(name), ins)?.outputs();
+                for (&q, w) in qs.iter().zip(outs) {
+                    wires[q] = w;
+                }
+            }
+            h.finish_hugr_with_outputs(wires, &registry())
+        };
+        let hugr: Result<_, BuildError> = build();
+        hugr.unwrap().into()
+    }
+
+    fn table() -> LoweringTable {
+        LoweringTable::new()
+            .with_lowering(Tk2Op::RzF64, lowering(1, 1, &[("frame_change", &[0])]))
+            .with_lowering(
+                Tk2Op::H,
+                lowering(1, 0, &[("drive", &[0]), ("drive", &[0])]),
+            )
+            .with_lowering(
+                Tk2Op::CX,
+                lowering(2, 0, &[("drive", &[1]), ("cross_resonance", &[0, 1])]),
+            )
+            .with_calibration(
+                Tk2Op::H,
+                [1],
+                lowering(1, 0, &[("drive", &[0]), ("drive", &[0]), ("drive", &[0])]),
+            )
+            .keep(Tk2Op::Measure)
+    }
+    fn op_names(circ: &Circuit) -> Vec<String> {
+        circ.commands()
+            .map(|cmd| cmd.optype().name().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn lower_circuit() {
+        let circ = tk1_circuit(
+            3,
+            1,
+            r#"{"args": [["q", [0]]], "op": {"type": "H"}},
+               {"args": [["q", [1]]], "op": {"type": "H"}},
+               {"args": [["q", [0]], ["q", [1]]], "op": {"type": "CX"}},
+               {"args": [["q", [2]]], "op": {"type": "Rz", "params": ["a"]}},
+               {"args": [["q", [1]], ["c", [0]]], "op": {"type": "Measure"}}"#,
+        );
+        let before = circ.clone();
+
+        let mut lowered = lower_to_pulses(&circ, &Architecture::line(3), &table()).unwrap();
+        lowered.hugr_mut().update_validate(&registry()).unwrap();
+
+        assert_eq!(circ.hugr().node_count(), before.hugr().node_count());
+        let names = op_names(&lowered);
+        let count = |name: &str| names.iter().filter(|n| n.ends_with(name)).count();
+        assert_eq!(count("drive"), 2 + 3 + 1);
+        assert_eq!(count("cross_resonance"), 1);
+        assert_eq!(count("frame_change"), 1);
+        assert_eq!(count("Measure"), 1);
+        assert!(lowered
+            .commands()
+            .filter_map(|cmd| Tk2Op::try_from(cmd.optype()).ok())
+            .all(|op| !op.is_quantum()));
+    }
+
+    #[rstest]
+    #[case::reversed_cx(r#"{"args": [["q", [1]], ["q", [0]]], "op": {"type": "CX"}}"#)]
+    #[case::distant_cx(r#"{"args": [["q", [0]], ["q", [2]]], "op": {"type": "CX"}}"#)]
+    fn unsupported_qubits(#[case] commands: &str) {
+        let circ = tk1_circuit(3, 1, commands);
+        assert!(matches!(
+            lower_to_pulses(&circ, &Architecture::line(3), &table()),
+            Err(LoweringError::UnsupportedQubits { .. })
+        ));
+    }
+
+    #[test]
+    fn missing_lowering() {
+        let circ = tk1_circuit(3, 1, r#"{"args": [["q", [2]]], "op": {"type": "X"}}"#);
+        let Err(LoweringError::MissingLowering { qubits, .. }) =
+            lower_to_pulses(&circ, &Architecture::line(3), &table())
+        else {
+            panic!("Expected a missing lowering error");
+        };
+        assert_eq!(qubits, [Qubit::new(2)]);
+    }
+
+    #[test]
+    fn invalid_lowering() {
+        let circ = tk1_circuit(3, 1, r#"{"args": [["q", [0]]], "op": {"type": "H"}}"#);
+        let table = LoweringTable::new().with_lowering(Tk2Op::H, lowering(2, 0, &[]));
+        assert!(matches!(
+            lower_to_pulses(&circ, &Architecture::line(3), &table),
+            Err(LoweringError::InvalidLowering { op: Tk2Op::H, .. })
+        ));
+    }
+
+    #[test]
+    fn calibrations_take_precedence() {
+        let table = table();
+        let [q0, q1] = [0, 1].map(Qubit::new);
+        let default = table.lowering(Tk2Op::H, &[q0]).unwrap();
+        let calibrated = table.lowering(Tk2Op::H, &[q1]).unwrap();
+        assert_eq!(default.num_operations(), 2);
+        assert_eq!(calibrated.num_operations(), 3);
+        assert!(table.lowering(Tk2Op::X, &[q0]).is_none());
+        assert!(table.is_kept(Tk2Op::Measure));
+    }
+}
@@ -0,0 +1,374 @@
+//! Noise-adaptive remapping of qubits between circuit segments.
+//!
+//! A [`Tk2Op::Reset`] discards the state of a qubit, splitting its wire into
+//! independent segments. The segment following a reset does not need to run
+//! on the same physical qubit as the one before it: any physical qubit that is
+//! idle at that point will do.
+//!
+//! [`plan_qubit_remap`] assigns each segment to a physical qubit of a device,
+//! described by a [`NoiseProfile`]. Segments starting at the circuit inputs
+//! stay on their initial qubit, while the segments starting at a reset are
+//! moved onto the idle physical qubit with the lowest error rate, spreading
+//! the segments between equally good qubits to even out their wear.
+//!
+//! [`remap_qubit_segments`] additionally records the chosen physical qubit on
+//! each reset node, under the [`METADATA_PHYSICAL_QUBIT`] key, for use by the
+//! routing and lowering stages of the compilation.
+
+use std::collections::HashMap;
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::{HugrView, Node};
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::circuit::units::Qubit;
+use crate::{Circuit, Tk2Op};
+
+/// Metadata key for the physical qubit assigned to the segment starting at a
+/// reset operation.
+pub const METADATA_PHYSICAL_QUBIT: &str = "TKET2.physical_qubit";
+
+/// The error rates of the physical qubits of a device.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NoiseProfile {
+    /// The error rate of each physical qubit.
+    error_rates: Vec<f64>,
+    /// The penalty added to the error rate of a qubit for each segment it
+    /// already hosts.
+    wear_penalty: f64,
+}
+
+impl NoiseProfile {
+    /// Create a new profile from the error rate of each physical qubit.
+    ///
+    /// Qubits with an infinite error rate are only used when no other qubit
+    /// is available.
+    pub fn new(error_rates: impl IntoIterator<Item = f64>) -> Self {
+        Self {
+            error_rates: error_rates.into_iter().collect(),
+            wear_penalty: 0.,
+        }
+    }
+
+    /// Penalise qubits that already host many segments.
+    ///
+    /// The penalty is added to the error rate of a qubit for each segment
+    /// assigned to it. Defaults to `0`, in which case the usage of the qubits
+    /// is only used to break ties between equal error rates.
+    pub fn with_wear_penalty(mut self, wear_penalty: f64) -> Self {
+        self.wear_penalty = wear_penalty;
+        self
+    }
+
+    /// The number of physical qubits in the device.
+    pub fn qubit_count(&self) -> usize {
+        self.error_rates.len()
+    }
+
+    /// The error rate of a physical qubit.
+    pub fn error_rate(&self, qubit: Qubit) -> Option<f64> {
+        self.error_rates.get(qubit.index()).copied()
+    }
+}
+
+/// A segment of a qubit wire, between two resets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QubitSegment {
+    /// The qubit of the circuit the segment belongs to.
+    pub qubit: Qubit,
+    /// The reset operation starting the segment, or `None` for the segment
+    /// starting at the circuit inputs.
+    pub reset: Option<Node>,
+    /// The physical qubit assigned to the segment.
+    pub physical: Qubit,
+}
+
+/// An assignment of the segments of a circuit to physical qubits.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct QubitRemap {
+    /// The segments of the circuit, ordered by their start in the circuit.
+    segments: Vec<QubitSegment>,
+}
+
+impl QubitRemap {
+    /// The segments of the circuit, ordered by their start in the circuit.
+    pub fn segments(&self) -> &[QubitSegment] {
+        &self.segments
+    }
+
+    /// The physical qubit assigned to the segment starting at a reset node.
+    pub fn physical_qubit(&self, reset: Node) -> Option<Qubit> {
+        self.segments
+            .iter()
+            .find(|seg| seg.reset == Some(reset))
+            .map(|seg| seg.physical)
+    }
+
+    /// The segments that were moved off their circuit qubit.
+    pub fn moved(&self) -> impl Iterator<Item = &QubitSegment> + '_ {
+        self.segments.iter().filter(|seg| seg.qubit != seg.physical)
+    }
+}
+
+/// Assign the segments of a circuit's qubits to the physical qubits of a
+/// device.
+///
+/// Qubit `i` of the circuit initially runs on physical qubit `i`. Each segment
+/// starting at a [`Tk2Op::Reset`] is moved to the idle physical qubit with the
+/// lowest error rate, plus the [wear penalty](NoiseProfile::with_wear_penalty)
+/// of the segments it already hosts. Ties are broken by picking the least used
+/// qubit.
+pub fn plan_qubit_remap(
+    circ: &Circuit<impl HugrView>,
+    noise: &NoiseProfile,
+) -> Result<QubitRemap, QubitRemapError> {
+    if noise.qubit_count() < circ.qubit_count() {
+        return Err(QubitRemapError::NotEnoughQubits {
+            circuit: circ.qubit_count(),
+            device: noise.qubit_count(),
+        });
+    }
+    if let Some((i, &rate)) = noise
+        .error_rates
+        .iter()
+        .find_position(|rate| rate.is_nan() || **rate < 0.)
+    {
+        return Err(QubitRemapError::InvalidErrorRate {
+            qubit: Qubit::new(i),
+            rate,
+        });
+    }
+
+    let segments = find_segments(circ);
+
+    // The command index after which each physical qubit is idle, and the
+    // number of segments it hosted.
+    let mut busy_until: Vec<Option<usize>> = vec![None; noise.qubit_count()];
+    let mut uses = vec![0usize; noise.qubit_count()];
+    let mut remap = QubitRemap::default();
+    for seg in segments {
+        let physical = match seg.reset {
+            None => seg.qubit,
+            Some(_) => {
+                let idle = (0..noise.qubit_count())
+                    .filter(|&p| busy_until[p].map_or(true, |end| end <= seg.start));
+                let score = |p: usize| noise.error_rates[p] + noise.wear_penalty * uses[p] as f64;
+                let best = idle
+                    .min_by(|&p1, &p2| {
+                        score(p1)
+                            .total_cmp(&score(p2))
+                            .then(uses[p1].cmp(&uses[p2]))
+                            .then(p1.cmp(&p2))
+                    })
+                    .expect("The qubit being reset is idle.");
+                Qubit::new(best)
+            }
+        };
+        busy_until[physical.index()] = Some(seg.end);
+        uses[physical.index()] += 1;
+        remap.segments.push(QubitSegment {
+            qubit: seg.qubit,
+            reset: seg.reset,
+            physical,
+        });
+    }
+    Ok(remap)
+}
+
+/// Assign the segments of a circuit's qubits to physical qubits, and record
+/// the assignment of each reset node in its metadata.
+///
+/// See [`plan_qubit_remap`].
+pub fn remap_qubit_segments(
+    circ: &mut Circuit<impl HugrMut>,
+    noise: &NoiseProfile,
+) -> Result<QubitRemap, QubitRemapError> {
+    let remap = plan_qubit_remap(circ, noise)?;
+    for seg in remap.segments() {
+        if let Some(reset) = seg.reset {
+            circ.hugr_mut()
+                .set_metadata(reset, METADATA_PHYSICAL_QUBIT, seg.physical.index());
+        }
+    }
+    Ok(remap)
+}
+
+/// A qubit segment, spanning an interval of command indices.
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    qubit: Qubit,
+    reset: Option<Node>,
+    /// The index of the first command of the segment.
+    start: usize,
+    /// The index of the last command of the segment, or the number of
+    /// commands if the segment reaches the circuit outputs.
+    end: usize,
+}
+
+/// Split the qubit wires of a circuit at each reset, ordered by start.
+fn find_segments(circ: &Circuit<impl HugrView>) -> Vec<Interval> {
+    let n_commands = circ.commands().count();
+    let mut segments = Vec::new();
+    // The index of the current segment of each qubit in `segments`.
+    let mut current: HashMap<Qubit, usize> = (0..circ.qubit_count())
+        .map(|q| {
+            segments.push(Interval {
+                qubit: Qubit::new(q),
+                reset: None,
+                start: 0,
+                end: 0,
+            });
+            (Qubit::new(q), q)
+        })
+        .collect();
+
+    for (i, cmd) in circ.commands().enumerate() {
+        let is_reset = matches!(Tk2Op::try_from(cmd.optype()), Ok(Tk2Op::Reset));
+        for (unit, _, _) in cmd.input_qubits() {
+            let qubit = Qubit::from(unit);
+            if is_reset {
+                current.insert(qubit, segments.len());
+                segments.push(Interval {
+                    qubit,
+                    reset: Some(cmd.node()),
+                    start: i,
+                    end: i,
+                });
+            } else if let Some(&seg) = current.get(&qubit) {
+                segments[seg].end = i;
+            }
+        }
+    }
+    // The last segment of each qubit is live until the circuit outputs.
+    for seg in current.into_values() {
+        segments[seg].end = n_commands;
+    }
+
+    segments.sort_by_key(|seg| (seg.start, seg.reset.is_some(), seg.qubit));
+    segments
+}
+
+/// Errors that can occur while remapping qubits.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum QubitRemapError {
+    /// The device has fewer qubits than the circuit.
+    #[error("The circuit uses {circuit} qubits, but the device only has {device}.")]
+    NotEnoughQubits {
+        /// The number of qubits of the circuit.
+        circuit: usize,
+        /// The number of qubits of the device.
+        device: usize,
+    },
+    /// An error rate is negative or NaN.
+    #[error("Invalid error rate {rate} for qubit {qubit}.")]
+    InvalidErrorRate {
+        /// The physical qubit.
+        qubit: Qubit,
+        /// The invalid error rate.
+        rate: f64,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::utils::build_simple_circuit;
+
+    /// A circuit reusing qubit 0 after a mid-circuit measurement.
+    fn measure_reset() -> Circuit {
+        build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::X, [1])?;
+            circ.append_with_outputs(Tk2Op::Measure, [0])?;
+            circ.append(Tk2Op::Reset, [0])?;
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    fn reset_node(circ: &Circuit) -> Node {
+        circ.commands()
+            .find(|cmd| cmd.optype() == &Tk2Op::Reset.into())
+            .unwrap()
+            .node()
+    }
+
+    #[rstest]
+    #[case::spare_qubit(vec![0.01, 0.02, 0.001], 2)]
+    #[case::bad_spare_qubit(vec![0.01, 0.02, f64::INFINITY], 0)]
+    #[case::no_spare_qubit(vec![0.01, 0.02], 0)]
+    fn remap_after_reset(#[case] error_rates: Vec<f64>, #[case] expected: usize) {
+        let mut circ = measure_reset();
+        let reset = reset_node(&circ);
+        let noise = NoiseProfile::new(error_rates);
+        let remap = remap_qubit_segments(&mut circ, &noise).unwrap();
+
+        assert_eq!(remap.segments().len(), 3);
+        assert_eq!(remap.physical_qubit(reset), Some(Qubit::new(expected)));
+        assert_eq!(remap.moved().count(), usize::from(expected != 0));
+        assert_eq!(
+            circ.hugr().get_metadata(reset, METADATA_PHYSICAL_QUBIT),
+            Some(&expected.into())
+        );
+    }
+
+    #[test]
+    fn busy_qubits_are_skipped() {
+        // Qubit 1 is in use while qubit 0 is reset, so the segment cannot be
+        // moved onto it despite its lower error rate.
+        let circ = measure_reset();
+        let noise = NoiseProfile::new([0.02, 0.01]);
+        let remap = plan_qubit_remap(&circ, &noise).unwrap();
+        assert_eq!(remap.physical_qubit(reset_node(&circ)), Some(Qubit::new(0)));
+    }
+
+    #[test]
+    fn spread_wear() {
+        let circ = build_simple_circuit(1, |circ| {
+            for _ in 0..3 {
+                circ.append(Tk2Op::Reset, [0])?;
+                circ.append(Tk2Op::H, [0])?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        let physical = |noise: NoiseProfile| {
+            plan_qubit_remap(&circ, &noise)
+                .unwrap()
+                .segments()
+                .iter()
+                .map(|seg| seg.physical.index())
+                .collect_vec()
+        };
+
+        // Equally good qubits are used in turn.
+        assert_eq!(physical(NoiseProfile::new([0.01; 3])), [0, 1, 2, 0]);
+        // The worse qubit is avoided...
+        let noise = NoiseProfile::new([0.01, 0.01, 0.05]);
+        assert_eq!(physical(noise.clone()), [0, 1, 0, 1]);
+        // ...unless the wear of the other qubits outweighs its error rate.
+        assert_eq!(physical(noise.with_wear_penalty(0.1)), [0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn invalid_profiles() {
+        let circ = measure_reset();
+        assert_eq!(
+            plan_qubit_remap(&circ, &NoiseProfile::new([0.01])),
+            Err(QubitRemapError::NotEnoughQubits {
+                circuit: 2,
+                device: 1
+            })
+        );
+        assert!(matches!(
+            plan_qubit_remap(&circ, &NoiseProfile::new([0.01, -1.])),
+            Err(QubitRemapError::InvalidErrorRate { qubit, .. }) if qubit == Qubit::new(1)
+        ));
+    }
+}
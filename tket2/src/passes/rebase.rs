@@ -0,0 +1,717 @@
+//! Conversion of circuits to hardware-native gate sets.
+//!
+//! A [`GateSet`] lists the gates supported natively by a target, along with
+//! the decompositions used to replace the other gates. [`rebase`] applies the
+//! decompositions until only native gates remain.
+//!
+//! Gate sets for Quantinuum-style and IBM-style devices are provided by
+//! [`GateSet::quantinuum`] and [`GateSet::ibm`], along with an exact
+//! Clifford+T gate set in [`GateSet::clifford_t`]. Custom gate sets can be
+//! registered by name in a [`RebaseRegistry`].
+
+use std::collections::BTreeMap;
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use hugr::builder::{BuildError, CircuitBuilder, DFGBuilder, Dataflow, DataflowHugr};
+use hugr::extension::prelude::QB_T;
+use hugr::hugr::views::sibling_subgraph::InvalidReplacement;
+use hugr::ops::{NamedOp, OpTrait, OpType};
+use hugr::std_extensions::arithmetic::float_types::{self, ConstF64, FLOAT64_TYPE};
+use hugr::types::Signature;
+use hugr::{CircuitUnit, Hugr, HugrView, Node, Wire};
+use itertools::Itertools;
+use thiserror::Error;
+use tket_json_rs::circuit_json;
+use tket_json_rs::optype::OpType as Tk1OpType;
+
+use super::controlled::{controlled_decomposition, decompose_controlled_ops};
+use crate::circuit::phase::GlobalPhase;
+use crate::extension::{ControlledOp, REGISTRY};
+use crate::rewrite::Subcircuit;
+use crate::serialize::pytket::OpaqueTk1Op;
+use crate::sim::unitary::relative_phase;
+use crate::utils::build_simple_circuit;
+use crate::{Circuit, Pauli, Tk2Op};
+
+/// The maximum number of rounds of decompositions applied by [`rebase`].
+///
+/// Decompositions may produce gates that need to be decomposed further, but
+/// cyclic decompositions would never terminate.
+const MAX_ROUNDS: usize = 16;
+
+/// A quantum gate, as listed in a [`GateSet`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NativeGate {
+    /// A TKET2 operation.
+    Tk2(Tk2Op),
+    /// A pytket operation without a TKET2 equivalent, such as `SX`, encoded as
+    /// an opaque operation.
+    Pytket(Tk1OpType),
+}
+
+impl NativeGate {
+    /// The gate corresponding to an operation, if it is a quantum gate.
+    ///
+    /// Returns `None` for classical operations and for non-unitary quantum
+    /// operations such as measurements, which are kept by [`rebase`].
+    pub fn from_optype(op: &OpType) -> Option<Self> {
+        if let Ok(tk2op) = Tk2Op::try_from(op) {
+            return tk2op.is_quantum().then_some(Self::Tk2(tk2op));
+        }
+        let tk1op = OpaqueTk1Op::try_from_tket2(op).ok()??;
+        Some(Self::Pytket(tk1op.serialised_op().op_type.clone()))
+    }
+
+    /// The operation for this gate.
+    ///
+    /// Pytket operations are created without classical bits.
+    pub fn to_optype(&self) -> OpType {
+        match self {
+            Self::Tk2(op) => (*op).into(),
+            Self::Pytket(optype) => {
+                let op = circuit_json::Operation::from_optype(optype.clone());
+                let num_qubits = match optype {
+                    Tk1OpType::SWAP => 2,
+                    _ => 1,
+                };
+                OpaqueTk1Op::new_from_op(op, num_qubits, 0)
+                    .as_custom_op()
+                    .into()
+            }
+        }
+    }
+}
+
+impl From<Tk2Op> for NativeGate {
+    fn from(op: Tk2Op) -> Self {
+        Self::Tk2(op)
+    }
+}
+
+impl From<Tk1OpType> for NativeGate {
+    fn from(op: Tk1OpType) -> Self {
+        Self::Pytket(op)
+    }
+}
+
+/// A set of gates supported by a target, and the decompositions of the other
+/// gates into them.
+#[derive(Debug, Clone)]
+pub struct GateSet {
+    name: String,
+    native: Vec<NativeGate>,
+    decompositions: Vec<(NativeGate, Circuit)>,
+}
+
+impl GateSet {
+    /// Create a gate set with the given native gates, and no decompositions.
+    pub fn new(
+        name: impl Into<String>,
+        native: impl IntoIterator<Item = impl Into<NativeGate>>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            native: native.into_iter().map_into().collect(),
+            decompositions: Vec::new(),
+        }
+    }
+
+    /// Add a decomposition of a gate, replacing any previous one.
+    ///
+    /// The replacement circuit must have the same signature as the gate: its
+    /// inputs are the qubits followed by the angle parameters. It may contain
+    /// non-native gates, which are decomposed further.
+    ///
+    /// If the replacement is only equivalent to the gate up to a global phase,
+    /// the phase should be recorded in its [`METADATA_PHASE_DELTA`] metadata.
+    ///
+    /// [`METADATA_PHASE_DELTA`]: crate::circuit::phase::METADATA_PHASE_DELTA
+    pub fn with_decomposition(mut self, gate: impl Into<NativeGate>, replacement: Circuit) -> Self {
+        let gate = gate.into();
+        self.decompositions.retain(|(g, _)| g != &gate);
+        self.decompositions.push((gate, replacement));
+        self
+    }
+
+    /// The name of the gate set.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The native gates of the set.
+    pub fn native_gates(&self) -> &[NativeGate] {
+        &self.native
+    }
+
+    /// Returns `true` if the gate is supported natively.
+    pub fn is_native(&self, gate: &NativeGate) -> bool {
+        self.native.contains(gate)
+    }
+
+    /// The decomposition of a gate, if any.
+    pub fn decomposition(&self, gate: &NativeGate) -> Option<&Circuit> {
+        self.decompositions
+            .iter()
+            .find(|(g, _)| g == gate)
+            .map(|(_, circ)| circ)
+    }
+
+    /// Quantinuum-style gate set, with `PhasedX` and `Rz` single-qubit gates
+    /// and `ZZMax` or `ZZPhase` entangling gates.
+    pub fn quantinuum() -> Self {
+        use Tk2Op::*;
+        let native = [PhasedX, RzF64, ZZMax, ZZPhase];
+        Self::new("quantinuum", native)
+            .with_decomposition(Z, constant_rule(Z, |c| rz(c, 0, PI)))
+            .with_decomposition(S, constant_rule(S, |c| rz(c, 0, FRAC_PI_2)))
+            .with_decomposition(T, constant_rule(T, |c| rz(c, 0, FRAC_PI_4)))
+            .with_decomposition(Sdg, constant_rule(Sdg, |c| rz(c, 0, -FRAC_PI_2)))
+            .with_decomposition(Tdg, constant_rule(Tdg, |c| rz(c, 0, -FRAC_PI_4)))
+            .with_decomposition(X, constant_rule(X, |c| phased_x(c, 0, PI, 0.)))
+            .with_decomposition(Y, constant_rule(Y, |c| phased_x(c, 0, PI, FRAC_PI_2)))
+            .with_decomposition(
+                H,
+                constant_rule(H, |c| {
+                    rz(c, 0, PI)?;
+                    phased_x(c, 0, FRAC_PI_2, FRAC_PI_2)
+                }),
+            )
+            .with_decomposition(
+                RxF64,
+                rule(1, |c, [theta]| {
+                    let zero = c.add_constant(ConstF64::new(0.));
+                    c.append_and_consume(PhasedX, [qubit(0), theta.into(), zero.into()])?;
+                    Ok(())
+                }),
+            )
+            .with_decomposition(
+                TK1,
+                // `TK1(a, b, c) = PhasedX(b, a) Rz(a + c)`
+                rule(1, |c, [a, b, c_]| {
+                    let sum = c.append_with_outputs(AngleAdd, [a, c_])?[0];
+                    c.append_and_consume(RzF64, [qubit(0), sum.into()])?;
+                    c.append_and_consume(PhasedX, [qubit(0), b.into(), a.into()])?;
+                    Ok(())
+                }),
+            )
+            .with_decomposition(CZ, constant_rule(CZ, cz_from_zzmax))
+            .with_decomposition(
+                CX,
+                constant_rule(CX, |c| {
+                    c.append(H, [1])?;
+                    cz_from_zzmax(c)?;
+                    c.append(H, [1])?;
+                    Ok(())
+                }),
+            )
+            .with_decomposition(XXPhase, xx_from_zz())
+            .with_decomposition(YYPhase, yy_from_zz())
+            .with_decomposition(Tk1OpType::SWAP, swap_from_cx())
+            .with_toffoli_decompositions()
+    }
+
+    /// IBM-style gate set, with `Rz`, `SX` and `X` single-qubit gates and `CX`
+    /// entangling gates.
+    ///
+    /// The `SX` gates are pytket operations.
+    pub fn ibm() -> Self {
+        use Tk2Op::*;
+        let native: [NativeGate; 4] = [RzF64.into(), Tk1OpType::SX.into(), X.into(), CX.into()];
+        let mut h = rule(1, |c, []| {
+            rz(c, 0, FRAC_PI_2)?;
+            c.append(NativeGate::Pytket(Tk1OpType::SX).to_optype(), [0])?;
+            rz(c, 0, FRAC_PI_2)
+        });
+        // `H = e^{iπ/4} Rz(π/2) SX Rz(π/2)`
+        h.set_phase_delta(Some(GlobalPhase::new(0.25)));
+
+        Self::new("ibm", native)
+            .with_decomposition(H, h)
+            .with_decomposition(Z, constant_rule(Z, |c| rz(c, 0, PI)))
+            .with_decomposition(S, constant_rule(S, |c| rz(c, 0, FRAC_PI_2)))
+            .with_decomposition(Sdg, constant_rule(Sdg, |c| rz(c, 0, -FRAC_PI_2)))
+            .with_decomposition(T, constant_rule(T, |c| rz(c, 0, FRAC_PI_4)))
+            .with_decomposition(Tdg, constant_rule(Tdg, |c| rz(c, 0, -FRAC_PI_4)))
+            .with_decomposition(
+                Y,
+                constant_rule(Y, |c| {
+                    rz(c, 0, PI)?;
+                    c.append(X, [0])?;
+                    Ok(())
+                }),
+            )
+            .with_decomposition(
+                RxF64,
+                rule(1, |c, [theta]| {
+                    c.append(H, [0])?;
+                    c.append_and_consume(RzF64, [qubit(0), theta.into()])?;
+                    c.append(H, [0])?;
+                    Ok(())
+                }),
+            )
+            .with_decomposition(
+                PhasedX,
+                // `X Rz(φ) X = Rz(-φ)`
+                rule(1, |c, [theta, phi]| {
+                    c.append(X, [0])?;
+                    c.append_and_consume(RzF64, [qubit(0), phi.into()])?;
+                    c.append(X, [0])?;
+                    c.append_and_consume(RxF64, [qubit(0), theta.into()])?;
+                    c.append_and_consume(RzF64, [qubit(0), phi.into()])?;
+                    Ok(())
+                }),
+            )
+            .with_decomposition(
+                TK1,
+                rule(1, |c, [a, b, c_]| {
+                    c.append_and_consume(RzF64, [qubit(0), c_.into()])?;
+                    c.append_and_consume(RxF64, [qubit(0), b.into()])?;
+                    c.append_and_consume(RzF64, [qubit(0), a.into()])?;
+                    Ok(())
+                }),
+            )
+            .with_decomposition(
+                CZ,
+                constant_rule(CZ, |c| {
+                    c.append(H, [1])?;
+                    c.append(CX, [0, 1])?;
+                    c.append(H, [1])?;
+                    Ok(())
+                }),
+            )
+            .with_decomposition(
+                ZZMax,
+                constant_rule(ZZMax, |c| {
+                    c.append(CX, [0, 1])?;
+                    rz(c, 1, FRAC_PI_2)?;
+                    c.append(CX, [0, 1])?;
+                    Ok(())
+                }),
+            )
+            .with_decomposition(
+                ZZPhase,
+                rule(2, |c, [theta]| {
+                    c.append(CX, [0, 1])?;
+                    c.append_and_consume(RzF64, [qubit(1), theta.into()])?;
+                    c.append(CX, [0, 1])?;
+                    Ok(())
+                }),
+            )
+            .with_decomposition(XXPhase, xx_from_zz())
+            .with_decomposition(YYPhase, yy_from_zz())
+            .with_decomposition(Tk1OpType::SWAP, swap_from_cx())
+            .with_toffoli_decompositions()
+    }
+
+    /// Clifford+T gate set, with `H`, `S`, `T`, Pauli gates and their adjoints,
+    /// and `CX` entangling gates.
+    ///
+    /// Only the gates with an exact Clifford+T decomposition can be rebased,
+    /// so arbitrary rotations are not supported.
+    pub fn clifford_t() -> Self {
+        use Tk2Op::*;
+        let native = [H, S, Sdg, T, Tdg, X, Y, Z, CX];
+        Self::new("clifford_t", native)
+            .with_decomposition(
+                CZ,
+                constant_rule(CZ, |c| {
+                    c.append(H, [1])?;
+                    c.append(CX, [0, 1])?;
+                    c.append(H, [1])?;
+                    Ok(())
+                }),
+            )
+            .with_decomposition(Tk1OpType::SWAP, swap_from_cx())
+            .with_toffoli_decompositions()
+    }
+
+    /// Add the Clifford+T decompositions of the `CCX` and `CCZ` gates.
+    fn with_toffoli_decompositions(self) -> Self {
+        self.with_decomposition(
+            Tk2Op::CCX,
+            controlled_decomposition(ControlledOp::new(2, Pauli::X)),
+        )
+        .with_decomposition(
+            Tk2Op::CCZ,
+            controlled_decomposition(ControlledOp::new(2, Pauli::Z)),
+        )
+    }
+}
+
+/// A collection of gate sets, indexed by name.
+///
+/// The built-in gate sets are registered as `"quantinuum"`, `"ibm"` and
+/// `"clifford_t"`.
+#[derive(Debug, Clone)]
+pub struct RebaseRegistry {
+    gate_sets: BTreeMap<String, GateSet>,
+}
+
+impl RebaseRegistry {
+    /// Create a registry containing the built-in gate sets.
+    pub fn new() -> Self {
+        let mut registry = Self {
+            gate_sets: BTreeMap::new(),
+        };
+        registry.register(GateSet::quantinuum());
+        registry.register(GateSet::ibm());
+        registry.register(GateSet::clifford_t());
+        registry
+    }
+
+    /// Register a gate set under its name.
+    ///
+    /// Returns the gate set previously registered with the same name, if any.
+    pub fn register(&mut self, gate_set: GateSet) -> Option<GateSet> {
+        self.gate_sets.insert(gate_set.name.clone(), gate_set)
+    }
+
+    /// The gate set registered with a name.
+    pub fn get(&self, name: &str) -> Option<&GateSet> {
+        self.gate_sets.get(name)
+    }
+
+    /// The names of the registered gate sets, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.gate_sets.keys().map(String::as_str)
+    }
+
+    /// Rebase a circuit to the gate set registered with a name.
+    ///
+    /// See [`rebase`].
+    pub fn rebase(&self, circ: &mut Circuit, name: &str) -> Result<usize, RebaseError> {
+        let gate_set = self
+            .get(name)
+            .ok_or_else(|| RebaseError::UnknownGateSet(name.to_string()))?;
+        rebase(circ, gate_set)
+    }
+}
+
+impl Default for RebaseRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Errors that can occur when rebasing a circuit.
+#[derive(Debug, Clone, Error)]
+#[non_exhaustive]
+pub enum RebaseError {
+    /// The circuit contains a gate that is not native and has no
+    /// decomposition.
+    #[error("Operation {} in {node} is not supported by gate set {gate_set}.", optype.name())]
+    UnsupportedGate {
+        /// The unsupported operation.
+        optype: OpType,
+        /// The node.
+        node: Node,
+        /// The name of the gate set.
+        gate_set: String,
+    },
+    /// A decomposition does not match the signature of its gate.
+    #[error("Invalid decomposition in gate set {gate_set}: {source}")]
+    InvalidDecomposition {
+        /// The name of the gate set.
+        gate_set: String,
+        /// The replacement error.
+        source: InvalidReplacement,
+    },
+    /// The decompositions of the gate set produced non-native gates after
+    /// the maximum number of rounds, probably because they are cyclic.
+    #[error("The decompositions of gate set {0} did not terminate.")]
+    NonTerminating(String),
+    /// No gate set is registered with the name.
+    #[error("Unknown gate set {0}.")]
+    UnknownGateSet(String),
+}
+
+/// Convert the gates of a circuit to a gate set.
+///
+/// Each gate that is not native is replaced by its decomposition, and the
+/// decompositions are applied again to the new gates until only native ones
+/// remain. The global phase of the circuit is updated with the phase of the
+/// decompositions. Classical operations, measurements and frozen gates are
+/// left untouched.
+///
+/// Multi-controlled [`ControlledOp`]s are never native, and are first
+/// expanded into Clifford+T gates with [`controlled_decomposition`].
+///
+/// Returns the number of decompositions applied.
+pub fn rebase(circ: &mut Circuit, gate_set: &GateSet) -> Result<usize, RebaseError> {
+    let mut applied = decompose_controlled_ops(circ);
+    for _ in 0..MAX_ROUNDS {
+        let targets = circ
+            .commands()
+            .filter(|cmd| !circ.is_frozen(cmd.node()))
+            .filter_map(|cmd| {
+                let gate = NativeGate::from_optype(cmd.optype())?;
+                (!gate_set.is_native(&gate)).then_some((cmd.node(), gate))
+            })
+            .collect_vec();
+        if targets.is_empty() {
+            return Ok(applied);
+        }
+        for (node, gate) in targets {
+            let Some(replacement) = gate_set.decomposition(&gate) else {
+                return Err(RebaseError::UnsupportedGate {
+                    optype: circ.hugr().get_optype(node).clone(),
+                    node,
+                    gate_set: gate_set.name.clone(),
+                });
+            };
+            let subcircuit = Subcircuit::try_from_nodes([node], circ).unwrap();
+            let rewrite = subcircuit
+                .create_rewrite(circ, replacement.clone())
+                .map_err(|source| RebaseError::InvalidDecomposition {
+                    gate_set: gate_set.name.clone(),
+                    source,
+                })?;
+            rewrite.apply(circ).unwrap();
+            applied += 1;
+        }
+    }
+    Err(RebaseError::NonTerminating(gate_set.name.clone()))
+}
+
+/// Build a decomposition with the given number of qubits, and `N` angle
+/// inputs.
+pub(super) fn rule<const N: usize>(
+    num_qubits: usize,
+    f: impl FnOnce(&mut CircuitBuilder<DFGBuilder<Hugr>>, [Wire; N]) -> Result<(), BuildError>,
+) -> Circuit {
+    let build = || {
+        let qubits = vec![QB_T; num_qubits];
+        let inputs = [qubits.clone(), vec![FLOAT64_TYPE; N]].concat();
+        let signature =
+            Signature::new(inputs, qubits).with_extension_delta(float_types::EXTENSION_ID);
+        let mut h = DFGBuilder::new(signature)?;
+        let inputs = h.input_wires().collect_vec();
+        let (qubits, params) = inputs.split_at(num_qubits);
+        let mut circ = h.as_circuit(qubits.to_vec());
+        f(&mut circ, params.try_into().unwrap())?;
+        let qubits = circ.finish();
+        h.finish_hugr_with_outputs(qubits, &REGISTRY)
+    };
+    let hugr: Result<Hugr, BuildError> = build();
+    hugr.unwrap().into()
+}
+
+/// Build a decomposition of a gate without parameters, recording the global
+/// phase between the gate and the decomposition.
+fn constant_rule(
+    op: Tk2Op,
+    f: impl FnOnce(&mut CircuitBuilder<DFGBuilder<Hugr>>) -> Result<(), BuildError>,
+) -> Circuit {
+    let num_qubits = OpType::from(op).dataflow_signature().unwrap().input_count();
+    let mut replacement = rule(num_qubits, |c, []| f(c));
+    let gate = build_simple_circuit(num_qubits, |c| {
+        c.append(op, 0..num_qubits)?;
+        Ok(())
+    })
+    .unwrap();
+    let phase = relative_phase(&gate.unitary().unwrap(), &replacement.unitary().unwrap());
+    replacement.set_phase_delta(Some(GlobalPhase::new(phase / PI)));
+    replacement
+}
+
+fn qubit(index: usize) -> CircuitUnit {
+    CircuitUnit::Linear(index)
+}
+
+/// Append a Z rotation by a constant angle.
+fn rz(c: &mut CircuitBuilder<DFGBuilder<Hugr>>, q: usize, angle: f64) -> Result<(), BuildError> {
+    let angle = c.add_constant(ConstF64::new(angle));
+    c.append_and_consume(Tk2Op::RzF64, [qubit(q), angle.into()])?;
+    Ok(())
+}
+
+/// Append an X rotation by a constant angle.
+fn rx(c: &mut CircuitBuilder<DFGBuilder<Hugr>>, q: usize, angle: f64) -> Result<(), BuildError> {
+    let angle = c.add_constant(ConstF64::new(angle));
+    c.append_and_consume(Tk2Op::RxF64, [qubit(q), angle.into()])?;
+    Ok(())
+}
+
+/// Append a `PhasedX` gate with constant angles.
+fn phased_x(
+    c: &mut CircuitBuilder<DFGBuilder<Hugr>>,
+    q: usize,
+    theta: f64,
+    phi: f64,
+) -> Result<(), BuildError> {
+    let theta = c.add_constant(ConstF64::new(theta));
+    let phi = c.add_constant(ConstF64::new(phi));
+    c.append_and_consume(Tk2Op::PhasedX, [qubit(q), theta.into(), phi.into()])?;
+    Ok(())
+}
+
+/// `CZ = Rz(-π/2) ⊗ Rz(-π/2) · ZZMax`, up to a global phase.
+fn cz_from_zzmax(c: &mut CircuitBuilder<DFGBuilder<Hugr>>) -> Result<(), BuildError> {
+    rz(c, 0, -FRAC_PI_2)?;
+    rz(c, 1, -FRAC_PI_2)?;
+    c.append(Tk2Op::ZZMax, [0, 1])?;
+    Ok(())
+}
+
+/// `XXPhase(θ) = (H ⊗ H) ZZPhase(θ) (H ⊗ H)`.
+fn xx_from_zz() -> Circuit {
+    rule(2, |c, [theta]| {
+        c.append(Tk2Op::H, [0])?;
+        c.append(Tk2Op::H, [1])?;
+        c.append_and_consume(Tk2Op::ZZPhase, [qubit(0), qubit(1), theta.into()])?;
+        c.append(Tk2Op::H, [0])?;
+        c.append(Tk2Op::H, [1])?;
+        Ok(())
+    })
+}
+
+/// `YYPhase(θ) = (V ⊗ V) ZZPhase(θ) (V ⊗ V)†`, with `V = Rx(π/2)`.
+fn yy_from_zz() -> Circuit {
+    rule(2, |c, [theta]| {
+        rx(c, 0, -FRAC_PI_2)?;
+        rx(c, 1, -FRAC_PI_2)?;
+        c.append_and_consume(Tk2Op::ZZPhase, [qubit(0), qubit(1), theta.into()])?;
+        rx(c, 0, FRAC_PI_2)?;
+        rx(c, 1, FRAC_PI_2)?;
+        Ok(())
+    })
+}
+
+/// A SWAP gate as three CX gates.
+fn swap_from_cx() -> Circuit {
+    rule(2, |c, []| {
+        c.append(Tk2Op::CX, [0, 1])?;
+        c.append(Tk2Op::CX, [1, 0])?;
+        c.append(Tk2Op::CX, [0, 1])?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use num_complex::Complex64;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::sim::unitary::equal_up_to_phase;
+    use crate::utils::test::tk1_circuit;
+    const ALL_GATES: &str = r#"{"args": [["q", [0]]], "op": {"type": "H"}},
+        {"args": [["q", [1]]], "op": {"type": "X"}},
+        {"args": [["q", [0]]], "op": {"type": "Y"}},
+        {"args": [["q", [1]]], "op": {"type": "Z"}},
+        {"args": [["q", [0]]], "op": {"type": "S"}},
+        {"args": [["q", [1]]], "op": {"type": "Sdg"}},
+        {"args": [["q", [0]]], "op": {"type": "T"}},
+        {"args": [["q", [1]]], "op": {"type": "Tdg"}},
+        {"args": [["q", [0]]], "op": {"type": "Rx", "params": ["0.3"]}},
+        {"args": [["q", [1]]], "op": {"type": "PhasedX", "params": ["0.7", "0.2"]}},
+        {"args": [["q", [0]]], "op": {"type": "TK1", "params": ["0.1", "0.4", "0.6"]}},
+        {"args": [["q", [0]], ["q", [1]]], "op": {"type": "CX"}},
+        {"args": [["q", [1]], ["q", [0]]], "op": {"type": "CZ"}},
+        {"args": [["q", [0]], ["q", [1]]], "op": {"type": "ZZMax"}},
+        {"args": [["q", [0]], ["q", [1]]], "op": {"type": "ZZPhase", "params": ["0.9"]}},
+        {"args": [["q", [1]], ["q", [0]]], "op": {"type": "XXPhase", "params": ["0.35"]}},
+        {"args": [["q", [0]], ["q", [1]]], "op": {"type": "YYPhase", "params": ["1.2"]}}"#;
+
+    const SWAP: &str = r#"{"args": [["q", [0]], ["q", [1]]], "op": {"type": "SWAP"}}"#;
+
+    #[rstest]
+    #[case::quantinuum(GateSet::quantinuum())]
+    #[case::ibm(GateSet::ibm())]
+    fn rebase_to_native_gates(#[case] gate_set: GateSet) {
+        let mut circ = tk1_circuit(2, 0, &format!("{ALL_GATES}, {SWAP}"));
+
+        let applied = rebase(&mut circ, &gate_set).unwrap();
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert!(applied > 0);
+        for cmd in circ.commands() {
+            if let Some(gate) = NativeGate::from_optype(cmd.optype()) {
+                assert!(gate_set.is_native(&gate), "{gate:?} is not native");
+            }
+        }
+    }
+
+    #[test]
+    fn rebase_tracks_global_phase() {
+        let mut circ = tk1_circuit(2, 0, ALL_GATES);
+        let unitary = circ.unitary().unwrap();
+
+        rebase(&mut circ, &GateSet::quantinuum()).unwrap();
+        let half_turns = circ.global_phase().unwrap().constant();
+        let phase = Complex64::from_polar(1., half_turns * PI);
+        let rebased = circ.unitary().unwrap().mapv(|x| x * phase);
+
+        assert!(equal_up_to_phase(&rebased, &unitary, 1e-9));
+        assert!((&rebased - &unitary).iter().all(|x| x.norm() < 1e-9));
+    }
+
+    #[rstest]
+    // The `SX` gates of the IBM gate set cannot be simulated.
+    #[case::quantinuum(GateSet::quantinuum(), true)]
+    #[case::ibm(GateSet::ibm(), false)]
+    #[case::clifford_t(GateSet::clifford_t(), true)]
+    fn rebase_toffoli_gates(#[case] gate_set: GateSet, #[case] simulable: bool) {
+        let mut circ = build_simple_circuit(3, |c| {
+            c.append(Tk2Op::CCX, [0, 1, 2])?;
+            c.append(Tk2Op::CCZ, [2, 0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        let unitary = circ.unitary().unwrap();
+
+        rebase(&mut circ, &gate_set).unwrap();
+        for cmd in circ.commands() {
+            if let Some(gate) = NativeGate::from_optype(cmd.optype()) {
+                assert!(gate_set.is_native(&gate), "{gate:?} is not native");
+            }
+        }
+        if simulable {
+            assert!(equal_up_to_phase(&circ.unitary().unwrap(), &unitary, 1e-9));
+        }
+    }
+
+    #[test]
+    fn unsupported_gate() {
+        let mut circ = tk1_circuit(2, 0, r#"{"args": [["q", [0]]], "op": {"type": "H"}}"#);
+        let gate_set = GateSet::new("cx_only", [Tk2Op::CX]);
+
+        assert!(matches!(
+            rebase(&mut circ, &gate_set),
+            Err(RebaseError::UnsupportedGate { gate_set, .. }) if gate_set == "cx_only"
+        ));
+    }
+
+    #[test]
+    fn custom_gate_set() {
+        let cx_to_cz = rule(2, |c, []| {
+            c.append(Tk2Op::H, [1])?;
+            c.append(Tk2Op::CZ, [0, 1])?;
+            c.append(Tk2Op::H, [1])?;
+            Ok(())
+        });
+        let gate_set =
+            GateSet::new("cz", [Tk2Op::H, Tk2Op::CZ]).with_decomposition(Tk2Op::CX, cx_to_cz);
+        let mut registry = RebaseRegistry::new();
+        assert!(registry.register(gate_set).is_none());
+        assert_eq!(
+            registry.names().collect_vec(),
+            ["clifford_t", "cz", "ibm", "quantinuum"]
+        );
+
+        let mut circ = tk1_circuit(
+            2,
+            0,
+            r#"{"args": [["q", [0]], ["q", [1]]], "op": {"type": "CX"}}"#,
+        );
+        let unitary = circ.unitary().unwrap();
+        assert_eq!(registry.rebase(&mut circ, "cz").unwrap(), 1);
+        assert!(equal_up_to_phase(&circ.unitary().unwrap(), &unitary, 1e-9));
+
+        assert!(matches!(
+            registry.rebase(&mut circ, "unknown"),
+            Err(RebaseError::UnknownGateSet(_))
+        ));
+    }
+}
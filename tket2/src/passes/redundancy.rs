@@ -0,0 +1,428 @@
+//! Removal of redundant gates.
+//!
+//! [`remove_redundancies`] removes the gates that implement the identity up
+//! to a global phase, such as rotations by a zero angle, and the pairs of
+//! adjacent gates that undo each other, such as `S` followed by `Sdg` or two
+//! consecutive CX gates on the same qubits. [`cancel_commuting_inverses`] also
+//! cancels the inverse pairs separated by gates they commute with. The global
+//! phase of the circuit is updated with the phase of the removed gates.
+
+use std::collections::HashSet;
+use std::f64::consts::PI;
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::{HugrView, IncomingPort, Node, OutgoingPort, PortIndex};
+use itertools::Itertools;
+use num_complex::Complex64;
+
+use super::cx_cancellation::wire_successors;
+use crate::circuit::phase::GlobalPhase;
+use crate::ops::op_commutation;
+use crate::sim::{gate_matrix, matmul};
+use crate::utils::TOLERANCE;
+use crate::utils::{constant_params, remove_unused_param};
+use crate::{Circuit, Tk2Op};
+
+/// The changes made by [`remove_redundancies`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RedundancyReport {
+    /// The number of gates removed for implementing the identity.
+    pub identities: usize,
+    /// The number of pairs of adjacent inverse gates removed.
+    pub cancelled_pairs: usize,
+}
+
+/// Remove redundant gates from a circuit, until none remain.
+///
+/// Each round removes the gates implementing the identity up to a global
+/// phase, then the pairs of adjacent gates whose product is the identity up
+/// to a global phase. A pair is adjacent if each qubit output of the first
+/// gate is connected to the input at the same port of the second. Removing a
+/// pair may make other gates adjacent, so the rounds are repeated until no
+/// gate is removed.
+///
+/// Only [`Tk2Op`] gates with constant angles are considered, and frozen gates
+/// are never removed. The global phase of the removed gates is added to the
+/// circuit's [global phase](Circuit::global_phase).
+pub fn remove_redundancies(circ: &mut Circuit) -> RedundancyReport {
+    let mut report = RedundancyReport::default();
+    loop {
+        let identities = remove_identities(circ);
+        let cancelled_pairs = cancel_inverse_pairs(circ);
+        if identities == 0 && cancelled_pairs == 0 {
+            break;
+        }
+        report.identities += identities;
+        report.cancelled_pairs += cancelled_pairs;
+    }
+    report
+}
+
+/// Remove the gates implementing the identity up to a global phase. Returns
+/// the number of removed gates.
+fn remove_identities(circ: &mut Circuit) -> usize {
+    let mut removed = 0;
+    for node in gate_nodes(circ) {
+        let Some(phase) = gate_matrix_at(circ, node).and_then(|m| identity_phase(&m)) else {
+            continue;
+        };
+        remove_gates(circ, &[node]);
+        circ.add_global_phase(Some(GlobalPhase::new(phase / PI)));
+        removed += 1;
+    }
+    removed
+}
+
+/// Remove the pairs of adjacent gates whose product is the identity up to a
+/// global phase. Returns the number of removed pairs.
+fn cancel_inverse_pairs(circ: &mut Circuit) -> usize {
+    let mut removed = HashSet::new();
+    for first in gate_nodes(circ) {
+        if removed.contains(&first) || !circ.hugr().valid_node(first) {
+            continue;
+        }
+        let Some(second) = adjacent_successor(circ, first) else {
+            continue;
+        };
+        let Some(phase) = gate_matrix_at(circ, first)
+            .zip(gate_matrix_at(circ, second))
+            .and_then(|(a, b)| identity_phase(&matmul(&b, &a)))
+        else {
+            continue;
+        };
+        remove_gates(circ, &[first, second]);
+        circ.add_global_phase(Some(GlobalPhase::new(phase / PI)));
+        removed.extend([first, second]);
+    }
+    removed.len() / 2
+}
+
+/// Cancel the pairs of inverse gates separated by gates they commute with,
+/// until none remain.
+///
+/// A gate is moved forward past the gates acting on each of its qubits in the
+/// same Pauli basis, as given by their [commutation](op_commutation), such as
+/// a Z gate on the control qubit of a CX. If it reaches a gate acting on the
+/// same qubits at the same ports, whose product with it is the identity up to
+/// a global phase, both gates are removed. This includes the adjacent pairs
+/// removed by [`remove_redundancies`].
+///
+/// Only [`Tk2Op`] gates with constant angles are cancelled, and frozen gates
+/// are neither removed nor commuted through. The global phase of the removed
+/// gates is added to the circuit's [global phase](Circuit::global_phase).
+///
+/// Returns the number of cancelled pairs.
+pub fn cancel_commuting_inverses(circ: &mut Circuit) -> usize {
+    let mut cancelled = 0;
+    loop {
+        let round = cancel_commuting_pairs(circ);
+        if round == 0 {
+            break;
+        }
+        cancelled += round;
+    }
+    cancelled
+}
+
+/// A single pass over the gates of the circuit, cancelling the inverse pairs
+/// separated by commuting gates. Returns the number of cancelled pairs.
+fn cancel_commuting_pairs(circ: &mut Circuit) -> usize {
+    let mut removed = HashSet::new();
+    for first in gate_nodes(circ) {
+        if removed.contains(&first) || !circ.hugr().valid_node(first) {
+            continue;
+        }
+        let Some((second, phase)) = find_commuting_inverse(circ, first) else {
+            continue;
+        };
+        remove_gates(circ, &[first]);
+        remove_gates(circ, &[second]);
+        circ.add_global_phase(Some(GlobalPhase::new(phase / PI)));
+        removed.extend([first, second]);
+    }
+    removed.len() / 2
+}
+
+/// Find the first gate that `node` can be commuted to and that cancels with
+/// it, along with the global phase of the pair in radians.
+fn find_commuting_inverse(circ: &Circuit, node: Node) -> Option<(Node, f64)> {
+    let matrix = gate_matrix_at(circ, node)?;
+    let n = num_qubits(circ, node);
+    let commutation = op_commutation(circ.hugr(), node).unwrap_or_default();
+    let basis = |port: usize| {
+        commutation
+            .iter()
+            .find(|&&(p, _)| p == port)
+            .map(|&(_, pauli)| pauli)
+    };
+    // The gates reachable on each qubit but the first, at the same port.
+    let reachable = (1..n)
+        .map(|i| {
+            wire_successors(circ, node, i, basis(i))
+                .filter(|&(_, port)| port.index() == i)
+                .map(|(next, _)| next)
+                .collect::<HashSet<_>>()
+        })
+        .collect_vec();
+    wire_successors(circ, node, 0, basis(0))
+        .filter(|&(next, port)| {
+            port.index() == 0
+                && num_qubits(circ, next) == n
+                && reachable.iter().all(|gates| gates.contains(&next))
+        })
+        .find_map(|(next, _)| {
+            let phase = identity_phase(&matmul(&gate_matrix_at(circ, next)?, &matrix))?;
+            Some((next, phase))
+        })
+}
+
+/// The gates of the circuit, in topological order.
+fn gate_nodes(circ: &Circuit) -> Vec<Node> {
+    circ.commands()
+        .filter(|cmd| Tk2Op::try_from(cmd.optype()).is_ok())
+        .map(|cmd| cmd.node())
+        .collect_vec()
+}
+
+/// The number of qubits of a gate.
+fn num_qubits(circ: &Circuit, node: Node) -> usize {
+    circ.hugr()
+        .signature(node)
+        .map_or(0, |sig| sig.output_count())
+}
+
+/// The unitary matrix of a gate, if it is not frozen and all its angles are
+/// constant.
+///
+/// Returns `None` for nodes removed along with the parameters of a gate.
+fn gate_matrix_at(circ: &Circuit, node: Node) -> Option<Vec<Complex64>> {
+    if !circ.hugr().valid_node(node) || circ.is_frozen(node) {
+        return None;
+    }
+    let op = Tk2Op::try_from(circ.hugr().get_optype(node)).ok()?;
+    let params = constant_params(circ.hugr(), node)?;
+    gate_matrix(op, &params)
+}
+
+/// The gate following `node` on all of its qubits, at the same ports.
+fn adjacent_successor(circ: &Circuit, node: Node) -> Option<Node> {
+    let hugr = circ.hugr();
+    let n = num_qubits(circ, node);
+    let (next, _) = hugr.single_linked_input(node, 0)?;
+    let adjacent = n == num_qubits(circ, next)
+        && (0..n).all(|i| hugr.single_linked_input(node, i) == Some((next, i.into())));
+    adjacent.then_some(next)
+}
+
+/// If the matrix is the identity up to a global phase, returns the phase in
+/// radians.
+fn identity_phase(matrix: &[Complex64]) -> Option<f64> {
+    let dim = (matrix.len() as f64).sqrt() as usize;
+    let phase = matrix[0];
+    let is_identity = (phase.norm() - 1.).abs() < TOLERANCE
+        && (0..dim).cartesian_product(0..dim).all(|(row, col)| {
+            let expected = if row == col {
+                phase
+            } else {
+                Complex64::new(0., 0.)
+            };
+            (matrix[row * dim + col] - expected).norm() < TOLERANCE
+        });
+    is_identity.then(|| phase.arg())
+}
+
+/// Remove a sequence of adjacent gates on the same qubits, connecting the
+/// qubit inputs of the first gate to the qubit outputs of the last one.
+fn remove_gates(circ: &mut Circuit, gates: &[Node]) {
+    let (first, last) = (gates[0], gates[gates.len() - 1]);
+    let n = num_qubits(circ, first);
+    let hugr = circ.hugr_mut();
+    let links = (0..n)
+        .map(|i| {
+            (
+                hugr.single_linked_output(first, IncomingPort::from(i))
+                    .unwrap(),
+                hugr.single_linked_input(last, OutgoingPort::from(i))
+                    .unwrap(),
+            )
+        })
+        .collect_vec();
+    let param_sources = gates
+        .iter()
+        .flat_map(|&gate| hugr.input_neighbours(gate).collect_vec())
+        .filter(|src| !gates.contains(src))
+        .unique()
+        .collect_vec();
+    for &gate in gates {
+        hugr.remove_node(gate);
+    }
+    for ((src, src_port), (dst, dst_port)) in links {
+        hugr.connect(src, src_port, dst, dst_port);
+    }
+    for src in param_sources {
+        remove_unused_param(hugr, src);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::extension::prelude::QB_T;
+    use hugr::std_extensions::arithmetic::float_types::{self, ConstF64};
+    use hugr::types::Signature;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::sim::unitary::equal_up_to_phase;
+    use crate::utils::test::{circuit_from_gates, gates};
+
+    /// A circuit of rotations by constant angles, in radians.
+    fn rotations(rotations: &[(Tk2Op, usize, f64)]) -> Circuit {
+        let mut h = DFGBuilder::new(
+            Signature::new(vec![QB_T, QB_T], vec![QB_T, QB_T])
+                .with_extension_delta(float_types::EXTENSION_ID),
+        )
+        .unwrap();
+        let mut qbs = h.input_wires().collect_vec();
+        for &(op, q, angle) in rotations {
+            let angle = h.add_load_value(ConstF64::new(angle));
+            qbs[q] = h.add_dataflow_op(op, [qbs[q], angle]).unwrap().out_wire(0);
+        }
+        h.finish_hugr_with_outputs(qbs, &REGISTRY).unwrap().into()
+    }
+
+    #[rstest]
+    #[case::self_inverse(&[(Tk2Op::H, vec![0]), (Tk2Op::H, vec![0])], vec![], 1)]
+    #[case::dagger(&[(Tk2Op::S, vec![1]), (Tk2Op::Sdg, vec![1])], vec![], 1)]
+    #[case::two_qubit(&[(Tk2Op::CX, vec![0, 1]), (Tk2Op::CX, vec![0, 1])], vec![], 1)]
+    #[case::three_qubit(&[(Tk2Op::CCX, vec![0, 1, 2]), (Tk2Op::CCX, vec![0, 1, 2])], vec![], 1)]
+    #[case::nested(
+        &[(Tk2Op::T, vec![0]), (Tk2Op::CZ, vec![0, 1]), (Tk2Op::CZ, vec![0, 1]), (Tk2Op::Tdg, vec![0])],
+        vec![],
+        2,
+    )]
+    #[case::flipped_qubits(
+        &[(Tk2Op::CX, vec![0, 1]), (Tk2Op::CX, vec![1, 0])],
+        vec![Tk2Op::CX, Tk2Op::CX],
+        0,
+    )]
+    #[case::not_inverse(&[(Tk2Op::S, vec![0]), (Tk2Op::S, vec![0])], vec![Tk2Op::S, Tk2Op::S], 0)]
+    fn cancel_pairs(
+        #[case] ops: &[(Tk2Op, Vec<usize>)],
+        #[case] expected: Vec<Tk2Op>,
+        #[case] cancelled_pairs: usize,
+    ) {
+        let mut circ = circuit_from_gates(3, ops);
+        let unitary = circ.unitary().unwrap();
+
+        let report = remove_redundancies(&mut circ);
+        assert_eq!(report.cancelled_pairs, cancelled_pairs);
+        assert_eq!(gates(&circ), expected);
+        assert!(equal_up_to_phase(&circ.unitary().unwrap(), &unitary, 1e-10));
+    }
+
+    #[test]
+    fn identities() {
+        let mut circ = rotations(&[
+            (Tk2Op::RzF64, 0, 0.),
+            (Tk2Op::RxF64, 1, 0.5),
+            (Tk2Op::RzF64, 1, 2. * PI),
+        ]);
+        let n_nodes = circ.hugr().node_count();
+
+        let report = remove_redundancies(&mut circ);
+        assert_eq!(report.identities, 2);
+        assert_eq!(gates(&circ), [Tk2Op::RxF64]);
+        // Rz(2π) = -I
+        assert_eq!(circ.global_phase(), Some(GlobalPhase::new(1.)));
+        // The angle constants of the removed gates are removed too.
+        assert_eq!(circ.hugr().node_count(), n_nodes - 6);
+        circ.hugr().validate(&REGISTRY).unwrap();
+    }
+
+    #[test]
+    fn inverse_rotations() {
+        let mut circ = rotations(&[
+            (Tk2Op::RzF64, 0, 0.3),
+            (Tk2Op::RzF64, 0, -0.3),
+            (Tk2Op::RxF64, 1, 0.5),
+        ]);
+
+        let report = remove_redundancies(&mut circ);
+        assert_eq!(report.cancelled_pairs, 1);
+        assert_eq!(gates(&circ), [Tk2Op::RxF64]);
+        assert_eq!(circ.global_phase(), Some(GlobalPhase::new(0.)));
+        circ.hugr().validate(&REGISTRY).unwrap();
+    }
+
+    #[rstest]
+    #[case::z_on_control(
+        &[(Tk2Op::CX, vec![0, 1]), (Tk2Op::Z, vec![0]), (Tk2Op::CX, vec![0, 1])],
+        vec![Tk2Op::Z],
+        1,
+    )]
+    #[case::x_on_target(
+        &[(Tk2Op::CX, vec![0, 1]), (Tk2Op::X, vec![1]), (Tk2Op::CX, vec![0, 1])],
+        vec![Tk2Op::X],
+        1,
+    )]
+    #[case::through_cz(
+        &[(Tk2Op::S, vec![0]), (Tk2Op::CZ, vec![0, 1]), (Tk2Op::T, vec![0]), (Tk2Op::Sdg, vec![0])],
+        vec![Tk2Op::CZ, Tk2Op::T],
+        1,
+    )]
+    #[case::shared_control(
+        &[(Tk2Op::CX, vec![0, 1]), (Tk2Op::CX, vec![0, 2]), (Tk2Op::CX, vec![0, 1])],
+        vec![Tk2Op::CX],
+        1,
+    )]
+    #[case::x_on_control(
+        &[(Tk2Op::CX, vec![0, 1]), (Tk2Op::X, vec![0]), (Tk2Op::CX, vec![0, 1])],
+        vec![Tk2Op::CX, Tk2Op::X, Tk2Op::CX],
+        0,
+    )]
+    #[case::h_in_between(
+        &[(Tk2Op::T, vec![0]), (Tk2Op::H, vec![0]), (Tk2Op::Tdg, vec![0])],
+        vec![Tk2Op::T, Tk2Op::H, Tk2Op::Tdg],
+        0,
+    )]
+    fn commuting_inverses(
+        #[case] ops: &[(Tk2Op, Vec<usize>)],
+        #[case] expected: Vec<Tk2Op>,
+        #[case] cancelled_pairs: usize,
+    ) {
+        let mut circ = circuit_from_gates(3, ops);
+        let unitary = circ.unitary().unwrap();
+
+        assert_eq!(cancel_commuting_inverses(&mut circ), cancelled_pairs);
+        assert_eq!(gates(&circ), expected);
+        assert!(equal_up_to_phase(&circ.unitary().unwrap(), &unitary, 1e-10));
+    }
+
+    #[test]
+    fn commuting_rotations() {
+        let mut circ = rotations(&[
+            (Tk2Op::RzF64, 0, 0.3),
+            (Tk2Op::RzF64, 0, 0.5),
+            (Tk2Op::RzF64, 0, -0.3),
+        ]);
+        let unitary = circ.unitary().unwrap();
+
+        assert_eq!(cancel_commuting_inverses(&mut circ), 1);
+        assert_eq!(gates(&circ), [Tk2Op::RzF64]);
+        assert!(equal_up_to_phase(&circ.unitary().unwrap(), &unitary, 1e-10));
+        circ.hugr().validate(&REGISTRY).unwrap();
+    }
+
+    #[test]
+    fn frozen_gates() {
+        let mut circ = circuit_from_gates(3, &[(Tk2Op::H, vec![0]), (Tk2Op::H, vec![0])]);
+        let first = circ.commands().next().unwrap().node();
+        circ.freeze_nodes([first]);
+
+        let report = remove_redundancies(&mut circ);
+        assert_eq!(report, RedundancyReport::default());
+        assert_eq!(gates(&circ), [Tk2Op::H, Tk2Op::H]);
+    }
+}
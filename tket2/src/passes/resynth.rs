@@ -0,0 +1,406 @@
+//! Peephole resynthesis of small blocks of gates.
+//!
+//! [`peephole`] partitions a circuit into blocks of consecutive gates acting
+//! on a few qubits, computes the unitary of each block, and replaces it with
+//! the circuit produced by [`synthesize_unitary`] when that is cheaper.
+
+use std::collections::{HashMap, HashSet};
+use std::iter::Sum;
+
+use hugr::builder::{BuildError, DFGBuilder, Dataflow, DataflowHugr};
+use hugr::ops::OpType;
+use hugr::std_extensions::arithmetic::float_types::ConstF64;
+use hugr::{CircuitUnit, Hugr, HugrView, Node};
+use itertools::Itertools;
+use ndarray::Array2;
+use num_complex::Complex64;
+
+use crate::circuit::extract::ExtractedSubcircuit;
+use crate::circuit::units::{LinearUnit, Qubit};
+use crate::extension::REGISTRY;
+use crate::sim::{gate_matrix, StateVector};
+use crate::synthesis::{synthesize_unitary, SynthesisBasis, SynthesisError, MAX_SYNTHESIS_QUBITS};
+use crate::utils::{constant_params, remove_unused_param};
+use crate::{Circuit, Tk2Op};
+
+/// The changes made by [`peephole`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PeepholeReport {
+    /// The number of blocks of gates considered for resynthesis.
+    pub blocks: usize,
+    /// The number of blocks replaced by a cheaper circuit.
+    pub replaced_blocks: usize,
+}
+
+/// Resynthesise blocks of gates acting on at most `block_qubits` qubits.
+///
+/// The commands of the circuit are scanned in order, greedily growing blocks
+/// of gates while they act on at most `block_qubits` qubits. Each block acting
+/// on at least two qubits is resynthesised with [`synthesize_unitary`] in the
+/// default [`SynthesisBasis`], and replaced if the new gates have a lower
+/// total cost according to `cost`. The global phase of the circuit is updated
+/// accordingly.
+///
+/// Only gates with constant angles can be part of a block. Rotations by
+/// symbolic angles, non-unitary operations and frozen gates end the blocks on
+/// their qubits.
+///
+/// # Errors
+///
+/// Returns an error if `block_qubits` is not between two and
+/// [`MAX_SYNTHESIS_QUBITS`].
+pub fn peephole<C: Sum + Ord>(
+    circ: &mut Circuit,
+    block_qubits: usize,
+    cost: impl Fn(&OpType) -> C,
+) -> Result<PeepholeReport, SynthesisError> {
+    if !(2..=MAX_SYNTHESIS_QUBITS).contains(&block_qubits) {
+        return Err(SynthesisError::InvalidBlockSize {
+            qubits: block_qubits,
+            max: MAX_SYNTHESIS_QUBITS,
+        });
+    }
+    let mut report = PeepholeReport::default();
+    for block in collect_blocks(circ, block_qubits) {
+        if block.units.len() < 2 || block.gates.len() < 2 {
+            continue;
+        }
+        report.blocks += 1;
+        let Ok(synthesised) = synthesize_unitary(&block.unitary(), SynthesisBasis::default())
+        else {
+            continue;
+        };
+        let nodes = block.gates.iter().map(|gate| gate.node).collect_vec();
+        let old_cost: C = circ.nodes_cost(nodes.iter().copied(), &cost);
+        let new_cost: C = synthesised
+            .commands()
+            .filter(|cmd| Tk2Op::try_from(cmd.optype()).is_ok())
+            .map(|cmd| cost(cmd.optype()))
+            .sum();
+        if new_cost >= old_cost {
+            continue;
+        }
+        replace_block(circ, &nodes, &block.units, &synthesised);
+        report.replaced_blocks += 1;
+    }
+    Ok(report)
+}
+
+/// A gate with constant parameters, as part of a block.
+#[derive(Debug, Clone)]
+struct BlockGate {
+    node: Node,
+    /// The unitary of the gate, in row-major order.
+    matrix: Vec<Complex64>,
+    /// The qubits of the gate.
+    units: Vec<LinearUnit>,
+}
+
+/// A convex set of gates acting on a few qubits.
+#[derive(Debug, Clone, Default)]
+struct Block {
+    /// The qubits of the block, in the order used for its unitary.
+    units: Vec<LinearUnit>,
+    /// The gates of the block, in topological order.
+    gates: Vec<BlockGate>,
+}
+
+impl Block {
+    /// The unitary implemented by the gates of the block.
+    ///
+    /// Qubit `i` of the block corresponds to `self.units[i]`.
+    fn unitary(&self) -> Array2<Complex64> {
+        let num_qubits = self.units.len();
+        let index: HashMap<LinearUnit, usize> = self
+            .units
+            .iter()
+            .enumerate()
+            .map(|(i, &unit)| (unit, i))
+            .collect();
+        let dim = 1 << num_qubits;
+        let mut matrix = Array2::zeros((dim, dim));
+        for (j, mut column) in matrix.columns_mut().into_iter().enumerate() {
+            let mut state = StateVector::basis_state(num_qubits, j);
+            for gate in &self.gates {
+                let qubits = gate
+                    .units
+                    .iter()
+                    .map(|unit| Qubit::new(index[unit]))
+                    .collect_vec();
+                state.apply_matrix(&gate.matrix, &qubits);
+            }
+            column.assign(&ndarray::aview1(state.amplitudes()));
+        }
+        matrix
+    }
+}
+
+/// Greedily partition the gates of a circuit into blocks acting on at most
+/// `block_qubits` qubits.
+///
+/// A gate joins the open blocks on its qubits if they act on at most
+/// `block_qubits` qubits together. Otherwise, these blocks are closed and a
+/// new one is started. Since an open block contains every gate applied to its
+/// qubits since it started, the blocks are convex.
+fn collect_blocks(circ: &Circuit, block_qubits: usize) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut open: HashMap<LinearUnit, usize> = HashMap::new();
+    for cmd in circ.commands() {
+        let units = cmd.input_qubits().map(|(unit, _, _)| unit).collect_vec();
+        if units.is_empty() {
+            continue;
+        }
+        let ids: HashSet<usize> = units.iter().filter_map(|u| open.get(u).copied()).collect();
+        let Some(gate) = block_gate(circ, cmd.node(), &units) else {
+            for unit in ids.iter().flat_map(|&id| &blocks[id].units) {
+                open.remove(unit);
+            }
+            continue;
+        };
+        let merged_units: Vec<LinearUnit> = ids
+            .iter()
+            .sorted()
+            .flat_map(|&id| blocks[id].units.iter().copied())
+            .chain(units.iter().copied())
+            .unique()
+            .collect();
+
+        let mut block = Block::default();
+        if merged_units.len() <= block_qubits {
+            // Gates of different open blocks act on disjoint qubits, so they
+            // commute and can be concatenated.
+            for &id in ids.iter().sorted() {
+                let old = std::mem::take(&mut blocks[id]);
+                block.gates.extend(old.gates);
+            }
+            block.units = merged_units;
+        } else {
+            for unit in ids.iter().flat_map(|&id| &blocks[id].units) {
+                open.remove(unit);
+            }
+            if units.len() > block_qubits {
+                continue;
+            }
+            block.units = units;
+        }
+        block.gates.push(gate);
+        let id = blocks.len();
+        for &unit in &block.units {
+            open.insert(unit, id);
+        }
+        blocks.push(block);
+    }
+    blocks.retain(|block| !block.gates.is_empty());
+    blocks
+}
+
+/// The gate at a node, if it can be part of a block.
+fn block_gate(circ: &Circuit, node: Node, units: &[LinearUnit]) -> Option<BlockGate> {
+    if circ.is_frozen(node) {
+        return None;
+    }
+    let op = Tk2Op::try_from(circ.hugr().get_optype(node)).ok()?;
+    let params = constant_params(circ.hugr(), node)?;
+    let matrix = gate_matrix(op, &params)?;
+    Some(BlockGate {
+        node,
+        matrix,
+        units: units.to_vec(),
+    })
+}
+
+/// Replace the gates of a block with a synthesised circuit on its qubits.
+fn replace_block(circ: &mut Circuit, nodes: &[Node], units: &[LinearUnit], synthesised: &Circuit) {
+    let extracted = circ
+        .extract_subcircuit(nodes.iter().copied())
+        .expect("Blocks are convex.");
+    let params: Vec<Node> = nodes
+        .iter()
+        .flat_map(|&node| circ.hugr().input_neighbours(node))
+        .filter(|node| !nodes.contains(node))
+        .unique()
+        .collect();
+    let replacement = replacement_circuit(&extracted, units, synthesised)
+        .expect("The replacement has the signature of the block.");
+    extracted
+        .subcircuit
+        .create_rewrite(circ, replacement)
+        .expect("The replacement has the signature of the block.")
+        .apply(circ)
+        .expect("Replacing a block is a valid rewrite.");
+    for param in params {
+        remove_unused_param(circ.hugr_mut(), param);
+    }
+    circ.add_global_phase(synthesised.global_phase());
+}
+
+/// Copy the gates of a synthesised circuit into a circuit with the signature
+/// of an extracted block.
+///
+/// The angle inputs of the block are left unused, since the synthesised gates
+/// have constant angles.
+fn replacement_circuit(
+    extracted: &ExtractedSubcircuit,
+    units: &[LinearUnit],
+    synthesised: &Circuit,
+) -> Result<Circuit, BuildError> {
+    let position = |unit: &Option<LinearUnit>| units.iter().position(|u| Some(*u) == *unit);
+    let mut h = DFGBuilder::new(extracted.circuit.circuit_signature())?;
+    let inputs = h.input_wires().collect_vec();
+    let qubits = (0..units.len()).map(|k| {
+        let input = extracted
+            .input_units
+            .iter()
+            .position(|unit| position(unit) == Some(k))
+            .unwrap();
+        inputs[input]
+    });
+    let mut c = h.as_circuit(qubits);
+    for cmd in synthesised.commands() {
+        let Ok(op) = Tk2Op::try_from(cmd.optype()) else {
+            continue;
+        };
+        let qubits = cmd
+            .input_qubits()
+            .map(|(unit, _, _)| CircuitUnit::Linear(unit.index()))
+            .collect_vec();
+        let params = constant_params(synthesised.hugr(), cmd.node())
+            .unwrap()
+            .into_iter()
+            .map(|angle| CircuitUnit::Wire(c.add_constant(ConstF64::new(angle))))
+            .collect_vec();
+        c.append_and_consume(op, qubits.into_iter().chain(params))?;
+    }
+    let wires = c.finish();
+    let outputs = extracted
+        .output_units
+        .iter()
+        .map(|unit| wires[position(unit).unwrap()]);
+    let hugr: Hugr = h.finish_hugr_with_outputs(outputs, &REGISTRY)?;
+    Ok(hugr.into())
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use rstest::rstest;
+
+    use super::*;
+    use crate::circuit::cost::is_cx;
+    use crate::extension::REGISTRY;
+    use crate::utils::test::tk1_circuit;
+    /// The unitary of a circuit, including its global phase.
+    fn circuit_unitary(circ: &Circuit) -> Array2<Complex64> {
+        let phase = circ.global_phase().map_or(0., |p| p.constant()) * PI;
+        circ.unitary()
+            .unwrap()
+            .mapv(|x| x * Complex64::from_polar(1., phase))
+    }
+
+    fn cx_count(circ: &Circuit) -> usize {
+        circ.circuit_cost(|op| is_cx(op) as usize)
+    }
+
+    fn gate(op: &str, qubits: &[usize], params: &[&str]) -> String {
+        let args = qubits.iter().map(|q| format!(r#"["q", [{q}]]"#)).join(", ");
+        let params = params.iter().map(|p| format!(r#""{p}""#)).join(", ");
+        format!(r#"{{"args": [{args}], "op": {{"type": "{op}", "params": [{params}]}}}}"#)
+    }
+
+    #[rstest]
+    #[case::two_qubits(2, 2, 3)]
+    #[case::three_qubits(3, 3, 24)]
+    fn resynthesise_blocks(
+        #[case] num_qubits: usize,
+        #[case] block_qubits: usize,
+        #[case] max_cx: usize,
+    ) {
+        // A long sequence of gates on the same qubits.
+        let commands = (0..30)
+            .map(|i| {
+                let (a, b) = (i % num_qubits, (i + 1) % num_qubits);
+                [
+                    gate("CX", &[a, b], &[]),
+                    gate("Rz", &[b], &[&format!("0.{}", i + 1)]),
+                    gate("H", &[a], &[]),
+                ]
+                .join(", ")
+            })
+            .join(", ");
+        let mut circ = tk1_circuit(num_qubits, 0, &commands);
+        let expected = circuit_unitary(&circ);
+
+        let report = peephole(&mut circ, block_qubits, |op| is_cx(op) as usize).unwrap();
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.blocks, 1);
+        assert_eq!(report.replaced_blocks, 1);
+        assert!(cx_count(&circ) <= max_cx);
+        let diff = (circuit_unitary(&circ) - expected)
+            .iter()
+            .map(|x| x.norm())
+            .fold(0., f64::max);
+        assert!(diff < 1e-8, "{diff}");
+    }
+
+    #[test]
+    fn blocks_split_by_barriers() {
+        // The symbolic rotation on qubit 1 splits the gates on qubits 0 and 1
+        // into two blocks, and the gates on qubits 1 and 2 are too wide to
+        // join the second one.
+        let commands = [
+            gate("CX", &[0, 1], &[]),
+            gate("CX", &[0, 1], &[]),
+            gate("Rz", &[1], &["a"]),
+            gate("CX", &[1, 0], &[]),
+            gate("CX", &[1, 0], &[]),
+            gate("CX", &[1, 2], &[]),
+            gate("CX", &[1, 2], &[]),
+        ]
+        .join(", ");
+        let mut circ = tk1_circuit(3, 0, &commands);
+
+        let report = peephole(&mut circ, 2, |op| is_cx(op) as usize).unwrap();
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        // Each block implements the identity.
+        assert_eq!(report.blocks, 3);
+        assert_eq!(report.replaced_blocks, 3);
+        let gates = circ
+            .commands()
+            .filter_map(|cmd| Tk2Op::try_from(cmd.optype()).ok())
+            .collect_vec();
+        assert_eq!(gates, [Tk2Op::RzF64]);
+    }
+
+    #[test]
+    fn keep_cheaper_blocks() {
+        let commands = [
+            gate("H", &[0], &[]),
+            gate("CX", &[0, 1], &[]),
+            gate("T", &[1], &[]),
+        ]
+        .join(", ");
+        let mut circ = tk1_circuit(2, 0, &commands);
+        let before = circ.clone();
+        let report = peephole(&mut circ, 2, |op| is_cx(op) as usize).unwrap();
+        assert_eq!(report.blocks, 1);
+        assert_eq!(report.replaced_blocks, 0);
+        assert_eq!(circ.num_operations(), before.num_operations());
+    }
+
+    #[rstest]
+    #[case(1)]
+    #[case(4)]
+    fn invalid_block_size(#[case] block_qubits: usize) {
+        let mut circ = tk1_circuit(2, 0, &gate("CX", &[0, 1], &[]));
+        assert_eq!(
+            peephole(&mut circ, block_qubits, |op| is_cx(op) as usize),
+            Err(SynthesisError::InvalidBlockSize {
+                qubits: block_qubits,
+                max: MAX_SYNTHESIS_QUBITS
+            })
+        );
+    }
+}
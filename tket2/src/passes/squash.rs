@@ -0,0 +1,630 @@
+//! Squashing of single-qubit gates into Euler-angle rotations.
+//!
+//! [`squash_single_qubit_gates`] finds the maximal runs of single-qubit gates
+//! on each qubit and resynthesises each of them as at most three rotations
+//! around the axes of an [`EulerBasis`].
+
+use std::collections::HashMap;
+use std::f64::consts::{FRAC_PI_2, PI, TAU};
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::ops::{Const, LoadConstant};
+use hugr::std_extensions::arithmetic::float_types::{ConstF64, FLOAT64_TYPE};
+use hugr::{HugrView, Node, Wire};
+use itertools::Itertools;
+use num_complex::Complex64;
+use num_rational::Rational64;
+
+use crate::circuit::phase::GlobalPhase;
+use crate::ops::match_symbolic_expr;
+use crate::sim::unitary::relative_phase;
+use crate::sim::{gate_matrix, matmul};
+use crate::utils::TOLERANCE;
+use crate::utils::{float_wire_value, remove_unused_param};
+use crate::{symbolic_expr_op, Circuit, Pauli, SymbolicExpr, Tk2Op};
+
+/// The largest denominator of a constant angle folded into a symbolic
+/// expression, as a multiple of π.
+const MAX_SYMBOLIC_DENOM: i64 = 1 << 10;
+
+/// The rotation axes used by [`squash_single_qubit_gates`].
+///
+/// Each basis `ABA` resynthesises a run of gates as `Ra(α) Rb(β) Ra(γ)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum EulerBasis {
+    /// Z, Y and Z rotations. The Y rotations are emitted as
+    /// [`Tk2Op::PhasedX`] gates with a phase of π/2.
+    ZYZ,
+    /// X, Z and X rotations.
+    XZX,
+    /// Z, X and Z rotations, as in a [`Tk2Op::TK1`] gate.
+    #[default]
+    ZXZ,
+}
+
+/// The changes made by [`squash_single_qubit_gates`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SquashReport {
+    /// The number of runs of gates that were replaced.
+    pub squashed_runs: usize,
+    /// The number of gates removed from the circuit.
+    pub removed_gates: usize,
+}
+
+/// Squash the runs of single-qubit gates in a circuit into Euler-angle
+/// rotations.
+///
+/// Each maximal run of consecutive single-qubit gates on a qubit is replaced
+/// by at most three rotations in the given `basis`, when that reduces the
+/// number of gates. Runs whose angles are all known are resynthesised from
+/// their unitary. Runs of rotations around a single axis with symbolic angles
+/// are merged into one rotation. The symbolic constants and rational
+/// multiples of π in its angle are combined into a single simplified
+/// [`SymbolicExpr`], and other angles are summed with [`Tk2Op::AngleAdd`]
+/// operations. Merged rotations whose symbols cancel out are removed. In other
+/// runs, only the gates between the symbolic rotations are squashed.
+///
+/// The global phase of the circuit is updated with the phase introduced by
+/// the resynthesis. Frozen gates are left untouched, and split the runs.
+pub fn squash_single_qubit_gates(circ: &mut Circuit, basis: EulerBasis) -> SquashReport {
+    let mut report = SquashReport::default();
+    let mut phase = 0.;
+    for run in single_qubit_runs(circ) {
+        for (nodes, squashed) in squash_run(&run, basis) {
+            if squashed.gates.len() >= nodes.len() {
+                continue;
+            }
+            report.squashed_runs += 1;
+            report.removed_gates += nodes.len() - squashed.gates.len();
+            phase += squashed.phase;
+            replace_run(circ, &nodes, &squashed.gates);
+        }
+    }
+    if normalise(phase).abs() > TOLERANCE {
+        circ.add_global_phase(Some(GlobalPhase::new(phase / PI)));
+    }
+    report
+}
+
+/// The effect of a single-qubit gate on its qubit.
+#[derive(Debug, Clone)]
+enum Rotation {
+    /// A gate with known parameters, and its unitary.
+    Fixed(Vec<Complex64>),
+    /// A rotation around the Z or X axis, by an angle that is not known at
+    /// compile time.
+    Symbolic(Pauli, Angle),
+}
+
+/// An angle parameter, as a sum of wires, a symbolic expression in
+/// half-turns and a constant in radians.
+#[derive(Debug, Clone, Default)]
+struct Angle {
+    wires: Vec<Wire>,
+    symbol: SymbolicExpr,
+    value: f64,
+}
+
+impl Angle {
+    /// The angle carried by a wire, reading symbolic constants.
+    fn from_wire(hugr: &impl HugrView, wire: Wire) -> Self {
+        match match_symbolic_expr(hugr.get_optype(wire.node())) {
+            Some(symbol) => Self {
+                symbol,
+                ..Self::default()
+            },
+            None => Self {
+                wires: vec![wire],
+                ..Self::default()
+            },
+        }
+    }
+
+    /// Move the constant part of the symbolic expression into the value. If
+    /// the angle is still symbolic, fold the value into the expression when it
+    /// is a rational multiple of π.
+    fn simplify(mut self) -> Self {
+        let constant = self.symbol.constant_term();
+        self.symbol = self.symbol - SymbolicExpr::constant(constant);
+        self.value += *constant.numer() as f64 / *constant.denom() as f64 * PI;
+        if !self.symbol.is_zero() {
+            let half_turns = Rational64::approximate_float(self.value / PI)
+                .filter(|r| *r.denom() <= MAX_SYMBOLIC_DENOM)
+                .filter(|r| {
+                    (*r.numer() as f64 / *r.denom() as f64 * PI - self.value).abs() < TOLERANCE
+                });
+            if let Some(half_turns) = half_turns {
+                self.symbol = self.symbol + SymbolicExpr::constant(half_turns);
+                self.value = 0.;
+            }
+        }
+        self
+    }
+
+    /// Returns `true` if the angle is a constant zero.
+    ///
+    /// Unlike [`is_zero_angle`], multiples of `2π` are not considered zero.
+    fn is_zero(&self) -> bool {
+        self.wires.is_empty() && self.symbol.is_zero() && self.value.abs() < TOLERANCE
+    }
+}
+
+impl From<f64> for Angle {
+    fn from(value: f64) -> Self {
+        Self {
+            value,
+            ..Self::default()
+        }
+    }
+}
+
+impl std::ops::Add for Angle {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self {
+        self.wires.extend(rhs.wires);
+        Self {
+            wires: self.wires,
+            symbol: self.symbol + rhs.symbol,
+            value: self.value + rhs.value,
+        }
+    }
+}
+
+/// The gates replacing a run, and the global phase they introduce.
+#[derive(Debug, Clone, Default)]
+struct Squashed {
+    gates: Vec<(Tk2Op, Vec<Angle>)>,
+    /// The phase of the original run relative to the new gates, in radians.
+    phase: f64,
+}
+
+/// The maximal runs of unfrozen single-qubit gates on each qubit, with their
+/// rotations, in topological order.
+fn single_qubit_runs(circ: &Circuit) -> Vec<Vec<(Node, Rotation)>> {
+    let hugr = circ.hugr();
+    let nodes: Vec<Node> = circ.commands().map(|cmd| cmd.node()).collect();
+    let mut rotations: HashMap<Node, Rotation> = nodes
+        .iter()
+        .filter(|&&node| !circ.is_frozen(node))
+        .filter_map(|&node| Some((node, gate_rotation(hugr, node)?)))
+        .collect();
+
+    let mut runs = Vec::new();
+    let is_run_start = |node: &Node| {
+        hugr.single_linked_output(*node, 0)
+            .map_or(true, |(prev, _)| !rotations.contains_key(&prev))
+    };
+    let starts: Vec<Node> = nodes
+        .into_iter()
+        .filter(|node| rotations.contains_key(node) && is_run_start(node))
+        .collect();
+    for start in starts {
+        let mut run = Vec::new();
+        let mut next = Some(start);
+        while let Some(rotation) = next.and_then(|node| rotations.remove_entry(&node)) {
+            next = hugr.single_linked_input(rotation.0, 0).map(|(n, _)| n);
+            run.push(rotation);
+        }
+        runs.push(run);
+    }
+    runs
+}
+
+/// The rotation applied by a single-qubit gate, if it can be squashed.
+///
+/// Gates with symbolic parameters can only be squashed if they are Z or X
+/// rotations.
+fn gate_rotation(hugr: &impl HugrView, node: Node) -> Option<Rotation> {
+    let op = Tk2Op::try_from(hugr.get_optype(node)).ok()?;
+    if !matches!(
+        op,
+        Tk2Op::H
+            | Tk2Op::X
+            | Tk2Op::Y
+            | Tk2Op::Z
+            | Tk2Op::S
+            | Tk2Op::Sdg
+            | Tk2Op::T
+            | Tk2Op::Tdg
+            | Tk2Op::RzF64
+            | Tk2Op::RxF64
+            | Tk2Op::PhasedX
+            | Tk2Op::TK1
+    ) {
+        return None;
+    }
+    let sig = hugr.signature(node)?;
+    let wires = sig
+        .input_types()
+        .iter()
+        .enumerate()
+        .filter(|(_, ty)| *ty == &FLOAT64_TYPE)
+        .map(|(port, _)| {
+            let (src, src_port) = hugr.single_linked_output(node, port)?;
+            Some(Wire::new(src, src_port))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let params = wires
+        .iter()
+        .map(|&wire| float_wire_value(hugr, wire))
+        .collect::<Option<Vec<f64>>>();
+    match (params, op) {
+        (Some(params), _) => Some(Rotation::Fixed(gate_matrix(op, &params)?)),
+        (None, Tk2Op::RzF64) => Some(Rotation::Symbolic(
+            Pauli::Z,
+            Angle::from_wire(hugr, wires[0]),
+        )),
+        (None, Tk2Op::RxF64) => Some(Rotation::Symbolic(
+            Pauli::X,
+            Angle::from_wire(hugr, wires[0]),
+        )),
+        (None, _) => None,
+    }
+}
+
+/// Compute the replacements for the gates of a run.
+///
+/// Returns the sub-runs to replace, each with their new gates.
+fn squash_run(run: &[(Node, Rotation)], basis: EulerBasis) -> Vec<(Vec<Node>, Squashed)> {
+    let nodes = || run.iter().map(|(node, _)| *node).collect::<Vec<_>>();
+    let symbolic_axes: Vec<Pauli> = run
+        .iter()
+        .filter_map(|(_, rot)| match rot {
+            Rotation::Symbolic(axis, _) => Some(*axis),
+            Rotation::Fixed(_) => None,
+        })
+        .collect();
+
+    if symbolic_axes.is_empty() {
+        let matrices = run.iter().map(|(_, rot)| match rot {
+            Rotation::Fixed(m) => m,
+            Rotation::Symbolic(..) => unreachable!(),
+        });
+        return vec![(nodes(), euler_gates(&product(matrices), basis))];
+    }
+
+    // A run of rotations around a single axis is merged into one rotation.
+    if let Ok(&axis) = symbolic_axes.iter().all_equal_value() {
+        if let Some(merged) = merge_rotations(run, axis) {
+            return vec![(nodes(), merged)];
+        }
+    }
+
+    // Otherwise, squash the fixed gates between the symbolic rotations.
+    run.split(|(_, rot)| matches!(rot, Rotation::Symbolic(..)))
+        .filter(|segment| !segment.is_empty())
+        .flat_map(|segment| squash_run(segment, basis))
+        .collect()
+}
+
+/// Merge a run of rotations around the same axis, if all its fixed gates are
+/// rotations around that axis.
+fn merge_rotations(run: &[(Node, Rotation)], axis: Pauli) -> Option<Squashed> {
+    let mut symbolic = Angle::default();
+    let mut fixed = Vec::new();
+    for (_, rot) in run {
+        match rot {
+            Rotation::Symbolic(_, angle) => symbolic = symbolic + angle.clone(),
+            Rotation::Fixed(m) => fixed.push(m),
+        }
+    }
+    let fixed = product(fixed);
+    let value = normalise(axis_angle(&fixed, axis)?);
+    let (op, rotation) = match axis {
+        Pauli::Z => (Tk2Op::RzF64, gate_matrix(Tk2Op::RzF64, &[value])?),
+        Pauli::X => (Tk2Op::RxF64, gate_matrix(Tk2Op::RxF64, &[value])?),
+        _ => return None,
+    };
+    // Rotations around the same axis compose exactly, so folding constants in
+    // and out of the symbolic expression introduces no phase.
+    let angle = (symbolic + Angle::from(value)).simplify();
+    let gates = match angle.is_zero() {
+        true => vec![],
+        false => vec![(op, vec![angle])],
+    };
+    Some(Squashed {
+        gates,
+        phase: relative_phase(&fixed, &rotation),
+    })
+}
+
+/// Resynthesise a single-qubit unitary as at most three rotations.
+fn euler_gates(u: &[Complex64], basis: EulerBasis) -> Squashed {
+    let gates = euler_rotations(u, basis);
+    let matrices: Vec<_> = gates
+        .iter()
+        .map(|(op, params)| gate_matrix(*op, params).unwrap())
+        .collect();
+    let new_unitary = product(&matrices);
+    Squashed {
+        phase: relative_phase(u, &new_unitary),
+        gates: gates
+            .into_iter()
+            .map(|(op, params)| (op, params.into_iter().map(Angle::from).collect()))
+            .collect(),
+    }
+}
+
+/// Decompose a single-qubit unitary as at most three rotations around the
+/// axes of `basis`, up to a global phase.
+///
+/// Rotations by a zero angle are omitted, so the identity decomposes into an
+/// empty sequence. The gates are returned in order of application, with their
+/// angle parameters.
+pub(crate) fn euler_rotations(u: &[Complex64], basis: EulerBasis) -> Vec<(Tk2Op, Vec<f64>)> {
+    let (outer, middle, [a, b, c]) = match basis {
+        EulerBasis::ZXZ => (Tk2Op::RzF64, Tk2Op::RxF64, zxz_angles(u)),
+        EulerBasis::ZYZ => {
+            let [a, b, c] = zxz_angles(u);
+            (
+                Tk2Op::RzF64,
+                Tk2Op::PhasedX,
+                [a - FRAC_PI_2, b, c + FRAC_PI_2],
+            )
+        }
+        EulerBasis::XZX => (Tk2Op::RxF64, Tk2Op::RzF64, zxz_angles(&conjugate_h(u))),
+    };
+    let outer_gate = |angle: f64| (outer, vec![normalise(angle)]);
+    let middle_gate = |angle: f64| match middle {
+        Tk2Op::PhasedX => (middle, vec![normalise(angle), FRAC_PI_2]),
+        _ => (middle, vec![normalise(angle)]),
+    };
+    match is_zero_angle(b) {
+        true => vec![outer_gate(a + c)],
+        false => vec![outer_gate(c), middle_gate(b), outer_gate(a)],
+    }
+    .into_iter()
+    .filter(|(op, params)| op == &middle || !is_zero_angle(params[0]))
+    .collect()
+}
+
+/// Decompose a single-qubit unitary as `Rz(a) Rx(b) Rz(c)`, up to a global
+/// phase. Returns the angles `[a, b, c]`.
+fn zxz_angles(u: &[Complex64]) -> [f64; 3] {
+    // Normalise the unitary to SU(2), and decompose it as `Rz(a) Ry(b) Rz(c)`.
+    let det = u[0] * u[3] - u[1] * u[2];
+    let u: Vec<Complex64> = u.iter().map(|x| x / det.sqrt()).collect();
+    let b = 2. * u[2].norm().atan2(u[0].norm());
+    let arg = |x: Complex64| if x.norm() < TOLERANCE { 0. } else { x.arg() };
+    let a = arg(u[2]) - arg(u[0]);
+    let c = -arg(u[0]) - arg(u[2]);
+    // `Ry(b) = Rz(π/2) Rx(b) Rz(-π/2)`.
+    [a + FRAC_PI_2, b, c - FRAC_PI_2]
+}
+
+/// The angle of a unitary that is a rotation around the Z or X axis, up to a
+/// global phase.
+fn axis_angle(u: &[Complex64], axis: Pauli) -> Option<f64> {
+    let u = match axis {
+        Pauli::Z => u.to_vec(),
+        Pauli::X => conjugate_h(u),
+        _ => return None,
+    };
+    (u[1].norm() < TOLERANCE && u[2].norm() < TOLERANCE).then(|| u[3].arg() - u[0].arg())
+}
+
+/// Conjugate a single-qubit unitary by a Hadamard gate, exchanging the X and Z
+/// axes.
+fn conjugate_h(u: &[Complex64]) -> Vec<Complex64> {
+    let h = gate_matrix(Tk2Op::H, &[]).unwrap();
+    matmul(&matmul(&h, u), &h)
+}
+
+/// The unitary of a sequence of single-qubit gates, given in order of
+/// application.
+fn product<'a>(matrices: impl IntoIterator<Item = &'a Vec<Complex64>>) -> Vec<Complex64> {
+    let identity = gate_matrix(Tk2Op::RzF64, &[0.]).unwrap();
+    matrices
+        .into_iter()
+        .fold(identity, |acc, m| matmul(m, &acc))
+}
+
+/// Bring an angle into the range `[-π, π]`.
+///
+/// Rotations by angles differing by `2π` only differ by a global phase.
+fn normalise(angle: f64) -> f64 {
+    angle - TAU * (angle / TAU).round()
+}
+
+fn is_zero_angle(angle: f64) -> bool {
+    normalise(angle).abs() < TOLERANCE
+}
+
+/// Replace a run of consecutive gates on a qubit by new gates.
+fn replace_run(circ: &mut Circuit, nodes: &[Node], gates: &[(Tk2Op, Vec<Angle>)]) {
+    let parent = circ.parent();
+    let hugr = circ.hugr_mut();
+    let first = *nodes.first().unwrap();
+    let last = *nodes.last().unwrap();
+    let (src, src_port) = hugr.single_linked_output(first, 0).unwrap();
+    let (dst, dst_port) = hugr.single_linked_input(last, 0).unwrap();
+    let param_sources: Vec<Node> = nodes
+        .iter()
+        .flat_map(|&node| hugr.input_neighbours(node).collect::<Vec<_>>())
+        .filter(|n| !nodes.contains(n))
+        .collect();
+    for &node in nodes {
+        hugr.remove_node(node);
+    }
+
+    let mut prev = Wire::new(src, src_port);
+    for (op, params) in gates {
+        let params: Vec<Wire> = params
+            .iter()
+            .map(|angle| angle_wire(hugr, parent, angle))
+            .collect();
+        let node = hugr.add_node_with_parent(parent, *op);
+        hugr.connect(prev.node(), prev.source(), node, 0);
+        for (port, param) in params.into_iter().enumerate() {
+            hugr.connect(param.node(), param.source(), node, port + 1);
+        }
+        prev = Wire::new(node, 0);
+    }
+    hugr.connect(prev.node(), prev.source(), dst, dst_port);
+
+    for node in param_sources {
+        remove_unused_param(hugr, node);
+    }
+}
+
+/// Add the operations computing an angle, returning the wire carrying it.
+fn angle_wire(hugr: &mut impl HugrMut, parent: Node, angle: &Angle) -> Wire {
+    let mut terms = angle.wires.clone();
+    if !angle.symbol.is_zero() {
+        let symbol = hugr.add_node_with_parent(parent, symbolic_expr_op(&angle.symbol));
+        terms.push(Wire::new(symbol, 0));
+    }
+    if terms.is_empty() || !is_zero_angle(angle.value) {
+        let value = ConstF64::new(angle.value);
+        let constant = hugr.add_node_with_parent(parent, Const::new(value.into()));
+        let load = hugr.add_node_with_parent(
+            parent,
+            LoadConstant {
+                datatype: FLOAT64_TYPE,
+            },
+        );
+        hugr.connect(constant, 0, load, 0);
+        terms.push(Wire::new(load, 0));
+    }
+    terms
+        .into_iter()
+        .reduce(|lhs, rhs| {
+            let add = hugr.add_node_with_parent(parent, Tk2Op::AngleAdd);
+            hugr.connect(lhs.node(), lhs.source(), add, 0);
+            hugr.connect(rhs.node(), rhs.source(), add, 1);
+            Wire::new(add, 0)
+        })
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::ops::op_matches;
+    use crate::sim::unitary::equal_up_to_phase;
+    use crate::utils;
+    use crate::utils::test::tk1_circuit;
+    /// The gates of a circuit, without the parameter additions.
+    fn gates(circ: &Circuit) -> Vec<Tk2Op> {
+        utils::test::gates(circ)
+            .into_iter()
+            .filter(|op| op != &Tk2Op::AngleAdd)
+            .collect()
+    }
+
+    const RUN: &str = r#"{"args": [["q", [0]]], "op": {"type": "H"}},
+        {"args": [["q", [0]]], "op": {"type": "Rz", "params": ["0.3"]}},
+        {"args": [["q", [0]]], "op": {"type": "T"}},
+        {"args": [["q", [0]]], "op": {"type": "Rx", "params": ["0.7"]}},
+        {"args": [["q", [0]]], "op": {"type": "Rz", "params": ["0.2"]}}"#;
+
+    #[rstest]
+    #[case::zyz(EulerBasis::ZYZ, [Tk2Op::RzF64, Tk2Op::PhasedX, Tk2Op::RzF64])]
+    #[case::xzx(EulerBasis::XZX, [Tk2Op::RxF64, Tk2Op::RzF64, Tk2Op::RxF64])]
+    #[case::zxz(EulerBasis::ZXZ, [Tk2Op::RzF64, Tk2Op::RxF64, Tk2Op::RzF64])]
+    fn squash_constant_run(#[case] basis: EulerBasis, #[case] expected: [Tk2Op; 3]) {
+        let mut circ = tk1_circuit(1, 0, RUN);
+        let unitary = circ.unitary().unwrap();
+
+        let report = squash_single_qubit_gates(&mut circ, basis);
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.squashed_runs, 1);
+        assert_eq!(report.removed_gates, 2);
+        assert_eq!(gates(&circ), expected);
+        assert!(equal_up_to_phase(&circ.unitary().unwrap(), &unitary, 1e-9));
+    }
+
+    #[test]
+    fn squash_to_identity() {
+        // `Rz(π) Rz(π) = -I`
+        let mut circ = tk1_circuit(
+            1,
+            0,
+            r#"{"args": [["q", [0]]], "op": {"type": "Rz", "params": ["1"]}},
+               {"args": [["q", [0]]], "op": {"type": "Rz", "params": ["1"]}}"#,
+        );
+
+        let report = squash_single_qubit_gates(&mut circ, EulerBasis::default());
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.removed_gates, 2);
+        assert_eq!(circ.num_operations(), 0);
+        // The unused angle constants are removed too.
+        assert_eq!(circ.hugr().children(circ.parent()).count(), 2);
+        let phase = circ.global_phase().unwrap();
+        assert!((phase.constant().abs() - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn symbolic_rotations_are_summed() {
+        let mut circ = tk1_circuit(
+            1,
+            0,
+            r#"{"args": [["q", [0]]], "op": {"type": "Rz", "params": ["a"]}},
+               {"args": [["q", [0]]], "op": {"type": "T"}},
+               {"args": [["q", [0]]], "op": {"type": "Rz", "params": ["b"]}}"#,
+        );
+
+        let report = squash_single_qubit_gates(&mut circ, EulerBasis::ZXZ);
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.squashed_runs, 1);
+        assert_eq!(gates(&circ), [Tk2Op::RzF64]);
+        let adds = circ
+            .commands()
+            .filter(|cmd| op_matches(cmd.optype(), Tk2Op::AngleAdd))
+            .count();
+        assert_eq!(adds, 0);
+        let symbols = circ
+            .commands()
+            .filter_map(|cmd| match_symbolic_expr(cmd.optype()))
+            .collect::<Vec<_>>();
+        assert_eq!(symbols, [SymbolicExpr::parse("a + b + 1/4")]);
+    }
+
+    #[test]
+    fn symbolic_rotations_cancel() {
+        let mut circ = tk1_circuit(
+            1,
+            0,
+            r#"{"args": [["q", [0]]], "op": {"type": "Rz", "params": ["2*a + 1/2"]}},
+               {"args": [["q", [0]]], "op": {"type": "Sdg"}},
+               {"args": [["q", [0]]], "op": {"type": "Rz", "params": ["-a - a"]}}"#,
+        );
+
+        let report = squash_single_qubit_gates(&mut circ, EulerBasis::ZXZ);
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.removed_gates, 3);
+        assert_eq!(circ.num_operations(), 0);
+        assert_eq!(circ.hugr().children(circ.parent()).count(), 2);
+        // `Sdg = e^{-iπ/4} Rz(-π/2)`
+        let phase = circ.global_phase().unwrap();
+        assert!((phase.constant() - 1.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn symbolic_rotations_split_runs() {
+        let mut circ = tk1_circuit(
+            1,
+            0,
+            r#"{"args": [["q", [0]]], "op": {"type": "H"}},
+               {"args": [["q", [0]]], "op": {"type": "H"}},
+               {"args": [["q", [0]]], "op": {"type": "Rz", "params": ["a"]}},
+               {"args": [["q", [0]]], "op": {"type": "H"}},
+               {"args": [["q", [0]]], "op": {"type": "Rx", "params": ["b"]}}"#,
+        );
+
+        let report = squash_single_qubit_gates(&mut circ, EulerBasis::ZXZ);
+        circ.hugr_mut().update_validate(&REGISTRY).unwrap();
+
+        assert_eq!(report.squashed_runs, 1);
+        assert_eq!(gates(&circ), [Tk2Op::RzF64, Tk2Op::H, Tk2Op::RxF64]);
+    }
+}
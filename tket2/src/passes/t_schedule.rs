@@ -0,0 +1,294 @@
+//! Scheduling of T gates according to the availability of magic states.
+//!
+//! On fault-tolerant backends, each T gate consumes a magic state produced by
+//! a distillation factory at a finite rate. A circuit schedule is only
+//! feasible if no T gate is executed before a magic state is available for it.
+//!
+//! [`plan_t_schedule`] computes an as-soon-as-possible schedule for a circuit
+//! in which each T gate is delayed until a magic state is ready. Gates that do
+//! not depend on a delayed T gate are free to run earlier, effectively
+//! reordering commuting operations around it.
+//!
+//! [`schedule_t_gates`] additionally records the start time of each operation
+//! in its metadata, under the [`METADATA_START_TIME`] key, so that the delays
+//! are carried to the backend along with the circuit.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::{HugrView, Node};
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::{Circuit, Tk2Op};
+
+/// Metadata key for the time unit at which an operation starts, in a schedule
+/// respecting the availability of magic states.
+pub const METADATA_START_TIME: &str = "TKET2.start_time";
+
+/// Configuration for [`plan_t_schedule`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MagicStateConfig {
+    /// Number of magic states produced per time unit.
+    ///
+    /// Defaults to `1.0`.
+    pub production_rate: f64,
+    /// Number of magic states available at the start of the circuit.
+    ///
+    /// Defaults to `0`.
+    pub initial_states: usize,
+}
+
+impl Default for MagicStateConfig {
+    fn default() -> Self {
+        Self {
+            production_rate: 1.,
+            initial_states: 0,
+        }
+    }
+}
+
+/// A schedule assigning a start time to each operation of a circuit.
+///
+/// Every quantum operation takes one time unit to execute. Classical
+/// operations take no time.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TGateSchedule {
+    /// The start time of each operation.
+    start_times: HashMap<Node, usize>,
+    /// The total delay introduced by waiting for magic states.
+    total_delay: usize,
+    /// The time at which the last operation finishes.
+    duration: usize,
+    /// The number of T gates in the circuit.
+    t_count: usize,
+}
+
+impl TGateSchedule {
+    /// The start time of an operation, if it is part of the schedule.
+    pub fn start_time(&self, node: Node) -> Option<usize> {
+        self.start_times.get(&node).copied()
+    }
+
+    /// The time at which the last operation finishes.
+    pub fn duration(&self) -> usize {
+        self.duration
+    }
+
+    /// The total number of time units T gates spent waiting for a magic state.
+    pub fn total_delay(&self) -> usize {
+        self.total_delay
+    }
+
+    /// The number of T gates in the schedule.
+    pub fn t_count(&self) -> usize {
+        self.t_count
+    }
+
+    /// The scheduled operations, ordered by start time.
+    pub fn operations(&self) -> impl Iterator<Item = (Node, usize)> + '_ {
+        self.start_times
+            .iter()
+            .map(|(&n, &t)| (n, t))
+            .sorted_by_key(|&(n, t)| (t, n))
+    }
+}
+
+/// Compute a schedule for a circuit that respects the availability of magic
+/// states for its T gates.
+///
+/// Both [`Tk2Op::T`] and [`Tk2Op::Tdg`] consume a magic state. Operations are
+/// scheduled as soon as their inputs are ready, and T gates are assigned magic
+/// states in the order in which they become ready.
+///
+/// The circuit is not modified. See [`schedule_t_gates`] to record the
+/// schedule in the circuit.
+pub fn plan_t_schedule(
+    circ: &Circuit<impl HugrView>,
+    config: MagicStateConfig,
+) -> Result<TGateSchedule, TSchedulingError> {
+    if !(config.production_rate > 0. && config.production_rate.is_finite()) {
+        return Err(TSchedulingError::InvalidProductionRate(
+            config.production_rate,
+        ));
+    }
+
+    let hugr = circ.hugr();
+    let commands = circ
+        .commands()
+        .map(|cmd| {
+            let op = Tk2Op::try_from(cmd.optype()).ok();
+            (cmd.node(), op)
+        })
+        .collect_vec();
+    let index: HashMap<Node, usize> = commands
+        .iter()
+        .enumerate()
+        .map(|(i, &(n, _))| (n, i))
+        .collect();
+
+    // Count the unscheduled predecessors of each command.
+    let mut pending = vec![0; commands.len()];
+    let successors = commands
+        .iter()
+        .map(|&(node, _)| {
+            hugr.output_neighbours(node)
+                .filter_map(|n| index.get(&n).copied())
+                .unique()
+                .inspect(|&succ| pending[succ] += 1)
+                .collect_vec()
+        })
+        .collect_vec();
+
+    let mut ready_at = vec![0; commands.len()];
+    let mut queue: BinaryHeap<_> = pending
+        .iter()
+        .positions(|&p| p == 0)
+        .map(|i| Reverse((0, i)))
+        .collect();
+
+    let mut schedule = TGateSchedule::default();
+    while let Some(Reverse((ready, i))) = queue.pop() {
+        let (node, op) = commands[i];
+        let mut start = ready;
+        if matches!(op, Some(Tk2Op::T | Tk2Op::Tdg)) {
+            schedule.t_count += 1;
+            start = start.max(magic_state_time(schedule.t_count, config));
+            schedule.total_delay += start - ready;
+        }
+        let duration = usize::from(op.is_some_and(|op| op.is_quantum()));
+        let finish = start + duration;
+
+        schedule.start_times.insert(node, start);
+        schedule.duration = schedule.duration.max(finish);
+        for &succ in &successors[i] {
+            ready_at[succ] = ready_at[succ].max(finish);
+            pending[succ] -= 1;
+            if pending[succ] == 0 {
+                queue.push(Reverse((ready_at[succ], succ)));
+            }
+        }
+    }
+
+    Ok(schedule)
+}
+
+/// Schedule the operations of a circuit according to the availability of
+/// magic states for its T gates, and record the start time of each operation
+/// in its metadata.
+///
+/// Operations following a T gate that waits for a magic state are delayed
+/// accordingly. See [`plan_t_schedule`].
+pub fn schedule_t_gates(
+    circ: &mut Circuit<impl HugrMut>,
+    config: MagicStateConfig,
+) -> Result<TGateSchedule, TSchedulingError> {
+    let schedule = plan_t_schedule(circ, config)?;
+    for (node, start) in schedule.operations() {
+        circ.hugr_mut()
+            .set_metadata(node, METADATA_START_TIME, start);
+    }
+    Ok(schedule)
+}
+
+/// The earliest time at which the `n`-th magic state (1-indexed) is available.
+fn magic_state_time(n: usize, config: MagicStateConfig) -> usize {
+    let missing = n.saturating_sub(config.initial_states);
+    (missing as f64 / config.production_rate).ceil() as usize
+}
+
+/// Errors that can occur while scheduling T gates.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum TSchedulingError {
+    /// The magic state production rate must be positive and finite.
+    #[error("Invalid magic state production rate {0}. The rate must be positive and finite.")]
+    InvalidProductionRate(f64),
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::utils::build_simple_circuit;
+
+    /// Three T gates on separate qubits.
+    fn parallel_t() -> Circuit {
+        build_simple_circuit(3, |circ| {
+            circ.append(Tk2Op::T, [0])?;
+            circ.append(Tk2Op::T, [1])?;
+            circ.append(Tk2Op::Tdg, [2])?;
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case::slow(0.5, 0, 7, 12)]
+    #[case::unit(1., 0, 4, 6)]
+    #[case::buffered(1., 2, 2, 1)]
+    #[case::fast(3., 0, 2, 3)]
+    fn schedule_parallel_t(
+        #[case] production_rate: f64,
+        #[case] initial_states: usize,
+        #[case] duration: usize,
+        #[case] total_delay: usize,
+    ) {
+        let circ = parallel_t();
+        let config = MagicStateConfig {
+            production_rate,
+            initial_states,
+        };
+        let schedule = plan_t_schedule(&circ, config).unwrap();
+
+        assert_eq!(schedule.t_count(), 3);
+        assert_eq!(schedule.duration(), duration);
+        assert_eq!(schedule.total_delay(), total_delay);
+    }
+
+    #[test]
+    fn schedule_respects_dependencies() {
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::T, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::H, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        let schedule = plan_t_schedule(&circ, MagicStateConfig::default()).unwrap();
+
+        let times = schedule.operations().map(|(_, t)| t).collect_vec();
+        assert_eq!(times, vec![1, 2, 3]);
+        assert_eq!(schedule.duration(), 4);
+    }
+
+    #[test]
+    fn schedule_records_start_times() {
+        let mut circ = parallel_t();
+        let schedule = schedule_t_gates(&mut circ, MagicStateConfig::default()).unwrap();
+
+        for (node, start) in schedule.operations() {
+            let time = circ.hugr().get_metadata(node, METADATA_START_TIME);
+            assert_eq!(time, Some(&start.into()));
+        }
+        let times = schedule.operations().map(|(_, t)| t).collect_vec();
+        assert_eq!(times, vec![1, 2, 3]);
+    }
+
+    #[rstest]
+    #[case(0.)]
+    #[case(-1.)]
+    #[case(f64::NAN)]
+    fn invalid_rate(#[case] production_rate: f64) {
+        let config = MagicStateConfig {
+            production_rate,
+            ..Default::default()
+        };
+        assert!(matches!(
+            plan_t_schedule(&parallel_t(), config),
+            Err(TSchedulingError::InvalidProductionRate(_))
+        ));
+    }
+}
@@ -75,8 +75,10 @@ type PNode = MatchOp;
 
 /// An edge property in a circuit pattern.
 ///
-/// Edges are
-/// Edges are reversible if the edge type is linear.
+/// Edges are reversible if the wire has a single target, which is always the
+/// case for linear types. Classical wires may also be used several times, in
+/// which case each use is matched separately through a hidden copy node, as
+/// for copied inputs.
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
 )]
@@ -87,12 +89,13 @@ enum PEdge {
         dst: Port,
         is_reversible: bool,
     },
-    /// An edge from a copied input to src.
+    /// An edge from a copied value to src.
     ///
     /// Edges from inputs are typically not matched as part of the pattern,
     /// unless a single input is copied multiple times. In this case, an
     /// InputEdge is used to link the source port to the (usually hidden)
-    /// copy node.
+    /// copy node. The same applies to classical values computed within the
+    /// pattern and used several times.
     ///
     /// Input edges are always irreversible.
     InputEdge { src: Port },
@@ -136,7 +139,8 @@ impl PEdge {
             Some(EdgeKind::Const(typ)) => typ,
             _ => return Err(InvalidEdgeProperty::UntypedPort(node, src)),
         };
-        let is_reversible = type_is_linear(&port_type);
+        let is_reversible =
+            type_is_linear(&port_type) || hugr.linked_ports(dst_node, dst).count() == 1;
         Ok(Self::InternalEdge {
             src,
             dst,
@@ -1,9 +1,10 @@
 //! Pattern and matcher objects for circuit matching
 
 use std::{
+    collections::{BTreeSet, HashSet, VecDeque},
     fmt::Debug,
-    fs::File,
-    io,
+    fs::{self, File},
+    io::{self, Read},
     path::{Path, PathBuf},
 };
 
@@ -18,14 +19,17 @@ use itertools::Itertools;
 use portgraph::algorithms::ConvexChecker;
 use portmatching::{
     automaton::{LineBuilder, ScopeAutomaton},
-    EdgeProperty, PatternID,
+    EdgeProperty, HashMap, PatternID,
 };
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use smol_str::SmolStr;
+use strum::IntoEnumIterator;
 use thiserror::Error;
 
 use crate::{
     circuit::Circuit,
     rewrite::{CircuitRewrite, Subcircuit},
+    Tk2Op,
 };
 
 /// Matchable operations in a circuit.
@@ -122,6 +126,8 @@ impl PatternMatch {
     ///  - the subcircuit does not match the pattern
     ///  - the subcircuit is empty
     ///  - the subcircuit obtained is not a valid circuit region
+    ///  - the pattern has several connected components, which must be matched
+    ///    with [`PatternMatcher::find_matches`] instead
     pub fn try_from_root_match(
         root: Node,
         pattern: PatternID,
@@ -148,9 +154,26 @@ impl PatternMatch {
         let pattern_ref = matcher
             .get_pattern(pattern)
             .ok_or(InvalidPatternMatch::MatchNotFound)?;
+        if pattern_ref.n_components() > 1 {
+            // Only the first component can be matched from a single root.
+            return Err(InvalidPatternMatch::MatchNotFound);
+        }
         let map = pattern_ref
             .get_match_map(root, circ)
             .ok_or(InvalidPatternMatch::MatchNotFound)?;
+        Self::try_from_match_map(root, pattern, pattern_ref, &map, circ, checker)
+    }
+
+    /// Create a pattern match from the map from pattern nodes to circuit
+    /// nodes.
+    fn try_from_match_map(
+        root: Node,
+        pattern: PatternID,
+        pattern_ref: &CircuitPattern,
+        map: &HashMap<Node, Node>,
+        circ: &Circuit<impl HugrView>,
+        checker: &impl ConvexChecker,
+    ) -> Result<Self, InvalidPatternMatch> {
         let inputs = pattern_ref
             .inputs
             .iter()
@@ -238,10 +261,28 @@ impl Debug for PatternMatch {
 ///
 /// This uses a state automaton internally to match against a set of patterns
 /// simultaneously.
+///
+/// Each connected component of a pattern is a separate pattern of the
+/// automaton. The automaton pattern `i` is the first component of the pattern
+/// `i`, and the other components follow the patterns.
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct PatternMatcher {
     automaton: ScopeAutomaton<PNode, PEdge, Port>,
     patterns: Vec<CircuitPattern>,
+    /// The pattern and component index of the automaton patterns after the
+    /// first components.
+    #[serde(default)]
+    components: Vec<(PatternID, usize)>,
+    /// The maximum distance between the components of a match.
+    #[serde(default = "default_component_radius")]
+    component_radius: usize,
+}
+
+/// The default [`PatternMatcher::component_radius`].
+pub const DEFAULT_COMPONENT_RADIUS: usize = 4;
+
+fn default_component_radius() -> usize {
+    DEFAULT_COMPONENT_RADIUS
 }
 
 impl Debug for PatternMatcher {
@@ -254,22 +295,39 @@ impl Debug for PatternMatcher {
 
 impl PatternMatcher {
     /// Construct a matcher from a set of patterns
+    ///
+    /// The patterns are converted to line patterns in parallel, before
+    /// building the automaton.
     pub fn from_patterns(patterns: impl Into<Vec<CircuitPattern>>) -> Self {
         let patterns = patterns.into();
+        let components = patterns
+            .iter()
+            .enumerate()
+            .flat_map(|(i, p)| (1..p.n_components()).map(move |c| (PatternID(i), c)))
+            .collect_vec();
         let line_patterns = patterns
             .iter()
+            .map(|p| &p.pattern)
+            .chain(
+                components
+                    .iter()
+                    .map(|&(id, c)| &patterns[id.0].components[c - 1]),
+            )
+            .collect_vec()
+            .into_par_iter()
             .map(|p| {
-                p.pattern
-                    .clone()
+                p.clone()
                     .try_into_line_pattern(compatible_offsets)
                     .expect("Failed to express pattern as line pattern")
             })
-            .collect_vec();
+            .collect();
         let builder = LineBuilder::from_patterns(line_patterns);
         let automaton = builder.build();
         Self {
             automaton,
             patterns,
+            components,
+            component_radius: DEFAULT_COMPONENT_RADIUS,
         }
     }
 
@@ -281,7 +339,31 @@ impl PatternMatcher {
         let checker = TopoConvexChecker::new(circuit.hugr());
         circuit
             .commands()
-            .flat_map(move |cmd| self.find_rooted_matches(circuit, cmd.node(), &checker))
+            .filter(|cmd| !circuit.is_frozen(cmd.node()))
+            .flat_map(move |cmd| self.find_rooted_matches(circuit, cmd.node(), &checker, None))
+    }
+
+    /// Find all convex pattern matches in a circuit rooted at one of `roots`.
+    ///
+    /// A match of a pattern with several connected components is rooted at
+    /// one of `roots` if any of its components is. The other components are
+    /// only searched for within [`PatternMatcher::component_radius`] of the
+    /// roots, so the running time only depends on the neighbourhood of
+    /// `roots`.
+    pub fn find_matches_at(
+        &self,
+        circuit: &Circuit<impl HugrView>,
+        roots: impl IntoIterator<Item = Node>,
+    ) -> Vec<PatternMatch> {
+        let checker = TopoConvexChecker::new(circuit.hugr());
+        let roots: HashSet<Node> = roots
+            .into_iter()
+            .filter(|&root| !circuit.is_frozen(root))
+            .collect();
+        roots
+            .iter()
+            .flat_map(|&root| self.find_rooted_matches(circuit, root, &checker, Some(&roots)))
+            .collect()
     }
 
     /// Find all convex pattern matches in a circuit.and collect in to a vector
@@ -290,31 +372,185 @@ impl PatternMatcher {
     }
 
     /// Find all convex pattern matches in a circuit rooted at a given node.
+    ///
+    /// A match with several components is only returned for the first of its
+    /// components rooted in `roots`, so that it is returned once when
+    /// searching from all of `roots`. If `roots` is `None`, every node is a
+    /// root.
     fn find_rooted_matches(
         &self,
         circ: &Circuit<impl HugrView>,
         root: Node,
         checker: &impl ConvexChecker,
+        roots: Option<&HashSet<Node>>,
     ) -> Vec<PatternMatch> {
-        self.automaton
-            .run(
-                root.into(),
-                // Node weights (none)
-                validate_circuit_node(circ),
-                // Check edge exist
-                validate_circuit_edge(circ),
-            )
-            .filter_map(|pattern_id| {
+        self.run_automaton(circ, root)
+            .flat_map(|id| {
+                let (pattern_id, component) = self.component_of(id);
+                if self.patterns[pattern_id.0].n_components() > 1 {
+                    return self
+                        .find_component_matches(circ, pattern_id, component, root, checker, roots);
+                }
                 handle_match_error(
                     PatternMatch::try_from_root_match_with_checker(
                         root, pattern_id, circ, self, checker,
                     ),
                     root,
                 )
+                .into_iter()
+                .collect()
             })
             .collect()
     }
 
+    /// Find the matches of a pattern with several components, with the
+    /// component `component` rooted at `root`.
+    ///
+    /// The other components are searched for within the component radius of
+    /// the first component. See [`PatternMatcher::find_rooted_matches`] for
+    /// the meaning of `roots`.
+    fn find_component_matches(
+        &self,
+        circ: &Circuit<impl HugrView>,
+        pattern_id: PatternID,
+        component: usize,
+        root: Node,
+        checker: &impl ConvexChecker,
+        roots: Option<&HashSet<Node>>,
+    ) -> Vec<PatternMatch> {
+        let pattern = &self.patterns[pattern_id.0];
+        let is_root = |node: Node| roots.map_or(true, |roots| roots.contains(&node));
+        // Whether a match of component `c` makes the match rooted at an
+        // earlier component than `component`.
+        let is_earlier_root = |c: usize, m: &ComponentMatch| c < component && is_root(m.root);
+        if component > 0 && roots.is_none() {
+            // Found from the root of the first component.
+            return Vec::new();
+        }
+        let Some(map) = pattern.get_component_match_map(component, root, circ) else {
+            return Vec::new();
+        };
+        let root_match = ComponentMatch { root, map };
+        let anchors = match component {
+            0 => vec![root_match.clone()],
+            _ => self
+                .nearby_component_matches(circ, pattern_id, root)
+                .into_iter()
+                .filter(|(c, m)| *c == 0 && !is_earlier_root(0, m))
+                .map(|(_, m)| m)
+                .collect(),
+        };
+
+        let mut matches = Vec::new();
+        for anchor in anchors {
+            let mut candidates = vec![vec![]; pattern.n_components()];
+            candidates[0].push(anchor.clone());
+            candidates[component] = vec![root_match.clone()];
+            for (c, m) in self.nearby_component_matches(circ, pattern_id, anchor.root) {
+                if c != 0 && c != component && !is_earlier_root(c, &m) {
+                    candidates[c].push(m);
+                }
+            }
+            for candidate in candidates
+                .iter()
+                .map(|c| c.iter())
+                .multi_cartesian_product()
+            {
+                let n_nodes: usize = candidate.iter().map(|m| m.map.len()).sum();
+                let map: HashMap<Node, Node> = candidate
+                    .iter()
+                    .flat_map(|m| m.map.iter().map(|(&p, &c)| (p, c)))
+                    .collect();
+                // The components must be matched on disjoint nodes.
+                if map.values().unique().count() != n_nodes {
+                    continue;
+                }
+                if !is_boundary_consistent(pattern, &map, circ) {
+                    continue;
+                }
+                matches.extend(handle_match_error(
+                    PatternMatch::try_from_match_map(
+                        anchor.root,
+                        pattern_id,
+                        pattern,
+                        &map,
+                        circ,
+                        checker,
+                    ),
+                    anchor.root,
+                ));
+            }
+        }
+        matches
+    }
+
+    /// The matches of the components of a pattern rooted within the component
+    /// radius of `node`, along with their component index.
+    fn nearby_component_matches(
+        &self,
+        circ: &Circuit<impl HugrView>,
+        pattern_id: PatternID,
+        node: Node,
+    ) -> Vec<(usize, ComponentMatch)> {
+        let pattern = &self.patterns[pattern_id.0];
+        nodes_within(circ, node, self.component_radius)
+            .into_iter()
+            .flat_map(|root| {
+                self.run_automaton(circ, root)
+                    .map(|id| self.component_of(id))
+                    .filter(|&(id, _)| id == pattern_id)
+                    .filter_map(|(_, c)| {
+                        let map = pattern.get_component_match_map(c, root, circ)?;
+                        Some((c, ComponentMatch { root, map }))
+                    })
+                    .collect_vec()
+            })
+            .collect()
+    }
+
+    /// The maximum distance between the root of the first component of a
+    /// match and the roots of its other components, for patterns with several
+    /// connected components.
+    ///
+    /// The distance is the number of edges of the shortest path between the
+    /// roots, ignoring the direction of the edges. Components further apart
+    /// are not matched together.
+    ///
+    /// Defaults to [`DEFAULT_COMPONENT_RADIUS`].
+    pub fn component_radius(&self) -> usize {
+        self.component_radius
+    }
+
+    /// Set the maximum distance between the components of a match, see
+    /// [`PatternMatcher::component_radius`].
+    pub fn with_component_radius(mut self, radius: usize) -> Self {
+        self.component_radius = radius;
+        self
+    }
+
+    /// Run the automaton at `root`, returning the matched automaton patterns.
+    fn run_automaton<'a>(
+        &'a self,
+        circ: &'a Circuit<impl HugrView>,
+        root: Node,
+    ) -> impl Iterator<Item = PatternID> + 'a {
+        self.automaton.run(
+            root.into(),
+            // Node weights (none)
+            validate_circuit_node(circ),
+            // Check edge exist
+            validate_circuit_edge(circ),
+        )
+    }
+
+    /// The pattern and component index of an automaton pattern.
+    fn component_of(&self, id: PatternID) -> (PatternID, usize) {
+        match id.0.checked_sub(self.patterns.len()) {
+            None => (id, 0),
+            Some(i) => self.components[i],
+        }
+    }
+
     /// Get a pattern by ID.
     pub fn get_pattern(&self, id: PatternID) -> Option<&CircuitPattern> {
         self.patterns.get(id.0)
@@ -325,23 +561,77 @@ impl PatternMatcher {
         self.patterns.len()
     }
 
+    /// A digest of the encoding of the gates used by the patterns.
+    ///
+    /// Only the gates of the standard [`Tk2Op`] gate set are included. The
+    /// digest changes if the encoding of one of these gates changes, in which
+    /// case the patterns no longer match circuits using the gates.
+    pub fn gate_set_digest(&self) -> u64 {
+        let gate_set: BTreeSet<MatchOp> = Tk2Op::iter()
+            .map(|op| MatchOp::from(OpType::from(op)))
+            .collect();
+        let gates: BTreeSet<&MatchOp> = self
+            .patterns
+            .iter()
+            .flat_map(CircuitPattern::ops)
+            .filter(|op| gate_set.contains(op))
+            .collect();
+        fxhash::hash64(&gates)
+    }
+
     /// Serialise a matcher into an IO stream.
     ///
     /// Precomputed matchers can be serialised as binary and then loaded
     /// later using [`PatternMatcher::load_binary_io`].
+    ///
+    /// The stream starts with a header identifying the format version and
+    /// the [gate set digest](PatternMatcher::gate_set_digest), checked when
+    /// loading the matcher.
     pub fn save_binary_io<W: io::Write>(
         &self,
         writer: &mut W,
     ) -> Result<(), MatcherSerialisationError> {
+        writer.write_all(MATCHER_MAGIC)?;
+        writer.write_all(&MATCHER_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&self.gate_set_digest().to_le_bytes())?;
         rmp_serde::encode::write(writer, &self)?;
         Ok(())
     }
 
     /// Loads a matcher from an IO stream.
     ///
-    /// Loads streams as created by [`PatternMatcher::save_binary_io`].
+    /// Loads streams as created by [`PatternMatcher::save_binary_io`], as
+    /// well as the unversioned streams written by earlier releases. The
+    /// latter cannot be checked against the current gate set, and can be
+    /// upgraded with [`PatternMatcher::migrate_binary`].
+    ///
+    /// Returns an error if the stream was written in a newer format version,
+    /// or if the encoding of its gates differs from the current one.
     pub fn load_binary_io<R: io::Read>(reader: &mut R) -> Result<Self, MatcherSerialisationError> {
+        let mut magic = [0; MATCHER_MAGIC.len()];
+        let n = read_prefix(reader, &mut magic)?;
+        if &magic[..n] != MATCHER_MAGIC {
+            // Unversioned stream, starting directly with the matcher.
+            let matcher: Self = rmp_serde::decode::from_read(magic[..n].chain(reader))?;
+            return Ok(matcher);
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version > MATCHER_FORMAT_VERSION {
+            return Err(MatcherSerialisationError::UnsupportedVersion {
+                version,
+                supported: MATCHER_FORMAT_VERSION,
+            });
+        }
+        let mut digest = [0; 8];
+        reader.read_exact(&mut digest)?;
+        let digest = u64::from_le_bytes(digest);
         let matcher: Self = rmp_serde::decode::from_read(reader)?;
+        let expected = matcher.gate_set_digest();
+        if digest != expected {
+            return Err(MatcherSerialisationError::GateSetMismatch { digest, expected });
+        }
         Ok(matcher)
     }
 
@@ -371,6 +661,122 @@ impl PatternMatcher {
         let mut reader = std::io::BufReader::new(file);
         Self::load_binary_io(&mut reader)
     }
+
+    /// Rewrite a matcher file in the current format version.
+    ///
+    /// Loads the file at `path` as [`PatternMatcher::load_binary`] does, and
+    /// saves it back under the same name. Use this to upgrade the unversioned
+    /// files written by earlier releases.
+    pub fn migrate_binary(path: impl AsRef<Path>) -> Result<(), MatcherSerialisationError> {
+        let path = path.as_ref();
+        let matcher = Self::load_binary(path)?;
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(format!(".{}.tmp", std::process::id()));
+        let mut file = io::BufWriter::new(File::create(&tmp_path)?);
+        matcher.save_binary_io(&mut file)?;
+        file.into_inner().map_err(|e| e.into_error())?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// A match of a connected component of a pattern.
+#[derive(Clone)]
+struct ComponentMatch {
+    /// The root of the component in the circuit.
+    root: Node,
+    /// The map from the component nodes to circuit nodes.
+    map: HashMap<Node, Node>,
+}
+
+/// The commands of `circ` within `radius` edges of `node`, including `node`.
+///
+/// The paths may go through the input and output nodes of the circuit.
+fn nodes_within(circ: &Circuit<impl HugrView>, node: Node, radius: usize) -> Vec<Node> {
+    let hugr = circ.hugr();
+    let parent = circ.parent();
+    let mut visited = HashSet::from([node]);
+    let mut queue = VecDeque::from([(node, 0)]);
+    while let Some((n, dist)) = queue.pop_front() {
+        if dist >= radius {
+            continue;
+        }
+        for next in hugr.all_neighbours(n) {
+            if hugr.get_parent(next) == Some(parent) && visited.insert(next) {
+                queue.push_back((next, dist + 1));
+            }
+        }
+    }
+    visited
+        .into_iter()
+        .filter(|&n| !circ.io_nodes().contains(&n) && !circ.is_frozen(n))
+        .collect()
+}
+
+/// Whether the pattern boundary is a valid boundary of the nodes of `map` in
+/// `circ`.
+///
+/// Each input must be linked to a single wire from outside the match, and the
+/// outputs must not be linked to the match, other than by the classical wires
+/// used several times in the pattern. These wires must link the same ports as
+/// in the pattern. This is guaranteed within a connected component, but not
+/// between components.
+fn is_boundary_consistent(
+    pattern: &CircuitPattern,
+    map: &HashMap<Node, Node>,
+    circ: &Circuit<impl HugrView>,
+) -> bool {
+    let hugr = circ.hugr();
+    let nodes: HashSet<Node> = map.values().copied().collect();
+    let inputs_consistent = pattern.inputs.iter().all(|ports| {
+        let sources = ports
+            .iter()
+            .map(|(n, p)| hugr.single_linked_output(map[n], p.as_incoming().unwrap()))
+            .collect_vec();
+        sources.iter().all_equal() && sources[0].map_or(true, |(n, _)| !nodes.contains(&n))
+    });
+    let copies_consistent = pattern
+        .copied_wires
+        .iter()
+        .all(|&((src, src_port), (n, p))| {
+            hugr.single_linked_output(map[&n], p.as_incoming().unwrap())
+                == Some((map[&src], src_port.as_outgoing().unwrap()))
+        });
+    let outputs_consistent = pattern.outputs.iter().all(|&(n, p)| {
+        // The targets of the wire within the pattern.
+        let targets: HashSet<_> = pattern
+            .copied_wires
+            .iter()
+            .filter(|&&(src, _)| src == (n, p))
+            .map(|&(_, (tgt, tgt_port))| (map[&tgt], tgt_port.as_incoming().unwrap()))
+            .collect();
+        hugr.linked_inputs(map[&n], p.as_outgoing().unwrap())
+            .all(|(n, p)| !nodes.contains(&n) || targets.contains(&(n, p)))
+    });
+    inputs_consistent && copies_consistent && outputs_consistent
+}
+
+/// The magic bytes at the start of a serialised matcher.
+const MATCHER_MAGIC: &[u8; 8] = b"TK2MATCH";
+
+/// The format version of serialised matchers.
+///
+/// Version 0 is the unversioned format of earlier releases, without a header.
+pub const MATCHER_FORMAT_VERSION: u32 = 1;
+
+/// Read as many bytes as possible into `buf`, stopping early at the end of the
+/// stream. Returns the number of bytes read.
+fn read_prefix(reader: &mut impl io::Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(k) => n += k,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
 }
 
 /// Errors that can occur when constructing matches.
@@ -410,6 +816,23 @@ pub enum MatcherSerialisationError {
     /// An error occurred during serialisation
     #[error("Serialisation error: {0}")]
     Serialisation(#[from] rmp_serde::encode::Error),
+    /// The matcher was saved in a newer format version.
+    #[error("Unsupported matcher format version {version}, this release supports versions up to {supported}")]
+    UnsupportedVersion {
+        /// The format version of the saved matcher.
+        version: u32,
+        /// The latest supported format version.
+        supported: u32,
+    },
+    /// The matcher was saved with a different encoding of its gates, and its
+    /// patterns would not match circuits using them.
+    #[error("Matcher gate set digest {digest:#018x} does not match the current encoding {expected:#018x}, the matcher must be recompiled from its patterns")]
+    GateSetMismatch {
+        /// The gate set digest of the saved matcher.
+        digest: u64,
+        /// The digest of the same gates in the current encoding.
+        expected: u64,
+    },
 }
 
 impl From<InvalidSubgraph> for InvalidPatternMatch {
@@ -451,10 +874,14 @@ pub(super) fn validate_circuit_edge(
             PEdge::InternalEdge {
                 src: src_port,
                 dst: dst_port,
-                ..
+                is_reversible,
             } => {
                 let (next_node, next_port) = hugr.linked_ports(src, src_port).exactly_one().ok()?;
-                (dst_port == next_port).then_some(NodeID::HugrNode(next_node))
+                // Reversible edges match wires with a single target, so that
+                // classical wires are matched the same way in both directions.
+                let single_target =
+                    !is_reversible || hugr.linked_ports(next_node, next_port).count() == 1;
+                (dst_port == next_port && single_target).then_some(NodeID::HugrNode(next_node))
             }
             PEdge::InputEdge { src: src_port } => {
                 let (next_node, next_port) = hugr.linked_ports(src, src_port).exactly_one().ok()?;
@@ -472,6 +899,9 @@ pub(crate) fn validate_circuit_node(
         let NodeID::HugrNode(node) = node else {
             return false;
         };
+        if circ.is_frozen(node) {
+            return false;
+        }
         &MatchOp::from(circ.hugr().get_optype(node).clone()) == prop
     }
 }
@@ -491,13 +921,24 @@ fn handle_match_error<T>(match_res: Result<T, InvalidPatternMatch>, root: Node)
 
 #[cfg(test)]
 mod tests {
+    use cool_asserts::assert_matches;
     use itertools::Itertools;
     use rstest::{fixture, rstest};
 
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr, DataflowSubContainer, SubContainer};
+    use hugr::extension::prelude::{BOOL_T, QB_T};
+    use hugr::type_row;
+    use hugr::types::Signature;
+    use hugr::{Hugr, Wire};
+
+    use crate::extension::REGISTRY;
     use crate::utils::build_simple_circuit;
     use crate::{Circuit, Tk2Op};
 
-    use super::{CircuitPattern, PatternMatcher};
+    use super::{
+        CircuitPattern, MatcherSerialisationError, PatternID, PatternMatcher,
+        MATCHER_FORMAT_VERSION, MATCHER_MAGIC,
+    };
 
     fn h_cx() -> Circuit {
         build_simple_circuit(2, |circ| {
@@ -548,6 +989,40 @@ mod tests {
         assert_eq!(matches.len(), 1);
     }
 
+    #[rstest]
+    fn pattern_ids_follow_input_order(cx_cx: Circuit) {
+        // Enough patterns for the line patterns to be converted on several threads.
+        let circs = [cx_xc(), cx_cx, h_cx()];
+        let patterns = (0..64)
+            .flat_map(|_| circs.iter())
+            .map(|circ| CircuitPattern::try_from_circuit(circ).unwrap())
+            .collect_vec();
+        let m = PatternMatcher::from_patterns(patterns);
+
+        let ids = m
+            .find_matches(&h_cx())
+            .iter()
+            .map(|pm| pm.pattern_id().0)
+            .sorted()
+            .collect_vec();
+        assert_eq!(ids, (0..64).map(|i| 3 * i + 2).collect_vec());
+    }
+
+    #[test]
+    fn frozen_nodes_are_not_matched() {
+        let mut circ = h_cx();
+
+        let p = CircuitPattern::try_from_circuit(&circ).unwrap();
+        let m = PatternMatcher::from_patterns(vec![p]);
+
+        let cx = circ.commands().nth(1).unwrap().node();
+        circ.freeze_nodes([cx]);
+        assert_eq!(m.find_matches(&circ).len(), 0);
+
+        circ.unfreeze_all();
+        assert_eq!(m.find_matches(&circ).len(), 1);
+    }
+
     #[test]
     fn serialise_round_trip() {
         let circs = [h_cx(), cx_xc()];
@@ -568,6 +1043,84 @@ mod tests {
         assert_eq!(buf, buf2);
     }
 
+    #[test]
+    fn serialised_header() {
+        let m =
+            PatternMatcher::from_patterns(vec![CircuitPattern::try_from_circuit(&h_cx()).unwrap()]);
+        let mut buf = Vec::new();
+        m.save_binary_io(&mut buf).unwrap();
+
+        assert_eq!(&buf[..8], MATCHER_MAGIC);
+        assert_eq!(buf[8..12], MATCHER_FORMAT_VERSION.to_le_bytes());
+        assert_eq!(buf[12..20], m.gate_set_digest().to_le_bytes());
+    }
+
+    #[test]
+    fn load_unversioned() {
+        let m =
+            PatternMatcher::from_patterns(vec![CircuitPattern::try_from_circuit(&h_cx()).unwrap()]);
+        let mut buf = Vec::new();
+        rmp_serde::encode::write(&mut buf, &m).unwrap();
+
+        let m2 = PatternMatcher::load_binary_io(&mut buf.as_slice()).unwrap();
+        assert_eq!(m2.n_patterns(), 1);
+        assert_eq!(m2.find_matches(&h_cx()).len(), 1);
+    }
+
+    #[test]
+    fn load_newer_version() {
+        let m =
+            PatternMatcher::from_patterns(vec![CircuitPattern::try_from_circuit(&h_cx()).unwrap()]);
+        let mut buf = Vec::new();
+        m.save_binary_io(&mut buf).unwrap();
+        buf[8..12].copy_from_slice(&(MATCHER_FORMAT_VERSION + 1).to_le_bytes());
+
+        assert_matches!(
+            PatternMatcher::load_binary_io(&mut buf.as_slice()),
+            Err(MatcherSerialisationError::UnsupportedVersion { .. })
+        );
+    }
+
+    #[test]
+    fn load_gate_set_mismatch() {
+        let m =
+            PatternMatcher::from_patterns(vec![CircuitPattern::try_from_circuit(&h_cx()).unwrap()]);
+        let mut buf = Vec::new();
+        m.save_binary_io(&mut buf).unwrap();
+        buf[12..20].copy_from_slice(&0u64.to_le_bytes());
+
+        assert_matches!(
+            PatternMatcher::load_binary_io(&mut buf.as_slice()),
+            Err(MatcherSerialisationError::GateSetMismatch { .. })
+        );
+    }
+
+    #[test]
+    fn gate_set_digest() {
+        let h_cx =
+            PatternMatcher::from_patterns(vec![CircuitPattern::try_from_circuit(&h_cx()).unwrap()]);
+        let cx_xc =
+            PatternMatcher::from_patterns(
+                vec![CircuitPattern::try_from_circuit(&cx_xc()).unwrap()],
+            );
+        assert_ne!(h_cx.gate_set_digest(), cx_xc.gate_set_digest());
+    }
+
+    #[test]
+    fn migrate_unversioned_file() {
+        let m =
+            PatternMatcher::from_patterns(vec![CircuitPattern::try_from_circuit(&h_cx()).unwrap()]);
+        let path = std::env::temp_dir().join(format!("tket2_matcher_{}.bin", std::process::id()));
+        std::fs::write(&path, rmp_serde::encode::to_vec(&m).unwrap()).unwrap();
+
+        PatternMatcher::migrate_binary(&path).unwrap();
+        let buf = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(&buf[..8], MATCHER_MAGIC);
+        let m2 = PatternMatcher::load_binary_io(&mut buf.as_slice()).unwrap();
+        assert_eq!(m2.find_matches(&h_cx()).len(), 1);
+    }
+
     #[rstest]
     fn cx_cx_replace_to_id(cx_cx: Circuit, cx_cx_3: Circuit) {
         let p = CircuitPattern::try_from_circuit(&cx_cx_3).unwrap();
@@ -576,4 +1129,234 @@ mod tests {
         let matches = m.find_matches(&cx_cx);
         assert_eq!(matches.len(), 0);
     }
+
+    /// Two CX gates on distinct qubits.
+    #[fixture]
+    fn parallel_cx() -> Circuit {
+        build_simple_circuit(4, |circ| {
+            circ.append(Tk2Op::CX, [0, 1]).unwrap();
+            circ.append(Tk2Op::CX, [2, 3]).unwrap();
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case::parallel(parallel_cx(), 2)]
+    #[case::single_cx(build_simple_circuit(2, |circ| {
+        circ.append(Tk2Op::CX, [0, 1]).unwrap();
+        Ok(())
+    }).unwrap(), 0)]
+    #[case::not_convex(build_simple_circuit(4, |circ| {
+        circ.append(Tk2Op::CX, [0, 1]).unwrap();
+        circ.append(Tk2Op::CX, [1, 2]).unwrap();
+        circ.append(Tk2Op::CX, [2, 3]).unwrap();
+        Ok(())
+    }).unwrap(), 0)]
+    fn disconnected_pattern_matches(#[case] circ: Circuit, #[case] n_matches: usize) {
+        let p = CircuitPattern::try_from_circuit(&parallel_cx()).unwrap();
+        let m = PatternMatcher::from_patterns(vec![
+            CircuitPattern::try_from_circuit(&h_cx()).unwrap(),
+            p,
+        ]);
+
+        let matches = m.find_matches(&circ);
+        assert_eq!(matches.len(), n_matches);
+        for pm in &matches {
+            assert_eq!(pm.pattern_id(), PatternID(1));
+            assert_eq!(pm.nodes().len(), 2);
+        }
+    }
+
+    #[rstest]
+    fn disconnected_pattern_matches_at(parallel_cx: Circuit) {
+        let p = CircuitPattern::try_from_circuit(&parallel_cx).unwrap();
+        let m = PatternMatcher::from_patterns(vec![p]);
+
+        let [cx1, cx2] = parallel_cx
+            .commands()
+            .map(|cmd| cmd.node())
+            .collect_vec()
+            .try_into()
+            .unwrap();
+        // Each match is rooted at both CX gates.
+        assert_eq!(m.find_matches_at(&parallel_cx, [cx1]).len(), 2);
+        assert_eq!(m.find_matches_at(&parallel_cx, [cx2]).len(), 2);
+        assert_eq!(m.find_matches_at(&parallel_cx, []).len(), 0);
+    }
+
+    /// Parallel CX gates at both ends of a long circuit.
+    #[fixture]
+    fn distant_parallel_cx() -> Circuit {
+        build_simple_circuit(4, |circ| {
+            circ.append(Tk2Op::CX, [0, 1]).unwrap();
+            circ.append(Tk2Op::CX, [2, 3]).unwrap();
+            for _ in 0..6 {
+                for q in 0..4 {
+                    circ.append(Tk2Op::X, [q]).unwrap();
+                }
+            }
+            circ.append(Tk2Op::CX, [0, 1]).unwrap();
+            circ.append(Tk2Op::CX, [2, 3]).unwrap();
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    #[rstest]
+    fn disconnected_pattern_matches_local(parallel_cx: Circuit, distant_parallel_cx: Circuit) {
+        let p = CircuitPattern::try_from_circuit(&parallel_cx).unwrap();
+        let m = PatternMatcher::from_patterns(vec![p]);
+        let cxs = distant_parallel_cx
+            .commands()
+            .filter(|cmd| cmd.optype() == &Tk2Op::CX.into())
+            .map(|cmd| cmd.node())
+            .sorted()
+            .collect_vec();
+
+        // Only the CX gates at the same end of the circuit are matched
+        // together.
+        assert_eq!(m.find_matches(&distant_parallel_cx).len(), 4);
+        let matches = m.find_matches_at(&distant_parallel_cx, [cxs[0]]);
+        assert_eq!(matches.len(), 2);
+        for pm in &matches {
+            assert_eq!(pm.nodes().iter().copied().sorted().collect_vec(), cxs[..2]);
+        }
+
+        let m = m.with_component_radius(usize::MAX);
+        assert_eq!(m.find_matches(&distant_parallel_cx).len(), 8);
+        assert_eq!(m.find_matches_at(&distant_parallel_cx, [cxs[0]]).len(), 4);
+    }
+
+    /// Apply an X gate to `q` if `bit` is set.
+    fn controlled_x(h: &mut DFGBuilder<Hugr>, bit: Wire, q: Wire) -> Wire {
+        let mut cond = h
+            .conditional_builder(
+                ([type_row![], type_row![]], bit),
+                [(QB_T, q)],
+                type_row![QB_T],
+            )
+            .unwrap();
+        let case0 = cond.case_builder(0).unwrap();
+        let [q] = case0.input_wires_arr();
+        case0.finish_with_outputs([q]).unwrap();
+        let mut case1 = cond.case_builder(1).unwrap();
+        let [q] = case1.input_wires_arr();
+        let [q] = case1.add_dataflow_op(Tk2Op::X, [q]).unwrap().outputs_arr();
+        case1.finish_with_outputs([q]).unwrap();
+        let [q] = cond.finish_sub_container().unwrap().outputs_arr();
+        q
+    }
+
+    /// A measurement of the first qubit controlling X gates on the others,
+    /// with the measurement result optionally returned.
+    fn measure_and_correct(n_qubits: usize, prefix_h: bool, return_bit: bool) -> Circuit {
+        let mut outputs = vec![QB_T; n_qubits];
+        if return_bit {
+            outputs.push(BOOL_T);
+        }
+        let mut h = DFGBuilder::new(Signature::new(vec![QB_T; n_qubits], outputs)).unwrap();
+        let mut qubits = h.input_wires().collect_vec();
+        if prefix_h {
+            qubits[0] = h
+                .add_dataflow_op(Tk2Op::H, [qubits[0]])
+                .unwrap()
+                .out_wire(0);
+        }
+        let [q, bit] = h
+            .add_dataflow_op(Tk2Op::Measure, [qubits[0]])
+            .unwrap()
+            .outputs_arr();
+        qubits[0] = q;
+        for q in &mut qubits[1..] {
+            *q = controlled_x(&mut h, bit, *q);
+        }
+        if return_bit {
+            qubits.push(bit);
+        }
+        h.finish_hugr_with_outputs(qubits, &REGISTRY)
+            .unwrap()
+            .into()
+    }
+
+    #[rstest]
+    #[case::single_use(2, false, false, 1)]
+    #[case::returned(2, true, true, 1)]
+    #[case::returned_by_circuit(2, false, true, 0)]
+    #[case::returned_by_pattern(2, true, false, 0)]
+    // The two corrections can be swapped.
+    #[case::copied(3, false, false, 2)]
+    #[case::copied_and_returned(3, true, true, 2)]
+    fn classical_wires(
+        #[case] n_qubits: usize,
+        #[case] pattern_bit: bool,
+        #[case] circ_bit: bool,
+        #[case] n_matches: usize,
+    ) {
+        let pattern = measure_and_correct(n_qubits, false, pattern_bit);
+        let p = CircuitPattern::try_from_circuit(&pattern).unwrap();
+        let m = PatternMatcher::from_patterns(vec![p]);
+
+        let circ = measure_and_correct(n_qubits, true, circ_bit);
+        let matches = m.find_matches(&circ);
+        assert_eq!(matches.len(), n_matches);
+        for pm in &matches {
+            assert_eq!(pm.nodes().len(), n_qubits);
+        }
+    }
+
+    /// Corrections controlled by two measurements must not match a pattern
+    /// with corrections controlled by a single measurement.
+    #[test]
+    fn copied_classical_wire_mismatch() {
+        let p = CircuitPattern::try_from_circuit(&measure_and_correct(3, false, false)).unwrap();
+        let m = PatternMatcher::from_patterns(vec![p]);
+
+        let mut h = DFGBuilder::new(Signature::new(vec![QB_T; 4], vec![QB_T; 4])).unwrap();
+        let [q0, q1, q2, q3] = h.input_wires_arr();
+        let [q0, bit0] = h
+            .add_dataflow_op(Tk2Op::Measure, [q0])
+            .unwrap()
+            .outputs_arr();
+        let [q3, bit3] = h
+            .add_dataflow_op(Tk2Op::Measure, [q3])
+            .unwrap()
+            .outputs_arr();
+        let q1 = controlled_x(&mut h, bit0, q1);
+        let q2 = controlled_x(&mut h, bit3, q2);
+        let circ: Circuit = h
+            .finish_hugr_with_outputs([q0, q1, q2, q3], &REGISTRY)
+            .unwrap()
+            .into();
+
+        assert_eq!(m.find_matches(&circ).len(), 0);
+    }
+
+    /// Two measurements in sequence, each controlling a correction. The
+    /// pattern can only be traversed by following the classical wires from
+    /// the measurements to the corrections.
+    #[test]
+    fn classical_wires_from_measurements() {
+        let mut h = DFGBuilder::new(Signature::new(vec![QB_T; 3], vec![QB_T; 3])).unwrap();
+        let [q0, q1, q2] = h.input_wires_arr();
+        let [q0, bit1] = h
+            .add_dataflow_op(Tk2Op::Measure, [q0])
+            .unwrap()
+            .outputs_arr();
+        let [q0, bit2] = h
+            .add_dataflow_op(Tk2Op::Measure, [q0])
+            .unwrap()
+            .outputs_arr();
+        let q1 = controlled_x(&mut h, bit1, q1);
+        let q2 = controlled_x(&mut h, bit2, q2);
+        let circ: Circuit = h
+            .finish_hugr_with_outputs([q0, q1, q2], &REGISTRY)
+            .unwrap()
+            .into();
+
+        let p = CircuitPattern::try_from_circuit(&circ).unwrap();
+        assert_eq!(p.n_components(), 1);
+        let m = PatternMatcher::from_patterns(vec![p]);
+        assert_eq!(m.find_matches(&circ).len(), 1);
+    }
 }
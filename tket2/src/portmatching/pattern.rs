@@ -5,6 +5,7 @@ use hugr::{Node, Port};
 use itertools::Itertools;
 use portmatching::{patterns::NoRootFound, HashMap, Pattern, SinglePatternMatcher};
 use std::fmt::Debug;
+use std::iter;
 use thiserror::Error;
 
 use super::{
@@ -14,55 +15,151 @@ use super::{
 use crate::{circuit::Circuit, portmatching::NodeID};
 
 /// A pattern that match a circuit exactly
+///
+/// The pattern may have several connected components, such as two CX gates
+/// on distinct qubits. Each component is matched separately, and a match of
+/// the pattern is a combination of matches of its components within the
+/// [component radius](super::PatternMatcher::component_radius) of each other.
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct CircuitPattern {
+    /// The first connected component of the pattern.
     pub(super) pattern: Pattern<NodeID, PNode, PEdge>,
     /// The input ports
     pub(super) inputs: Vec<Vec<(Node, Port)>>,
     /// The output ports
     pub(super) outputs: Vec<(Node, Port)>,
+    /// The other connected components of the pattern, if any.
+    #[serde(default)]
+    pub(super) components: Vec<Pattern<NodeID, PNode, PEdge>>,
+    /// The links of the classical wires used several times within the
+    /// pattern, from their source port to one of their target ports.
+    #[serde(default)]
+    pub(super) copied_wires: Vec<((Node, Port), (Node, Port))>,
 }
 
 impl CircuitPattern {
     /// The number of edges in the pattern.
     pub fn n_edges(&self) -> usize {
-        self.pattern.n_edges()
+        self.components().map(|p| p.n_edges()).sum()
+    }
+
+    /// The number of connected components of the pattern.
+    pub fn n_components(&self) -> usize {
+        1 + self.components.len()
+    }
+
+    /// The connected components of the pattern.
+    pub(super) fn components(&self) -> impl Iterator<Item = &Pattern<NodeID, PNode, PEdge>> {
+        iter::once(&self.pattern).chain(&self.components)
+    }
+
+    /// The operations of the pattern.
+    ///
+    /// Operations appearing several times are repeated.
+    pub(super) fn ops(&self) -> impl Iterator<Item = &PNode> {
+        self.components().flat_map(|pattern| {
+            let root = pattern.root().and_then(|root| pattern.node_property(root));
+            let edges = pattern.edges().unwrap_or_default();
+            let ops = edges
+                .into_iter()
+                .flat_map(|e| [e.source, e.target])
+                .flatten()
+                .filter_map(|node| pattern.node_property(node));
+            root.into_iter().chain(ops).collect_vec()
+        })
     }
 
     /// Construct a pattern from a circuit.
+    ///
+    /// The operations of the circuit are split into connected components,
+    /// linked by the wires between them. Components that only share an input
+    /// wire, such as two rotations by the same angle, are matched separately.
+    /// This also applies to classical values used several times: for example,
+    /// gates classically controlled by the same measurement are matched
+    /// separately from the measurement if they share no other wire with it.
     pub fn try_from_circuit(circuit: &Circuit) -> Result<Self, InvalidPattern> {
         let hugr = circuit.hugr();
         if circuit.num_operations() == 0 {
             return Err(InvalidPattern::EmptyCircuit);
         }
-        let mut pattern = Pattern::new();
+        // The operations and their edges, with the component of each operation.
+        let mut ops = Vec::new();
+        let mut edges = Vec::new();
+        let mut copied_wires = Vec::new();
+        let mut components = UnionFind::new();
         for cmd in circuit.commands() {
-            let op = cmd.optype().clone();
-            pattern.require(cmd.node().into(), op.into());
+            let node = cmd.node();
+            ops.push((node, cmd.optype().clone()));
+            components.insert(node);
             for in_offset in 0..cmd.input_count() {
                 let in_offset: IncomingPort = in_offset.into();
-                let edge_prop = PEdge::try_from_port(cmd.node(), in_offset.into(), circuit)
+                let edge_prop = PEdge::try_from_port(node, in_offset.into(), circuit)
                     .unwrap_or_else(|e| panic!("Invalid HUGR, {e}"));
                 let (prev_node, prev_port) = hugr
-                    .linked_outputs(cmd.node(), in_offset)
+                    .linked_outputs(node, in_offset)
                     .exactly_one()
                     .unwrap_or_else(|_| {
-                        panic!(
-                            "{} input port {in_offset} does not have a single neighbour",
-                            cmd.node()
-                        )
+                        panic!("{node} input port {in_offset} does not have a single neighbour")
                     });
-                let prev_node = match edge_prop {
-                    PEdge::InternalEdge { .. } => NodeID::HugrNode(prev_node),
-                    PEdge::InputEdge { .. } => NodeID::new_copy(prev_node, prev_port),
-                };
-                pattern.add_edge(cmd.node().into(), prev_node, edge_prop);
+                match edge_prop {
+                    PEdge::InternalEdge {
+                        is_reversible: true,
+                        ..
+                    } => {
+                        components.union(node, prev_node);
+                    }
+                    PEdge::InternalEdge { .. } => {
+                        copied_wires
+                            .push(((prev_node, prev_port.into()), (node, in_offset.into())));
+                    }
+                    PEdge::InputEdge { .. } => {}
+                }
+                edges.push((node, prev_node, prev_port, edge_prop));
             }
         }
-        pattern.set_any_root()?;
-        if !pattern.is_valid() {
-            return Err(InvalidPattern::NotConnected);
+        // Classical values used several times link their uses through hidden
+        // copy nodes, unless the uses are already in the same component as
+        // the value.
+        let edges = edges
+            .into_iter()
+            .map(|(node, prev_node, prev_port, edge_prop)| match edge_prop {
+                PEdge::InputEdge { .. } => {
+                    (node, NodeID::new_copy(prev_node, prev_port), edge_prop)
+                }
+                PEdge::InternalEdge { src, .. }
+                    if components.find(node) != components.find(prev_node) =>
+                {
+                    let copy = NodeID::new_copy(prev_node, prev_port);
+                    (node, copy, PEdge::InputEdge { src })
+                }
+                PEdge::InternalEdge { .. } => (node, NodeID::HugrNode(prev_node), edge_prop),
+            })
+            .collect_vec();
+        let mut patterns: Vec<Pattern<NodeID, PNode, PEdge>> = Vec::new();
+        let mut component_index = HashMap::default();
+        for (node, op) in ops {
+            let index = *component_index
+                .entry(components.find(node))
+                .or_insert_with(|| {
+                    patterns.push(Pattern::new());
+                    patterns.len() - 1
+                });
+            patterns[index].require(node.into(), op.into());
+        }
+        for (node, prev_node, edge_prop) in edges {
+            let index = component_index[&components.find(node)];
+            patterns[index].add_edge(node.into(), prev_node, edge_prop);
         }
+        for pattern in &mut patterns {
+            pattern.set_any_root()?;
+            if !pattern.is_valid() {
+                return Err(InvalidPattern::NotConnected);
+            }
+        }
+        let mut patterns = patterns.into_iter();
+        let pattern = patterns.next().expect("non-empty pattern");
+        let components = patterns.collect();
+
         let [inp, out] = circuit.io_nodes();
         let inp_ports = hugr.signature(inp).unwrap().output_ports();
         let out_ports = hugr.signature(out).unwrap().input_ports();
@@ -95,16 +192,36 @@ impl CircuitPattern {
             pattern,
             inputs,
             outputs,
+            components,
+            copied_wires,
         })
     }
 
     /// Compute the map from pattern nodes to circuit nodes in `circ`.
+    ///
+    /// For patterns with several connected components, only the nodes of the
+    /// first component are mapped. Use [`PatternMatcher::find_matches`] to
+    /// match all the components.
+    ///
+    /// [`PatternMatcher::find_matches`]: super::PatternMatcher::find_matches
     pub fn get_match_map(
         &self,
         root: Node,
         circ: &Circuit<impl HugrView>,
     ) -> Option<HashMap<Node, Node>> {
-        let single_matcher = SinglePatternMatcher::from_pattern(self.pattern.clone());
+        self.get_component_match_map(0, root, circ)
+    }
+
+    /// Compute the map from the nodes of a connected component of the pattern
+    /// to circuit nodes in `circ`.
+    pub(super) fn get_component_match_map(
+        &self,
+        component: usize,
+        root: Node,
+        circ: &Circuit<impl HugrView>,
+    ) -> Option<HashMap<Node, Node>> {
+        let pattern = self.components().nth(component)?;
+        let single_matcher = SinglePatternMatcher::from_pattern(pattern.clone());
         single_matcher
             .get_match_map(
                 root.into(),
@@ -125,9 +242,39 @@ impl CircuitPattern {
     }
 }
 
+/// A union-find structure over the nodes of a pattern.
+struct UnionFind(HashMap<Node, Node>);
+
+impl UnionFind {
+    fn new() -> Self {
+        Self(HashMap::default())
+    }
+
+    fn insert(&mut self, node: Node) {
+        self.0.entry(node).or_insert(node);
+    }
+
+    fn find(&self, mut node: Node) -> Node {
+        while let Some(&parent) = self.0.get(&node).filter(|&&p| p != node) {
+            node = parent;
+        }
+        node
+    }
+
+    fn union(&mut self, a: Node, b: Node) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.0.insert(a, b);
+    }
+}
+
 impl Debug for CircuitPattern {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.pattern.fmt(f)?;
+        for (i, pattern) in self.components().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            pattern.fmt(f)?;
+        }
         Ok(())
     }
 }
@@ -261,10 +408,11 @@ mod tests {
             Ok(())
         })
         .unwrap();
-        assert_eq!(
-            CircuitPattern::try_from_circuit(&circ).unwrap_err(),
-            InvalidPattern::NotConnected
-        );
+        let pattern = CircuitPattern::try_from_circuit(&circ).unwrap();
+        assert_eq!(pattern.n_components(), 2);
+        assert_eq!(pattern.n_edges(), 2);
+        assert_eq!(pattern.inputs.len(), 2);
+        assert_eq!(pattern.outputs.len(), 2);
     }
 
     #[test]
@@ -307,9 +455,15 @@ mod tests {
     #[test]
     fn pattern_with_copy_disconnected() {
         let circ = circ_with_copy_disconnected();
-        assert_eq!(
-            CircuitPattern::try_from_circuit(&circ).unwrap_err(),
-            InvalidPattern::NotConnected
-        );
+        let pattern = CircuitPattern::try_from_circuit(&circ).unwrap();
+        assert_eq!(pattern.n_components(), 2);
+        // Each component has its own copy of the shared input.
+        let inp = circ.input_node();
+        for component in pattern.components() {
+            let edges = component.edges().unwrap();
+            assert!(edges
+                .iter()
+                .any(|e| e.target.unwrap() == NodeID::new_copy(inp, 2)));
+        }
     }
 }
@@ -0,0 +1,448 @@
+//! Resource estimation for compiled circuits.
+//!
+//! [`estimate_resources`] counts the qubits, depth and the main gate counts of
+//! a circuit. Multi-controlled gates and nested dataflow graphs are expanded
+//! before counting, so that the estimate reflects the Clifford+T circuit that
+//! is eventually executed.
+//!
+//! A [`ResourceBudget`] bounds these figures, and can be used as a regression
+//! check on the size of compiled circuits:
+//!
+//! ```
+//! use tket2::resource::{estimate_resources, ResourceBudget};
+//! use tket2::serialize::{load_tk1_json_file, DecodeOptions};
+//!
+//! let circ =
+//!     load_tk1_json_file("../test_files/barenco_tof_5.json", DecodeOptions::default()).unwrap();
+//! let budget = ResourceBudget {
+//!     qubits: Some(9),
+//!     t_count: Some(100),
+//!     ..Default::default()
+//! };
+//! estimate_resources(&circ).assert_within(&budget);
+//! ```
+
+use std::fmt;
+
+use hugr::ops::{OpTrait, OpType};
+use hugr::HugrView;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::circuit::units::LinearUnit;
+use crate::extension::controlled::ControlledOp;
+use crate::passes::controlled::{as_controlled_op, controlled_decomposition};
+use crate::{Circuit, Tk2Op};
+
+/// An estimate of the resources required to run a circuit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ResourceEstimate {
+    /// The peak number of live qubits, including ancillas.
+    pub qubits: usize,
+    /// The number of layers of operations acting on qubits.
+    ///
+    /// Qubit allocations and frees do not contribute to the depth.
+    pub depth: usize,
+    /// The number of [`Tk2Op::CX`] gates.
+    pub cx_count: usize,
+    /// The number of [`Tk2Op::T`] and [`Tk2Op::Tdg`] gates.
+    pub t_count: usize,
+    /// The number of [`Tk2Op::Measure`] operations.
+    pub measurement_count: usize,
+}
+
+impl ResourceEstimate {
+    /// Check that the estimate is within a budget.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing every resource that exceeds its limit.
+    pub fn check_within(&self, budget: &ResourceBudget) -> Result<(), BudgetExceeded> {
+        let excess = Resource::ALL
+            .into_iter()
+            .filter_map(|resource| {
+                let limit = resource.limit(budget)?;
+                let used = resource.used(self);
+                (used > limit).then_some(ResourceExcess {
+                    resource,
+                    used,
+                    limit,
+                })
+            })
+            .collect_vec();
+        match excess.is_empty() {
+            true => Ok(()),
+            false => Err(BudgetExceeded { excess }),
+        }
+    }
+
+    /// Assert that the estimate is within a budget.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any resource exceeds its limit, listing all of them.
+    #[track_caller]
+    pub fn assert_within(&self, budget: &ResourceBudget) {
+        if let Err(err) = self.check_within(budget) {
+            panic!("{err}");
+        }
+    }
+}
+
+/// Upper bounds on the resources used by a circuit.
+///
+/// Resources without a limit are not checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ResourceBudget {
+    /// Maximum number of live qubits.
+    pub qubits: Option<usize>,
+    /// Maximum depth.
+    pub depth: Option<usize>,
+    /// Maximum number of CX gates.
+    pub cx_count: Option<usize>,
+    /// Maximum number of T gates.
+    pub t_count: Option<usize>,
+    /// Maximum number of measurements.
+    pub measurement_count: Option<usize>,
+}
+
+/// A resource tracked by a [`ResourceEstimate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Resource {
+    /// Number of live qubits.
+    Qubits,
+    /// Circuit depth.
+    Depth,
+    /// Number of CX gates.
+    CxCount,
+    /// Number of T gates.
+    TCount,
+    /// Number of measurements.
+    MeasurementCount,
+}
+
+impl Resource {
+    const ALL: [Resource; 5] = [
+        Resource::Qubits,
+        Resource::Depth,
+        Resource::CxCount,
+        Resource::TCount,
+        Resource::MeasurementCount,
+    ];
+
+    /// The amount of the resource used by an estimate.
+    pub fn used(&self, estimate: &ResourceEstimate) -> usize {
+        match self {
+            Resource::Qubits => estimate.qubits,
+            Resource::Depth => estimate.depth,
+            Resource::CxCount => estimate.cx_count,
+            Resource::TCount => estimate.t_count,
+            Resource::MeasurementCount => estimate.measurement_count,
+        }
+    }
+
+    /// The limit set on the resource by a budget, if any.
+    pub fn limit(&self, budget: &ResourceBudget) -> Option<usize> {
+        match self {
+            Resource::Qubits => budget.qubits,
+            Resource::Depth => budget.depth,
+            Resource::CxCount => budget.cx_count,
+            Resource::TCount => budget.t_count,
+            Resource::MeasurementCount => budget.measurement_count,
+        }
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Resource::Qubits => "qubits",
+            Resource::Depth => "depth",
+            Resource::CxCount => "CX count",
+            Resource::TCount => "T count",
+            Resource::MeasurementCount => "measurement count",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A resource used beyond its budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceExcess {
+    /// The resource.
+    pub resource: Resource,
+    /// The amount used by the circuit.
+    pub used: usize,
+    /// The limit in the budget.
+    pub limit: usize,
+}
+
+impl fmt::Display for ResourceExcess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} > {}", self.resource, self.used, self.limit)
+    }
+}
+
+/// Error returned when a circuit exceeds its [`ResourceBudget`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("The circuit exceeds its resource budget: {}.", excess.iter().join(", "))]
+pub struct BudgetExceeded {
+    /// The resources above their limit.
+    pub excess: Vec<ResourceExcess>,
+}
+
+/// Estimate the resources required to run a circuit.
+///
+/// Multi-controlled gates, including [`Tk2Op::CCX`] and [`Tk2Op::CCZ`], are
+/// counted as their Clifford+T decomposition (see
+/// [`controlled_decomposition`]), and nested dataflow graphs are counted as
+/// their contents. Any ancillas they allocate contribute to the qubit count.
+///
+/// The qubit count is the peak number of qubits live at once when executing
+/// the commands in the order of [`Circuit::commands`].
+pub fn estimate_resources(circ: &Circuit<impl HugrView>) -> ResourceEstimate {
+    Estimator::default().estimate(circ)
+}
+
+/// Estimates resources, caching the estimates of expanded gates.
+#[derive(Default)]
+struct Estimator {
+    gates: Vec<(ControlledOp, ResourceEstimate)>,
+}
+
+impl Estimator {
+    fn estimate(&mut self, circ: &Circuit<impl HugrView>) -> ResourceEstimate {
+        let mut estimate = ResourceEstimate::default();
+        let mut live = circ.qubit_count();
+        let mut peak = live;
+        let mut levels: Vec<usize> = Vec::new();
+        let level = |levels: &[usize], unit: LinearUnit| levels.get(unit.index()).copied();
+
+        for cmd in circ.commands() {
+            // The resources of the command, and the qubits it requires on top
+            // of the ones it acts on.
+            let (inner, extra_qubits) = self.command_estimate(circ, cmd.node(), cmd.optype());
+            estimate.cx_count += inner.cx_count;
+            estimate.t_count += inner.t_count;
+            estimate.measurement_count += inner.measurement_count;
+
+            let start = cmd
+                .linear_inputs()
+                .filter_map(|(unit, _, _)| level(&levels, unit))
+                .max()
+                .unwrap_or(0);
+            for (unit, _, _) in cmd.linear_outputs() {
+                if levels.len() <= unit.index() {
+                    levels.resize(unit.index() + 1, 0);
+                }
+                levels[unit.index()] = start + inner.depth;
+            }
+
+            let inputs = cmd.input_qubits().count();
+            let outputs = cmd.output_qubits().count();
+            peak = peak.max(live + extra_qubits);
+            live = (live + outputs).saturating_sub(inputs);
+            peak = peak.max(live);
+        }
+
+        estimate.depth = levels.into_iter().max().unwrap_or(0);
+        estimate.qubits = peak;
+        estimate
+    }
+
+    /// Returns the resources used by a command, and the number of ancillas it
+    /// requires.
+    fn command_estimate(
+        &mut self,
+        circ: &Circuit<impl HugrView>,
+        node: hugr::Node,
+        optype: &OpType,
+    ) -> (ResourceEstimate, usize) {
+        if let Some(op) = as_controlled_op(optype) {
+            let estimate = self.controlled_estimate(op);
+            return (estimate, estimate.qubits - op.num_qubits());
+        }
+        if let OpType::DFG(_) = optype {
+            let inner = Circuit::new(circ.hugr().base_hugr(), node);
+            let estimate = self.estimate(&inner);
+            return (estimate, estimate.qubits - inner.qubit_count());
+        }
+
+        let mut estimate = ResourceEstimate::default();
+        let op = Tk2Op::try_from(optype).ok();
+        match op {
+            Some(Tk2Op::CX) => estimate.cx_count = 1,
+            Some(Tk2Op::T | Tk2Op::Tdg) => estimate.t_count = 1,
+            Some(Tk2Op::Measure) => estimate.measurement_count = 1,
+            _ => {}
+        }
+        let acts_on_qubits = optype
+            .dataflow_signature()
+            .is_some_and(|sig| sig.input_types().iter().any(|t| !t.copyable()));
+        if acts_on_qubits && !matches!(op, Some(Tk2Op::QAlloc | Tk2Op::QFree)) {
+            estimate.depth = 1;
+        }
+        (estimate, 0)
+    }
+
+    /// The resources used by the decomposition of a multi-controlled gate.
+    fn controlled_estimate(&mut self, op: ControlledOp) -> ResourceEstimate {
+        if let Some((_, estimate)) = self.gates.iter().find(|(gate, _)| gate == &op) {
+            return *estimate;
+        }
+        let estimate = self.estimate(&controlled_decomposition(op));
+        self.gates.push((op, estimate));
+        estimate
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{Dataflow, DataflowHugr, DataflowSubContainer, FunctionBuilder};
+    use hugr::extension::prelude::QB_T;
+    use hugr::types::Signature;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::utils::build_simple_circuit;
+    use crate::Pauli;
+
+    fn estimate(qubits: usize, depth: usize, cx: usize, t: usize, m: usize) -> ResourceEstimate {
+        ResourceEstimate {
+            qubits,
+            depth,
+            cx_count: cx,
+            t_count: t,
+            measurement_count: m,
+        }
+    }
+
+    #[rstest]
+    #[case::empty(build_simple_circuit(2, |_| Ok(())).unwrap(), estimate(2, 0, 0, 0, 0))]
+    #[case::gates(
+        build_simple_circuit(3, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::T, [2])?;
+            circ.append(Tk2Op::CX, [1, 2])?;
+            circ.append(Tk2Op::Tdg, [0])?;
+            circ.append(Tk2Op::Measure, [2])?;
+            Ok(())
+        }).unwrap(),
+        estimate(3, 4, 2, 2, 1)
+    )]
+    #[case::toffoli(
+        build_simple_circuit(3, |circ| {
+            circ.append(Tk2Op::CCX, [0, 1, 2])?;
+            Ok(())
+        }).unwrap(),
+        estimate_resources(&controlled_decomposition(ControlledOp::new(2, Pauli::X)))
+    )]
+    fn estimates(#[case] circ: Circuit, #[case] expected: ResourceEstimate) {
+        assert_eq!(estimate_resources(&circ), expected);
+    }
+
+    #[test]
+    fn toffoli_counts() {
+        let circ = build_simple_circuit(3, |circ| {
+            circ.append(Tk2Op::CCX, [0, 1, 2])?;
+            circ.append(Tk2Op::CCZ, [0, 1, 2])?;
+            Ok(())
+        })
+        .unwrap();
+        let estimate = estimate_resources(&circ);
+        assert_eq!(estimate.qubits, 3);
+        assert_eq!(estimate.cx_count, 12);
+        assert_eq!(estimate.t_count, 14);
+    }
+
+    #[test]
+    fn controlled_ancillas() {
+        let op = ControlledOp::new(4, Pauli::Z);
+        let circ = build_simple_circuit(5, |circ| {
+            circ.append(OpType::from(op), 0..5)?;
+            Ok(())
+        })
+        .unwrap();
+        let estimate = estimate_resources(&circ);
+        // Two ancillas are needed for a chain of Toffoli gates.
+        assert_eq!(estimate.qubits, 7);
+        assert_eq!(estimate.t_count, 5 * 7);
+    }
+
+    #[test]
+    fn nested_dfg() {
+        let mut func = FunctionBuilder::new("main", Signature::new_endo(vec![QB_T, QB_T])).unwrap();
+        let [q0, q1] = func.input_wires_arr();
+        let mut dfg = func
+            .dfg_builder(Signature::new_endo(vec![QB_T, QB_T]), [q0, q1])
+            .unwrap();
+        let [a, b] = dfg.input_wires_arr();
+        let [a, b] = dfg
+            .add_dataflow_op(Tk2Op::CX, [a, b])
+            .unwrap()
+            .outputs_arr();
+        let [b] = dfg.add_dataflow_op(Tk2Op::T, [b]).unwrap().outputs_arr();
+        let dfg = dfg.finish_with_outputs([a, b]).unwrap();
+        let [q0, q1] = dfg.outputs_arr();
+        let [q0] = func.add_dataflow_op(Tk2Op::H, [q0]).unwrap().outputs_arr();
+        let circ: Circuit = func
+            .finish_hugr_with_outputs([q0, q1], &REGISTRY)
+            .unwrap()
+            .into();
+
+        assert_eq!(estimate_resources(&circ), estimate(2, 3, 1, 1, 0));
+    }
+
+    #[test]
+    fn budget() {
+        let estimate = estimate(4, 10, 5, 7, 1);
+        let within = ResourceBudget {
+            qubits: Some(4),
+            t_count: Some(7),
+            ..Default::default()
+        };
+        estimate.assert_within(&within);
+        assert_eq!(estimate.check_within(&ResourceBudget::default()), Ok(()));
+
+        let tight = ResourceBudget {
+            depth: Some(8),
+            t_count: Some(6),
+            cx_count: Some(5),
+            ..Default::default()
+        };
+        let err = estimate.check_within(&tight).unwrap_err();
+        assert_eq!(
+            err.excess,
+            vec![
+                ResourceExcess {
+                    resource: Resource::Depth,
+                    used: 10,
+                    limit: 8
+                },
+                ResourceExcess {
+                    resource: Resource::TCount,
+                    used: 7,
+                    limit: 6
+                },
+            ]
+        );
+        assert_eq!(
+            err.to_string(),
+            "The circuit exceeds its resource budget: depth 10 > 8, T count 7 > 6."
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "qubits 4 > 3")]
+    fn assert_within_panics() {
+        let budget = ResourceBudget {
+            qubits: Some(3),
+            ..Default::default()
+        };
+        estimate(4, 0, 0, 0, 0).assert_within(&budget);
+    }
+}
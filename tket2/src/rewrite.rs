@@ -1,26 +1,50 @@
 //! Transform circuits using rewrite rules.
 
+pub mod architecture;
+pub mod builder;
 #[cfg(feature = "portmatching")]
 pub mod ecc_rewriter;
+pub mod incremental;
+pub mod independent;
+pub mod profile;
+#[cfg(feature = "portmatching")]
+pub mod rules;
 pub mod strategy;
 pub mod trace;
+pub mod transaction;
+
+use std::collections::{HashMap, HashSet};
 
+pub use builder::{RewriteBuildError, RewriteBuilder};
 use bytemuck::TransparentWrapper;
 #[cfg(feature = "portmatching")]
-pub use ecc_rewriter::ECCRewriter;
+pub use ecc_rewriter::{prune_eccs, ECCPruneOptions, ECCRewriter};
+pub use independent::{apply_independent, select_independent};
+#[cfg(feature = "portmatching")]
+pub use rules::RuleRewriter;
+pub use transaction::CircuitTransaction;
 
 use derive_more::{From, Into};
 use hugr::hugr::hugrmut::HugrMut;
 use hugr::hugr::views::sibling_subgraph::{InvalidReplacement, InvalidSubgraph};
 use hugr::hugr::views::ExtractHugr;
+use hugr::ops::{OpTag, OpTrait};
 use hugr::types::Signature;
 use hugr::{
     hugr::{views::SiblingSubgraph, Rewrite, SimpleReplacementError},
     SimpleReplacement,
 };
-use hugr::{Hugr, HugrView, Node};
+use hugr::{Hugr, HugrView, IncomingPort, Node, OutgoingPort, PortIndex};
+use itertools::Itertools;
+use ndarray::Array2;
+use num_complex::Complex64;
 
+use crate::circuit::opgroup::transfer_op_groups;
+use crate::circuit::phase::GlobalPhase;
 use crate::circuit::Circuit;
+use crate::sim::SimulationError;
+use thiserror::Error;
+use trace::RewriteTrace;
 
 /// A subcircuit of a circuit.
 #[derive(Debug, Clone, From, Into)]
@@ -51,11 +75,34 @@ impl Subcircuit {
         self.subgraph.node_count()
     }
 
+    /// The input ports of the subcircuit, grouped by the input of its
+    /// signature they correspond to.
+    pub fn incoming_ports(&self) -> &[Vec<(Node, IncomingPort)>] {
+        self.subgraph.incoming_ports()
+    }
+
+    /// The output ports of the subcircuit, in the order of its signature.
+    pub fn outgoing_ports(&self) -> &[(Node, OutgoingPort)] {
+        self.subgraph.outgoing_ports()
+    }
+
     /// The signature of the subcircuit.
     pub fn signature(&self, circ: &Circuit<impl HugrView>) -> Signature {
         self.subgraph.signature(circ.hugr())
     }
 
+    /// Compute the dense unitary matrix implemented by the subcircuit.
+    ///
+    /// The subcircuit qubits are ordered as in its [`Subcircuit::signature`].
+    /// See [`crate::sim::unitary`] for the matrix conventions.
+    pub fn unitary(
+        &self,
+        circ: &Circuit<impl HugrView>,
+    ) -> Result<Array2<Complex64>, SimulationError> {
+        let extracted: Circuit = self.subgraph.extract_subgraph(circ.hugr(), "").into();
+        extracted.unitary()
+    }
+
     /// Create a rewrite rule to replace the subcircuit with a new circuit.
     ///
     /// # Parameters
@@ -67,10 +114,11 @@ impl Subcircuit {
         replacement: Circuit<impl ExtractHugr>,
     ) -> Result<CircuitRewrite, InvalidReplacement> {
         // The replacement must be a Dfg rooted hugr.
-        let replacement = replacement
+        let mut replacement = replacement
             .extract_dfg()
             .unwrap_or_else(|e| panic!("{}", e))
             .into_hugr();
+        transfer_op_groups(circuit, self.nodes(), &mut replacement);
         Ok(CircuitRewrite(
             self.subgraph
                 .create_simple_replacement(circuit.hugr(), replacement)?,
@@ -89,10 +137,11 @@ impl CircuitRewrite {
         circuit: &Circuit<impl HugrView>,
         replacement: Circuit<impl ExtractHugr>,
     ) -> Result<Self, InvalidReplacement> {
-        let replacement = replacement
+        let mut replacement = replacement
             .extract_dfg()
             .unwrap_or_else(|e| panic!("{}", e))
             .into_hugr();
+        transfer_op_groups(circuit, circuit_position.nodes(), &mut replacement);
         circuit_position
             .subgraph
             .create_simple_replacement(circuit.hugr(), replacement)
@@ -119,6 +168,17 @@ impl CircuitRewrite {
         self.0.replacement().into()
     }
 
+    /// The global phase introduced by the rewrite, in half-turns.
+    ///
+    /// This is read from the [`METADATA_PHASE_DELTA`] metadata of the
+    /// replacement's root, and is zero if it is missing. Returns `None` if the
+    /// phase of the rewrite is unknown.
+    ///
+    /// [`METADATA_PHASE_DELTA`]: crate::circuit::phase::METADATA_PHASE_DELTA
+    pub fn phase_delta(&self) -> Option<GlobalPhase> {
+        self.replacement().phase_delta()
+    }
+
     /// Returns a set of nodes referenced by the rewrite. Modifying any these
     /// nodes will invalidate it.
     ///
@@ -129,11 +189,56 @@ impl CircuitRewrite {
         self.0.invalidation_set()
     }
 
+    /// The pairs of conflicting rewrites in `rewrites`, by index, with a node
+    /// witnessing each conflict.
+    ///
+    /// Two rewrites conflict if they remove a common node, or if the boundary
+    /// of one of them is linked to a node removed by the other. Rewrites that
+    /// only share nodes outside of their subcircuits, such as two rewrites on
+    /// parallel wires that both end at the output node, do not conflict and
+    /// can be applied together.
+    ///
+    /// An input of one rewrite linked to a node removed by another is an
+    /// output of the latter, so only the [invalidation
+    /// sets](CircuitRewrite::invalidation_set) need to be checked against the
+    /// removed nodes.
+    pub(crate) fn conflicts<'a>(
+        rewrites: impl IntoIterator<Item = &'a CircuitRewrite>,
+    ) -> Vec<(usize, usize, Node)> {
+        let rewrites = rewrites.into_iter().collect_vec();
+        let mut removed: HashMap<Node, Vec<usize>> = HashMap::new();
+        for (i, rw) in rewrites.iter().enumerate() {
+            for &node in rw.subcircuit().nodes() {
+                removed.entry(node).or_default().push(i);
+            }
+        }
+        let mut conflicts = Vec::new();
+        for (i, rw) in rewrites.iter().enumerate() {
+            for node in rw.invalidation_set().unique() {
+                let Some(removing) = removed.get(&node) else {
+                    continue;
+                };
+                conflicts.extend(
+                    removing
+                        .iter()
+                        .filter(|&&j| j != i)
+                        .map(|&j| (i.min(j), i.max(j), node)),
+                );
+            }
+        }
+        conflicts.sort_unstable();
+        conflicts.dedup_by_key(|&mut (i, j, _)| (i, j));
+        conflicts
+    }
+
     /// Apply the rewrite rule to a circuit.
+    ///
+    /// The [phase](CircuitRewrite::phase_delta) of the rewrite is added to the
+    /// global phase of the circuit.
     #[inline]
     pub fn apply(self, circ: &mut Circuit<impl HugrMut>) -> Result<(), SimpleReplacementError> {
         circ.add_rewrite_trace(&self);
-        self.0.apply(circ.hugr_mut())
+        self.apply_notrace(circ)
     }
 
     /// Apply the rewrite rule to a circuit, without registering it in the rewrite trace.
@@ -142,12 +247,318 @@ impl CircuitRewrite {
         self,
         circ: &mut Circuit<impl HugrMut>,
     ) -> Result<(), SimpleReplacementError> {
-        self.0.apply(circ.hugr_mut())
+        let phase_delta = self.phase_delta();
+        self.0.apply(circ.hugr_mut())?;
+        circ.add_global_phase(phase_delta);
+        Ok(())
+    }
+
+    /// Apply a batch of independent rewrites to a circuit.
+    ///
+    /// The rewrites must not remove a common node, nor be linked to a node
+    /// removed by another rewrite of the batch. Rewrites may share boundary
+    /// nodes, such as the output node of the circuit.
+    ///
+    /// The boundaries of all the rewrites are resolved on the unmodified
+    /// circuit, then all the replacement nodes are inserted and connected, and
+    /// the replaced nodes are removed in a single pass. The batch is recorded
+    /// as a single entry of the rewrite trace.
+    ///
+    /// The circuit is left unchanged if an error is returned.
+    pub fn apply_batch(
+        circ: &mut Circuit<impl HugrMut>,
+        rewrites: impl IntoIterator<Item = CircuitRewrite>,
+    ) -> Result<(), BatchRewriteError> {
+        let rewrites: Vec<CircuitRewrite> = rewrites.into_iter().collect();
+        if let Some(&(_, _, node)) = Self::conflicts(&rewrites).first() {
+            return Err(BatchRewriteError::Overlap { node });
+        }
+        for rw in &rewrites {
+            rw.check_removal(circ.hugr())?;
+        }
+
+        // Resolve the boundary of each rewrite on the unmodified circuit.
+        let boundaries = rewrites
+            .iter()
+            .map(|rw| rw.boundary(circ.hugr()))
+            .collect_vec();
+        let hugr = circ.hugr_mut();
+        let mut connections = Vec::new();
+        for (rw, boundary) in rewrites.iter().zip(boundaries) {
+            let replacement = rw.0.replacement();
+            let parent = rw.0.subgraph().get_parent(hugr);
+            let output = hugr.children(parent).nth(1).unwrap();
+            let [rep_input, rep_output] = replacement.get_io(replacement.root()).unwrap();
+            let mut index_map = HashMap::new();
+            for node in replacement.children(replacement.root()).skip(2) {
+                let new_node = hugr.add_node_after(output, replacement.get_optype(node).clone());
+                hugr.overwrite_node_metadata(
+                    new_node,
+                    replacement.get_node_metadata(node).cloned(),
+                );
+                index_map.insert(node, new_node);
+            }
+            for (&node, &new_node) in &index_map {
+                for port in replacement.node_outputs(node) {
+                    for (target, target_port) in replacement.linked_inputs(node, port) {
+                        if target != rep_output {
+                            hugr.connect(new_node, port, index_map[&target], target_port);
+                        }
+                    }
+                }
+            }
+            // Map the boundary sources and targets to the inserted nodes.
+            let source = |(node, port): (Node, OutgoingPort)| match node == rep_input {
+                true => boundary.input_sources[port.index()],
+                false => Some((index_map[&node], port)),
+            };
+            for (i, source_port) in boundary.input_sources.iter().enumerate() {
+                let Some(source_port) = *source_port else {
+                    continue;
+                };
+                for (target, target_port) in replacement.linked_inputs(rep_input, i) {
+                    if target != rep_output {
+                        connections.push((source_port, (index_map[&target], target_port)));
+                    }
+                }
+            }
+            for (i, targets) in boundary.output_targets.into_iter().enumerate() {
+                let Some(source_port) = replacement
+                    .single_linked_output(rep_output, i)
+                    .and_then(source)
+                else {
+                    continue;
+                };
+                for target in targets {
+                    connections.push((source_port, target));
+                }
+            }
+        }
+        for ((source, source_port), (target, target_port)) in connections {
+            hugr.connect(source, source_port, target, target_port);
+        }
+        for rw in &rewrites {
+            for &node in rw.subcircuit().nodes() {
+                hugr.remove_node(node);
+            }
+        }
+
+        for rw in &rewrites {
+            circ.add_global_phase(rw.phase_delta());
+        }
+        if !rewrites.is_empty() {
+            circ.add_rewrite_trace(RewriteTrace::new(rewrites.len() as u16));
+        }
+        Ok(())
+    }
+
+    /// Check that the replaced nodes are leaves in a dataflow region of
+    /// `hugr`.
+    fn check_removal(&self, hugr: &impl HugrView) -> Result<(), SimpleReplacementError> {
+        let parent = self.0.subgraph().get_parent(hugr);
+        if !OpTag::DataflowParent.is_superset(hugr.get_optype(parent).tag()) {
+            return Err(SimpleReplacementError::InvalidParentNode());
+        }
+        for &node in self.subcircuit().nodes() {
+            if hugr.get_parent(node) != Some(parent) || hugr.children(node).next().is_some() {
+                return Err(SimpleReplacementError::InvalidRemovedNode());
+            }
+        }
+        Ok(())
+    }
+
+    /// The ports outside of the replaced subcircuit linked to its boundary.
+    fn boundary(&self, hugr: &impl HugrView) -> RewriteBoundary {
+        let subcircuit = self.subcircuit();
+        let input_sources = subcircuit
+            .incoming_ports()
+            .iter()
+            .map(|ports| {
+                let &(node, port) = ports.first()?;
+                hugr.single_linked_output(node, port)
+            })
+            .collect();
+        let output_targets = subcircuit
+            .outgoing_ports()
+            .iter()
+            .map(|&(node, port)| hugr.linked_inputs(node, port).collect())
+            .collect();
+        RewriteBoundary {
+            input_sources,
+            output_targets,
+        }
     }
 }
 
+/// The ports linked to the boundary of a rewrite's subcircuit.
+struct RewriteBoundary {
+    /// The source of each input of the subcircuit.
+    input_sources: Vec<Option<(Node, OutgoingPort)>>,
+    /// The targets of each output of the subcircuit.
+    output_targets: Vec<Vec<(Node, IncomingPort)>>,
+}
+
+/// Errors that can occur when applying a batch of rewrites with
+/// [`CircuitRewrite::apply_batch`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum BatchRewriteError {
+    /// Two rewrites of the batch overlap: a node removed by one of them is
+    /// removed or linked to by the other.
+    #[error("Two rewrites of the batch overlap at node {node}")]
+    Overlap {
+        /// A node removed by one rewrite and invalidated by the other.
+        node: Node,
+    },
+    /// A rewrite of the batch cannot be applied.
+    #[error(transparent)]
+    Replacement(#[from] SimpleReplacementError),
+}
+
 /// Generate rewrite rules for circuits.
 pub trait Rewriter {
     /// Get the rewrite rules for a circuit.
     fn get_rewrites(&self, circ: &Circuit<impl HugrView>) -> Vec<CircuitRewrite>;
+
+    /// Get the rewrite rules for a circuit that are rooted at one of `roots`.
+    ///
+    /// Each rewrite is rooted at one of the nodes of its subcircuit, chosen by
+    /// the rewriter. This is used to update rewrites incrementally, see
+    /// [`incremental`].
+    ///
+    /// The default implementation filters the output of
+    /// [`Rewriter::get_rewrites`], keeping the rewrites whose subcircuit
+    /// contains one of `roots`.
+    fn get_rewrites_at(
+        &self,
+        circ: &Circuit<impl HugrView>,
+        roots: &HashSet<Node>,
+    ) -> Vec<CircuitRewrite> {
+        self.get_rewrites(circ)
+            .into_iter()
+            .filter(|rw| rw.subcircuit().nodes().iter().any(|n| roots.contains(n)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cool_asserts::assert_matches;
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::circuit::CircuitHash;
+    use crate::extension::REGISTRY;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    fn n_cx(n_gates: usize) -> Circuit {
+        build_simple_circuit(2, |circ| {
+            for _ in 0..n_gates {
+                circ.append(Tk2Op::CX, [0, 1]).unwrap();
+            }
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    fn rewrite(circ: &Circuit, nodes: &[Node], replacement: Circuit) -> CircuitRewrite {
+        Subcircuit::try_from_nodes(nodes.to_vec(), circ)
+            .unwrap()
+            .create_rewrite(circ, replacement)
+            .unwrap()
+    }
+
+    #[test]
+    fn apply_batch() {
+        let mut circ = n_cx(8);
+        let cx = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        let rws = [
+            rewrite(&circ, &cx[0..2], n_cx(0)),
+            rewrite(&circ, &cx[3..6], n_cx(1)),
+        ];
+
+        let mut sequential = circ.clone();
+        for rw in rws.clone() {
+            rw.apply(&mut sequential).unwrap();
+        }
+        CircuitRewrite::apply_batch(&mut circ, rws).unwrap();
+
+        assert_eq!(circ.hugr().validate(&REGISTRY), Ok(()));
+        assert_eq!(circ.num_operations(), 4);
+        assert_eq!(circ.circuit_hash(), sequential.circuit_hash());
+    }
+
+    #[test]
+    fn apply_batch_mixed_replacements() {
+        let mut circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::X, [1])?;
+            circ.append(Tk2Op::X, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        let nodes = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        let identity = build_simple_circuit(1, |_| Ok(())).unwrap();
+        let z = build_simple_circuit(1, |circ| {
+            circ.append(Tk2Op::Z, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        let rws = [
+            rewrite(&circ, &nodes[0..2], identity),
+            rewrite(&circ, &nodes[3..5], z),
+        ];
+
+        CircuitRewrite::apply_batch(&mut circ, rws).unwrap();
+
+        assert_eq!(circ.hugr().validate(&REGISTRY), Ok(()));
+        let ops = circ
+            .commands()
+            .map(|cmd| Tk2Op::try_from(cmd.optype()).unwrap())
+            .collect_vec();
+        assert_eq!(ops, [Tk2Op::CX, Tk2Op::Z]);
+    }
+
+    #[test]
+    fn apply_batch_parallel() {
+        let mut circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::H, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        let h = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        let identity = || build_simple_circuit(1, |_| Ok(())).unwrap();
+        // Both rewrites end at the output node.
+        let rws = [
+            rewrite(&circ, &h[0..1], identity()),
+            rewrite(&circ, &h[1..2], identity()),
+        ];
+        let output = circ.output_node();
+        assert!(rws.iter().all(|rw| rw.invalidation_set().contains(&output)));
+
+        CircuitRewrite::apply_batch(&mut circ, rws).unwrap();
+
+        assert_eq!(circ.hugr().validate(&REGISTRY), Ok(()));
+        assert_eq!(circ.num_operations(), 0);
+    }
+
+    #[test]
+    fn apply_batch_overlap() {
+        let mut circ = n_cx(4);
+        let cx = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        let rws = [
+            rewrite(&circ, &cx[0..2], n_cx(0)),
+            rewrite(&circ, &cx[2..4], n_cx(0)),
+        ];
+
+        assert_matches!(
+            CircuitRewrite::apply_batch(&mut circ, rws),
+            Err(BatchRewriteError::Overlap { node }) => assert_eq!(node, cx[2])
+        );
+        assert_eq!(circ.num_operations(), 4);
+    }
 }
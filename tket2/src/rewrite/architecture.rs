@@ -0,0 +1,484 @@
+//! Rewrites for circuits targeting devices with a constrained connectivity.
+//!
+//! An [`Architecture`] describes which pairs of qubits two-qubit gates can act
+//! on, and in which direction CX gates are natively supported.
+//!
+//! This module provides
+//! - [`ArchitectureRewriter`], a rewrite rule source with CX-reversal and
+//!   bridge rules, restricted to the parts of the circuit that do not respect
+//!   the architecture, and
+//! - [`ArchitectureAwareStrategy`], a wrapper around a [`RewriteStrategy`]
+//!   that penalises or forbids gates acting on qubits that are not coupled in
+//!   the architecture.
+
+use std::collections::{BTreeSet, HashSet};
+
+use hugr::ops::OpType;
+use hugr::{HugrView, Node};
+use itertools::Itertools;
+
+use crate::circuit::cost::LexicographicCost;
+use crate::circuit::units::Qubit;
+use crate::circuit::Command;
+use crate::ops::op_matches;
+use crate::{Circuit, Tk2Op};
+
+#[cfg(feature = "portmatching")]
+use super::rules::{RuleError, RuleRewriter};
+use super::strategy::{RewriteResult, RewriteStrategy};
+use super::CircuitRewrite;
+#[cfg(feature = "portmatching")]
+use super::Rewriter;
+
+/// The rules used by [`ArchitectureRewriter`], in the format of
+/// [`super::rules`].
+///
+/// The bridge rules replace a CX between two qubits with a sequence of CX gates
+/// through a third qubit. The third qubit must already be used by an adjacent
+/// CX so that the rewrite is local to the matched gates.
+pub const ARCHITECTURE_RULES: &str = r"
+    hh: H 0; H 0 =>
+    cx_cancel: CX 0 1; CX 0 1 =>
+    cx_reverse: CX 0 1 <=> H 0; H 1; CX 1 0; H 0; H 1
+    bridge_control: CX 0 1; CX 0 2 <=> CX 1 2; CX 0 1; CX 1 2
+    bridge_target: CX 1 2; CX 0 2 <=> CX 0 1; CX 1 2; CX 0 1
+";
+
+/// The qubit connectivity of a device.
+///
+/// Qubits are identified by their [`Qubit`] index in the circuit inputs, and
+/// the constructors also accept plain indices. Each coupling
+/// is directed: a coupling `(a, b)` means that a CX with control `a` and target
+/// `b` is natively supported. Any other two-qubit gate is supported between
+/// qubits coupled in either direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Architecture {
+    /// The number of qubits of the device.
+    num_qubits: usize,
+    /// The directed couplings between qubits.
+    edges: BTreeSet<(Qubit, Qubit)>,
+}
+
+impl Architecture {
+    /// Create a new architecture from a list of directed couplings.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a coupling refers to a qubit outside of `0..num_qubits`, or
+    /// couples a qubit with itself.
+    pub fn new<Q: Into<Qubit>>(num_qubits: usize, edges: impl IntoIterator<Item = (Q, Q)>) -> Self {
+        let edges: BTreeSet<(Qubit, Qubit)> = edges
+            .into_iter()
+            .map(|(a, b)| (a.into(), b.into()))
+            .collect();
+        for &(a, b) in &edges {
+            assert!(
+                a.index() < num_qubits && b.index() < num_qubits,
+                "Coupling ({a}, {b}) is out of range for {num_qubits} qubits"
+            );
+            assert!(a != b, "Qubit {a} cannot be coupled with itself");
+        }
+        Self { num_qubits, edges }
+    }
+
+    /// Create a new architecture where every coupling supports CX gates in
+    /// both directions.
+    pub fn undirected<Q: Into<Qubit>>(
+        num_qubits: usize,
+        edges: impl IntoIterator<Item = (Q, Q)>,
+    ) -> Self {
+        let edges = edges.into_iter().flat_map(|(a, b)| {
+            let (a, b): (Qubit, Qubit) = (a.into(), b.into());
+            [(a, b), (b, a)]
+        });
+        Self::new(num_qubits, edges)
+    }
+
+    /// A line of `num_qubits` qubits, with CX gates supported from each qubit
+    /// to the next one.
+    pub fn line(num_qubits: usize) -> Self {
+        Self::new(num_qubits, (1..num_qubits).map(|q| (q - 1, q)))
+    }
+
+    /// The number of qubits of the device.
+    #[inline]
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    /// The directed couplings of the device.
+    #[inline]
+    pub fn edges(&self) -> impl Iterator<Item = (Qubit, Qubit)> + '_ {
+        self.edges.iter().copied()
+    }
+
+    /// Whether a CX with the given control and target is natively supported.
+    #[inline]
+    pub fn supports_cx(&self, control: Qubit, target: Qubit) -> bool {
+        self.edges.contains(&(control, target))
+    }
+
+    /// Whether two qubits are coupled, in either direction.
+    #[inline]
+    pub fn are_adjacent(&self, a: Qubit, b: Qubit) -> bool {
+        self.supports_cx(a, b) || self.supports_cx(b, a)
+    }
+
+    /// Whether an operation acting on the given qubits respects the
+    /// architecture.
+    ///
+    /// Single-qubit operations are always supported, CX gates must act along
+    /// a coupling direction, other two-qubit operations on adjacent qubits.
+    /// Operations on more than two qubits are never supported.
+    pub fn supports_op(&self, op: &OpType, qubits: &[Qubit]) -> bool {
+        match *qubits {
+            [] | [_] => true,
+            [a, b] if op_matches(op, Tk2Op::CX) => self.supports_cx(a, b),
+            [a, b] => self.are_adjacent(a, b),
+            _ => false,
+        }
+    }
+
+    /// The number of operations in a circuit that do not respect the
+    /// architecture.
+    pub fn count_violations(&self, circ: &Circuit<impl HugrView>) -> usize {
+        circ.commands()
+            .filter(|cmd| !self.supports_command(cmd))
+            .count()
+    }
+
+    /// The operations of a circuit that do not respect the architecture.
+    pub fn violations(&self, circ: &Circuit<impl HugrView>) -> HashSet<Node> {
+        circ.commands()
+            .filter(|cmd| !self.supports_command(cmd))
+            .map(|cmd| cmd.node())
+            .collect()
+    }
+
+    /// Whether a command respects the architecture.
+    fn supports_command<T: HugrView>(&self, cmd: &Command<'_, T>) -> bool {
+        let qubits = cmd
+            .input_qubits()
+            .map(|(q, _, _)| Qubit::from(q))
+            .collect_vec();
+        self.supports_op(cmd.optype(), &qubits)
+    }
+}
+
+/// A rewriter with CX-reversal and bridge rules for an [`Architecture`].
+///
+/// Only rewrites that either touch an operation violating the architecture, or
+/// reduce the number of operations in the circuit, are returned. The rules are
+/// listed in [`ARCHITECTURE_RULES`].
+#[cfg(feature = "portmatching")]
+#[derive(Debug, Clone)]
+pub struct ArchitectureRewriter {
+    /// The rewriter for the architecture rules.
+    rules: RuleRewriter,
+    /// The target architecture.
+    arch: Architecture,
+}
+
+#[cfg(feature = "portmatching")]
+impl ArchitectureRewriter {
+    /// Create a new rewriter for an architecture, using [`ARCHITECTURE_RULES`].
+    pub fn new(arch: Architecture) -> Self {
+        let rules =
+            RuleRewriter::from_text(ARCHITECTURE_RULES).expect("The architecture rules are valid");
+        Self { rules, arch }
+    }
+
+    /// Create a new rewriter for an architecture, with additional rules in the
+    /// text format of [`super::rules`].
+    pub fn with_extra_rules(arch: Architecture, rules: &str) -> Result<Self, RuleError> {
+        let rules = RuleRewriter::from_text(&format!("{ARCHITECTURE_RULES}\n{rules}"))?;
+        Ok(Self { rules, arch })
+    }
+
+    /// The target architecture.
+    #[inline]
+    pub fn architecture(&self) -> &Architecture {
+        &self.arch
+    }
+
+    /// The underlying rule rewriter.
+    #[inline]
+    pub fn rules(&self) -> &RuleRewriter {
+        &self.rules
+    }
+
+    /// Keep the rewrites that are relevant for the architecture.
+    fn filter_rewrites(
+        &self,
+        circ: &Circuit<impl HugrView>,
+        rewrites: Vec<CircuitRewrite>,
+    ) -> Vec<CircuitRewrite> {
+        let violations = self.arch.violations(circ);
+        rewrites
+            .into_iter()
+            .filter(|rw| {
+                rw.node_count_delta() < 0
+                    || rw
+                        .subcircuit()
+                        .nodes()
+                        .iter()
+                        .any(|n| violations.contains(n))
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "portmatching")]
+impl Rewriter for ArchitectureRewriter {
+    fn get_rewrites(&self, circ: &Circuit<impl HugrView>) -> Vec<CircuitRewrite> {
+        let rewrites = self.rules.get_rewrites(circ);
+        self.filter_rewrites(circ, rewrites)
+    }
+
+    fn get_rewrites_at(
+        &self,
+        circ: &Circuit<impl HugrView>,
+        roots: &HashSet<Node>,
+    ) -> Vec<CircuitRewrite> {
+        let rewrites = self.rules.get_rewrites_at(circ, roots);
+        self.filter_rewrites(circ, rewrites)
+    }
+}
+
+/// How [`ArchitectureAwareStrategy`] handles operations that do not respect
+/// the architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArchitectureMode {
+    /// Discard any rewrite result with more violations than the original
+    /// circuit.
+    Forbid,
+    /// Add a penalty to the major cost component for each violation.
+    Penalise(usize),
+}
+
+impl Default for ArchitectureMode {
+    fn default() -> Self {
+        Self::Penalise(1)
+    }
+}
+
+/// A rewrite strategy that takes the connectivity of an [`Architecture`] into
+/// account.
+///
+/// Wraps a strategy with a lexicographic cost, and either penalises or forbids
+/// operations that do not respect the architecture. See [`ArchitectureMode`].
+#[derive(Debug, Clone)]
+pub struct ArchitectureAwareStrategy<S> {
+    /// The wrapped strategy.
+    strategy: S,
+    /// The target architecture.
+    arch: Architecture,
+    /// How to handle architecture violations.
+    mode: ArchitectureMode,
+}
+
+impl<S> ArchitectureAwareStrategy<S> {
+    /// Wrap a rewrite strategy for an architecture.
+    pub fn new(strategy: S, arch: Architecture, mode: ArchitectureMode) -> Self {
+        Self {
+            strategy,
+            arch,
+            mode,
+        }
+    }
+
+    /// The target architecture.
+    #[inline]
+    pub fn architecture(&self) -> &Architecture {
+        &self.arch
+    }
+
+    /// How architecture violations are handled.
+    #[inline]
+    pub fn mode(&self) -> ArchitectureMode {
+        self.mode
+    }
+
+    /// The wrapped strategy.
+    #[inline]
+    pub fn inner(&self) -> &S {
+        &self.strategy
+    }
+
+    /// The penalty for each architecture violation.
+    fn penalty(&self) -> usize {
+        match self.mode {
+            ArchitectureMode::Forbid => 0,
+            ArchitectureMode::Penalise(penalty) => penalty,
+        }
+    }
+}
+
+impl<S, const N: usize> RewriteStrategy for ArchitectureAwareStrategy<S>
+where
+    S: RewriteStrategy<Cost = LexicographicCost<usize, N>>,
+{
+    type Cost = LexicographicCost<usize, N>;
+
+    fn apply_rewrites(
+        &self,
+        rewrites: impl IntoIterator<Item = CircuitRewrite>,
+        circ: &Circuit,
+    ) -> impl Iterator<Item = RewriteResult<Self::Cost>> {
+        let violations = self.arch.count_violations(circ);
+        let mode = self.mode;
+        let penalty = self.penalty() as isize;
+        self.strategy
+            .apply_rewrites(rewrites, circ)
+            .filter_map(move |mut result| {
+                let new_violations = self.arch.count_violations(&result.circ);
+                match mode {
+                    ArchitectureMode::Forbid if new_violations > violations => return None,
+                    ArchitectureMode::Forbid => {}
+                    ArchitectureMode::Penalise(_) => {
+                        let delta = (new_violations as isize - violations as isize) * penalty;
+                        result.cost_delta += major_cost(delta);
+                    }
+                }
+                Some(result)
+            })
+    }
+
+    #[inline]
+    fn op_cost(&self, op: &OpType) -> Self::Cost {
+        self.strategy.op_cost(op)
+    }
+
+    fn circuit_cost(&self, circ: &Circuit<impl HugrView>) -> Self::Cost {
+        let cost = self.strategy.circuit_cost(circ);
+        match self.mode {
+            ArchitectureMode::Forbid => cost,
+            ArchitectureMode::Penalise(penalty) => {
+                cost + major_cost(penalty * self.arch.count_violations(circ))
+            }
+        }
+    }
+}
+
+/// A lexicographic cost with only the major component set.
+fn major_cost<T: Default + Copy, const N: usize>(major: T) -> LexicographicCost<T, N> {
+    let mut cost = [T::default(); N];
+    if let Some(first) = cost.first_mut() {
+        *first = major;
+    }
+    LexicographicCost::from(cost)
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "portmatching")]
+    use rstest::rstest;
+
+    use super::*;
+    #[cfg(feature = "portmatching")]
+    use crate::circuit::cost::CircuitCost;
+    #[cfg(feature = "portmatching")]
+    use crate::rewrite::strategy::LexicographicCostFunction;
+    use crate::utils::build_simple_circuit;
+
+    /// A CX against the direction of a line architecture, followed by a CX
+    /// between non-adjacent qubits.
+    fn misrouted_circ() -> Circuit {
+        build_simple_circuit(3, |circ| {
+            circ.append(Tk2Op::CX, [1, 0])?;
+            circ.append(Tk2Op::CX, [0, 2])?;
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn architecture() {
+        let arch = Architecture::line(3);
+        assert_eq!(arch.num_qubits(), 3);
+        let [q0, q1, q2] = [0, 1, 2].map(Qubit::new);
+        assert!(arch.supports_cx(q0, q1));
+        assert!(!arch.supports_cx(q1, q0));
+        assert!(arch.are_adjacent(q1, q0));
+        assert!(!arch.are_adjacent(q0, q2));
+        assert_eq!(arch.count_violations(&misrouted_circ()), 2);
+
+        let undirected = Architecture::undirected(3, [(0, 1), (1, 2)]);
+        assert!(undirected.supports_cx(q1, q0));
+        assert_eq!(undirected.count_violations(&misrouted_circ()), 1);
+    }
+
+    #[cfg(feature = "portmatching")]
+    #[test]
+    fn rules_preserve_unitary() {
+        let rewriter = ArchitectureRewriter::new(Architecture::line(3));
+        for rule in rewriter.rules().rules() {
+            let lhs = rule.lhs.unitary().unwrap();
+            let rhs = rule.rhs.unitary().unwrap();
+            assert!(
+                lhs.iter()
+                    .zip(rhs.iter())
+                    .all(|(a, b)| (a - b).norm() < 1e-9),
+                "rule {} does not preserve the unitary",
+                rule.name
+            );
+        }
+    }
+
+    #[cfg(feature = "portmatching")]
+    #[test]
+    fn rewrites_touch_violations() {
+        let arch = Architecture::line(3);
+        let rewriter = ArchitectureRewriter::new(arch.clone());
+        let circ = misrouted_circ();
+        let rewrites = rewriter.get_rewrites(&circ);
+        assert!(!rewrites.is_empty());
+
+        // The supported CX is never reversed on its own.
+        let correct = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(rewriter.get_rewrites(&correct).is_empty());
+
+        // Some rewrite reduces the number of violations.
+        assert!(rewrites.into_iter().any(|rw| {
+            let mut circ = circ.clone();
+            rw.apply(&mut circ).unwrap();
+            arch.count_violations(&circ) < 2
+        }));
+    }
+
+    #[cfg(feature = "portmatching")]
+    #[rstest]
+    #[case::forbid(ArchitectureMode::Forbid)]
+    #[case::penalise(ArchitectureMode::Penalise(10))]
+    fn strategy(#[case] mode: ArchitectureMode) {
+        let arch = Architecture::line(3);
+        let rewriter = ArchitectureRewriter::new(arch.clone());
+        let strategy =
+            ArchitectureAwareStrategy::new(LexicographicCostFunction::default_cx(), arch, mode);
+        let circ = misrouted_circ();
+
+        let base_cost = strategy.circuit_cost(&circ);
+        let results = strategy
+            .apply_rewrites(rewriter.get_rewrites(&circ), &circ)
+            .collect_vec();
+        assert!(!results.is_empty());
+        for result in results {
+            let violations = strategy.architecture().count_violations(&result.circ);
+            let cost = strategy.circuit_cost(&result.circ);
+            assert_eq!(base_cost.add_delta(&result.cost_delta), cost);
+            if mode == ArchitectureMode::Forbid {
+                assert!(violations <= 2);
+            }
+        }
+
+        if let ArchitectureMode::Penalise(penalty) = mode {
+            let inner_cost = strategy.inner().circuit_cost(&circ);
+            assert_eq!(base_cost.as_usize(), inner_cost.as_usize() + 2 * penalty);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Construction of rewrites from lists of operations.
+//!
+//! A [`RewriteBuilder`] builds the replacement of a [`Subcircuit`] by
+//! appending operations on the units of its signature, in the same way as
+//! hugr's [`CircuitBuilder`](hugr::builder::CircuitBuilder), without having to
+//! construct the replacement [`Hugr`] manually.
+
+use std::collections::HashMap;
+
+use hugr::builder::{BuildError, CircuitBuildError, DFGBuilder, Dataflow, DataflowHugr};
+use hugr::hugr::views::sibling_subgraph::InvalidReplacement;
+use hugr::ops::{OpType, Value};
+use hugr::std_extensions::arithmetic::float_types;
+use hugr::{CircuitUnit, Hugr, HugrView, Wire};
+use itertools::Itertools;
+use thiserror::Error;
+
+use super::{CircuitRewrite, Subcircuit};
+use crate::circuit::phase::GlobalPhase;
+use crate::extension::REGISTRY;
+use crate::Circuit;
+
+/// A builder for the replacement of a subcircuit.
+///
+/// The units of the builder are the inputs of the subcircuit's
+/// [signature](Subcircuit::signature), referred to by their index. As in
+/// hugr's [`CircuitBuilder`](hugr::builder::CircuitBuilder), an operation
+/// applied to a linear unit, such as a qubit, replaces it with the output on
+/// the same port. Copyable units, such as angles, can be used by any number of
+/// operations.
+///
+/// # Example
+///
+/// ```
+/// # use tket2::{Circuit, Tk2Op};
+/// # use tket2::rewrite::{RewriteBuilder, Subcircuit};
+/// /// Replace a subcircuit implementing a CX gate with a CZ conjugated by
+/// /// Hadamards.
+/// fn cx_to_cz(circ: &Circuit, subcircuit: Subcircuit) -> tket2::rewrite::CircuitRewrite {
+///     let mut builder = RewriteBuilder::new(subcircuit, circ).unwrap();
+///     builder
+///         .append(Tk2Op::H, [1])
+///         .unwrap()
+///         .append(Tk2Op::CZ, [0, 1])
+///         .unwrap()
+///         .append(Tk2Op::H, [1])
+///         .unwrap();
+///     builder.finish().unwrap()
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RewriteBuilder<'c, T> {
+    /// The circuit containing the subcircuit.
+    circ: &'c Circuit<T>,
+    /// The subcircuit to replace.
+    subcircuit: Subcircuit,
+    /// The replacement being built.
+    builder: DFGBuilder<Hugr>,
+    /// The current wire of each unit.
+    wires: Vec<Wire>,
+    /// Whether each unit is linear.
+    linear: Vec<bool>,
+    /// The global phase introduced by the rewrite.
+    phase_delta: Option<GlobalPhase>,
+}
+
+impl<'c, T: HugrView> RewriteBuilder<'c, T> {
+    /// Start building a replacement for `subcircuit`, a subcircuit of `circ`.
+    pub fn new(subcircuit: Subcircuit, circ: &'c Circuit<T>) -> Result<Self, RewriteBuildError> {
+        let signature = subcircuit.signature(circ);
+        let linear = signature
+            .input_types()
+            .iter()
+            .map(|ty| !ty.copyable())
+            .collect_vec();
+        // The replacement may introduce float constants.
+        let builder = DFGBuilder::new(signature.with_extension_delta(float_types::EXTENSION_ID))?;
+        let wires = builder.input_wires().collect_vec();
+        Ok(Self {
+            circ,
+            subcircuit,
+            builder,
+            wires,
+            linear,
+            phase_delta: Some(GlobalPhase::default()),
+        })
+    }
+
+    /// Set the global phase introduced by the rewrite, in half-turns.
+    ///
+    /// See [`CircuitRewrite::phase_delta`]. Defaults to zero.
+    pub fn with_phase_delta(mut self, phase_delta: Option<GlobalPhase>) -> Self {
+        self.phase_delta = phase_delta;
+        self
+    }
+
+    /// The number of units of the builder, i.e. the number of inputs of the
+    /// subcircuit.
+    pub fn n_units(&self) -> usize {
+        self.wires.len()
+    }
+
+    /// The current wire of a unit.
+    pub fn unit_wire(&self, index: usize) -> Option<Wire> {
+        self.wires.get(index).copied()
+    }
+
+    /// Append an operation on the units at `indices`.
+    #[inline]
+    pub fn append(
+        &mut self,
+        op: impl Into<OpType>,
+        indices: impl IntoIterator<Item = usize>,
+    ) -> Result<&mut Self, RewriteBuildError> {
+        self.append_and_consume(op, indices)
+    }
+
+    /// Append an operation on a list of units and wires, discarding its
+    /// non-linear outputs.
+    ///
+    /// Wires can be obtained from [`RewriteBuilder::append_with_outputs`] or
+    /// [`RewriteBuilder::add_constant`].
+    #[inline]
+    pub fn append_and_consume<A: Into<CircuitUnit>>(
+        &mut self,
+        op: impl Into<OpType>,
+        inputs: impl IntoIterator<Item = A>,
+    ) -> Result<&mut Self, RewriteBuildError> {
+        self.append_with_outputs(op, inputs)?;
+        Ok(self)
+    }
+
+    /// Append an operation on a list of units and wires, and return the
+    /// outputs of the operation that do not replace a linear unit.
+    pub fn append_with_outputs<A: Into<CircuitUnit>>(
+        &mut self,
+        op: impl Into<OpType>,
+        inputs: impl IntoIterator<Item = A>,
+    ) -> Result<Vec<Wire>, RewriteBuildError> {
+        let op = op.into();
+        // The linear units of the operation, by input port.
+        let mut linear_inputs = HashMap::new();
+        let input_wires = inputs
+            .into_iter()
+            .enumerate()
+            .map(|(port, unit)| match unit.into() {
+                CircuitUnit::Wire(wire) => Ok(wire),
+                CircuitUnit::Linear(index) => {
+                    let wire = self.unit_wire(index).ok_or_else(|| {
+                        BuildError::from(CircuitBuildError::InvalidWireIndex {
+                            op: Some(op.clone()),
+                            invalid_index: index,
+                        })
+                    })?;
+                    if self.linear[index] {
+                        linear_inputs.insert(port, index);
+                    }
+                    Ok(wire)
+                }
+            })
+            .collect::<Result<Vec<_>, BuildError>>()?;
+
+        let outputs = self
+            .builder
+            .add_dataflow_op(op.clone(), input_wires)?
+            .outputs()
+            .enumerate()
+            .filter_map(|(port, wire)| match linear_inputs.remove(&port) {
+                Some(index) => {
+                    self.wires[index] = wire;
+                    None
+                }
+                None => Some(wire),
+            })
+            .collect();
+        if !linear_inputs.is_empty() {
+            let index = linear_inputs.into_values().sorted().collect();
+            return Err(
+                BuildError::from(CircuitBuildError::MismatchedLinearInputs { op, index }).into(),
+            );
+        }
+        Ok(outputs)
+    }
+
+    /// Add a constant value to the replacement, and return its wire.
+    pub fn add_constant(&mut self, value: impl Into<Value>) -> Wire {
+        self.builder.add_load_value(value)
+    }
+
+    /// Finish the replacement, with the linear units as outputs in order, and
+    /// create the rewrite.
+    ///
+    /// This is the common case of subcircuits acting on qubits, with
+    /// copyable inputs such as angles not returned.
+    pub fn finish(self) -> Result<CircuitRewrite, RewriteBuildError> {
+        let outputs = (0..self.n_units())
+            .filter(|&index| self.linear[index])
+            .map(CircuitUnit::Linear)
+            .collect_vec();
+        self.finish_with_outputs(outputs)
+    }
+
+    /// Finish the replacement with the given units and wires as outputs, and
+    /// create the rewrite.
+    ///
+    /// The outputs must match the outputs of the subcircuit's signature.
+    pub fn finish_with_outputs<A: Into<CircuitUnit>>(
+        self,
+        outputs: impl IntoIterator<Item = A>,
+    ) -> Result<CircuitRewrite, RewriteBuildError> {
+        let outputs = outputs
+            .into_iter()
+            .map(|unit| match unit.into() {
+                CircuitUnit::Wire(wire) => Ok(wire),
+                CircuitUnit::Linear(index) => self.unit_wire(index).ok_or_else(|| {
+                    BuildError::from(CircuitBuildError::InvalidWireIndex {
+                        op: None,
+                        invalid_index: index,
+                    })
+                }),
+            })
+            .collect::<Result<Vec<_>, BuildError>>()?;
+        let mut replacement: Circuit = self
+            .builder
+            .finish_hugr_with_outputs(outputs, &REGISTRY)?
+            .into();
+        replacement.set_phase_delta(self.phase_delta);
+        Ok(self.subcircuit.create_rewrite(self.circ, replacement)?)
+    }
+}
+
+/// Error from a [`RewriteBuilder`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum RewriteBuildError {
+    /// The replacement could not be built.
+    #[error("Invalid replacement operations: {0}")]
+    Build(#[from] BuildError),
+    /// The replacement does not match the subcircuit.
+    #[error(transparent)]
+    Replacement(#[from] InvalidReplacement),
+}
+
+#[cfg(test)]
+mod test {
+    use cool_asserts::assert_matches;
+    use hugr::builder::FunctionBuilder;
+    use hugr::extension::prelude::QB_T;
+    use hugr::std_extensions::arithmetic::float_types::{ConstF64, FLOAT64_TYPE};
+    use hugr::types::Signature;
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::sim::unitary::equal_up_to_phase;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    fn subcircuit(circ: &Circuit) -> Subcircuit {
+        let nodes = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        Subcircuit::try_from_nodes(nodes, circ).unwrap()
+    }
+
+    #[test]
+    fn cx_to_cz() {
+        let mut circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::X, [1])?;
+            Ok(())
+        })
+        .unwrap();
+
+        let mut builder = RewriteBuilder::new(subcircuit(&circ), &circ).unwrap();
+        assert_eq!(builder.n_units(), 2);
+        builder
+            .append(Tk2Op::H, [1])
+            .unwrap()
+            .append(Tk2Op::CZ, [0, 1])
+            .unwrap()
+            .append(Tk2Op::H, [1])
+            .unwrap()
+            .append(Tk2Op::X, [1])
+            .unwrap();
+        let rewrite = builder.finish().unwrap();
+        assert_eq!(rewrite.node_count_delta(), 2);
+        assert_eq!(rewrite.phase_delta(), Some(GlobalPhase::default()));
+
+        let unitary = circ.unitary().unwrap();
+        rewrite.apply(&mut circ).unwrap();
+        assert_eq!(circ.num_operations(), 4);
+        assert!(equal_up_to_phase(&circ.unitary().unwrap(), &unitary, 1e-10));
+    }
+
+    #[test]
+    fn parametric() {
+        // A circuit with a single rotation by an input angle.
+        let mut h = FunctionBuilder::new(
+            "main",
+            Signature::new(vec![QB_T, FLOAT64_TYPE], vec![QB_T])
+                .with_extension_delta(float_types::EXTENSION_ID),
+        )
+        .unwrap();
+        let [q, angle] = h.input_wires_arr();
+        let rz = h.add_dataflow_op(Tk2Op::RzF64, [q, angle]).unwrap();
+        let mut circ: Circuit = h
+            .finish_hugr_with_outputs(rz.outputs(), &REGISTRY)
+            .unwrap()
+            .into();
+
+        // Apply the rotation twice, followed by a constant rotation.
+        let mut builder = RewriteBuilder::new(subcircuit(&circ), &circ)
+            .unwrap()
+            .with_phase_delta(None);
+        let angle = builder.add_constant(ConstF64::new(0.5));
+        builder
+            .append(Tk2Op::RzF64, [0, 1])
+            .unwrap()
+            .append(Tk2Op::RzF64, [0, 1])
+            .unwrap()
+            .append_and_consume(Tk2Op::RzF64, [CircuitUnit::Linear(0), angle.into()])
+            .unwrap();
+        let rewrite = builder.finish().unwrap();
+        assert_eq!(rewrite.phase_delta(), None);
+
+        rewrite.apply(&mut circ).unwrap();
+        let ops = circ
+            .commands()
+            .filter_map(|cmd| Tk2Op::try_from(cmd.optype()).ok())
+            .collect_vec();
+        assert_eq!(ops, [Tk2Op::RzF64; 3]);
+    }
+
+    #[test]
+    fn invalid_unit() {
+        let circ = build_simple_circuit(1, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            Ok(())
+        })
+        .unwrap();
+
+        let mut builder = RewriteBuilder::new(subcircuit(&circ), &circ).unwrap();
+        assert_matches!(
+            builder.append(Tk2Op::CX, [0, 1]),
+            Err(RewriteBuildError::Build(BuildError::CircuitError(
+                CircuitBuildError::InvalidWireIndex {
+                    invalid_index: 1,
+                    ..
+                }
+            )))
+        );
+    }
+
+    #[test]
+    fn invalid_outputs() {
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+
+        let builder = RewriteBuilder::new(subcircuit(&circ), &circ).unwrap();
+        assert_matches!(
+            builder.finish_with_outputs([0]),
+            Err(RewriteBuildError::Build(_))
+        );
+    }
+}
@@ -13,21 +13,34 @@
 //! of the Quartz repository.
 
 use derive_more::{From, Into};
-use hugr::{Hugr, HugrView, PortIndex};
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::hugr::views::sibling_subgraph::{InvalidReplacement, SiblingSubgraph};
+use hugr::ops::OpType;
+use hugr::{Hugr, HugrView, IncomingPort, Node, PortIndex, Wire};
 use itertools::Itertools;
 use portmatching::PatternID;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::{
-    collections::HashSet,
-    fs::File,
+    collections::{HashMap, HashSet},
     io,
-    path::{Path, PathBuf},
+    path::Path,
+};
+#[cfg(feature = "binary-eccs")]
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::PathBuf,
 };
 use thiserror::Error;
 
 use crate::{
-    circuit::{remove_empty_wire, Circuit},
-    optimiser::badger::{load_eccs_json_file, EqCircClass},
-    portmatching::{CircuitPattern, PatternMatcher},
+    circuit::{phase::GlobalPhase, remove_empty_wire, Circuit},
+    memory::{track_phase, Phase},
+    ops::op_matches,
+    optimiser::badger::{load_eccs_json_file, load_eccs_json_files, EqCircClass},
+    portmatching::{CircuitPattern, PatternMatch, PatternMatcher},
+    utils::{float_wire_value, load_float, remove_unused_param},
+    Tk2Op,
 };
 
 use super::{CircuitRewrite, Rewriter};
@@ -41,6 +54,11 @@ struct TargetID(usize);
 /// Valid rewrites turn a non-representative circuit into its representative,
 /// or a representative circuit into any of the equivalent non-representative
 /// circuits.
+///
+/// Replacement circuits may compute their angles from the matched parameters,
+/// e.g. replacing `Rz(a)·Rz(b)` by `Rz(a + b)`. When the matched angles are
+/// constant, the angle expressions are evaluated and the replacement uses the
+/// resulting constants instead.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ECCRewriter {
     /// Matcher for finding patterns.
@@ -54,6 +72,96 @@ pub struct ECCRewriter {
     /// Wires that have been removed in the pattern circuit -- to be removed
     /// in the target circuit as well when generating a rewrite.
     empty_wires: Vec<Vec<usize>>,
+    /// The number of qubits in each pattern, once empty wires are removed.
+    ///
+    /// Empty for rewriters serialised before this was recorded.
+    #[serde(default)]
+    pattern_qubits: Vec<usize>,
+    /// The global phase of each pattern, or `None` if it is unknown.
+    ///
+    /// Empty for rewriters serialised before phases were recorded, in which
+    /// case all the phases are zero.
+    #[serde(default)]
+    pattern_phases: Vec<Option<GlobalPhase>>,
+}
+
+/// Options for pruning a set of equivalence classes with [`prune_eccs`].
+///
+/// Pruning discards the circuits that can never match in the circuits to be
+/// optimised, reducing the size of the pattern matcher and the time needed to
+/// build or load it.
+#[derive(Debug, Clone, Default)]
+pub struct ECCPruneOptions {
+    /// The quantum gates allowed in the pruned circuits.
+    ///
+    /// Circuits containing other quantum gates are removed. Classical
+    /// operations, such as angle arithmetic, are always allowed.
+    ///
+    /// Defaults to `None`, allowing all gates.
+    pub gate_set: Option<HashSet<Tk2Op>>,
+    /// The maximum number of operations in the pruned circuits.
+    ///
+    /// Defaults to `None`, allowing circuits of any size.
+    pub max_pattern_size: Option<usize>,
+    /// The maximum number of qubits acted on by the pruned circuits.
+    ///
+    /// Qubits on empty wires are not counted, as they are removed from the
+    /// patterns. Set this to the qubit count of the circuits to be optimised
+    /// to skip patterns that can never match them.
+    ///
+    /// Defaults to `None`, allowing circuits of any width.
+    pub max_qubits: Option<usize>,
+}
+
+impl ECCPruneOptions {
+    /// Whether a circuit is kept by the pruning.
+    fn keeps(&self, circ: &Circuit<impl HugrView>) -> bool {
+        if self
+            .max_pattern_size
+            .is_some_and(|max| circ.num_operations() > max)
+        {
+            return false;
+        }
+        if self
+            .max_qubits
+            .is_some_and(|max| used_qubit_count(circ) > max)
+        {
+            return false;
+        }
+        let Some(gate_set) = &self.gate_set else {
+            return true;
+        };
+        circ.commands()
+            .all(|cmd| match Tk2Op::try_from(cmd.optype()) {
+                Ok(op) => !op.is_quantum() || gate_set.contains(&op),
+                Err(_) => true,
+            })
+    }
+}
+
+/// Prune a set of equivalence classes.
+///
+/// Removes the circuits rejected by `options` from every class. Classes that
+/// are left with fewer than two circuits define no rewrite rules and are
+/// discarded. The representative of each remaining class is the smallest of
+/// its remaining circuits.
+pub fn prune_eccs(
+    eccs: impl IntoIterator<Item = EqCircClass>,
+    options: &ECCPruneOptions,
+) -> Vec<EqCircClass> {
+    eccs.into_iter()
+        .filter_map(|ecc| {
+            let circs = ecc
+                .into_circuits()
+                .map(Circuit::from)
+                .filter(|circ| options.keeps(circ))
+                .collect_vec();
+            if circs.len() < 2 {
+                return None;
+            }
+            EqCircClass::from_circuits(circs).ok()
+        })
+        .collect()
 }
 
 impl ECCRewriter {
@@ -69,6 +177,47 @@ impl ECCRewriter {
         Ok(Self::from_eccs(eccs))
     }
 
+    /// Create a new rewriter from the equivalent circuit classes of several
+    /// JSON files.
+    ///
+    /// Classes sharing circuits are merged, so that each rewrite rule is only
+    /// generated once. See
+    /// [`merge_eccs`](crate::optimiser::badger::merge_eccs).
+    pub fn try_from_eccs_json_files(
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    ) -> io::Result<Self> {
+        let eccs = load_eccs_json_files(paths)?;
+        Ok(Self::from_eccs(eccs))
+    }
+
+    /// Create a new rewriter from equivalent circuit classes in JSON file,
+    /// keeping only the circuits accepted by `options`.
+    ///
+    /// See [`ECCRewriter::try_from_eccs_json_file`] and [`prune_eccs`].
+    pub fn try_from_eccs_json_file_pruned(
+        path: impl AsRef<Path>,
+        options: &ECCPruneOptions,
+    ) -> io::Result<Self> {
+        let eccs = load_eccs_json_file(path)?;
+        Ok(Self::from_eccs(prune_eccs(eccs, options)))
+    }
+
+    /// Create a new rewriter from the merged equivalent circuit classes of
+    /// several JSON files, keeping only the circuits accepted by `options`.
+    ///
+    /// Setting [`ECCPruneOptions::gate_set`] discards the rules whose pattern
+    /// or replacement contains other gates, so that rewriting never
+    /// introduces gates outside of the gate set.
+    ///
+    /// See [`ECCRewriter::try_from_eccs_json_files`] and [`prune_eccs`].
+    pub fn try_from_eccs_json_files_pruned(
+        paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        options: &ECCPruneOptions,
+    ) -> io::Result<Self> {
+        let eccs = load_eccs_json_files(paths)?;
+        Ok(Self::from_eccs(prune_eccs(eccs, options)))
+    }
+
     /// Create a new rewriter from a list of equivalent circuit classes.
     ///
     /// Equivalence classes are represented as [`EqCircClass`]s, lists of
@@ -79,12 +228,18 @@ impl ECCRewriter {
         let patterns = get_patterns(&eccs);
         let targets = into_targets(eccs);
         // Remove failed patterns
-        let (patterns, empty_wires, rewrite_rules): (Vec<_>, Vec<_>, Vec<_>) = patterns
+        let (patterns, empty_wires, rewrite_rules, pattern_qubits, pattern_phases): (
+            Vec<_>,
+            Vec<_>,
+            Vec<_>,
+            Vec<_>,
+            Vec<_>,
+        ) = patterns
             .into_iter()
             .zip(rewrite_rules)
             .filter_map(|(p, r)| {
                 // Filter out target IDs where empty wires are not empty
-                let (pattern, pattern_empty_wires) = p?;
+                let (pattern, pattern_empty_wires, n_qubits, phase) = p?;
                 let targets = r
                     .into_iter()
                     .filter(|&id| {
@@ -96,15 +251,86 @@ impl ECCRewriter {
                             .all(|&w| target_empty_wires.contains(&w))
                     })
                     .collect();
-                Some((pattern, pattern_empty_wires, targets))
+                Some((pattern, pattern_empty_wires, targets, n_qubits, phase))
             })
             .multiunzip();
-        let matcher = PatternMatcher::from_patterns(patterns);
+        let matcher = track_phase(Phase::MatcherBuild, || {
+            PatternMatcher::from_patterns(patterns)
+        });
         Self {
             matcher,
             targets,
             rewrite_rules,
             empty_wires,
+            pattern_qubits,
+            pattern_phases,
+        }
+    }
+
+    /// Specialise the rewriter to circuits with at most `n_qubits` qubits.
+    ///
+    /// Patterns acting on more qubits can never match such circuits. They are
+    /// dropped and the matcher is rebuilt without them, together with the
+    /// targets that are no longer used. This reduces the memory footprint and
+    /// matching time when optimising small circuits with large ECC sets.
+    ///
+    /// Rewriters serialised before pattern qubit counts were recorded are
+    /// returned unchanged.
+    pub fn specialise_to_qubits(&self, n_qubits: usize) -> Self {
+        if self.pattern_qubits.is_empty() || self.pattern_qubits.iter().all(|&q| q <= n_qubits) {
+            return self.clone();
+        }
+        let kept = (0..self.n_patterns())
+            .filter(|&i| self.pattern_qubits[i] <= n_qubits)
+            .collect_vec();
+
+        // Keep only the targets used by the remaining patterns.
+        let mut new_target_ids = vec![None; self.targets.len()];
+        let mut targets = Vec::new();
+        let rewrite_rules = kept
+            .iter()
+            .map(|&i| {
+                self.rewrite_rules[i]
+                    .iter()
+                    .map(|&TargetID(t)| {
+                        *new_target_ids[t].get_or_insert_with(|| {
+                            targets.push(self.targets[t].clone());
+                            TargetID(targets.len() - 1)
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        let patterns = kept
+            .iter()
+            .map(|&i| self.matcher.get_pattern(PatternID(i)).unwrap().clone())
+            .collect_vec();
+        let matcher = track_phase(Phase::MatcherBuild, || {
+            PatternMatcher::from_patterns(patterns)
+        });
+        Self {
+            matcher,
+            targets,
+            rewrite_rules,
+            empty_wires: kept.iter().map(|&i| self.empty_wires[i].clone()).collect(),
+            pattern_qubits: kept.iter().map(|&i| self.pattern_qubits[i]).collect(),
+            pattern_phases: kept
+                .iter()
+                .filter_map(|&i| self.pattern_phases.get(i).cloned())
+                .collect(),
+        }
+    }
+
+    /// The number of patterns in the rewriter.
+    pub fn n_patterns(&self) -> usize {
+        self.matcher.n_patterns()
+    }
+
+    /// The global phase of a pattern, or `None` if it is unknown.
+    fn pattern_phase(&self, pattern: PatternID) -> Option<GlobalPhase> {
+        match self.pattern_phases.get(pattern.0) {
+            Some(phase) => phase.clone(),
+            None => Some(GlobalPhase::default()),
         }
     }
 
@@ -124,10 +350,12 @@ impl ECCRewriter {
         &self,
         writer: W,
     ) -> Result<(), RewriterSerialisationError> {
-        let mut encoder = zstd::Encoder::new(writer, 9)?;
-        rmp_serde::encode::write(&mut encoder, &self)?;
-        encoder.finish()?;
-        Ok(())
+        track_phase(Phase::Serialisation, || {
+            let mut encoder = zstd::Encoder::new(writer, 9)?;
+            rmp_serde::encode::write(&mut encoder, &self)?;
+            encoder.finish()?;
+            Ok(())
+        })
     }
 
     /// Load a rewriter from an IO stream.
@@ -135,8 +363,10 @@ impl ECCRewriter {
     /// Loads streams as created by [`ECCRewriter::save_binary_io`].
     #[cfg(feature = "binary-eccs")]
     pub fn load_binary_io<R: io::Read>(reader: R) -> Result<Self, RewriterSerialisationError> {
-        let data = zstd::decode_all(reader)?;
-        Ok(rmp_serde::decode::from_slice(&data)?)
+        track_phase(Phase::MatcherBuild, || {
+            let data = zstd::decode_all(reader)?;
+            Ok(rmp_serde::decode::from_slice(&data)?)
+        })
     }
 
     /// Save a rewriter as a binary file.
@@ -171,28 +401,191 @@ impl ECCRewriter {
         // `zstd::decode_all`.
         Self::load_binary_io(&mut file)
     }
+
+    /// Create a rewriter from the ECC JSON file at `eccs_path`, caching the
+    /// compiled rewriter at `cache_path`.
+    ///
+    /// If `cache_path` holds a rewriter compiled from the current contents of
+    /// the JSON file, it is loaded without recompiling the patterns.
+    /// Otherwise, the rewriter is compiled from the JSON file, see
+    /// [`ECCRewriter::try_from_eccs_json_file`], and saved to `cache_path`
+    /// for later calls.
+    ///
+    /// Requires the `binary-eccs` feature to be enabled.
+    #[cfg(feature = "binary-eccs")]
+    pub fn try_from_eccs_json_file_cached(
+        eccs_path: impl AsRef<Path>,
+        cache_path: impl AsRef<Path>,
+    ) -> Result<Self, RewriterSerialisationError> {
+        let eccs_path = eccs_path.as_ref();
+        Self::cached(&[eccs_path], cache_path.as_ref(), false, || {
+            Self::try_from_eccs_json_file(eccs_path)
+        })
+    }
+
+    /// Create a rewriter from the merged ECC sets of several JSON files,
+    /// caching the compiled rewriter at `cache_path`.
+    ///
+    /// See [`ECCRewriter::try_from_eccs_json_file_cached`] and
+    /// [`ECCRewriter::try_from_eccs_json_files`]. The cache is recompiled if
+    /// any of the files changes.
+    ///
+    /// Requires the `binary-eccs` feature to be enabled.
+    #[cfg(feature = "binary-eccs")]
+    pub fn try_from_eccs_json_files_cached(
+        eccs_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        cache_path: impl AsRef<Path>,
+    ) -> Result<Self, RewriterSerialisationError> {
+        let eccs_paths = eccs_paths.into_iter().collect_vec();
+        Self::cached(&eccs_paths, cache_path.as_ref(), true, || {
+            Self::try_from_eccs_json_files(&eccs_paths)
+        })
+    }
+
+    /// Load the rewriter cached at `cache_path` if it was compiled from the
+    /// current contents of `eccs_paths`, or compile and cache it otherwise.
+    #[cfg(feature = "binary-eccs")]
+    fn cached(
+        eccs_paths: &[impl AsRef<Path>],
+        cache_path: &Path,
+        merged: bool,
+        compile: impl FnOnce() -> io::Result<Self>,
+    ) -> Result<Self, RewriterSerialisationError> {
+        let mut source_hashes = Vec::with_capacity(eccs_paths.len());
+        for path in eccs_paths {
+            source_hashes.push(fxhash::hash64(&fs::read(path)?));
+        }
+        let source_hash = fxhash::hash64(&(merged, source_hashes));
+        if let Some(rewriter) = Self::load_cache(cache_path, source_hash)? {
+            return Ok(rewriter);
+        }
+        let rewriter = compile()?;
+        rewriter.save_cache(cache_path, source_hash)?;
+        Ok(rewriter)
+    }
+
+    /// Load a cached rewriter, if it was compiled from a source with the
+    /// given hash.
+    #[cfg(feature = "binary-eccs")]
+    fn load_cache(
+        cache_path: &Path,
+        source_hash: u64,
+    ) -> Result<Option<Self>, RewriterSerialisationError> {
+        let mut file = match File::open(cache_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut header = [0; CACHE_HEADER_LEN];
+        match file.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        if header != cache_header(source_hash) {
+            return Ok(None);
+        }
+        Self::load_binary_io(&mut file).map(Some)
+    }
+
+    /// Save a rewriter compiled from a source with the given hash to a cache
+    /// file.
+    ///
+    /// The file is written under a temporary name first, so that concurrent
+    /// readers never see a partially written cache.
+    #[cfg(feature = "binary-eccs")]
+    fn save_cache(
+        &self,
+        cache_path: &Path,
+        source_hash: u64,
+    ) -> Result<(), RewriterSerialisationError> {
+        let mut tmp_path = cache_path.as_os_str().to_owned();
+        tmp_path.push(format!(".{}.tmp", std::process::id()));
+        let mut file = io::BufWriter::new(File::create(&tmp_path)?);
+        file.write_all(&cache_header(source_hash))?;
+        self.save_binary_io(&mut file)?;
+        file.into_inner().map_err(|e| e.into_error())?;
+        fs::rename(&tmp_path, cache_path)?;
+        Ok(())
+    }
 }
 
-impl Rewriter for ECCRewriter {
-    fn get_rewrites(&self, circ: &Circuit<impl HugrView>) -> Vec<CircuitRewrite> {
-        let matches = self.matcher.find_matches(circ);
+/// The magic bytes identifying a compiled rewriter cache file, followed by the
+/// format version.
+#[cfg(feature = "binary-eccs")]
+const CACHE_MAGIC: &[u8; 8] = b"TK2RWR\0\x01";
+
+/// The length of the header of a compiled rewriter cache file.
+#[cfg(feature = "binary-eccs")]
+const CACHE_HEADER_LEN: usize = 16;
+
+/// The header of a compiled rewriter cache file: the magic bytes, followed by
+/// the hash of the ECC JSON source.
+#[cfg(feature = "binary-eccs")]
+fn cache_header(source_hash: u64) -> [u8; CACHE_HEADER_LEN] {
+    let mut header = [0; CACHE_HEADER_LEN];
+    header[..8].copy_from_slice(CACHE_MAGIC);
+    header[8..].copy_from_slice(&source_hash.to_le_bytes());
+    header
+}
+
+impl ECCRewriter {
+    /// Build the rewrites for a set of pattern matches.
+    ///
+    /// Each rewrite records the global phase between its pattern and its
+    /// replacement. Rewrites with an unknown phase are not generated for
+    /// [phase-sensitive](Circuit::is_phase_sensitive) circuits.
+    fn matches_to_rewrites(
+        &self,
+        circ: &Circuit<impl HugrView>,
+        matches: Vec<PatternMatch>,
+    ) -> Vec<CircuitRewrite> {
+        let phase_sensitive = circ.is_phase_sensitive();
         matches
             .into_iter()
             .flat_map(|m| {
                 let pattern_id = m.pattern_id();
-                self.get_targets(pattern_id).map(move |repl| {
+                let pattern_phase = self.pattern_phase(pattern_id);
+                self.get_targets(pattern_id).filter_map(move |repl| {
+                    let phase_delta = repl
+                        .global_phase()
+                        .zip(pattern_phase.clone())
+                        .map(|(target, pattern)| target - pattern);
+                    if phase_sensitive && phase_delta.is_none() {
+                        return None;
+                    }
                     let mut repl = repl.to_owned();
                     for &empty_qb in self.empty_wires[pattern_id.0].iter().rev() {
                         remove_empty_wire(&mut repl, empty_qb).unwrap();
                     }
-                    m.to_rewrite(circ, repl).expect("invalid replacement")
+                    if !phase_delta.as_ref().is_some_and(GlobalPhase::is_zero) {
+                        repl.set_phase_delta(phase_delta);
+                    }
+                    Some(fold_matched_params(circ, &m, repl).expect("invalid replacement"))
                 })
             })
             .collect()
     }
 }
 
-/// Errors that can occur when (de)serialising an [`ECCRewriter`].
+impl Rewriter for ECCRewriter {
+    fn get_rewrites(&self, circ: &Circuit<impl HugrView>) -> Vec<CircuitRewrite> {
+        let matches = self.matcher.find_matches(circ);
+        self.matches_to_rewrites(circ, matches)
+    }
+
+    fn get_rewrites_at(
+        &self,
+        circ: &Circuit<impl HugrView>,
+        roots: &HashSet<Node>,
+    ) -> Vec<CircuitRewrite> {
+        let matches = self.matcher.find_matches_at(circ, roots.iter().copied());
+        self.matches_to_rewrites(circ, matches)
+    }
+}
+
+/// Errors that can occur when (de)serialising an [`ECCRewriter`] or a
+/// [`RuleRewriter`](super::RuleRewriter) rule pack.
 #[derive(Debug, Error)]
 pub enum RewriterSerialisationError {
     /// An IO error occurred
@@ -231,25 +624,43 @@ fn get_rewrite_rules(rep_sets: &[EqCircClass]) -> Vec<Vec<TargetID>> {
     rewrite_rules
 }
 
-/// For an equivalence class, return all valid patterns together with the
-/// indices of the wires that have been removed in the pattern circuit.
-fn get_patterns(rep_sets: &[EqCircClass]) -> Vec<Option<(CircuitPattern, Vec<usize>)>> {
+/// A valid pattern, together with the indices of the wires that have been
+/// removed in the pattern circuit, the number of qubits in the pattern and its
+/// global phase.
+type PatternData = (CircuitPattern, Vec<usize>, usize, Option<GlobalPhase>);
+
+/// For an equivalence class, return all valid patterns and their data.
+///
+/// The patterns are constructed in parallel.
+fn get_patterns(rep_sets: &[EqCircClass]) -> Vec<Option<PatternData>> {
     rep_sets
         .iter()
         .flat_map(|rs| rs.circuits())
+        .collect_vec()
+        .into_par_iter()
         .map(|hugr| {
             let mut circ: Circuit = hugr.clone().into();
+            let phase = circ.global_phase();
             let empty_qbs = empty_wires(&circ);
             for &qb in empty_qbs.iter().rev() {
                 remove_empty_wire(&mut circ, qb).unwrap();
             }
+            let n_qubits = circ.qubit_count();
             CircuitPattern::try_from_circuit(&circ)
                 .ok()
-                .map(|circ| (circ, empty_qbs))
+                .map(|pattern| (pattern, empty_qbs, n_qubits, phase))
         })
         .collect()
 }
 
+/// The number of qubits that are not on empty wires.
+fn used_qubit_count(circ: &Circuit<impl HugrView>) -> usize {
+    let empty = empty_wires(circ);
+    circ.qubits()
+        .filter(|(_, port, _)| !empty.contains(&port.index()))
+        .count()
+}
+
 /// The port offsets of wires that are empty.
 fn empty_wires(circ: &Circuit<impl HugrView>) -> Vec<usize> {
     let hugr = circ.hugr();
@@ -273,8 +684,171 @@ fn empty_wires(circ: &Circuit<impl HugrView>) -> Vec<usize> {
         .collect()
 }
 
+/// Construct the rewrite replacing a match with `repl`, folding the angle
+/// expressions of `repl` that only depend on constant matched parameters.
+///
+/// Every [`Tk2Op::AngleAdd`] expression of the replacement whose inputs are
+/// matched to constant angles in `circ` is replaced by the constant it
+/// computes, so that e.g. matched `Rz(0.5)·Rz(0.25)` gates are replaced by a
+/// single `Rz(0.75)`. The replacement inputs that are no longer used are
+/// removed, and the constants feeding them in `circ` are replaced along with
+/// the match. Only the constants that are used exclusively by the matched
+/// gates are folded.
+fn fold_matched_params(
+    circ: &Circuit<impl HugrView>,
+    m: &PatternMatch,
+    repl: Circuit,
+) -> Result<CircuitRewrite, InvalidReplacement> {
+    let hugr = circ.hugr();
+    let inputs = m.subcircuit().incoming_ports();
+    let values: HashMap<usize, f64> = inputs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, ports)| {
+            let &(node, port) = ports.first()?;
+            let (src, src_port) = hugr.single_linked_output(node, port)?;
+            let value = float_wire_value(hugr, Wire::new(src, src_port))?;
+            is_exclusive_param(hugr, src, ports).then_some((i, value))
+        })
+        .collect();
+    if values.is_empty() {
+        return m.to_rewrite(circ, repl);
+    }
+
+    let mut folded = repl.clone();
+    if !fold_angle_exprs(&mut folded, &values) {
+        return m.to_rewrite(circ, repl);
+    }
+    let input = folded.input_node();
+    let unused = values
+        .keys()
+        .copied()
+        .filter(|&i| folded.hugr().linked_inputs(input, i).next().is_none())
+        .sorted()
+        .collect_vec();
+    let subcirc_inputs = inputs
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !unused.contains(i))
+        .map(|(_, ports)| ports.clone())
+        .collect_vec();
+    let Ok(subgraph) = SiblingSubgraph::try_new(
+        subcirc_inputs,
+        m.subcircuit().outgoing_ports().to_vec(),
+        hugr,
+    ) else {
+        return m.to_rewrite(circ, repl);
+    };
+    for &i in unused.iter().rev() {
+        remove_empty_wire(&mut folded, i).unwrap();
+    }
+    CircuitRewrite::try_new(&subgraph.into(), circ, folded)
+}
+
+/// Check that the constant parameter computed by `node` is only used at
+/// `uses`, so that it can be removed along with them.
+fn is_exclusive_param(hugr: &impl HugrView, node: Node, uses: &[(Node, IncomingPort)]) -> bool {
+    let mut param_nodes = vec![node];
+    let mut i = 0;
+    while let Some(&n) = param_nodes.get(i) {
+        let is_param_op = match hugr.get_optype(n) {
+            OpType::Const(_) | OpType::LoadConstant(_) => true,
+            op => op_matches(op, Tk2Op::AngleAdd),
+        };
+        if !is_param_op {
+            return false;
+        }
+        for pred in hugr.input_neighbours(n) {
+            if !param_nodes.contains(&pred) {
+                param_nodes.push(pred);
+            }
+        }
+        i += 1;
+    }
+    hugr.all_linked_inputs(node)
+        .all(|link| uses.contains(&link))
+        && param_nodes[1..]
+            .iter()
+            .all(|&n| hugr.output_neighbours(n).all(|m| param_nodes.contains(&m)))
+}
+
+/// Replace the angle expressions of a circuit that are constant, given the
+/// values of some of its inputs, by their value.
+///
+/// `values` maps input port offsets to their value. Returns `true` if any
+/// expression was folded.
+fn fold_angle_exprs(circ: &mut Circuit, values: &HashMap<usize, f64>) -> bool {
+    let parent = circ.parent();
+    let input = circ.input_node();
+    let hugr = circ.hugr();
+    let exprs = hugr
+        .children(parent)
+        .filter(|&n| op_matches(hugr.get_optype(n), Tk2Op::AngleAdd))
+        .filter_map(|n| Some((n, param_value(hugr, input, values, Wire::new(n, 0))?)))
+        .collect_vec();
+    let is_expr = |n: Node| exprs.iter().any(|&(e, _)| e == n);
+
+    // Only the outermost expressions are replaced, the nested ones are removed
+    // once unused.
+    let mut folded = false;
+    for &(node, value) in &exprs {
+        let uses = circ
+            .hugr()
+            .linked_inputs(node, 0)
+            .filter(|&(n, _)| !is_expr(n))
+            .collect_vec();
+        if uses.is_empty() {
+            continue;
+        }
+        let hugr = circ.hugr_mut();
+        let load = load_float(hugr, parent, value);
+        for (n, port) in uses {
+            hugr.disconnect(n, port);
+            hugr.connect(load, 0, n, port);
+        }
+        folded = true;
+    }
+    for &(node, _) in &exprs {
+        if circ.hugr().valid_node(node) {
+            remove_unused_param(circ.hugr_mut(), node);
+        }
+    }
+    folded
+}
+
+/// The value of an angle in a circuit, given the values of some of its
+/// inputs.
+fn param_value(
+    hugr: &impl HugrView,
+    input: Node,
+    values: &HashMap<usize, f64>,
+    wire: Wire,
+) -> Option<f64> {
+    let node = wire.node();
+    if node == input {
+        return values.get(&wire.source().index()).copied();
+    }
+    if !op_matches(hugr.get_optype(node), Tk2Op::AngleAdd) {
+        return float_wire_value(hugr, wire);
+    }
+    (0..hugr.signature(node)?.input_count())
+        .map(|port| {
+            let (src, src_port) = hugr.single_linked_output(node, port)?;
+            param_value(hugr, input, values, Wire::new(src, src_port))
+        })
+        .sum()
+}
+
 #[cfg(test)]
 mod tests {
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::extension::prelude::QB_T;
+    use hugr::std_extensions::arithmetic::float_types::{ConstF64, FLOAT64_TYPE};
+    use hugr::types::Signature;
+
+    use crate::extension::REGISTRY;
+    use crate::optimiser::badger::merge_eccs;
+    use crate::utils::constant_params;
     use crate::{utils::build_simple_circuit, Tk2Op};
 
     use super::*;
@@ -390,6 +964,214 @@ mod tests {
         assert_eq!(rewriter.get_rewrites(&cx_cx).len(), 1);
     }
 
+    #[test]
+    fn prune_by_gate_set() {
+        let ecc1 = EqCircClass::new(h_h(), vec![empty(), cx_cx()]);
+        let ecc2 = EqCircClass::new(cx_x(), vec![x_cx()]);
+        let options = ECCPruneOptions {
+            gate_set: Some([Tk2Op::CX].into_iter().collect()),
+            ..Default::default()
+        };
+        let pruned = prune_eccs([ecc1, ecc2], &options);
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].n_circuits(), 2);
+        assert_eq!(Circuit::from(pruned[0].rep_circ()).num_operations(), 0);
+    }
+
+    #[test]
+    fn prune_files_by_gate_set() {
+        let test_files = [
+            "../test_files/eccs/small_eccs.json",
+            "../test_files/eccs/nam_4_2.json",
+        ];
+        let gate_set = [Tk2Op::H, Tk2Op::CX];
+        let options = ECCPruneOptions {
+            gate_set: Some(gate_set.into_iter().collect()),
+            ..Default::default()
+        };
+        let pruned = ECCRewriter::try_from_eccs_json_files_pruned(test_files, &options).unwrap();
+        assert!(pruned.n_patterns() > 0);
+        // No rewrite introduces gates outside the gate set.
+        for target in &pruned.targets {
+            let circ = Circuit::from(target);
+            assert!(circ
+                .commands()
+                .filter_map(|cmd| Tk2Op::try_from(cmd.optype()).ok())
+                .all(|op| !op.is_quantum() || gate_set.contains(&op)));
+        }
+    }
+
+    #[test]
+    fn prune_by_size() {
+        let test_file = "../test_files/eccs/small_eccs.json";
+        let full = ECCRewriter::try_from_eccs_json_file(test_file).unwrap();
+        let options = ECCPruneOptions {
+            max_pattern_size: Some(2),
+            ..Default::default()
+        };
+        let pruned = ECCRewriter::try_from_eccs_json_file_pruned(test_file, &options).unwrap();
+        assert!(pruned.n_patterns() < full.n_patterns());
+        assert!(pruned
+            .targets
+            .iter()
+            .all(|t| Circuit::from(t).num_operations() <= 2));
+    }
+
+    /// Two H gates on the first qubit, leaving the second wire empty.
+    fn h_h_q0() -> Circuit {
+        build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0]).unwrap();
+            circ.append(Tk2Op::H, [0]).unwrap();
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn prune_by_qubits() {
+        // The empty wire of `h_h_q0` does not count towards the qubit limit.
+        let ecc1 = EqCircClass::new(h_h(), vec![empty(), cx_cx()]);
+        let ecc2 = EqCircClass::new(empty(), vec![h_h_q0()]);
+        let options = ECCPruneOptions {
+            max_qubits: Some(1),
+            ..Default::default()
+        };
+        let pruned = prune_eccs([ecc1, ecc2], &options);
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].n_circuits(), 2);
+    }
+
+    #[test]
+    fn specialise_to_qubits() {
+        let ecc1 = EqCircClass::new(h_h(), vec![empty(), cx_cx()]);
+        let ecc2 = EqCircClass::new(empty(), vec![h_h_q0()]);
+        let rewriter = ECCRewriter::from_eccs(vec![ecc1, ecc2]);
+        assert_eq!(rewriter.pattern_qubits, [2, 2, 1]);
+
+        let small = rewriter.specialise_to_qubits(1);
+        assert_eq!(small.n_patterns(), 1);
+        assert_eq!(small.rewrite_rules, [vec![TargetID(0)]]);
+        assert_eq!(small.targets.len(), 1);
+        assert_eq!(small.get_rewrites(&h_h_q0()).len(), 1);
+
+        let large = rewriter.specialise_to_qubits(2);
+        assert_eq!(large.n_patterns(), rewriter.n_patterns());
+    }
+
+    fn t_x() -> Circuit {
+        build_simple_circuit(1, |circ| {
+            circ.append(Tk2Op::T, [0]).unwrap();
+            circ.append(Tk2Op::X, [0]).unwrap();
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    fn x_tdg(phase: Option<GlobalPhase>) -> Circuit {
+        let mut circ = build_simple_circuit(1, |circ| {
+            circ.append(Tk2Op::X, [0]).unwrap();
+            circ.append(Tk2Op::Tdg, [0]).unwrap();
+            Ok(())
+        })
+        .unwrap();
+        circ.set_global_phase(phase);
+        circ
+    }
+
+    #[test]
+    fn merge_ecc_files() {
+        let test_file = "../test_files/eccs/small_eccs.json";
+        // The file repeats some circuits of its classes, which are only kept
+        // once.
+        let single = ECCRewriter::try_from_eccs_json_files([test_file]).unwrap();
+        assert_eq!(single.targets.len(), 26);
+        let merged = ECCRewriter::try_from_eccs_json_files([test_file, test_file]).unwrap();
+        assert_eq!(merged.targets.len(), single.targets.len());
+        assert_eq!(merged.n_patterns(), single.n_patterns());
+    }
+
+    #[test]
+    fn merge_eccs_phase() {
+        let x_tdg_t_tdg = build_simple_circuit(1, |circ| {
+            circ.append(Tk2Op::X, [0]).unwrap();
+            circ.append(Tk2Op::Tdg, [0]).unwrap();
+            circ.append(Tk2Op::T, [0]).unwrap();
+            circ.append(Tk2Op::Tdg, [0]).unwrap();
+            Ok(())
+        })
+        .unwrap();
+        let ecc1 = EqCircClass::new(t_x(), vec![x_tdg(Some(GlobalPhase::new(0.25)))]);
+        let ecc2 = EqCircClass::new(x_tdg(Some(GlobalPhase::default())), vec![x_tdg_t_tdg]);
+        let ecc3 = EqCircClass::new(x_tdg(Some(GlobalPhase::default())), vec![t_x()]);
+        let ecc4 = EqCircClass::new(cx_x(), vec![x_cx()]);
+
+        let merged = merge_eccs([ecc1, ecc2, ecc3, ecc4]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].n_circuits(), 3);
+        assert_eq!(merged[1].n_circuits(), 2);
+        // The phase of the new circuit is relative to the first class.
+        let phases = merged[0]
+            .circuits()
+            .map(|hugr| {
+                let circ: Circuit<&Hugr> = hugr.into();
+                (circ.num_operations(), circ.global_phase())
+            })
+            .collect_vec();
+        assert!(phases.contains(&(2, Some(GlobalPhase::default()))));
+        assert!(phases.contains(&(2, Some(GlobalPhase::new(0.25)))));
+        assert!(phases.contains(&(4, Some(GlobalPhase::new(0.25)))));
+    }
+
+    #[test]
+    fn ecc_rewriter_phase() {
+        let ecc = EqCircClass::new(t_x(), vec![x_tdg(Some(GlobalPhase::new(0.25)))]);
+        let rewriter = ECCRewriter::from_eccs([ecc]);
+
+        let mut circ = t_x();
+        let rewrites = rewriter.get_rewrites(&circ);
+        assert_eq!(rewrites.len(), 1);
+        assert_eq!(rewrites[0].phase_delta(), Some(GlobalPhase::new(0.25)));
+
+        rewrites
+            .into_iter()
+            .next()
+            .unwrap()
+            .apply(&mut circ)
+            .unwrap();
+        assert_eq!(circ.global_phase(), Some(GlobalPhase::new(0.25)));
+
+        // The rewritten circuit implements the same operator.
+        let rotation = num_complex::Complex64::from_polar(1., std::f64::consts::FRAC_PI_4);
+        let expected = t_x().unitary().unwrap();
+        let unitary = circ.unitary().unwrap() * rotation;
+        assert!(expected
+            .iter()
+            .zip(unitary.iter())
+            .all(|(x, y)| (x - y).norm() < 1e-8));
+    }
+
+    #[test]
+    fn ecc_rewriter_unknown_phase() {
+        let ecc = EqCircClass::new(t_x(), vec![x_tdg(None)]);
+        let rewriter = ECCRewriter::from_eccs([ecc]);
+
+        let mut circ = t_x();
+        circ.set_phase_sensitive(true);
+        assert!(rewriter.get_rewrites(&circ).is_empty());
+
+        circ.set_phase_sensitive(false);
+        let rewrites = rewriter.get_rewrites(&circ);
+        assert_eq!(rewrites.len(), 1);
+        assert_eq!(rewrites[0].phase_delta(), None);
+        rewrites
+            .into_iter()
+            .next()
+            .unwrap()
+            .apply(&mut circ)
+            .unwrap();
+        assert_eq!(circ.global_phase(), None);
+    }
+
     #[test]
     #[cfg(feature = "binary-eccs")]
     fn ecc_file_roundtrip() {
@@ -407,5 +1189,168 @@ mod tests {
         assert_eq!(rewriter.targets, loaded_rewriter.targets);
         assert_eq!(rewriter.rewrite_rules, loaded_rewriter.rewrite_rules);
         assert_eq!(rewriter.empty_wires, loaded_rewriter.empty_wires);
+        assert_eq!(rewriter.pattern_phases, loaded_rewriter.pattern_phases);
+    }
+
+    #[test]
+    #[cfg(feature = "binary-eccs")]
+    #[cfg_attr(miri, ignore)] // Opening files is not supported in (isolated) miri
+    fn compiled_cache() {
+        let dir = std::env::temp_dir().join(format!("tket2_compiled_cache_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let eccs_path = dir.join("eccs.json");
+        let cache_path = dir.join("eccs.cache");
+        fs::copy("../test_files/eccs/small_eccs.json", &eccs_path).unwrap();
+        let expected = ECCRewriter::try_from_eccs_json_file(&eccs_path).unwrap();
+
+        // The first call compiles the rewriter and creates the cache.
+        let compiled =
+            ECCRewriter::try_from_eccs_json_file_cached(&eccs_path, &cache_path).unwrap();
+        let cache = fs::read(&cache_path).unwrap();
+        assert_eq!(compiled.n_patterns(), expected.n_patterns());
+        assert_eq!(compiled.targets.len(), expected.targets.len());
+
+        // The second call loads the cache.
+        let cached = ECCRewriter::try_from_eccs_json_file_cached(&eccs_path, &cache_path).unwrap();
+        assert_eq!(cached.n_patterns(), compiled.n_patterns());
+        assert_eq!(cached.targets, compiled.targets);
+        assert_eq!(cached.rewrite_rules, compiled.rewrite_rules);
+        assert_eq!(fs::read(&cache_path).unwrap(), cache);
+
+        // Changing the ECC file invalidates the cache.
+        let mut eccs = fs::read(&eccs_path).unwrap();
+        eccs.push(b'\n');
+        fs::write(&eccs_path, eccs).unwrap();
+        ECCRewriter::try_from_eccs_json_file_cached(&eccs_path, &cache_path).unwrap();
+        let new_cache = fs::read(&cache_path).unwrap();
+        assert_ne!(new_cache[..CACHE_HEADER_LEN], cache[..CACHE_HEADER_LEN]);
+
+        // So does an invalid cache file.
+        fs::write(&cache_path, b"not a cache").unwrap();
+        let recompiled =
+            ECCRewriter::try_from_eccs_json_file_cached(&eccs_path, &cache_path).unwrap();
+        assert_eq!(recompiled.n_patterns(), expected.n_patterns());
+        let recompiled_cache = fs::read(&cache_path).unwrap();
+        assert_eq!(
+            recompiled_cache[..CACHE_HEADER_LEN],
+            new_cache[..CACHE_HEADER_LEN]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// `Rz(a)·Rz(b)`, with the angles as inputs.
+    fn rz_rz() -> Circuit {
+        let mut h = DFGBuilder::new(Signature::new(
+            vec![QB_T, FLOAT64_TYPE, FLOAT64_TYPE],
+            vec![QB_T],
+        ))
+        .unwrap();
+        let [q, a, b] = h.input_wires_arr();
+        let [q] = h
+            .add_dataflow_op(Tk2Op::RzF64, [q, a])
+            .unwrap()
+            .outputs_arr();
+        let [q] = h
+            .add_dataflow_op(Tk2Op::RzF64, [q, b])
+            .unwrap()
+            .outputs_arr();
+        h.finish_hugr_with_outputs([q], &REGISTRY).unwrap().into()
+    }
+
+    /// `Rz(a + b)`, with the angles as inputs.
+    fn rz_sum() -> Circuit {
+        let mut h = DFGBuilder::new(Signature::new(
+            vec![QB_T, FLOAT64_TYPE, FLOAT64_TYPE],
+            vec![QB_T],
+        ))
+        .unwrap();
+        let [q, a, b] = h.input_wires_arr();
+        let [sum] = h
+            .add_dataflow_op(Tk2Op::AngleAdd, [a, b])
+            .unwrap()
+            .outputs_arr();
+        let [q] = h
+            .add_dataflow_op(Tk2Op::RzF64, [q, sum])
+            .unwrap()
+            .outputs_arr();
+        h.finish_hugr_with_outputs([q], &REGISTRY).unwrap().into()
+    }
+
+    /// `H·Rz(a)·Rz(b)` with constant angles. If `shared`, the angle `b` is also
+    /// used by a rotation before the `H` gate.
+    fn h_rz_rz_constants(a: f64, b: f64, shared: bool) -> Circuit {
+        let mut h = DFGBuilder::new(Signature::new(vec![QB_T], vec![QB_T])).unwrap();
+        let [mut q] = h.input_wires_arr();
+        let a = h.add_load_value(ConstF64::new(a));
+        let b = h.add_load_value(ConstF64::new(b));
+        if shared {
+            [q] = h
+                .add_dataflow_op(Tk2Op::RzF64, [q, b])
+                .unwrap()
+                .outputs_arr();
+        }
+        let [q] = h.add_dataflow_op(Tk2Op::H, [q]).unwrap().outputs_arr();
+        let [q] = h
+            .add_dataflow_op(Tk2Op::RzF64, [q, a])
+            .unwrap()
+            .outputs_arr();
+        let [q] = h
+            .add_dataflow_op(Tk2Op::RzF64, [q, b])
+            .unwrap()
+            .outputs_arr();
+        h.finish_hugr_with_outputs([q], &REGISTRY).unwrap().into()
+    }
+
+    /// Count the nodes of a circuit with a given operation.
+    fn count_ops(circ: &Circuit, op: Tk2Op) -> usize {
+        circ.commands()
+            .filter(|cmd| op_matches(cmd.optype(), op))
+            .count()
+    }
+
+    #[test]
+    fn fold_constant_params() {
+        let rewriter = ECCRewriter::from_eccs([EqCircClass::new(rz_sum(), vec![rz_rz()])]);
+        let mut circ = h_rz_rz_constants(0.5, 0.25, false);
+
+        let rewrites = rewriter.get_rewrites(&circ);
+        assert_eq!(rewrites.len(), 1);
+        rewrites[0].clone().apply(&mut circ).unwrap();
+        circ.hugr().validate(&REGISTRY).unwrap();
+
+        // A single rotation by the sum of the constants, with no other
+        // parameter computations left.
+        let rz = circ
+            .commands()
+            .filter(|cmd| op_matches(cmd.optype(), Tk2Op::RzF64))
+            .exactly_one()
+            .ok()
+            .unwrap()
+            .node();
+        assert_eq!(constant_params(circ.hugr(), rz), Some(vec![0.75]));
+        assert_eq!(count_ops(&circ, Tk2Op::AngleAdd), 0);
+        let n_consts = circ
+            .hugr()
+            .children(circ.parent())
+            .filter(|&n| circ.hugr().get_optype(n).is_const())
+            .count();
+        assert_eq!(n_consts, 1);
+    }
+
+    #[test]
+    fn fold_shared_params() {
+        // One of the angles is used outside the match, so the sum is not
+        // folded.
+        let rewriter = ECCRewriter::from_eccs([EqCircClass::new(rz_sum(), vec![rz_rz()])]);
+        let mut circ = h_rz_rz_constants(0.5, 0.25, true);
+
+        let rewrites = rewriter.get_rewrites(&circ);
+        assert_eq!(rewrites.len(), 1);
+        rewrites[0].clone().apply(&mut circ).unwrap();
+        circ.hugr().validate(&REGISTRY).unwrap();
+
+        assert_eq!(count_ops(&circ, Tk2Op::RzF64), 2);
+        assert_eq!(count_ops(&circ, Tk2Op::AngleAdd), 1);
     }
 }
@@ -0,0 +1,223 @@
+//! Incremental update of the rewrites of a circuit after it is rewritten.
+//!
+//! Applying a rewrite only modifies the circuit locally. Instead of rescanning
+//! the whole rewritten circuit for matches, [`update_rewrites`] reuses the
+//! rewrites of the original circuit that are not affected by the modification,
+//! and only re-matches around the modified nodes.
+//!
+//! The modified nodes are tracked in a [`ModifiedRegion`], built from the
+//! invalidation sets of the applied [`CircuitRewrite`]s.
+//!
+//! Re-matching is restricted to the nodes within a given radius of the
+//! modified region. Choosing a radius at least as large as the largest pattern
+//! of the rewriter ensures that every new match overlapping with the modified
+//! region is found. Reused rewrites are checked again for convexity, but
+//! matches far from the modified region that were not convex in the original
+//! circuit and became convex are not recovered.
+
+use std::collections::{HashSet, VecDeque};
+
+use hugr::hugr::views::sibling_subgraph::TopoConvexChecker;
+use hugr::hugr::views::SiblingSubgraph;
+use hugr::{HugrView, Node};
+
+use crate::Circuit;
+
+use super::{CircuitRewrite, Rewriter};
+
+/// The nodes of a circuit modified by applying a set of rewrites.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModifiedRegion {
+    /// Nodes removed or invalidated by the rewrites.
+    invalidated: HashSet<Node>,
+    /// Nodes outside of the rewritten subcircuits, adjacent to them.
+    boundary: HashSet<Node>,
+}
+
+impl ModifiedRegion {
+    /// Create an empty region.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a rewrite that is about to be applied to `circ`.
+    ///
+    /// This must be called before the rewrite is applied.
+    pub fn record(&mut self, rewrite: &CircuitRewrite, circ: &Circuit<impl HugrView>) {
+        let hugr = circ.hugr();
+        let nodes = rewrite.subcircuit().nodes();
+        let neighbours = nodes
+            .iter()
+            .flat_map(|&n| hugr.all_neighbours(n))
+            .filter(|n| !nodes.contains(n));
+        self.boundary.extend(neighbours);
+        self.invalidated.extend(rewrite.invalidation_set());
+    }
+
+    /// Returns `true` if no rewrite has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.invalidated.is_empty()
+    }
+
+    /// Merge another region into this one.
+    pub fn extend(&mut self, other: ModifiedRegion) {
+        self.invalidated.extend(other.invalidated);
+        self.boundary.extend(other.boundary);
+    }
+
+    /// The commands of the rewritten circuit within `radius` edges of the
+    /// modified region.
+    ///
+    /// With a radius of at least the size of the replacement circuits, this
+    /// includes all the nodes inserted by the rewrites.
+    pub fn neighbourhood(&self, circ: &Circuit<impl HugrView>, radius: usize) -> HashSet<Node> {
+        let hugr = circ.hugr();
+        let parent = circ.parent();
+        let is_command = |n: Node| {
+            hugr.valid_node(n)
+                && hugr.get_parent(n) == Some(parent)
+                && !circ.io_nodes().contains(&n)
+        };
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        for &n in self.boundary.iter().chain(&self.invalidated) {
+            if is_command(n) && visited.insert(n) {
+                queue.push_back((n, 0));
+            }
+        }
+        while let Some((node, dist)) = queue.pop_front() {
+            if dist >= radius {
+                continue;
+            }
+            for n in hugr.all_neighbours(node) {
+                if is_command(n) && visited.insert(n) {
+                    queue.push_back((n, dist + 1));
+                }
+            }
+        }
+        visited
+    }
+}
+
+/// Compute the rewrites of a circuit from the rewrites of the circuit it was
+/// obtained from.
+///
+/// `circ` must be the result of applying the rewrites recorded in `modified`
+/// to the circuit for which `previous` was computed.
+///
+/// The rewrites of `previous` that do not overlap with the modified region
+/// and its neighbourhood of size `radius` are kept if they are still convex.
+/// New rewrites are computed with [`Rewriter::get_rewrites_at`] for the
+/// nodes of the neighbourhood.
+pub fn update_rewrites(
+    rewriter: &impl Rewriter,
+    circ: &Circuit<impl HugrView>,
+    previous: &[CircuitRewrite],
+    modified: &ModifiedRegion,
+    radius: usize,
+) -> Vec<CircuitRewrite> {
+    let rematched = modified.neighbourhood(circ, radius);
+    let checker = TopoConvexChecker::new(circ.hugr());
+    let reused = previous.iter().filter(|rw| {
+        let unaffected = rw
+            .invalidation_set()
+            .all(|n| !modified.invalidated.contains(&n) && !rematched.contains(&n));
+        unaffected && {
+            let subgraph = &rw.subcircuit().subgraph;
+            SiblingSubgraph::try_new_with_checker(
+                subgraph.incoming_ports().clone(),
+                subgraph.outgoing_ports().clone(),
+                circ.hugr(),
+                &checker,
+            )
+            .is_ok()
+        }
+    });
+    let mut rewrites = reused.cloned().collect::<Vec<_>>();
+    rewrites.extend(rewriter.get_rewrites_at(circ, &rematched));
+    rewrites
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    #[cfg(feature = "portmatching")]
+    use rstest::rstest;
+
+    use super::*;
+    use crate::rewrite::Subcircuit;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    #[cfg(feature = "portmatching")]
+    fn ecc_rewriter() -> crate::rewrite::ECCRewriter {
+        let test_file = "../test_files/eccs/small_eccs.json";
+        crate::rewrite::ECCRewriter::try_from_eccs_json_file(test_file).unwrap()
+    }
+
+    fn circ() -> Circuit {
+        build_simple_circuit(3, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::CX, [1, 2])?;
+            circ.append(Tk2Op::X, [2])?;
+            circ.append(Tk2Op::Tdg, [2])?;
+            circ.append(Tk2Op::X, [2])?;
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    #[cfg(feature = "portmatching")]
+    fn rewrite_keys(rewrites: &[CircuitRewrite]) -> Vec<(Vec<Node>, usize)> {
+        rewrites
+            .iter()
+            .map(|rw| {
+                let nodes = rw.subcircuit().nodes().iter().copied().sorted().collect();
+                (nodes, rw.replacement().num_operations())
+            })
+            .sorted()
+            .collect()
+    }
+
+    #[rstest]
+    #[case::far_from_matches(vec![0, 1], 1)]
+    #[case::next_to_matches(vec![3], 2)]
+    #[cfg(feature = "portmatching")]
+    fn incremental_matches_full_rescan(#[case] removed: Vec<usize>, #[case] n_qubits: usize) {
+        let rewriter = ecc_rewriter();
+        let mut circ = circ();
+        let rewrites = rewriter.get_rewrites(&circ);
+
+        // Remove some of the commands.
+        let nodes = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        let removed = removed.into_iter().map(|i| nodes[i]).collect_vec();
+        let subcirc = Subcircuit::try_from_nodes(removed, &circ).unwrap();
+        let empty = build_simple_circuit(n_qubits, |_| Ok(())).unwrap();
+        let rewrite = subcirc.create_rewrite(&circ, empty).unwrap();
+        let mut modified = ModifiedRegion::new();
+        modified.record(&rewrite, &circ);
+        rewrite.apply(&mut circ).unwrap();
+
+        let incremental = update_rewrites(&rewriter, &circ, &rewrites, &modified, 4);
+        let full = rewriter.get_rewrites(&circ);
+        assert!(!full.is_empty());
+        assert_eq!(rewrite_keys(&incremental), rewrite_keys(&full));
+    }
+
+    #[test]
+    fn neighbourhood_radius() {
+        let circ = circ();
+        let nodes = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        let rewrite = Subcircuit::try_from_nodes([nodes[6]], &circ)
+            .unwrap()
+            .create_rewrite(&circ, build_simple_circuit(1, |_| Ok(())).unwrap())
+            .unwrap();
+        let mut modified = ModifiedRegion::new();
+        modified.record(&rewrite, &circ);
+        // The last X gate and its preceding Tdg gate.
+        assert_eq!(modified.neighbourhood(&circ, 0).len(), 2);
+        assert_eq!(modified.neighbourhood(&circ, 1).len(), 3);
+    }
+}
@@ -0,0 +1,359 @@
+//! Selection of non-overlapping sets of rewrites.
+//!
+//! Two rewrites are independent if neither removes a node removed or linked
+//! to by the other, in which case both can be applied to the same circuit in
+//! any order. Independent rewrites may share boundary nodes, such as the
+//! output node of the circuit. Given a gain for each
+//! rewrite, [`select_independent`] picks a set of pairwise independent
+//! rewrites maximising the total gain, and [`apply_independent`] applies such
+//! a set in one pass. This is useful for one-shot peephole optimisation,
+//! without a search over the rewritten circuits.
+//!
+//! Finding a maximum-weight independent set is NP-hard in general. The
+//! rewrites are split into groups of transitively overlapping rewrites, and
+//! the best selection of each group is computed exactly if the group is small
+//! enough, or greedily by decreasing gain otherwise.
+
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::ops::Add;
+
+use hugr::hugr::hugrmut::HugrMut;
+use itertools::Itertools;
+
+use super::{BatchRewriteError, CircuitRewrite};
+use crate::circuit::Circuit;
+
+/// The maximum number of overlapping rewrites for which
+/// [`select_independent`] computes the best selection exactly.
+pub const DEFAULT_EXACT_LIMIT: usize = 20;
+
+/// Select a set of pairwise independent rewrites maximising the total gain.
+///
+/// Rewrites with a gain that is not strictly positive are never selected.
+/// Groups of at most [`DEFAULT_EXACT_LIMIT`] overlapping rewrites are solved
+/// exactly, larger groups greedily. See
+/// [`select_independent_with_limit`] to change the limit.
+///
+/// The selected rewrites are returned in their input order.
+pub fn select_independent<G>(
+    rewrites: impl IntoIterator<Item = CircuitRewrite>,
+    cost_gain: impl Fn(&CircuitRewrite) -> G,
+) -> Vec<CircuitRewrite>
+where
+    G: Copy + Ord + Default + Add<Output = G>,
+{
+    select_independent_with_limit(rewrites, cost_gain, DEFAULT_EXACT_LIMIT)
+}
+
+/// Select a set of pairwise independent rewrites maximising the total gain,
+/// solving groups of at most `exact_limit` overlapping rewrites exactly.
+///
+/// The exact search takes time exponential in `exact_limit` in the worst
+/// case. With an `exact_limit` of zero, all the rewrites are selected
+/// greedily by decreasing gain.
+///
+/// See [`select_independent`].
+pub fn select_independent_with_limit<G>(
+    rewrites: impl IntoIterator<Item = CircuitRewrite>,
+    cost_gain: impl Fn(&CircuitRewrite) -> G,
+    exact_limit: usize,
+) -> Vec<CircuitRewrite>
+where
+    G: Copy + Ord + Default + Add<Output = G>,
+{
+    let candidates = rewrites
+        .into_iter()
+        .map(|rw| {
+            let gain = cost_gain(&rw);
+            (rw, gain)
+        })
+        .filter(|(_, gain)| *gain > G::default())
+        .collect_vec();
+    let conflicts = conflict_graph(candidates.iter().map(|(rw, _)| rw));
+    let gains = candidates.iter().map(|&(_, gain)| gain).collect_vec();
+
+    let mut selected = vec![false; candidates.len()];
+    for group in connected_components(&conflicts) {
+        let chosen = if group.len() <= exact_limit {
+            select_exact(&group, &gains, &conflicts)
+        } else {
+            select_greedy(&group, &gains, &conflicts)
+        };
+        for i in chosen {
+            selected[i] = true;
+        }
+    }
+    candidates
+        .into_iter()
+        .zip(selected)
+        .filter_map(|((rw, _), selected)| selected.then_some(rw))
+        .collect()
+}
+
+/// Select a set of pairwise independent rewrites maximising the total gain,
+/// and apply them to `circ` as a batch.
+///
+/// Returns the applied rewrites. See [`select_independent`] and
+/// [`CircuitRewrite::apply_batch`].
+pub fn apply_independent<G>(
+    circ: &mut Circuit<impl HugrMut>,
+    rewrites: impl IntoIterator<Item = CircuitRewrite>,
+    cost_gain: impl Fn(&CircuitRewrite) -> G,
+) -> Result<Vec<CircuitRewrite>, BatchRewriteError>
+where
+    G: Copy + Ord + Default + Add<Output = G>,
+{
+    let selected = select_independent(rewrites, cost_gain);
+    CircuitRewrite::apply_batch(circ, selected.clone())?;
+    Ok(selected)
+}
+
+/// The indices of the rewrites overlapping each rewrite.
+///
+/// See [`CircuitRewrite::conflicts`].
+fn conflict_graph<'a>(rewrites: impl IntoIterator<Item = &'a CircuitRewrite>) -> Vec<Vec<usize>> {
+    let rewrites = rewrites.into_iter().collect_vec();
+    let mut conflicts = vec![Vec::new(); rewrites.len()];
+    for (i, j, _) in CircuitRewrite::conflicts(rewrites) {
+        conflicts[i].push(j);
+        conflicts[j].push(i);
+    }
+    conflicts
+}
+
+/// The groups of transitively overlapping rewrites.
+fn connected_components(conflicts: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut visited = vec![false; conflicts.len()];
+    let mut components = Vec::new();
+    for start in 0..conflicts.len() {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut component = Vec::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(i) = queue.pop_front() {
+            component.push(i);
+            for &j in &conflicts[i] {
+                if !visited[j] {
+                    visited[j] = true;
+                    queue.push_back(j);
+                }
+            }
+        }
+        components.push(component);
+    }
+    components
+}
+
+/// Select rewrites of a group by decreasing gain, skipping those that overlap
+/// the rewrites already selected.
+fn select_greedy<G: Copy + Ord>(
+    group: &[usize],
+    gains: &[G],
+    conflicts: &[Vec<usize>],
+) -> Vec<usize> {
+    let mut blocked = vec![false; gains.len()];
+    let mut chosen = Vec::new();
+    for &i in group.iter().sorted_by_key(|&&i| Reverse(gains[i])) {
+        if blocked[i] {
+            continue;
+        }
+        chosen.push(i);
+        for &j in &conflicts[i] {
+            blocked[j] = true;
+        }
+    }
+    chosen
+}
+
+/// Select the rewrites of a group maximising the total gain, by a branch and
+/// bound search.
+fn select_exact<G>(group: &[usize], gains: &[G], conflicts: &[Vec<usize>]) -> Vec<usize>
+where
+    G: Copy + Ord + Default + Add<Output = G>,
+{
+    // Explore the rewrites by decreasing gain, so that good selections are
+    // found early and prune the search.
+    let order = group
+        .iter()
+        .copied()
+        .sorted_by_key(|&i| Reverse(gains[i]))
+        .collect_vec();
+    let mut remaining = vec![G::default(); order.len() + 1];
+    for pos in (0..order.len()).rev() {
+        remaining[pos] = remaining[pos + 1] + gains[order[pos]];
+    }
+    let mut search = ExactSearch {
+        order: &order,
+        gains,
+        conflicts,
+        blocked: vec![0; gains.len()],
+        chosen: Vec::new(),
+        best: Vec::new(),
+        best_gain: G::default(),
+        remaining,
+    };
+    search.explore(0, G::default());
+    search.best
+}
+
+/// The state of the exact search of [`select_exact`].
+struct ExactSearch<'a, G> {
+    /// The rewrites of the group, by decreasing gain.
+    order: &'a [usize],
+    gains: &'a [G],
+    conflicts: &'a [Vec<usize>],
+    /// The number of chosen rewrites overlapping each rewrite.
+    blocked: Vec<usize>,
+    chosen: Vec<usize>,
+    best: Vec<usize>,
+    best_gain: G,
+    /// The total gain of the rewrites from each position in `order`.
+    remaining: Vec<G>,
+}
+
+impl<G> ExactSearch<'_, G>
+where
+    G: Copy + Ord + Default + Add<Output = G>,
+{
+    fn explore(&mut self, pos: usize, gain: G) {
+        if gain > self.best_gain {
+            self.best_gain = gain;
+            self.best.clone_from(&self.chosen);
+        }
+        if pos == self.order.len() || gain + self.remaining[pos] <= self.best_gain {
+            return;
+        }
+        let i = self.order[pos];
+        if self.blocked[i] == 0 {
+            self.chosen.push(i);
+            for &j in &self.conflicts[i] {
+                self.blocked[j] += 1;
+            }
+            self.explore(pos + 1, gain + self.gains[i]);
+            for &j in &self.conflicts[i] {
+                self.blocked[j] -= 1;
+            }
+            self.chosen.pop();
+        }
+        self.explore(pos + 1, gain);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::Node;
+    use itertools::Itertools;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::rewrite::Subcircuit;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    fn n_cx(n_gates: usize) -> Circuit {
+        build_simple_circuit(2, |circ| {
+            for _ in 0..n_gates {
+                circ.append(Tk2Op::CX, [0, 1]).unwrap();
+            }
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    /// Rewrite cx_nodes -> empty
+    fn rw_to_empty(circ: &Circuit, cx_nodes: &[Node]) -> CircuitRewrite {
+        Subcircuit::try_from_nodes(cx_nodes.to_vec(), circ)
+            .unwrap()
+            .create_rewrite(circ, n_cx(0))
+            .unwrap()
+    }
+
+    /// Three rewrites, the largest of which overlaps the two others.
+    fn overlapping_rewrites(circ: &Circuit) -> Vec<CircuitRewrite> {
+        let cx = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        vec![
+            rw_to_empty(circ, &cx[0..2]),
+            rw_to_empty(circ, &cx[1..4]),
+            rw_to_empty(circ, &cx[3..5]),
+        ]
+    }
+
+    fn removed_gates(rw: &CircuitRewrite) -> isize {
+        -rw.node_count_delta()
+    }
+
+    #[rstest]
+    #[case::exact(DEFAULT_EXACT_LIMIT, 4)]
+    #[case::greedy(0, 3)]
+    fn select(#[case] exact_limit: usize, #[case] gain: isize) {
+        let circ = n_cx(5);
+        let rws = overlapping_rewrites(&circ);
+
+        let selected = select_independent_with_limit(rws, removed_gates, exact_limit);
+        assert_eq!(selected.iter().map(removed_gates).sum::<isize>(), gain);
+    }
+
+    #[test]
+    fn select_skips_non_positive_gains() {
+        let circ = n_cx(5);
+        let rws = overlapping_rewrites(&circ);
+
+        let selected = select_independent(rws, |rw| removed_gates(rw).min(0));
+        assert!(selected.is_empty());
+    }
+
+    #[test]
+    fn select_independent_groups() {
+        let circ = n_cx(12);
+        let cx = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        let rws = [
+            rw_to_empty(&circ, &cx[0..2]),
+            rw_to_empty(&circ, &cx[1..4]),
+            rw_to_empty(&circ, &cx[3..5]),
+            rw_to_empty(&circ, &cx[8..10]),
+        ];
+
+        let selected = select_independent(rws, removed_gates);
+        assert_eq!(selected.len(), 3);
+        assert_eq!(selected.iter().map(removed_gates).sum::<isize>(), 6);
+    }
+
+    #[test]
+    fn select_parallel() {
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::H, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        let nodes = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        let identity = || build_simple_circuit(1, |_| Ok(())).unwrap();
+        // Both rewrites end at the output node.
+        let rws = nodes[1..]
+            .iter()
+            .map(|&h| {
+                Subcircuit::try_from_nodes([h], &circ)
+                    .unwrap()
+                    .create_rewrite(&circ, identity())
+                    .unwrap()
+            })
+            .collect_vec();
+
+        let selected = select_independent(rws, removed_gates);
+        assert_eq!(selected.len(), 2);
+    }
+
+    #[test]
+    fn apply() {
+        let mut circ = n_cx(5);
+        let rws = overlapping_rewrites(&circ);
+
+        let applied = apply_independent(&mut circ, rws, removed_gates).unwrap();
+        assert_eq!(applied.len(), 2);
+        assert_eq!(circ.num_operations(), 1);
+    }
+}
@@ -0,0 +1,375 @@
+//! Profile-guided ordering of rewrite candidates.
+//!
+//! A [`RewriteProfile`] records the rewrites that led to the best circuit of
+//! an optimisation run: for each class of rewrite rules and for each region of
+//! the circuit, how many of these rewrites were applied and how many
+//! operations they removed. Profiles can be saved to disk, and then used to
+//! bias the order in which rewrites are proposed in later runs on similar
+//! circuits, so that the rules and regions that produced gains are explored
+//! first. See [`BadgerOptions::profile`] and
+//! [`BadgerLogger::with_profile_log`].
+//!
+//! Rewrites are grouped into rule classes by the operations they replace and
+//! introduce, see [`rule_class`], and into regions by the qubits they act on,
+//! see [`ProfiledRewrite`]. This makes profiles independent of the specific
+//! rewriter and of the node indices of the circuit they were recorded on.
+//!
+//! [`BadgerOptions::profile`]: crate::optimiser::badger::BadgerOptions::profile
+//! [`BadgerLogger::with_profile_log`]: crate::optimiser::badger::BadgerLogger::with_profile_log
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use hugr::ops::NamedOp;
+use hugr::{HugrView, Node};
+use itertools::Itertools;
+
+use crate::Circuit;
+
+use super::CircuitRewrite;
+
+/// Statistics for the rewrites of a rule class or region.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RewriteStats {
+    /// The number of rewrites applied on the paths to the best circuits.
+    pub count: usize,
+    /// The total number of operations removed by these rewrites.
+    ///
+    /// This is negative if the rewrites added operations on average.
+    pub total_gain: isize,
+}
+
+impl RewriteStats {
+    /// The average number of operations removed per rewrite.
+    pub fn mean_gain(&self) -> f64 {
+        match self.count {
+            0 => 0.,
+            n => self.total_gain as f64 / n as f64,
+        }
+    }
+
+    fn add(&mut self, count: usize, gain: isize) {
+        self.count += count;
+        self.total_gain += gain;
+    }
+}
+
+/// A report of the rule classes and regions of the rewrites that led to the
+/// best circuits of previous optimisation runs.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RewriteProfile {
+    classes: BTreeMap<String, RewriteStats>,
+    #[serde(default)]
+    regions: BTreeMap<String, RewriteStats>,
+}
+
+impl RewriteProfile {
+    /// Create an empty profile.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a rewrite applied on the path to a best circuit.
+    pub fn record(&mut self, rewrite: &ProfiledRewrite) {
+        let stats = self.classes.entry(rewrite.class.clone()).or_default();
+        stats.add(1, rewrite.gain);
+        let stats = self.regions.entry(rewrite.region.clone()).or_default();
+        stats.add(1, rewrite.gain);
+    }
+
+    /// Merge the statistics of another profile into this one.
+    pub fn merge(&mut self, other: &RewriteProfile) {
+        for (class, other_stats) in &other.classes {
+            let stats = self.classes.entry(class.clone()).or_default();
+            stats.add(other_stats.count, other_stats.total_gain);
+        }
+        for (region, other_stats) in &other.regions {
+            let stats = self.regions.entry(region.clone()).or_default();
+            stats.add(other_stats.count, other_stats.total_gain);
+        }
+    }
+
+    /// The statistics for a rule class, if it has been recorded.
+    pub fn class_stats(&self, class: &str) -> Option<&RewriteStats> {
+        self.classes.get(class)
+    }
+
+    /// The statistics for a region, if it has been recorded.
+    pub fn region_stats(&self, region: &str) -> Option<&RewriteStats> {
+        self.regions.get(region)
+    }
+
+    /// The recorded rule classes and their statistics.
+    pub fn classes(&self) -> impl Iterator<Item = (&str, &RewriteStats)> {
+        self.classes.iter().map(|(c, s)| (c.as_str(), s))
+    }
+
+    /// The recorded regions and their statistics.
+    pub fn regions(&self) -> impl Iterator<Item = (&str, &RewriteStats)> {
+        self.regions.iter().map(|(r, s)| (r.as_str(), s))
+    }
+
+    /// Returns `true` if no rewrite has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+
+    /// The priority of a rewrite. Higher is better.
+    ///
+    /// Rewrites are compared by the number of recorded rewrites of their rule
+    /// class, and then by the number of recorded rewrites in their region.
+    pub fn priority(&self, rewrite: &ProfiledRewrite) -> (usize, usize) {
+        let count = |stats: Option<&RewriteStats>| stats.map_or(0, |s| s.count);
+        (
+            count(self.class_stats(&rewrite.class)),
+            count(self.region_stats(&rewrite.region)),
+        )
+    }
+
+    /// Sort the rewrites of a circuit by descending priority.
+    ///
+    /// The relative order of rewrites with equal priorities is preserved.
+    pub fn order_rewrites(
+        &self,
+        rewrites: Vec<CircuitRewrite>,
+        circ: &Circuit<impl HugrView>,
+    ) -> Vec<CircuitRewrite> {
+        if self.is_empty() {
+            return rewrites;
+        }
+        let qubits = node_qubits(circ);
+        rewrites
+            .into_iter()
+            .map(|rw| {
+                let priority = self.priority(&ProfiledRewrite::new(&rw, circ, &qubits));
+                (Reverse(priority), rw)
+            })
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, rw)| rw)
+            .collect()
+    }
+
+    /// Save the profile as a JSON file.
+    pub fn save_json(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = io::BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Load a profile saved with [`RewriteProfile::save_json`].
+    pub fn load_json(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = io::BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+}
+
+/// The rule class of a rewrite.
+///
+/// This is a textual description of the sorted operation names in the matched
+/// subcircuit and in its replacement, e.g. `"quantum.tket2.CX,quantum.tket2.CX -> "`
+/// for a rule cancelling two CX gates.
+pub fn rule_class(rewrite: &CircuitRewrite, circ: &Circuit<impl HugrView>) -> String {
+    let lhs = rewrite
+        .subcircuit()
+        .nodes()
+        .iter()
+        .map(|&n| circ.hugr().get_optype(n).name())
+        .sorted()
+        .join(",");
+    let replacement = rewrite.replacement();
+    let rhs = replacement
+        .commands()
+        .map(|cmd| cmd.optype().name())
+        .sorted()
+        .join(",");
+    format!("{lhs} -> {rhs}")
+}
+
+/// The rule class and region of a rewrite, as recorded in a
+/// [`RewriteProfile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfiledRewrite {
+    /// The rule class of the rewrite, see [`rule_class`].
+    pub class: String,
+    /// The region of the rewrite: the sorted indices of the qubits it acts
+    /// on, e.g. `"0,2"`.
+    pub region: String,
+    /// The number of operations removed by the rewrite.
+    pub gain: isize,
+}
+
+impl ProfiledRewrite {
+    /// Describe a rewrite of `circ`, given the qubits of the commands of the
+    /// circuit computed by [`node_qubits`].
+    pub fn new(
+        rewrite: &CircuitRewrite,
+        circ: &Circuit<impl HugrView>,
+        qubits: &HashMap<Node, Vec<usize>>,
+    ) -> Self {
+        let region = rewrite
+            .subcircuit()
+            .nodes()
+            .iter()
+            .flat_map(|n| qubits.get(n).into_iter().flatten())
+            .sorted()
+            .dedup()
+            .join(",");
+        Self {
+            class: rule_class(rewrite, circ),
+            region,
+            gain: -rewrite.node_count_delta(),
+        }
+    }
+}
+
+/// The indices of the qubits of each command of a circuit.
+pub fn node_qubits(circ: &Circuit<impl HugrView>) -> HashMap<Node, Vec<usize>> {
+    circ.commands()
+        .map(|cmd| {
+            let qubits = cmd.input_qubits().map(|(q, _, _)| q.index()).collect();
+            (cmd.node(), qubits)
+        })
+        .collect()
+}
+
+/// The rewrites applied to obtain a circuit from the input of an optimisation
+/// run.
+///
+/// Paths share their prefix with the paths of the circuits they extend, so
+/// that each queued circuit of an optimiser can record its own path cheaply.
+#[derive(Debug, Clone, Default)]
+pub struct RewritePath(Option<Arc<PathStep>>);
+
+/// The last step of a [`RewritePath`].
+#[derive(Debug)]
+struct PathStep {
+    rewrites: Vec<ProfiledRewrite>,
+    parent: RewritePath,
+}
+
+impl RewritePath {
+    /// The empty path.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The path obtained by applying `rewrites` after this one.
+    pub fn extend(&self, rewrites: Vec<ProfiledRewrite>) -> Self {
+        Self(Some(Arc::new(PathStep {
+            rewrites,
+            parent: self.clone(),
+        })))
+    }
+
+    /// The profile of the rewrites of the path.
+    pub fn to_profile(&self) -> RewriteProfile {
+        let mut profile = RewriteProfile::new();
+        let mut step = self.0.as_deref();
+        while let Some(PathStep { rewrites, parent }) = step {
+            rewrites.iter().for_each(|rw| profile.record(rw));
+            step = parent.0.as_deref();
+        }
+        profile
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rewrite::Subcircuit;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    fn circ() -> Circuit {
+        build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::T, [1])?;
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    /// Rewrites replacing the T gate with a copy, and removing the H gate.
+    fn rewrites(circ: &Circuit) -> Vec<CircuitRewrite> {
+        let node = |op: Tk2Op| {
+            circ.commands()
+                .find(|cmd| Tk2Op::try_from(cmd.optype()).ok() == Some(op))
+                .unwrap()
+                .node()
+        };
+        let rewrite = |node, op| {
+            let repl = build_simple_circuit(1, |c| {
+                if let Some(op) = op {
+                    c.append(op, [0])?;
+                }
+                Ok(())
+            })
+            .unwrap();
+            let subcirc = Subcircuit::try_from_nodes([node], circ).unwrap();
+            subcirc.create_rewrite(circ, repl).unwrap()
+        };
+        vec![
+            rewrite(node(Tk2Op::T), Some(Tk2Op::T)),
+            rewrite(node(Tk2Op::H), None),
+        ]
+    }
+
+    #[test]
+    fn record_path_and_reorder() {
+        let circ = circ();
+        let qubits = node_qubits(&circ);
+        let [keep_t, remove_h] = rewrites(&circ)
+            .iter()
+            .map(|rw| ProfiledRewrite::new(rw, &circ, &qubits))
+            .collect_vec()
+            .try_into()
+            .unwrap();
+        assert_eq!(remove_h.class, format!("{} -> ", Tk2Op::H.exposed_name()));
+        assert_eq!(remove_h.region, "0");
+        assert_eq!(keep_t.region, "1");
+
+        // Only the rewrites on the path are recorded.
+        let path = RewritePath::new()
+            .extend(vec![remove_h.clone()])
+            .extend(vec![]);
+        let _ = path.extend(vec![keep_t]);
+        let profile = path.to_profile();
+        assert_eq!(profile.classes().count(), 1);
+        assert_eq!(
+            profile.class_stats(&remove_h.class),
+            Some(&RewriteStats {
+                count: 1,
+                total_gain: 1
+            })
+        );
+        assert_eq!(profile.region_stats("0").map(|s| s.count), Some(1));
+
+        let unguided = rewrites(&circ);
+        assert_eq!(unguided[0].node_count_delta(), 0);
+        let guided = profile.order_rewrites(unguided, &circ);
+        assert_eq!(guided[0].node_count_delta(), -1);
+        assert_eq!(guided[1].node_count_delta(), 0);
+    }
+
+    #[test]
+    fn merge_profiles() {
+        let circ = circ();
+        let qubits = node_qubits(&circ);
+        let mut profile = RewriteProfile::new();
+        for rw in rewrites(&circ) {
+            profile.record(&ProfiledRewrite::new(&rw, &circ, &qubits));
+        }
+        let mut merged = profile.clone();
+        merged.merge(&profile);
+        for (class, stats) in merged.classes() {
+            let original = profile.class_stats(class).unwrap();
+            assert_eq!(stats.count, 2 * original.count);
+            assert_eq!(stats.mean_gain(), original.mean_gain());
+        }
+        assert_eq!(merged.regions().count(), 2);
+    }
+}
@@ -0,0 +1,723 @@
+//! A rewriter for user-defined rewrite rules, loaded from a file.
+//!
+//! Rules can be written in a simple line-based text format, or in JSON.
+//!
+//! # Text format
+//!
+//! Each non-empty line defines a rule. Comments start with `#`.
+//!
+//! ```text
+//! # Cancel adjacent Hadamards.
+//! hh: H 0; H 0 =>
+//! # Rewrite in both directions.
+//! cx_flip: H 0; H 1; CX 0 1; H 0; H 1 <=> CX 1 0
+//! # Only apply the rule if it removes at least two operations.
+//! cx_cancel: CX 0 1; CX 0 1 => | min_gain=2
+//! ```
+//!
+//! Circuits are lists of operations separated by `;`. Each operation is a
+//! [`Tk2Op`] name followed by the indices of the qubits it acts on. Only
+//! operations acting purely on qubits are supported in this format.
+//!
+//! # JSON format
+//!
+//! A JSON file contains a list of rules. Circuits are either given in the
+//! text format above, or as serialised pytket circuits.
+//!
+//! ```json
+//! [
+//!     {
+//!         "name": "hh",
+//!         "lhs": "H 0; H 0",
+//!         "rhs": "",
+//!         "bidirectional": false,
+//!         "conditions": [{"min_gain": 1}]
+//!     }
+//! ]
+//! ```
+//!
+//! # Rule packs
+//!
+//! A [`RuleRewriter`] can be saved along with its compiled pattern matcher as a
+//! binary rule pack, see [`RuleRewriter::save_binary`].
+
+use std::collections::HashSet;
+use std::fs;
+#[cfg(feature = "binary-eccs")]
+use std::fs::File;
+#[cfg(feature = "binary-eccs")]
+use std::io;
+use std::path::Path;
+#[cfg(feature = "binary-eccs")]
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use hugr::builder::BuildError;
+use hugr::extension::prelude::QB_T;
+#[cfg(feature = "binary-eccs")]
+use hugr::hugr::views::{DescendantsGraph, ExtractHugr, HierarchyView};
+use hugr::ops::{OpTrait, OpType};
+#[cfg(feature = "binary-eccs")]
+use hugr::Hugr;
+use hugr::{HugrView, Node};
+use itertools::{Either, Itertools};
+use thiserror::Error;
+use tket_json_rs::circuit_json::SerialCircuit;
+
+use crate::portmatching::pattern::InvalidPattern;
+use crate::portmatching::{CircuitPattern, PatternMatch, PatternMatcher};
+use crate::serialize::pytket::TK1ConvertError;
+use crate::serialize::TKETDecode;
+use crate::utils::build_simple_circuit;
+use crate::{Circuit, Tk2Op};
+
+#[cfg(feature = "binary-eccs")]
+use super::ecc_rewriter::RewriterSerialisationError;
+use super::{CircuitRewrite, Rewriter};
+
+/// A condition restricting when a rewrite rule may be applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum RuleCondition {
+    /// Only apply the rule if it removes at least this many operations.
+    MinGain(isize),
+}
+
+impl RuleCondition {
+    /// Check whether a rewrite satisfies the condition.
+    pub fn is_satisfied(&self, rewrite: &CircuitRewrite) -> bool {
+        match *self {
+            RuleCondition::MinGain(gain) => -rewrite.node_count_delta() >= gain,
+        }
+    }
+}
+
+/// A user-defined rewrite rule.
+#[derive(Debug, Clone)]
+pub struct RewriteRule {
+    /// The name of the rule.
+    pub name: String,
+    /// The circuit to match.
+    pub lhs: Circuit,
+    /// The circuit to replace the match with.
+    pub rhs: Circuit,
+    /// The conditions that must hold to apply the rule.
+    pub conditions: Vec<RuleCondition>,
+}
+
+impl RewriteRule {
+    /// The same rule, applied from right to left.
+    pub fn reversed(&self) -> Self {
+        Self {
+            name: format!("{}_rev", self.name),
+            lhs: self.rhs.clone(),
+            rhs: self.lhs.clone(),
+            conditions: self.conditions.clone(),
+        }
+    }
+}
+
+/// A rewriter applying a set of user-defined [`RewriteRule`]s.
+#[derive(Debug, Clone)]
+pub struct RuleRewriter {
+    /// Matcher for the left-hand sides of the rules.
+    matcher: PatternMatcher,
+    /// The rules, indexed by pattern ID.
+    rules: Vec<RewriteRule>,
+}
+
+impl RuleRewriter {
+    /// Create a new rewriter from a list of rules.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the left-hand side of a rule is not a valid pattern.
+    pub fn try_from_rules(rules: impl Into<Vec<RewriteRule>>) -> Result<Self, RuleError> {
+        let rules: Vec<RewriteRule> = rules.into();
+        if let Some(rule) = rules.iter().find(|rule| {
+            let (lhs, rhs) = (rule.lhs.circuit_signature(), rule.rhs.circuit_signature());
+            lhs.input() != rhs.input() || lhs.output() != rhs.output()
+        }) {
+            return Err(RuleError::InvalidCircuit {
+                rule: rule.name.clone(),
+                message: "the two sides of the rule have different signatures".to_string(),
+            });
+        }
+        let patterns = rules
+            .iter()
+            .map(|rule| {
+                CircuitPattern::try_from_circuit(&rule.lhs).map_err(|source| {
+                    RuleError::InvalidPattern {
+                        rule: rule.name.clone(),
+                        source,
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            matcher: PatternMatcher::from_patterns(patterns),
+            rules,
+        })
+    }
+
+    /// Load rules from a file.
+    ///
+    /// Files with a `.json` extension are parsed as JSON, any other file is
+    /// parsed using the text format.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RuleError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::from_json_str(&contents),
+            _ => Self::from_text(&contents),
+        }
+    }
+
+    /// Parse rules in the text format.
+    pub fn from_text(text: &str) -> Result<Self, RuleError> {
+        let rules = parse_text_rules(text)?;
+        Self::try_from_rules(rules)
+    }
+
+    /// Parse rules in the JSON format.
+    pub fn from_json_str(json: &str) -> Result<Self, RuleError> {
+        let serial_rules: Vec<SerialRule> = serde_json::from_str(json)?;
+        let mut rules = Vec::with_capacity(serial_rules.len());
+        for rule in serial_rules {
+            let (lhs, rhs) = decode_rule_circuits(&rule.name, rule.lhs, rule.rhs)?;
+            let rule_def = RewriteRule {
+                name: rule.name,
+                lhs,
+                rhs,
+                conditions: rule.conditions,
+            };
+            if rule.bidirectional {
+                rules.push(rule_def.reversed());
+            }
+            rules.push(rule_def);
+        }
+        Self::try_from_rules(rules)
+    }
+
+    /// The rules of the rewriter.
+    pub fn rules(&self) -> &[RewriteRule] {
+        &self.rules
+    }
+
+    /// The matcher for the left-hand sides of the rules.
+    ///
+    /// The ID of each pattern is the index of its rule in
+    /// [`RuleRewriter::rules`].
+    pub fn matcher(&self) -> &PatternMatcher {
+        &self.matcher
+    }
+
+    /// Serialise the rewriter as a binary rule pack to an IO stream.
+    ///
+    /// The compiled pattern matcher is included, so that loading the rule
+    /// pack with [`RuleRewriter::load_binary_io`] does not recompile it.
+    #[cfg(feature = "binary-eccs")]
+    pub fn save_binary_io<W: io::Write>(
+        &self,
+        writer: W,
+    ) -> Result<(), RewriterSerialisationError> {
+        let pack = SerialRulePack {
+            matcher: self.matcher.clone(),
+            rules: self
+                .rules
+                .iter()
+                .map(|rule| SerialPackedRule {
+                    name: rule.name.clone(),
+                    lhs: circuit_hugr(&rule.lhs),
+                    rhs: circuit_hugr(&rule.rhs),
+                    conditions: rule.conditions.clone(),
+                })
+                .collect(),
+        };
+        let mut encoder = zstd::Encoder::new(writer, 9)?;
+        rmp_serde::encode::write(&mut encoder, &pack)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    /// Load a rule pack from an IO stream.
+    ///
+    /// Loads streams as created by [`RuleRewriter::save_binary_io`].
+    #[cfg(feature = "binary-eccs")]
+    pub fn load_binary_io<R: io::Read>(reader: R) -> Result<Self, RewriterSerialisationError> {
+        let data = zstd::decode_all(reader)?;
+        let pack: SerialRulePack = rmp_serde::decode::from_slice(&data)?;
+        let rules = pack
+            .rules
+            .into_iter()
+            .map(|rule| RewriteRule {
+                name: rule.name,
+                lhs: rule.lhs.into(),
+                rhs: rule.rhs.into(),
+                conditions: rule.conditions,
+            })
+            .collect();
+        Ok(Self {
+            matcher: pack.matcher,
+            rules,
+        })
+    }
+
+    /// Save the rewriter as a binary rule pack file.
+    ///
+    /// The extension of the file name will always be set or amended to be
+    /// `.rwp`.
+    ///
+    /// If successful, returns the path to the newly created file.
+    #[cfg(feature = "binary-eccs")]
+    pub fn save_binary(
+        &self,
+        name: impl AsRef<Path>,
+    ) -> Result<PathBuf, RewriterSerialisationError> {
+        let mut file_name = PathBuf::from(name.as_ref());
+        file_name.set_extension("rwp");
+        let file = File::create(&file_name)?;
+        let mut file = io::BufWriter::new(file);
+        self.save_binary_io(&mut file)?;
+        Ok(file_name)
+    }
+
+    /// Loads a rule pack saved using [`RuleRewriter::save_binary`].
+    #[cfg(feature = "binary-eccs")]
+    pub fn load_binary(name: impl AsRef<Path>) -> Result<Self, RewriterSerialisationError> {
+        let mut file = File::open(name)?;
+        Self::load_binary_io(&mut file)
+    }
+}
+
+impl RuleRewriter {
+    /// Build the rewrites for a set of pattern matches, checking the rule
+    /// conditions.
+    fn matches_to_rewrites(
+        &self,
+        circ: &Circuit<impl HugrView>,
+        matches: Vec<PatternMatch>,
+    ) -> Vec<CircuitRewrite> {
+        matches
+            .into_iter()
+            .filter_map(|m| {
+                let rule = &self.rules[m.pattern_id().0];
+                let rewrite = m
+                    .to_rewrite(circ, rule.rhs.clone())
+                    .expect("invalid replacement");
+                rule.conditions
+                    .iter()
+                    .all(|c| c.is_satisfied(&rewrite))
+                    .then_some(rewrite)
+            })
+            .collect()
+    }
+}
+
+impl Rewriter for RuleRewriter {
+    fn get_rewrites(&self, circ: &Circuit<impl HugrView>) -> Vec<CircuitRewrite> {
+        let matches = self.matcher.find_matches(circ);
+        self.matches_to_rewrites(circ, matches)
+    }
+
+    fn get_rewrites_at(
+        &self,
+        circ: &Circuit<impl HugrView>,
+        roots: &HashSet<Node>,
+    ) -> Vec<CircuitRewrite> {
+        let matches = self.matcher.find_matches_at(circ, roots.iter().copied());
+        self.matches_to_rewrites(circ, matches)
+    }
+}
+
+/// Errors that can occur while loading rewrite rules.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum RuleError {
+    /// A line of a text rule file could not be parsed.
+    #[error("Syntax error on line {line}: {message}")]
+    Syntax {
+        /// The 1-indexed line number.
+        line: usize,
+        /// A description of the error.
+        message: String,
+    },
+    /// A circuit in a rule could not be parsed, or the two sides of the rule
+    /// do not have the same signature.
+    #[error("Invalid circuit in rule {rule}: {message}")]
+    InvalidCircuit {
+        /// The name of the rule.
+        rule: String,
+        /// A description of the error.
+        message: String,
+    },
+    /// The left-hand side of a rule is not a valid pattern.
+    #[error("Invalid pattern in rule {rule}: {source}")]
+    InvalidPattern {
+        /// The name of the rule.
+        rule: String,
+        /// The pattern error.
+        #[source]
+        source: InvalidPattern,
+    },
+    /// A pytket circuit in a JSON rule could not be decoded.
+    #[error("Could not decode the circuits of rule {rule}: {source}")]
+    Decode {
+        /// The name of the rule.
+        rule: String,
+        /// The decoding error.
+        #[source]
+        source: TK1ConvertError,
+    },
+    /// The JSON rule file is malformed.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// The rule file could not be read.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A binary rule pack, see [`RuleRewriter::save_binary`].
+#[cfg(feature = "binary-eccs")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerialRulePack {
+    matcher: PatternMatcher,
+    rules: Vec<SerialPackedRule>,
+}
+
+/// A rule in a binary rule pack.
+///
+/// The circuits are stored as HUGRs rooted at the circuit parent.
+#[cfg(feature = "binary-eccs")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerialPackedRule {
+    name: String,
+    lhs: Hugr,
+    rhs: Hugr,
+    conditions: Vec<RuleCondition>,
+}
+
+/// Extract the HUGR of a circuit, rooted at its parent.
+#[cfg(feature = "binary-eccs")]
+fn circuit_hugr(circ: &Circuit) -> Hugr {
+    if circ.parent() == circ.hugr().root() {
+        return circ.hugr().clone();
+    }
+    DescendantsGraph::<Node>::try_new(circ.hugr(), circ.parent())
+        .expect("Circuit parent was not a dataflow container.")
+        .extract_hugr()
+}
+
+/// A rule in the JSON format.
+#[derive(Debug, Clone, serde::Deserialize)]
+struct SerialRule {
+    name: String,
+    lhs: SerialRuleCircuit,
+    rhs: SerialRuleCircuit,
+    #[serde(default)]
+    bidirectional: bool,
+    #[serde(default)]
+    conditions: Vec<RuleCondition>,
+}
+
+/// A circuit in a JSON rule.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(untagged)]
+enum SerialRuleCircuit {
+    /// A circuit in the text format.
+    Text(String),
+    /// A serialised pytket circuit.
+    Tk1(Box<SerialCircuit>),
+}
+
+/// Decode the two sides of a JSON rule.
+///
+/// Text circuits use the same number of qubits as the other side of the rule.
+fn decode_rule_circuits(
+    rule: &str,
+    lhs: SerialRuleCircuit,
+    rhs: SerialRuleCircuit,
+) -> Result<(Circuit, Circuit), RuleError> {
+    let invalid_circuit = |message: String| RuleError::InvalidCircuit {
+        rule: rule.to_string(),
+        message,
+    };
+    let decode = |circ: SerialRuleCircuit| match circ {
+        SerialRuleCircuit::Text(text) => parse_text_circuit(&text)
+            .map(Either::Left)
+            .map_err(invalid_circuit),
+        SerialRuleCircuit::Tk1(serial) => {
+            serial
+                .decode()
+                .map(Either::Right)
+                .map_err(|source| RuleError::Decode {
+                    rule: rule.to_string(),
+                    source,
+                })
+        }
+    };
+    let (lhs, rhs) = (decode(lhs)?, decode(rhs)?);
+    let n_qubits = [&lhs, &rhs]
+        .iter()
+        .map(|side| match side {
+            Either::Left(ops) => num_qubits(ops),
+            Either::Right(circ) => circ.qubit_count(),
+        })
+        .max()
+        .unwrap();
+    let build = |side: Either<Vec<_>, Circuit>| match side {
+        Either::Left(ops) => {
+            build_text_circuit(&ops, n_qubits).map_err(|e| invalid_circuit(e.to_string()))
+        }
+        Either::Right(circ) => Ok(circ),
+    };
+    Ok((build(lhs)?, build(rhs)?))
+}
+
+/// Parse all the rules in a text rule file.
+fn parse_text_rules(text: &str) -> Result<Vec<RewriteRule>, RuleError> {
+    let mut rules = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let syntax_error = |message: String| RuleError::Syntax {
+            line: i + 1,
+            message,
+        };
+        let (name, body) = line
+            .split_once(':')
+            .ok_or_else(|| syntax_error("expected `name: lhs => rhs`".to_string()))?;
+        let (body, conditions) = match body.split_once('|') {
+            Some((body, conditions)) => (body, parse_conditions(conditions).map_err(syntax_error)?),
+            None => (body, vec![]),
+        };
+        let (lhs, rhs, bidirectional) = if let Some((lhs, rhs)) = body.split_once("<=>") {
+            (lhs, rhs, true)
+        } else if let Some((lhs, rhs)) = body.split_once("=>") {
+            (lhs, rhs, false)
+        } else {
+            return Err(syntax_error("expected `=>` or `<=>`".to_string()));
+        };
+        let (lhs, rhs) = build_rule_circuits(lhs, rhs).map_err(syntax_error)?;
+        let rule = RewriteRule {
+            name: name.trim().to_string(),
+            lhs,
+            rhs,
+            conditions,
+        };
+        if bidirectional {
+            rules.push(rule.reversed());
+        }
+        rules.push(rule);
+    }
+    Ok(rules)
+}
+
+/// Parse a comma-separated list of `key=value` conditions.
+fn parse_conditions(text: &str) -> Result<Vec<RuleCondition>, String> {
+    text.split(',')
+        .map(|cond| {
+            let (key, value) = cond
+                .split_once('=')
+                .ok_or_else(|| format!("invalid condition `{}`", cond.trim()))?;
+            match key.trim() {
+                "min_gain" => {
+                    let gain = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("invalid gain `{}`", value.trim()))?;
+                    Ok(RuleCondition::MinGain(gain))
+                }
+                key => Err(format!("unknown condition `{key}`")),
+            }
+        })
+        .collect()
+}
+
+/// Build the two sides of a rule from text circuits, using the same number of
+/// qubits for both.
+fn build_rule_circuits(lhs: &str, rhs: &str) -> Result<(Circuit, Circuit), String> {
+    let lhs = parse_text_circuit(lhs)?;
+    let rhs = parse_text_circuit(rhs)?;
+    let n_qubits = num_qubits(&lhs).max(num_qubits(&rhs));
+    let build = |ops| build_text_circuit(ops, n_qubits).map_err(|e| e.to_string());
+    Ok((build(&lhs)?, build(&rhs)?))
+}
+
+/// Parse a `;`-separated list of operations.
+fn parse_text_circuit(text: &str) -> Result<Vec<(Tk2Op, Vec<usize>)>, String> {
+    text.split(';')
+        .map(str::trim)
+        .filter(|cmd| !cmd.is_empty())
+        .map(|cmd| {
+            let mut tokens = cmd.split_whitespace();
+            let name = tokens.next().unwrap();
+            let op = Tk2Op::from_str(name).map_err(|_| format!("unknown operation `{name}`"))?;
+            let qubits: Vec<usize> = tokens
+                .map(|q| q.parse().map_err(|_| format!("invalid qubit index `{q}`")))
+                .try_collect()?;
+
+            let signature = OpType::from(op).dataflow_signature().unwrap();
+            let qubit_only = signature.input_types().iter().all(|t| t == &QB_T)
+                && signature.input_types() == signature.output_types();
+            if !qubit_only {
+                return Err(format!("operation `{name}` is not supported in text rules"));
+            }
+            if qubits.len() != signature.input_count() || !qubits.iter().all_unique() {
+                return Err(format!(
+                    "operation `{name}` expects {} distinct qubits",
+                    signature.input_count()
+                ));
+            }
+            Ok((op, qubits))
+        })
+        .collect()
+}
+
+/// The number of qubits used by a list of operations.
+fn num_qubits(ops: &[(Tk2Op, Vec<usize>)]) -> usize {
+    ops.iter()
+        .flat_map(|(_, qbs)| qbs)
+        .max()
+        .map_or(0, |&q| q + 1)
+}
+
+/// Build a circuit from a list of operations.
+fn build_text_circuit(ops: &[(Tk2Op, Vec<usize>)], n_qubits: usize) -> Result<Circuit, BuildError> {
+    build_simple_circuit(n_qubits, |circ| {
+        for (op, qubits) in ops {
+            circ.append(*op, qubits.iter().copied())?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::ops::op_matches;
+
+    const RULES: &str = r"
+        # Cancel adjacent Hadamards.
+        hh: H 0; H 0 =>
+        cx_flip: H 0; H 1; CX 0 1; H 0; H 1 <=> CX 1 0
+        cx_cancel: CX 0 1; CX 0 1 => | min_gain=3
+    ";
+
+    #[test]
+    fn parse_text() {
+        let rewriter = RuleRewriter::from_text(RULES).unwrap();
+        let names = rewriter
+            .rules()
+            .iter()
+            .map(|r| r.name.as_str())
+            .collect_vec();
+        assert_eq!(names, ["hh", "cx_flip_rev", "cx_flip", "cx_cancel"]);
+        assert_eq!(rewriter.rules()[0].rhs.num_operations(), 0);
+        assert_eq!(rewriter.rules()[3].conditions, [RuleCondition::MinGain(3)]);
+    }
+
+    #[test]
+    fn apply_rules() {
+        let rewriter = RuleRewriter::from_text(RULES).unwrap();
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [1, 0])?;
+            circ.append(Tk2Op::CX, [1, 0])?;
+            Ok(())
+        })
+        .unwrap();
+
+        // The CX cancellation only removes two operations, so it is filtered
+        // out by its condition. Each CX can be flipped.
+        let rewrites = rewriter.get_rewrites(&circ);
+        assert_eq!(rewrites.len(), 3);
+
+        let mut circ = circ;
+        let hh = rewrites
+            .into_iter()
+            .find(|rw| rw.node_count_delta() == -2)
+            .unwrap();
+        hh.apply(&mut circ).unwrap();
+        assert!(circ
+            .commands()
+            .all(|cmd| op_matches(cmd.optype(), Tk2Op::CX)));
+    }
+
+    #[test]
+    fn parse_json() {
+        let json = r#"[
+            {"name": "hh", "lhs": "H 0; H 0", "rhs": "", "conditions": [{"min_gain": 1}]},
+            {"name": "xx", "lhs": {
+                "phase": "0",
+                "bits": [],
+                "qubits": [["q", [0]]],
+                "commands": [
+                    {"args": [["q", [0]]], "op": {"type": "X"}},
+                    {"args": [["q", [0]]], "op": {"type": "X"}}
+                ],
+                "implicit_permutation": []
+            }, "rhs": "", "bidirectional": false}
+        ]"#;
+        let rewriter = RuleRewriter::from_json_str(json).unwrap();
+        assert_eq!(rewriter.rules().len(), 2);
+        assert_eq!(rewriter.rules()[1].lhs.num_operations(), 2);
+    }
+
+    #[rstest]
+    #[case::no_arrow("hh: H 0; H 0", 1)]
+    #[case::no_name("H 0 => H 0", 1)]
+    #[case::unknown_op("foo: Foo 0 =>", 1)]
+    #[case::param_op("rz: RzF64 0 =>", 1)]
+    #[case::wrong_arity("cx: CX 0 =>", 1)]
+    #[case::bad_condition("\nhh: H 0; H 0 => | max_gain=1", 2)]
+    fn syntax_errors(#[case] text: &str, #[case] line: usize) {
+        assert!(matches!(
+            RuleRewriter::from_text(text),
+            Err(RuleError::Syntax { line: l, .. }) if l == line
+        ));
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        assert!(matches!(
+            RuleRewriter::from_text("empty: => H 0"),
+            Err(RuleError::InvalidPattern { .. })
+        ));
+    }
+
+    #[cfg(feature = "binary-eccs")]
+    #[test]
+    fn rule_pack_roundtrip() {
+        let rewriter = RuleRewriter::from_text(RULES).unwrap();
+        let mut data = Vec::new();
+        rewriter.save_binary_io(&mut data).unwrap();
+        let loaded = RuleRewriter::load_binary_io(data.as_slice()).unwrap();
+
+        assert_eq!(loaded.rules().len(), rewriter.rules().len());
+        for (a, b) in rewriter.rules().iter().zip(loaded.rules()) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.conditions, b.conditions);
+            assert!(a.lhs.equal_structure(&b.lhs));
+            assert!(a.rhs.equal_structure(&b.rhs));
+        }
+
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [1, 0])?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            loaded.get_rewrites(&circ).len(),
+            rewriter.get_rewrites(&circ).len()
+        );
+    }
+}
@@ -31,6 +31,7 @@ use itertools::Itertools;
 use crate::circuit::cost::{is_cx, is_quantum, CircuitCost, CostDelta, LexicographicCost};
 use crate::Circuit;
 
+use super::incremental::ModifiedRegion;
 use super::trace::RewriteTrace;
 use super::CircuitRewrite;
 
@@ -84,6 +85,33 @@ pub struct RewriteResult<C: CircuitCost> {
     pub circ: Circuit,
     /// The cost delta of the rewrite.
     pub cost_delta: C::CostDelta,
+    /// The region of the original circuit modified by the rewrites, if known.
+    ///
+    /// This can be used to update the rewrites of the new circuit
+    /// incrementally, see [`super::incremental`].
+    pub modified: Option<ModifiedRegion>,
+    /// The rewrites applied to the original circuit to obtain `circ`, in
+    /// order, if known.
+    ///
+    /// Applying them to a copy of the original circuit reproduces `circ`, so
+    /// that the optimiser can store them instead of the rewritten circuit.
+    pub rewrites: Option<Vec<CircuitRewrite>>,
+}
+
+impl<C: CircuitCost> RewriteResult<C> {
+    /// Set the region of the original circuit modified by the rewrites.
+    #[inline]
+    pub fn with_modified(mut self, modified: ModifiedRegion) -> Self {
+        self.modified = Some(modified);
+        self
+    }
+
+    /// Set the rewrites applied to the original circuit.
+    #[inline]
+    pub fn with_rewrites(mut self, rewrites: Vec<CircuitRewrite>) -> Self {
+        self.rewrites = Some(rewrites);
+        self
+    }
 }
 
 impl<C: CircuitCost, T: HugrView> From<(Circuit<T>, C::CostDelta)> for RewriteResult<C> {
@@ -92,6 +120,8 @@ impl<C: CircuitCost, T: HugrView> From<(Circuit<T>, C::CostDelta)> for RewriteRe
         Self {
             circ: circ.to_owned(),
             cost_delta,
+            modified: None,
+            rewrites: None,
         }
     }
 }
@@ -123,8 +153,10 @@ impl RewriteStrategy for GreedyRewriteStrategy {
             .sorted_by_key(|rw| rw.node_count_delta())
             .take_while(|rw| rw.node_count_delta() < 0);
         let mut changed_nodes = HashSet::new();
+        let mut modified = ModifiedRegion::new();
         let mut cost_delta = 0;
         let mut circ = circ.clone();
+        let mut applied = Vec::new();
         for rewrite in rewrites {
             if rewrite
                 .subcircuit()
@@ -136,11 +168,14 @@ impl RewriteStrategy for GreedyRewriteStrategy {
             }
             changed_nodes.extend(rewrite.subcircuit().nodes().iter().copied());
             cost_delta += rewrite.node_count_delta();
+            modified.record(&rewrite, &circ);
+            applied.push(rewrite.clone());
             rewrite
                 .apply(&mut circ)
                 .expect("Could not perform rewrite in greedy strategy");
         }
-        iter::once((circ, cost_delta).into())
+        let result = RewriteResult::from((circ, cost_delta));
+        iter::once(result.with_modified(modified).with_rewrites(applied))
     }
 
     fn circuit_cost(&self, circ: &Circuit<impl HugrView>) -> Self::Cost {
@@ -206,8 +241,10 @@ impl<T: StrategyCost> RewriteStrategy for ExhaustiveGreedyStrategy<T> {
         (0..rewrites.len()).map(move |i| {
             let mut curr_circ = circ.clone();
             let mut changed_nodes = HashSet::new();
+            let mut modified = ModifiedRegion::new();
             let mut cost_delta = Default::default();
             let mut composed_rewrite_count = 0;
+            let mut applied = Vec::new();
             for (rewrite, delta) in &rewrites[i..] {
                 if !changed_nodes.is_empty()
                     && rewrite
@@ -221,6 +258,8 @@ impl<T: StrategyCost> RewriteStrategy for ExhaustiveGreedyStrategy<T> {
 
                 composed_rewrite_count += 1;
 
+                modified.record(rewrite, &curr_circ);
+                applied.push(rewrite.clone());
                 rewrite
                     .clone()
                     .apply_notrace(&mut curr_circ)
@@ -228,7 +267,9 @@ impl<T: StrategyCost> RewriteStrategy for ExhaustiveGreedyStrategy<T> {
             }
 
             curr_circ.add_rewrite_trace(RewriteTrace::new(composed_rewrite_count));
-            (curr_circ, cost_delta).into()
+            RewriteResult::from((curr_circ, cost_delta))
+                .with_modified(modified)
+                .with_rewrites(applied)
         })
     }
 
@@ -274,8 +315,11 @@ impl<T: StrategyCost> RewriteStrategy for ExhaustiveThresholdStrategy<T> {
                 return None;
             }
             let mut circ = circ.clone();
-            rw.apply(&mut circ).expect("invalid pattern match");
-            Some((circ, target_cost.sub_cost(&pattern_cost)).into())
+            let mut modified = ModifiedRegion::new();
+            modified.record(&rw, &circ);
+            rw.clone().apply(&mut circ).expect("invalid pattern match");
+            let result = RewriteResult::from((circ, target_cost.sub_cost(&pattern_cost)));
+            Some(result.with_modified(modified).with_rewrites(vec![rw]))
         })
     }
 
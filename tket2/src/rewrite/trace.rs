@@ -109,6 +109,22 @@ impl<T: HugrMut> Circuit<T> {
         }
     }
 
+    /// Remove the last rewrite registered in the trace, when undoing it.
+    #[inline]
+    pub(crate) fn pop_rewrite_trace(&mut self) {
+        if !REWRITE_TRACING_ENABLED {
+            return;
+        }
+        let root = self.parent();
+        if let Some(meta) = self
+            .hugr_mut()
+            .get_metadata_mut(root, METADATA_REWRITES)
+            .as_array_mut()
+        {
+            meta.pop();
+        }
+    }
+
     /// Returns the traces of rewrites applied to the circuit.
     ///
     /// Returns `None` if rewrite tracing is not enabled for this circuit.
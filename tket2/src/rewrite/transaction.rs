@@ -0,0 +1,337 @@
+//! Speculative application of rewrites, with rollback.
+//!
+//! A [`CircuitTransaction`] applies rewrites to a circuit while recording the
+//! nodes they remove, so that they can be undone without keeping a copy of
+//! the whole circuit.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::hugr::{NodeMetadataMap, SimpleReplacementError};
+use hugr::ops::OpType;
+use hugr::{Hugr, HugrView, IncomingPort, Node, OutgoingPort};
+use itertools::Itertools;
+
+use super::trace::RewriteTrace;
+use super::CircuitRewrite;
+use crate::circuit::phase::GlobalPhase;
+use crate::Circuit;
+
+/// A guard applying rewrites to a circuit, that undoes them unless it is
+/// committed.
+///
+/// The rewrites are rolled back when calling
+/// [`CircuitTransaction::rollback`], or when the transaction is dropped
+/// without calling [`CircuitTransaction::commit`], for example when a pass
+/// returns early with an error. This allows passes to try a rewrite and only
+/// keep it if it improves the circuit.
+///
+/// The circuit can be inspected through the transaction, which dereferences
+/// to it.
+///
+/// Rolling back restores the operations, connections and metadata of the
+/// removed nodes, but not their indices.
+///
+/// # Example
+///
+/// ```
+/// # use tket2::Circuit;
+/// # use tket2::rewrite::CircuitRewrite;
+/// /// Apply a rewrite only if it reduces the number of operations.
+/// fn apply_if_smaller(circ: &mut Circuit, rewrite: CircuitRewrite) {
+///     let num_operations = circ.num_operations();
+///     let mut transaction = circ.transaction();
+///     transaction.apply_rewrite(rewrite).unwrap();
+///     if transaction.num_operations() < num_operations {
+///         transaction.commit();
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CircuitTransaction<'c, T: HugrMut = Hugr> {
+    /// The circuit being rewritten.
+    circ: &'c mut Circuit<T>,
+    /// The undo information of each applied rewrite, in order.
+    log: Vec<RewriteUndo>,
+}
+
+/// The information needed to undo a rewrite.
+#[derive(Debug, Clone)]
+struct RewriteUndo {
+    /// The nodes added by the rewrite.
+    added: Vec<Node>,
+    /// The nodes removed by the rewrite.
+    removed: Vec<RemovedNode>,
+    /// The global phase of the circuit before the rewrite.
+    phase: Option<GlobalPhase>,
+    /// Whether the rewrite was registered in the rewrite trace.
+    traced: bool,
+}
+
+/// A node removed by a rewrite.
+#[derive(Debug, Clone)]
+struct RemovedNode {
+    node: Node,
+    optype: OpType,
+    metadata: Option<NodeMetadataMap>,
+    /// The links to the inputs of the node.
+    inputs: Vec<(IncomingPort, Node, OutgoingPort)>,
+    /// The links from the outputs of the node to nodes that were not removed.
+    outputs: Vec<(OutgoingPort, Node, IncomingPort)>,
+}
+
+impl<T: HugrMut> Circuit<T> {
+    /// Start a transaction, applying rewrites that are undone unless it is
+    /// committed.
+    ///
+    /// See [`CircuitTransaction`].
+    pub fn transaction(&mut self) -> CircuitTransaction<'_, T> {
+        CircuitTransaction {
+            circ: self,
+            log: Vec::new(),
+        }
+    }
+}
+
+impl<'c, T: HugrMut> CircuitTransaction<'c, T> {
+    /// Apply a rewrite to the circuit, recording how to undo it.
+    ///
+    /// The circuit is unchanged if the rewrite fails.
+    pub fn apply_rewrite(&mut self, rewrite: CircuitRewrite) -> Result<(), SimpleReplacementError> {
+        let hugr = self.circ.hugr();
+        let parent = self.circ.parent();
+        if rewrite
+            .subcircuit()
+            .nodes()
+            .iter()
+            .any(|&node| !hugr.contains_node(node) || hugr.get_parent(node) != Some(parent))
+        {
+            return Err(SimpleReplacementError::InvalidRemovedNode());
+        }
+        let removed_nodes: HashSet<Node> = rewrite.subcircuit().nodes().iter().copied().collect();
+        let removed = rewrite
+            .subcircuit()
+            .nodes()
+            .iter()
+            .map(|&node| RemovedNode {
+                node,
+                optype: hugr.get_optype(node).clone(),
+                metadata: hugr.get_node_metadata(node).cloned(),
+                inputs: hugr
+                    .node_inputs(node)
+                    .flat_map(|port| {
+                        hugr.linked_outputs(node, port)
+                            .map(move |(src, src_port)| (port, src, src_port))
+                    })
+                    .collect(),
+                outputs: hugr
+                    .node_outputs(node)
+                    .flat_map(|port| {
+                        hugr.linked_inputs(node, port)
+                            .map(move |(dst, dst_port)| (port, dst, dst_port))
+                    })
+                    .filter(|(_, dst, _)| !removed_nodes.contains(dst))
+                    .collect(),
+            })
+            .collect_vec();
+        let replacement = rewrite.replacement();
+        let num_added = replacement.hugr().children(replacement.parent()).count() - 2;
+        let phase = self.circ.global_phase();
+
+        let trace = RewriteTrace::from(&rewrite);
+        rewrite.apply_notrace(self.circ)?;
+        let traced = self.circ.add_rewrite_trace(trace);
+        // The replacement nodes are inserted right after the output node.
+        let added = self
+            .circ
+            .hugr()
+            .children(parent)
+            .skip(2)
+            .take(num_added)
+            .collect();
+        self.log.push(RewriteUndo {
+            added,
+            removed,
+            phase,
+            traced,
+        });
+        Ok(())
+    }
+
+    /// The number of rewrites applied in the transaction.
+    pub fn num_rewrites(&self) -> usize {
+        self.log.len()
+    }
+
+    /// Keep the rewrites applied in the transaction.
+    pub fn commit(mut self) {
+        self.log.clear();
+    }
+
+    /// Undo the rewrites applied in the transaction.
+    pub fn rollback(self) {
+        // Dropping the transaction undoes the rewrites.
+    }
+
+    /// Undo the rewrites in the log, in reverse order.
+    fn undo_all(&mut self) {
+        let parent = self.circ.parent();
+        // The new indices of the removed nodes that have been restored.
+        let mut restored: HashMap<Node, Node> = HashMap::new();
+        while let Some(undo) = self.log.pop() {
+            let hugr = self.circ.hugr_mut();
+            let get = |restored: &HashMap<Node, Node>, n| restored.get(&n).copied().unwrap_or(n);
+            for node in undo.added {
+                hugr.remove_node(get(&restored, node));
+                restored.remove(&node);
+            }
+            for removed in &undo.removed {
+                for &(_, dst, dst_port) in &removed.outputs {
+                    hugr.disconnect(get(&restored, dst), dst_port);
+                }
+            }
+            for removed in &undo.removed {
+                let node = hugr.add_node_with_parent(parent, removed.optype.clone());
+                hugr.overwrite_node_metadata(node, removed.metadata.clone());
+                restored.insert(removed.node, node);
+            }
+            for removed in undo.removed {
+                let node = get(&restored, removed.node);
+                for (port, src, src_port) in removed.inputs {
+                    hugr.connect(get(&restored, src), src_port, node, port);
+                }
+                for (port, dst, dst_port) in removed.outputs {
+                    hugr.connect(node, port, get(&restored, dst), dst_port);
+                }
+            }
+
+            self.circ.set_global_phase(undo.phase);
+            if undo.traced {
+                self.circ.pop_rewrite_trace();
+            }
+        }
+    }
+}
+
+impl<'c, T: HugrMut> Deref for CircuitTransaction<'c, T> {
+    type Target = Circuit<T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.circ
+    }
+}
+
+impl<'c, T: HugrMut> Drop for CircuitTransaction<'c, T> {
+    fn drop(&mut self) {
+        self.undo_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::{fixture, rstest};
+
+    use super::*;
+    use crate::circuit::CircuitHash;
+    use crate::extension::REGISTRY;
+    use crate::rewrite::Subcircuit;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    /// `H(0); CX(0, 1); CX(0, 1); T(1)`
+    #[fixture]
+    fn circ() -> Circuit {
+        let mut circ = build_simple_circuit(2, |c| {
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            c.append(Tk2Op::T, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        circ.set_global_phase(Some(GlobalPhase::new(0.5)));
+        circ
+    }
+
+    /// A rewrite replacing the commands at the given indices.
+    fn rewrite(circ: &Circuit, indices: &[usize], replacement: Circuit) -> CircuitRewrite {
+        let commands = circ.commands().map(|cmd| cmd.node()).collect_vec();
+        let nodes = indices.iter().map(|&i| commands[i]).collect_vec();
+        let subcircuit = Subcircuit::try_from_nodes(nodes, circ).unwrap();
+        subcircuit.create_rewrite(circ, replacement).unwrap()
+    }
+
+    /// A rewrite cancelling the two CX gates.
+    fn cancel_cx(circ: &Circuit) -> CircuitRewrite {
+        let mut identity = build_simple_circuit(2, |_| Ok(())).unwrap();
+        identity.set_phase_delta(Some(GlobalPhase::new(0.25)));
+        rewrite(circ, &[1, 2], identity)
+    }
+
+    /// A rewrite replacing the last command with `Z; Z; T`.
+    fn expand_last(circ: &Circuit) -> CircuitRewrite {
+        let last = circ.num_operations() - 1;
+        let replacement = build_simple_circuit(1, |c| {
+            c.append(Tk2Op::Z, [0])?;
+            c.append(Tk2Op::Z, [0])?;
+            c.append(Tk2Op::T, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        rewrite(circ, &[last], replacement)
+    }
+
+    #[rstest]
+    fn rollback(mut circ: Circuit) {
+        let original = circ.clone();
+        let mut transaction = circ.transaction();
+        transaction.apply_rewrite(cancel_cx(&transaction)).unwrap();
+        transaction
+            .apply_rewrite(expand_last(&transaction))
+            .unwrap();
+        // The second replacement's nodes are replaced again.
+        transaction
+            .apply_rewrite(expand_last(&transaction))
+            .unwrap();
+        assert_eq!(transaction.num_rewrites(), 3);
+        assert_eq!(transaction.num_operations(), 6);
+        assert_eq!(transaction.global_phase(), Some(GlobalPhase::new(0.75)));
+        transaction.rollback();
+
+        circ.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(circ.num_operations(), 4);
+        assert_eq!(circ.global_phase(), original.global_phase());
+        assert_eq!(circ.circuit_hash(), original.circuit_hash());
+        assert_eq!(circ.unitary(), original.unitary());
+    }
+
+    #[rstest]
+    fn commit(mut circ: Circuit) {
+        let mut transaction = circ.transaction();
+        transaction.apply_rewrite(cancel_cx(&transaction)).unwrap();
+        let expected = transaction.circuit_hash();
+        transaction.commit();
+
+        circ.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(circ.num_operations(), 2);
+        assert_eq!(circ.circuit_hash(), expected);
+    }
+
+    #[rstest]
+    fn rollback_on_error(mut circ: Circuit) {
+        let original = circ.circuit_hash();
+        let speculate = |circ: &mut Circuit| -> Result<(), SimpleReplacementError> {
+            let mut transaction = circ.transaction();
+            let stale = cancel_cx(&transaction);
+            transaction.apply_rewrite(cancel_cx(&transaction))?;
+            // The nodes of the second rewrite were already removed.
+            transaction.apply_rewrite(stale)?;
+            transaction.commit();
+            Ok(())
+        };
+        assert!(speculate(&mut circ).is_err());
+        circ.hugr().validate(&REGISTRY).unwrap();
+        assert_eq!(circ.circuit_hash(), original);
+    }
+}
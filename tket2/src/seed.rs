@@ -0,0 +1,76 @@
+//! Seeding of the random components of the crate.
+//!
+//! Every randomised component can be made reproducible:
+//!
+//! - Functions drawing random values take a [`Seed`], and have a `_with_rng`
+//!   variant taking any [`Rng`], such as
+//!   [`random_circuit`](crate::circuit::generators::random_circuit) and
+//!   [`random_circuit_with_rng`](crate::circuit::generators::random_circuit_with_rng),
+//!   or [`sample`](crate::sim::sample) and
+//!   [`sample_with_rng`](crate::sim::sample_with_rng).
+//! - Components holding random state are configured with a `with_seed`
+//!   builder method or a `seed` option, such as
+//!   [`LocalSimulator::with_seed`](crate::backend::LocalSimulator::with_seed)
+//!   and [`BadgerOptions::seed`](crate::optimiser::badger::BadgerOptions::seed).
+//!
+//! Components that draw from several independent random streams, such as one
+//! per job or per worker, [`derive`] a seed for each stream from the
+//! user-provided one, so that a single seed controls a whole run.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// A seed for the random number generators of the crate.
+pub type Seed = u64;
+
+/// A random number generator seeded with `seed`.
+///
+/// The generator is portable, so the same seed gives the same values on
+/// every platform.
+pub fn rng(seed: Seed) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+/// Derive the seed of an independent random stream from a seed.
+///
+/// Different streams of the same seed, and the same stream of different
+/// seeds, give unrelated seeds.
+pub fn derive(seed: Seed, stream: u64) -> Seed {
+    // SplitMix64 finaliser, applied to the combined seed and stream.
+    let mut z = seed ^ stream.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A fresh seed drawn from the entropy of the system.
+///
+/// Use it to pick a seed for runs that do not need to be reproducible, and
+/// report it so that the run can be repeated.
+pub fn from_entropy() -> Seed {
+    rand::thread_rng().gen()
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+
+    use super::*;
+
+    #[test]
+    fn seeded_rng() {
+        let values = |seed| rng(seed).sample_iter(rand::distributions::Standard);
+        let a: Vec<u64> = values(3).take(4).collect();
+        assert_eq!(a, values(3).take(4).collect_vec());
+        assert_ne!(a, values(4).take(4).collect_vec());
+    }
+
+    #[test]
+    fn derived_seeds() {
+        let seeds = (0..4)
+            .flat_map(|seed| (0..4).map(move |stream| derive(seed, stream)))
+            .collect_vec();
+        assert!(seeds.iter().all_unique());
+        assert_eq!(derive(1, 2), derive(1, 2));
+    }
+}
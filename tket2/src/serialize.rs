@@ -1,13 +1,20 @@
 //! Utilities for serializing circuits.
 //!
 //! See [`crate::serialize::pytket`] for serialization to and from the legacy pytket format.
+//! Circuits exported from Qiskit can be loaded with [`crate::serialize::qiskit`].
 pub mod guppy;
+pub mod hugr_file;
 pub mod pytket;
+pub mod qiskit;
 
 pub use guppy::{
     load_guppy_json_file, load_guppy_json_reader, load_guppy_json_str, CircuitLoadError,
 };
+pub use hugr_file::HugrFileError;
 pub use pytket::{
     load_tk1_json_file, load_tk1_json_reader, load_tk1_json_str, save_tk1_json_file,
-    save_tk1_json_str, save_tk1_json_writer, TKETDecode,
+    save_tk1_json_str, save_tk1_json_writer, ConversionReport, DecodeOptions, TKETDecode,
+};
+pub use qiskit::{
+    load_qiskit_json_file, load_qiskit_json_reader, load_qiskit_json_str, QiskitConvertError,
 };
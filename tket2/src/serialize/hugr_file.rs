@@ -0,0 +1,164 @@
+//! Saving and loading circuits in the native HUGR JSON format.
+
+use std::path::Path;
+use std::{fs, io};
+
+use hugr::hugr::views::ExtractHugr;
+use hugr::hugr::ValidationError;
+use hugr::{Hugr, HugrView};
+use thiserror::Error;
+
+use crate::extension::REGISTRY;
+use crate::{Circuit, CircuitError, CircuitMutError};
+
+impl<T: ExtractHugr> Circuit<T> {
+    /// Save the circuit to a file as a HUGR in JSON format.
+    ///
+    /// If the circuit is not the root of its HUGR, it is first extracted into
+    /// a new HUGR with [`Circuit::extract_dfg`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written, or if the circuit
+    /// cannot be extracted.
+    pub fn save_hugr_file(&self, path: impl AsRef<Path>) -> Result<(), HugrFileError> {
+        let file = fs::File::create(path)?;
+        let writer = io::BufWriter::new(file);
+        self.save_hugr_writer(writer)
+    }
+
+    /// Save the circuit to a writer as a HUGR in JSON format.
+    ///
+    /// See [`Circuit::save_hugr_file`].
+    pub fn save_hugr_writer(&self, w: impl io::Write) -> Result<(), HugrFileError> {
+        let base = self.hugr().base_hugr();
+        if base.root() == self.parent() {
+            serde_json::to_writer(w, base)?;
+        } else {
+            serde_json::to_writer(w, self.extract_dfg()?.hugr())?;
+        }
+        Ok(())
+    }
+}
+
+impl Circuit {
+    /// Load a circuit from a file containing a HUGR in JSON format.
+    ///
+    /// The HUGR is validated against the tket2 extension
+    /// [`REGISTRY`], and its root is used as the circuit parent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid HUGR,
+    /// or if its root cannot contain a circuit.
+    pub fn load_hugr_file(path: impl AsRef<Path>) -> Result<Self, HugrFileError> {
+        let file = fs::File::open(path)?;
+        let reader = io::BufReader::new(file);
+        Self::load_hugr_reader(reader)
+    }
+
+    /// Load a circuit from a reader containing a HUGR in JSON format.
+    ///
+    /// See [`Circuit::load_hugr_file`].
+    pub fn load_hugr_reader(reader: impl io::Read) -> Result<Self, HugrFileError> {
+        let mut hugr: Hugr = serde_json::from_reader(reader)?;
+        hugr.update_validate(&REGISTRY)?;
+        let root = hugr.root();
+        Ok(Circuit::try_new(hugr, root)?)
+    }
+}
+
+/// Error type for saving and loading HUGR files.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum HugrFileError {
+    /// Cannot read or write the file.
+    #[error("Unable to access the HUGR file. {0}")]
+    Io(#[from] io::Error),
+    /// Invalid JSON.
+    #[error("Invalid HUGR JSON. {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    /// The loaded HUGR is not valid.
+    #[error("Invalid HUGR. {0}")]
+    Validation(#[from] ValidationError),
+    /// The root of the loaded HUGR cannot contain a circuit.
+    #[error(transparent)]
+    InvalidCircuit(#[from] CircuitError),
+    /// The circuit could not be extracted from its HUGR.
+    #[error(transparent)]
+    Extraction(#[from] CircuitMutError),
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+    use hugr::extension::prelude::QB_T;
+    use hugr::hugr::hugrmut::HugrMut;
+    use hugr::types::Signature;
+    use hugr::IncomingPort;
+
+    use super::*;
+    use crate::utils::{build_module_with_circuit, build_simple_circuit};
+    use crate::Tk2Op;
+
+    fn roundtrip(circ: &Circuit<impl ExtractHugr>) -> Circuit {
+        let mut buf = Vec::new();
+        circ.save_hugr_writer(&mut buf).unwrap();
+        Circuit::load_hugr_reader(buf.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn root_circuit() {
+        let circ = build_simple_circuit(2, |c| {
+            c.append(Tk2Op::H, [0])?;
+            c.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        let loaded = roundtrip(&circ);
+        assert_eq!(loaded.circuit_signature(), circ.circuit_signature());
+        assert_eq!(loaded.unitary(), circ.unitary());
+    }
+
+    #[test]
+    fn nested_circuit() {
+        let circ = build_module_with_circuit(1, |c| {
+            c.append(Tk2Op::H, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        assert_ne!(circ.parent(), circ.hugr().root());
+
+        let loaded = roundtrip(&circ);
+        assert_eq!(loaded.parent(), loaded.hugr().root());
+        assert_eq!(loaded.num_operations(), 1);
+    }
+
+    #[test]
+    fn invalid_hugr() {
+        let dfg = DFGBuilder::new(Signature::new_endo(vec![QB_T])).unwrap();
+        let [q] = dfg.input_wires_arr();
+        let mut hugr = dfg.finish_hugr_with_outputs([q], &REGISTRY).unwrap();
+        // Disconnect the output, invalidating the HUGR.
+        let output = hugr.get_io(hugr.root()).unwrap()[1];
+        hugr.disconnect(output, IncomingPort::from(0));
+        let json = serde_json::to_vec(&hugr).unwrap();
+        let err = Circuit::load_hugr_reader(json.as_slice()).unwrap_err();
+        assert!(matches!(err, HugrFileError::Validation(_)));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // Opening files is not supported in (isolated) miri
+    fn file_roundtrip() {
+        let circ = build_simple_circuit(1, |c| {
+            c.append(Tk2Op::T, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        let path = std::env::temp_dir().join("tket2_hugr_file_roundtrip.json");
+        circ.save_hugr_file(&path).unwrap();
+        let loaded = Circuit::load_hugr_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unitary(), circ.unitary());
+    }
+}
@@ -2,11 +2,14 @@
 
 mod decoder;
 mod encoder;
+mod fallback;
 mod op;
+pub mod op_table;
 
+use hugr::hugr::hugrmut::HugrMut;
 use hugr::types::Type;
 
-use hugr::Node;
+use hugr::{HugrView, Node};
 use itertools::Itertools;
 // Required for serialising ops in the tket1 hugr extension.
 pub(crate) use op::serialised::OpaqueTk1Op;
@@ -26,17 +29,20 @@ use thiserror::Error;
 use tket_json_rs::circuit_json::{self, SerialCircuit};
 use tket_json_rs::optype::OpType as SerialOpType;
 
+use crate::circuit::opgroup::METADATA_OPGROUP;
+use crate::circuit::phase::METADATA_PHASE;
+use crate::circuit::units::Qubit;
 use crate::circuit::Circuit;
+use crate::memory::{track_phase, Phase};
 
 use self::decoder::Tk1Decoder;
 use self::encoder::Tk1Encoder;
+use self::op_table::AngleUnit;
 
 pub use crate::passes::pytket::lower_to_pytket;
 
 /// Prefix used for storing metadata in the hugr nodes.
 pub const METADATA_PREFIX: &str = "TKET1";
-/// The global phase specified as metadata.
-const METADATA_PHASE: &str = "TKET1.phase";
 /// Explicit names for the input qubit registers.
 const METADATA_Q_REGISTERS: &str = "TKET1.qubit_registers";
 /// The reordered qubit registers in the output, if an implicit permutation was applied.
@@ -45,8 +51,6 @@ const METADATA_Q_OUTPUT_REGISTERS: &str = "TKET1.qubit_output_registers";
 const METADATA_B_REGISTERS: &str = "TKET1.bit_registers";
 /// The reordered bit registers in the output, if an implicit permutation was applied.
 const METADATA_B_OUTPUT_REGISTERS: &str = "TKET1.bit_output_registers";
-/// A tket1 operation "opgroup" field.
-const METADATA_OPGROUP: &str = "TKET1.opgroup";
 
 /// A serialized representation of a [`Circuit`].
 ///
@@ -67,18 +71,7 @@ impl TKETDecode for SerialCircuit {
     type EncodeError = TK1ConvertError;
 
     fn decode(self) -> Result<Circuit, Self::DecodeError> {
-        let mut decoder = Tk1Decoder::try_new(&self)?;
-
-        if !self.phase.is_empty() {
-            // TODO - add a phase gate
-            // let phase = Param::new(serialcirc.phase);
-            // decoder.add_phase(phase);
-        }
-
-        for com in self.commands {
-            decoder.add_command(com)?;
-        }
-        Ok(decoder.finish().into())
+        decode_with_options(self, DecodeOptions::default())
     }
 
     fn encode(circ: &Circuit) -> Result<Self, Self::EncodeError> {
@@ -91,24 +84,153 @@ impl TKETDecode for SerialCircuit {
     }
 }
 
+/// Convert a circuit to a pytket circuit, keeping the regions that pytket
+/// cannot represent.
+///
+/// Unlike [`TKETDecode::encode`], operations without a pytket counterpart do
+/// not make the encoding fail. Each region of such operations is replaced by a
+/// pytket `Barrier` over the qubits and bits it acts on, storing the region as
+/// a serialised HUGR in its `data` field. Decoding the circuit restores the
+/// regions as nested DFG operations.
+///
+/// A region is grown from the unsupported operations until its boundary only
+/// carries qubits, bits and incoming parameters, so some supported operations
+/// may be stored in it too.
+///
+/// # Errors
+///
+/// Returns an error if the circuit has inputs that are not qubits, bits or
+/// parameters, or if a region acting on a different number of input and
+/// output qubits cannot be avoided.
+pub fn encode_with_fallback(circ: &Circuit) -> Result<SerialCircuit, TK1ConvertError> {
+    match fallback::wrap_unsupported_regions(circ)? {
+        Some(wrapped) => SerialCircuit::encode(&wrapped),
+        None => SerialCircuit::encode(circ),
+    }
+}
+
+/// Options for decoding pytket circuits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DecodeOptions {
+    /// Fail on operations without a native tket2 counterpart.
+    ///
+    /// By default these operations are wrapped as opaque pytket operations,
+    /// which the optimisation passes leave untouched. Use
+    /// [`ConversionReport`] to list them.
+    pub strict: bool,
+}
+
+impl DecodeOptions {
+    /// Options that fail on operations without a native tket2 counterpart.
+    pub fn strict() -> Self {
+        Self { strict: true }
+    }
+
+    /// Options that wrap operations without a native tket2 counterpart as
+    /// opaque pytket operations.
+    pub fn lossy() -> Self {
+        Self { strict: false }
+    }
+}
+
+/// Convert a serialized circuit to a circuit.
+///
+/// # Errors
+///
+/// Returns an error if the circuit is invalid, or if `options` are strict and
+/// the circuit contains an operation without a native tket2 counterpart.
+pub fn decode_with_options(
+    serialcirc: SerialCircuit,
+    options: DecodeOptions,
+) -> Result<Circuit, TK1ConvertError> {
+    let mut decoder = Tk1Decoder::try_new(&serialcirc, options)?;
+
+    if !serialcirc.phase.is_empty() {
+        // TODO - add a phase gate
+        // let phase = Param::new(serialcirc.phase);
+        // decoder.add_phase(phase);
+    }
+
+    for com in serialcirc.commands {
+        decoder.add_command(com)?;
+    }
+    Ok(decoder.finish().into())
+}
+
+/// The operations of a circuit that are wrapped as opaque pytket operations.
+///
+/// These operations have no native tket2 counterpart, so the optimisation
+/// passes cannot reason about them.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConversionReport {
+    /// The opaque operations, in topological order.
+    pub opaque_ops: Vec<OpaqueOperation>,
+}
+
+/// An operation wrapped as an opaque pytket operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpaqueOperation {
+    /// The node of the operation.
+    pub node: Node,
+    /// The pytket operation type.
+    pub op_type: SerialOpType,
+}
+
+impl ConversionReport {
+    /// List the opaque pytket operations in a circuit.
+    pub fn new(circ: &Circuit<impl HugrView>) -> Self {
+        let opaque_ops = circ
+            .commands()
+            .filter_map(|cmd| {
+                let op = OpaqueTk1Op::try_from_tket2(cmd.optype()).ok()??;
+                Some(OpaqueOperation {
+                    node: cmd.node(),
+                    op_type: op.serialised_op().op_type.clone(),
+                })
+            })
+            .collect();
+        Self { opaque_ops }
+    }
+
+    /// Returns `true` if every operation was converted to a native tket2
+    /// operation.
+    pub fn is_lossless(&self) -> bool {
+        self.opaque_ops.is_empty()
+    }
+}
+
 /// Load a TKET1 circuit from a JSON file.
-pub fn load_tk1_json_file(path: impl AsRef<Path>) -> Result<Circuit, TK1ConvertError> {
+///
+/// See [`DecodeOptions`] for the handling of operations without a native tket2
+/// counterpart.
+pub fn load_tk1_json_file(
+    path: impl AsRef<Path>,
+    options: DecodeOptions,
+) -> Result<Circuit, TK1ConvertError> {
     let file = fs::File::open(path)?;
     let reader = io::BufReader::new(file);
-    load_tk1_json_reader(reader)
+    load_tk1_json_reader(reader, options)
 }
 
 /// Load a TKET1 circuit from a JSON reader.
-pub fn load_tk1_json_reader(json: impl io::Read) -> Result<Circuit, TK1ConvertError> {
+///
+/// See [`DecodeOptions`] for the handling of operations without a native tket2
+/// counterpart.
+pub fn load_tk1_json_reader(
+    json: impl io::Read,
+    options: DecodeOptions,
+) -> Result<Circuit, TK1ConvertError> {
     let ser: SerialCircuit = serde_json::from_reader(json)?;
-    let circ: Circuit = ser.decode()?;
-    Ok(circ)
+    decode_with_options(ser, options)
 }
 
 /// Load a TKET1 circuit from a JSON string.
-pub fn load_tk1_json_str(json: &str) -> Result<Circuit, TK1ConvertError> {
+///
+/// See [`DecodeOptions`] for the handling of operations without a native tket2
+/// counterpart.
+pub fn load_tk1_json_str(json: &str, options: DecodeOptions) -> Result<Circuit, TK1ConvertError> {
     let reader = json.as_bytes();
-    load_tk1_json_reader(reader)
+    load_tk1_json_reader(reader, options)
 }
 
 /// Save a circuit to file in TK1 JSON format.
@@ -134,9 +256,11 @@ pub fn save_tk1_json_file(circ: &Circuit, path: impl AsRef<Path>) -> Result<(),
 /// Returns an error if the circuit is not flat or if it contains operations not
 /// supported by pytket.
 pub fn save_tk1_json_writer(circ: &Circuit, w: impl io::Write) -> Result<(), TK1ConvertError> {
-    let serial_circ = SerialCircuit::encode(circ)?;
-    serde_json::to_writer(w, &serial_circ)?;
-    Ok(())
+    track_phase(Phase::Serialisation, || {
+        let serial_circ = SerialCircuit::encode(circ)?;
+        serde_json::to_writer(w, &serial_circ)?;
+        Ok(())
+    })
 }
 
 /// Save a circuit in TK1 JSON format to a String.
@@ -154,6 +278,108 @@ pub fn save_tk1_json_str(circ: &Circuit) -> Result<String, TK1ConvertError> {
     Ok(String::from_utf8(bytes)?)
 }
 
+/// The implicit qubit permutation of a circuit decoded from pytket.
+///
+/// Pytket circuits may end with an implicit permutation of their qubits,
+/// which is kept as metadata when decoding. Entry `i` of the returned vector
+/// is the qubit on which the state of qubit `i` ends up.
+///
+/// Returns the identity permutation if the circuit has no implicit
+/// permutation.
+pub fn implicit_qubit_permutation(circ: &Circuit<impl HugrView>) -> Vec<Qubit> {
+    let num_qubits = circ.qubit_count();
+    let identity = (0..num_qubits).map(Qubit::new).collect_vec();
+    let read_registers = |key| -> Option<Vec<circuit_json::Register>> {
+        let value = circ.hugr().get_metadata(circ.parent(), key)?;
+        serde_json::from_value(value.clone()).ok()
+    };
+    let (Some(registers), Some(outputs)) = (
+        read_registers(METADATA_Q_REGISTERS),
+        read_registers(METADATA_Q_OUTPUT_REGISTERS),
+    ) else {
+        return identity;
+    };
+    if registers.len() != num_qubits || outputs.len() != num_qubits {
+        return identity;
+    }
+
+    // The state of the input register `outputs[k]` ends up on `registers[k]`.
+    let mut permutation = vec![None; num_qubits];
+    for (k, reg) in outputs.iter().enumerate() {
+        match registers.iter().position(|r| r == reg) {
+            Some(i) if permutation[i].is_none() => permutation[i] = Some(Qubit::new(k)),
+            _ => return identity,
+        }
+    }
+    permutation.into_iter().map(Option::unwrap).collect()
+}
+
+/// Replace the implicit qubit permutation of a circuit decoded from pytket
+/// with explicit pytket `SWAP` gates at the end of the circuit.
+///
+/// After this call [`implicit_qubit_permutation`] returns the identity, and
+/// the circuit is encoded without an implicit qubit permutation. The `SWAP`
+/// gates can be decomposed with [`rebase`](crate::passes::rebase()).
+///
+/// Returns the number of `SWAP` gates added.
+pub fn elaborate_implicit_permutation(circ: &mut Circuit) -> usize {
+    let permutation = implicit_qubit_permutation(circ);
+    let swaps = circ
+        .apply_final_permutation(&permutation)
+        .expect("The implicit permutation is a valid permutation of the qubits.");
+
+    let parent = circ.parent();
+    let hugr = circ.hugr_mut();
+    if let Some(registers) = hugr.get_metadata(parent, METADATA_Q_REGISTERS).cloned() {
+        hugr.set_metadata(parent, METADATA_Q_OUTPUT_REGISTERS, registers);
+    }
+    swaps
+}
+
+/// Relabel the implicit qubit permutation of a circuit decoded from pytket,
+/// after its qubits have been relabelled with [`Circuit::permute_qubits`].
+///
+/// The qubit names are kept in place, so the qubit previously named by
+/// position `i` is named by position `permutation[i]` after the relabelling.
+pub(crate) fn permute_implicit_permutation(
+    circ: &mut Circuit<impl HugrMut>,
+    permutation: &[Qubit],
+) {
+    let parent = circ.parent();
+    if circ
+        .hugr()
+        .get_metadata(parent, METADATA_Q_OUTPUT_REGISTERS)
+        .is_none()
+    {
+        return;
+    }
+    let Some(registers) = circ
+        .hugr()
+        .get_metadata(parent, METADATA_Q_REGISTERS)
+        .and_then(|value| {
+            serde_json::from_value::<Vec<circuit_json::Register>>(value.clone()).ok()
+        })
+    else {
+        return;
+    };
+    let implicit = implicit_qubit_permutation(circ);
+    if registers.len() != permutation.len() || implicit.len() != permutation.len() {
+        return;
+    }
+
+    // The state of qubit `permutation[i]` now ends up on qubit
+    // `permutation[implicit[i]]`.
+    let mut outputs = registers.clone();
+    for (i, target) in implicit.iter().enumerate() {
+        outputs[permutation[target.index()].index()] = registers[permutation[i].index()].clone();
+    }
+    circ.hugr_mut().set_metadata(
+        parent,
+        METADATA_Q_OUTPUT_REGISTERS,
+        serde_json::to_value(outputs).unwrap(),
+    );
+}
+
 /// Error type for conversion between `Op` and `OpType`.
 #[derive(Debug, Error)]
 #[non_exhaustive]
@@ -241,6 +467,13 @@ pub enum OpConvertError {
         /// The given of parameters.
         args: Vec<circuit_json::Register>,
     },
+    /// A barrier storing a HUGR region, created by [`encode_with_fallback`],
+    /// had an invalid payload.
+    #[error("Invalid HUGR region stored in a pytket barrier. {error}")]
+    InvalidFallbackRegion {
+        /// The deserialization error.
+        error: serde_json::Error,
+    },
 }
 
 /// Error type for conversion between `Op` and `OpType`.
@@ -256,14 +489,6 @@ pub enum TK1ConvertError {
         /// The unsupported type.
         typ: Type,
     },
-    /// The circuit uses multi-indexed registers.
-    //
-    // This could be supported in the future, if there is a need for it.
-    #[error("Register {register} in the circuit has multiple indices. Tket2 does not support multi-indexed registers.")]
-    MultiIndexedRegister {
-        /// The register name.
-        register: String,
-    },
     /// Invalid JSON,
     #[error("Invalid pytket JSON. {0}")]
     InvalidJson(#[from] serde_json::Error),
@@ -273,6 +498,13 @@ pub enum TK1ConvertError {
     /// File not found.,
     #[error("Unable to load pytket json file. {0}")]
     FileLoadError(#[from] io::Error),
+    /// The unsupported operations around a node could not be stored in a
+    /// pytket operation by [`encode_with_fallback`].
+    #[error("Cannot encode the unsupported operations around {node} as a pytket operation.")]
+    UnsupportedRegion {
+        /// A node of the region.
+        node: Node,
+    },
 }
 
 /// Try to interpret a TKET1 parameter as a constant value.
@@ -297,7 +529,7 @@ fn try_param_to_constant(param: &str) -> Option<Value> {
         return None;
     };
 
-    let radians = half_turns * std::f64::consts::PI;
+    let radians = AngleUnit::TK1.convert(half_turns, AngleUnit::TK2);
     Some(ConstF64::new(radians).into())
 }
 
@@ -309,7 +541,7 @@ fn try_param_to_constant(param: &str) -> Option<Value> {
 fn try_constant_to_param(val: &Value) -> Option<String> {
     let const_float = val.get_custom_value::<ConstF64>()?;
     let radians: f64 = **const_float;
-    let half_turns = radians / std::f64::consts::PI;
+    let half_turns = AngleUnit::TK2.convert(radians, AngleUnit::TK1);
     Some(half_turns.to_string())
 }
 
@@ -6,18 +6,21 @@ use hugr::builder::{Container, Dataflow, DataflowHugr, FunctionBuilder};
 use hugr::extension::prelude::{BOOL_T, QB_T};
 
 use hugr::ops::handle::NodeHandle;
-use hugr::ops::OpType;
-use hugr::types::Signature;
-use hugr::{Hugr, Wire};
+use hugr::ops::{OpTrait, OpType};
+use hugr::std_extensions::arithmetic::float_types::FLOAT64_TYPE;
+use hugr::types::{Signature, Type};
+use hugr::{Hugr, HugrView, Wire};
 
 use itertools::{EitherOrBoth, Itertools};
 use serde_json::json;
 use tket_json_rs::circuit_json;
 use tket_json_rs::circuit_json::SerialCircuit;
 
+use super::fallback::decode_region;
+use super::op::decomposed::decompose_command;
 use super::op::Tk1Op;
 use super::{
-    try_param_to_constant, OpConvertError, RegisterHash, TK1ConvertError,
+    try_param_to_constant, DecodeOptions, OpConvertError, RegisterHash, TK1ConvertError,
     METADATA_B_OUTPUT_REGISTERS, METADATA_B_REGISTERS, METADATA_OPGROUP, METADATA_PHASE,
     METADATA_Q_OUTPUT_REGISTERS, METADATA_Q_REGISTERS,
 };
@@ -37,11 +40,16 @@ pub(super) struct Tk1Decoder {
     ordered_registers: Vec<RegisterHash>,
     /// A set of registers that encode qubits.
     qubit_registers: HashSet<RegisterHash>,
+    /// Whether to fail on operations without a native tket2 counterpart.
+    strict: bool,
 }
 
 impl Tk1Decoder {
     /// Initialize a new [`Tk1Decoder`], using the metadata from a [`SerialCircuit`].
-    pub fn try_new(serialcirc: &SerialCircuit) -> Result<Self, TK1ConvertError> {
+    pub fn try_new(
+        serialcirc: &SerialCircuit,
+        options: DecodeOptions,
+    ) -> Result<Self, TK1ConvertError> {
         let num_qubits = serialcirc.qubits.len();
         let num_bits = serialcirc.bits.len();
         let sig = Signature::new_endo([vec![QB_T; num_qubits], vec![BOOL_T; num_bits]].concat())
@@ -94,11 +102,8 @@ impl Tk1Decoder {
             .qubits
             .iter()
             .chain(&serialcirc.bits)
-            .map(|reg| {
-                check_register(reg)?;
-                Ok(RegisterHash::from(reg))
-            })
-            .collect::<Result<Vec<RegisterHash>, TK1ConvertError>>()?;
+            .map(RegisterHash::from)
+            .collect_vec();
 
         // Map each register element to their starting wire.
         let register_wires: HashMap<RegisterHash, Wire> = ordered_registers
@@ -112,6 +117,7 @@ impl Tk1Decoder {
             register_wires,
             ordered_registers,
             qubit_registers,
+            strict: options.strict,
         })
     }
 
@@ -135,7 +141,19 @@ impl Tk1Decoder {
 
     /// Add a tket1 [`circuit_json::Command`] from the serial circuit to the
     /// decoder.
+    ///
+    /// Operations with a decomposition into native operations are added as a
+    /// sequence of commands, see [`decompose_command`]. Barriers storing a HUGR
+    /// region are replaced by the region, see [`add_region`](Self::add_region).
     pub fn add_command(&mut self, command: circuit_json::Command) -> Result<(), OpConvertError> {
+        if let Some(region) = decode_region(&command.op)? {
+            return self.add_region(region, command);
+        }
+        if let Some(commands) = decompose_command(&command) {
+            return commands
+                .into_iter()
+                .try_for_each(|cmd| self.add_command(cmd));
+        }
         let circuit_json::Command {
             op, args, opgroup, ..
         } = command;
@@ -148,6 +166,10 @@ impl Tk1Decoder {
             .count();
         let num_input_bits = args.len() - num_qubits;
         let tk1op = Tk1Op::from_serialised_op(op, num_qubits, num_input_bits);
+        if let (true, Tk1Op::Opaque(opaque)) = (self.strict, &tk1op) {
+            let op_type = opaque.serialised_op().op_type.clone();
+            return Err(OpConvertError::UnsupportedSerializedOp(op_type));
+        }
 
         let (input_wires, output_registers) = self.get_op_wires(&tk1op, &args, op_params)?;
         let op: OpType = (&tk1op).into();
@@ -169,6 +191,92 @@ impl Tk1Decoder {
         Ok(())
     }
 
+    /// Add a HUGR region stored by the fallback encoding as a nested DFG.
+    ///
+    /// The command arguments are the qubits of the region, followed by its
+    /// bit inputs and then its bit outputs. Its parameters are the float
+    /// inputs of the region.
+    fn add_region(
+        &mut self,
+        region: Hugr,
+        command: circuit_json::Command,
+    ) -> Result<(), OpConvertError> {
+        let circuit_json::Command {
+            op, args, opgroup, ..
+        } = command;
+        let optype = region.get_optype(region.root()).clone();
+        let signature = optype.dataflow_signature().unwrap_or_default();
+        let count = |types: &[Type], ty: &Type| types.iter().filter(|&t| t == ty).count();
+        let num_qubits = count(signature.input_types(), &QB_T);
+        let num_input_bits = count(signature.input_types(), &BOOL_T);
+        let num_output_bits = count(signature.output_types(), &BOOL_T);
+        if args.len() != num_qubits + num_input_bits + num_output_bits {
+            return Err(OpConvertError::MissingSerialisedArguments {
+                optype,
+                expected_qubits: num_qubits,
+                expected_bits: num_input_bits + num_output_bits,
+                args,
+            });
+        }
+        let params = op.params.unwrap_or_default();
+        let num_params = count(signature.input_types(), &FLOAT64_TYPE);
+        if params.len() < num_params {
+            return Err(OpConvertError::MissingSerialisedParams {
+                optype,
+                expected: num_params,
+                params,
+            });
+        }
+        let (qubits, bits) = args.split_at(num_qubits);
+        let (input_bits, output_bits) = bits.split_at(num_input_bits);
+
+        let mut qubit_wires = qubits
+            .iter()
+            .map(|reg| self.register_wire(reg))
+            .collect_vec();
+        let mut bit_wires = input_bits
+            .iter()
+            .map(|reg| self.register_wire(reg))
+            .collect_vec();
+        let mut param_wires = params
+            .into_iter()
+            .map(|param| self.create_param_wire(param))
+            .collect_vec();
+        qubit_wires.reverse();
+        bit_wires.reverse();
+        param_wires.reverse();
+        let inputs = signature
+            .input_types()
+            .iter()
+            .map(|ty| match ty {
+                ty if ty == &QB_T => qubit_wires.pop(),
+                ty if ty == &BOOL_T => bit_wires.pop(),
+                _ => param_wires.pop(),
+            })
+            .collect::<Option<Vec<Wire>>>()
+            .expect("The region inputs are qubits, bits and parameters.");
+        let node = self
+            .hugr
+            .add_hugr_with_wires(region, inputs)
+            .expect("The region inputs have been checked.");
+        if let Some(opgroup) = opgroup {
+            self.hugr
+                .set_child_metadata(node.node(), METADATA_OPGROUP, json!(opgroup));
+        }
+
+        // Each qubit output replaces the corresponding qubit input.
+        let mut qubits = qubits.iter();
+        let mut output_bits = output_bits.iter();
+        for (ty, wire) in signature.output_types().iter().zip(node.outputs()) {
+            let register = match ty {
+                ty if ty == &QB_T => qubits.next(),
+                _ => output_bits.next(),
+            };
+            self.set_register_wire(register.unwrap(), wire);
+        }
+        Ok(())
+    }
+
     /// Returns the input wires to connect to a new operation
     /// and the registers to associate with outputs.
     ///
@@ -284,14 +392,3 @@ impl Tk1Decoder {
         self.qubit_registers.contains(&register.into())
     }
 }
-
-/// Only single-indexed registers are supported.
-fn check_register(register: &circuit_json::Register) -> Result<(), TK1ConvertError> {
-    if register.1.len() != 1 {
-        Err(TK1ConvertError::MultiIndexedRegister {
-            register: register.0.clone(),
-        })
-    } else {
-        Ok(())
-    }
-}
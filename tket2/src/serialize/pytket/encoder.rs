@@ -57,8 +57,10 @@ impl Tk1Encoder {
         // Recover other parameters stored in the metadata
         // TODO: Check for invalid encoded metadata
         let phase = match hugr.get_metadata(circ.parent(), METADATA_PHASE) {
-            Some(p) => p.as_str().unwrap().to_string(),
-            None => "0".to_string(),
+            Some(serde_json::Value::String(p)) => p.clone(),
+            // Unknown phases, introduced by rewrites whose phase could not
+            // be determined, are dropped.
+            _ => circ.global_phase().unwrap_or_default().to_string(),
         };
 
         let qubit_tracker = QubitTracker::new(circ);
@@ -316,46 +318,26 @@ impl QubitTracker {
     /// Consumes the tracker and returns the final list of qubit registers, along
     /// with the final permutation of the outputs.
     pub fn finish(
-        mut self,
+        self,
         _circ: &Circuit<impl HugrView>,
     ) -> (Vec<RegisterUnit>, Vec<circuit_json::Permutation>) {
-        // Ensure the input and output lists have the same registers.
-        let mut outputs = self.outputs.unwrap_or_default();
-        let mut input_regs: HashSet<RegisterHash> =
-            self.inputs.iter().map(RegisterHash::from).collect();
-        let output_regs: HashSet<RegisterHash> = outputs.iter().map(RegisterHash::from).collect();
-
-        for inp in &self.inputs {
-            if !output_regs.contains(&inp.into()) {
-                outputs.push(inp.clone());
-            }
-        }
-        for out in &outputs {
-            if !input_regs.contains(&out.into()) {
-                self.inputs.push(out.clone());
-            }
-        }
-        input_regs.extend(output_regs);
-
-        // Add registers defined mid-circuit to both ends.
-        for reg in self.qubit_to_reg.into_values() {
-            if !input_regs.contains(&(&reg).into()) {
-                self.inputs.push(reg.clone());
-                outputs.push(reg);
-            }
-        }
-
-        // TODO: Look at the circuit outputs to determine the final permutation.
-        //
-        // We don't have the `CircuitUnit::Linear` assignments for the outputs
-        // here, so that requires some extra piping.
-        let permutation = outputs
+        // Add the registers only present in the output metadata, and then the
+        // registers defined mid-circuit in the order of their qubits.
+        let registers = with_new_registers(self.inputs, self.outputs.iter().flatten().cloned());
+        let new_registers = self
+            .qubit_to_reg
             .into_iter()
-            .zip(&self.inputs)
-            .map(|(out, inp)| circuit_json::Permutation(inp.clone(), out))
-            .collect_vec();
+            .sorted_by_key(|(unit_id, _)| *unit_id)
+            .map(|(_, reg)| reg);
+        let registers = with_new_registers(registers, new_registers);
 
-        (self.inputs, permutation)
+        // Each qubit keeps its register until the end of the circuit.
+        //
+        // TODO: Look at the circuit outputs to detect wires crossing at the
+        // output node. We don't have the `CircuitUnit::Linear` assignments for
+        // the outputs here, so that requires some extra piping.
+        let permutation = implicit_permutation(&registers, self.outputs.as_deref(), &registers);
+        (registers, permutation)
     }
 }
 
@@ -376,6 +358,9 @@ struct BitTracker {
     /// Registers defined in the metadata, but not present in the circuit
     /// inputs.
     unused_registers: VecDeque<RegisterUnit>,
+    /// The register name of each wire connected to a bit output of the
+    /// circuit, according to the metadata.
+    output_wire_registers: HashMap<Wire, RegisterUnit>,
     /// A generator of new registers units to use for bit wires.
     unit_generator: RegisterUnitGenerator,
 }
@@ -418,7 +403,7 @@ impl BitTracker {
             _ => None,
         });
 
-        let mut unused_registers: HashSet<RegisterUnit> = tracker.inputs.iter().cloned().collect();
+        let mut used_registers: HashSet<RegisterUnit> = HashSet::new();
         for (i, wire) in bit_input_wires.enumerate() {
             // If the input is not used in the circuit, ignore it.
             if circ
@@ -432,7 +417,7 @@ impl BitTracker {
 
             // Use the given input register names if available, or create new ones.
             if let Some(reg) = tracker.inputs.get(i) {
-                unused_registers.remove(reg);
+                used_registers.insert(reg.clone());
                 tracker.bit_to_reg.insert(wire, reg.clone());
             } else {
                 let reg = tracker.add_bit_register(wire).clone();
@@ -441,17 +426,39 @@ impl BitTracker {
         }
 
         // If a register was defined in the metadata but not used in the circuit,
-        // we keep it so it can be assigned to an operation output.
-        tracker.unused_registers = unused_registers.into_iter().collect();
+        // we keep it so it can be assigned to an operation output. They are
+        // kept in input order, so that encoding is deterministic.
+        tracker.unused_registers = tracker
+            .inputs
+            .iter()
+            .filter(|reg| !used_registers.contains(reg))
+            .cloned()
+            .collect();
+
+        // Decoded circuits carry the final value of the `i`-th bit register
+        // in their `i`-th bit output. Keep track of these, so that operations
+        // writing to a bit output reuse the original register name.
+        let output_types = circ.circuit_signature().output;
+        tracker.output_wire_registers = circ
+            .hugr()
+            .all_linked_outputs(circ.output_node())
+            .zip(output_types.iter())
+            .filter(|(_, ty)| *ty == &BOOL_T)
+            .zip(&tracker.inputs)
+            .map(|(((node, port), _), reg)| (Wire::new(node, port), reg.clone()))
+            .collect();
 
         tracker
     }
 
     /// Add a new register unit for a bit wire.
     pub fn add_bit_register(&mut self, wire: Wire) -> &RegisterUnit {
-        let reg = self
-            .unused_registers
-            .pop_front()
+        let output_reg = self.output_wire_registers.get(&wire).and_then(|reg| {
+            let pos = self.unused_registers.iter().position(|r| r == reg)?;
+            self.unused_registers.remove(pos)
+        });
+        let reg = output_reg
+            .or_else(|| self.unused_registers.pop_front())
             .unwrap_or_else(|| self.unit_generator.next());
 
         self.bit_to_reg.insert(wire, reg);
@@ -466,78 +473,98 @@ impl BitTracker {
     /// Consumes the tracker and returns the final list of bit registers, along
     /// with the final permutation of the outputs.
     pub fn finish(
-        mut self,
+        self,
         circ: &Circuit<impl HugrView>,
     ) -> (Vec<RegisterUnit>, Vec<circuit_json::Permutation>) {
-        let mut circuit_output_order: Vec<RegisterUnit> = Vec::with_capacity(self.inputs.len());
-        for (node, port) in circ.hugr().all_linked_outputs(circ.output_node()) {
-            let wire = Wire::new(node, port);
-            if let Some(reg) = self.bit_to_reg.get(&wire) {
-                circuit_output_order.push(reg.clone());
-            }
-        }
-
-        // Ensure the input and output lists have the same registers.
-        let mut outputs = self.outputs.unwrap_or_default();
-        let mut input_regs: HashSet<RegisterHash> =
-            self.inputs.iter().map(RegisterHash::from).collect();
-        let output_regs: HashSet<RegisterHash> = outputs.iter().map(RegisterHash::from).collect();
+        // The registers of the wires reaching each bit output of the circuit.
+        let output_types = circ.circuit_signature().output;
+        let circuit_outputs = circ
+            .hugr()
+            .all_linked_outputs(circ.output_node())
+            .zip(output_types.iter())
+            .filter(|(_, ty)| *ty == &BOOL_T)
+            .filter_map(|((node, port), _)| self.bit_to_reg.get(&Wire::new(node, port)).cloned())
+            .collect_vec();
 
-        for inp in &self.inputs {
-            if !output_regs.contains(&inp.into()) {
-                outputs.push(inp.clone());
-            }
-        }
-        for out in &outputs {
-            if !input_regs.contains(&out.into()) {
-                self.inputs.push(out.clone());
-            }
-        }
-        input_regs.extend(output_regs);
+        // Add the registers only present in the output metadata, and then the
+        // registers defined mid-circuit.
+        let registers = with_new_registers(self.inputs, self.outputs.iter().flatten().cloned());
+        let new_registers = self
+            .bit_to_reg
+            .into_values()
+            .sorted_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        let registers = with_new_registers(registers, new_registers);
+
+        let permutation =
+            implicit_permutation(&registers, self.outputs.as_deref(), &circuit_outputs);
+        (registers, permutation)
+    }
+}
 
-        // Add registers defined mid-circuit to both ends.
-        for reg in self.bit_to_reg.into_values() {
-            if !input_regs.contains(&(&reg).into()) {
-                self.inputs.push(reg.clone());
-                outputs.push(reg);
-            }
+/// Append new registers to a list of registers, skipping the ones already
+/// present.
+fn with_new_registers(
+    mut registers: Vec<RegisterUnit>,
+    new_registers: impl IntoIterator<Item = RegisterUnit>,
+) -> Vec<RegisterUnit> {
+    let mut known: HashSet<RegisterHash> = registers.iter().map(RegisterHash::from).collect();
+    for reg in new_registers {
+        if known.insert(RegisterHash::from(&reg)) {
+            registers.push(reg);
         }
+    }
+    registers
+}
 
-        // And ensure `circuit_output_order` has all virtual registers added too.
-        let circuit_outputs: HashSet<RegisterHash> = circuit_output_order
+/// Compute the implicit permutation of a list of registers.
+///
+/// The `i`-th output of the circuit is associated with `registers[i]`, and
+/// `circuit_outputs[i]` is the register of the wire reaching it. When the
+/// circuit was decoded from pytket, `original_outputs[i]` is the register
+/// whose wire originally ended on `registers[i]`, as stored in the
+/// [`METADATA_Q_OUTPUT_REGISTERS`] and [`METADATA_B_OUTPUT_REGISTERS`]
+/// metadata.
+///
+/// Registers without an assigned output are mapped to the remaining ones, in
+/// order, so that the result is always a permutation of `registers`.
+fn implicit_permutation(
+    registers: &[RegisterUnit],
+    original_outputs: Option<&[RegisterUnit]>,
+    circuit_outputs: &[RegisterUnit],
+) -> Vec<circuit_json::Permutation> {
+    // The original outputs are ignored if they contain duplicates.
+    let original_outputs = original_outputs
+        .filter(|outputs| outputs.iter().map(RegisterHash::from).all_unique())
+        .unwrap_or_default();
+    let origin = |reg: &RegisterUnit| {
+        registers
             .iter()
-            .map(RegisterHash::from)
-            .collect();
-        for out in &outputs {
-            if !circuit_outputs.contains(&out.into()) {
-                circuit_output_order.push(out.clone());
-            }
+            .position(|r| r == reg)
+            .and_then(|i| original_outputs.get(i))
+            .unwrap_or(reg)
+            .clone()
+    };
+
+    let mut sources: HashSet<RegisterUnit> = HashSet::new();
+    let mut targets: HashSet<&RegisterUnit> = HashSet::new();
+    let mut permutation = Vec::with_capacity(registers.len());
+    for (wire_reg, target) in circuit_outputs.iter().zip(registers) {
+        let source = origin(wire_reg);
+        if sources.contains(&source) || targets.contains(target) {
+            continue;
         }
+        sources.insert(source.clone());
+        targets.insert(target);
+        permutation.push(circuit_json::Permutation(source, target.clone()));
+    }
 
-        // Compute the final permutation. This is a combination of two mappings:
-        // - First, the original implicit permutation for the circuit, if this was decoded from pytket.
-        let original_permutation: HashMap<RegisterUnit, RegisterHash> = self
-            .inputs
-            .iter()
-            .zip(&outputs)
-            .map(|(inp, out)| (inp.clone(), RegisterHash::from(out)))
-            .collect();
-        // - Second, the actual reordering of outputs seen at the circuit's output node.
-        let mut circuit_permutation: HashMap<RegisterHash, RegisterUnit> = outputs
-            .iter()
-            .zip(circuit_output_order)
-            .map(|(out, circ_out)| (RegisterHash::from(out), circ_out))
-            .collect();
-        // The final permutation is the composition of these two mappings.
-        let permutation = original_permutation
-            .into_iter()
-            .map(|(inp, out)| {
-                circuit_json::Permutation(inp, circuit_permutation.remove(&out).unwrap())
-            })
-            .collect_vec();
-
-        (self.inputs, permutation)
+    // Complete the permutation with the unassigned registers.
+    let free_sources = registers.iter().filter(|r| !sources.contains(r));
+    let free_targets = registers.iter().filter(|r| !targets.contains(r));
+    for (source, target) in free_sources.zip(free_targets) {
+        permutation.push(circuit_json::Permutation(source.clone(), target.clone()));
     }
+    permutation
 }
 
 /// A structure for tracking the parameters of a circuit being encoded.
@@ -661,8 +688,8 @@ struct RegisterUnitGenerator {
 impl RegisterUnitGenerator {
     /// Create a new [`RegisterUnitGenerator`]
     ///
-    /// Scans the set of existing registers to find the last used index, and
-    /// starts generating new unit names from there.
+    /// Scans the set of existing single-indexed registers to find the last
+    /// used index, and starts generating new unit names from there.
     pub fn new<'a>(
         register: impl ToString,
         existing: impl IntoIterator<Item = &'a RegisterUnit>,
@@ -670,10 +697,13 @@ impl RegisterUnitGenerator {
         let register = register.to_string();
         let mut last_unit: Option<u16> = None;
         for reg in existing {
+            let [index] = reg.1[..] else {
+                continue;
+            };
             if reg.0 != register {
                 continue;
             }
-            last_unit = Some(last_unit.unwrap_or_default().max(reg.1[0] as u16));
+            last_unit = Some(last_unit.unwrap_or_default().max(index as u16));
         }
         RegisterUnitGenerator {
             register,
@@ -0,0 +1,379 @@
+//! Fallback encoding of the circuit regions that pytket cannot represent.
+//!
+//! With [`encode_with_fallback`](super::encode_with_fallback), every region
+//! of a circuit that the encoder does not understand is replaced by a pytket
+//! `Barrier` over the qubits and bits it acts on. The region is stored as a
+//! serialised HUGR in the `data` field of the barrier, and restored as a
+//! nested DFG when the circuit is decoded.
+//!
+//! Regions are grown from the unsupported operations until their boundary
+//! only carries qubits, bits, and parameters flowing into the region, and
+//! until they are convex.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use hugr::extension::prelude::{BOOL_T, QB_T};
+use hugr::hugr::hugrmut::HugrMut;
+use hugr::hugr::views::{DescendantsGraph, HierarchyView, SiblingSubgraph};
+use hugr::ops::custom::OpaqueOp;
+use hugr::ops::{CustomOp, Input, OpType, Output, DFG};
+use hugr::std_extensions::arithmetic::float_types::FLOAT64_TYPE;
+use hugr::types::{EdgeKind, Signature, Type, TypeArg};
+use hugr::{Direction, Hugr, HugrView, Node, Port};
+use itertools::Itertools;
+use tket_json_rs::circuit_json;
+use tket_json_rs::optype::OpType as SerialOpType;
+
+use super::op::serialised::OpaqueTk1Op;
+use super::op_table::op_mapping;
+use super::{try_constant_to_param, OpConvertError, TK1ConvertError};
+use crate::extension::{TKET1_EXTENSION_ID, TKET1_OP_NAME};
+use crate::ops::match_symb_const_op;
+use crate::{Circuit, Tk2Op};
+
+/// Prefix of the `data` field of the pytket barriers storing a HUGR region.
+pub(super) const FALLBACK_DATA_PREFIX: &str = "tket2.hugr:";
+
+/// Replace the regions of a circuit that cannot be encoded by opaque pytket
+/// barriers storing them.
+///
+/// Returns `None` if every operation of the circuit can be encoded.
+pub(super) fn wrap_unsupported_regions(circ: &Circuit) -> Result<Option<Circuit>, TK1ConvertError> {
+    let order: HashMap<Node, usize> = circ
+        .commands()
+        .enumerate()
+        .map(|(i, cmd)| (cmd.node(), i))
+        .collect();
+    let unsupported = circ
+        .commands()
+        .filter(|cmd| !is_encodable(cmd.optype()))
+        .map(|cmd| cmd.node())
+        .collect_vec();
+    if unsupported.is_empty() {
+        return Ok(None);
+    }
+
+    let mut wrapped = circ.clone();
+    for region in unsupported_regions(circ, unsupported, &order) {
+        let subgraph = SiblingSubgraph::try_from_nodes(region.clone(), circ.hugr())
+            .map_err(|_| TK1ConvertError::UnsupportedRegion { node: region[0] })?;
+        let op = region_op(circ, &subgraph)
+            .ok_or(TK1ConvertError::UnsupportedRegion { node: region[0] })?;
+        let signature = subgraph.signature(circ.hugr());
+        let input_order = port_order(signature.input_types());
+        let output_order = port_order(signature.output_types());
+        replace_region(&mut wrapped, &subgraph, op, &input_order, &output_order);
+    }
+    Ok(Some(wrapped))
+}
+
+/// Returns the HUGR region stored in a pytket operation, if it is a barrier
+/// created by [`wrap_unsupported_regions`].
+pub(super) fn decode_region(op: &circuit_json::Operation) -> Result<Option<Hugr>, OpConvertError> {
+    if op.op_type != SerialOpType::Barrier {
+        return Ok(None);
+    }
+    let Some(payload) = op
+        .data
+        .as_deref()
+        .and_then(|data| data.strip_prefix(FALLBACK_DATA_PREFIX))
+    else {
+        return Ok(None);
+    };
+    let region = serde_json::from_str(payload)
+        .map_err(|error| OpConvertError::InvalidFallbackRegion { error })?;
+    Ok(Some(region))
+}
+
+/// Check whether the encoder supports an operation.
+///
+/// Native operations without a pytket counterpart, other than the ones the
+/// encoder handles implicitly, are not supported.
+fn is_encodable(op: &OpType) -> bool {
+    match op {
+        OpType::Const(const_op) => try_constant_to_param(const_op.value()).is_some(),
+        OpType::LoadConstant(load) => load.constant_type() == &FLOAT64_TYPE,
+        _ if match_symb_const_op(op).is_some() => true,
+        _ => match Tk2Op::try_from(op) {
+            Ok(Tk2Op::QAlloc | Tk2Op::QFree | Tk2Op::AngleAdd) => true,
+            Ok(tk2op) => op_mapping(tk2op).is_some(),
+            Err(_) => OpaqueTk1Op::try_from_tket2(op).is_ok_and(|op| op.is_some()),
+        },
+    }
+}
+
+/// Whether a link between a region and a node outside it cannot be part of
+/// the region boundary, so the node must be added to the region.
+///
+/// `port` is the port of the link on the region side.
+fn must_absorb(hugr: &impl HugrView, node: Node, port: Port) -> bool {
+    match hugr.get_optype(node).port_kind(port) {
+        Some(EdgeKind::Value(ty)) => {
+            let is_param_output = ty == FLOAT64_TYPE && port.direction() == Direction::Outgoing;
+            is_param_output || ![QB_T, BOOL_T, FLOAT64_TYPE].contains(&ty)
+        }
+        _ => true,
+    }
+}
+
+/// Group the unsupported operations of a circuit into regions that can be
+/// replaced by a single pytket operation.
+///
+/// The regions are returned in topological order, with their nodes sorted
+/// topologically.
+fn unsupported_regions(
+    circ: &Circuit,
+    unsupported: Vec<Node>,
+    order: &HashMap<Node, usize>,
+) -> Vec<Vec<Node>> {
+    let hugr = circ.hugr();
+    let [input, output] = circ.io_nodes();
+    let is_command = |n: Node| order.contains_key(&n) && n != input && n != output;
+
+    let mut nodes: HashSet<Node> = unsupported.into_iter().collect();
+    loop {
+        // Absorb the neighbours linked by edges that cannot cross the boundary.
+        let mut queue: VecDeque<Node> = nodes.iter().copied().collect();
+        while let Some(node) = queue.pop_front() {
+            for port in hugr.all_node_ports(node) {
+                for (neighbour, _) in hugr.linked_ports(node, port) {
+                    if is_command(neighbour)
+                        && !nodes.contains(&neighbour)
+                        && must_absorb(hugr, node, port)
+                    {
+                        nodes.insert(neighbour);
+                        queue.push_back(neighbour);
+                    }
+                }
+            }
+        }
+
+        // Make each connected region convex.
+        let regions = connected_regions(hugr, &nodes, order);
+        let hull = regions
+            .iter()
+            .flat_map(|region| {
+                let descendants = reachable(hugr, region, Direction::Outgoing, is_command);
+                let ancestors = reachable(hugr, region, Direction::Incoming, is_command);
+                descendants.intersection(&ancestors).copied().collect_vec()
+            })
+            .filter(|n| !nodes.contains(n))
+            .collect_vec();
+        if hull.is_empty() {
+            return regions;
+        }
+        nodes.extend(hull);
+    }
+}
+
+/// Split a set of nodes into connected regions, sorted topologically.
+fn connected_regions(
+    hugr: &impl HugrView,
+    nodes: &HashSet<Node>,
+    order: &HashMap<Node, usize>,
+) -> Vec<Vec<Node>> {
+    let mut visited: HashSet<Node> = HashSet::new();
+    let mut regions = Vec::new();
+    for &start in nodes.iter().sorted_by_key(|n| order[n]) {
+        if !visited.insert(start) {
+            continue;
+        }
+        let mut region = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(node) = queue.pop_front() {
+            let neighbours = hugr
+                .input_neighbours(node)
+                .chain(hugr.output_neighbours(node));
+            for neighbour in neighbours {
+                if nodes.contains(&neighbour) && visited.insert(neighbour) {
+                    region.push(neighbour);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        region.sort_by_key(|n| order[n]);
+        regions.push(region);
+    }
+    regions
+}
+
+/// The commands reachable from a region in the given direction, excluding
+/// the region itself.
+fn reachable(
+    hugr: &impl HugrView,
+    region: &[Node],
+    dir: Direction,
+    is_command: impl Fn(Node) -> bool,
+) -> HashSet<Node> {
+    let mut visited: HashSet<Node> = HashSet::new();
+    let mut queue: VecDeque<Node> = region.iter().copied().collect();
+    while let Some(node) = queue.pop_front() {
+        for neighbour in hugr.neighbours(node, dir) {
+            if is_command(neighbour) && !region.contains(&neighbour) && visited.insert(neighbour) {
+                queue.push_back(neighbour);
+            }
+        }
+    }
+    visited
+}
+
+/// Build the opaque pytket barrier storing a region.
+///
+/// Returns `None` if the region boundary cannot be encoded, i.e. if it has
+/// inputs other than qubits, bits and parameters, outputs other than qubits
+/// and bits, or a different number of input and output qubits.
+fn region_op(circ: &Circuit, subgraph: &SiblingSubgraph) -> Option<OpType> {
+    let signature = subgraph.signature(circ.hugr());
+    let count = |types: &[Type], ty: &Type| types.iter().filter(|&t| t == ty).count();
+    let inputs = signature.input_types();
+    let outputs = signature.output_types();
+    let num_qubits = count(inputs, &QB_T);
+    let supported_inputs = num_qubits + count(inputs, &BOOL_T) + count(inputs, &FLOAT64_TYPE);
+    if supported_inputs != inputs.len()
+        || count(outputs, &QB_T) != num_qubits
+        || num_qubits + count(outputs, &BOOL_T) != outputs.len()
+    {
+        return None;
+    }
+
+    // The region is stored as a DFG, to be inserted back when decoding.
+    let region = extract_region(circ.hugr(), subgraph);
+    let mut serial_op = circuit_json::Operation::default();
+    serial_op.op_type = SerialOpType::Barrier;
+    serial_op.n_qb = Some(num_qubits as u32);
+    serial_op.data = Some(format!(
+        "{FALLBACK_DATA_PREFIX}{}",
+        serde_json::to_string(&region).ok()?
+    ));
+    // Bit inputs and outputs are encoded as separate arguments.
+    let num_bits = count(inputs, &BOOL_T) + count(outputs, &BOOL_T);
+    let tk1op = OpaqueTk1Op::new_from_op(serial_op, num_qubits, num_bits);
+
+    // The opaque operation has the signature of the region, with qubits first
+    // as expected by the encoder, which is not the one of regular opaque
+    // pytket operations. It only lives in the circuit being encoded.
+    let reorder = |types: &[Type]| {
+        port_order(types)
+            .into_iter()
+            .map(|i| types[i].clone())
+            .collect_vec()
+    };
+    let signature = Signature::new(reorder(inputs), reorder(outputs));
+    let payload = TypeArg::String {
+        arg: serde_json::to_string(&tk1op).ok()?,
+    };
+    let op = OpaqueOp::new(
+        TKET1_EXTENSION_ID,
+        TKET1_OP_NAME,
+        String::new(),
+        vec![payload],
+        signature,
+    );
+    Some(CustomOp::new_opaque(op).into())
+}
+
+/// Extract a region into a DFG-rooted HUGR.
+///
+/// Unlike [`SiblingSubgraph::extract_subgraph`], the nodes nested inside the
+/// region nodes are extracted too.
+fn extract_region(hugr: &impl HugrView, subgraph: &SiblingSubgraph) -> Hugr {
+    let signature = subgraph.signature(hugr);
+    let mut region = Hugr::new(DFG {
+        signature: signature.clone(),
+    });
+    let root = region.root();
+    let input = region.add_node_with_parent(
+        root,
+        Input {
+            types: signature.input,
+        },
+    );
+    let output = region.add_node_with_parent(
+        root,
+        Output {
+            types: signature.output,
+        },
+    );
+    let node_map: HashMap<Node, Node> = subgraph
+        .nodes()
+        .iter()
+        .map(|&node| {
+            let view = DescendantsGraph::<Node>::try_new(hugr, node).unwrap();
+            (node, region.insert_from_view(root, &view).new_root)
+        })
+        .collect();
+
+    for (&node, &new_node) in &node_map {
+        for port in hugr.node_outputs(node) {
+            for (target, target_port) in hugr.linked_inputs(node, port) {
+                if let Some(&new_target) = node_map.get(&target) {
+                    region.connect(new_node, port, new_target, target_port);
+                }
+            }
+        }
+    }
+    for (port, uses) in subgraph.incoming_ports().iter().enumerate() {
+        for &(node, node_port) in uses {
+            region.connect(input, port, node_map[&node], node_port);
+        }
+    }
+    for (port, &(node, node_port)) in subgraph.outgoing_ports().iter().enumerate() {
+        region.connect(node_map[&node], node_port, output, port);
+    }
+    region
+}
+
+/// The order of the ports of a region on its opaque operation: qubits first,
+/// then bits, then parameters.
+///
+/// Entry `i` is the index of the region port for the `i`-th port of the
+/// operation.
+fn port_order(types: &[Type]) -> Vec<usize> {
+    let rank = |ty: &Type| [QB_T, BOOL_T].iter().position(|t| t == ty).unwrap_or(2);
+    (0..types.len())
+        .sorted_by_key(|&i| rank(&types[i]))
+        .collect()
+}
+
+/// Replace a region of a circuit by a single operation.
+///
+/// The ports of the operation are the ones of the region, reordered as given
+/// by `input_order` and `output_order`. Unlike a
+/// [`SimpleReplacement`](hugr::hugr::rewrite::simple_replace::SimpleReplacement),
+/// this supports regions containing nested operations.
+fn replace_region(
+    circ: &mut Circuit,
+    subgraph: &SiblingSubgraph,
+    op: OpType,
+    input_order: &[usize],
+    output_order: &[usize],
+) {
+    let parent = circ.parent();
+    let hugr = circ.hugr_mut();
+    let node = hugr.add_node_with_parent(parent, op);
+    for (port, &i) in input_order.iter().enumerate() {
+        let (n, p) = subgraph.incoming_ports()[i][0];
+        let (src, src_port) = hugr.single_linked_output(n, p).unwrap();
+        hugr.connect(src, src_port, node, port);
+    }
+    for (port, &i) in output_order.iter().enumerate() {
+        let (n, p) = subgraph.outgoing_ports()[i];
+        let targets = hugr.linked_inputs(n, p).collect_vec();
+        for (tgt, tgt_port) in targets {
+            hugr.disconnect(tgt, tgt_port);
+            hugr.connect(node, port, tgt, tgt_port);
+        }
+    }
+    for &n in subgraph.nodes() {
+        remove_subtree(hugr, n);
+    }
+}
+
+/// Remove a node and all its descendants.
+fn remove_subtree(hugr: &mut impl HugrMut, node: Node) {
+    let children = hugr.children(node).collect_vec();
+    for child in children {
+        remove_subtree(hugr, child);
+    }
+    hugr.remove_node(node);
+}
@@ -6,6 +6,7 @@
 //! circuits by ensuring they always define a signature, and computing the
 //! explicit count of qubits and linear bits.
 
+pub(crate) mod decomposed;
 mod native;
 pub(crate) mod serialised;
 
@@ -0,0 +1,171 @@
+//! Pytket operations without a native tket2 counterpart that can be decoded as
+//! a short sequence of native operations.
+//!
+//! Decoding these operations natively, instead of as opaque pytket
+//! operations, lets the optimisation passes reason about them. The
+//! decompositions are exact, including the global phase.
+//!
+//! Some operations are deliberately kept as opaque pytket operations:
+//! - `SWAP` gates, which the passes already recognise. Commutation moves
+//!   commands through them, and [`cx_cancellation`] produces them.
+//! - `Barrier`s, which must not be reordered with the surrounding operations.
+//! - `CnRy` gates with more than one control.
+//!
+//! [`cx_cancellation`]: crate::passes::cx_cancellation()
+
+use tket_json_rs::circuit_json::{Command, Operation, Register};
+use tket_json_rs::optype::OpType as Tk1OpType;
+
+/// Returns a sequence of commands with native tket2 counterparts implementing
+/// a pytket command, if the command is one of the supported non-native
+/// operations.
+///
+/// Only unconditional operations acting solely on qubits are decomposed.
+pub(crate) fn decompose_command(command: &Command) -> Option<Vec<Command>> {
+    let Command { op, args, opgroup } = command;
+    if op.conditional.is_some() || op.classical.is_some() {
+        return None;
+    }
+    let params = op.params.as_deref().unwrap_or_default();
+    let gate = |op_type: Tk1OpType, qubits: &[&Register], params: &[String]| {
+        let mut op = Operation::default();
+        op.op_type = op_type;
+        op.n_qb = Some(qubits.len() as u32);
+        op.params = (!params.is_empty()).then(|| params.to_vec());
+        Command {
+            op,
+            args: qubits.iter().map(|&q| q.clone()).collect(),
+            opgroup: opgroup.clone(),
+        }
+    };
+
+    let commands = match (&op.op_type, &args[..], params) {
+        (Tk1OpType::V, [q], []) => vec![gate(Tk1OpType::Rx, &[q], &["0.5".into()])],
+        (Tk1OpType::Vdg, [q], []) => vec![gate(Tk1OpType::Rx, &[q], &["-0.5".into()])],
+        // Ry(θ) = S Rx(θ) S†
+        (Tk1OpType::Ry, [q], [angle]) => vec![
+            gate(Tk1OpType::Sdg, &[q], &[]),
+            gate(Tk1OpType::Rx, &[q], std::slice::from_ref(angle)),
+            gate(Tk1OpType::S, &[q], &[]),
+        ],
+        // CY = (I ⊗ S) CX (I ⊗ S†)
+        (Tk1OpType::CY, [c, t], []) => vec![
+            gate(Tk1OpType::Sdg, &[t], &[]),
+            gate(Tk1OpType::CX, &[c, t], &[]),
+            gate(Tk1OpType::S, &[t], &[]),
+        ],
+        // Conjugating Ry(-θ/2) by X flips its angle, so the rotations cancel
+        // out unless the control is set.
+        (Tk1OpType::CRy | Tk1OpType::CnRy, [c, t], [angle]) => [
+            vec![gate(Tk1OpType::Ry, &[t], &[half_angle(angle, false)])],
+            vec![gate(Tk1OpType::CX, &[c, t], &[])],
+            vec![gate(Tk1OpType::Ry, &[t], &[half_angle(angle, true)])],
+            vec![gate(Tk1OpType::CX, &[c, t], &[])],
+        ]
+        .concat(),
+        _ => return None,
+    };
+
+    // Decompose the intermediate `Ry` gates.
+    let commands = commands
+        .into_iter()
+        .flat_map(|cmd| decompose_command(&cmd).unwrap_or_else(|| vec![cmd]))
+        .collect();
+    Some(commands)
+}
+
+/// Halve a pytket angle expression, optionally negating it.
+fn half_angle(angle: &str, negate: bool) -> String {
+    let sign = if negate { -1. } else { 1. };
+    match angle.parse::<f64>() {
+        Ok(value) => (sign * value / 2.).to_string(),
+        Err(_) if negate => format!("-({angle})/2"),
+        Err(_) => format!("({angle})/2"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use ndarray::{arr2, Array2};
+    use num_complex::Complex64;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::serialize::pytket::{load_tk1_json_str, DecodeOptions};
+
+    /// A single-qubit rotation by `angle` radians around the X or Y axis.
+    fn rotation(angle: f64, y_axis: bool) -> Array2<Complex64> {
+        let c = Complex64::new((angle / 2.).cos(), 0.);
+        let s = Complex64::new((angle / 2.).sin(), 0.);
+        let i = Complex64::i();
+        match y_axis {
+            false => arr2(&[[c, -i * s], [-i * s, c]]),
+            true => arr2(&[[c, -s], [s, c]]),
+        }
+    }
+
+    /// A two-qubit gate applying `u` to qubit 1, controlled by qubit 0.
+    fn controlled(u: Array2<Complex64>) -> Array2<Complex64> {
+        let mut matrix = Array2::eye(4);
+        for (t_out, t_in) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            matrix[(1 + 2 * t_out, 1 + 2 * t_in)] = u[(t_out, t_in)];
+        }
+        matrix
+    }
+
+    #[rstest]
+    #[case::v(r#""op": {"type": "V"}, "args": [["q", [0]]]"#, rotation(PI / 2., false))]
+    #[case::vdg(r#""op": {"type": "Vdg"}, "args": [["q", [0]]]"#, rotation(-PI / 2., false))]
+    #[case::ry(
+        r#""op": {"type": "Ry", "params": ["0.3"]}, "args": [["q", [0]]]"#,
+        rotation(0.3 * PI, true)
+    )]
+    #[case::cy(
+        r#""op": {"type": "CY"}, "args": [["q", [0]], ["q", [1]]]"#,
+        // Y = i Ry(π)
+        controlled(rotation(PI, true).mapv(|x| x * Complex64::i()))
+    )]
+    #[case::cry(
+        r#""op": {"type": "CRy", "params": ["0.7"]}, "args": [["q", [0]], ["q", [1]]]"#,
+        controlled(rotation(0.7 * PI, true))
+    )]
+    #[case::cnry(
+        r#""op": {"type": "CnRy", "params": ["-1.2"]}, "args": [["q", [0]], ["q", [1]]]"#,
+        controlled(rotation(-1.2 * PI, true))
+    )]
+    fn decomposed_unitary(#[case] command: &str, #[case] expected: Array2<Complex64>) {
+        let num_qubits = expected.nrows().ilog2();
+        let qubits = (0..num_qubits).map(|i| format!(r#"["q", [{i}]]"#));
+        let json = format!(
+            r#"{{"phase": "0", "bits": [], "qubits": [{}], "commands": [{{{command}}}], "implicit_permutation": []}}"#,
+            qubits.collect::<Vec<_>>().join(", ")
+        );
+        // Strict decoding fails on opaque operations.
+        let circ = load_tk1_json_str(&json, DecodeOptions::strict()).unwrap();
+        let unitary = circ.unitary().unwrap();
+        assert!(
+            (&unitary - &expected).iter().all(|x| x.norm() < 1e-10),
+            "{unitary} != {expected}"
+        );
+    }
+
+    #[test]
+    fn symbolic_parameters() {
+        let json = r#"{"phase": "0", "bits": [], "qubits": [["q", [0]], ["q", [1]]],
+            "commands": [{"op": {"type": "CRy", "params": ["a"]}, "args": [["q", [0]], ["q", [1]]]}],
+            "implicit_permutation": []}"#;
+        let circ = load_tk1_json_str(json, DecodeOptions::strict()).unwrap();
+        assert_eq!(circ.commands().count(), 10);
+    }
+
+    #[rstest]
+    #[case("1", false, "0.5")]
+    #[case("0.5", true, "-0.25")]
+    #[case("a", false, "(a)/2")]
+    #[case("a + b", true, "-(a + b)/2")]
+    fn half_angles(#[case] angle: &str, #[case] negate: bool, #[case] expected: &str) {
+        assert_eq!(half_angle(angle, negate), expected);
+    }
+}
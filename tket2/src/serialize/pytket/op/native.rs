@@ -10,6 +10,7 @@ use hugr::IncomingPort;
 use tket_json_rs::circuit_json;
 use tket_json_rs::optype::OpType as Tk1OpType;
 
+use crate::serialize::pytket::op_table::{op_mapping, op_mapping_from_tk1};
 use crate::Tk2Op;
 
 /// An operation with a native TKET2 counterpart.
@@ -49,68 +50,27 @@ impl NativeOp {
     }
 
     /// Create a new `NativeOp` from a `circuit_json::Operation`.
+    ///
+    /// The correspondence between operations is given by [`OP_TABLE`].
+    ///
+    /// [`OP_TABLE`]: crate::serialize::pytket::op_table::OP_TABLE
     pub fn try_from_tk2op(tk2op: Tk2Op) -> Option<Self> {
-        let serial_op = match tk2op {
-            Tk2Op::H => Tk1OpType::H,
-            Tk2Op::CX => Tk1OpType::CX,
-            Tk2Op::T => Tk1OpType::T,
-            Tk2Op::S => Tk1OpType::S,
-            Tk2Op::X => Tk1OpType::X,
-            Tk2Op::Y => Tk1OpType::Y,
-            Tk2Op::Z => Tk1OpType::Z,
-            Tk2Op::Tdg => Tk1OpType::Tdg,
-            Tk2Op::Sdg => Tk1OpType::Sdg,
-            Tk2Op::ZZMax => Tk1OpType::ZZMax,
-            Tk2Op::RzF64 => Tk1OpType::Rz,
-            Tk2Op::RxF64 => Tk1OpType::Rx,
-            Tk2Op::TK1 => Tk1OpType::TK1,
-            Tk2Op::PhasedX => Tk1OpType::PhasedX,
-            Tk2Op::ZZPhase => Tk1OpType::ZZPhase,
-            Tk2Op::CZ => Tk1OpType::CZ,
-            Tk2Op::Reset => Tk1OpType::Reset,
-            Tk2Op::Measure => Tk1OpType::Measure,
-            Tk2Op::AngleAdd => {
-                // These operations should be folded into constant before serialisation,
-                // or replaced by pytket logic expressions.
-                return Some(Self::new(tk2op.into(), None));
-            }
-            // These operations do not have a direct pytket counterpart.
-            Tk2Op::QAlloc | Tk2Op::QFree => {
-                // These operations are implicitly supported by the encoding,
-                // they do not create an explicit pytket operation but instead
-                // add new qubits to the circuit input/output.
-                return Some(Self::new(tk2op.into(), None));
-            }
-        };
-
-        Some(Self::new(tk2op.into(), Some(serial_op)))
+        // Operations without a pytket counterpart are handled separately.
+        //
+        // `AngleAdd` should be folded into constants before serialisation, or
+        // replaced by pytket logic expressions. `QAlloc` and `QFree` are
+        // implicitly supported by the encoding; they do not create an explicit
+        // pytket operation but instead add new qubits to the circuit
+        // input/output.
+        let serial_op = op_mapping(tk2op).map(|m| m.tk1op.clone());
+        Some(Self::new(tk2op.into(), serial_op))
     }
 
     /// Returns the translated tket2 optype for this operation, if it exists.
     pub fn try_from_serial_optype(serial_op: Tk1OpType) -> Option<Self> {
         let op = match serial_op {
-            Tk1OpType::H => Tk2Op::H.into(),
-            Tk1OpType::CX => Tk2Op::CX.into(),
-            Tk1OpType::T => Tk2Op::T.into(),
-            Tk1OpType::S => Tk2Op::S.into(),
-            Tk1OpType::X => Tk2Op::X.into(),
-            Tk1OpType::Y => Tk2Op::Y.into(),
-            Tk1OpType::Z => Tk2Op::Z.into(),
-            Tk1OpType::Tdg => Tk2Op::Tdg.into(),
-            Tk1OpType::Sdg => Tk2Op::Sdg.into(),
-            Tk1OpType::Rz => Tk2Op::RzF64.into(),
-            Tk1OpType::Rx => Tk2Op::RxF64.into(),
-            Tk1OpType::TK1 => Tk2Op::TK1.into(),
-            Tk1OpType::PhasedX => Tk2Op::PhasedX.into(),
-            Tk1OpType::ZZMax => Tk2Op::ZZMax.into(),
-            Tk1OpType::ZZPhase => Tk2Op::ZZPhase.into(),
-            Tk1OpType::CZ => Tk2Op::CZ.into(),
-            Tk1OpType::Reset => Tk2Op::Reset.into(),
-            Tk1OpType::Measure => Tk2Op::Measure.into(),
             Tk1OpType::noop => Noop::new(QB_T).into(),
-            _ => {
-                return None;
-            }
+            _ => op_mapping_from_tk1(&serial_op)?.tk2op.into(),
         };
         Some(Self::new(op, Some(serial_op)))
     }
@@ -0,0 +1,194 @@
+//! The correspondence between [`Tk2Op`]s and pytket operations.
+//!
+//! [`OP_TABLE`] lists every [`Tk2Op`] with a native pytket counterpart, and is
+//! the single source of truth used by the pytket encoder and decoder. External
+//! serialisers can use it to translate operations without duplicating the
+//! conversion logic.
+//!
+//! Parameters are given in the same order for both representations, but use
+//! different angle units: pytket angles are a number of half-turns, while
+//! tket2 angles are in radians. See [`OpMapping::params_to_tk1`] and
+//! [`OpMapping::params_from_tk1`].
+
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use tket_json_rs::optype::OpType as Tk1OpType;
+
+use crate::Tk2Op;
+
+/// The unit of an angle parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AngleUnit {
+    /// Radians, used by tket2 operations.
+    Radians,
+    /// Half-turns (multiples of π radians), used by pytket operations.
+    HalfTurns,
+}
+
+impl AngleUnit {
+    /// The angle unit of tket2 operation parameters.
+    pub const TK2: Self = AngleUnit::Radians;
+    /// The angle unit of pytket operation parameters.
+    pub const TK1: Self = AngleUnit::HalfTurns;
+
+    /// Convert an angle in this unit to another unit.
+    pub fn convert(self, angle: f64, to: AngleUnit) -> f64 {
+        match (self, to) {
+            (AngleUnit::Radians, AngleUnit::HalfTurns) => angle / PI,
+            (AngleUnit::HalfTurns, AngleUnit::Radians) => angle * PI,
+            _ => angle,
+        }
+    }
+}
+
+/// The correspondence between a [`Tk2Op`] and a pytket operation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpMapping {
+    /// The tket2 operation.
+    pub tk2op: Tk2Op,
+    /// The pytket operation type.
+    pub tk1op: Tk1OpType,
+    /// The names of the angle parameters, in the order they are given to both
+    /// operations.
+    pub params: &'static [&'static str],
+}
+
+impl OpMapping {
+    /// Convert the parameters of the tket2 operation, in radians, into the
+    /// parameters of the pytket operation, in half-turns.
+    pub fn params_to_tk1(&self, params: &[f64]) -> Vec<f64> {
+        debug_assert_eq!(params.len(), self.params.len());
+        params
+            .iter()
+            .map(|&p| AngleUnit::TK2.convert(p, AngleUnit::TK1))
+            .collect()
+    }
+
+    /// Convert the parameters of the pytket operation, in half-turns, into the
+    /// parameters of the tket2 operation, in radians.
+    pub fn params_from_tk1(&self, params: &[f64]) -> Vec<f64> {
+        debug_assert_eq!(params.len(), self.params.len());
+        params
+            .iter()
+            .map(|&p| AngleUnit::TK1.convert(p, AngleUnit::TK2))
+            .collect()
+    }
+}
+
+/// The [`Tk2Op`]s with a native pytket counterpart.
+///
+/// The remaining operations are handled specially by the encoder:
+/// [`Tk2Op::QAlloc`] and [`Tk2Op::QFree`] add qubits to the circuit's inputs
+/// and outputs, and [`Tk2Op::AngleAdd`] must be folded into the parameter
+/// expressions. pytket has no fixed-arity counterpart of [`Tk2Op::CCZ`], so it
+/// must be decomposed before encoding.
+pub static OP_TABLE: &[OpMapping] = &[
+    op(Tk2Op::H, Tk1OpType::H, &[]),
+    op(Tk2Op::CX, Tk1OpType::CX, &[]),
+    op(Tk2Op::T, Tk1OpType::T, &[]),
+    op(Tk2Op::S, Tk1OpType::S, &[]),
+    op(Tk2Op::X, Tk1OpType::X, &[]),
+    op(Tk2Op::Y, Tk1OpType::Y, &[]),
+    op(Tk2Op::Z, Tk1OpType::Z, &[]),
+    op(Tk2Op::Tdg, Tk1OpType::Tdg, &[]),
+    op(Tk2Op::Sdg, Tk1OpType::Sdg, &[]),
+    op(Tk2Op::ZZMax, Tk1OpType::ZZMax, &[]),
+    op(Tk2Op::RzF64, Tk1OpType::Rz, &["angle"]),
+    op(Tk2Op::RxF64, Tk1OpType::Rx, &["angle"]),
+    // Rz(β) Rx(α) Rz(-β)
+    op(Tk2Op::PhasedX, Tk1OpType::PhasedX, &["angle", "phase"]),
+    // Rz(α) Rx(β) Rz(γ)
+    op(Tk2Op::TK1, Tk1OpType::TK1, &["alpha", "beta", "gamma"]),
+    op(Tk2Op::ZZPhase, Tk1OpType::ZZPhase, &["angle"]),
+    op(Tk2Op::XXPhase, Tk1OpType::XXPhase, &["angle"]),
+    op(Tk2Op::YYPhase, Tk1OpType::YYPhase, &["angle"]),
+    op(Tk2Op::CZ, Tk1OpType::CZ, &[]),
+    op(Tk2Op::CCX, Tk1OpType::CCX, &[]),
+    op(Tk2Op::Reset, Tk1OpType::Reset, &[]),
+    op(Tk2Op::Measure, Tk1OpType::Measure, &[]),
+];
+
+/// Helper to define the entries of [`OP_TABLE`].
+const fn op(tk2op: Tk2Op, tk1op: Tk1OpType, params: &'static [&'static str]) -> OpMapping {
+    OpMapping {
+        tk2op,
+        tk1op,
+        params,
+    }
+}
+
+/// Returns the pytket counterpart of a [`Tk2Op`], if it has one.
+pub fn op_mapping(tk2op: Tk2Op) -> Option<&'static OpMapping> {
+    OP_TABLE.iter().find(|m| m.tk2op == tk2op)
+}
+
+/// Returns the [`Tk2Op`] counterpart of a pytket operation type, if it has
+/// one.
+pub fn op_mapping_from_tk1(tk1op: &Tk1OpType) -> Option<&'static OpMapping> {
+    OP_TABLE.iter().find(|m| &m.tk1op == tk1op)
+}
+
+/// Returns the [`Tk2Op`] counterpart of a pytket operation type given by name,
+/// such as `"Rz"`, if it has one.
+pub fn op_mapping_from_tk1_name(name: &str) -> Option<&'static OpMapping> {
+    op_mapping_from_tk1(&Tk1OpType::from_str(name).ok()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hugr::ops::{OpTrait, OpType};
+    use hugr::std_extensions::arithmetic::float_types::FLOAT64_TYPE;
+    use itertools::Itertools;
+    use rstest::rstest;
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn table_is_bijective() {
+        assert!(OP_TABLE.iter().map(|m| m.tk2op).all_unique());
+        for m in OP_TABLE {
+            assert_eq!(op_mapping(m.tk2op), Some(m));
+            assert_eq!(op_mapping_from_tk1(&m.tk1op), Some(m));
+        }
+        let unmapped = Tk2Op::iter()
+            .filter(|&op| op_mapping(op).is_none())
+            .collect_vec();
+        assert_eq!(
+            unmapped,
+            [Tk2Op::AngleAdd, Tk2Op::QAlloc, Tk2Op::QFree, Tk2Op::CCZ]
+        );
+    }
+
+    #[test]
+    fn param_counts() {
+        for m in OP_TABLE {
+            let op: OpType = m.tk2op.into();
+            let sig = op.dataflow_signature().unwrap();
+            let num_params = sig
+                .input_types()
+                .iter()
+                .filter(|&ty| ty == &FLOAT64_TYPE)
+                .count();
+            assert_eq!(num_params, m.params.len(), "{:?}", m.tk2op);
+        }
+    }
+
+    #[rstest]
+    #[case("Rz", Some(Tk2Op::RzF64))]
+    #[case("PhasedX", Some(Tk2Op::PhasedX))]
+    #[case("CCX", Some(Tk2Op::CCX))]
+    #[case("CnX", None)]
+    #[case("NotAnOp", None)]
+    fn lookup_by_name(#[case] name: &str, #[case] tk2op: Option<Tk2Op>) {
+        assert_eq!(op_mapping_from_tk1_name(name).map(|m| m.tk2op), tk2op);
+    }
+
+    #[test]
+    fn angle_conversion() {
+        let m = op_mapping(Tk2Op::PhasedX).unwrap();
+        let tk1_params = m.params_to_tk1(&[PI, -PI / 2.]);
+        assert_eq!(tk1_params, [1., -0.5]);
+        assert_eq!(m.params_from_tk1(&tk1_params), [PI, -PI / 2.]);
+    }
+}
@@ -3,20 +3,29 @@
 use std::collections::{HashMap, HashSet};
 use std::io::BufReader;
 
-use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr};
+use hugr::builder::{DFGBuilder, Dataflow, DataflowHugr, DataflowSubContainer, SubContainer};
 use hugr::extension::prelude::{BOOL_T, QB_T};
 
 use hugr::hugr::hugrmut::HugrMut;
+use hugr::ops::{Noop, OpType};
 use hugr::std_extensions::arithmetic::float_types::{ConstF64, FLOAT64_TYPE};
 use hugr::types::Signature;
-use hugr::HugrView;
+use hugr::{type_row, HugrView};
+use itertools::Itertools;
 use rstest::{fixture, rstest};
 use tket_json_rs::circuit_json::{self, SerialCircuit};
 use tket_json_rs::optype;
 
-use super::{TKETDecode, METADATA_Q_OUTPUT_REGISTERS};
+use super::fallback::FALLBACK_DATA_PREFIX;
+use super::{
+    elaborate_implicit_permutation, encode_with_fallback, implicit_qubit_permutation,
+    load_tk1_json_str, ConversionReport, DecodeOptions, OpConvertError, TK1ConvertError,
+    TKETDecode, METADATA_Q_OUTPUT_REGISTERS,
+};
+use crate::circuit::units::Qubit;
 use crate::circuit::Circuit;
 use crate::extension::REGISTRY;
+use crate::passes::{cx_cancellation, rebase, CxCancellationConfig, GateSet};
 use crate::Tk2Op;
 
 const SIMPLE_JSON: &str = r#"{
@@ -41,6 +50,43 @@ const MULTI_REGISTER: &str = r#"{
         "implicit_permutation": []
     }"#;
 
+const MULTI_INDEXED: &str = r#"{
+        "phase": "0",
+        "bits": [["syndrome", [0]], ["syndrome", [1]]],
+        "qubits": [["grid", [0, 0]], ["grid", [0, 1]], ["grid", [1, 0]], ["anc", [0]]],
+        "commands": [
+            {"args": [["grid", [0, 0]], ["anc", [0]]], "op": {"type": "CX"}},
+            {"args": [["grid", [1, 0]], ["anc", [0]]], "op": {"type": "CX"}},
+            {"args": [["anc", [0]], ["syndrome", [1]]], "op": {"type": "Measure"}},
+            {"args": [["grid", [0, 1]], ["syndrome", [0]]], "op": {"type": "Measure"}}
+        ],
+        "implicit_permutation": [
+            [["grid", [0, 0]], ["grid", [1, 0]]],
+            [["grid", [0, 1]], ["grid", [0, 1]]],
+            [["grid", [1, 0]], ["grid", [0, 0]]],
+            [["anc", [0]], ["anc", [0]]]
+        ]
+    }"#;
+
+const PERMUTED: &str = r#"{
+        "phase": "0",
+        "bits": [["c", [0]], ["c", [1]], ["c", [2]]],
+        "qubits": [["q", [0]], ["q", [1]], ["q", [2]]],
+        "commands": [
+            {"args": [["q", [0]]], "op": {"type": "H"}},
+            {"args": [["q", [0]], ["q", [1]]], "op": {"type": "CX"}},
+            {"args": [["q", [2]], ["c", [0]]], "op": {"type": "Measure"}}
+        ],
+        "implicit_permutation": [
+            [["q", [0]], ["q", [1]]],
+            [["q", [1]], ["q", [2]]],
+            [["q", [2]], ["q", [0]]],
+            [["c", [0]], ["c", [2]]],
+            [["c", [1]], ["c", [0]]],
+            [["c", [2]], ["c", [1]]]
+        ]
+    }"#;
+
 const UNKNOWN_OP: &str = r#"{
         "phase": "1/2",
         "bits": [["c", [0]], ["c", [1]]],
@@ -229,6 +275,7 @@ fn circ_add_angles_constants() -> Circuit {
 #[rstest]
 #[case::simple(SIMPLE_JSON, 2, 2)]
 #[case::simple(MULTI_REGISTER, 2, 3)]
+#[case::multi_indexed(MULTI_INDEXED, 4, 4)]
 #[case::unknown_op(UNKNOWN_OP, 2, 3)]
 #[case::parametrized(PARAMETERIZED, 4, 2)]
 fn json_roundtrip(#[case] circ_s: &str, #[case] num_commands: usize, #[case] num_qubits: usize) {
@@ -244,6 +291,199 @@ fn json_roundtrip(#[case] circ_s: &str, #[case] num_commands: usize, #[case] num
     compare_serial_circs(&ser, &reser);
 }
 
+/// The register names, indices and permutation of a decoded circuit are
+/// restored exactly when encoding it back.
+#[rstest]
+#[case::multi_register(MULTI_REGISTER)]
+#[case::multi_indexed(MULTI_INDEXED)]
+#[case::permuted(PERMUTED)]
+#[case::unknown_op(UNKNOWN_OP)]
+fn json_register_roundtrip(#[case] circ_s: &str) {
+    let ser: SerialCircuit = serde_json::from_str(circ_s).unwrap();
+    let circ: Circuit = ser.clone().decode().unwrap();
+    let reser: SerialCircuit = SerialCircuit::encode(&circ).unwrap();
+
+    assert_eq!(reser.qubits, ser.qubits);
+    assert_eq!(reser.bits, ser.bits);
+    let args = |c: &SerialCircuit| {
+        c.commands
+            .iter()
+            .map(|cmd| cmd.args.clone())
+            .collect::<HashSet<_>>()
+    };
+    assert_eq!(args(&reser), args(&ser));
+
+    // Identity permutations may be omitted.
+    let permutation = |c: &SerialCircuit| {
+        c.implicit_permutation
+            .iter()
+            .filter(|p| p.0 != p.1)
+            .map(|p| (p.0.clone(), p.1.clone()))
+            .collect::<HashMap<_, _>>()
+    };
+    assert_eq!(permutation(&reser), permutation(&ser));
+}
+
+#[test]
+fn implicit_permutation_elaboration() {
+    let ser: SerialCircuit = serde_json::from_str(PERMUTED).unwrap();
+    let mut circ: Circuit = ser.decode().unwrap();
+    assert_eq!(implicit_qubit_permutation(&circ), [1, 2, 0].map(Qubit::new));
+
+    assert_eq!(elaborate_implicit_permutation(&mut circ), 2);
+    assert_eq!(implicit_qubit_permutation(&circ), [0, 1, 2].map(Qubit::new));
+    assert_eq!(elaborate_implicit_permutation(&mut circ), 0);
+
+    let reser = SerialCircuit::encode(&circ).unwrap();
+    let swaps = reser
+        .commands
+        .iter()
+        .filter(|cmd| cmd.op.op_type == optype::OpType::SWAP)
+        .count();
+    assert_eq!(swaps, 2);
+    assert!(reser
+        .implicit_permutation
+        .iter()
+        .filter(|p| p.0 .0 == "q")
+        .all(|p| p.0 == p.1));
+}
+
+/// The elaborated `SWAP`s move the state of each qubit to its permuted position.
+#[test]
+fn implicit_permutation_unitary() {
+    let ser: SerialCircuit = serde_json::from_str(
+        r#"{
+        "phase": "0",
+        "bits": [],
+        "qubits": [["q", [0]], ["q", [1]], ["q", [2]]],
+        "commands": [
+            {"args": [["q", [0]]], "op": {"type": "H"}},
+            {"args": [["q", [0]], ["q", [1]]], "op": {"type": "CX"}},
+            {"args": [["q", [2]]], "op": {"type": "T"}}
+        ],
+        "implicit_permutation": [
+            [["q", [0]], ["q", [2]]],
+            [["q", [1]], ["q", [0]]],
+            [["q", [2]], ["q", [1]]]
+        ]
+    }"#,
+    )
+    .unwrap();
+    let mut circ: Circuit = ser.decode().unwrap();
+    let permutation = implicit_qubit_permutation(&circ);
+    assert_eq!(permutation, [2, 0, 1].map(Qubit::new));
+    let before = circ.unitary().unwrap();
+
+    elaborate_implicit_permutation(&mut circ);
+    rebase(&mut circ, &GateSet::clifford_t()).unwrap();
+    let after = circ.unitary().unwrap();
+
+    // Move bit `i` of a basis state index to bit `permutation[i]`.
+    let permute = |x: usize| {
+        (0..permutation.len())
+            .filter(|&i| x >> i & 1 == 1)
+            .map(|i| 1 << permutation[i].index())
+            .sum::<usize>()
+    };
+    for ((x, y), amplitude) in before.indexed_iter() {
+        assert!((after[(permute(x), y)] - amplitude).norm() < 1e-8);
+    }
+}
+
+#[test]
+fn implicit_permutation_relabelling() {
+    let ser: SerialCircuit = serde_json::from_str(
+        r#"{
+        "phase": "0",
+        "bits": [],
+        "qubits": [["q", [0]], ["q", [1]], ["q", [2]]],
+        "commands": [],
+        "implicit_permutation": [
+            [["q", [0]], ["q", [1]]],
+            [["q", [1]], ["q", [0]]],
+            [["q", [2]], ["q", [2]]]
+        ]
+    }"#,
+    )
+    .unwrap();
+    let mut circ: Circuit = ser.decode().unwrap();
+    assert_eq!(implicit_qubit_permutation(&circ), [1, 0, 2].map(Qubit::new));
+
+    // After relabelling qubits 0 and 1 as 2 and 0, the permutation swaps
+    // qubits 2 and 0 instead.
+    circ.permute_qubits(&[2, 0, 1].map(Qubit::new)).unwrap();
+    assert_eq!(implicit_qubit_permutation(&circ), [2, 1, 0].map(Qubit::new));
+}
+
+#[rstest]
+#[case::simple(SIMPLE_JSON, vec![])]
+#[case::unknown_op(UNKNOWN_OP, vec![optype::OpType::CSWAP])]
+fn decode_options(#[case] circ_s: &str, #[case] opaque: Vec<optype::OpType>) {
+    let lossy = load_tk1_json_str(circ_s, DecodeOptions::lossy()).unwrap();
+    let report = ConversionReport::new(&lossy);
+    assert_eq!(report.is_lossless(), opaque.is_empty());
+    let op_types = report
+        .opaque_ops
+        .iter()
+        .map(|op| op.op_type.clone())
+        .collect_vec();
+    assert_eq!(op_types, opaque);
+
+    let strict = load_tk1_json_str(circ_s, DecodeOptions::strict());
+    match opaque.first() {
+        None => assert_eq!(strict.unwrap(), lossy),
+        Some(op_type) => assert!(matches!(
+            strict,
+            Err(TK1ConvertError::OpConversionError(OpConvertError::UnsupportedSerializedOp(ref op)))
+                if op == op_type
+        )),
+    }
+}
+
+/// Operation groups are preserved by optimisation passes, for the operations
+/// that are not removed.
+#[test]
+fn opgroup_preservation() {
+    let ser: SerialCircuit = serde_json::from_str(
+        r#"{
+        "phase": "0",
+        "bits": [],
+        "qubits": [["q", [0]], ["q", [1]]],
+        "commands": [
+            {"args": [["q", [0]]], "op": {"type": "H"}, "opgroup": "prep"},
+            {"args": [["q", [0]], ["q", [1]]], "op": {"type": "CX"}, "opgroup": "cancelled"},
+            {"args": [["q", [0]], ["q", [1]]], "op": {"type": "CX"}, "opgroup": "cancelled"},
+            {"args": [["q", [1]]], "op": {"params": ["0.25"], "type": "Rz"}, "opgroup": "angle"}
+        ],
+        "implicit_permutation": []
+    }"#,
+    )
+    .unwrap();
+    let mut circ: Circuit = ser.decode().unwrap();
+    assert_eq!(circ.ops_in_group("cancelled").count(), 2);
+
+    cx_cancellation(&mut circ, CxCancellationConfig::default());
+    assert_eq!(circ.ops_in_group("cancelled").count(), 0);
+    let [rz] = circ.ops_in_group("angle").collect_vec()[..] else {
+        panic!("Expected a single operation in the group");
+    };
+    assert_eq!(circ.hugr().get_optype(rz), &Tk2Op::RzF64.into());
+
+    let reser = SerialCircuit::encode(&circ).unwrap();
+    let groups = reser
+        .commands
+        .iter()
+        .map(|cmd| (cmd.op.op_type.clone(), cmd.opgroup.clone()))
+        .collect::<HashSet<_>>();
+    assert_eq!(
+        groups,
+        HashSet::from([
+            (optype::OpType::H, Some("prep".to_string())),
+            (optype::OpType::Rz, Some("angle".to_string())),
+        ])
+    );
+}
+
 #[rstest]
 #[cfg_attr(miri, ignore)] // Opening files is not supported in (isolated) miri
 #[case::barenco_tof_10("../test_files/barenco_tof_10.json")]
@@ -259,6 +499,120 @@ fn json_file_roundtrip(#[case] circ: impl AsRef<std::path::Path>) {
 /// Test the serialisation roundtrip from a tket2 circuit.
 ///
 /// Note: this is not a pure roundtrip as the encoder may add internal qubits/bits to the circuit.
+/// A measurement of the first qubit controlling an X gate on the second one.
+///
+/// The conditional has no pytket counterpart. If `noop_angle`, the angle of a
+/// final rotation goes through a no-op, which has no pytket counterpart
+/// either.
+fn circ_conditional_correction(noop_angle: bool) -> Circuit {
+    let mut h =
+        DFGBuilder::new(Signature::new(vec![QB_T, QB_T], vec![QB_T, QB_T, BOOL_T])).unwrap();
+    let [q0, q1] = h.input_wires_arr();
+    let [q0] = h.add_dataflow_op(Tk2Op::H, [q0]).unwrap().outputs_arr();
+    let [q0, bit] = h
+        .add_dataflow_op(Tk2Op::Measure, [q0])
+        .unwrap()
+        .outputs_arr();
+
+    let mut cond = h
+        .conditional_builder(
+            ([type_row![], type_row![]], bit),
+            [(QB_T, q1)],
+            type_row![QB_T],
+        )
+        .unwrap();
+    let case0 = cond.case_builder(0).unwrap();
+    let [q] = case0.input_wires_arr();
+    case0.finish_with_outputs([q]).unwrap();
+    let mut case1 = cond.case_builder(1).unwrap();
+    let [q] = case1.input_wires_arr();
+    let [q] = case1.add_dataflow_op(Tk2Op::X, [q]).unwrap().outputs_arr();
+    case1.finish_with_outputs([q]).unwrap();
+    let [q1] = cond.finish_sub_container().unwrap().outputs_arr();
+
+    let mut angle = h.add_load_value(ConstF64::new(0.5));
+    if noop_angle {
+        [angle] = h
+            .add_dataflow_op(Noop::new(FLOAT64_TYPE), [angle])
+            .unwrap()
+            .outputs_arr();
+    }
+    let [q1] = h
+        .add_dataflow_op(Tk2Op::RzF64, [q1, angle])
+        .unwrap()
+        .outputs_arr();
+    h.finish_hugr_with_outputs([q0, q1, bit], &REGISTRY)
+        .unwrap()
+        .into()
+}
+
+/// The number of pytket barriers storing a HUGR region.
+fn count_fallback_regions(ser: &SerialCircuit) -> usize {
+    ser.commands
+        .iter()
+        .filter(|cmd| {
+            cmd.op.op_type == optype::OpType::Barrier
+                && cmd
+                    .op
+                    .data
+                    .as_deref()
+                    .is_some_and(|data| data.starts_with(FALLBACK_DATA_PREFIX))
+        })
+        .count()
+}
+
+/// Test the fallback encoding of operations without a pytket counterpart.
+#[rstest]
+#[case::conditional(false, 4, [Tk2Op::H, Tk2Op::Measure, Tk2Op::RzF64])]
+// The rotation is stored along with the no-op computing its angle, in the
+// same region as the adjacent conditional.
+#[case::noop_angle(true, 3, [Tk2Op::H, Tk2Op::Measure])]
+fn fallback_roundtrip<const N: usize>(
+    #[case] noop_angle: bool,
+    #[case] num_commands: usize,
+    #[case] native_ops: [Tk2Op; N],
+) {
+    let circ = circ_conditional_correction(noop_angle);
+    let ser = encode_with_fallback(&circ).unwrap();
+    validate_serial_circ(&ser);
+    assert_eq!(ser.commands.len(), num_commands);
+    assert_eq!(count_fallback_regions(&ser), 1);
+
+    let deser: Circuit = ser.clone().decode().unwrap();
+    deser.hugr().validate(&REGISTRY).unwrap();
+    let ops = deser
+        .commands()
+        .filter_map(|cmd| Tk2Op::try_from(cmd.optype()).ok())
+        .collect_vec();
+    assert_eq!(ops, native_ops);
+    let regions = deser
+        .commands()
+        .filter(|cmd| matches!(cmd.optype(), OpType::DFG(_)))
+        .count();
+    assert_eq!(regions, 1);
+
+    let reser = encode_with_fallback(&deser).unwrap();
+    validate_serial_circ(&reser);
+    compare_serial_circs(&ser, &reser);
+}
+
+#[test]
+fn fallback_invalid_region() {
+    let mut ser = encode_with_fallback(&circ_conditional_correction(false)).unwrap();
+    let barrier = ser
+        .commands
+        .iter_mut()
+        .find(|cmd| cmd.op.op_type == optype::OpType::Barrier)
+        .unwrap();
+    barrier.op.data = Some(format!("{FALLBACK_DATA_PREFIX}not a hugr"));
+    assert!(matches!(
+        ser.decode(),
+        Err(TK1ConvertError::OpConversionError(
+            OpConvertError::InvalidFallbackRegion { .. }
+        ))
+    ));
+}
+
 #[rstest]
 #[case::meas_ancilla(circ_measure_ancilla(), Signature::new_endo(vec![QB_T, QB_T, BOOL_T, BOOL_T]))]
 #[case::preset_qubits(circ_preset_qubits(), Signature::new_endo(vec![QB_T, QB_T, QB_T]))]
@@ -0,0 +1,385 @@
+//! Load circuits exported from Qiskit.
+//!
+//! Qiskit's binary QPY format is not supported. Instead, circuits are read
+//! from a JSON description of a `QuantumCircuit`, listing its instructions
+//! with the indices of the qubits and classical bits they act on. It can be
+//! produced from Python with:
+//!
+//! ```python
+//! import json
+//!
+//! def qiskit_to_json(qc):
+//!     return json.dumps({
+//!         "name": qc.name,
+//!         "num_qubits": qc.num_qubits,
+//!         "num_clbits": qc.num_clbits,
+//!         "global_phase": float(qc.global_phase),
+//!         "instructions": [
+//!             {
+//!                 "name": inst.operation.name,
+//!                 "qubits": [qc.find_bit(q).index for q in inst.qubits],
+//!                 "clbits": [qc.find_bit(c).index for c in inst.clbits],
+//!                 "params": [
+//!                     float(p) if not hasattr(p, "parameters") or not p.parameters else str(p)
+//!                     for p in inst.operation.params
+//!                 ],
+//!             }
+//!             for inst in qc.data
+//!         ],
+//!     })
+//! ```
+//!
+//! The circuit is translated into a pytket circuit and decoded with
+//! [`decode_with_options`], so gates from Qiskit's standard library become
+//! [`Tk2Op`](crate::Tk2Op)s or their native decompositions where possible.
+//! Gates with a pytket counterpart but no native tket2 one are kept as opaque
+//! pytket operations, see [`DecodeOptions`].
+
+use std::f64::consts::PI;
+use std::path::Path;
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tket_json_rs::circuit_json::{Command, Operation, Register, SerialCircuit};
+use tket_json_rs::optype::OpType as Tk1OpType;
+
+use super::pytket::{decode_with_options, DecodeOptions, TK1ConvertError};
+use crate::Circuit;
+
+/// The JSON description of a Qiskit `QuantumCircuit`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QiskitCircuit {
+    /// The name of the circuit.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The number of qubits.
+    pub num_qubits: usize,
+    /// The number of classical bits.
+    #[serde(default)]
+    pub num_clbits: usize,
+    /// The global phase, in radians.
+    #[serde(default)]
+    pub global_phase: f64,
+    /// The instructions of the circuit, in order.
+    pub instructions: Vec<QiskitInstruction>,
+}
+
+/// An instruction in a [`QiskitCircuit`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QiskitInstruction {
+    /// The name of the operation, such as `"cx"`.
+    pub name: String,
+    /// The indices of the qubits the operation acts on.
+    pub qubits: Vec<usize>,
+    /// The indices of the classical bits the operation acts on.
+    #[serde(default)]
+    pub clbits: Vec<usize>,
+    /// The parameters of the operation. Angles are given in radians.
+    #[serde(default)]
+    pub params: Vec<QiskitParam>,
+}
+
+/// A parameter of a [`QiskitInstruction`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum QiskitParam {
+    /// A numeric value.
+    Value(f64),
+    /// A symbolic expression.
+    Symbol(String),
+}
+
+impl QiskitCircuit {
+    /// Translate the circuit into a pytket circuit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the circuit contains an unsupported operation, or
+    /// refers to qubits or bits outside the circuit.
+    pub fn to_serial_circuit(&self) -> Result<SerialCircuit, QiskitConvertError> {
+        let qubit = |i: usize| Register("q".to_string(), vec![i as i64]);
+        let bit = |i: usize| Register("c".to_string(), vec![i as i64]);
+
+        let mut commands = Vec::with_capacity(self.instructions.len());
+        for (index, inst) in self.instructions.iter().enumerate() {
+            let Some(op_type) = tk1_optype(&inst.name) else {
+                return Err(QiskitConvertError::UnsupportedOperation {
+                    name: inst.name.clone(),
+                    index,
+                });
+            };
+            if inst.qubits.iter().any(|&q| q >= self.num_qubits)
+                || inst.clbits.iter().any(|&c| c >= self.num_clbits)
+            {
+                return Err(QiskitConvertError::InvalidArguments { index });
+            }
+            let mut op = Operation::default();
+            op.op_type = op_type;
+            op.n_qb = Some(inst.qubits.len() as u32);
+            op.params = (!inst.params.is_empty())
+                .then(|| inst.params.iter().map(QiskitParam::to_half_turns).collect());
+            let args = inst
+                .qubits
+                .iter()
+                .map(|&q| qubit(q))
+                .chain(inst.clbits.iter().map(|&c| bit(c)))
+                .collect();
+            commands.push(Command {
+                op,
+                args,
+                opgroup: None,
+            });
+        }
+
+        Ok(SerialCircuit {
+            name: self.name.clone(),
+            phase: (self.global_phase / PI).to_string(),
+            commands,
+            qubits: (0..self.num_qubits).map(qubit).collect(),
+            bits: (0..self.num_clbits).map(bit).collect(),
+            implicit_permutation: vec![],
+        })
+    }
+
+    /// Convert the circuit into a tket2 circuit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the circuit contains an unsupported operation, or
+    /// if `options` are strict and it contains an operation without a native
+    /// tket2 counterpart.
+    pub fn decode(&self, options: DecodeOptions) -> Result<Circuit, QiskitConvertError> {
+        Ok(decode_with_options(self.to_serial_circuit()?, options)?)
+    }
+}
+
+impl QiskitParam {
+    /// The parameter as a pytket expression, in half-turns.
+    fn to_half_turns(&self) -> String {
+        match self {
+            QiskitParam::Value(radians) => (radians / PI).to_string(),
+            QiskitParam::Symbol(expr) => format!("({expr})/pi"),
+        }
+    }
+}
+
+/// The pytket counterpart of a Qiskit standard library operation.
+///
+/// Both libraries define their gates with the same conventions, up to the
+/// unit of the angles.
+fn tk1_optype(name: &str) -> Option<Tk1OpType> {
+    Some(match name {
+        "id" => Tk1OpType::noop,
+        "h" => Tk1OpType::H,
+        "x" => Tk1OpType::X,
+        "y" => Tk1OpType::Y,
+        "z" => Tk1OpType::Z,
+        "s" => Tk1OpType::S,
+        "sdg" => Tk1OpType::Sdg,
+        "t" => Tk1OpType::T,
+        "tdg" => Tk1OpType::Tdg,
+        "sx" => Tk1OpType::SX,
+        "sxdg" => Tk1OpType::SXdg,
+        "rx" => Tk1OpType::Rx,
+        "ry" => Tk1OpType::Ry,
+        "rz" => Tk1OpType::Rz,
+        "p" | "u1" => Tk1OpType::U1,
+        "u2" => Tk1OpType::U2,
+        "u" | "u3" => Tk1OpType::U3,
+        "cx" => Tk1OpType::CX,
+        "cy" => Tk1OpType::CY,
+        "cz" => Tk1OpType::CZ,
+        "ch" => Tk1OpType::CH,
+        "crx" => Tk1OpType::CRx,
+        "cry" => Tk1OpType::CRy,
+        "crz" => Tk1OpType::CRz,
+        "cp" | "cu1" => Tk1OpType::CU1,
+        "swap" => Tk1OpType::SWAP,
+        "ccx" => Tk1OpType::CCX,
+        "cswap" => Tk1OpType::CSWAP,
+        "rxx" => Tk1OpType::XXPhase,
+        "ryy" => Tk1OpType::YYPhase,
+        "rzz" => Tk1OpType::ZZPhase,
+        "measure" => Tk1OpType::Measure,
+        "reset" => Tk1OpType::Reset,
+        "barrier" => Tk1OpType::Barrier,
+        _ => return None,
+    })
+}
+
+/// Load a Qiskit circuit from a JSON file.
+pub fn load_qiskit_json_file(
+    path: impl AsRef<Path>,
+    options: DecodeOptions,
+) -> Result<Circuit, QiskitConvertError> {
+    let file = fs::File::open(path)?;
+    let reader = io::BufReader::new(file);
+    load_qiskit_json_reader(reader, options)
+}
+
+/// Load a Qiskit circuit from a JSON reader.
+pub fn load_qiskit_json_reader(
+    json: impl io::Read,
+    options: DecodeOptions,
+) -> Result<Circuit, QiskitConvertError> {
+    let circ: QiskitCircuit = serde_json::from_reader(json)?;
+    circ.decode(options)
+}
+
+/// Load a Qiskit circuit from a JSON string.
+pub fn load_qiskit_json_str(
+    json: &str,
+    options: DecodeOptions,
+) -> Result<Circuit, QiskitConvertError> {
+    load_qiskit_json_reader(json.as_bytes(), options)
+}
+
+/// Error type for the conversion of Qiskit circuits.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum QiskitConvertError {
+    /// The circuit contains an operation that is not supported.
+    #[error("Unsupported Qiskit operation '{name}' in instruction {index}.")]
+    UnsupportedOperation {
+        /// The name of the operation.
+        name: String,
+        /// The position of the instruction in the circuit.
+        index: usize,
+    },
+    /// An instruction refers to a qubit or bit outside the circuit.
+    #[error("Instruction {index} refers to a qubit or bit outside the circuit.")]
+    InvalidArguments {
+        /// The position of the instruction in the circuit.
+        index: usize,
+    },
+    /// The translated circuit could not be decoded.
+    #[error(transparent)]
+    Decode(#[from] TK1ConvertError),
+    /// Invalid JSON.
+    #[error("Invalid Qiskit JSON. {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    /// Cannot load the circuit file.
+    #[error("Unable to load Qiskit json file. {0}")]
+    FileLoadError(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::FRAC_PI_2;
+
+    use hugr::builder::CircuitBuilder;
+    use hugr::std_extensions::arithmetic::float_types::ConstF64;
+    use hugr::CircuitUnit::{Linear, Wire};
+    use itertools::Itertools;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::serialize::pytket::ConversionReport;
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    const BELL: &str = r#"{
+        "name": "bell",
+        "num_qubits": 2,
+        "num_clbits": 2,
+        "global_phase": 0.0,
+        "instructions": [
+            {"name": "h", "qubits": [0], "clbits": [], "params": []},
+            {"name": "cx", "qubits": [0, 1], "clbits": [], "params": []},
+            {"name": "barrier", "qubits": [0, 1], "clbits": [], "params": []},
+            {"name": "measure", "qubits": [0], "clbits": [0], "params": []},
+            {"name": "measure", "qubits": [1], "clbits": [1], "params": []}
+        ]
+    }"#;
+
+    #[test]
+    fn load_bell() {
+        let circ = load_qiskit_json_str(BELL, DecodeOptions::default()).unwrap();
+        assert_eq!(circ.qubit_count(), 2);
+        let ops = circ
+            .commands()
+            .filter_map(|cmd| Tk2Op::try_from(cmd.optype()).ok())
+            .collect_vec();
+        assert_eq!(ops, [Tk2Op::H, Tk2Op::CX, Tk2Op::Measure, Tk2Op::Measure]);
+        // Only the barrier is kept opaque.
+        let report = ConversionReport::new(&circ);
+        let opaque = report.opaque_ops.iter().map(|op| &op.op_type).collect_vec();
+        assert_eq!(opaque, [&Tk1OpType::Barrier]);
+        assert!(load_qiskit_json_str(BELL, DecodeOptions::strict()).is_err());
+    }
+
+    /// Rotations are converted from radians, and match the equivalent tket2
+    /// circuit.
+    #[test]
+    fn rotations() {
+        let json = format!(
+            r#"{{
+            "num_qubits": 2,
+            "instructions": [
+                {{"name": "rz", "qubits": [0], "params": [{FRAC_PI_2}]}},
+                {{"name": "ry", "qubits": [1], "params": [0.3]}},
+                {{"name": "rzz", "qubits": [0, 1], "params": [1.1]}},
+                {{"name": "cry", "qubits": [1, 0], "params": [-0.4]}}
+            ]
+        }}"#
+        );
+        let circ = load_qiskit_json_str(&json, DecodeOptions::strict()).unwrap();
+
+        let expected = build_simple_circuit(2, |c| {
+            let param = |c: &mut CircuitBuilder<_>, angle| c.add_constant(ConstF64::new(angle));
+            let rz = param(c, FRAC_PI_2);
+            c.append_and_consume(Tk2Op::RzF64, [Linear(0), Wire(rz)])?;
+            let rx = param(c, 0.3);
+            c.append(Tk2Op::Sdg, [1])?;
+            c.append_and_consume(Tk2Op::RxF64, [Linear(1), Wire(rx)])?;
+            c.append(Tk2Op::S, [1])?;
+            let zz = param(c, 1.1);
+            c.append_and_consume(Tk2Op::ZZPhase, [Linear(0), Linear(1), Wire(zz)])?;
+            for angle in [-0.2, 0.2] {
+                let rx = param(c, angle);
+                c.append(Tk2Op::Sdg, [0])?;
+                c.append_and_consume(Tk2Op::RxF64, [Linear(0), Wire(rx)])?;
+                c.append(Tk2Op::S, [0])?;
+                c.append(Tk2Op::CX, [1, 0])?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        let (actual, expected) = (circ.unitary().unwrap(), expected.unitary().unwrap());
+        assert!((&actual - &expected).iter().all(|x| x.norm() < 1e-10));
+    }
+
+    #[rstest]
+    #[case::unsupported(
+        r#"{"num_qubits": 1, "instructions": [{"name": "my_gate", "qubits": [0]}]}"#,
+        "Unsupported Qiskit operation 'my_gate' in instruction 0."
+    )]
+    #[case::out_of_range(
+        r#"{"num_qubits": 1, "instructions": [{"name": "h", "qubits": [0]}, {"name": "x", "qubits": [1]}]}"#,
+        "Instruction 1 refers to a qubit or bit outside the circuit."
+    )]
+    fn invalid_circuits(#[case] json: &str, #[case] message: &str) {
+        let err = load_qiskit_json_str(json, DecodeOptions::default()).unwrap_err();
+        assert_eq!(err.to_string(), message);
+    }
+
+    #[test]
+    fn symbolic_parameters() {
+        let circ: QiskitCircuit = serde_json::from_str(
+            r#"{"num_qubits": 1, "instructions": [{"name": "rz", "qubits": [0], "params": ["theta"]}]}"#,
+        )
+        .unwrap();
+        let serial = circ.to_serial_circuit().unwrap();
+        assert_eq!(
+            serial.commands[0].op.params,
+            Some(vec!["(theta)/pi".to_string()])
+        );
+        let circ = circ.decode(DecodeOptions::strict()).unwrap();
+        let ops = circ
+            .commands()
+            .filter_map(|cmd| Tk2Op::try_from(cmd.optype()).ok())
+            .collect_vec();
+        assert_eq!(ops, [Tk2Op::RzF64]);
+    }
+}
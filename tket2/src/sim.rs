@@ -0,0 +1,541 @@
+//! Simulation of quantum circuits.
+//!
+//! This module contains a dense [`StateVector`] simulator for circuits of
+//! [`Tk2Op`]s, and a shot-based [`sample`] API that reads out the classical
+//! outputs of a circuit.
+//!
+//! Angle parameters must be defined by constants in the circuit. Symbolic
+//! parameters and classical control flow are not supported.
+
+pub mod statevector;
+pub mod unitary;
+
+pub use statevector::StateVector;
+
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::FRAC_1_SQRT_2;
+
+use hugr::extension::prelude::BOOL_T;
+use hugr::ops::{NamedOp, OpType};
+use hugr::std_extensions::arithmetic::float_types::FLOAT64_TYPE;
+use hugr::{CircuitUnit, HugrView, Node, OutgoingPort, Wire};
+use num_complex::Complex64;
+use rand::Rng;
+use thiserror::Error;
+
+use crate::circuit::units::Qubit;
+use crate::seed::{self, Seed};
+use crate::utils::float_wire_value;
+use crate::{Circuit, Tk2Op};
+
+/// Measurement counts, keyed by the values of the circuit's classical outputs.
+///
+/// The outcome vectors list the boolean outputs of the circuit in the order
+/// they appear in its signature.
+pub type Counts = BTreeMap<Vec<bool>, usize>;
+
+/// Compute the final statevector of a circuit.
+///
+/// The circuit qubits are mapped to the statevector qubits in input order,
+/// followed by any qubits allocated during the circuit.
+///
+/// # Errors
+///
+/// Returns an error if the circuit contains non-unitary operations such as
+/// measurements, or operations that cannot be simulated.
+pub fn simulate_statevector(circ: &Circuit<impl HugrView>) -> Result<StateVector, SimulationError> {
+    let program = Program::compile(circ)?;
+    let mut state = StateVector::new(program.num_qubits);
+    program.run_unitary(&mut state, circ.hugr())?;
+    Ok(state)
+}
+
+/// Run a circuit `shots` times and count the observed classical outputs.
+///
+/// Measurements are applied in the circuit's topological order, collapsing
+/// the state, so mid-circuit measurements and resets are honoured. Boolean
+/// outputs that are never written to by a measurement read as `false`.
+///
+/// The unitary prefix of the circuit up to the first measurement is only
+/// simulated once, and shared between all shots.
+///
+/// # Errors
+///
+/// Returns an error if the circuit contains operations that cannot be
+/// simulated, or if any boolean output is not produced by a measurement.
+pub fn sample(
+    circ: &Circuit<impl HugrView>,
+    shots: usize,
+    seed: Seed,
+) -> Result<Counts, SimulationError> {
+    sample_with_rng(circ, shots, &mut seed::rng(seed))
+}
+
+/// Run a circuit `shots` times and count the observed classical outputs,
+/// drawing the measurement outcomes from `rng`.
+///
+/// See [`sample`].
+pub fn sample_with_rng(
+    circ: &Circuit<impl HugrView>,
+    shots: usize,
+    rng: &mut impl Rng,
+) -> Result<Counts, SimulationError> {
+    let program = Program::compile(circ)?;
+
+    // Simulate the deterministic prefix of the circuit only once.
+    let prefix_len = program
+        .instructions
+        .iter()
+        .position(|i| !matches!(i, Instruction::Unitary { .. }))
+        .unwrap_or(program.instructions.len());
+    let mut prefix_state = StateVector::new(program.num_qubits);
+    program.run(&mut prefix_state, ..prefix_len, &mut HashMap::new(), rng);
+
+    let mut counts = Counts::new();
+    for _ in 0..shots {
+        let mut state = prefix_state.clone();
+        let mut bits = HashMap::new();
+        program.run(&mut state, prefix_len.., &mut bits, rng);
+        let outcome = program
+            .outputs
+            .iter()
+            .map(|bit| bit.is_some_and(|w| bits.get(&w).copied().unwrap_or_default()))
+            .collect();
+        *counts.entry(outcome).or_default() += 1;
+    }
+    Ok(counts)
+}
+
+/// Returns the unitary matrix for a [`Tk2Op`], in row-major order.
+///
+/// Angle parameters are given in radians, in the same order as the operation
+/// inputs. Multi-qubit matrices take the first qubit as the most significant
+/// bit of their indices.
+///
+/// Returns `None` for non-unitary operations.
+pub(crate) fn gate_matrix(op: Tk2Op, params: &[f64]) -> Option<Vec<Complex64>> {
+    let zero = Complex64::new(0., 0.);
+    let one = Complex64::new(1., 0.);
+    let i = Complex64::new(0., 1.);
+    let phase = |theta: f64| Complex64::from_polar(1., theta);
+    let rz = |theta: f64| vec![phase(-theta / 2.), zero, zero, phase(theta / 2.)];
+    let rx = |theta: f64| {
+        let (s, c) = (theta / 2.).sin_cos();
+        vec![c * one, -s * i, -s * i, c * one]
+    };
+    let zz_phase = |theta: f64| {
+        let (a, b) = (phase(-theta / 2.), phase(theta / 2.));
+        diagonal(&[a, b, b, a])
+    };
+    // `exp(-iθ/2 P ⊗ P)`, for a Pauli `P` given by the anti-diagonal entries
+    // of `P ⊗ P`.
+    let anti_diagonal_phase = |theta: f64, anti_diagonal: [Complex64; 4]| {
+        let (s, c) = (theta / 2.).sin_cos();
+        let mut m = diagonal(&[c * one; 4]);
+        for (k, p) in anti_diagonal.into_iter().enumerate() {
+            m[k * 4 + 3 - k] = -s * i * p;
+        }
+        m
+    };
+
+    let matrix = match op {
+        Tk2Op::H => [one, one, one, -one].map(|x| x * FRAC_1_SQRT_2).to_vec(),
+        Tk2Op::X => vec![zero, one, one, zero],
+        Tk2Op::Y => vec![zero, -i, i, zero],
+        Tk2Op::Z => diagonal(&[one, -one]),
+        Tk2Op::S => diagonal(&[one, i]),
+        Tk2Op::Sdg => diagonal(&[one, -i]),
+        Tk2Op::T => diagonal(&[one, phase(std::f64::consts::FRAC_PI_4)]),
+        Tk2Op::Tdg => diagonal(&[one, phase(-std::f64::consts::FRAC_PI_4)]),
+        Tk2Op::RzF64 => rz(*params.first()?),
+        Tk2Op::RxF64 => rx(*params.first()?),
+        Tk2Op::PhasedX => {
+            let [theta, phi] = params.get(..2)?.try_into().ok()?;
+            matmul(&matmul(&rz(phi), &rx(theta)), &rz(-phi))
+        }
+        Tk2Op::TK1 => {
+            let [a, b, c] = params.get(..3)?.try_into().ok()?;
+            matmul(&matmul(&rz(a), &rx(b)), &rz(c))
+        }
+        Tk2Op::CX => {
+            let mut m = diagonal(&[one, one, zero, zero]);
+            m[2 * 4 + 3] = one;
+            m[3 * 4 + 2] = one;
+            m
+        }
+        Tk2Op::CZ => diagonal(&[one, one, one, -one]),
+        Tk2Op::ZZMax => zz_phase(std::f64::consts::FRAC_PI_2),
+        Tk2Op::ZZPhase => zz_phase(*params.first()?),
+        Tk2Op::XXPhase => anti_diagonal_phase(*params.first()?, [one, one, one, one]),
+        Tk2Op::YYPhase => anti_diagonal_phase(*params.first()?, [-one, one, one, -one]),
+        Tk2Op::CCX => {
+            let mut m = diagonal(&[one, one, one, one, one, one, zero, zero]);
+            m[6 * 8 + 7] = one;
+            m[7 * 8 + 6] = one;
+            m
+        }
+        Tk2Op::CCZ => diagonal(&[one, one, one, one, one, one, one, -one]),
+        Tk2Op::Measure | Tk2Op::AngleAdd | Tk2Op::QAlloc | Tk2Op::QFree | Tk2Op::Reset => {
+            return None
+        }
+    };
+    Some(matrix)
+}
+
+/// A diagonal matrix in row-major order.
+fn diagonal(entries: &[Complex64]) -> Vec<Complex64> {
+    let dim = entries.len();
+    let mut m = vec![Complex64::new(0., 0.); dim * dim];
+    for (k, &e) in entries.iter().enumerate() {
+        m[k * dim + k] = e;
+    }
+    m
+}
+
+/// Multiply two square matrices in row-major order.
+pub(crate) fn matmul(a: &[Complex64], b: &[Complex64]) -> Vec<Complex64> {
+    let dim = (a.len() as f64).sqrt() as usize;
+    let mut m = vec![Complex64::new(0., 0.); dim * dim];
+    for row in 0..dim {
+        for col in 0..dim {
+            m[row * dim + col] = (0..dim).map(|k| a[row * dim + k] * b[k * dim + col]).sum();
+        }
+    }
+    m
+}
+
+/// A circuit lowered into a sequence of simulator instructions.
+#[derive(Debug, Clone)]
+struct Program {
+    /// The total number of qubits, including allocated ones.
+    num_qubits: usize,
+    /// The instructions to execute, in order.
+    instructions: Vec<Instruction>,
+    /// The wire connected to each boolean output of the circuit.
+    ///
+    /// `None` for outputs connected directly to the circuit input.
+    outputs: Vec<Option<Wire>>,
+}
+
+/// A single simulator step.
+#[derive(Debug, Clone)]
+enum Instruction {
+    /// Apply a unitary to some qubits.
+    Unitary {
+        matrix: Vec<Complex64>,
+        qubits: Vec<Qubit>,
+    },
+    /// Measure a qubit, storing the result in a boolean wire.
+    Measure { node: Node, qubit: Qubit, bit: Wire },
+    /// Reset a qubit to `|0⟩`.
+    Reset { node: Node, qubit: Qubit },
+}
+
+impl Program {
+    /// Translate the commands of a circuit into simulator instructions.
+    fn compile(circ: &Circuit<impl HugrView>) -> Result<Self, SimulationError> {
+        let hugr = circ.hugr();
+        let mut num_qubits = circ.qubit_count();
+        let mut instructions = Vec::new();
+
+        for cmd in circ.commands() {
+            let node = cmd.node();
+            let optype = cmd.optype();
+            let mut qubits: Vec<Qubit> = cmd.input_qubits().map(|(u, _, _)| u.into()).collect();
+            if qubits.is_empty() {
+                // Qubit allocations have no qubit inputs.
+                qubits = cmd.output_qubits().map(|(u, _, _)| u.into()).collect();
+            }
+            if qubits.is_empty() {
+                // Purely classical operations are only evaluated when their
+                // outputs are required as parameters.
+                continue;
+            }
+            num_qubits = num_qubits.max(qubits.iter().max().unwrap().index() + 1);
+
+            let unsupported = || SimulationError::UnsupportedOperation {
+                optype: optype.clone(),
+                node,
+            };
+            let Ok(op) = Tk2Op::try_from(optype) else {
+                if matches!(optype, OpType::Noop(_)) {
+                    continue;
+                }
+                return Err(unsupported());
+            };
+
+            let instruction = match op {
+                Tk2Op::Measure => Instruction::Measure {
+                    node,
+                    qubit: qubits[0],
+                    bit: Wire::new(node, OutgoingPort::from(1)),
+                },
+                Tk2Op::Reset | Tk2Op::QAlloc | Tk2Op::QFree => Instruction::Reset {
+                    node,
+                    qubit: qubits[0],
+                },
+                _ => {
+                    let params = cmd
+                        .inputs()
+                        .filter(|(_, _, ty)| ty == &FLOAT64_TYPE)
+                        .map(|(unit, _, _)| match unit {
+                            CircuitUnit::Wire(wire) => float_wire_value(hugr, wire),
+                            CircuitUnit::Linear(_) => None,
+                        })
+                        .collect::<Option<Vec<f64>>>()
+                        .ok_or_else(|| SimulationError::UnresolvedParameter {
+                            optype: optype.clone(),
+                            node,
+                        })?;
+                    let matrix = gate_matrix(op, &params).ok_or_else(unsupported)?;
+                    Instruction::Unitary { matrix, qubits }
+                }
+            };
+            instructions.push(instruction);
+        }
+
+        let [input, output] = circ.io_nodes();
+        let outputs = hugr
+            .node_inputs(output)
+            .filter(|&port| {
+                hugr.signature(output)
+                    .and_then(|sig| sig.in_port_type(port).cloned())
+                    == Some(BOOL_T)
+            })
+            .map(|port| {
+                let (node, src_port) = hugr.single_linked_output(output, port).unwrap();
+                if node == input {
+                    Ok(None)
+                } else if instructions
+                    .iter()
+                    .any(|i| matches!(i, Instruction::Measure { node: n, .. } if *n == node))
+                {
+                    Ok(Some(Wire::new(node, src_port)))
+                } else {
+                    Err(SimulationError::UnresolvedBitOutput {
+                        optype: hugr.get_optype(node).clone(),
+                        node,
+                    })
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            num_qubits,
+            instructions,
+            outputs,
+        })
+    }
+
+    /// Execute all the program's instructions on a state, failing if any of
+    /// them is not unitary.
+    fn run_unitary(
+        &self,
+        state: &mut StateVector,
+        hugr: &impl HugrView,
+    ) -> Result<(), SimulationError> {
+        for instruction in &self.instructions {
+            match instruction {
+                Instruction::Unitary { matrix, qubits } => state.apply_matrix(matrix, qubits),
+                Instruction::Measure { node, .. } | Instruction::Reset { node, .. } => {
+                    return Err(SimulationError::NonUnitaryOperation {
+                        optype: hugr.get_optype(*node).clone(),
+                        node: *node,
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute a range of the program's instructions on a state.
+    fn run(
+        &self,
+        state: &mut StateVector,
+        range: impl std::slice::SliceIndex<[Instruction], Output = [Instruction]>,
+        bits: &mut HashMap<Wire, bool>,
+        rng: &mut impl Rng,
+    ) {
+        for instruction in &self.instructions[range] {
+            match instruction {
+                Instruction::Unitary { matrix, qubits } => state.apply_matrix(matrix, qubits),
+                Instruction::Measure { qubit, bit, .. } => {
+                    bits.insert(*bit, state.measure(*qubit, rng));
+                }
+                Instruction::Reset { qubit, .. } => state.reset(*qubit, rng),
+            }
+        }
+    }
+}
+
+/// Errors that can occur while simulating a circuit.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum SimulationError {
+    /// The circuit contains an operation that cannot be simulated.
+    #[error("Operation {} in {node} cannot be simulated.", optype.name())]
+    UnsupportedOperation {
+        /// The unsupported operation.
+        optype: OpType,
+        /// The node.
+        node: Node,
+    },
+    /// An angle parameter is not a constant value.
+    #[error("The parameters for operation {} in {node} could not be resolved to constants.", optype.name())]
+    UnresolvedParameter {
+        /// The operation with the unresolved parameter.
+        optype: OpType,
+        /// The node.
+        node: Node,
+    },
+    /// The statevector simulation encountered a non-unitary operation.
+    #[error("Operation {} in {node} is not unitary.", optype.name())]
+    NonUnitaryOperation {
+        /// The non-unitary operation.
+        optype: OpType,
+        /// The node.
+        node: Node,
+    },
+    /// A boolean circuit output is not produced by a measurement.
+    #[error("The boolean output produced by {} in {node} is not a measurement result.", optype.name())]
+    UnresolvedBitOutput {
+        /// The operation producing the output.
+        optype: OpType,
+        /// The node.
+        node: Node,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::builder::{Dataflow, DataflowHugr, FunctionBuilder};
+    use hugr::extension::prelude::QB_T;
+    use hugr::types::Signature;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::extension::REGISTRY;
+    use crate::serialize::{load_tk1_json_str, DecodeOptions};
+    use crate::utils::build_simple_circuit;
+
+    /// A circuit measuring both qubits of a bell pair.
+    fn measured_bell() -> Circuit {
+        load_tk1_json_str(
+            r#"{
+            "phase": "0",
+            "bits": [["c", [0]], ["c", [1]]],
+            "qubits": [["q", [0]], ["q", [1]]],
+            "commands": [
+                {"args": [["q", [0]]], "op": {"type": "H"}},
+                {"args": [["q", [0]], ["q", [1]]], "op": {"type": "CX"}},
+                {"args": [["q", [0]], ["c", [0]]], "op": {"type": "Measure"}},
+                {"args": [["q", [1]], ["c", [1]]], "op": {"type": "Measure"}}
+            ],
+            "implicit_permutation": []
+        }"#,
+            DecodeOptions::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn statevector_rotations() {
+        let circ = load_tk1_json_str(
+            r#"{
+            "phase": "0",
+            "bits": [],
+            "qubits": [["q", [0]]],
+            "commands": [
+                {"args": [["q", [0]]], "op": {"type": "Rx", "params": ["0.5"]}},
+                {"args": [["q", [0]]], "op": {"type": "Rx", "params": ["0.5"]}}
+            ],
+            "implicit_permutation": []
+        }"#,
+            DecodeOptions::default(),
+        )
+        .unwrap();
+        let state = simulate_statevector(&circ).unwrap();
+        assert!((state.probability_one(Qubit::new(0)) - 1.).abs() < 1e-10);
+    }
+
+    #[test]
+    fn statevector_rejects_measurements() {
+        let err = simulate_statevector(&measured_bell()).unwrap_err();
+        assert!(matches!(err, SimulationError::NonUnitaryOperation { .. }));
+    }
+
+    #[test]
+    fn sample_bell() {
+        let counts = sample(&measured_bell(), 200, 42).unwrap();
+        assert_eq!(counts.values().sum::<usize>(), 200);
+        assert_eq!(counts.len(), 2);
+        assert!(counts[&vec![false, false]] > 0);
+        assert!(counts[&vec![true, true]] > 0);
+
+        // Sampling is deterministic for a given seed.
+        assert_eq!(counts, sample(&measured_bell(), 200, 42).unwrap());
+    }
+
+    #[rstest]
+    #[case::reset(true)]
+    #[case::no_reset(false)]
+    fn sample_mid_circuit_measurement(#[case] reset: bool) {
+        // Measure the same qubit twice, flipping it in between.
+        let circ = {
+            let mut h =
+                FunctionBuilder::new("main", Signature::new(vec![QB_T], vec![BOOL_T, BOOL_T]))
+                    .unwrap();
+            let [q] = h.input_wires_arr();
+            let [q] = h.add_dataflow_op(Tk2Op::X, [q]).unwrap().outputs_arr();
+            let [q, b0] = h
+                .add_dataflow_op(Tk2Op::Measure, [q])
+                .unwrap()
+                .outputs_arr();
+            let [q] = match reset {
+                true => h.add_dataflow_op(Tk2Op::Reset, [q]).unwrap().outputs_arr(),
+                false => h.add_dataflow_op(Tk2Op::X, [q]).unwrap().outputs_arr(),
+            };
+            let [q, b1] = h
+                .add_dataflow_op(Tk2Op::Measure, [q])
+                .unwrap()
+                .outputs_arr();
+            h.add_dataflow_op(Tk2Op::QFree, [q]).unwrap();
+            let hugr = h.finish_hugr_with_outputs([b0, b1], &REGISTRY).unwrap();
+            Circuit::from(hugr)
+        };
+
+        let counts = sample(&circ, 10, 0).unwrap();
+        assert_eq!(counts, Counts::from([(vec![true, false], 10)]));
+    }
+
+    #[rstest]
+    #[case::xx(Tk2Op::XXPhase, Tk2Op::X)]
+    #[case::yy(Tk2Op::YYPhase, Tk2Op::Y)]
+    #[case::zz(Tk2Op::ZZPhase, Tk2Op::Z)]
+    fn two_qubit_pauli_rotations(#[case] op: Tk2Op, #[case] pauli: Tk2Op) {
+        // `exp(-iθ/2 P ⊗ P) = cos(θ/2) I - i sin(θ/2) P ⊗ P`
+        let theta: f64 = 0.7;
+        let p = gate_matrix(pauli, &[]).unwrap();
+        let pp = (0..16).map(|k| p[(k / 8) * 2 + (k % 4) / 2] * p[((k / 4) % 2) * 2 + k % 2]);
+        let (s, c) = (theta / 2.).sin_cos();
+        let expected = pp
+            .enumerate()
+            .map(|(k, x)| if k % 5 == 0 { c } else { 0. } - Complex64::i() * s * x);
+
+        let matrix = gate_matrix(op, &[theta]).unwrap();
+        for (x, y) in matrix.iter().zip(expected) {
+            assert!((x - y).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn unmeasured_bits_read_false() {
+        let circ = build_simple_circuit(1, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        let counts = sample(&circ, 5, 0).unwrap();
+        assert_eq!(counts, Counts::from([(vec![], 5)]));
+    }
+}
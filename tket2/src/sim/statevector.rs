@@ -0,0 +1,210 @@
+//! Dense statevector representation of a quantum state.
+
+use num_complex::Complex64;
+use rand::Rng;
+
+use crate::circuit::units::Qubit;
+
+/// A dense statevector over a fixed number of qubits.
+///
+/// Basis states are indexed in little-endian order: qubit `i` corresponds to
+/// the `i`-th least significant bit of the amplitude index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateVector {
+    /// The number of qubits in the state.
+    num_qubits: usize,
+    /// The `2^num_qubits` complex amplitudes.
+    amplitudes: Vec<Complex64>,
+}
+
+impl StateVector {
+    /// Create a new statevector initialised to the all-zero state.
+    pub fn new(num_qubits: usize) -> Self {
+        Self::basis_state(num_qubits, 0)
+    }
+
+    /// Create a new statevector initialised to a computational basis state.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not smaller than `2^num_qubits`.
+    pub fn basis_state(num_qubits: usize, index: usize) -> Self {
+        let mut amplitudes = vec![Complex64::new(0., 0.); 1 << num_qubits];
+        amplitudes[index] = Complex64::new(1., 0.);
+        Self {
+            num_qubits,
+            amplitudes,
+        }
+    }
+
+    /// The number of qubits in the state.
+    pub fn num_qubits(&self) -> usize {
+        self.num_qubits
+    }
+
+    /// The complex amplitudes of the state.
+    pub fn amplitudes(&self) -> &[Complex64] {
+        &self.amplitudes
+    }
+
+    /// The probability of observing each basis state.
+    pub fn probabilities(&self) -> Vec<f64> {
+        self.amplitudes.iter().map(|a| a.norm_sqr()).collect()
+    }
+
+    /// The probability of measuring `qubit` in the `|1⟩` state.
+    pub fn probability_one(&self, qubit: Qubit) -> f64 {
+        let mask = 1 << qubit.index();
+        self.amplitudes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i & mask != 0)
+            .map(|(_, a)| a.norm_sqr())
+            .sum()
+    }
+
+    /// Apply a unitary matrix to a list of qubits.
+    ///
+    /// The matrix is given in row-major order, with dimension `2^k` for `k`
+    /// qubits. The first qubit in `qubits` corresponds to the most significant
+    /// bit of the matrix indices, so a `CX` matrix in the usual textbook form
+    /// takes the control as its first qubit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the matrix does not have the right dimension, or if any of the
+    /// qubits is out of range.
+    pub fn apply_matrix(&mut self, matrix: &[Complex64], qubits: &[Qubit]) {
+        let k = qubits.len();
+        let dim = 1 << k;
+        assert_eq!(matrix.len(), dim * dim, "Invalid matrix dimension.");
+        assert!(
+            qubits.iter().all(|q| q.index() < self.num_qubits),
+            "Qubit index out of range."
+        );
+
+        // Offset in the amplitude vector for each matrix index.
+        let offsets: Vec<usize> = (0..dim)
+            .map(|j| {
+                qubits
+                    .iter()
+                    .enumerate()
+                    .filter(|(t, _)| j & (1 << (k - 1 - t)) != 0)
+                    .map(|(_, q)| 1 << q.index())
+                    .sum()
+            })
+            .collect();
+        let qubit_mask: usize = qubits.iter().map(|q| 1 << q.index()).sum();
+
+        let mut local = vec![Complex64::new(0., 0.); dim];
+        for base in 0..self.amplitudes.len() {
+            if base & qubit_mask != 0 {
+                continue;
+            }
+            for (j, &offset) in offsets.iter().enumerate() {
+                local[j] = self.amplitudes[base | offset];
+            }
+            for (row, &offset) in offsets.iter().enumerate() {
+                self.amplitudes[base | offset] = matrix[row * dim..(row + 1) * dim]
+                    .iter()
+                    .zip(&local)
+                    .map(|(m, a)| m * a)
+                    .sum();
+            }
+        }
+    }
+
+    /// Measure a qubit in the computational basis, collapsing the state.
+    ///
+    /// Returns the measurement outcome.
+    pub fn measure(&mut self, qubit: Qubit, rng: &mut impl Rng) -> bool {
+        let p_one = self.probability_one(qubit);
+        let outcome = rng.gen::<f64>() < p_one;
+        self.collapse(qubit, outcome, if outcome { p_one } else { 1. - p_one });
+        outcome
+    }
+
+    /// Reset a qubit to the `|0⟩` state.
+    ///
+    /// This measures the qubit and flips it if the outcome was `|1⟩`.
+    pub fn reset(&mut self, qubit: Qubit, rng: &mut impl Rng) {
+        if self.measure(qubit, rng) {
+            let mask = 1 << qubit.index();
+            for i in 0..self.amplitudes.len() {
+                if i & mask == 0 {
+                    self.amplitudes.swap(i, i | mask);
+                }
+            }
+        }
+    }
+
+    /// Project `qubit` onto the given outcome and renormalise the state.
+    fn collapse(&mut self, qubit: Qubit, outcome: bool, probability: f64) {
+        let mask = 1 << qubit.index();
+        let norm = probability.sqrt();
+        for (i, amp) in self.amplitudes.iter_mut().enumerate() {
+            if (i & mask != 0) == outcome {
+                *amp /= norm;
+            } else {
+                *amp = Complex64::new(0., 0.);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn c(re: f64) -> Complex64 {
+        Complex64::new(re, 0.)
+    }
+
+    #[test]
+    fn bell_state() {
+        let h = [
+            c(FRAC_1_SQRT_2),
+            c(FRAC_1_SQRT_2),
+            c(FRAC_1_SQRT_2),
+            c(-FRAC_1_SQRT_2),
+        ];
+        let cx = [
+            c(1.),
+            c(0.),
+            c(0.),
+            c(0.),
+            c(0.),
+            c(1.),
+            c(0.),
+            c(0.),
+            c(0.),
+            c(0.),
+            c(0.),
+            c(1.),
+            c(0.),
+            c(0.),
+            c(1.),
+            c(0.),
+        ];
+        let mut state = StateVector::new(2);
+        state.apply_matrix(&h, &[Qubit::new(0)]);
+        state.apply_matrix(&cx, &[Qubit::new(0), Qubit::new(1)]);
+
+        let probs = state.probabilities();
+        assert!((probs[0b00] - 0.5).abs() < 1e-10);
+        assert!((probs[0b11] - 0.5).abs() < 1e-10);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let first = state.measure(Qubit::new(0), &mut rng);
+        assert_eq!(state.measure(Qubit::new(1), &mut rng), first);
+
+        state.reset(Qubit::new(0), &mut rng);
+        state.reset(Qubit::new(1), &mut rng);
+        assert!((state.probabilities()[0] - 1.).abs() < 1e-10);
+    }
+}
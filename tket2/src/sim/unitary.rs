@@ -0,0 +1,168 @@
+//! Dense unitary matrices of pure quantum circuits.
+//!
+//! Matrices are indexed consistently with [`StateVector`]: qubit `i` of the
+//! circuit corresponds to the `i`-th least significant bit of the row and
+//! column indices.
+
+use hugr::HugrView;
+use ndarray::Array2;
+use num_complex::Complex64;
+
+use super::{Program, SimulationError, StateVector};
+use crate::Circuit;
+
+/// Compute the unitary matrix implemented by a circuit.
+///
+/// Entry `(i, j)` of the result is the amplitude of basis state `i` after
+/// applying the circuit to basis state `j`.
+///
+/// # Errors
+///
+/// Returns an error if the circuit contains non-unitary operations such as
+/// measurements or qubit allocations, or operations that cannot be simulated.
+pub fn unitary(circ: &Circuit<impl HugrView>) -> Result<Array2<Complex64>, SimulationError> {
+    let program = Program::compile(circ)?;
+    let dim = 1 << program.num_qubits;
+    let mut matrix = Array2::zeros((dim, dim));
+    for (j, mut column) in matrix.columns_mut().into_iter().enumerate() {
+        let mut state = StateVector::basis_state(program.num_qubits, j);
+        program.run_unitary(&mut state, circ.hugr())?;
+        column.assign(&ndarray::aview1(state.amplitudes()));
+    }
+    Ok(matrix)
+}
+
+/// Check whether two unitaries are equal up to a global phase.
+///
+/// Entries are compared with an absolute tolerance of `tol`, after
+/// removing the relative phase between the two matrices.
+pub fn equal_up_to_phase(a: &Array2<Complex64>, b: &Array2<Complex64>, tol: f64) -> bool {
+    if a.dim() != b.dim() {
+        return false;
+    }
+    // Use the largest entry of `a` to fix the relative phase.
+    let Some((idx, &pivot)) = a
+        .indexed_iter()
+        .max_by(|(_, x), (_, y)| x.norm_sqr().total_cmp(&y.norm_sqr()))
+    else {
+        return true;
+    };
+    if b[idx].norm() < tol {
+        return pivot.norm() < tol;
+    }
+    let phase = pivot / b[idx];
+    let phase = phase / phase.norm();
+    a.iter()
+        .zip(b.iter())
+        .all(|(x, y)| (x - y * phase).norm() < tol)
+}
+
+/// The phase `φ` such that `a = e^{iφ} b`, for two unitaries equal up to a
+/// global phase.
+///
+/// The unitaries may be given as matrices or as flat slices of their entries,
+/// in the same order.
+pub(crate) fn relative_phase<'a>(
+    a: impl IntoIterator<Item = &'a Complex64>,
+    b: impl IntoIterator<Item = &'a Complex64>,
+) -> f64 {
+    let (x, pivot) = a
+        .into_iter()
+        .zip(b)
+        .max_by(|(_, x), (_, y)| x.norm_sqr().total_cmp(&y.norm_sqr()))
+        .unwrap();
+    (x / pivot).arg()
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    use ndarray::array;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::ops::op_matches;
+    use crate::rewrite::Subcircuit;
+    use crate::utils::{build_simple_circuit, TOLERANCE};
+    use crate::Tk2Op;
+
+    fn c(re: f64, im: f64) -> Complex64 {
+        Complex64::new(re, im)
+    }
+
+    #[test]
+    fn unitary_h() {
+        let circ = build_simple_circuit(1, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        let h = c(FRAC_1_SQRT_2, 0.);
+        let expected = array![[h, h], [h, -h]];
+        assert!(equal_up_to_phase(
+            &circ.unitary().unwrap(),
+            &expected,
+            TOLERANCE
+        ));
+    }
+
+    #[test]
+    fn unitary_cx_little_endian() {
+        // Control on qubit 0, the least significant bit.
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        let (o, l) = (c(0., 0.), c(1., 0.));
+        let expected = array![[l, o, o, o], [o, o, o, l], [o, o, l, o], [o, l, o, o]];
+        assert_eq!(circ.unitary().unwrap(), expected);
+    }
+
+    #[test]
+    fn subcircuit_unitary() {
+        let circ = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            circ.append(Tk2Op::T, [1])?;
+            Ok(())
+        })
+        .unwrap();
+        let cx_node = circ
+            .commands()
+            .find(|cmd| op_matches(cmd.optype(), Tk2Op::CX))
+            .unwrap()
+            .node();
+        let subcirc = Subcircuit::try_from_nodes([cx_node], &circ).unwrap();
+
+        let cx = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(subcirc.unitary(&circ).unwrap(), cx.unitary().unwrap());
+    }
+
+    #[rstest]
+    #[case::s_is_tt(Tk2Op::S, vec![Tk2Op::T, Tk2Op::T], true)]
+    #[case::z_is_ss(Tk2Op::Z, vec![Tk2Op::S, Tk2Op::S], true)]
+    #[case::x_is_hzh(Tk2Op::X, vec![Tk2Op::H, Tk2Op::Z, Tk2Op::H], true)]
+    #[case::x_is_not_z(Tk2Op::X, vec![Tk2Op::Z], false)]
+    fn equivalence(#[case] op: Tk2Op, #[case] decomposition: Vec<Tk2Op>, #[case] equal: bool) {
+        let lhs = build_simple_circuit(1, |circ| {
+            circ.append(op, [0])?;
+            Ok(())
+        })
+        .unwrap();
+        let rhs = build_simple_circuit(1, |circ| {
+            for &op in &decomposition {
+                circ.append(op, [0])?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        let (lhs, rhs) = (lhs.unitary().unwrap(), rhs.unitary().unwrap());
+        assert_eq!(equal_up_to_phase(&lhs, &rhs, TOLERANCE), equal);
+    }
+}
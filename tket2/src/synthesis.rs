@@ -0,0 +1,76 @@
+//! Synthesis of circuits from unitary specifications.
+//!
+//! [`clifford_t`] approximates single-qubit rotations by sequences of
+//! Clifford+T gates, for compiling continuous-angle circuits to
+//! fault-tolerant gate sets. [`unitary`] synthesises exact circuits for
+//! arbitrary unitaries on a few qubits.
+
+pub mod clifford_t;
+pub use clifford_t::{approximate_rz, approximate_unitary, CliffordTApproximation};
+
+pub mod unitary;
+pub use unitary::{synthesize_unitary, Entangler, SynthesisBasis, MAX_SYNTHESIS_QUBITS};
+
+mod linalg;
+
+use thiserror::Error;
+
+/// Tolerance used when checking that an input matrix is unitary.
+///
+/// Looser than [`crate::utils::TOLERANCE`], since the matrices are usually
+/// computed as products of many gates.
+pub(crate) const UNITARY_TOLERANCE: f64 = 1e-8;
+
+/// Error from synthesising a circuit.
+#[derive(Debug, Clone, Error, PartialEq)]
+#[non_exhaustive]
+pub enum SynthesisError {
+    /// The requested precision is not a positive number.
+    #[error("Invalid approximation precision {epsilon}.")]
+    InvalidPrecision {
+        /// The requested precision.
+        epsilon: f64,
+    },
+    /// The best approximation found is not within the requested precision.
+    #[error("Could not approximate the unitary within {epsilon}, the best approximation has an error of {achieved}.")]
+    PrecisionNotReached {
+        /// The requested precision.
+        epsilon: f64,
+        /// The error of the best approximation found.
+        achieved: f64,
+    },
+    /// The matrix does not have the expected dimensions.
+    #[error("Expected a {expected}x{expected} matrix, got a {rows}x{cols} matrix.")]
+    InvalidDimensions {
+        /// The expected number of rows and columns.
+        expected: usize,
+        /// The number of rows of the matrix.
+        rows: usize,
+        /// The number of columns of the matrix.
+        cols: usize,
+    },
+    /// The matrix is not unitary.
+    #[error("The matrix is not unitary.")]
+    NotUnitary,
+    /// The unitary acts on more qubits than supported.
+    #[error("Cannot synthesise a unitary on {qubits} qubits, at most {max} are supported.")]
+    TooManyQubits {
+        /// The number of qubits of the unitary.
+        qubits: usize,
+        /// The largest number of qubits supported.
+        max: usize,
+    },
+    /// The requested block size is not supported.
+    #[error(
+        "Cannot resynthesise blocks of {qubits} qubits, the size must be between 2 and {max}."
+    )]
+    InvalidBlockSize {
+        /// The requested number of qubits per block.
+        qubits: usize,
+        /// The largest number of qubits supported.
+        max: usize,
+    },
+    /// A numerical decomposition did not converge.
+    #[error("The unitary decomposition did not converge.")]
+    DecompositionFailed,
+}
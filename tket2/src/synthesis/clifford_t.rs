@@ -0,0 +1,549 @@
+//! Approximation of single-qubit unitaries by Clifford+T circuits.
+//!
+//! [`approximate_rz`] and [`approximate_unitary`] return a sequence of `H`,
+//! `S`, `T` gates, their inverses and `Z`, implementing a unitary up to a
+//! global phase and within a given error.
+//!
+//! The approximation uses the Solovay-Kitaev algorithm. A base net of all the
+//! Clifford+T unitaries with up to [`BASE_NET_T_COUNT`] T gates is enumerated
+//! once, in Matsumoto-Amano normal form. The closest element of the net is
+//! then refined recursively by approximating the remaining error as a group
+//! commutator, until the requested precision is reached. Each level of
+//! recursion reduces the error super-exponentially, at the cost of a five-fold
+//! increase in the length of the sequence.
+//!
+//! Errors are measured as the operator norm distance between the unitaries,
+//! minimised over their global phase.
+
+use std::collections::{HashSet, VecDeque};
+use std::ops::Mul;
+
+use lazy_static::lazy_static;
+use ndarray::Array2;
+use num_complex::Complex64;
+
+use super::{SynthesisError, UNITARY_TOLERANCE};
+use crate::sim::gate_matrix;
+use crate::Tk2Op;
+
+/// The largest number of T gates in the elements of the base net.
+pub const BASE_NET_T_COUNT: usize = 7;
+
+/// The largest number of Solovay-Kitaev refinement steps.
+pub const MAX_DEPTH: usize = 6;
+
+/// A Clifford+T circuit approximating a single-qubit unitary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CliffordTApproximation {
+    /// The gates of the circuit, in order of application.
+    pub gates: Vec<Tk2Op>,
+    /// The global phase `φ`, in radians, such that the target unitary is
+    /// approximately `e^{iφ}` times the unitary of the circuit.
+    pub phase: f64,
+    /// The distance between the target and the circuit, up to the global
+    /// phase.
+    pub error: f64,
+}
+
+impl CliffordTApproximation {
+    /// The number of T and Tdg gates in the circuit.
+    pub fn t_count(&self) -> usize {
+        self.gates
+            .iter()
+            .filter(|op| matches!(op, Tk2Op::T | Tk2Op::Tdg))
+            .count()
+    }
+}
+
+/// Approximate a Z rotation `Rz(θ)` by a Clifford+T circuit.
+///
+/// # Errors
+///
+/// Returns an error if `epsilon` is not positive, or if the precision cannot
+/// be reached within [`MAX_DEPTH`] refinement steps.
+pub fn approximate_rz(theta: f64, epsilon: f64) -> Result<CliffordTApproximation, SynthesisError> {
+    let rz = gate_matrix(Tk2Op::RzF64, &[theta]).unwrap();
+    approximate(Mat2::from_slice(&rz), epsilon)
+}
+
+/// Approximate a single-qubit unitary by a Clifford+T circuit.
+///
+/// # Errors
+///
+/// Returns an error if the matrix is not a 2x2 unitary, if `epsilon` is not
+/// positive, or if the precision cannot be reached within [`MAX_DEPTH`]
+/// refinement steps.
+pub fn approximate_unitary(
+    u: &Array2<Complex64>,
+    epsilon: f64,
+) -> Result<CliffordTApproximation, SynthesisError> {
+    let (rows, cols) = u.dim();
+    if (rows, cols) != (2, 2) {
+        return Err(SynthesisError::InvalidDimensions {
+            expected: 2,
+            rows,
+            cols,
+        });
+    }
+    let u = Mat2([u[(0, 0)], u[(0, 1)], u[(1, 0)], u[(1, 1)]]);
+    let identity = u.dagger() * u;
+    if distance_to_identity(&identity) > UNITARY_TOLERANCE {
+        return Err(SynthesisError::NotUnitary);
+    }
+    approximate(u, epsilon)
+}
+
+/// Refine the approximation of a unitary until it reaches the precision.
+fn approximate(u: Mat2, epsilon: f64) -> Result<CliffordTApproximation, SynthesisError> {
+    if epsilon.is_nan() || epsilon <= 0. {
+        return Err(SynthesisError::InvalidPrecision { epsilon });
+    }
+    let target = u.to_su2();
+    let mut best: Option<CliffordTApproximation> = None;
+    for depth in 0..=MAX_DEPTH {
+        let approx = solovay_kitaev(&target, depth).finish(&u);
+        if approx.error <= epsilon {
+            return Ok(approx);
+        }
+        match &best {
+            Some(best) if best.error <= approx.error => {}
+            _ => best = Some(approx),
+        }
+    }
+    Err(SynthesisError::PrecisionNotReached {
+        epsilon,
+        achieved: best.unwrap().error,
+    })
+}
+
+/// A 2x2 complex matrix, in row-major order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Mat2([Complex64; 4]);
+
+impl Mat2 {
+    const IDENTITY: Self = Self([
+        Complex64::new(1., 0.),
+        Complex64::new(0., 0.),
+        Complex64::new(0., 0.),
+        Complex64::new(1., 0.),
+    ]);
+
+    fn from_slice(m: &[Complex64]) -> Self {
+        Self([m[0], m[1], m[2], m[3]])
+    }
+
+    fn dagger(&self) -> Self {
+        let [a, b, c, d] = self.0;
+        Self([a.conj(), c.conj(), b.conj(), d.conj()])
+    }
+
+    fn trace(&self) -> Complex64 {
+        self.0[0] + self.0[3]
+    }
+
+    /// Rescale a unitary to have determinant one.
+    fn to_su2(self) -> Self {
+        let [a, b, c, d] = self.0;
+        let scale = (a * d - b * c).sqrt();
+        Self(self.0.map(|x| x / scale))
+    }
+
+    /// The rotation angle and axis of an SU(2) matrix `cos(θ/2) I - i sin(θ/2)
+    /// (n·σ)`, with `θ` in `[0, π]` up to the sign of the matrix.
+    fn axis_angle(&self) -> (f64, [f64; 3]) {
+        let [u00, u01, u10, u11] = self.0;
+        let c = (u00 + u11).re / 2.;
+        let axis = [
+            -(u01 + u10).im / 2.,
+            (u10 - u01).re / 2.,
+            (u11 - u00).im / 2.,
+        ];
+        // Pick the sign of the matrix with a non-negative cosine.
+        let (c, axis) = match c < 0. {
+            true => (-c, axis.map(|x| -x)),
+            false => (c, axis),
+        };
+        let s = norm(axis);
+        let angle = 2. * s.atan2(c);
+        match s < 1e-15 {
+            true => (0., [0., 0., 1.]),
+            false => (angle, axis.map(|x| x / s)),
+        }
+    }
+
+    /// The rotation by an angle around an axis.
+    fn rotation(angle: f64, axis: [f64; 3]) -> Self {
+        let (s, c) = (angle / 2.).sin_cos();
+        let [x, y, z] = axis;
+        Self([
+            Complex64::new(c, -s * z),
+            Complex64::new(-s * y, -s * x),
+            Complex64::new(s * y, -s * x),
+            Complex64::new(c, s * z),
+        ])
+    }
+}
+
+impl Mul for Mat2 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let [a, b, c, d] = self.0;
+        let [e, f, g, h] = rhs.0;
+        Self([a * e + b * g, a * f + b * h, c * e + d * g, c * f + d * h])
+    }
+}
+
+fn norm(v: [f64; 3]) -> f64 {
+    v.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// The distance between two unitaries up to a global phase, given the
+/// product `U† V`.
+///
+/// If the eigenvalues of `U† V` are `e^{iα}` and `e^{iβ}`, the distance is
+/// `2 sin(|α - β| / 4)`.
+fn phase_distance(u_dagger_v: Mat2) -> f64 {
+    let (angle, _) = u_dagger_v.to_su2().axis_angle();
+    2. * (angle / 4.).sin()
+}
+
+fn distance_to_identity(u: &Mat2) -> f64 {
+    u.0.iter()
+        .zip(Mat2::IDENTITY.0)
+        .map(|(x, y)| (x - y).norm())
+        .fold(0., f64::max)
+}
+
+/// A sequence of gates, with its SU(2) unitary.
+#[derive(Debug, Clone)]
+struct Sequence {
+    /// The gates, in order of application.
+    gates: Vec<Tk2Op>,
+    unitary: Mat2,
+}
+
+impl Sequence {
+    fn identity() -> Self {
+        Self {
+            gates: Vec::new(),
+            unitary: Mat2::IDENTITY,
+        }
+    }
+
+    fn dagger(&self) -> Self {
+        Self {
+            gates: self.gates.iter().rev().map(|&op| gate_dagger(op)).collect(),
+            unitary: self.unitary.dagger(),
+        }
+    }
+
+    /// The sequence applying `self` and then `other`.
+    fn then(&self, other: &Self) -> Self {
+        Self {
+            gates: self.gates.iter().chain(&other.gates).copied().collect(),
+            unitary: other.unitary * self.unitary,
+        }
+    }
+
+    /// Simplify the gates, and compare them with the target unitary.
+    fn finish(self, target: &Mat2) -> CliffordTApproximation {
+        let gates = simplify(&self.gates);
+        let unitary = gates.iter().fold(Mat2::IDENTITY, |acc, &op| {
+            Mat2::from_slice(&gate_matrix(op, &[]).unwrap()) * acc
+        });
+        let product = unitary.dagger() * *target;
+        CliffordTApproximation {
+            gates,
+            phase: product.trace().arg(),
+            error: phase_distance(product),
+        }
+    }
+}
+
+fn gate_dagger(op: Tk2Op) -> Tk2Op {
+    match op {
+        Tk2Op::S => Tk2Op::Sdg,
+        Tk2Op::Sdg => Tk2Op::S,
+        Tk2Op::T => Tk2Op::Tdg,
+        Tk2Op::Tdg => Tk2Op::T,
+        op => op,
+    }
+}
+
+/// The power of `T` implementing a diagonal gate exactly.
+fn t_power(op: Tk2Op) -> Option<u8> {
+    match op {
+        Tk2Op::T => Some(1),
+        Tk2Op::S => Some(2),
+        Tk2Op::Z => Some(4),
+        Tk2Op::Sdg => Some(6),
+        Tk2Op::Tdg => Some(7),
+        _ => None,
+    }
+}
+
+/// Merge the consecutive diagonal gates, and cancel pairs of Hadamard gates.
+fn simplify(gates: &[Tk2Op]) -> Vec<Tk2Op> {
+    // Each entry is a Hadamard gate, or a power of T.
+    let mut stack: Vec<Option<u8>> = Vec::new();
+    for &op in gates {
+        match (t_power(op), stack.last_mut()) {
+            (None, Some(None)) => {
+                stack.pop();
+            }
+            (Some(k), Some(Some(power))) => {
+                *power = (*power + k) % 8;
+                if *power == 0 {
+                    stack.pop();
+                }
+            }
+            (power, _) => stack.push(power),
+        }
+    }
+    stack
+        .into_iter()
+        .flat_map(|entry| match entry {
+            None => vec![Tk2Op::H],
+            Some(1) => vec![Tk2Op::T],
+            Some(2) => vec![Tk2Op::S],
+            Some(3) => vec![Tk2Op::S, Tk2Op::T],
+            Some(4) => vec![Tk2Op::Z],
+            Some(5) => vec![Tk2Op::Z, Tk2Op::T],
+            Some(6) => vec![Tk2Op::Sdg],
+            Some(7) => vec![Tk2Op::Tdg],
+            _ => vec![],
+        })
+        .collect()
+}
+
+lazy_static! {
+    /// The Clifford+T unitaries with at most [`BASE_NET_T_COUNT`] T gates.
+    static ref BASE_NET: Vec<Sequence> = base_net();
+}
+
+/// A hashable key identifying a unitary up to a global phase.
+fn phase_key(u: &Mat2) -> [i64; 8] {
+    let pivot = u.0.iter().copied().find(|x| x.norm() > 1e-6).unwrap();
+    let phase = pivot.conj() / pivot.norm();
+    let mut key = [0; 8];
+    for (i, x) in u.0.iter().enumerate() {
+        let x = x * phase;
+        key[2 * i] = (x.re * 1e6).round() as i64;
+        key[2 * i + 1] = (x.im * 1e6).round() as i64;
+    }
+    key
+}
+
+/// Enumerate the base net, as the Matsumoto-Amano normal forms
+/// `[T] (HT | SHT)^k C` for a Clifford `C`.
+fn base_net() -> Vec<Sequence> {
+    let gate = |op: Tk2Op| Sequence {
+        gates: vec![op],
+        unitary: Mat2::from_slice(&gate_matrix(op, &[]).unwrap()).to_su2(),
+    };
+    let [h, s, t] = [Tk2Op::H, Tk2Op::S, Tk2Op::T].map(gate);
+
+    // The single-qubit Clifford group, generated by H and S.
+    let mut cliffords = vec![Sequence::identity()];
+    let mut seen = HashSet::from([phase_key(&Mat2::IDENTITY)]);
+    let mut queue = VecDeque::from([Sequence::identity()]);
+    while let Some(seq) = queue.pop_front() {
+        for gen in [&h, &s] {
+            let next = seq.then(gen);
+            if seen.insert(phase_key(&next.unitary)) {
+                cliffords.push(next.clone());
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let ht = t.then(&h);
+    let sht = ht.then(&s);
+    let mut level = cliffords;
+    let mut net = level.clone();
+    for _ in 0..BASE_NET_T_COUNT - 1 {
+        level = level
+            .iter()
+            .flat_map(|seq| [seq.then(&ht), seq.then(&sht)])
+            .collect();
+        net.extend(level.iter().cloned());
+    }
+    let with_t: Vec<Sequence> = net.iter().map(|seq| seq.then(&t)).collect();
+    net.extend(with_t);
+    net
+}
+
+/// The element of the base net closest to a unitary.
+fn closest_in_net(u: &Mat2) -> Sequence {
+    let u_dagger = u.dagger();
+    BASE_NET
+        .iter()
+        .max_by(|a, b| {
+            let a = (u_dagger * a.unitary).trace().norm();
+            let b = (u_dagger * b.unitary).trace().norm();
+            a.total_cmp(&b)
+        })
+        .unwrap()
+        .clone()
+}
+
+/// Approximate an SU(2) unitary with `depth` Solovay-Kitaev refinements.
+fn solovay_kitaev(u: &Mat2, depth: usize) -> Sequence {
+    if depth == 0 {
+        return closest_in_net(u);
+    }
+    let approx = solovay_kitaev(u, depth - 1);
+    let delta = *u * approx.unitary.dagger();
+    let (v, w) = group_commutator(&delta);
+    let v_approx = solovay_kitaev(&v, depth - 1);
+    let w_approx = solovay_kitaev(&w, depth - 1);
+    // `Δ ≈ V W V† W†`, applied after the previous approximation.
+    approx
+        .then(&w_approx.dagger())
+        .then(&v_approx.dagger())
+        .then(&w_approx)
+        .then(&v_approx)
+}
+
+/// Decompose a rotation close to the identity as a balanced group commutator
+/// `Δ = V W V† W†`.
+fn group_commutator(delta: &Mat2) -> (Mat2, Mat2) {
+    let (theta, axis) = delta.axis_angle();
+    // Rotations by `φ` around the X and Y axes have a commutator rotating by
+    // `θ`, for `sin(θ/2) = 2 sin²(φ/2) sqrt(1 - sin⁴(φ/2))`.
+    let phi = 2. * ((1. - (theta / 2.).cos()) / 2.).powf(0.25).asin();
+    let v = Mat2::rotation(phi, [1., 0., 0.]);
+    let w = Mat2::rotation(phi, [0., 1., 0.]);
+    let commutator = v * w * v.dagger() * w.dagger();
+    let (_, comm_axis) = commutator.axis_angle();
+
+    // Conjugate by the rotation taking the commutator's axis to `Δ`'s axis.
+    let normal = cross(comm_axis, axis);
+    let cos = comm_axis.iter().zip(axis).map(|(a, b)| a * b).sum::<f64>();
+    let similarity = match norm(normal) < 1e-12 {
+        true if cos > 0. => Mat2::IDENTITY,
+        // Opposite axes, rotate by π around any orthogonal axis.
+        true => {
+            let orthogonal = cross(comm_axis, [1., 0., 0.]);
+            let orthogonal = match norm(orthogonal) < 1e-6 {
+                true => cross(comm_axis, [0., 1., 0.]),
+                false => orthogonal,
+            };
+            let n = norm(orthogonal);
+            Mat2::rotation(std::f64::consts::PI, orthogonal.map(|x| x / n))
+        }
+        false => {
+            let n = norm(normal);
+            Mat2::rotation(n.atan2(cos), normal.map(|x| x / n))
+        }
+    };
+    (
+        similarity * v * similarity.dagger(),
+        similarity * w * similarity.dagger(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use ndarray::array;
+    use rstest::rstest;
+
+    use super::*;
+
+    /// The unitary of a sequence of gates.
+    fn unitary(gates: &[Tk2Op]) -> Mat2 {
+        gates.iter().fold(Mat2::IDENTITY, |acc, &op| {
+            Mat2::from_slice(&gate_matrix(op, &[]).unwrap()) * acc
+        })
+    }
+
+    #[test]
+    fn base_net_size() {
+        assert_eq!(BASE_NET.len(), 24 * 2 * ((1 << BASE_NET_T_COUNT) - 1));
+        let distinct: HashSet<_> = BASE_NET.iter().map(|seq| phase_key(&seq.unitary)).collect();
+        assert_eq!(distinct.len(), BASE_NET.len());
+    }
+
+    #[rstest]
+    #[case::exact_t(PI / 4., 1e-10, Some(1))]
+    #[case::exact_s(-PI / 2., 1e-10, Some(0))]
+    #[case::coarse(0.3, 1e-1, None)]
+    #[case::fine(1.234, 1e-3, None)]
+    #[case::finer(-2.5, 1e-5, None)]
+    fn rz(#[case] theta: f64, #[case] epsilon: f64, #[case] t_count: Option<usize>) {
+        let approx = approximate_rz(theta, epsilon).unwrap();
+        assert!(approx.error <= epsilon);
+        if let Some(t_count) = t_count {
+            assert_eq!(approx.t_count(), t_count);
+        }
+        assert!(approx.gates.iter().all(|op| matches!(
+            op,
+            Tk2Op::H | Tk2Op::S | Tk2Op::Sdg | Tk2Op::T | Tk2Op::Tdg | Tk2Op::Z
+        )));
+
+        // The target is `e^{iφ}` times the circuit's unitary.
+        let rz = Mat2::from_slice(&gate_matrix(Tk2Op::RzF64, &[theta]).unwrap());
+        let circ = unitary(&approx.gates);
+        let phase = Complex64::from_polar(1., approx.phase);
+        let diff =
+            rz.0.iter()
+                .zip(circ.0)
+                .map(|(a, b)| (a - b * phase).norm())
+                .fold(0., f64::max);
+        assert!(diff <= 2. * epsilon, "{diff}");
+    }
+
+    #[test]
+    fn unitary_input() {
+        let (s, c) = (0.4f64.sin(), 0.4f64.cos());
+        let u = array![
+            [Complex64::new(c, 0.), Complex64::new(0., s)],
+            [Complex64::new(0., s), Complex64::new(c, 0.)]
+        ];
+        let approx = approximate_unitary(&u, 1e-3).unwrap();
+        assert!(approx.error <= 1e-3);
+
+        let not_unitary = array![
+            [Complex64::new(1., 0.), Complex64::new(1., 0.)],
+            [Complex64::new(0., 0.), Complex64::new(1., 0.)]
+        ];
+        assert_eq!(
+            approximate_unitary(&not_unitary, 1e-3),
+            Err(SynthesisError::NotUnitary)
+        );
+        assert!(matches!(
+            approximate_unitary(&Array2::zeros((4, 4)), 1e-3),
+            Err(SynthesisError::InvalidDimensions { rows: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_precision() {
+        assert_eq!(
+            approximate_rz(0.1, 0.),
+            Err(SynthesisError::InvalidPrecision { epsilon: 0. })
+        );
+        assert!(matches!(
+            approximate_rz(0.1, 1e-300),
+            Err(SynthesisError::PrecisionNotReached { .. })
+        ));
+    }
+
+    #[test]
+    fn simplify_gates() {
+        use Tk2Op::*;
+        assert_eq!(simplify(&[T, T, H, H, S, Tdg, H]), [S, T, H]);
+        assert_eq!(simplify(&[S, S, T, H, Sdg, S]), [Z, T, H]);
+    }
+}
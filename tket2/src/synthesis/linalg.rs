@@ -0,0 +1,374 @@
+//! Dense linear algebra on the small complex matrices used by the synthesis
+//! routines.
+//!
+//! The matrices involved have at most a few dozen entries, so the algorithms
+//! favour robustness over speed: eigendecompositions use cyclic Jacobi
+//! rotations, which remain accurate for degenerate spectra.
+
+use ndarray::{s, Array2};
+use num_complex::Complex64;
+
+/// Relative size of the off-diagonal entries at which the Jacobi iteration
+/// stops.
+const JACOBI_TOLERANCE: f64 = 1e-15;
+
+/// The largest number of Jacobi sweeps.
+const MAX_SWEEPS: usize = 64;
+
+/// Largest off-diagonal entry accepted when diagonalising a normal matrix.
+const DIAGONAL_TOLERANCE: f64 = 1e-9;
+
+/// Singular values below this are treated as zero in the cosine-sine
+/// decomposition.
+const SINGULAR_TOLERANCE: f64 = 1e-12;
+
+/// The conjugate transpose of a matrix.
+pub(super) fn dagger(a: &Array2<Complex64>) -> Array2<Complex64> {
+    a.t().mapv(|x| x.conj())
+}
+
+/// The largest absolute value of an entry of `a - b`.
+pub(super) fn max_distance(a: &Array2<Complex64>, b: &Array2<Complex64>) -> f64 {
+    (a - b).iter().map(|x| x.norm()).fold(0., f64::max)
+}
+
+/// Whether a square matrix is unitary, up to `tol`.
+pub(super) fn is_unitary(u: &Array2<Complex64>, tol: f64) -> bool {
+    max_distance(&dagger(u).dot(u), &Array2::eye(u.nrows())) < tol
+}
+
+/// The determinant of a square matrix, by Gaussian elimination with partial
+/// pivoting.
+pub(super) fn det(a: &Array2<Complex64>) -> Complex64 {
+    let n = a.nrows();
+    let mut a = a.clone();
+    let mut det = Complex64::new(1., 0.);
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&i, &j| a[(i, col)].norm().total_cmp(&a[(j, col)].norm()))
+            .unwrap();
+        if a[(pivot, col)].norm() == 0. {
+            return Complex64::new(0., 0.);
+        }
+        if pivot != col {
+            for k in 0..n {
+                a.swap((pivot, k), (col, k));
+            }
+            det = -det;
+        }
+        det *= a[(col, col)];
+        for row in col + 1..n {
+            let factor = a[(row, col)] / a[(col, col)];
+            for k in col..n {
+                let x = a[(col, k)];
+                a[(row, k)] -= factor * x;
+            }
+        }
+    }
+    det
+}
+
+/// Eigendecomposition `a = V diag(λ) V†` of a Hermitian matrix.
+///
+/// Returns the real eigenvalues and the unitary `V`, whose columns are the
+/// corresponding eigenvectors. If `a` is real, so is `V`.
+pub(super) fn eigh(a: &Array2<Complex64>) -> (Vec<f64>, Array2<Complex64>) {
+    let n = a.nrows();
+    let mut a = a.clone();
+    let mut v = Array2::eye(n);
+    let scale = a.iter().map(|x| x.norm_sqr()).sum::<f64>().sqrt().max(1.);
+    for _ in 0..MAX_SWEEPS {
+        let off_diagonal: f64 = a
+            .indexed_iter()
+            .filter(|((i, j), _)| i != j)
+            .map(|(_, x)| x.norm_sqr())
+            .sum();
+        if off_diagonal.sqrt() <= JACOBI_TOLERANCE * scale {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[(p, q)];
+                if apq.norm() == 0. {
+                    continue;
+                }
+                // Rotate the phase of `q` so that the entry is real, then
+                // apply a real Jacobi rotation zeroing it.
+                let phase = apq.conj() / apq.norm();
+                let theta = (a[(q, q)].re - a[(p, p)].re) / (2. * apq.norm());
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+                let c = 1. / (t * t + 1.).sqrt();
+                let s = t * c;
+                let mut j = Array2::eye(n);
+                j[(p, p)] = Complex64::new(c, 0.);
+                j[(p, q)] = Complex64::new(s, 0.);
+                j[(q, p)] = -s * phase;
+                j[(q, q)] = c * phase;
+                a = dagger(&j).dot(&a).dot(&j);
+                v = v.dot(&j);
+            }
+        }
+    }
+    ((0..n).map(|i| a[(i, i)].re).collect(), v)
+}
+
+/// Eigendecomposition `u = V diag(λ) V†` of a normal matrix, such as a
+/// unitary or a complex symmetric unitary.
+///
+/// The Hermitian and anti-Hermitian parts of `u` commute, so a generic real
+/// combination of them has the same eigenvectors as `u`. A few combinations
+/// are tried in case of accidental degeneracies. If the real and imaginary
+/// parts of `u` are symmetric, `V` is real.
+///
+/// Returns `None` if no combination diagonalises `u`.
+pub(super) fn normal_eig(u: &Array2<Complex64>) -> Option<(Vec<Complex64>, Array2<Complex64>)> {
+    let u_dag = dagger(u);
+    let hermitian = (u + &u_dag).mapv(|x| x / 2.);
+    let anti_hermitian = (u - &u_dag).mapv(|x| x / Complex64::new(0., 2.));
+    (0..8).find_map(|k| {
+        let angle = 0.4 + 0.77 * k as f64;
+        let h = &hermitian * angle.cos() + &anti_hermitian * angle.sin();
+        let (_, v) = eigh(&h);
+        let d = dagger(&v).dot(u).dot(&v);
+        let is_diagonal = d
+            .indexed_iter()
+            .all(|((i, j), x)| i == j || x.norm() < DIAGONAL_TOLERANCE);
+        is_diagonal.then(|| (d.diag().to_vec(), v))
+    })
+}
+
+/// The cosine-sine decomposition of a unitary with an even number of rows.
+///
+/// Splitting `u` into four square blocks, returns the factors such that
+///
+/// ```text
+/// u = diag(l0, l1) · [[C, -S], [S, C]] · diag(r0, r1)
+/// ```
+///
+/// where `C` and `S` are the diagonal matrices of `cos(θ/2)` and `sin(θ/2)`.
+pub(super) fn cosine_sine(u: &Array2<Complex64>) -> CosineSine {
+    let m = u.nrows() / 2;
+    let u00 = u.slice(s![..m, ..m]);
+    let u01 = u.slice(s![..m, m..]);
+    let u10 = u.slice(s![m.., ..m]);
+    let u11 = u.slice(s![m.., m..]);
+
+    // `u00 = l0 C r0` and `u10 = l1 S r0`, from the eigenvectors of `u00† u00`.
+    let (_, q) = eigh(&dagger(&u00.to_owned()).dot(&u00));
+    let cos_cols = u00.dot(&q);
+    let sin_cols = u10.dot(&q);
+    let cos: Vec<f64> = cos_cols.columns().into_iter().map(norm).collect();
+    let sin: Vec<f64> = sin_cols.columns().into_iter().map(norm).collect();
+    let l0 = complete_unitary(&cos_cols, &cos);
+    let l1 = complete_unitary(&sin_cols, &sin);
+    let r0 = dagger(&q);
+
+    // The remaining blocks are `u01 = -l0 S r1` and `u11 = l1 C r1`.
+    let x = dagger(&l0).dot(&u01);
+    let y = dagger(&l1).dot(&u11);
+    let mut r1 = Array2::zeros((m, m));
+    for i in 0..m {
+        let row = &y.row(i) * cos[i] - &x.row(i) * sin[i];
+        r1.row_mut(i).assign(&row);
+    }
+
+    let theta = cos
+        .iter()
+        .zip(&sin)
+        .map(|(c, s)| 2. * s.atan2(*c))
+        .collect();
+    CosineSine {
+        l0,
+        l1,
+        theta,
+        r0,
+        r1,
+    }
+}
+
+/// The factors of a cosine-sine decomposition, see [`cosine_sine`].
+pub(super) struct CosineSine {
+    pub l0: Array2<Complex64>,
+    pub l1: Array2<Complex64>,
+    pub theta: Vec<f64>,
+    pub r0: Array2<Complex64>,
+    pub r1: Array2<Complex64>,
+}
+
+/// Decompose a square matrix as a tensor product `a ⊗ b` of two single-qubit
+/// unitaries with unit determinant, up to a global phase.
+///
+/// In the indexing convention of [`crate::sim::unitary`], `a` acts on qubit 1
+/// and `b` on qubit 0.
+pub(super) fn kron_factor(u: &Array2<Complex64>) -> (Array2<Complex64>, Array2<Complex64>) {
+    let block = |i: usize, j: usize| u.slice(s![2 * i..2 * i + 2, 2 * j..2 * j + 2]).to_owned();
+    let (i, j) = [(0, 0), (0, 1), (1, 0), (1, 1)]
+        .into_iter()
+        .max_by(|&(a, b), &(c, d)| {
+            let norm = |i, j| block(i, j).iter().map(|x| x.norm_sqr()).sum::<f64>();
+            norm(a, b).total_cmp(&norm(c, d))
+        })
+        .unwrap();
+    let b = block(i, j);
+    let b = &b / det(&b).sqrt();
+    let b_dag = dagger(&b);
+    let mut a = Array2::zeros((2, 2));
+    for (k, l) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+        a[(k, l)] = block(k, l).dot(&b_dag).diag().sum() / 2.;
+    }
+    let a = &a / det(&a).sqrt();
+    (a, b)
+}
+
+/// The Euclidean norm of a vector.
+fn norm(v: ndarray::ArrayView1<Complex64>) -> f64 {
+    v.iter().map(|x| x.norm_sqr()).sum::<f64>().sqrt()
+}
+
+/// Normalise the columns of a matrix with the given norms into a unitary.
+///
+/// Columns are orthonormalised in decreasing order of their norms, and those
+/// with a negligible norm are replaced by vectors completing the basis.
+fn complete_unitary(cols: &Array2<Complex64>, norms: &[f64]) -> Array2<Complex64> {
+    let n = cols.nrows();
+    let mut order: Vec<usize> = (0..norms.len()).collect();
+    order.sort_by(|&i, &j| norms[j].total_cmp(&norms[i]));
+    let mut result = Array2::zeros((n, norms.len()));
+    let mut basis: Vec<ndarray::Array1<Complex64>> = Vec::new();
+    let mut candidates = (0..n).map(|k| {
+        let mut e = ndarray::Array1::zeros(n);
+        e[k] = Complex64::new(1., 0.);
+        e
+    });
+    for i in order {
+        let mut v = match norms[i] > SINGULAR_TOLERANCE {
+            true => orthogonalise(cols.column(i).to_owned(), &basis),
+            false => ndarray::Array1::zeros(n),
+        };
+        while norm(v.view()) < 0.5 {
+            v = orthogonalise(candidates.next().unwrap(), &basis);
+        }
+        let v = &v / Complex64::new(norm(v.view()), 0.);
+        result.column_mut(i).assign(&v);
+        basis.push(v);
+    }
+    result
+}
+
+/// Remove the components of `v` along an orthonormal set of vectors, then
+/// normalise it.
+fn orthogonalise(
+    mut v: ndarray::Array1<Complex64>,
+    basis: &[ndarray::Array1<Complex64>],
+) -> ndarray::Array1<Complex64> {
+    let initial = norm(v.view());
+    if initial == 0. {
+        return v;
+    }
+    v /= Complex64::new(initial, 0.);
+    // Two passes of Gram-Schmidt keep the result orthogonal to working
+    // precision.
+    for _ in 0..2 {
+        for b in basis {
+            let overlap: Complex64 = b.iter().zip(&v).map(|(x, y)| x.conj() * y).sum();
+            v = &v - &(b * overlap);
+        }
+    }
+    v
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// A fixed unitary without any particular structure.
+    pub(crate) fn generic_unitary(dim: usize, seed: u64) -> Array2<Complex64> {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let h = Array2::from_shape_fn((dim, dim), |_| {
+            Complex64::new(rng.gen_range(-1.0..1.), rng.gen_range(-1.0..1.))
+        });
+        let h = &h + &dagger(&h);
+        let (eigenvalues, v) = eigh(&h);
+        let d = Array2::from_diag(&ndarray::Array1::from_iter(
+            eigenvalues.iter().map(|x| Complex64::from_polar(1., *x)),
+        ));
+        v.dot(&d).dot(&dagger(&v))
+    }
+
+    #[test]
+    fn hermitian_eigendecomposition() {
+        let u = generic_unitary(4, 1);
+        let h = &u + &dagger(&u);
+        let (eigenvalues, v) = eigh(&h);
+        assert!(is_unitary(&v, 1e-12));
+        let d = Array2::from_diag(&ndarray::Array1::from_iter(
+            eigenvalues.iter().map(|x| Complex64::new(*x, 0.)),
+        ));
+        assert!(max_distance(&v.dot(&d).dot(&dagger(&v)), &h) < 1e-12);
+    }
+
+    #[test]
+    fn degenerate_normal_matrix() {
+        // A unitary with a repeated eigenvalue.
+        let v = generic_unitary(4, 2);
+        let d = Array2::from_diag(&ndarray::arr1(&[
+            Complex64::new(0., 1.),
+            Complex64::new(0., 1.),
+            Complex64::new(-1., 0.),
+            Complex64::new(1., 0.),
+        ]));
+        let u = v.dot(&d).dot(&dagger(&v));
+        let (eigenvalues, w) = normal_eig(&u).unwrap();
+        let d = Array2::from_diag(&ndarray::Array1::from_vec(eigenvalues));
+        assert!(max_distance(&w.dot(&d).dot(&dagger(&w)), &u) < 1e-10);
+    }
+
+    #[rstest::rstest]
+    #[case::generic(generic_unitary(8, 3))]
+    #[case::block_diagonal(Array2::from_diag(&ndarray::arr1(&[Complex64::new(0., 1.); 4])))]
+    #[case::swap(ndarray::array![
+        [0., 0., 1., 0.],
+        [0., 0., 0., 1.],
+        [1., 0., 0., 0.],
+        [0., 1., 0., 0.]
+    ].mapv(|x| Complex64::new(x, 0.)))]
+    fn cosine_sine_decomposition(#[case] u: Array2<Complex64>) {
+        let m = u.nrows() / 2;
+        let CosineSine {
+            l0,
+            l1,
+            theta,
+            r0,
+            r1,
+        } = cosine_sine(&u);
+        for factor in [&l0, &l1, &r0, &r1] {
+            assert!(is_unitary(factor, 1e-10));
+        }
+        let mut left = Array2::zeros((2 * m, 2 * m));
+        left.slice_mut(s![..m, ..m]).assign(&l0);
+        left.slice_mut(s![m.., m..]).assign(&l1);
+        let mut right = Array2::zeros((2 * m, 2 * m));
+        right.slice_mut(s![..m, ..m]).assign(&r0);
+        right.slice_mut(s![m.., m..]).assign(&r1);
+        let mut middle = Array2::zeros((2 * m, 2 * m));
+        for (i, theta) in theta.iter().enumerate() {
+            let (s, c) = (theta / 2.).sin_cos();
+            middle[(i, i)] = Complex64::new(c, 0.);
+            middle[(i + m, i + m)] = Complex64::new(c, 0.);
+            middle[(i, i + m)] = Complex64::new(-s, 0.);
+            middle[(i + m, i)] = Complex64::new(s, 0.);
+        }
+        assert!(max_distance(&left.dot(&middle).dot(&right), &u) < 1e-10);
+    }
+
+    #[test]
+    fn tensor_factors() {
+        let a = generic_unitary(2, 4);
+        let b = generic_unitary(2, 5);
+        let u = ndarray::linalg::kron(&a, &b);
+        let (x, y) = kron_factor(&u);
+        let v = ndarray::linalg::kron(&x, &y);
+        assert!(crate::sim::unitary::equal_up_to_phase(&u, &v, 1e-12));
+    }
+}
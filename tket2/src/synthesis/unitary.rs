@@ -0,0 +1,561 @@
+//! Exact synthesis of circuits implementing small unitaries.
+//!
+//! [`synthesize_unitary`] decomposes a unitary on up to
+//! [`MAX_SYNTHESIS_QUBITS`] qubits into single-qubit rotations and a
+//! two-qubit [`Entangler`].
+//!
+//! Two-qubit unitaries use the KAK decomposition `U = (A₁ ⊗ B₁) · N · (A₂ ⊗
+//! B₂)`, where `N = exp(i(a XX + b YY + c ZZ))` is implemented with at most
+//! three entangling gates. Larger unitaries use the quantum Shannon
+//! decomposition: a cosine-sine decomposition splits the unitary into
+//! multiplexed operations on the other qubits, controlled by the last one,
+//! which are in turn decomposed into uniformly controlled rotations and
+//! unitaries on one qubit less.
+//!
+//! Adjacent single-qubit gates are merged and resynthesised in the requested
+//! [`EulerBasis`]. The global phase of the circuit is set so that its unitary
+//! equals the input matrix exactly.
+
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use hugr::builder::{BuildError, CircuitBuilder, DFGBuilder, Dataflow, DataflowHugr};
+use hugr::extension::prelude::QB_T;
+use hugr::std_extensions::arithmetic::float_types::{self, ConstF64};
+use hugr::types::Signature;
+use hugr::{CircuitUnit, Hugr};
+use itertools::Itertools;
+use ndarray::{Array1, Array2};
+use num_complex::Complex64;
+
+use super::linalg::{cosine_sine, dagger, det, is_unitary, kron_factor, normal_eig, CosineSine};
+use super::{SynthesisError, UNITARY_TOLERANCE};
+use crate::circuit::phase::GlobalPhase;
+use crate::extension::REGISTRY;
+use crate::passes::squash::{euler_rotations, EulerBasis};
+use crate::sim::gate_matrix;
+use crate::sim::unitary::{equal_up_to_phase, relative_phase};
+use crate::{Circuit, Pauli, Tk2Op};
+
+/// The largest number of qubits supported by [`synthesize_unitary`].
+pub const MAX_SYNTHESIS_QUBITS: usize = 3;
+
+/// Largest entry-wise error accepted between the input and the synthesised
+/// circuit.
+const VERIFICATION_TOLERANCE: f64 = 1e-7;
+
+/// Distance to a multiple of π/2 under which an interaction coefficient is
+/// considered local.
+const LOCAL_TOLERANCE: f64 = 1e-9;
+
+/// The two-qubit gate used by [`synthesize_unitary`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum Entangler {
+    /// [`Tk2Op::CX`].
+    #[default]
+    CX,
+    /// [`Tk2Op::CZ`].
+    CZ,
+}
+
+impl Entangler {
+    /// The operation implementing the entangler.
+    pub fn op(self) -> Tk2Op {
+        match self {
+            Entangler::CX => Tk2Op::CX,
+            Entangler::CZ => Tk2Op::CZ,
+        }
+    }
+}
+
+/// The gate set targeted by [`synthesize_unitary`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SynthesisBasis {
+    /// The two-qubit gate.
+    pub entangler: Entangler,
+    /// The axes of the single-qubit rotations.
+    pub euler: EulerBasis,
+}
+
+impl SynthesisBasis {
+    /// Create a new synthesis basis.
+    pub fn new(entangler: Entangler, euler: EulerBasis) -> Self {
+        Self { entangler, euler }
+    }
+}
+
+/// Synthesise a circuit implementing a unitary on up to
+/// [`MAX_SYNTHESIS_QUBITS`] qubits.
+///
+/// The matrix is indexed as in [`crate::sim::unitary`], with qubit `i` of the
+/// circuit corresponding to the `i`-th least significant bit of the row and
+/// column indices. The circuit only contains gates of `basis`, with constant
+/// angles, and its global phase is set so that its unitary equals `matrix`.
+///
+/// Two-qubit unitaries use at most three entangling gates, and three-qubit
+/// unitaries at most 24.
+///
+/// # Errors
+///
+/// Returns an error if the matrix is not a square unitary matrix on between
+/// one and [`MAX_SYNTHESIS_QUBITS`] qubits.
+pub fn synthesize_unitary(
+    matrix: &Array2<Complex64>,
+    basis: SynthesisBasis,
+) -> Result<Circuit, SynthesisError> {
+    let (rows, cols) = matrix.dim();
+    if rows != cols || rows < 2 || !rows.is_power_of_two() {
+        return Err(SynthesisError::InvalidDimensions {
+            expected: rows.max(2).next_power_of_two(),
+            rows,
+            cols,
+        });
+    }
+    let num_qubits = rows.trailing_zeros() as usize;
+    if num_qubits > MAX_SYNTHESIS_QUBITS {
+        return Err(SynthesisError::TooManyQubits {
+            qubits: num_qubits,
+            max: MAX_SYNTHESIS_QUBITS,
+        });
+    }
+    if !is_unitary(matrix, UNITARY_TOLERANCE) {
+        return Err(SynthesisError::NotUnitary);
+    }
+
+    let mut instructions = Vec::new();
+    decompose(matrix, &mut instructions)?;
+    let mut circ = build_circuit(num_qubits, &instructions, basis);
+    let u = circ
+        .unitary()
+        .expect("Synthesised circuits only contain unitary gates.");
+    if !equal_up_to_phase(matrix, &u, VERIFICATION_TOLERANCE) {
+        return Err(SynthesisError::DecompositionFailed);
+    }
+    circ.set_global_phase(Some(GlobalPhase::new(relative_phase(matrix, &u) / PI)));
+    Ok(circ)
+}
+
+/// A gate of a decomposition, before resynthesis in the target basis.
+#[derive(Debug, Clone)]
+enum Instruction {
+    /// A single-qubit unitary.
+    Single(usize, Array2<Complex64>),
+    /// A CX gate, with its control and target qubits.
+    Cx(usize, usize),
+}
+
+/// Decompose a unitary on the first qubits, appending the gates to `out`.
+fn decompose(u: &Array2<Complex64>, out: &mut Vec<Instruction>) -> Result<(), SynthesisError> {
+    match u.nrows() {
+        2 => out.push(Instruction::Single(0, u.clone())),
+        4 => decompose_two_qubit(u, out)?,
+        _ => decompose_shannon(u, out)?,
+    }
+    Ok(())
+}
+
+/// The quantum Shannon decomposition of a unitary on three or more qubits.
+fn decompose_shannon(
+    u: &Array2<Complex64>,
+    out: &mut Vec<Instruction>,
+) -> Result<(), SynthesisError> {
+    let target = u.nrows().trailing_zeros() as usize - 1;
+    let CosineSine {
+        l0,
+        l1,
+        theta,
+        r0,
+        r1,
+    } = cosine_sine(u);
+    decompose_multiplexed(&r0, &r1, out)?;
+    multiplexed_rotation(Pauli::Y, target, &theta, out);
+    decompose_multiplexed(&l0, &l1, out)
+}
+
+/// Decompose the block-diagonal unitary `diag(a, b)`, applying `a` or `b` to
+/// the lower qubits depending on the state of the next one.
+///
+/// With `a b† = V D² V†`, the unitary is `(I ⊗ V) · diag(D, D†) · (I ⊗ W)`
+/// where `W = D V† b`, and the middle factor is a multiplexed Z rotation.
+fn decompose_multiplexed(
+    a: &Array2<Complex64>,
+    b: &Array2<Complex64>,
+    out: &mut Vec<Instruction>,
+) -> Result<(), SynthesisError> {
+    let target = a.nrows().trailing_zeros() as usize;
+    let (eigenvalues, v) =
+        normal_eig(&a.dot(&dagger(b))).ok_or(SynthesisError::DecompositionFailed)?;
+    let half_phases: Vec<f64> = eigenvalues.iter().map(|x| x.arg() / 2.).collect();
+    let d = Array1::from_iter(half_phases.iter().map(|&x| Complex64::from_polar(1., x)));
+    let w = Array2::from_diag(&d).dot(&dagger(&v)).dot(b);
+
+    decompose(&w, out)?;
+    let angles: Vec<f64> = half_phases.iter().map(|x| -2. * x).collect();
+    multiplexed_rotation(Pauli::Z, target, &angles, out);
+    decompose(&v, out)
+}
+
+/// Append a uniformly controlled rotation of `target` around `axis`,
+/// controlled by all the lower qubits.
+///
+/// The rotation angle for the basis state `j` of the controls is `angles[j]`.
+/// The rotations are implemented with one CX per angle, following a Gray code
+/// over the controls.
+fn multiplexed_rotation(axis: Pauli, target: usize, angles: &[f64], out: &mut Vec<Instruction>) {
+    let len = angles.len();
+    if len == 1 {
+        out.push(Instruction::Single(target, rotation(axis, angles[0])));
+        return;
+    }
+    let gray = |i: usize| i ^ (i >> 1);
+    for i in 0..len {
+        // The angle for control state `j` is the sum of the rotations, negated
+        // by the CX gates whose controls are set in `j`.
+        let angle = (0..len)
+            .map(|j| match (j & gray(i)).count_ones() % 2 {
+                0 => angles[j],
+                _ => -angles[j],
+            })
+            .sum::<f64>()
+            / len as f64;
+        out.push(Instruction::Single(target, rotation(axis, angle)));
+        let control = (gray(i) ^ gray((i + 1) % len)).trailing_zeros() as usize;
+        out.push(Instruction::Cx(control, target));
+    }
+}
+
+/// The KAK decomposition of a two-qubit unitary.
+fn decompose_two_qubit(
+    u: &Array2<Complex64>,
+    out: &mut Vec<Instruction>,
+) -> Result<(), SynthesisError> {
+    let u = u / det(u).sqrt().sqrt();
+    let magic = magic_basis();
+    let magic_dag = dagger(&magic);
+    let u_magic = magic_dag.dot(&u).dot(&magic);
+
+    // Local unitaries are real orthogonal in the magic basis, and the
+    // interaction is diagonal. Diagonalise `Uᵀ U = P D² Pᵀ` with a real
+    // orthogonal `P`, so that `U = K₁ D Pᵀ` with `K₁ = U P D⁻¹`.
+    let symmetric = u_magic.t().dot(&u_magic);
+    // Remove rounding errors, so that the eigenvectors are exactly real.
+    let symmetric = (&symmetric + &symmetric.t()).mapv(|x| x / 2.);
+    let (eigenvalues, p) = normal_eig(&symmetric).ok_or(SynthesisError::DecompositionFailed)?;
+    let mut p = p.mapv(|x| Complex64::new(x.re, 0.));
+    if det(&p).re < 0. {
+        p.column_mut(0).mapv_inplace(|x| -x);
+    }
+    let mut phases: Vec<f64> = eigenvalues.iter().map(|x| x.arg() / 2.).collect();
+    let d_inv = Array1::from_iter(phases.iter().map(|&x| Complex64::from_polar(1., -x)));
+    let mut k1 = u_magic.dot(&p).dot(&Array2::from_diag(&d_inv));
+    if det(&k1).re < 0. {
+        phases[0] += PI;
+        k1.column_mut(0).mapv_inplace(|x| -x);
+    }
+    let k1 = magic.dot(&k1).dot(&magic_dag);
+    let k2 = magic.dot(&p.t()).dot(&magic_dag);
+
+    // Solve `phases[k] = λ + a x[k] + b y[k] + c z[k]` for the interaction
+    // coefficients, where `x`, `y` and `z` are the eigenvalues of `XX`, `YY`
+    // and `ZZ` in the magic basis.
+    let coefficients = [Tk2Op::X, Tk2Op::Y, Tk2Op::Z].map(|pauli| {
+        let p = matrix(pauli, &[]);
+        let eigenvalues = magic_dag.dot(&ndarray::linalg::kron(&p, &p)).dot(&magic);
+        (0..4)
+            .map(|k| phases[k] * eigenvalues[(k, k)].re)
+            .sum::<f64>()
+            / 4.
+    });
+
+    if coefficients
+        .iter()
+        .all(|&x| local_offset(x) < LOCAL_TOLERANCE)
+    {
+        let (a, b) = kron_factor(&u);
+        out.push(Instruction::Single(1, a));
+        out.push(Instruction::Single(0, b));
+        return Ok(());
+    }
+    let (a2, b2) = kron_factor(&k2);
+    let (a1, b1) = kron_factor(&k1);
+    out.push(Instruction::Single(1, a2));
+    out.push(Instruction::Single(0, b2));
+    interaction(coefficients, out);
+    out.push(Instruction::Single(1, a1));
+    out.push(Instruction::Single(0, b1));
+    Ok(())
+}
+
+/// Append a circuit implementing `exp(i(a XX + b YY + c ZZ))` up to a global
+/// phase, with qubit 1 as the first tensor factor.
+///
+/// Three CX gates are used in general, and two if one of the coefficients is
+/// a multiple of π/2.
+fn interaction(coefficients: [f64; 3], out: &mut Vec<Instruction>) {
+    // Conjugating by local Cliffords permutes the coefficients. Move the one
+    // closest to a local interaction to the `YY` term.
+    let closest = (0..3)
+        .min_by(|&i, &j| local_offset(coefficients[i]).total_cmp(&local_offset(coefficients[j])))
+        .unwrap();
+    let [a, b, c] = coefficients;
+    let (clifford, [a, b, c]) = match closest {
+        // `S` maps `XX` to `YY` and `YY` to `XX`.
+        0 => (Some(matrix(Tk2Op::S, &[])), [b, a, c]),
+        // `Rx(π/2)` maps `YY` to `ZZ` and `ZZ` to `YY`.
+        2 => (Some(rotation(Pauli::X, FRAC_PI_2)), [a, c, b]),
+        _ => (None, [a, b, c]),
+    };
+    if let Some(clifford) = &clifford {
+        for q in [0, 1] {
+            out.push(Instruction::Single(q, dagger(clifford)));
+        }
+    }
+
+    let single = |q, op, params: &[f64]| Instruction::Single(q, matrix(op, params));
+    if local_offset(b) < LOCAL_TOLERANCE {
+        // `N = CX · exp(ia XI) exp(ic IZ) exp(-ib XZ) · CX`, where the last
+        // factor is local.
+        out.push(Instruction::Cx(1, 0));
+        if (b / FRAC_PI_2).round().rem_euclid(2.) != 0. {
+            out.push(single(1, Tk2Op::X, &[]));
+            out.push(single(0, Tk2Op::Z, &[]));
+        }
+    } else {
+        // `N = CX · exp(ia XI) exp(ic IZ) · CZ · exp(-ib XI) · CZ · CX`, where
+        // `CZ · CX = (S ⊗ S) · CX · (I ⊗ S†)`.
+        out.extend([
+            single(0, Tk2Op::Sdg, &[]),
+            Instruction::Cx(1, 0),
+            single(0, Tk2Op::S, &[]),
+            single(1, Tk2Op::S, &[]),
+            single(1, Tk2Op::RxF64, &[2. * b]),
+            single(0, Tk2Op::H, &[]),
+            Instruction::Cx(1, 0),
+            single(0, Tk2Op::H, &[]),
+        ]);
+    }
+    out.extend([
+        single(1, Tk2Op::RxF64, &[-2. * a]),
+        single(0, Tk2Op::RzF64, &[-2. * c]),
+        Instruction::Cx(1, 0),
+    ]);
+
+    if let Some(clifford) = clifford {
+        for q in [0, 1] {
+            out.push(Instruction::Single(q, clifford.clone()));
+        }
+    }
+}
+
+/// The distance from an interaction coefficient to the closest multiple of
+/// π/2, for which the interaction is local.
+fn local_offset(x: f64) -> f64 {
+    (x - FRAC_PI_2 * (x / FRAC_PI_2).round()).abs()
+}
+
+/// The magic basis, in which tensor products of single-qubit unitaries with
+/// unit determinant are real orthogonal matrices, and the `XX`, `YY` and `ZZ`
+/// interactions are diagonal.
+fn magic_basis() -> Array2<Complex64> {
+    let (o, l, i) = (
+        Complex64::new(0., 0.),
+        Complex64::new(1., 0.),
+        Complex64::new(0., 1.),
+    );
+    ndarray::array![[l, i, o, o], [o, o, i, l], [o, o, i, -l], [l, -i, o, o]]
+        .mapv(|x| x * std::f64::consts::FRAC_1_SQRT_2)
+}
+
+/// The matrix of a single-qubit gate.
+fn matrix(op: Tk2Op, params: &[f64]) -> Array2<Complex64> {
+    Array2::from_shape_vec((2, 2), gate_matrix(op, params).unwrap()).unwrap()
+}
+
+/// The matrix of a rotation around a Pauli axis.
+fn rotation(axis: Pauli, angle: f64) -> Array2<Complex64> {
+    match axis {
+        Pauli::I => Array2::eye(2),
+        Pauli::X => matrix(Tk2Op::RxF64, &[angle]),
+        Pauli::Y => matrix(Tk2Op::PhasedX, &[angle, FRAC_PI_2]),
+        Pauli::Z => matrix(Tk2Op::RzF64, &[angle]),
+    }
+}
+
+/// Build a circuit from the gates of a decomposition, merging adjacent
+/// single-qubit gates and resynthesising them in the target basis.
+fn build_circuit(
+    num_qubits: usize,
+    instructions: &[Instruction],
+    basis: SynthesisBasis,
+) -> Circuit {
+    let build = || -> Result<Hugr, BuildError> {
+        let signature = Signature::new(vec![QB_T; num_qubits], vec![QB_T; num_qubits])
+            .with_extension_delta(float_types::EXTENSION_ID);
+        let mut h = DFGBuilder::new(signature)?;
+        let inputs = h.input_wires().collect_vec();
+        let mut circ = h.as_circuit(inputs);
+
+        let hadamard = matrix(Tk2Op::H, &[]);
+        let mut pending: Vec<Array2<Complex64>> = vec![Array2::eye(2); num_qubits];
+        for instruction in instructions {
+            match instruction {
+                Instruction::Single(q, u) => pending[*q] = u.dot(&pending[*q]),
+                Instruction::Cx(control, target) => {
+                    if basis.entangler == Entangler::CZ {
+                        pending[*target] = hadamard.dot(&pending[*target]);
+                    }
+                    for q in [*control, *target] {
+                        flush(&mut circ, q, &mut pending[q], basis.euler)?;
+                    }
+                    circ.append(basis.entangler.op(), [*control, *target])?;
+                    if basis.entangler == Entangler::CZ {
+                        pending[*target] = hadamard.clone();
+                    }
+                }
+            }
+        }
+        for (q, u) in pending.iter_mut().enumerate() {
+            flush(&mut circ, q, u, basis.euler)?;
+        }
+
+        let outputs = circ.finish();
+        h.finish_hugr_with_outputs(outputs, &REGISTRY)
+    };
+    build().expect("Synthesised circuits are valid.").into()
+}
+
+/// Append the rotations implementing a pending single-qubit unitary, and
+/// reset it to the identity.
+fn flush(
+    circ: &mut CircuitBuilder<DFGBuilder<Hugr>>,
+    qubit: usize,
+    u: &mut Array2<Complex64>,
+    basis: EulerBasis,
+) -> Result<(), BuildError> {
+    let entries = u.iter().copied().collect_vec();
+    for (op, params) in euler_rotations(&entries, basis) {
+        let params = params
+            .into_iter()
+            .map(|angle| CircuitUnit::Wire(circ.add_constant(ConstF64::new(angle))))
+            .collect_vec();
+        circ.append_and_consume(op, [CircuitUnit::Linear(qubit)].into_iter().chain(params))?;
+    }
+    *u = Array2::eye(2);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::synthesis::linalg::test::generic_unitary;
+
+    /// The unitary of a circuit, including its global phase.
+    fn circuit_unitary(circ: &Circuit) -> Array2<Complex64> {
+        let phase = circ.global_phase().unwrap().constant() * PI;
+        circ.unitary()
+            .unwrap()
+            .mapv(|x| x * Complex64::from_polar(1., phase))
+    }
+
+    fn count(circ: &Circuit, op: Tk2Op) -> usize {
+        circ.commands()
+            .filter(|cmd| Tk2Op::try_from(cmd.optype()) == Ok(op))
+            .count()
+    }
+
+    fn gate(op: Tk2Op) -> Array2<Complex64> {
+        let dim = match op {
+            Tk2Op::CCX | Tk2Op::CCZ => 8,
+            _ => 4,
+        };
+        Array2::from_shape_vec((dim, dim), gate_matrix(op, &[]).unwrap()).unwrap()
+    }
+
+    #[rstest]
+    #[case::single_qubit(generic_unitary(2, 10), 0)]
+    #[case::local(ndarray::linalg::kron(&generic_unitary(2, 11), &generic_unitary(2, 12)), 0)]
+    #[case::cx(gate(Tk2Op::CX), 2)]
+    #[case::cz(gate(Tk2Op::CZ), 2)]
+    #[case::swap(ndarray::array![
+        [1., 0., 0., 0.],
+        [0., 0., 1., 0.],
+        [0., 1., 0., 0.],
+        [0., 0., 0., 1.]
+    ].mapv(|x| Complex64::new(x, 0.)), 3)]
+    #[case::two_qubit(generic_unitary(4, 13), 3)]
+    #[case::toffoli(gate(Tk2Op::CCX), 24)]
+    #[case::three_qubit(generic_unitary(8, 14), 24)]
+    fn synthesise(#[case] u: Array2<Complex64>, #[case] max_entanglers: usize) {
+        for basis in [
+            SynthesisBasis::default(),
+            SynthesisBasis::new(Entangler::CZ, EulerBasis::ZYZ),
+        ] {
+            let circ = synthesize_unitary(&u, basis).unwrap();
+            let diff = (circuit_unitary(&circ) - &u)
+                .iter()
+                .map(|x| x.norm())
+                .fold(0., f64::max);
+            assert!(diff < 1e-8, "{diff}");
+            let other = match basis.entangler {
+                Entangler::CX => Tk2Op::CZ,
+                Entangler::CZ => Tk2Op::CX,
+            };
+            assert_eq!(count(&circ, other), 0);
+            assert!(count(&circ, basis.entangler.op()) <= max_entanglers);
+        }
+    }
+
+    #[rstest]
+    #[case::xx([0.3, 0., 0.])]
+    #[case::yy([0., -0.7, 0.])]
+    #[case::zz([FRAC_PI_2, 0., 1.1])]
+    #[case::generic([0.4, 0.25, -0.1])]
+    fn interaction_circuit(#[case] coefficients: [f64; 3]) {
+        let mut instructions = Vec::new();
+        interaction(coefficients, &mut instructions);
+        let circ = build_circuit(2, &instructions, SynthesisBasis::default());
+
+        let pauli = |op| {
+            let p = matrix(op, &[]);
+            ndarray::linalg::kron(&p, &p)
+        };
+        let generator = [Tk2Op::X, Tk2Op::Y, Tk2Op::Z]
+            .into_iter()
+            .zip(coefficients)
+            .fold(Array2::zeros((4, 4)), |acc, (op, x)| acc + pauli(op) * x);
+        // The generator is a combination of commuting Paulis, so its
+        // exponential is diagonal in their joint eigenbasis.
+        let magic = magic_basis();
+        let diagonal = dagger(&magic).dot(&generator).dot(&magic);
+        let exp = Array1::from_iter((0..4).map(|k| Complex64::from_polar(1., diagonal[(k, k)].re)));
+        let expected = magic.dot(&Array2::from_diag(&exp)).dot(&dagger(&magic));
+        assert!(equal_up_to_phase(
+            &circ.unitary().unwrap(),
+            &expected,
+            1e-10
+        ));
+        let cx = count(&circ, Tk2Op::CX);
+        assert!(cx <= 3);
+    }
+
+    #[test]
+    fn invalid_inputs() {
+        let not_square = Array2::zeros((2, 4));
+        assert!(matches!(
+            synthesize_unitary(&not_square, SynthesisBasis::default()),
+            Err(SynthesisError::InvalidDimensions { expected: 2, .. })
+        ));
+        let too_large = generic_unitary(16, 0);
+        assert!(matches!(
+            synthesize_unitary(&too_large, SynthesisBasis::default()),
+            Err(SynthesisError::TooManyQubits { qubits: 4, max: 3 })
+        ));
+        let not_unitary = Array2::ones((4, 4));
+        assert!(matches!(
+            synthesize_unitary(&not_unitary, SynthesisBasis::default()),
+            Err(SynthesisError::NotUnitary)
+        ));
+    }
+}
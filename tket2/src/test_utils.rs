@@ -0,0 +1,423 @@
+//! Helpers for golden-file regression tests of passes.
+//!
+//! [`circuit_snapshot`] renders a circuit in a canonical text form, listing
+//! its operations layer by layer. The form does not depend on the node indices
+//! of the HUGR, or on the order in which independent operations were added, so
+//! equivalent constructions of a circuit give the same snapshot.
+//!
+//! [`assert_golden`] compares a text with the contents of a golden file, and
+//! panics with a line diff when they differ. Running the tests with the
+//! [`UPDATE_GOLDEN_ENV`] environment variable set writes the texts to their
+//! golden files instead, to create or update them.
+//!
+//! ```no_run
+//! use tket2::circuit::generators::qft;
+//! use tket2::passes::{squash_single_qubit_gates, EulerBasis};
+//! use tket2::test_utils::assert_circuit_golden;
+//!
+//! let mut circ = qft(3);
+//! squash_single_qubit_gates(&mut circ, EulerBasis::ZXZ);
+//! assert_circuit_golden("tests/golden/qft_squashed.txt", &circ);
+//! ```
+//!
+//! This module requires the `test-utils` feature.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
+
+use hugr::std_extensions::arithmetic::float_types::FLOAT64_TYPE;
+use hugr::{CircuitUnit, Direction, HugrView, PortIndex, Wire};
+use itertools::Itertools;
+
+use crate::circuit::text_diagram::{format_angle, op_name};
+use crate::circuit::Command;
+use crate::ops::match_symbolic_expr;
+use crate::utils::float_wire_value;
+use crate::Circuit;
+
+/// Environment variable that makes [`assert_golden`] write the golden files
+/// instead of comparing against them.
+pub const UPDATE_GOLDEN_ENV: &str = "TKET2_UPDATE_GOLDEN";
+
+/// The number of unchanged lines shown around each change by [`text_diff`].
+const DIFF_CONTEXT: usize = 3;
+
+/// Render a circuit in a canonical text form.
+///
+/// The snapshot starts with the number of qubits and the global phase of the
+/// circuit, followed by a line per operation. Operations are sorted into
+/// layers, each operation being in the layer after the last operation it
+/// depends on, and sorted by their first qubit within a layer.
+///
+/// Each line shows the name of the operation, its angle parameters in
+/// multiples of π, and its qubits. Classical values produced by operations are
+/// named `c0`, `c1`, … in order of appearance, and classical inputs of the
+/// circuit `in0`, `in1`, … after their input port. Angles that are neither
+/// constant nor symbolic are shown as `?`, and the operations computing them
+/// are not listed.
+///
+/// ```text
+/// qubits: 2
+/// phase: 0
+/// H q0
+/// CX q0, q1
+/// Rz(0.5π) q1
+/// Measure q1 -> c0
+/// ```
+pub fn circuit_snapshot(circ: &Circuit<impl HugrView>) -> String {
+    let mut lines = vec![format!("qubits: {}", circ.qubit_count())];
+    let phase = match circ.global_phase() {
+        None => "unknown".to_string(),
+        Some(phase) if phase.is_symbolic() => format!("({phase})π"),
+        Some(phase) => format_angle(Some(phase.constant() * PI)),
+    };
+    lines.push(format!("phase: {phase}"));
+
+    // The layer following the last operation on each qubit and classical wire.
+    let mut qubit_layers: HashMap<usize, usize> = HashMap::new();
+    let mut wire_layers: HashMap<Wire, usize> = HashMap::new();
+    let mut layers: Vec<Vec<Command<'_, _>>> = Vec::new();
+    for cmd in circ.commands() {
+        let qubits = cmd_qubits(&cmd);
+        let outputs = classical_wires(&cmd, Direction::Outgoing);
+        if qubits.is_empty() && outputs.is_empty() {
+            continue;
+        }
+        let layer = qubits
+            .iter()
+            .filter_map(|q| qubit_layers.get(q))
+            .chain(
+                classical_wires(&cmd, Direction::Incoming)
+                    .iter()
+                    .filter_map(|w| wire_layers.get(w)),
+            )
+            .max()
+            .copied()
+            .unwrap_or(0);
+        qubit_layers.extend(qubits.iter().map(|&q| (q, layer + 1)));
+        wire_layers.extend(outputs.iter().map(|&w| (w, layer + 1)));
+        if layers.len() <= layer {
+            layers.resize_with(layer + 1, Vec::new);
+        }
+        layers[layer].push(cmd);
+    }
+
+    let mut names: HashMap<Wire, String> = HashMap::new();
+    for layer in layers {
+        let layer = layer
+            .into_iter()
+            .map(|cmd| {
+                let first_qubit = cmd_qubits(&cmd).into_iter().min().unwrap_or(usize::MAX);
+                (first_qubit, command_body(circ, &cmd, &names), cmd)
+            })
+            .sorted_by(|(q1, b1, _), (q2, b2, _)| (q1, b1).cmp(&(q2, b2)));
+        for (_, body, cmd) in layer {
+            let outputs = classical_wires(&cmd, Direction::Outgoing)
+                .into_iter()
+                .map(|wire| {
+                    let name = format!("c{}", names.len());
+                    names.insert(wire, name.clone());
+                    name
+                })
+                .collect_vec();
+            match outputs.is_empty() {
+                true => lines.push(body),
+                false => lines.push(format!("{body} -> {}", outputs.join(", "))),
+            }
+        }
+    }
+    lines.into_iter().map(|line| line + "\n").collect()
+}
+
+/// Compare a text with the contents of a golden file.
+///
+/// If the [`UPDATE_GOLDEN_ENV`] environment variable is set, the text is
+/// written to the golden file instead, creating its parent directories if
+/// needed. Line endings are normalised before the comparison.
+///
+/// # Panics
+///
+/// Panics with a line diff if the text differs from the golden file, or if the
+/// golden file cannot be read.
+#[track_caller]
+pub fn assert_golden(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+    if std::env::var_os(UPDATE_GOLDEN_ENV).is_some() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(path, actual)
+            .unwrap_or_else(|e| panic!("Unable to write golden file {}: {e}", path.display()));
+        return;
+    }
+    let expected = fs::read_to_string(path).unwrap_or_else(|e| {
+        panic!(
+            "Unable to read golden file {}: {e}\nSet {UPDATE_GOLDEN_ENV}=1 to create it.",
+            path.display()
+        )
+    });
+    let expected = expected.replace("\r\n", "\n");
+    if let Some(diff) = text_diff(&expected, &actual.replace("\r\n", "\n")) {
+        panic!(
+            "Output differs from golden file {}:\n{diff}Set {UPDATE_GOLDEN_ENV}=1 to update it.",
+            path.display()
+        );
+    }
+}
+
+/// Compare the [snapshot](circuit_snapshot) of a circuit with the contents of
+/// a golden file.
+///
+/// See [`assert_golden`].
+#[track_caller]
+pub fn assert_circuit_golden(path: impl AsRef<Path>, circ: &Circuit<impl HugrView>) {
+    assert_golden(path, &circuit_snapshot(circ));
+}
+
+/// A line diff between two texts, in the unified diff format.
+///
+/// Returns `None` if the texts are equal.
+pub fn text_diff(expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let old = expected.lines().collect_vec();
+    let new = actual.lines().collect_vec();
+
+    // The length of the longest common subsequence of each pair of suffixes.
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+    // The edits, along with the line numbers in both texts before each edit.
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        let edit = if i < old.len() && j < new.len() && old[i] == new[j] {
+            (' ', old[i])
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ('-', old[i])
+        } else {
+            ('+', new[j])
+        };
+        edits.push((edit, i, j));
+        match edit.0 {
+            ' ' => (i, j) = (i + 1, j + 1),
+            '-' => i += 1,
+            _ => j += 1,
+        }
+    }
+
+    let changes = edits
+        .iter()
+        .positions(|((kind, _), _, _)| *kind != ' ')
+        .collect_vec();
+    if changes.is_empty() {
+        return Some("(the texts only differ in their line endings)\n".to_string());
+    }
+    // Group the changes into hunks, with some context around each change.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for change in changes {
+        let start = change.saturating_sub(DIFF_CONTEXT);
+        let end = (change + DIFF_CONTEXT + 1).min(edits.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    let mut diff = String::new();
+    for (start, end) in hunks {
+        let hunk = &edits[start..end];
+        let old_count = hunk.iter().filter(|((kind, _), _, _)| *kind != '+').count();
+        let new_count = hunk.iter().filter(|((kind, _), _, _)| *kind != '-').count();
+        let (_, old_start, new_start) = hunk[0];
+        diff += &format!(
+            "@@ -{},{old_count} +{},{new_count} @@\n",
+            old_start + 1,
+            new_start + 1
+        );
+        for ((kind, line), _, _) in hunk {
+            diff += &format!("{kind}{line}\n");
+        }
+    }
+    Some(diff)
+}
+
+/// The indices of the qubits of a command.
+fn cmd_qubits<T: HugrView>(cmd: &Command<'_, T>) -> Vec<usize> {
+    cmd.linear_units(Direction::Incoming)
+        .chain(cmd.linear_units(Direction::Outgoing))
+        .map(|(unit, _, _)| unit.index())
+        .unique()
+        .collect()
+}
+
+/// The non-linear wires of a command in a direction, excluding angles.
+fn classical_wires<T: HugrView>(cmd: &Command<'_, T>, direction: Direction) -> Vec<Wire> {
+    cmd.units(direction)
+        .filter(|(_, _, ty)| ty != &FLOAT64_TYPE)
+        .filter_map(|(unit, _, _)| match unit {
+            CircuitUnit::Wire(wire) => Some(wire),
+            CircuitUnit::Linear(_) => None,
+        })
+        .collect()
+}
+
+/// The text of a command, without its outputs.
+fn command_body<T: HugrView>(
+    circ: &Circuit<T>,
+    cmd: &Command<'_, T>,
+    names: &HashMap<Wire, String>,
+) -> String {
+    let mut params = Vec::new();
+    let mut args = Vec::new();
+    for (unit, _, ty) in cmd.inputs() {
+        match unit {
+            CircuitUnit::Linear(unit) => args.push(format!("q{unit}")),
+            CircuitUnit::Wire(wire) if ty == FLOAT64_TYPE => {
+                params.push(param_label(circ.hugr(), wire))
+            }
+            CircuitUnit::Wire(wire) => args.push(match names.get(&wire) {
+                Some(name) => name.clone(),
+                None if wire.node() == circ.input_node() => format!("in{}", wire.source().index()),
+                None => "?".to_string(),
+            }),
+        }
+    }
+    // Qubits that are only produced by the command, such as allocations.
+    args.extend(
+        cmd.linear_outputs()
+            .filter(|(unit, _, _)| !cmd.linear_inputs().any(|(u, _, _)| u == *unit))
+            .map(|(unit, _, _)| format!("q{}", unit.index())),
+    );
+    let mut body = op_name(cmd.optype());
+    if !params.is_empty() {
+        body += &format!("({})", params.join(", "));
+    }
+    if !args.is_empty() {
+        body += &format!(" {}", args.join(", "));
+    }
+    body
+}
+
+/// The label of an angle parameter, in multiples of π.
+fn param_label(hugr: &impl HugrView, wire: Wire) -> String {
+    if let Some(angle) = float_wire_value(hugr, wire) {
+        return format_angle(Some(angle));
+    }
+    match match_symbolic_expr(hugr.get_optype(wire.node())) {
+        Some(expr) => {
+            let expr = expr.to_string();
+            match expr.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                true => format!("{expr}π"),
+                false => format!("({expr})π"),
+            }
+        }
+        None => "?".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::circuit::generators::qft;
+    use crate::serialize::{load_tk1_json_str, DecodeOptions};
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    #[test]
+    fn snapshot() {
+        let circ = load_tk1_json_str(
+            r#"{
+            "phase": "0.5",
+            "bits": [["c", [0]]],
+            "qubits": [["q", [0]], ["q", [1]]],
+            "commands": [
+                {"args": [["q", [0]]], "op": {"type": "H"}},
+                {"args": [["q", [0]], ["q", [1]]], "op": {"type": "CX"}},
+                {"args": [["q", [1]]], "op": {"type": "Rz", "params": ["0.25"]}},
+                {"args": [["q", [0]]], "op": {"type": "Rx", "params": ["a"]}},
+                {"args": [["q", [1]], ["c", [0]]], "op": {"type": "Measure"}}
+            ],
+            "implicit_permutation": []
+        }"#,
+            DecodeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            circuit_snapshot(&circ),
+            "qubits: 2
+phase: 0.5π
+H q0
+CX q0, q1
+Rx(aπ) q0
+Rz(0.25π) q1
+Measure q1 -> c0
+"
+        );
+    }
+
+    #[test]
+    fn snapshot_is_canonical() {
+        let circ1 = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::X, [1])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        let circ2 = build_simple_circuit(2, |circ| {
+            circ.append(Tk2Op::X, [1])?;
+            circ.append(Tk2Op::H, [0])?;
+            circ.append(Tk2Op::CX, [0, 1])?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(circuit_snapshot(&circ1), circuit_snapshot(&circ2));
+        assert_eq!(
+            circuit_snapshot(&circ1),
+            "qubits: 2\nphase: 0\nH q0\nX q1\nCX q0, q1\n"
+        );
+    }
+
+    #[rstest]
+    #[case("a\nb\nc\n", "a\nb\nc\n", None)]
+    #[case("a\nb\nc\n", "a\nx\nc\n", Some("@@ -1,3 +1,3 @@\n a\n-b\n+x\n c\n"))]
+    #[case(
+        "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n",
+        "1\n2\n3\n4\n5\n6\n7\n8\n9\n",
+        Some("@@ -7,4 +7,3 @@\n 7\n 8\n 9\n-10\n")
+    )]
+    #[case(
+        "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n",
+        "0\n1\n2\n3\n4\n5\n6\n7\n8\n10\n",
+        Some("@@ -1,3 +1,4 @@\n+0\n 1\n 2\n 3\n@@ -6,5 +7,4 @@\n 6\n 7\n 8\n-9\n 10\n")
+    )]
+    fn line_diff(#[case] expected: &str, #[case] actual: &str, #[case] diff: Option<&str>) {
+        assert_eq!(text_diff(expected, actual).as_deref(), diff);
+    }
+
+    #[test]
+    fn golden_file() {
+        assert_circuit_golden("../test_files/golden/qft_3.txt", &qft(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "Output differs from golden file")]
+    fn golden_file_mismatch() {
+        let path = std::env::temp_dir().join(format!("tket2_golden_{}.txt", std::process::id()));
+        fs::write(&path, circuit_snapshot(&qft(3))).unwrap();
+        let result = std::panic::catch_unwind(|| assert_circuit_golden(&path, &qft(2)));
+        fs::remove_file(&path).unwrap();
+        std::panic::resume_unwind(result.unwrap_err());
+    }
+}
@@ -2,23 +2,108 @@
 
 use hugr::builder::{Container, DataflowSubContainer, FunctionBuilder, HugrBuilder, ModuleBuilder};
 use hugr::extension::PRELUDE_REGISTRY;
+use hugr::hugr::hugrmut::HugrMut;
 use hugr::ops::handle::NodeHandle;
+use hugr::ops::{Const, LoadConstant, OpType};
 use hugr::std_extensions::arithmetic::float_ops::FLOAT_OPS_REGISTRY;
-use hugr::std_extensions::arithmetic::float_types;
+use hugr::std_extensions::arithmetic::float_types::{self, ConstF64, FLOAT64_TYPE};
 use hugr::types::{Type, TypeBound};
-use hugr::Hugr;
 use hugr::{
     builder::{BuildError, CircuitBuilder, Dataflow, DataflowHugr},
     extension::prelude::QB_T,
     types::Signature,
 };
+use hugr::{Hugr, HugrView, IncomingPort, Node, Wire};
 
 use crate::circuit::Circuit;
+use crate::ops::{match_symb_const_op, op_matches};
+use crate::Tk2Op;
+
+/// Absolute tolerance used when comparing angles, phases and the entries of
+/// gate matrices.
+///
+/// Comparisons of matrices built from many gates, such as the unitaries of
+/// whole circuits, accumulate more rounding errors and use looser tolerances.
+pub(crate) const TOLERANCE: f64 = 1e-9;
 
 pub(crate) fn type_is_linear(typ: &Type) -> bool {
     !TypeBound::Copyable.contains(typ.least_upper_bound())
 }
 
+/// Compute the value carried by a float wire, if it is fully determined by
+/// constants in the HUGR.
+///
+/// Follows `LoadConstant` nodes back to their `Const` definition, and folds
+/// [`Tk2Op::AngleAdd`] operations. Returns `None` for any other source, such as
+/// symbolic parameters or circuit inputs.
+pub(crate) fn float_wire_value(hugr: &impl HugrView, wire: Wire) -> Option<f64> {
+    let node = wire.node();
+    match hugr.get_optype(node) {
+        OpType::LoadConstant(_) => {
+            let (const_node, _) = hugr.single_linked_output(node, IncomingPort::from(0))?;
+            let OpType::Const(const_op) = hugr.get_optype(const_node) else {
+                return None;
+            };
+            let value = const_op.value().get_custom_value::<ConstF64>()?;
+            Some(**value)
+        }
+        // Only the value inputs are considered, ignoring any order edges.
+        op if op_matches(op, Tk2Op::AngleAdd) => (0..hugr.signature(node)?.input_count())
+            .map(|port| {
+                let (src, src_port) = hugr.single_linked_output(node, port)?;
+                float_wire_value(hugr, Wire::new(src, src_port))
+            })
+            .sum(),
+        _ => None,
+    }
+}
+
+/// The values of the angle inputs of a gate, in port order, if they are all
+/// constant.
+pub(crate) fn constant_params(hugr: &impl HugrView, node: Node) -> Option<Vec<f64>> {
+    let signature = hugr.signature(node)?;
+    signature
+        .input_types()
+        .iter()
+        .enumerate()
+        .filter(|(_, typ)| **typ == FLOAT64_TYPE)
+        .map(|(port, _)| {
+            let (src, src_port) = hugr.single_linked_output(node, port)?;
+            float_wire_value(hugr, Wire::new(src, src_port))
+        })
+        .collect()
+}
+
+/// Remove a parameter computation that is no longer used, along with the
+/// unused operations it depends on.
+pub(crate) fn remove_unused_param(hugr: &mut impl HugrMut, node: Node) {
+    let is_param_op = match hugr.get_optype(node) {
+        OpType::Const(_) | OpType::LoadConstant(_) => true,
+        op => op_matches(op, Tk2Op::AngleAdd) || match_symb_const_op(op).is_some(),
+    };
+    if !is_param_op || hugr.output_neighbours(node).next().is_some() {
+        return;
+    }
+    let inputs: Vec<Node> = hugr.input_neighbours(node).collect();
+    hugr.remove_node(node);
+    for input in inputs {
+        remove_unused_param(hugr, input);
+    }
+}
+
+/// Add the operations loading a constant float, returning the loading node.
+pub(crate) fn load_float(hugr: &mut impl HugrMut, parent: Node, value: f64) -> Node {
+    let constant = hugr.add_node_with_parent(parent, Const::new(ConstF64::new(value).into()));
+    let load = hugr.add_node_with_parent(
+        parent,
+        LoadConstant {
+            datatype: FLOAT64_TYPE,
+        },
+    );
+    hugr.connect(constant, 0, load, 0);
+    load
+}
+
 /// Utility for building simple qubit-only circuits.
 #[allow(unused)]
 pub(crate) fn build_simple_circuit<F>(num_qubits: usize, f: F) -> Result<Circuit, BuildError>
@@ -68,8 +153,60 @@ where
 #[allow(unused_imports)]
 #[cfg(test)]
 pub(crate) mod test {
-    use crate::Circuit;
+    use crate::serialize::{load_tk1_json_str, DecodeOptions};
+    use crate::{Circuit, Tk2Op};
     use hugr::HugrView;
+    use itertools::Itertools;
+
+    use super::build_simple_circuit;
+
+    /// The quantum operations of a circuit, in command order.
+    ///
+    /// Parameter operations such as [`Tk2Op::AngleAdd`] are included.
+    pub(crate) fn gates(circ: &Circuit) -> Vec<Tk2Op> {
+        circ.commands()
+            .filter_map(|cmd| cmd.optype().try_into().ok())
+            .collect()
+    }
+
+    /// Build a circuit on `num_qubits` qubits applying each gate to the
+    /// given qubit indices, in order.
+    pub(crate) fn circuit_from_gates(num_qubits: usize, ops: &[(Tk2Op, Vec<usize>)]) -> Circuit {
+        build_simple_circuit(num_qubits, |circ| {
+            for (op, qbs) in ops {
+                circ.append(*op, qbs.iter().copied())?;
+            }
+            Ok(())
+        })
+        .unwrap()
+    }
+
+    /// Decode a pytket circuit on `num_qubits` qubits and `num_bits` bits,
+    /// running the given pytket JSON `commands`.
+    ///
+    /// The qubits and bits are named `q[i]` and `c[i]`.
+    pub(crate) fn tk1_circuit(num_qubits: usize, num_bits: usize, commands: &str) -> Circuit {
+        let qubits = (0..num_qubits)
+            .map(|i| format!(r#"["q", [{i}]]"#))
+            .join(", ");
+        let bits = (0..num_bits).map(|i| format!(r#"["c", [{i}]]"#)).join(", ");
+        let permutation = (0..num_qubits)
+            .map(|i| format!(r#"[["q", [{i}]], ["q", [{i}]]]"#))
+            .join(", ");
+        load_tk1_json_str(
+            &format!(
+                r#"{{
+            "phase": "0",
+            "bits": [{bits}],
+            "qubits": [{qubits}],
+            "commands": [{commands}],
+            "implicit_permutation": [{permutation}]
+        }}"#
+            ),
+            DecodeOptions::default(),
+        )
+        .unwrap()
+    }
 
     /// Open a browser page to render a dot string graph.
     ///
@@ -0,0 +1,248 @@
+//! Utilities for checking the soundness of circuit transformations.
+//!
+//! Custom rewriters, such as `ECCRewriter`s built from user-provided
+//! equivalence classes or `RuleRewriter`s loaded from a rules file, are only
+//! correct if every rewrite they produce preserves the semantics of the
+//! circuit. [`check_rewriter_soundness`] tests this on random circuits by
+//! comparing their unitaries before and after each rewrite.
+
+use std::f64::consts::PI;
+
+use hugr::hugr::SimpleReplacementError;
+use ndarray::Array2;
+use num_complex::Complex64;
+use thiserror::Error;
+
+use crate::rewrite::Rewriter;
+use crate::sim::unitary::equal_up_to_phase;
+use crate::sim::SimulationError;
+use crate::Circuit;
+
+/// Tolerance used when comparing the unitaries of circuits.
+///
+/// Looser than [`crate::utils::TOLERANCE`], since the rounding errors of the
+/// gate matrices accumulate over the whole circuit.
+const TOLERANCE: f64 = 1e-8;
+
+/// A summary of a successful [`check_rewriter_soundness`] run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SoundnessReport {
+    /// The number of circuits the rewriter was run on.
+    pub cases: u64,
+    /// The number of rewrites checked, over all the circuits.
+    pub rewrites: usize,
+    /// The number of rewrites whose global phase was also checked.
+    pub phase_checked: usize,
+}
+
+/// Check that every rewrite produced by `rewriter` preserves the unitary of
+/// the circuit it applies to.
+///
+/// The `generator` is called with the indices `0..n_cases` to produce the
+/// circuits to test, for example with
+/// [`random_circuit`](crate::circuit::generators::random_circuit) using the
+/// index as seed. Each rewrite proposed for a circuit is applied to a fresh
+/// copy of it, and the unitaries before and after are compared.
+///
+/// When the global phase of the circuit is known after the rewrite, the
+/// unitaries must be exactly equal once the phase is taken into account.
+/// Otherwise they are compared up to a global phase.
+///
+/// # Errors
+///
+/// Returns an error describing the first unsound rewrite found, or if a
+/// circuit cannot be simulated.
+pub fn check_rewriter_soundness(
+    rewriter: &impl Rewriter,
+    mut generator: impl FnMut(u64) -> Circuit,
+    n_cases: u64,
+) -> Result<SoundnessReport, SoundnessError> {
+    let mut report = SoundnessReport::default();
+    for case in 0..n_cases {
+        let circ = generator(case);
+        let before =
+            phased_unitary(&circ).map_err(|source| SoundnessError::Simulation { case, source })?;
+        for (index, rewrite) in rewriter.get_rewrites(&circ).into_iter().enumerate() {
+            let mut rewritten = circ.clone();
+            rewrite.apply_notrace(&mut rewritten).map_err(|source| {
+                SoundnessError::InvalidRewrite {
+                    case,
+                    index,
+                    source,
+                }
+            })?;
+            let after = phased_unitary(&rewritten)
+                .map_err(|source| SoundnessError::Simulation { case, source })?;
+
+            let equivalent = match (&before, &after) {
+                ((before, Some(_)), (after, Some(_))) => {
+                    report.phase_checked += 1;
+                    (before - after).iter().all(|x| x.norm() < TOLERANCE)
+                }
+                ((before, _), (after, _)) => equal_up_to_phase(before, after, TOLERANCE),
+            };
+            if !equivalent {
+                return Err(SoundnessError::NotEquivalent {
+                    case,
+                    index,
+                    circuit: circ,
+                });
+            }
+            report.rewrites += 1;
+        }
+        report.cases += 1;
+    }
+    Ok(report)
+}
+
+/// The unitary of a circuit, including its global phase if it is a known
+/// constant. Returns the phase in half-turns alongside the unitary.
+fn phased_unitary(circ: &Circuit) -> Result<(Array2<Complex64>, Option<f64>), SimulationError> {
+    let unitary = circ.unitary()?;
+    let phase = circ
+        .global_phase()
+        .filter(|phase| !phase.is_symbolic())
+        .map(|phase| phase.constant());
+    Ok(match phase {
+        Some(half_turns) => {
+            let phase = Complex64::from_polar(1., half_turns * PI);
+            (unitary.mapv(|x| x * phase), Some(half_turns))
+        }
+        None => (unitary, None),
+    })
+}
+
+/// Errors that can occur while checking the soundness of a rewriter.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[non_exhaustive]
+pub enum SoundnessError {
+    /// A rewrite changed the unitary of a circuit.
+    #[error("Rewrite {index} of case {case} changed the unitary of the circuit.")]
+    NotEquivalent {
+        /// The index of the circuit passed to the generator.
+        case: u64,
+        /// The position of the rewrite in the rewriter's output.
+        index: usize,
+        /// The circuit, before the rewrite was applied.
+        circuit: Circuit,
+    },
+    /// A rewrite could not be applied to the circuit it was produced for.
+    #[error("Rewrite {index} of case {case} could not be applied: {source}")]
+    InvalidRewrite {
+        /// The index of the circuit passed to the generator.
+        case: u64,
+        /// The position of the rewrite in the rewriter's output.
+        index: usize,
+        /// The replacement error.
+        source: SimpleReplacementError,
+    },
+    /// A circuit could not be simulated.
+    #[error("Case {case} could not be simulated: {source}")]
+    Simulation {
+        /// The index of the circuit passed to the generator.
+        case: u64,
+        /// The simulation error.
+        source: SimulationError,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use hugr::HugrView;
+    use itertools::Itertools;
+    use rstest::rstest;
+
+    use super::*;
+    use crate::circuit::generators::{random_circuit, RandomCircuitSpec};
+    use crate::circuit::phase::GlobalPhase;
+    use crate::rewrite::{CircuitRewrite, Subcircuit};
+    use crate::utils::build_simple_circuit;
+    use crate::Tk2Op;
+
+    /// A rewriter replacing every `from` gate with a sequence of gates, with a
+    /// given phase.
+    struct GateRewriter {
+        from: Tk2Op,
+        to: Vec<Tk2Op>,
+        phase: Option<f64>,
+    }
+
+    impl Rewriter for GateRewriter {
+        fn get_rewrites(&self, circ: &Circuit<impl HugrView>) -> Vec<CircuitRewrite> {
+            circ.commands()
+                .filter(|cmd| Tk2Op::try_from(cmd.optype()) == Ok(self.from))
+                .map(|cmd| {
+                    let mut replacement = build_simple_circuit(1, |c| {
+                        for &op in &self.to {
+                            c.append(op, [0])?;
+                        }
+                        Ok(())
+                    })
+                    .unwrap();
+                    replacement.set_phase_delta(self.phase.map(GlobalPhase::new));
+                    let subcirc = Subcircuit::try_from_nodes([cmd.node()], circ).unwrap();
+                    subcirc.create_rewrite(circ, replacement).unwrap()
+                })
+                .collect_vec()
+        }
+    }
+
+    fn generator(seed: u64) -> Circuit {
+        let spec = RandomCircuitSpec {
+            num_qubits: 2,
+            depth: 5,
+            ..Default::default()
+        };
+        random_circuit(&spec, seed)
+    }
+
+    #[rstest]
+    // S = T·T
+    #[case(Tk2Op::S, vec![Tk2Op::T, Tk2Op::T], Some(0.))]
+    // X = H·Z·H
+    #[case(Tk2Op::X, vec![Tk2Op::H, Tk2Op::Z, Tk2Op::H], Some(0.))]
+    // Z = S·S, with an unknown phase
+    #[case(Tk2Op::Z, vec![Tk2Op::S, Tk2Op::S], None)]
+    fn sound_rewriter(#[case] from: Tk2Op, #[case] to: Vec<Tk2Op>, #[case] phase: Option<f64>) {
+        let rewriter = GateRewriter { from, to, phase };
+        let report = check_rewriter_soundness(&rewriter, generator, 20).unwrap();
+        assert_eq!(report.cases, 20);
+        assert!(report.rewrites > 0);
+        match phase {
+            Some(_) => assert_eq!(report.phase_checked, report.rewrites),
+            None => assert_eq!(report.phase_checked, 0),
+        }
+    }
+
+    #[rstest]
+    // T ≠ S
+    #[case(Tk2Op::T, vec![Tk2Op::S], None)]
+    // Y = i·X·Z, so the phase is wrong
+    #[case(Tk2Op::Y, vec![Tk2Op::Z, Tk2Op::X], Some(0.))]
+    fn unsound_rewriter(#[case] from: Tk2Op, #[case] to: Vec<Tk2Op>, #[case] phase: Option<f64>) {
+        let rewriter = GateRewriter { from, to, phase };
+        let err = check_rewriter_soundness(&rewriter, generator, 20).unwrap_err();
+        let SoundnessError::NotEquivalent { case, circuit, .. } = err else {
+            panic!("Unexpected error: {err}");
+        };
+        assert_eq!(circuit.unitary(), generator(case).unitary());
+    }
+
+    #[test]
+    fn unsimulable_circuit() {
+        let rewriter = GateRewriter {
+            from: Tk2Op::H,
+            to: vec![Tk2Op::H],
+            phase: Some(0.),
+        };
+        let measure = |_| {
+            build_simple_circuit(1, |c| {
+                c.append_and_consume(Tk2Op::Measure, [hugr::CircuitUnit::Linear(0)])?;
+                Ok(())
+            })
+            .unwrap()
+        };
+        let err = check_rewriter_soundness(&rewriter, measure, 1).unwrap_err();
+        assert!(matches!(err, SoundnessError::Simulation { case: 0, .. }));
+    }
+}
@@ -61,5 +61,19 @@ fn badger_termination(simple_circ: Circuit, nam_4_2: DefaultBadgerOptimiser) {
             ..Default::default()
         },
     );
-    assert_eq!(opt_circ.commands().count(), 11);
+    // The rewrites replace the sums of the constant angles by their values.
+    assert_eq!(opt_circ.commands().count(), 8);
+}
+
+#[rstest]
+fn badger_termination_compact_queue(simple_circ: Circuit, nam_4_2: DefaultBadgerOptimiser) {
+    let opt_circ = nam_4_2.optimise(
+        &simple_circ,
+        BadgerOptions {
+            queue_size: 10,
+            compact_queue: true,
+            ..Default::default()
+        },
+    );
+    assert_eq!(opt_circ.commands().count(), 8);
 }
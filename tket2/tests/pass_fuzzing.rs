@@ -0,0 +1,99 @@
+//! Checks that the optimisation passes preserve the unitary of small random
+//! circuits.
+
+use rstest::rstest;
+use tket2::circuit::generators::{random_circuit, RandomCircuitSpec};
+use tket2::passes::{
+    apply_greedy_commutation, cx_cancellation, decompose_controlled_gates, rebase,
+    squash_single_qubit_gates, CxCancellationConfig, EulerBasis, GateSet,
+};
+use tket2::sim::unitary::equal_up_to_phase;
+use tket2::{Circuit, Tk2Op};
+
+/// The number of random circuits each pass is run on.
+const NUM_CASES: u64 = 40;
+
+/// Run `pass` on random circuits satisfying `spec`, and check that the
+/// unitary is unchanged up to a global phase.
+fn fuzz_pass(spec: RandomCircuitSpec, pass: impl Fn(&mut Circuit)) {
+    for seed in 0..NUM_CASES {
+        let mut circ = random_circuit(&spec, seed);
+        let before = circ.unitary().unwrap();
+        pass(&mut circ);
+        let after = circ.unitary().unwrap();
+        assert!(
+            equal_up_to_phase(&before, &after, 1e-8),
+            "The pass changed the unitary of the circuit with seed {seed}."
+        );
+    }
+}
+
+/// Small circuits over the default gate set.
+fn default_spec(num_qubits: usize) -> RandomCircuitSpec {
+    RandomCircuitSpec {
+        num_qubits,
+        depth: 8,
+        ..Default::default()
+    }
+}
+
+/// Small Clifford+T circuits, including Toffoli gates.
+fn clifford_t_spec(num_qubits: usize) -> RandomCircuitSpec {
+    use Tk2Op::*;
+    RandomCircuitSpec {
+        num_qubits,
+        depth: 8,
+        gate_set: vec![H, S, Sdg, T, Tdg, X, Y, Z, CX, CZ, CCX, CCZ],
+        two_qubit_density: 0.4,
+    }
+}
+
+#[rstest]
+#[case(EulerBasis::ZYZ)]
+#[case(EulerBasis::XZX)]
+#[case(EulerBasis::ZXZ)]
+fn fuzz_squash(#[case] basis: EulerBasis) {
+    fuzz_pass(default_spec(3), |circ| {
+        squash_single_qubit_gates(circ, basis);
+    });
+}
+
+#[rstest]
+#[case(false)]
+#[case(true)]
+fn fuzz_cx_cancellation(#[case] commute: bool) {
+    let config = CxCancellationConfig {
+        commute,
+        recognise_swaps: false,
+    };
+    let spec = RandomCircuitSpec {
+        two_qubit_density: 0.6,
+        ..default_spec(3)
+    };
+    fuzz_pass(spec, |circ| {
+        cx_cancellation(circ, config);
+    });
+}
+
+#[test]
+fn fuzz_commutation() {
+    fuzz_pass(default_spec(3), |circ| {
+        apply_greedy_commutation(circ).unwrap();
+    });
+}
+
+#[test]
+fn fuzz_controlled_decomposition() {
+    fuzz_pass(clifford_t_spec(3), |circ| {
+        decompose_controlled_gates(circ);
+    });
+}
+
+#[rstest]
+#[case(GateSet::quantinuum(), default_spec(3))]
+#[case(GateSet::clifford_t(), clifford_t_spec(3))]
+fn fuzz_rebase(#[case] gate_set: GateSet, #[case] spec: RandomCircuitSpec) {
+    fuzz_pass(spec, |circ| {
+        rebase(circ, &gate_set).unwrap();
+    });
+}